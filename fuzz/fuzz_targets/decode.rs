@@ -0,0 +1,18 @@
+//! Decodes arbitrary input with tight limits, which must never panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mcp_rs_test::codec::{self, Limits};
+
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits {
+        max_size: 64 * 1024,
+        max_depth: 16,
+        max_string_length: 1024,
+    };
+    if let Ok(input) = std::str::from_utf8(data) {
+        let _ = codec::decode(input, &limits);
+    }
+    let _ = codec::check_limits(data, &limits);
+});
@@ -0,0 +1,25 @@
+//! Serves arbitrary lines through a server with the built-in calculator and prompts, which
+//! must answer every line without panicking.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mcp_rs_test::{builtin, server::Server};
+use std::{io, sync::OnceLock};
+
+fn server() -> &'static Server {
+    static SERVER: OnceLock<Server> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        Server::new()
+            .with_tool(builtin::calc::evaluate_tool())
+            .with_prompt(builtin::prompts::code_review_prompt())
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let lines: Vec<String> = String::from_utf8_lossy(data)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    server().serve(lines, io::sink());
+});
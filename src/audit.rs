@@ -0,0 +1,334 @@
+//! Audit log of the tool calls: every `tools/call` is recorded with the tool, a hash of its
+//! arguments (and the arguments themselves, redacted, with `audit_arguments`), the session and
+//! client that made it, its duration and outcome. The log is only ever appended to, as JSON lines
+//! or, for `.db`, `.sqlite` and `.sqlite3` files, in the `tool_calls` table of a SQLite database.
+
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "audit")]
+use rusqlite::{params, Connection};
+
+/// Records returned by `audit/query` when the query doesn't say how many.
+const DEFAULT_QUERY_LIMIT: usize = 100;
+
+/// How a call ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The tool returned its result
+    Ok,
+    /// The tool returned an `isError` result
+    Error,
+    /// The call was answered with a JSON-RPC error (unknown tool, invalid arguments...)
+    Failed,
+    /// The call wasn't approved, see [`crate::policy`]
+    Denied,
+}
+
+#[cfg(feature = "audit")]
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Error => "error",
+            Outcome::Failed => "failed",
+            Outcome::Denied => "denied",
+        }
+    }
+
+    fn parse(name: &str) -> Outcome {
+        match name {
+            "ok" => Outcome::Ok,
+            "error" => Outcome::Error,
+            "denied" => Outcome::Denied,
+            _ => Outcome::Failed,
+        }
+    }
+}
+
+/// One call of a tool.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// When the call was received, in RFC 3339 format
+    pub time: String,
+    /// Session that made the call
+    pub session: String,
+    /// `clientInfo.name` of the session
+    pub client: Option<String>,
+    pub tool: String,
+    /// SHA-256 of the arguments, as compact JSON with sorted keys
+    pub arguments_sha256: String,
+    /// The arguments, redacted, when `audit_arguments` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+    pub duration_ms: u64,
+    pub outcome: Outcome,
+    /// Error message, or reason of a refusal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Params of `audit/query`: the most recent records matching all the given fields, oldest first.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub tool: Option<String>,
+    pub session: Option<String>,
+    pub outcome: Option<Outcome>,
+    /// Only the records from this time on (RFC 3339)
+    pub since: Option<String>,
+    /// Number of records returned, 100 by default
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.tool.as_ref().is_none_or(|tool| *tool == record.tool)
+            && self
+                .session
+                .as_ref()
+                .is_none_or(|session| *session == record.session)
+            && self.outcome.is_none_or(|outcome| outcome == record.outcome)
+            && self
+                .since
+                .as_ref()
+                .is_none_or(|since| record.time.as_str() >= since.as_str())
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_QUERY_LIMIT)
+    }
+}
+
+/// SHA-256 of `arguments`, in hexadecimal.
+pub fn hash_arguments(arguments: &Value) -> String {
+    Sha256::digest(arguments.to_string().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Where the tool calls are recorded.
+pub trait AuditStore: Send + Sync {
+    fn record(&self, record: &AuditRecord) -> Result<(), String>;
+
+    fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>, String>;
+}
+
+/// The audit log of `audit_log`, opened on the first call and again when the setting changes.
+#[derive(Default)]
+pub(crate) struct AuditLog {
+    current: Mutex<Option<OpenedLog>>,
+}
+
+struct OpenedLog {
+    path: PathBuf,
+    /// None if it couldn't be opened
+    store: Option<Arc<dyn AuditStore>>,
+}
+
+impl AuditLog {
+    pub(crate) fn store(&self, config: &Config) -> Option<Arc<dyn AuditStore>> {
+        let path = config.audit_log.as_ref()?;
+        let mut current = self.current.lock().unwrap();
+        match &*current {
+            Some(opened) if opened.path == *path => opened.store.clone(),
+            _ => {
+                let store = open(path).inspect_err(|e| tracing::error!("{}", e)).ok();
+                *current = Some(OpenedLog {
+                    path: path.clone(),
+                    store: store.clone(),
+                });
+                store
+            }
+        }
+    }
+}
+
+/// Opens the audit log at `path`: a SQLite database for `.db`, `.sqlite` and `.sqlite3` files, a
+/// JSON lines file otherwise.
+pub fn open(path: &Path) -> Result<Arc<dyn AuditStore>, String> {
+    let sqlite = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "db" | "sqlite" | "sqlite3"));
+    let store = match sqlite {
+        true => open_sqlite(path),
+        false => FileAuditLog::open(path)
+            .map(|log| Arc::new(log) as Arc<dyn AuditStore>)
+            .map_err(|e| e.to_string()),
+    };
+    store.map_err(|e| format!("Cannot open the audit log {}: {}", path.display(), e))
+}
+
+#[cfg(feature = "audit")]
+fn open_sqlite(path: &Path) -> Result<Arc<dyn AuditStore>, String> {
+    SqliteAuditLog::open(path)
+        .map(|log| Arc::new(log) as Arc<dyn AuditStore>)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "audit"))]
+fn open_sqlite(_path: &Path) -> Result<Arc<dyn AuditStore>, String> {
+    Err("SQLite audit logs need the `audit` feature".to_string())
+}
+
+/// Records appended to a file, one JSON object per line.
+pub struct FileAuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl FileAuditLog {
+    pub fn open(path: &Path) -> io::Result<FileAuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileAuditLog {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditStore for FileAuditLog {
+    fn record(&self, record: &AuditRecord) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        line.push('\n');
+        // One write per record, so that records stay whole
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| e.to_string())
+    }
+
+    fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>, String> {
+        let file = File::open(&self.path).map_err(|e| e.to_string())?;
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| e.to_string())?;
+            match serde_json::from_str::<AuditRecord>(&line) {
+                Ok(record) if query.matches(&record) => records.push(record),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Skipping invalid audit record {}: {}", line, e),
+            }
+        }
+        let skipped = records.len().saturating_sub(query.limit());
+        records.drain(..skipped);
+        Ok(records)
+    }
+}
+
+/// Records kept in the `tool_calls` table of a SQLite database, where triggers refuse updates
+/// and deletions.
+#[cfg(feature = "audit")]
+pub struct SqliteAuditLog {
+    connection: Mutex<Connection>,
+}
+
+#[cfg(feature = "audit")]
+impl SqliteAuditLog {
+    /// Opens the database, creating it and its table if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<SqliteAuditLog> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS tool_calls (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 time TEXT NOT NULL,
+                 session TEXT NOT NULL,
+                 client TEXT,
+                 tool TEXT NOT NULL,
+                 arguments_sha256 TEXT NOT NULL,
+                 arguments TEXT,
+                 duration_ms INTEGER NOT NULL,
+                 outcome TEXT NOT NULL,
+                 error TEXT
+             );
+             CREATE TRIGGER IF NOT EXISTS tool_calls_no_update BEFORE UPDATE ON tool_calls
+             BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+             CREATE TRIGGER IF NOT EXISTS tool_calls_no_delete BEFORE DELETE ON tool_calls
+             BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;",
+        )?;
+        Ok(SqliteAuditLog {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn try_query(&self, query: &AuditQuery) -> rusqlite::Result<Vec<AuditRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT time, session, client, tool, arguments_sha256, arguments, duration_ms,
+                    outcome, error
+             FROM tool_calls
+             WHERE (?1 IS NULL OR tool = ?1) AND (?2 IS NULL OR session = ?2)
+                   AND (?3 IS NULL OR outcome = ?3) AND (?4 IS NULL OR time >= ?4)
+             ORDER BY id DESC LIMIT ?5",
+        )?;
+        let rows = statement.query_map(
+            params![
+                query.tool,
+                query.session,
+                query.outcome.map(Outcome::as_str),
+                query.since,
+                query.limit() as i64
+            ],
+            |row| {
+                let arguments: Option<String> = row.get(5)?;
+                let outcome: String = row.get(7)?;
+                Ok(AuditRecord {
+                    time: row.get(0)?,
+                    session: row.get(1)?,
+                    client: row.get(2)?,
+                    tool: row.get(3)?,
+                    arguments_sha256: row.get(4)?,
+                    arguments: arguments
+                        .and_then(|arguments| serde_json::from_str(&arguments).ok()),
+                    duration_ms: row.get::<_, i64>(6)? as u64,
+                    outcome: Outcome::parse(&outcome),
+                    error: row.get(8)?,
+                })
+            },
+        )?;
+        let mut records = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        records.reverse();
+        Ok(records)
+    }
+}
+
+#[cfg(feature = "audit")]
+impl AuditStore for SqliteAuditLog {
+    fn record(&self, record: &AuditRecord) -> Result<(), String> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "INSERT INTO tool_calls (time, session, client, tool, arguments_sha256, arguments,
+                                         duration_ms, outcome, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    record.time,
+                    record.session,
+                    record.client,
+                    record.tool,
+                    record.arguments_sha256,
+                    record.arguments.as_ref().map(Value::to_string),
+                    record.duration_ms as i64,
+                    record.outcome.as_str(),
+                    record.error
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>, String> {
+        self.try_query(query).map_err(|e| e.to_string())
+    }
+}
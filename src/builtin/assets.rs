@@ -0,0 +1,124 @@
+//! Files of the `resource_dir` directory, exposed as `static://` resources.
+
+use crate::{
+    builtin::{
+        cache::FileCache,
+        mime::{file_contents, guess_mime_type},
+    },
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{Annotations, ReadResourceResult},
+    resources::{Resource, ResourceProvider},
+    roots,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const STATIC_SCHEME: &str = "static://";
+/// Maximum number of files listed by `resources/list`.
+const MAX_LISTED_FILES: usize = 1000;
+
+/// Exposes every file under `resource_dir` as `static://<relative path>`. The MIME type is
+/// inferred from the extension, or from the contents for unknown extensions; text files are
+/// returned as text and other files as base64-encoded blobs. Files are read through a cache,
+/// see [`FileCache`].
+#[derive(Default)]
+pub struct StaticResources {
+    cache: FileCache,
+}
+
+impl StaticResources {
+    fn collect(root: &Path, dir: &Path, resources: &mut Vec<Resource>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if resources.len() >= MAX_LISTED_FILES {
+                return;
+            }
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => StaticResources::collect(root, &path, resources),
+                Ok(t) if t.is_file() => {
+                    let Ok(relative) = path.strip_prefix(root) else {
+                        continue;
+                    };
+                    resources.push(Resource {
+                        uri: static_uri(relative),
+                        name: relative.to_string_lossy().replace('\\', "/"),
+                        description: None,
+                        mime_type: Some(guess_mime_type(&path, None)),
+                        annotations: entry
+                            .metadata()
+                            .and_then(|metadata| metadata.modified())
+                            .ok()
+                            .map(Annotations::modified_at),
+                        ..Default::default()
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `static://` URI of a path relative to `resource_dir`, percent-encoded like file:// URIs.
+pub(crate) fn static_uri(relative: &Path) -> String {
+    let encoded = roots::path_to_uri(relative);
+    let encoded = encoded
+        .trim_start_matches("file://")
+        .trim_start_matches('/');
+    format!("{}{}", STATIC_SCHEME, encoded)
+}
+
+/// Path under `dir` of the percent-encoded `relative` path, refusing paths that escape it.
+fn resolve(dir: &Path, relative: &str) -> Result<PathBuf, String> {
+    let relative = roots::uri_to_path(&format!("file:///{}", relative))
+        .ok_or_else(|| format!("invalid resource path {}", relative))?;
+    let relative = relative.trim_start_matches('/');
+    roots::resolve(&[dir.to_path_buf()], relative)
+}
+
+impl ResourceProvider for StaticResources {
+    fn list(&self, ctx: &RequestContext) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        if let Some(dir) = ctx
+            .config
+            .resource_dir
+            .as_ref()
+            .and_then(|dir| fs::canonicalize(dir).ok())
+        {
+            StaticResources::collect(&dir, &dir, &mut resources);
+        }
+        resources
+    }
+
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let relative = uri.strip_prefix(STATIC_SCHEME)?;
+        let result = ctx
+            .config
+            .resource_dir
+            .as_ref()
+            .ok_or_else(|| "no resource directory is configured".to_string())
+            .and_then(|dir| resolve(dir, relative))
+            .and_then(|path| {
+                let file = self
+                    .cache
+                    .read(&path, ctx.config.resource_cache_size)
+                    .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+                Ok(ReadResourceResult::new(vec![file_contents(
+                    uri, &path, &file,
+                )]))
+            })
+            .map_err(|e| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e));
+        Some(result)
+    }
+}
@@ -0,0 +1,127 @@
+//! Cache of the files read as resources, so that reading the same large files again and again
+//! doesn't hit the disk every time. An entry is used as long as the size and modification time
+//! of its file don't change. Files read right after they were modified are read again on the
+//! next request and compared by hash, since another write within the resolution of the
+//! modification time would go unnoticed. The cache holds up to `resource_cache_size` bytes,
+//! dropping the least recently used files first.
+
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Time after its modification during which a file could be written again without its
+/// modification time changing.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// The contents of a file and when it was last modified.
+pub struct CachedFile {
+    pub contents: Arc<Vec<u8>>,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Default)]
+pub struct FileCache {
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<PathBuf, Entry>,
+    /// Bytes held by the entries
+    size: usize,
+    /// Incremented on every use, to find the least recently used entry
+    clock: u64,
+}
+
+struct Entry {
+    contents: Arc<Vec<u8>>,
+    modified: SystemTime,
+    len: u64,
+    sha256: Vec<u8>,
+    /// True once the file was read long enough after its modification
+    settled: bool,
+    used: u64,
+}
+
+impl FileCache {
+    /// Reads the file at `path`, from the cache when it didn't change. Files larger than
+    /// `capacity` aren't kept, and a capacity of 0 disables the cache.
+    pub fn read(&self, path: &Path, capacity: usize) -> io::Result<CachedFile> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified().ok();
+        let (Some(modified), true) = (modified, capacity > 0 && metadata.len() <= capacity as u64)
+        else {
+            return Ok(CachedFile {
+                contents: Arc::new(fs::read(path)?),
+                modified,
+            });
+        };
+        {
+            let mut state = self.state.lock().unwrap();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(entry) = state.entries.get_mut(path) {
+                if entry.settled && entry.modified == modified && entry.len == metadata.len() {
+                    entry.used = clock;
+                    return Ok(CachedFile {
+                        contents: entry.contents.clone(),
+                        modified: Some(modified),
+                    });
+                }
+            }
+        }
+
+        // Read without holding the lock, so that other files can be served meanwhile
+        let bytes = fs::read(path)?;
+        let sha256 = Sha256::digest(&bytes).to_vec();
+        let settled = SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= RACY_WINDOW);
+        let mut state = self.state.lock().unwrap();
+        let clock = state.clock;
+        let contents = match state.entries.remove(path) {
+            Some(entry) => {
+                state.size -= entry.contents.len();
+                match entry.sha256 == sha256 {
+                    true => entry.contents,
+                    false => Arc::new(bytes),
+                }
+            }
+            None => Arc::new(bytes),
+        };
+        state.size += contents.len();
+        state.entries.insert(
+            path.to_path_buf(),
+            Entry {
+                contents: contents.clone(),
+                modified,
+                len: metadata.len(),
+                sha256,
+                settled,
+                used: clock,
+            },
+        );
+        while state.size > capacity {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = state.entries.remove(&oldest) {
+                state.size -= entry.contents.len();
+            }
+        }
+        Ok(CachedFile {
+            contents,
+            modified: Some(modified),
+        })
+    }
+}
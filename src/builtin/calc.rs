@@ -0,0 +1,457 @@
+//! Evaluation of arithmetic expressions.
+//!
+//! Integers are computed exactly (as 128-bit integers) as long as the operations allow it,
+//! falling back to floating point for divisions with a remainder, overflows and functions.
+
+use crate::{
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use serde_json::json;
+use std::{f64::consts, fmt};
+
+/// Maximum nesting of parentheses, function calls and operators, to bound the recursion.
+const MAX_DEPTH: usize = 256;
+
+/// `evaluate` tool computing an arithmetic expression.
+pub fn evaluate_tool() -> Tool {
+    Tool::new(
+        "evaluate",
+        "Evaluate an arithmetic expression and return its value. Supports + - * / % ^ (or **), \
+         parentheses, the constants pi and e, and the functions sqrt, pow, exp, ln, log \
+         (log(x) is base 10, log(x, b) base b), log2, abs, floor, ceil, round, min, max, \
+         sin, cos, tan, asin, acos and atan (in radians). Integer arithmetic is exact.",
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": { "type": "string", "description": "Expression, e.g. 2^64 + sqrt(2)" }
+            },
+            "required": ["expression"]
+        }),
+        |_ctx, arguments| {
+            let expression = required_str(arguments, "expression")?;
+            match evaluate(expression) {
+                Ok(value) => Ok(CallToolResult::text(value.to_string())),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// Evaluates `expression`.
+pub fn evaluate(expression: &str) -> Result<Number, String> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.expression()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => Err(format!("unexpected {}", token)),
+    }
+}
+
+/// Value of an expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn to_f64(self) -> f64 {
+        match self {
+            Number::Integer(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    /// Applies an exact integer operation, falling back to `float` when either side is a
+    /// float or the integer operation fails.
+    fn apply(
+        self,
+        other: Number,
+        integer: fn(i128, i128) -> Option<i128>,
+        float: fn(f64, f64) -> f64,
+    ) -> Number {
+        if let (Number::Integer(a), Number::Integer(b)) = (self, other) {
+            if let Some(result) = integer(a, b) {
+                return Number::Integer(result);
+            }
+        }
+        Number::Float(float(self.to_f64(), other.to_f64()))
+    }
+
+    fn pow(self, exponent: Number) -> Number {
+        match (self, exponent) {
+            (Number::Integer(base), Number::Integer(exponent)) if exponent >= 0 => {
+                match u32::try_from(exponent)
+                    .ok()
+                    .and_then(|e| base.checked_pow(e))
+                {
+                    Some(result) => Number::Integer(result),
+                    None => Number::Float((base as f64).powf(exponent as f64)),
+                }
+            }
+            _ => Number::Float(self.to_f64().powf(exponent.to_f64())),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Integer(i) => write!(f, "{}", i),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Number),
+    Identifier(String),
+    Operator(char),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Identifier(name) => write!(f, "'{}'", name),
+            Token::Operator(op) => write!(f, "'{}'", op),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '0'..='9' | '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // Exponent, e.g. 1e-3
+                if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && matches!(chars[j], '+' | '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = match text.parse::<i128>() {
+                    Ok(integer) => Number::Integer(integer),
+                    Err(_) => Number::Float(
+                        text.parse()
+                            .map_err(|_| format!("invalid number '{}'", text))?,
+                    ),
+                };
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                tokens.push(Token::Operator('^'));
+                i += 2;
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Operator(c));
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            c => return Err(format!("unexpected character '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser evaluating while it parses.
+///
+/// ```text
+/// expression = term (("+" | "-") term)*
+/// term       = unary (("*" | "/" | "%") unary)*
+/// unary      = ("+" | "-") unary | power
+/// power      = primary ("^" unary)?
+/// primary    = number | constant | function "(" expression ("," expression)* ")"
+///            | "(" expression ")"
+/// ```
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {}, found {}", expected, token)),
+            None => Err(format!(
+                "expected {} at the end of the expression",
+                expected
+            )),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Number, String> {
+        let mut value = self.term()?;
+        while let Some(Token::Operator(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.position += 1;
+            let right = self.term()?;
+            value = match op {
+                '+' => value.apply(right, i128::checked_add, |a, b| a + b),
+                _ => value.apply(right, i128::checked_sub, |a, b| a - b),
+            };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Number, String> {
+        let mut value = self.unary()?;
+        while let Some(Token::Operator(op @ ('*' | '/' | '%'))) = self.peek().cloned() {
+            self.position += 1;
+            let right = self.unary()?;
+            if op != '*' && right.to_f64() == 0.0 {
+                return Err("division by zero".to_string());
+            }
+            if let (Number::Integer(a), Number::Integer(b)) = (value, right) {
+                // The one quotient of integers out of their range: the minimum divided by -1
+                if op != '*' && a.checked_div(b).is_none() {
+                    return Err(format!("{} {} {} overflows", a, op, b));
+                }
+            }
+            value = match op {
+                '*' => value.apply(right, i128::checked_mul, |a, b| a * b),
+                '/' => value.apply(
+                    right,
+                    |a, b| (a.checked_rem(b)? == 0).then(|| a.checked_div(b)).flatten(),
+                    |a, b| a / b,
+                ),
+                _ => value.apply(right, i128::checked_rem, |a, b| a % b),
+            };
+        }
+        Ok(value)
+    }
+
+    /// Every recursion goes through `unary`, so this is where the nesting is bounded.
+    fn unary(&mut self) -> Result<Number, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("the expression is nested too deeply".to_string());
+        }
+        let value = self.signed();
+        self.depth -= 1;
+        value
+    }
+
+    fn signed(&mut self) -> Result<Number, String> {
+        match self.peek() {
+            Some(Token::Operator('-')) => {
+                self.position += 1;
+                let value = self.unary()?;
+                Ok(Number::Integer(0).apply(value, i128::checked_sub, |a, b| a - b))
+            }
+            Some(Token::Operator('+')) => {
+                self.position += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Number, String> {
+        let base = self.primary()?;
+        if self.peek() == Some(&Token::Operator('^')) {
+            self.position += 1;
+            let exponent = self.unary()?;
+            return Ok(base.pow(exponent));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Number, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::Open) => {
+                let value = self.expression()?;
+                self.expect(Token::Close)?;
+                Ok(value)
+            }
+            Some(Token::Identifier(name)) => {
+                if self.peek() != Some(&Token::Open) {
+                    return constant(&name);
+                }
+                self.position += 1;
+                let mut args = vec![self.expression()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.position += 1;
+                    args.push(self.expression()?);
+                }
+                self.expect(Token::Close)?;
+                call(&name, &args)
+            }
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+}
+
+fn constant(name: &str) -> Result<Number, String> {
+    match name {
+        "pi" => Ok(Number::Float(consts::PI)),
+        "e" => Ok(Number::Float(consts::E)),
+        _ => Err(format!("unknown constant '{}'", name)),
+    }
+}
+
+fn call(name: &str, args: &[Number]) -> Result<Number, String> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} takes {} argument(s), got {}",
+                name,
+                n,
+                args.len()
+            ))
+        }
+    };
+    let float = |f: fn(f64) -> f64| -> Result<Number, String> {
+        arity(1)?;
+        Ok(Number::Float(f(args[0].to_f64())))
+    };
+    // Rounding functions keep integers exact
+    let round = |f: fn(f64) -> f64| -> Result<Number, String> {
+        arity(1)?;
+        Ok(match args[0] {
+            Number::Integer(i) => Number::Integer(i),
+            Number::Float(x) => Number::Float(f(x)),
+        })
+    };
+    match name {
+        "sqrt" => float(f64::sqrt),
+        "exp" => float(f64::exp),
+        "ln" => float(f64::ln),
+        "log2" => float(f64::log2),
+        "log" if args.len() == 2 => Ok(Number::Float(args[0].to_f64().log(args[1].to_f64()))),
+        "log" => float(f64::log10),
+        "sin" => float(f64::sin),
+        "cos" => float(f64::cos),
+        "tan" => float(f64::tan),
+        "asin" => float(f64::asin),
+        "acos" => float(f64::acos),
+        "atan" => float(f64::atan),
+        "floor" => round(f64::floor),
+        "ceil" => round(f64::ceil),
+        "round" => round(f64::round),
+        "abs" => {
+            arity(1)?;
+            Ok(match args[0] {
+                Number::Integer(i) => i
+                    .checked_abs()
+                    .map_or(Number::Float((i as f64).abs()), Number::Integer),
+                Number::Float(x) => Number::Float(x.abs()),
+            })
+        }
+        "pow" => {
+            arity(2)?;
+            Ok(args[0].pow(args[1]))
+        }
+        "min" | "max" if !args.is_empty() => {
+            let pick = |a: Number, b: Number| {
+                let b_first = if name == "min" {
+                    b.to_f64() < a.to_f64()
+                } else {
+                    b.to_f64() > a.to_f64()
+                };
+                if b_first {
+                    b
+                } else {
+                    a
+                }
+            };
+            Ok(args[1..].iter().copied().fold(args[0], pick))
+        }
+        _ => Err(format!("unknown function '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_division_out_of_range_is_an_error() {
+        let min = "(-170141183460469231731687303715884105727 - 1)";
+        for op in ['/', '%'] {
+            let error = evaluate(&format!("{} {} -1", min, op)).unwrap_err();
+            assert!(error.contains("overflows"), "{}", error);
+        }
+        assert_eq!(
+            evaluate(&format!("{} / 1", min)),
+            Ok(Number::Integer(i128::MIN))
+        );
+        assert_eq!(evaluate(&format!("{} % 2", min)), Ok(Number::Integer(0)));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        for expression in ["1 / 0", "1 % 0", "1.5 / 0", "1 / (2 - 2)", "1 / 0.0"] {
+            assert_eq!(
+                evaluate(expression),
+                Err("division by zero".to_string()),
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(evaluate("7 / 2"), Ok(Number::Float(3.5)));
+        assert_eq!(evaluate("-7 % 2"), Ok(Number::Integer(-1)));
+    }
+}
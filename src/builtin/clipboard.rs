@@ -0,0 +1,70 @@
+//! Text of the desktop clipboard, for assistants running on the user's machine. Only offered
+//! when `allow_clipboard` is set.
+
+use crate::{
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use arboard::Clipboard;
+use serde_json::json;
+use std::sync::Mutex;
+
+/// Clipboard opened by the first call and kept for the life of the process: on X11 and
+/// Wayland, the text set is only served to other applications while it is open.
+static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+/// `clipboard_get` tool returning the text of the clipboard.
+pub fn get_tool() -> Tool {
+    Tool::new(
+        "clipboard_get",
+        "Return the text currently in the user's clipboard.",
+        json!({ "type": "object", "properties": {} }),
+        |_ctx, _arguments| match with_clipboard(|clipboard| clipboard.get_text()) {
+            Ok(text) => Ok(CallToolResult::text(text)),
+            Err(e) => Ok(CallToolResult::error(e)),
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_clipboard)
+}
+
+/// `clipboard_set` tool replacing the contents of the clipboard with text.
+pub fn set_tool() -> Tool {
+    Tool::new(
+        "clipboard_set",
+        "Put text in the user's clipboard, replacing what it holds.",
+        json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string", "description": "Text to copy" }
+            },
+            "required": ["text"]
+        }),
+        |_ctx, arguments| {
+            let text = required_str(arguments, "text")?;
+            tracing::info!("Copying {} bytes to the clipboard", text.len());
+            match with_clipboard(|clipboard| clipboard.set_text(text)) {
+                Ok(()) => Ok(CallToolResult::text(format!(
+                    "Copied {} characters to the clipboard",
+                    text.chars().count()
+                ))),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "idempotentHint": true }))
+    .available_when(|config| config.allow_clipboard)
+}
+
+/// Runs `f` on the clipboard, opening it first if needed.
+fn with_clipboard<T>(
+    f: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>,
+) -> Result<T, String> {
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    let clipboard = match &mut *clipboard {
+        Some(clipboard) => clipboard,
+        closed => closed
+            .insert(Clipboard::new().map_err(|e| format!("cannot open the clipboard: {}", e))?),
+    };
+    f(clipboard).map_err(|e| format!("clipboard error: {}", e))
+}
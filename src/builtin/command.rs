@@ -0,0 +1,645 @@
+//! Execution of external commands, restricted by the `command_*` settings: which executables
+//! may run, for how long and with how much output, and for `run_command` in which directory,
+//! with which environment, CPU time and user.
+
+use crate::{
+    builtin::env::matches,
+    config::Config,
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INTERNAL_ERROR},
+    mcp::{CallToolResult, Content, ResourceContents},
+    roots,
+    tools::{required_str, Tool},
+};
+use serde_json::{json, Value};
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval at which a running command is checked for completion, timeout and cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Interval at which the lines a command printed are sent as progress notifications, together.
+const STREAM_INTERVAL: Duration = Duration::from_millis(100);
+/// Bytes after which a line still being printed is streamed as is.
+const MAX_STREAMED_LINE: usize = 4096;
+
+/// `run_command` tool running an executable (without a shell) and returning its exit code
+/// and output. Only offered when `allow_commands` is set.
+pub fn run_tool() -> Tool {
+    Tool::new(
+        "run_command",
+        "Run an executable with arguments (no shell is involved). Returns a JSON object with \
+         the exit code, stdout and stderr of the process, decoded as text, and the encoding \
+         each was decoded from (unknown when invalid bytes had to be replaced). Set \
+         raw_output to also get them as is, base64-encoded, for binary output.",
+        json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "Executable to run" },
+                "args": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Arguments passed to the executable"
+                },
+                "cwd": {
+                    "type": "string",
+                    "description": "Working directory, defaults to the first root directory"
+                },
+                "raw_output": {
+                    "type": "boolean",
+                    "description": "Also return stdout and stderr as base64-encoded blobs"
+                }
+            },
+            "required": ["command"]
+        }),
+        |ctx, arguments| {
+            let (command, args, cwd) = prepare(ctx, arguments)?;
+            let raw_output = arguments
+                .get("raw_output")
+                .and_then(Value::as_bool)
+                .unwrap_or_default();
+            let timeout = Duration::from_secs(ctx.config.command_timeout_secs);
+            let output = match run(ctx, command, &args, cwd.as_deref(), None, timeout, true) {
+                Ok(output) => output,
+                Err(e) => return Ok(CallToolResult::error(e)),
+            };
+            let mut result = CallToolResult {
+                is_error: output.summary["exit_code"] != json!(0),
+                ..CallToolResult::text(output.summary.to_string())
+            };
+            if raw_output {
+                result = result
+                    .with_content(output.blob("stdout"))
+                    .with_content(output.blob("stderr"));
+            }
+            Ok(result)
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "openWorldHint": true }))
+    .with_dry_run(|ctx, arguments| {
+        let (command, args, cwd) = prepare(ctx, arguments)?;
+        Ok(json!({ "commandsRun": [{ "command": command, "args": args, "cwd": cwd }] }))
+    })
+    .available_when(|config| config.allow_commands)
+}
+
+/// The executable `run_command` is called with, its arguments and working directory, once
+/// allowed. Refusals are INTERNAL_ERROR, which the model sees.
+pub(crate) fn prepare<'a>(
+    ctx: &RequestContext,
+    arguments: &'a Value,
+) -> Result<(&'a str, Vec<&'a str>, Option<PathBuf>), JsonRpcError> {
+    let internal = |e| JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, e);
+    let command = required_str(arguments, "command")?;
+    let args: Vec<&str> = arguments
+        .get("args")
+        .and_then(Value::as_array)
+        .map(|args| args.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let roots = &ctx.config.roots;
+    let cwd = arguments
+        .get("cwd")
+        .and_then(Value::as_str)
+        .map(|cwd| roots::resolve(roots, cwd).map_err(internal))
+        .transpose()?;
+    let cwd = match &ctx.config.command_cwd {
+        None => cwd.or_else(|| roots.first().cloned()),
+        Some(fixed) => {
+            let fixed = roots::resolve(roots, &fixed.to_string_lossy()).map_err(internal)?;
+            match cwd {
+                Some(cwd) if cwd != fixed => {
+                    return Err(internal(format!(
+                        "commands run in {}, not in {}",
+                        fixed.display(),
+                        cwd.display()
+                    )))
+                }
+                _ => Some(fixed),
+            }
+        }
+    };
+    // A relative path runs from the working directory of the command
+    check_allowed(&ctx.config, command, cwd.as_deref()).map_err(internal)?;
+    Ok((command, args, cwd))
+}
+
+/// Checks `command`, run from `cwd`, against `command_allowlist` and `command_denylist`. An
+/// entry with a path (`/usr/bin/git`) matches the executable it names, whichever path the
+/// command reaches it by. A bare name (`git`) only matches the command of that name, which is
+/// looked up on the `PATH`; in the denylist, it also matches the executable found there.
+fn check_allowed(config: &Config, command: &str, cwd: Option<&Path>) -> Result<(), String> {
+    let executable = match is_bare(command) {
+        true => find_on_path(command),
+        false => cwd
+            .map_or_else(|| PathBuf::from(command), |cwd| cwd.join(command))
+            .canonicalize()
+            .ok(),
+    };
+    let matches = |entry: &String| match is_bare(entry) {
+        true => entry == command,
+        false => executable.is_some() && Path::new(entry).canonicalize().ok() == executable,
+    };
+    let denied = config.command_denylist.iter().any(|entry| {
+        matches(entry)
+            || (is_bare(entry) && executable.is_some() && find_on_path(entry) == executable)
+    });
+    if denied {
+        return Err(format!("command '{}' is denied", command));
+    }
+    match &config.command_allowlist {
+        Some(allowlist) if !allowlist.iter().any(matches) => {
+            Err(format!("command '{}' is not in the allowlist", command))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// True if `command` is a name without any directory, which is looked up on the `PATH`.
+fn is_bare(command: &str) -> bool {
+    Path::new(command)
+        .parent()
+        .is_some_and(|parent| parent.as_os_str().is_empty())
+}
+
+/// Canonical path of the executable `name` runs: the first file of that name in a directory of
+/// the `PATH`, with the `.exe` extension on Windows.
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let name = match cfg!(windows) && Path::new(name).extension().is_none() {
+        true => format!("{}.exe", name),
+        false => name.to_string(),
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+        .and_then(|path| path.canonicalize().ok())
+}
+
+/// What a command printed, and how it ended.
+pub(crate) struct Output {
+    /// `{exit_code, stdout, stderr, stdout_encoding, stderr_encoding, timed_out, truncated}`,
+    /// with the output decoded as text
+    pub summary: Value,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl Output {
+    /// The bytes of `stream` (`stdout` or `stderr`) as they were printed, as the base64-encoded
+    /// blob of `command:<stream>`, whose MIME type gives the encoding found if any.
+    pub fn blob(&self, stream: &str) -> Content {
+        let bytes = if stream == "stdout" {
+            &self.stdout
+        } else {
+            &self.stderr
+        };
+        let mime_type = match decode(bytes).1 {
+            "unknown" => "application/octet-stream".to_string(),
+            encoding => format!("text/plain; charset={}", encoding),
+        };
+        Content::resource(ResourceContents::blob(
+            format!("command:{}", stream),
+            bytes,
+            mime_type,
+        ))
+    }
+}
+
+/// `bytes` printed by a command as text, and the encoding they were decoded from: UTF-8, or
+/// UTF-16 when they start with its byte order mark (as some Windows programs print). Invalid
+/// bytes are replaced with U+FFFD, the encoding being `unknown`, except for a character cut at
+/// the end by `command_max_output`.
+pub(crate) fn decode(bytes: &[u8]) -> (String, &'static str) {
+    let utf16 = |units: &[u8], unit: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = units
+            .chunks_exact(2)
+            .map(|pair| unit([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match bytes {
+        [0xFF, 0xFE, units @ ..] => (utf16(units, u16::from_le_bytes), "utf-16le"),
+        [0xFE, 0xFF, units @ ..] => (utf16(units, u16::from_be_bytes), "utf-16be"),
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) => (text.to_string(), "utf-8"),
+            Err(e) => {
+                let text = String::from_utf8_lossy(bytes).into_owned();
+                if e.error_len().is_none() {
+                    (text, "utf-8")
+                } else {
+                    (text, "unknown")
+                }
+            }
+        },
+    }
+}
+
+/// Runs the command until it exits, times out (after `timeout` unless it is zero, or at the
+/// deadline of the call) or the request is cancelled, writing `stdin` to its standard input.
+/// When the client asked for progress notifications, the lines it prints are streamed as their
+/// messages along the way.
+/// `sandboxed` commands get the environment, CPU time and user of the `command_*` settings.
+pub(crate) fn run(
+    ctx: &RequestContext,
+    command: &str,
+    args: &[&str],
+    cwd: Option<&Path>,
+    stdin: Option<Vec<u8>>,
+    timeout: Duration,
+    sandboxed: bool,
+) -> Result<Output, String> {
+    let mut process = Command::new(command);
+    if sandboxed {
+        sandbox(&ctx.config, &mut process)?;
+    }
+    process
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = cwd {
+        process.current_dir(cwd);
+    }
+    tracing::info!(
+        "Running {} {:?} in {:?}",
+        command,
+        args,
+        cwd.map(PathBuf::from)
+    );
+    let mut child =
+        ProcessTree::spawn(&mut process).map_err(|e| format!("cannot run {}: {}", command, e))?;
+
+    if let (Some(mut pipe), Some(input)) = (child.process.stdin.take(), stdin) {
+        // Written from a thread so a child that doesn't read its input can't block us
+        thread::spawn(move || {
+            let _ = pipe.write_all(&input);
+        });
+    }
+
+    let max_output = ctx.config.command_max_output;
+    let (lines, streamed) = match ctx.wants_progress_messages() {
+        true => {
+            let (lines, streamed) = mpsc::channel();
+            (Some(lines), Some(streamed))
+        }
+        false => (None, None),
+    };
+    let stdout = child
+        .process
+        .stdout
+        .take()
+        .map(|out| capture(out, max_output, lines.clone()));
+    let stderr = child
+        .process
+        .stderr
+        .take()
+        .map(|err| capture(err, max_output, lines));
+
+    let timeout = ctx.limit_timeout(timeout);
+    let started = Instant::now();
+    let mut timed_out = false;
+    let mut lines_sent = 0;
+    let mut last_sent = Instant::now();
+    let status = loop {
+        if let Some(streamed) = &streamed {
+            if last_sent.elapsed() >= STREAM_INTERVAL {
+                stream(ctx, streamed, &mut lines_sent);
+                last_sent = Instant::now();
+            }
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) => {}
+            Err(e) => return Err(format!("cannot wait for {}: {}", command, e)),
+        }
+        let cancelled = ctx.cancellation.is_cancelled();
+        if cancelled || (timeout > Duration::ZERO && started.elapsed() >= timeout) {
+            timed_out = !cancelled;
+            if cancelled {
+                tracing::info!("Request cancelled, killing {}", command);
+            }
+            child.kill();
+            break None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let collect = |capture: Option<thread::JoinHandle<(Vec<u8>, bool)>>| {
+        capture
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    };
+    let (stdout, stdout_truncated) = collect(stdout);
+    let (stderr, stderr_truncated) = collect(stderr);
+    if let Some(streamed) = &streamed {
+        stream(ctx, streamed, &mut lines_sent);
+    }
+    let (stdout_text, stdout_encoding) = decode(&stdout);
+    let (stderr_text, stderr_encoding) = decode(&stderr);
+    Ok(Output {
+        summary: json!({
+            "exit_code": status.and_then(|status| status.code()),
+            "stdout": stdout_text,
+            "stderr": stderr_text,
+            "stdout_encoding": stdout_encoding,
+            "stderr_encoding": stderr_encoding,
+            "timed_out": timed_out,
+            "truncated": stdout_truncated || stderr_truncated,
+        }),
+        stdout,
+        stderr,
+    })
+}
+
+/// Sends the lines printed since the last time as the message of one progress notification,
+/// the progress being the number of lines sent so far.
+fn stream(ctx: &RequestContext, streamed: &Receiver<String>, lines_sent: &mut usize) {
+    let lines: Vec<String> = streamed.try_iter().collect();
+    if !lines.is_empty() {
+        *lines_sent += lines.len();
+        ctx.send_progress(*lines_sent, None, Some(&lines.join("\n")));
+    }
+}
+
+/// Applies the `command_env_allowlist`, `command_env`, `command_cpu_secs` and `command_user`
+/// settings to `process`.
+pub(crate) fn sandbox(config: &Config, process: &mut Command) -> Result<(), String> {
+    if let Some(allowlist) = &config.command_env_allowlist {
+        process.env_clear();
+        process.envs(std::env::vars_os().filter(|(name, _)| {
+            let name = name.to_string_lossy();
+            allowlist.iter().any(|pattern| matches(pattern, &name))
+        }));
+    }
+    process.envs(&config.command_env);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        if let Some(name) = &config.command_user {
+            let user = unix_user(name)?;
+            process
+                .uid(user.uid)
+                .gid(user.gid)
+                .env("HOME", &user.home)
+                .env("USER", name)
+                .env("LOGNAME", name);
+        }
+        if config.command_cpu_secs > 0 {
+            let cpu_secs = config.command_cpu_secs as libc::rlim_t;
+            // SAFETY: setrlimit is async-signal-safe and touches no memory of the parent
+            unsafe {
+                process.pre_exec(move || {
+                    let limit = libc::rlimit {
+                        rlim_cur: cpu_secs,
+                        rlim_max: cpu_secs,
+                    };
+                    match libc::setrlimit(libc::RLIMIT_CPU, &limit) {
+                        0 => Ok(()),
+                        _ => Err(io::Error::last_os_error()),
+                    }
+                });
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    if config.command_user.is_some() {
+        return Err("command_user is only supported on Unix".to_string());
+    }
+    Ok(())
+}
+
+/// A user commands can run as.
+#[cfg(unix)]
+struct UnixUser {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    home: PathBuf,
+}
+
+/// Looks up the user `name` in the password database.
+#[cfg(unix)]
+fn unix_user(name: &str) -> Result<UnixUser, String> {
+    use std::{
+        ffi::{CStr, CString, OsStr},
+        os::unix::ffi::OsStrExt,
+    };
+    let unknown = || format!("unknown user '{}'", name);
+    let c_name = CString::new(name).map_err(|_| unknown())?;
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: an all-zero passwd is valid
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    // SAFETY: getpwnam_r fills `entry` with pointers into `buffer`, which outlives their use
+    // below
+    let status = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut entry,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut found,
+        )
+    };
+    if status != 0 {
+        return Err(format!(
+            "cannot look up the user '{}': {}",
+            name,
+            io::Error::from_raw_os_error(status)
+        ));
+    }
+    if found.is_null() {
+        return Err(unknown());
+    }
+    let home = match entry.pw_dir.is_null() {
+        true => PathBuf::from("/"),
+        // SAFETY: a NUL-terminated string in `buffer`
+        false => PathBuf::from(OsStr::from_bytes(
+            unsafe { CStr::from_ptr(entry.pw_dir) }.to_bytes(),
+        )),
+    };
+    Ok(UnixUser {
+        uid: entry.pw_uid,
+        gid: entry.pw_gid,
+        home,
+    })
+}
+
+/// A started command with the processes it starts in turn, so that they are all killed together:
+/// the command leads a new process group on Unix, and is put in a job object on Windows.
+struct ProcessTree {
+    process: Child,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ProcessTree {
+    fn spawn(command: &mut Command) -> io::Result<ProcessTree> {
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(command, 0);
+        let process = command.spawn()?;
+        Ok(ProcessTree {
+            #[cfg(windows)]
+            job: job::Job::assign(&process)
+                .inspect_err(|e| tracing::warn!("Cannot put the command in a job object: {}", e))
+                .ok(),
+            process,
+        })
+    }
+
+    /// The exit status of the command once it has exited, after killing what it left running
+    /// in the background: those processes inherit its output pipes, which would otherwise stay
+    /// open and never let its output be read to the end.
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        #[cfg(unix)]
+        {
+            if !self.exited()? {
+                return Ok(None);
+            }
+            self.kill_group();
+        }
+        let status = self.process.try_wait()?;
+        #[cfg(windows)]
+        if status.is_some() {
+            self.kill_group();
+        }
+        Ok(status)
+    }
+
+    /// Whether the command has exited, checked without reaping it so that its group can still
+    /// be killed.
+    #[cfg(unix)]
+    fn exited(&self) -> io::Result<bool> {
+        // SAFETY: siginfo_t is plain data, for which zeroes are valid
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+        // SAFETY: `info` is valid for waitid to write for the duration of the call
+        if unsafe { libc::waitid(libc::P_PID, self.process.id(), &mut info, options) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: si_pid is the command's once it has exited, and left zeroed while it runs
+        Ok(unsafe { info.si_pid() } != 0)
+    }
+
+    /// Kills everything the command started, and the command itself if it still runs.
+    fn kill_group(&self) {
+        #[cfg(unix)]
+        if let Ok(group) = libc::pid_t::try_from(self.process.id()) {
+            // SAFETY: kill has no memory safety requirements; the group is the one the command
+            // leads, which can't be reused while the command isn't reaped
+            unsafe {
+                libc::kill(-group, libc::SIGKILL);
+            }
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate();
+        }
+    }
+
+    /// Kills the command and everything it started, then reaps the command.
+    fn kill(&mut self) {
+        self.kill_group();
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use std::{io, os::windows::io::AsRawHandle, process::Child, ptr};
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW, TerminateJobObject},
+    };
+
+    /// Job object holding a command and, by inheritance, the processes it starts.
+    pub(super) struct Job(HANDLE);
+
+    impl Job {
+        pub(super) fn assign(process: &Child) -> io::Result<Job> {
+            // SAFETY: an anonymous job with default security, whose handle is owned by the Job
+            let job = unsafe { CreateJobObjectW(ptr::null(), ptr::null()) };
+            if job.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let job = Job(job);
+            // SAFETY: both handles are valid for the duration of the call
+            if unsafe { AssignProcessToJobObject(job.0, process.as_raw_handle() as HANDLE) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(job)
+        }
+
+        pub(super) fn terminate(&self) {
+            // SAFETY: the handle is valid until the Job is dropped
+            unsafe {
+                TerminateJobObject(self.0, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned by the Job and closed once
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+/// Reads `source` to the end on a separate thread so the child never blocks on a full pipe,
+/// keeping at most `max_size` bytes, each line of which is sent to `lines` once complete.
+/// Returns the bytes kept and whether some were not.
+fn capture<R: Read + Send + 'static>(
+    mut source: R,
+    max_size: usize,
+    lines: Option<Sender<String>>,
+) -> thread::JoinHandle<(Vec<u8>, bool)> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        let mut truncated = false;
+        // Line being printed, without its end yet
+        let mut line = Vec::new();
+        let mut buf = [0; 8192];
+        while let Ok(n) = source.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let room = max_size.saturating_sub(kept.len());
+            let read = &buf[..n.min(room)];
+            kept.extend_from_slice(read);
+            truncated |= n > room;
+            if let Some(lines) = &lines {
+                let mut rest = read;
+                while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+                    line.extend_from_slice(&rest[..end]);
+                    send_line(lines, &mut line);
+                    rest = &rest[end + 1..];
+                }
+                line.extend_from_slice(rest);
+                if line.len() >= MAX_STREAMED_LINE {
+                    send_line(lines, &mut line);
+                }
+            }
+        }
+        if let Some(lines) = lines.filter(|_| !line.is_empty()) {
+            send_line(&lines, &mut line);
+        }
+        (kept, truncated)
+    })
+}
+
+fn send_line(lines: &Sender<String>, line: &mut Vec<u8>) {
+    let text = String::from_utf8_lossy(line);
+    let _ = lines.send(text.trim_end_matches('\r').to_string());
+    line.clear();
+}
@@ -0,0 +1,321 @@
+//! CSV and TSV resources as tables: the `csv_preview` tool, and the `view` query parameter of
+//! the `file://` resources (`file:///data/sales.csv?view=markdown&max_rows=20`).
+
+use crate::{
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{CallToolResult, ResourceContents},
+    tools::{required_str, Tool},
+};
+use serde_json::{json, Value};
+
+/// Rows of a table returned by default, the header not included.
+const DEFAULT_ROWS: usize = 50;
+/// Upper bound of `max_rows`.
+const MAX_ROWS: usize = 1000;
+/// Columns of a table returned by default.
+const DEFAULT_COLUMNS: usize = 20;
+/// Upper bound of `max_columns`.
+const MAX_COLUMNS: usize = 200;
+
+/// Representation of a CSV or TSV resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum View {
+    /// JSON object with the columns and the rows
+    Rows,
+    /// Markdown table
+    Markdown,
+}
+
+impl View {
+    fn parse(view: &str) -> Option<View> {
+        match view {
+            "rows" => Some(View::Rows),
+            "markdown" => Some(View::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// How much of a table to return.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub rows: usize,
+    pub columns: usize,
+}
+
+impl Limits {
+    fn new(rows: Option<u64>, columns: Option<u64>) -> Limits {
+        let clamp = |value: Option<u64>, default: usize, max: usize| {
+            value.map_or(default, |value| (value as usize).clamp(1, max))
+        };
+        Limits {
+            rows: clamp(rows, DEFAULT_ROWS, MAX_ROWS),
+            columns: clamp(columns, DEFAULT_COLUMNS, MAX_COLUMNS),
+        }
+    }
+}
+
+/// `csv_preview` tool returning the first rows and columns of a CSV or TSV resource.
+pub fn preview_tool() -> Tool {
+    Tool::new(
+        "csv_preview",
+        "Parse a CSV or TSV resource and return its first rows and columns, as JSON (the \
+         column names from the first line and the rows as arrays of strings) or as a markdown \
+         table. Also tells how many rows and columns there are in all.",
+        json!({
+            "type": "object",
+            "properties": {
+                "uri": { "type": "string", "description": "URI of the resource, e.g. file:///data/sales.csv" },
+                "format": { "type": "string", "enum": ["rows", "markdown"], "description": "rows by default" },
+                "max_rows": { "type": "integer", "minimum": 1, "maximum": MAX_ROWS, "description": "Rows returned besides the header, 50 by default" },
+                "max_columns": { "type": "integer", "minimum": 1, "maximum": MAX_COLUMNS, "description": "Columns returned, 20 by default" },
+                "delimiter": { "type": "string", "description": "Field delimiter, a tab for .tsv resources and a comma else by default" }
+            },
+            "required": ["uri"]
+        }),
+        |ctx, arguments| {
+            let uri = required_str(arguments, "uri")?;
+            let view = match arguments.get("format").and_then(Value::as_str) {
+                None => View::Rows,
+                Some(format) => View::parse(format).ok_or_else(|| {
+                    JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("unsupported format '{}'", format),
+                    )
+                })?,
+            };
+            let number = |name| arguments.get(name).and_then(Value::as_u64);
+            let limits = Limits::new(number("max_rows"), number("max_columns"));
+            let Some(result) = ctx.read_resource(uri) else {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    format!("Resource not found: {}", uri),
+                ));
+            };
+            let result = result?;
+            let Some(contents) = result.contents.iter().find(|c| c.text.is_some()) else {
+                return Ok(CallToolResult::error(format!(
+                    "resource {} has no text contents",
+                    uri
+                )));
+            };
+            let delimiter = match arguments.get("delimiter").and_then(Value::as_str) {
+                Some(delimiter) => match delimiter.chars().collect::<Vec<_>>()[..] {
+                    [delimiter] => delimiter,
+                    _ => {
+                        return Err(JsonRpcError::new(
+                            ERROR_CODE_INVALID_PARAMS,
+                            "the delimiter must be a single character",
+                        ))
+                    }
+                },
+                None => delimiter_of(contents),
+            };
+            let table = Table::parse(contents.text.as_deref().unwrap_or_default(), delimiter);
+            Ok(match view {
+                View::Rows => CallToolResult::structured(table.to_json(limits)),
+                View::Markdown => CallToolResult::text(table.to_markdown(limits)),
+            })
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// True for the MIME types of CSV and TSV files.
+pub(crate) fn is_table_mime_type(mime_type: &str) -> bool {
+    matches!(
+        mime_type,
+        "text/csv" | "text/tab-separated-values" | "application/csv"
+    )
+}
+
+/// Tabs for TSV, commas for everything else.
+fn delimiter_of(contents: &ResourceContents) -> char {
+    let tsv = contents.mime_type.as_deref() == Some("text/tab-separated-values")
+        || contents
+            .uri
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .ends_with(".tsv");
+    if tsv {
+        '\t'
+    } else {
+        ','
+    }
+}
+
+/// Table asked for in the query of a resource URI.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TableQuery {
+    pub view: View,
+    pub limits: Limits,
+}
+
+/// Splits the `view`, `max_rows` and `max_columns` query parameters off `uri`. URIs without
+/// a `view` are returned whole, whatever their query.
+pub(crate) fn split_view(uri: &str) -> Result<(&str, Option<TableQuery>), JsonRpcError> {
+    let Some((base, query)) = uri.split_once('?') else {
+        return Ok((uri, None));
+    };
+    let params: Vec<(&str, &str)> = query
+        .split('&')
+        .map(|param| param.split_once('=').unwrap_or((param, "")))
+        .collect();
+    let param = |name| params.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+    let Some(view) = param("view") else {
+        return Ok((uri, None));
+    };
+    let view = View::parse(view).ok_or_else(|| {
+        JsonRpcError::new(
+            ERROR_CODE_INVALID_PARAMS,
+            format!("unsupported view '{}'", view),
+        )
+    })?;
+    let number = |name| -> Result<Option<u64>, JsonRpcError> {
+        param(name)
+            .map(|value| {
+                value.parse().map_err(|_| {
+                    JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("{} must be a positive integer", name),
+                    )
+                })
+            })
+            .transpose()
+    };
+    let limits = Limits::new(number("max_rows")?, number("max_columns")?);
+    Ok((base, Some(TableQuery { view, limits })))
+}
+
+/// `contents` of a CSV or TSV file replaced with the table `query` asks for.
+pub(crate) fn render(contents: ResourceContents, query: TableQuery) -> ResourceContents {
+    let TableQuery { view, limits } = query;
+    let delimiter = delimiter_of(&contents);
+    let table = Table::parse(contents.text.as_deref().unwrap_or_default(), delimiter);
+    let (text, mime_type) = match view {
+        View::Rows => (table.to_json(limits).to_string(), "application/json"),
+        View::Markdown => (table.to_markdown(limits), "text/markdown"),
+    };
+    ResourceContents {
+        text: Some(text),
+        mime_type: Some(mime_type.to_string()),
+        ..contents
+    }
+}
+
+/// Parsed CSV: the first record is taken as the header.
+#[derive(Debug, Default, PartialEq)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Parses `text` as RFC 4180 does, with `delimiter` between the fields: fields may be
+    /// quoted with `"`, doubling the quotes they contain, and span several lines. Blank lines
+    /// are skipped.
+    pub fn parse(text: &str, delimiter: char) -> Table {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                }
+                '"' if field.is_empty() => quoted = true,
+                c if quoted => field.push(c),
+                c if c == delimiter => record.push(std::mem::take(&mut field)),
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    if !field.is_empty() || !record.is_empty() {
+                        record.push(std::mem::take(&mut field));
+                        records.push(std::mem::take(&mut record));
+                    }
+                }
+                c => field.push(c),
+            }
+        }
+        if !field.is_empty() || !record.is_empty() {
+            record.push(field);
+            records.push(record);
+        }
+        let mut records = records.into_iter();
+        Table {
+            columns: records.next().unwrap_or_default(),
+            rows: records.collect(),
+        }
+    }
+
+    fn column_count(&self) -> usize {
+        self.rows
+            .iter()
+            .map(Vec::len)
+            .chain([self.columns.len()])
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// `{columns, rows, total_rows, total_columns, truncated}` with the rows as arrays, cut
+    /// to `limits`.
+    pub fn to_json(&self, limits: Limits) -> Value {
+        let columns: Vec<&String> = self.columns.iter().take(limits.columns).collect();
+        let rows: Vec<Vec<&String>> = self
+            .rows
+            .iter()
+            .take(limits.rows)
+            .map(|row| row.iter().take(limits.columns).collect())
+            .collect();
+        let total_columns = self.column_count();
+        json!({
+            "columns": columns,
+            "rows": rows,
+            "total_rows": self.rows.len(),
+            "total_columns": total_columns,
+            "truncated": self.rows.len() > limits.rows || total_columns > limits.columns,
+        })
+    }
+
+    /// Markdown table cut to `limits`, followed by a note of what was left out.
+    pub fn to_markdown(&self, limits: Limits) -> String {
+        let width = self.column_count().min(limits.columns);
+        if width == 0 {
+            return String::new();
+        }
+        let line = |cells: &[String]| {
+            let cells: Vec<String> = (0..width)
+                .map(|i| cells.get(i).map(|cell| escape(cell)).unwrap_or_default())
+                .collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let mut markdown = line(&self.columns);
+        markdown.push_str(&format!("|{}\n", " --- |".repeat(width)));
+        for row in self.rows.iter().take(limits.rows) {
+            markdown.push_str(&line(row));
+        }
+        let total_columns = self.column_count();
+        if self.rows.len() > limits.rows || total_columns > limits.columns {
+            markdown.push_str(&format!(
+                "\n({} of {} rows, {} of {} columns)\n",
+                self.rows.len().min(limits.rows),
+                self.rows.len(),
+                width,
+                total_columns
+            ));
+        }
+        markdown
+    }
+}
+
+/// A cell as markdown tables can hold it: pipes escaped and line breaks turned into spaces.
+fn escape(cell: &str) -> String {
+    cell.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
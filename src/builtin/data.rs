@@ -0,0 +1,57 @@
+//! `data:` URIs (RFC 2397), read as resources: `data:[<media type>][;base64],<data>`.
+
+use crate::{
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{ReadResourceResult, ResourceContents},
+    resources::SchemeHandler,
+    roots,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+/// Media type of the data URIs that don't give one.
+const DEFAULT_MEDIA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// Reads `data:` URIs, returning their data as text when the media type is textual and the
+/// data UTF-8, and as a blob otherwise.
+pub struct DataScheme;
+
+impl SchemeHandler for DataScheme {
+    fn read(&self, _ctx: &RequestContext, uri: &str) -> Result<ReadResourceResult, JsonRpcError> {
+        let invalid = |message: &str| {
+            JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, format!("{}: {}", message, uri))
+                .with_data("pointer", "/uri")
+        };
+        let (header, data) = uri
+            .get("data:".len()..)
+            .and_then(|rest| rest.split_once(','))
+            .ok_or_else(|| invalid("data URI without ','"))?;
+        let (media_type, base64) = match header.strip_suffix(";base64") {
+            Some(media_type) => (media_type, true),
+            None => (header, false),
+        };
+        let media_type = match media_type {
+            "" => DEFAULT_MEDIA_TYPE.to_string(),
+            // Only parameters, such as ";charset=utf-8"
+            parameters if parameters.starts_with(';') => format!("text/plain{}", parameters),
+            media_type => media_type.to_string(),
+        };
+        let bytes = roots::percent_decode(data);
+        let bytes = match base64 {
+            // Base64 data may be percent-encoded too
+            true => BASE64
+                .decode(bytes.trim_ascii())
+                .map_err(|_| invalid("invalid base64 in data URI"))?,
+            false => bytes,
+        };
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+        let textual = essence.starts_with("text/")
+            || matches!(essence, "application/json" | "application/xml");
+        let contents = match String::from_utf8(bytes) {
+            Ok(text) if textual => ResourceContents::text(uri, text).with_mime_type(media_type),
+            Ok(text) => ResourceContents::blob(uri, text.as_bytes(), media_type),
+            Err(e) => ResourceContents::blob(uri, e.as_bytes(), media_type),
+        };
+        Ok(ReadResourceResult::new(vec![contents]))
+    }
+}
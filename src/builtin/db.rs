@@ -0,0 +1,539 @@
+//! Queries against the Postgres and MySQL databases listed in `databases`, through pools of
+//! connections kept for the life of the server by [`Databases`].
+
+use crate::{
+    builtin::runtime,
+    config::Config,
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{CallToolResult, ReadResourceResult, ResourceContents},
+    resources::{Resource, ResourceProvider},
+    tools::{required_str, Tool},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::TryStreamExt;
+use serde_json::{json, Value};
+use sqlx_core::{
+    arguments::IntoArguments,
+    column::Column,
+    database::Database,
+    encode::Encode,
+    executor::Executor,
+    pool::{Pool, PoolOptions},
+    query::{query, Query},
+    row::Row,
+    sql_str::{AssertSqlSafe, SqlSafeStr},
+    statement::Statement,
+    type_info::TypeInfo,
+    types::{chrono, Decimal, Json, Type, Uuid},
+};
+use sqlx_mysql::{MySql, MySqlRow, MySqlTypeInfo};
+use sqlx_postgres::{types::Oid, PgRow, PgTypeInfo, Postgres};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Maximum number of rows returned by `db_query`.
+const MAX_ROWS: usize = 1000;
+
+/// The pools of the configured databases, created on first use and shared by the tool and the
+/// schema resources.
+#[derive(Default)]
+pub struct Databases {
+    /// Pool of each database by name, with the connection string it was created for
+    pools: Mutex<HashMap<String, (String, Connections)>>,
+}
+
+/// A pool of either kind.
+#[derive(Clone)]
+enum Connections {
+    Postgres(Pool<Postgres>),
+    MySql(Pool<MySql>),
+}
+
+impl Databases {
+    pub fn new() -> Arc<Databases> {
+        Arc::new(Databases::default())
+    }
+
+    /// The pool of a configured database, replaced when its connection string changed.
+    fn pool(&self, config: &Config, database: &str) -> Result<Connections, String> {
+        let Some(url) = config.databases.get(database) else {
+            return Err(format!("unknown database '{}'", database));
+        };
+        let mut pools = self.pools.lock().unwrap();
+        if let Some((pooled, connections)) = pools.get(database) {
+            if pooled == url {
+                return Ok(connections.clone());
+            }
+        }
+        // The pools connect lazily, but within the runtime
+        let _entered = runtime::runtime()?.enter();
+        let size = config.database_pool_size.max(1);
+        let timeout = Duration::from_secs(config.database_timeout_secs.max(1));
+        let invalid = |e: sqlx_core::Error| format!("invalid database '{}': {}", database, e);
+        let connections = match url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("postgres" | "postgresql") => Connections::Postgres(
+                PoolOptions::new()
+                    .max_connections(size)
+                    .acquire_timeout(timeout)
+                    .connect_lazy(url)
+                    .map_err(invalid)?,
+            ),
+            Some("mysql" | "mariadb") => Connections::MySql(
+                PoolOptions::new()
+                    .max_connections(size)
+                    .acquire_timeout(timeout)
+                    .connect_lazy(url)
+                    .map_err(invalid)?,
+            ),
+            _ => {
+                return Err(format!(
+                    "database '{}' is neither a postgres:// nor a mysql:// URL",
+                    database
+                ))
+            }
+        };
+        pools.insert(database.to_string(), (url.clone(), connections.clone()));
+        Ok(connections)
+    }
+}
+
+/// What the tool needs to know of a database besides what sqlx has in common.
+trait Driver: Database {
+    /// Tables of the current schema, by name
+    const TABLES: &'static str;
+    /// Columns of the table bound to the only parameter, with their type, nullability, default
+    /// and whether they're part of the primary key
+    const COLUMNS: &'static str;
+
+    /// Type of the NULL parameters, which the database infers when it can.
+    fn null_type() -> Self::TypeInfo;
+
+    fn rows_affected(result: &Self::QueryResult) -> u64;
+
+    /// Value of a column of a row as JSON.
+    fn to_json(row: &Self::Row, index: usize) -> Value;
+}
+
+/// `db_query` tool running one SQL statement with parameters (`$1`, `$2`... for Postgres, `?`
+/// for MySQL). Only offered when databases are configured.
+pub fn query_tool(databases: &Arc<Databases>) -> Tool {
+    let databases = databases.clone();
+    Tool::new(
+        "db_query",
+        "Run one SQL statement against a configured Postgres or MySQL database. Queries return \
+         a JSON object with the column names and the rows; other statements return the number \
+         of changed rows. Statements run in a read-only transaction unless the server allows \
+         writes to the databases.",
+        json!({
+            "type": "object",
+            "properties": {
+                "database": { "type": "string", "description": "Name of the database" },
+                "sql": { "type": "string", "description": "SQL statement, with $1, $2... placeholders for Postgres and ? for MySQL" },
+                "params": { "type": "array", "description": "Values bound to the placeholders" }
+            },
+            "required": ["database", "sql"]
+        }),
+        move |ctx, arguments| {
+            let database = required_str(arguments, "database")?;
+            let sql = required_str(arguments, "sql")?;
+            let params: &[Value] = arguments
+                .get("params")
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let read_only = ctx.config.database_read_only || ctx.config.read_only;
+            let timeout = Duration::from_secs(ctx.config.database_timeout_secs);
+            let timeout = ctx.limit_timeout(timeout);
+            let result = databases
+                .pool(&ctx.config, database)
+                .and_then(|connections| {
+                    tracing::info!("Running SQL on {}: {}", database, sql);
+                    let sql = sql.to_string();
+                    match connections {
+                        Connections::Postgres(pool) => {
+                            runtime::block_on(timeout, run(&pool, sql, params, read_only))
+                        }
+                        Connections::MySql(pool) => {
+                            runtime::block_on(timeout, run(&pool, sql, params, read_only))
+                        }
+                    }
+                });
+            match result {
+                Ok(result) => Ok(CallToolResult::text(result.to_string())),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .available_when(|config| !config.databases.is_empty())
+    .guards_read_only()
+}
+
+/// Runs `sql` in a transaction, rolled back when `read_only` so that the database refuses the
+/// changes and nothing is kept if it doesn't.
+async fn run<DB: Driver>(
+    pool: &Pool<DB>,
+    sql: String,
+    params: &[Value],
+    read_only: bool,
+) -> Result<Value, sqlx_core::Error>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    DB::Arguments: IntoArguments<DB>,
+    bool: for<'q> Encode<'q, DB> + Type<DB>,
+    i64: for<'q> Encode<'q, DB> + Type<DB>,
+    f64: for<'q> Encode<'q, DB> + Type<DB>,
+    String: for<'q> Encode<'q, DB> + Type<DB>,
+    Json<Value>: for<'q> Encode<'q, DB> + Type<DB>,
+    Option<String>: for<'q> Encode<'q, DB>,
+{
+    let mut transaction = match read_only {
+        true => pool.begin_with("START TRANSACTION READ ONLY").await?,
+        false => pool.begin().await?,
+    };
+    let sql = AssertSqlSafe(sql).into_sql_str();
+    // With the types of the parameters bound, which Postgres would otherwise infer
+    let types: Vec<DB::TypeInfo> = params.iter().map(parameter_type::<DB>).collect();
+    let statement = (&mut *transaction)
+        .prepare_with(sql.clone(), &types)
+        .await?;
+    let columns: Vec<String> = statement
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect();
+    let statement = params
+        .iter()
+        .fold(query::<DB>(sql), |statement, value| bind(statement, value));
+
+    let result = if columns.is_empty() {
+        let result = statement.execute(&mut *transaction).await?;
+        json!({ "changes": DB::rows_affected(&result) })
+    } else {
+        let mut rows = statement.fetch(&mut *transaction);
+        let mut result = Vec::new();
+        let mut truncated = false;
+        while let Some(row) = rows.try_next().await? {
+            if result.len() == MAX_ROWS {
+                truncated = true;
+                break;
+            }
+            let values = (0..columns.len()).map(|i| DB::to_json(&row, i)).collect();
+            result.push(Value::Array(values));
+        }
+        drop(rows);
+        json!({ "columns": columns, "rows": result, "truncated": truncated })
+    };
+    match read_only {
+        true => transaction.rollback().await?,
+        false => transaction.commit().await?,
+    }
+    Ok(result)
+}
+
+/// SQL type of a JSON parameter, as bound by [`bind`].
+fn parameter_type<DB: Driver>(value: &Value) -> DB::TypeInfo
+where
+    bool: Type<DB>,
+    i64: Type<DB>,
+    f64: Type<DB>,
+    String: Type<DB>,
+    Json<Value>: Type<DB>,
+{
+    match value {
+        Value::Null => DB::null_type(),
+        Value::Bool(_) => bool::type_info(),
+        Value::Number(n) if n.is_i64() => i64::type_info(),
+        Value::Number(_) => f64::type_info(),
+        Value::String(_) => String::type_info(),
+        _ => Json::<Value>::type_info(),
+    }
+}
+
+/// Binds a JSON parameter as the SQL type closest to its JSON type, arrays and objects as JSON.
+fn bind<'q, DB: Database>(
+    statement: Query<'q, DB, DB::Arguments>,
+    value: &Value,
+) -> Query<'q, DB, DB::Arguments>
+where
+    bool: for<'a> Encode<'a, DB> + Type<DB>,
+    i64: for<'a> Encode<'a, DB> + Type<DB>,
+    f64: for<'a> Encode<'a, DB> + Type<DB>,
+    String: for<'a> Encode<'a, DB> + Type<DB>,
+    Json<Value>: for<'a> Encode<'a, DB> + Type<DB>,
+    Option<String>: for<'a> Encode<'a, DB>,
+{
+    match value {
+        Value::Null => statement.bind(None::<String>),
+        Value::Bool(b) => statement.bind(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => statement.bind(i),
+            None => statement.bind(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => statement.bind(s.clone()),
+        other => statement.bind(Json(other.clone())),
+    }
+}
+
+/// JSON of a decoded column value, null for SQL NULL, and of the name of its type when it
+/// can't be decoded.
+fn decoded<T>(
+    value: Result<Option<T>, sqlx_core::Error>,
+    type_name: &str,
+    to_json: impl FnOnce(T) -> Value,
+) -> Value {
+    match value {
+        Ok(value) => value.map(to_json).unwrap_or(Value::Null),
+        Err(_) => json!(format!("[{}]", type_name)),
+    }
+}
+
+impl Driver for Postgres {
+    const TABLES: &'static str = "SELECT table_name::text FROM information_schema.tables \
+         WHERE table_schema = current_schema() ORDER BY table_name";
+    const COLUMNS: &'static str = "SELECT column_name::text, data_type::text, \
+         is_nullable = 'YES', column_default::text, EXISTS (\
+             SELECT 1 FROM information_schema.table_constraints t \
+             JOIN information_schema.key_column_usage k \
+             ON k.constraint_name = t.constraint_name AND k.table_schema = t.table_schema \
+             WHERE t.constraint_type = 'PRIMARY KEY' AND t.table_schema = c.table_schema \
+             AND t.table_name = c.table_name AND k.column_name = c.column_name) \
+         FROM information_schema.columns c \
+         WHERE table_schema = current_schema() AND table_name = $1 ORDER BY ordinal_position";
+
+    fn null_type() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(0))
+    }
+
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+
+    fn to_json(row: &PgRow, index: usize) -> Value {
+        let name = row.columns()[index].type_info().name();
+        match name {
+            "BOOL" => decoded(row.try_get::<Option<bool>, _>(index), name, Value::from),
+            "INT2" => decoded(row.try_get::<Option<i16>, _>(index), name, Value::from),
+            "INT4" => decoded(row.try_get::<Option<i32>, _>(index), name, Value::from),
+            "INT8" => decoded(row.try_get::<Option<i64>, _>(index), name, Value::from),
+            "FLOAT4" => decoded(row.try_get::<Option<f32>, _>(index), name, Value::from),
+            "FLOAT8" => decoded(row.try_get::<Option<f64>, _>(index), name, Value::from),
+            // As strings, which keep their precision
+            "NUMERIC" => decoded(row.try_get::<Option<Decimal>, _>(index), name, |d| {
+                json!(d.to_string())
+            }),
+            "JSON" | "JSONB" => {
+                decoded(row.try_get::<Option<Json<Value>>, _>(index), name, |j| j.0)
+            }
+            "BYTEA" => decoded(row.try_get::<Option<Vec<u8>>, _>(index), name, |b| {
+                json!(BASE64.encode(b))
+            }),
+            "UUID" => decoded(row.try_get::<Option<Uuid>, _>(index), name, |u| {
+                json!(u.to_string())
+            }),
+            "DATE" => decoded(
+                row.try_get::<Option<chrono::NaiveDate>, _>(index),
+                name,
+                |d| json!(d.to_string()),
+            ),
+            "TIME" => decoded(
+                row.try_get::<Option<chrono::NaiveTime>, _>(index),
+                name,
+                |t| json!(t.to_string()),
+            ),
+            "TIMESTAMP" => decoded(
+                row.try_get::<Option<chrono::NaiveDateTime>, _>(index),
+                name,
+                |t| json!(t.and_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+            ),
+            "TIMESTAMPTZ" => decoded(
+                row.try_get::<Option<chrono::DateTime<chrono::Utc>>, _>(index),
+                name,
+                |t| json!(t.to_rfc3339()),
+            ),
+            _ => decoded(row.try_get::<Option<String>, _>(index), name, Value::from),
+        }
+    }
+}
+
+impl Driver for MySql {
+    const TABLES: &'static str = "SELECT CAST(table_name AS CHAR) FROM information_schema.tables \
+         WHERE table_schema = DATABASE() ORDER BY table_name";
+    const COLUMNS: &'static str = "SELECT CAST(column_name AS CHAR), CAST(column_type AS CHAR), \
+         is_nullable = 'YES', CAST(column_default AS CHAR), column_key = 'PRI' \
+         FROM information_schema.columns \
+         WHERE table_schema = DATABASE() AND table_name = ? ORDER BY ordinal_position";
+
+    fn null_type() -> MySqlTypeInfo {
+        <String as Type<MySql>>::type_info()
+    }
+
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+
+    fn to_json(row: &MySqlRow, index: usize) -> Value {
+        let name = row.columns()[index].type_info().name();
+        match name {
+            "BOOLEAN" => decoded(row.try_get::<Option<bool>, _>(index), name, Value::from),
+            "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" | "YEAR" => {
+                match row.try_get::<Option<i64>, _>(index) {
+                    Ok(value) => value.map(Value::from).unwrap_or(Value::Null),
+                    Err(_) => decoded(row.try_get::<Option<u16>, _>(index), name, Value::from),
+                }
+            }
+            "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED"
+            | "BIGINT UNSIGNED" => decoded(row.try_get::<Option<u64>, _>(index), name, Value::from),
+            "FLOAT" => decoded(row.try_get::<Option<f32>, _>(index), name, Value::from),
+            "DOUBLE" => decoded(row.try_get::<Option<f64>, _>(index), name, Value::from),
+            // As strings, which keep their precision
+            "DECIMAL" => decoded(row.try_get::<Option<Decimal>, _>(index), name, |d| {
+                json!(d.to_string())
+            }),
+            "JSON" => decoded(row.try_get::<Option<Json<Value>>, _>(index), name, |j| j.0),
+            "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "BIT"
+            | "GEOMETRY" => decoded(row.try_get::<Option<Vec<u8>>, _>(index), name, |b| {
+                json!(BASE64.encode(b))
+            }),
+            "DATE" => decoded(
+                row.try_get::<Option<chrono::NaiveDate>, _>(index),
+                name,
+                |d| json!(d.to_string()),
+            ),
+            "TIME" => decoded(
+                row.try_get::<Option<chrono::NaiveTime>, _>(index),
+                name,
+                |t| json!(t.to_string()),
+            ),
+            "DATETIME" => decoded(
+                row.try_get::<Option<chrono::NaiveDateTime>, _>(index),
+                name,
+                |t| json!(t.and_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+            ),
+            "TIMESTAMP" => decoded(
+                row.try_get::<Option<chrono::DateTime<chrono::Utc>>, _>(index),
+                name,
+                |t| json!(t.to_rfc3339()),
+            ),
+            _ => decoded(row.try_get::<Option<String>, _>(index), name, Value::from),
+        }
+    }
+}
+
+/// Names of the tables of the current schema.
+async fn tables<DB: Driver>(pool: &Pool<DB>) -> Result<Vec<String>, sqlx_core::Error>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    DB::Arguments: IntoArguments<DB>,
+{
+    let mut connection = pool.acquire().await?;
+    let rows = query::<DB>(DB::TABLES).fetch_all(&mut *connection).await?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| DB::to_json(row, 0).as_str().map(str::to_string))
+        .collect())
+}
+
+/// Columns of `table`, none if there's no such table.
+async fn columns<DB: Driver>(pool: &Pool<DB>, table: &str) -> Result<Vec<Value>, sqlx_core::Error>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    DB::Arguments: IntoArguments<DB>,
+    String: for<'a> Encode<'a, DB> + Type<DB>,
+{
+    let mut connection = pool.acquire().await?;
+    let rows = query::<DB>(DB::COLUMNS)
+        .bind(table.to_string())
+        .fetch_all(&mut *connection)
+        .await?;
+    // MySQL has no booleans, only integers
+    let flag = |value: Value| value == json!(true) || value == json!(1);
+    Ok(rows
+        .iter()
+        .map(|row| {
+            json!({
+                "name": DB::to_json(row, 0),
+                "type": DB::to_json(row, 1),
+                "not_null": !flag(DB::to_json(row, 2)),
+                "default": DB::to_json(row, 3),
+                "primary_key": flag(DB::to_json(row, 4)),
+            })
+        })
+        .collect())
+}
+
+/// Exposes the schema of each table of the configured databases as a `db://<database>/<table>`
+/// resource.
+pub struct SchemaResources {
+    databases: Arc<Databases>,
+}
+
+impl SchemaResources {
+    pub fn new(databases: &Arc<Databases>) -> SchemaResources {
+        SchemaResources {
+            databases: databases.clone(),
+        }
+    }
+
+    fn tables(&self, config: &Config, database: &str) -> Result<Vec<String>, String> {
+        let timeout = Duration::from_secs(config.database_timeout_secs);
+        match self.databases.pool(config, database)? {
+            Connections::Postgres(pool) => runtime::block_on(timeout, tables(&pool)),
+            Connections::MySql(pool) => runtime::block_on(timeout, tables(&pool)),
+        }
+    }
+
+    fn schema(&self, config: &Config, database: &str, table: &str) -> Result<Value, String> {
+        let timeout = Duration::from_secs(config.database_timeout_secs);
+        let columns = match self.databases.pool(config, database)? {
+            Connections::Postgres(pool) => runtime::block_on(timeout, columns(&pool, table))?,
+            Connections::MySql(pool) => runtime::block_on(timeout, columns(&pool, table))?,
+        };
+        if columns.is_empty() {
+            return Err(format!("unknown table '{}'", table));
+        }
+        Ok(json!({ "table": table, "columns": columns }))
+    }
+}
+
+impl ResourceProvider for SchemaResources {
+    fn list(&self, ctx: &RequestContext) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        for database in ctx.config.databases.keys() {
+            let tables = self.tables(&ctx.config, database).unwrap_or_else(|e| {
+                tracing::warn!("Cannot list the tables of {}: {}", database, e);
+                Vec::new()
+            });
+            for table in tables {
+                resources.push(Resource {
+                    uri: format!("db://{}/{}", database, table),
+                    name: format!("{}.{}", database, table),
+                    description: Some(format!("Schema of table {}", table)),
+                    mime_type: Some("application/json".to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+        resources
+    }
+
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let (database, table) = uri.strip_prefix("db://")?.split_once('/')?;
+        let result = self
+            .schema(&ctx.config, database, table)
+            .map(|schema| {
+                let contents = ResourceContents::text(uri, schema.to_string())
+                    .with_mime_type("application/json");
+                ReadResourceResult::new(vec![contents])
+            })
+            .map_err(|e| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e));
+        Some(result)
+    }
+}
@@ -0,0 +1,296 @@
+//! Tools operating on the containers of the local Docker engine, or of the one at
+//! `docker_host`: listing, inspecting, tailing the logs of, starting and stopping them. Only
+//! offered when `allow_docker` is set.
+
+use crate::{
+    builtin::runtime,
+    config::Config,
+    context::RequestContext,
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use bollard::{
+    container::LogOutput,
+    query_parameters::{
+        InspectContainerOptions, ListContainersOptionsBuilder, LogsOptionsBuilder,
+        StopContainerOptionsBuilder,
+    },
+    Docker,
+};
+use futures_util::TryStreamExt;
+use serde_json::{json, Value};
+use std::{sync::Mutex, time::Duration};
+
+/// Time a call to the engine may take, on top of the grace period of `docker_stop`.
+const DOCKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Lines of logs returned by `docker_logs` by default, and at most.
+const DEFAULT_TAIL: u64 = 100;
+const MAX_TAIL: u64 = 10_000;
+
+/// Bytes of logs returned by `docker_logs`, the oldest lines being dropped past it.
+const MAX_LOG_SIZE: usize = 1024 * 1024;
+
+/// Client of the engine, with the `docker_host` it was created for.
+static CLIENT: Mutex<Option<(Option<String>, Docker)>> = Mutex::new(None);
+
+/// `docker_list_containers` tool listing the running containers, or all of them.
+pub fn list_containers_tool() -> Tool {
+    Tool::new(
+        "docker_list_containers",
+        "List the Docker containers that are running, or all of them with `all`, with their \
+         id, names, image, state and status.",
+        json!({
+            "type": "object",
+            "properties": {
+                "all": { "type": "boolean", "description": "Include the stopped containers" }
+            }
+        }),
+        |ctx, arguments| {
+            let all = arguments
+                .get("all")
+                .and_then(Value::as_bool)
+                .unwrap_or_default();
+            let result = call(ctx, DOCKER_TIMEOUT, |docker| async move {
+                let options = ListContainersOptionsBuilder::new().all(all).build();
+                docker.list_containers(Some(options)).await
+            });
+            match result {
+                Ok(containers) => {
+                    let containers: Vec<Value> = containers
+                        .into_iter()
+                        .map(|container| {
+                            let names: Vec<String> = container
+                                .names
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|name| name.trim_start_matches('/').to_string())
+                                .collect();
+                            // Short, as `docker ps` shows it
+                            let id: Option<String> =
+                                container.id.map(|id| id.chars().take(12).collect());
+                            json!({
+                                "id": id,
+                                "names": names,
+                                "image": container.image,
+                                "state": container.state,
+                                "status": container.status,
+                                "created": container.created,
+                            })
+                        })
+                        .collect();
+                    Ok(CallToolResult::structured(
+                        json!({ "containers": containers }),
+                    ))
+                }
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_docker)
+}
+
+/// `docker_inspect` tool returning the configuration and state of a container, as
+/// `docker inspect` does.
+pub fn inspect_tool() -> Tool {
+    Tool::new(
+        "docker_inspect",
+        "Return the configuration and state of a Docker container, as `docker inspect` does.",
+        json!({
+            "type": "object",
+            "properties": {
+                "container": { "type": "string", "description": "Id or name of the container" }
+            },
+            "required": ["container"]
+        }),
+        |ctx, arguments| {
+            let container = required_str(arguments, "container")?.to_string();
+            let result = call(ctx, DOCKER_TIMEOUT, |docker| async move {
+                docker
+                    .inspect_container(&container, None::<InspectContainerOptions>)
+                    .await
+            });
+            match result {
+                Ok(inspection) => Ok(CallToolResult::structured(
+                    serde_json::to_value(inspection).unwrap_or_default(),
+                )),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_docker)
+}
+
+/// `docker_logs` tool returning the last lines of the output of a container.
+pub fn logs_tool() -> Tool {
+    Tool::new(
+        "docker_logs",
+        "Return the last lines a Docker container wrote to its stdout and stderr.",
+        json!({
+            "type": "object",
+            "properties": {
+                "container": { "type": "string", "description": "Id or name of the container" },
+                "tail": { "type": "integer", "minimum": 1, "maximum": MAX_TAIL, "description": "Number of lines, 100 by default" },
+                "timestamps": { "type": "boolean", "description": "Prefix each line with its time" }
+            },
+            "required": ["container"]
+        }),
+        |ctx, arguments| {
+            let container = required_str(arguments, "container")?.to_string();
+            let tail = arguments
+                .get("tail")
+                .and_then(Value::as_u64)
+                .unwrap_or(DEFAULT_TAIL)
+                .clamp(1, MAX_TAIL);
+            let timestamps = arguments
+                .get("timestamps")
+                .and_then(Value::as_bool)
+                .unwrap_or_default();
+            let result = call(ctx, DOCKER_TIMEOUT, |docker| async move {
+                let options = LogsOptionsBuilder::new()
+                    .stdout(true)
+                    .stderr(true)
+                    .timestamps(timestamps)
+                    .tail(&tail.to_string())
+                    .build();
+                let mut output = docker.logs(&container, Some(options));
+                let mut logs = Vec::new();
+                while let Some(chunk) = output.try_next().await? {
+                    if let LogOutput::StdOut { message }
+                    | LogOutput::StdErr { message }
+                    | LogOutput::Console { message } = chunk
+                    {
+                        logs.extend_from_slice(&message);
+                    }
+                }
+                Ok::<_, bollard::errors::Error>(logs)
+            });
+            match result {
+                Ok(logs) => {
+                    // The last lines that fit
+                    let start = logs.len().saturating_sub(MAX_LOG_SIZE);
+                    let start = match start {
+                        0 => 0,
+                        start => logs[start..]
+                            .iter()
+                            .position(|byte| *byte == b'\n')
+                            .map_or(start, |newline| start + newline + 1),
+                    };
+                    Ok(CallToolResult::text(
+                        String::from_utf8_lossy(&logs[start..]).into_owned(),
+                    ))
+                }
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_docker)
+}
+
+/// `docker_start` tool starting a stopped container.
+pub fn start_tool() -> Tool {
+    Tool::new(
+        "docker_start",
+        "Start a stopped Docker container. Starting a running container does nothing.",
+        json!({
+            "type": "object",
+            "properties": {
+                "container": { "type": "string", "description": "Id or name of the container" }
+            },
+            "required": ["container"]
+        }),
+        |ctx, arguments| {
+            let container = required_str(arguments, "container")?.to_string();
+            tracing::info!("Starting the container {}", container);
+            let started = container.clone();
+            let result = call(ctx, DOCKER_TIMEOUT, |docker| async move {
+                docker.start_container(&started, None).await
+            });
+            match result {
+                Ok(()) => Ok(CallToolResult::structured(
+                    json!({ "container": container, "started": true }),
+                )),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": false, "idempotentHint": true }))
+    .available_when(|config| config.allow_docker)
+}
+
+/// `docker_stop` tool stopping a running container, killing it after a grace period.
+pub fn stop_tool() -> Tool {
+    Tool::new(
+        "docker_stop",
+        "Stop a running Docker container: send it SIGTERM, then SIGKILL if it is still running \
+         after `timeout_secs` (10 by default). What the container was doing is lost.",
+        json!({
+            "type": "object",
+            "properties": {
+                "container": { "type": "string", "description": "Id or name of the container" },
+                "timeout_secs": { "type": "integer", "minimum": 0, "maximum": 600, "description": "Seconds to wait before killing the container" }
+            },
+            "required": ["container"]
+        }),
+        |ctx, arguments| {
+            let container = required_str(arguments, "container")?.to_string();
+            let grace = arguments
+                .get("timeout_secs")
+                .and_then(Value::as_u64)
+                .unwrap_or(10)
+                .min(600);
+            tracing::info!("Stopping the container {}", container);
+            let stopped = container.clone();
+            let timeout = DOCKER_TIMEOUT + Duration::from_secs(grace);
+            let result = call(ctx, timeout, |docker| async move {
+                let options = StopContainerOptionsBuilder::new().t(grace as i32).build();
+                docker.stop_container(&stopped, Some(options)).await
+            });
+            match result {
+                Ok(()) => Ok(CallToolResult::structured(
+                    json!({ "container": container, "stopped": true }),
+                )),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "idempotentHint": true }))
+    .available_when(|config| config.allow_docker)
+}
+
+/// Runs `request` with the client of the engine, failing after `timeout` or the deadline of
+/// the call.
+fn call<T, F, E>(
+    ctx: &RequestContext,
+    timeout: Duration,
+    request: impl FnOnce(Docker) -> F,
+) -> Result<T, String>
+where
+    F: std::future::Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let docker = client(&ctx.config)?;
+    runtime::block_on(ctx.limit_timeout(timeout), request(docker))
+}
+
+/// The client of the engine at `docker_host`, or at `DOCKER_HOST` and else the local socket,
+/// created again when `docker_host` changed.
+fn client(config: &Config) -> Result<Docker, String> {
+    let mut client = CLIENT.lock().unwrap();
+    if let Some((host, docker)) = client.as_ref() {
+        if *host == config.docker_host {
+            return Ok(docker.clone());
+        }
+    }
+    let _entered = runtime::runtime()?.enter();
+    let docker = match &config.docker_host {
+        Some(host) => Docker::connect_with_host(host),
+        None => Docker::connect_with_defaults(),
+    }
+    .map_err(|e| format!("cannot connect to Docker: {}", e))?;
+    *client = Some((config.docker_host.clone(), docker.clone()));
+    Ok(docker)
+}
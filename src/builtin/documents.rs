@@ -0,0 +1,173 @@
+//! Plain text of the PDF and DOCX files under the roots.
+
+use crate::{
+    mcp::CallToolResult,
+    roots,
+    tools::{required_str, Tool},
+};
+use quick_xml::{events::Event, Reader};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+/// Size of the files text is extracted from.
+const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
+/// Characters of text returned by default.
+const DEFAULT_MAX_LENGTH: usize = 50_000;
+/// Size of the `word/document.xml` part of a DOCX file, which may be compressed far more than
+/// the file.
+const MAX_DOCUMENT_XML_SIZE: u64 = 200 * 1024 * 1024;
+
+/// `extract_text` tool returning the text of a PDF or DOCX file.
+pub fn extract_text_tool() -> Tool {
+    Tool::new(
+        "extract_text",
+        "Extract the plain text of a PDF or DOCX file. For PDFs, first_page and last_page \
+         (numbered from 1) select the pages; the number of pages is returned with the text.",
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the .pdf or .docx file" },
+                "first_page": { "type": "integer", "minimum": 1, "description": "First page of a PDF, 1 by default" },
+                "last_page": { "type": "integer", "minimum": 1, "description": "Last page of a PDF, the last one by default" },
+                "max_length": { "type": "integer", "minimum": 1, "description": "Characters of text returned, 50000 by default" }
+            },
+            "required": ["path"]
+        }),
+        |ctx, arguments| {
+            let path = required_str(arguments, "path")?;
+            let number = |name| arguments.get(name).and_then(Value::as_u64);
+            let max_length = number("max_length").map_or(DEFAULT_MAX_LENGTH, |n| n.max(1) as usize);
+            let pages = (number("first_page"), number("last_page"));
+            let extracted = roots::resolve(&ctx.config.roots, path).and_then(|path| {
+                tracing::info!("Extracting the text of {}", path.display());
+                extract(&path, pages)
+            });
+            match extracted {
+                Ok(mut result) => {
+                    let text = result["text"].as_str().unwrap_or_default();
+                    if let Some((end, _)) = text.char_indices().nth(max_length) {
+                        result["text"] = json!(text[..end]);
+                        result["truncated"] = json!(true);
+                    }
+                    Ok(CallToolResult::structured(result))
+                }
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `{text, truncated}` of the file at `path`, with `{pages, first_page, last_page}` for PDFs.
+fn extract(path: &Path, (first, last): (Option<u64>, Option<u64>)) -> Result<Value, String> {
+    let bytes = read(path)?;
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("pdf") => {
+            let pages = pdf_extract::extract_text_from_mem_by_pages(&bytes)
+                .map_err(|e| format!("cannot read the PDF {}: {}", path.display(), e))?;
+            let count = pages.len() as u64;
+            let first = first.unwrap_or(1).max(1);
+            let last = last.unwrap_or(count);
+            if first > last.min(count) {
+                return Err(format!(
+                    "no pages {} to {} in {}, which has {}",
+                    first,
+                    last,
+                    path.display(),
+                    count
+                ));
+            }
+            let last = last.min(count);
+            let text: Vec<&str> = pages[first as usize - 1..last as usize]
+                .iter()
+                .map(|page| page.trim())
+                .collect();
+            Ok(json!({
+                "text": text.join("\n\n"),
+                "pages": count,
+                "first_page": first,
+                "last_page": last,
+                "truncated": false,
+            }))
+        }
+        Some("docx") => {
+            let text = docx_text(&bytes)
+                .map_err(|e| format!("cannot read the DOCX {}: {}", path.display(), e))?;
+            Ok(json!({ "text": text, "truncated": false }))
+        }
+        _ => Err(format!(
+            "{} is neither a PDF nor a DOCX file",
+            path.display()
+        )),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    let size = fs::metadata(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?
+        .len();
+    if size > MAX_FILE_SIZE {
+        return Err(format!(
+            "{} is too large ({} bytes, at most {})",
+            path.display(),
+            size,
+            MAX_FILE_SIZE
+        ));
+    }
+    fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))
+}
+
+/// Text of the paragraphs of a DOCX file, one per line, with its tabs and line breaks.
+fn docx_text(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| e.to_string())?
+        .take(MAX_DOCUMENT_XML_SIZE)
+        .read_to_string(&mut xml)
+        .map_err(|e| e.to_string())?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut text = String::new();
+    // Within a `w:t` element, the only one whose text is the document's
+    let mut in_text = false;
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) if e.local_name().as_ref() == "t" => in_text = true,
+            Event::End(e) => match e.local_name().as_ref() {
+                "t" => in_text = false,
+                "p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                "tab" => text.push('\t'),
+                "br" | "cr" => text.push('\n'),
+                _ => {}
+            },
+            Event::Text(e) if in_text => text.push_str(&e.xml10_content()),
+            Event::CData(e) if in_text => text.push_str(&e),
+            Event::GeneralRef(e) if in_text => {
+                let resolved = match &*e {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    _ => e.resolve_char_ref().ok().flatten(),
+                };
+                text.extend(resolved);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
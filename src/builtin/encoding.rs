@@ -0,0 +1,252 @@
+//! Encodings and hashes models tend to get wrong when computing them themselves: UUIDs,
+//! base64, URL encoding and SHA-256/MD5 digests.
+
+use crate::{
+    context::RequestContext,
+    mcp::CallToolResult,
+    roots,
+    tools::{required_str, Tool},
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE},
+    Engine,
+};
+use md5::Md5;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{fs::File, io::Read};
+use uuid::Uuid;
+
+/// UUIDs generated by one call at most.
+const MAX_UUIDS: u64 = 100;
+
+/// `generate_uuid` tool returning random (version 4) UUIDs.
+pub fn uuid_tool() -> Tool {
+    Tool::new(
+        "generate_uuid",
+        "Generate random (version 4) UUIDs, one per line.",
+        json!({
+            "type": "object",
+            "properties": {
+                "count": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_UUIDS,
+                    "description": "Number of UUIDs, 1 by default"
+                }
+            }
+        }),
+        |_ctx, arguments| {
+            let count = arguments.get("count").and_then(Value::as_u64).unwrap_or(1);
+            let uuids: Vec<String> = (0..count.min(MAX_UUIDS))
+                .map(|_| Uuid::new_v4().to_string())
+                .collect();
+            Ok(CallToolResult::text(uuids.join("\n")))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `base64_encode` tool encoding text, or a file under the roots, in base64.
+pub fn base64_encode_tool() -> Tool {
+    Tool::new(
+        "base64_encode",
+        "Encode `text` (as UTF-8), or the file at `path`, in base64.",
+        json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string", "description": "Text to encode" },
+                "path": { "type": "string", "description": "File to encode instead, under the roots" },
+                "url_safe": { "type": "boolean", "description": "Use the URL-safe alphabet (`-` and `_`)" }
+            }
+        }),
+        |ctx, arguments| {
+            let bytes = match input(ctx, arguments) {
+                Ok(bytes) => bytes,
+                Err(e) => return Ok(CallToolResult::error(e)),
+            };
+            Ok(CallToolResult::text(engine(arguments).encode(bytes)))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `base64_decode` tool decoding base64 to text.
+pub fn base64_decode_tool() -> Tool {
+    Tool::new(
+        "base64_decode",
+        "Decode base64 to UTF-8 text. Whitespace in the input is ignored.",
+        json!({
+            "type": "object",
+            "properties": {
+                "data": { "type": "string", "description": "Base64 to decode" },
+                "url_safe": { "type": "boolean", "description": "Use the URL-safe alphabet (`-` and `_`)" }
+            },
+            "required": ["data"]
+        }),
+        |_ctx, arguments| {
+            let data: String = required_str(arguments, "data")?
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            let bytes = match engine(arguments).decode(data) {
+                Ok(bytes) => bytes,
+                Err(e) => return Ok(CallToolResult::error(format!("invalid base64: {}", e))),
+            };
+            match String::from_utf8(bytes) {
+                Ok(text) => Ok(CallToolResult::text(text)),
+                Err(e) => Ok(CallToolResult::error(format!(
+                    "the decoded {} bytes are not UTF-8 text",
+                    e.as_bytes().len()
+                ))),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `url_encode` tool percent-encoding text.
+pub fn url_encode_tool() -> Tool {
+    Tool::new(
+        "url_encode",
+        "Percent-encode text for a URL: every byte of its UTF-8 but the letters, digits and \
+         `-_.~`.",
+        json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string", "description": "Text to encode" },
+                "plus_for_space": { "type": "boolean", "description": "Encode spaces as `+`, as in form data" }
+            },
+            "required": ["text"]
+        }),
+        |_ctx, arguments| {
+            let text = required_str(arguments, "text")?;
+            let plus = flag(arguments, "plus_for_space");
+            let mut encoded = String::with_capacity(text.len());
+            for byte in text.bytes() {
+                match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        encoded.push(byte as char)
+                    }
+                    b' ' if plus => encoded.push('+'),
+                    _ => encoded.push_str(&format!("%{:02X}", byte)),
+                }
+            }
+            Ok(CallToolResult::text(encoded))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `url_decode` tool decoding percent-encoded text.
+pub fn url_decode_tool() -> Tool {
+    Tool::new(
+        "url_decode",
+        "Decode percent-encoded text, such as a URL query string.",
+        json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string", "description": "Text to decode" },
+                "plus_for_space": { "type": "boolean", "description": "Decode `+` as a space, as in form data" }
+            },
+            "required": ["text"]
+        }),
+        |_ctx, arguments| {
+            let text = required_str(arguments, "text")?;
+            let text = match flag(arguments, "plus_for_space") {
+                true => text.replace('+', " "),
+                false => text.to_string(),
+            };
+            match String::from_utf8(roots::percent_decode(&text)) {
+                Ok(text) => Ok(CallToolResult::text(text)),
+                Err(_) => Ok(CallToolResult::error("the decoded bytes are not UTF-8 text")),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `hash` tool returning the SHA-256 or MD5 digest of text, or of a file under the roots.
+pub fn hash_tool() -> Tool {
+    Tool::new(
+        "hash",
+        "Hash `text` (as UTF-8), or the file at `path`, returning the digest in hexadecimal.",
+        json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string", "description": "Text to hash" },
+                "path": { "type": "string", "description": "File to hash instead, under the roots" },
+                "algorithm": {
+                    "type": "string",
+                    "enum": ["sha256", "md5"],
+                    "description": "Hash algorithm, sha256 by default"
+                }
+            }
+        }),
+        |ctx, arguments| {
+            let algorithm = arguments.get("algorithm").and_then(Value::as_str);
+            let digest = match algorithm {
+                Some("md5") => digest::<Md5>(ctx, arguments),
+                _ => digest::<Sha256>(ctx, arguments),
+            };
+            match digest {
+                Ok(digest) => Ok(CallToolResult::text(digest)),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+fn flag(arguments: &Value, name: &str) -> bool {
+    arguments
+        .get(name)
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+fn engine(arguments: &Value) -> &'static base64::engine::GeneralPurpose {
+    match flag(arguments, "url_safe") {
+        true => &URL_SAFE,
+        false => &STANDARD,
+    }
+}
+
+/// The bytes of the `text` argument, or of the file at `path`.
+fn input(ctx: &RequestContext, arguments: &Value) -> Result<Vec<u8>, String> {
+    match (arguments.get("text"), arguments.get("path")) {
+        (Some(_), Some(_)) => Err("give either text or path, not both".to_string()),
+        (Some(text), None) => Ok(text.as_str().unwrap_or_default().as_bytes().to_vec()),
+        (None, Some(path)) => {
+            let path = roots::resolve(&ctx.config.roots, path.as_str().unwrap_or_default())?;
+            std::fs::read(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))
+        }
+        (None, None) => Err("give text or path".to_string()),
+    }
+}
+
+/// Hexadecimal digest of the `text` argument, or of the file at `path`, read as it is hashed.
+fn digest<D: Digest>(ctx: &RequestContext, arguments: &Value) -> Result<String, String> {
+    let mut hasher = D::new();
+    match arguments.get("path").and_then(Value::as_str) {
+        Some(path) if arguments.get("text").is_none() => {
+            let path = roots::resolve(&ctx.config.roots, path)?;
+            let mut file =
+                File::open(&path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+            let mut buf = [0; 8192];
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => hasher.update(&buf[..n]),
+                    Err(e) => return Err(format!("cannot read {}: {}", path.display(), e)),
+                }
+            }
+        }
+        _ => hasher.update(input(ctx, arguments)?),
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
@@ -0,0 +1,102 @@
+//! Environment variables of the server process, with secrets redacted.
+
+use crate::{
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{ReadResourceResult, ResourceContents},
+    resources::{Resource, ResourceProvider},
+};
+use serde_json::{Map, Value};
+use std::env;
+
+const ENV_URI: &str = "env://";
+/// Value shown in place of the redacted variables.
+const REDACTED: &str = "[REDACTED]";
+
+/// Exposes the environment as the `env://` resource (a JSON object of all the variables)
+/// and `env://<NAME>` (the value of one variable). Variables whose name matches one of the
+/// `env_redact` patterns have their value replaced by `[REDACTED]`.
+pub struct EnvResources;
+
+/// Matches `name` against a pattern where `*` stands for any sequence of characters,
+/// ignoring case.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    matches_case_sensitive(&pattern.to_ascii_uppercase(), &name.to_ascii_uppercase())
+}
+
+/// [`matches`], minding case.
+pub(crate) fn matches_case_sensitive(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` in the pattern
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+fn value(redact: &[String], name: &str, value: String) -> String {
+    if redact.iter().any(|pattern| matches(pattern, name)) {
+        REDACTED.to_string()
+    } else {
+        value
+    }
+}
+
+impl ResourceProvider for EnvResources {
+    fn list(&self, _ctx: &RequestContext) -> Vec<Resource> {
+        vec![Resource {
+            uri: ENV_URI.to_string(),
+            name: "Environment variables".to_string(),
+            description: Some(
+                "Environment of the server process, with secrets redacted".to_string(),
+            ),
+            mime_type: Some("application/json".to_string()),
+            ..Default::default()
+        }]
+    }
+
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let name = uri.strip_prefix(ENV_URI)?;
+        let redact = &ctx.config.env_redact;
+        let contents = if name.is_empty() {
+            let variables: Map<String, Value> = env::vars_os()
+                .map(|(name, v)| {
+                    let name = name.to_string_lossy().into_owned();
+                    let v = value(redact, &name, v.to_string_lossy().into_owned());
+                    (name, Value::String(v))
+                })
+                .collect();
+            ResourceContents::text(uri, Value::Object(variables).to_string())
+                .with_mime_type("application/json")
+        } else {
+            match env::var_os(name) {
+                Some(v) => ResourceContents::text(
+                    uri,
+                    value(redact, name, v.to_string_lossy().into_owned()),
+                ),
+                None => {
+                    return Some(Err(JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("environment variable {} is not set", name),
+                    )))
+                }
+            }
+        };
+        Some(Ok(ReadResourceResult::new(vec![contents])))
+    }
+}
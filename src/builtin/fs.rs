@@ -0,0 +1,543 @@
+//! Filesystem access restricted to the configured roots.
+
+use crate::{
+    builtin::{
+        cache::FileCache,
+        csv,
+        mime::{file_contents, guess_mime_type},
+    },
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INTERNAL_ERROR, ERROR_CODE_INVALID_PARAMS},
+    mcp::{Annotations, CallToolResult, ReadResourceResult, ResourceContents},
+    resources::{Resource, ResourceProvider, ResourceTemplate},
+    roots,
+    tools::{required_str, Tool},
+};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Maximum number of files listed by `resources/list`.
+const MAX_LISTED_FILES: usize = 1000;
+/// Template of the `file://` resources, whose `path` variable is completed.
+const FILE_TEMPLATE: &str = "file://{path}";
+
+/// `fs_read` tool returning the text of a file under the roots.
+pub fn read_tool() -> Tool {
+    Tool::new(
+        "fs_read",
+        "Read a UTF-8 text file. Relative paths are resolved against the first root directory.",
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the file to read" }
+            },
+            "required": ["path"]
+        }),
+        |ctx, arguments| {
+            let path = required_str(arguments, "path")?;
+            let path = match roots::resolve(&ctx.config.roots, path) {
+                Ok(path) => path,
+                Err(e) => return Ok(CallToolResult::error(e)),
+            };
+            tracing::info!("Reading {}", path.display());
+            match fs::read_to_string(&path) {
+                Ok(text) => Ok(CallToolResult::text(text)),
+                Err(e) => Ok(CallToolResult::error(format!(
+                    "cannot read {}: {}",
+                    path.display(),
+                    e
+                ))),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `fs_list` tool returning the entries of a directory under the roots.
+pub fn list_tool() -> Tool {
+    Tool::new(
+        "fs_list",
+        "List a directory. Returns a JSON array of entries with their name, size in bytes, \
+         modification time (seconds since the Unix epoch) and whether they are directories.",
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the directory to list" }
+            },
+            "required": ["path"]
+        }),
+        |ctx, arguments| {
+            let path = required_str(arguments, "path")?;
+            let entries = roots::resolve(&ctx.config.roots, path).and_then(|path| {
+                tracing::info!("Listing {}", path.display());
+                list_dir(&path)
+            });
+            match entries {
+                Ok(entries) => Ok(CallToolResult::text(entries.to_string())),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// Entries of `dir` sorted by name, as a JSON array of `{name, size, mtime, is_dir}`.
+fn list_dir(dir: &Path) -> Result<Value, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("cannot list {}: {}", dir.display(), e))?;
+    let mut entries: Vec<Value> = entries
+        .flatten()
+        .map(|entry| {
+            let metadata = entry.metadata().ok();
+            let mtime = metadata
+                .as_ref()
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs());
+            json!({
+                "name": entry.file_name().to_string_lossy(),
+                "size": metadata.as_ref().map(|m| m.len()),
+                "mtime": mtime,
+                "is_dir": metadata.as_ref().is_some_and(|m| m.is_dir()),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Ok(Value::Array(entries))
+}
+
+/// `fs_write` tool creating or overwriting a file under the roots.
+/// Only offered when `allow_writes` is set.
+pub fn write_tool() -> Tool {
+    Tool::new(
+        "fs_write",
+        "Create or overwrite a text file, creating missing parent directories.",
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the file to write" },
+                "content": { "type": "string", "description": "New content of the file" }
+            },
+            "required": ["path", "content"]
+        }),
+        |ctx, arguments| {
+            let path = required_str(arguments, "path")?;
+            let content = required_str(arguments, "content")?;
+            let path = match resolve_for_write(&ctx.config.roots, path) {
+                Ok(path) => path,
+                Err(e) => return Ok(CallToolResult::error(e)),
+            };
+            tracing::info!("Writing {}", path.display());
+            match write_file(&path, content.as_bytes()) {
+                Ok(()) => Ok(CallToolResult::text(format!(
+                    "Wrote {} bytes to {}",
+                    content.len(),
+                    path.display()
+                ))),
+                Err(e) => Ok(CallToolResult::error(format!(
+                    "cannot write {}: {}",
+                    path.display(),
+                    e
+                ))),
+            }
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "idempotentHint": true }))
+    .with_dry_run(|ctx, arguments| {
+        let path = required_str(arguments, "path")?;
+        let content = required_str(arguments, "content")?;
+        let (path, missing_dir) = write_target(&ctx.config.roots, path).map_err(internal)?;
+        let change = match path.exists() {
+            true => "overwrite",
+            false => "create",
+        };
+        Ok(json!({
+            "filesTouched": [{ "path": path, "change": change, "bytes": content.len() }],
+            "directoriesCreated": missing_dir.into_iter().collect::<Vec<_>>(),
+        }))
+    })
+    .available_when(|config| config.allow_writes)
+}
+
+/// `fs_edit` tool modifying a text file under the roots, either by replacing a unique piece
+/// of text or by replacing a range of lines. Only offered when `allow_writes` is set.
+pub fn edit_tool() -> Tool {
+    Tool::new(
+        "fs_edit",
+        "Edit a text file. Either replace `old_text` with `new_text` (which must match exactly \
+         once unless `replace_all` is set), or replace lines `start_line` to `end_line` \
+         (1-based, inclusive) with `new_text`.",
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the file to edit" },
+                "new_text": { "type": "string", "description": "Replacement text" },
+                "old_text": { "type": "string", "description": "Text to replace" },
+                "replace_all": { "type": "boolean", "description": "Replace every occurrence of old_text" },
+                "start_line": { "type": "integer", "minimum": 1, "description": "First line to replace" },
+                "end_line": { "type": "integer", "minimum": 1, "description": "Last line to replace" }
+            },
+            "required": ["path", "new_text"]
+        }),
+        |ctx, arguments| {
+            let (path, _, edited) = edit(ctx, arguments)?;
+            tracing::info!("Editing {}", path.display());
+            match write_file(&path, edited.as_bytes()) {
+                Ok(()) => Ok(CallToolResult::text(format!("Edited {}", path.display()))),
+                Err(e) => Ok(CallToolResult::error(format!(
+                    "cannot write {}: {}",
+                    path.display(),
+                    e
+                ))),
+            }
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true }))
+    .with_dry_run(|ctx, arguments| {
+        let (path, text, edited) = edit(ctx, arguments)?;
+        Ok(json!({
+            "filesTouched": [{
+                "path": path,
+                "change": "edit",
+                "bytesBefore": text.len(),
+                "bytesAfter": edited.len(),
+            }],
+        }))
+    })
+    .available_when(|config| config.allow_writes)
+}
+
+/// The file `fs_edit` is called on, with its current and edited text. Errors are
+/// INTERNAL_ERROR, which the model sees.
+fn edit(
+    ctx: &RequestContext,
+    arguments: &Value,
+) -> Result<(PathBuf, String, String), JsonRpcError> {
+    let path = required_str(arguments, "path")?;
+    let new_text = required_str(arguments, "new_text")?;
+    let path = roots::resolve(&ctx.config.roots, path).map_err(internal)?;
+    let text = fs::read_to_string(&path)
+        .map_err(|e| internal(format!("cannot read {}: {}", path.display(), e)))?;
+    let edited = if let Some(old_text) = arguments.get("old_text").and_then(Value::as_str) {
+        let replace_all = arguments
+            .get("replace_all")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        replace_text(&text, old_text, new_text, replace_all)
+    } else {
+        let line = |name| arguments.get(name).and_then(Value::as_u64);
+        match (line("start_line"), line("end_line")) {
+            (Some(start), Some(end)) => replace_lines(&text, start, end, new_text),
+            _ => Err("either old_text or start_line and end_line are required".to_string()),
+        }
+    };
+    let edited = edited.map_err(internal)?;
+    Ok((path, text, edited))
+}
+
+fn internal(message: impl Into<String>) -> JsonRpcError {
+    JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, message)
+}
+
+/// Where a write to `path` goes, and the missing parent directory created first if there's
+/// one, once checked to lie inside the roots. Symbolic links aren't written through.
+fn write_target(roots: &[PathBuf], path: &str) -> Result<(PathBuf, Option<PathBuf>), String> {
+    let requested = match roots.first() {
+        Some(first) => first.join(path),
+        None => return roots::resolve(roots, path).map(|path| (path, None)),
+    };
+    if fs::symlink_metadata(&requested).is_ok_and(|metadata| metadata.is_symlink()) {
+        return Err(format!(
+            "{} is a symbolic link, which isn't written through",
+            requested.display()
+        ));
+    }
+    match roots::resolve(roots, path) {
+        Ok(path) => return Ok((path, None)),
+        // Only a missing parent directory is worked around, by creating it
+        Err(e) if requested.parent().is_none_or(Path::exists) => return Err(e),
+        Err(_) => {}
+    }
+    // A `..` after a directory that doesn't exist yet can't be resolved, and would step out
+    // of the ancestor checked below.
+    if requested.components().any(|c| c == Component::ParentDir) {
+        return Err(format!(
+            "{} has `..` components and a missing parent directory",
+            requested.display()
+        ));
+    }
+    let Some(name) = requested.file_name() else {
+        return Err(format!("invalid path: {}", requested.display()));
+    };
+    // Find the deepest existing ancestor and check that it lies inside the roots: the
+    // directories to create are then plain names under it.
+    let mut missing = Vec::new();
+    let mut ancestor = requested.parent();
+    while let Some(dir) = ancestor {
+        if dir.exists() {
+            let dir = roots::resolve(roots, &dir.to_string_lossy())?;
+            let missing_dir = missing.iter().rev().fold(dir, |dir, name| dir.join(name));
+            return Ok((missing_dir.join(name), Some(missing_dir)));
+        }
+        missing.extend(dir.file_name());
+        ancestor = dir.parent();
+    }
+    Err(format!("cannot resolve {}", requested.display()))
+}
+
+/// Resolves a path to write to, creating its missing parent directories inside the roots.
+fn resolve_for_write(roots: &[PathBuf], path: &str) -> Result<PathBuf, String> {
+    let (target, missing_dir) = write_target(roots, path)?;
+    let Some(dir) = missing_dir else {
+        return Ok(target);
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    roots::resolve(roots, path)
+}
+
+/// Writes `contents` to the file at `path`, never through a symbolic link: `path` was checked
+/// to lie inside the roots, and a link put in its place since must not take the write out.
+fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NOFOLLOW);
+    #[cfg(not(unix))]
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink()) {
+        return Err(io::Error::other("the file is a symbolic link"));
+    }
+    options.open(path)?.write_all(contents)
+}
+
+fn replace_text(
+    text: &str,
+    old_text: &str,
+    new_text: &str,
+    replace_all: bool,
+) -> Result<String, String> {
+    if old_text.is_empty() {
+        return Err("old_text must not be empty".to_string());
+    }
+    match text.matches(old_text).count() {
+        0 => Err("old_text was not found in the file".to_string()),
+        1 => Ok(text.replacen(old_text, new_text, 1)),
+        _ if replace_all => Ok(text.replace(old_text, new_text)),
+        n => Err(format!(
+            "old_text matches {} times; make it unique or set replace_all",
+            n
+        )),
+    }
+}
+
+fn replace_lines(text: &str, start: u64, end: u64, new_text: &str) -> Result<String, String> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    if start == 0 || start > end || end as usize > lines.len() {
+        return Err(format!(
+            "invalid line range {}-{} (the file has {} lines)",
+            start,
+            end,
+            lines.len()
+        ));
+    }
+    let mut edited: String = lines[..start as usize - 1].concat();
+    edited.push_str(new_text);
+    // Keep the line break of the last replaced line
+    if !new_text.is_empty() && !new_text.ends_with('\n') && lines[end as usize - 1].ends_with('\n')
+    {
+        edited.push('\n');
+    }
+    edited.push_str(&lines[end as usize..].concat());
+    Ok(edited)
+}
+
+/// Exposes the files under the roots as `file://` resources. Directories can be read too
+/// (the roots are listed as `file:///dir/`), returning their entries like `fs_list`. Text files
+/// are returned as text and the others, like images and archives, as base64-encoded blobs with
+/// their MIME type. Files are read through a cache, see [`FileCache`].
+#[derive(Default)]
+pub struct FileResources {
+    cache: FileCache,
+}
+
+impl FileResources {
+    fn collect(dir: &Path, resources: &mut Vec<Resource>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if resources.len() >= MAX_LISTED_FILES {
+                return;
+            }
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => FileResources::collect(&path, resources),
+                Ok(t) if t.is_file() => resources.push(Resource {
+                    uri: roots::path_to_uri(&path),
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    description: None,
+                    mime_type: Some(guess_mime_type(&path, None)),
+                    annotations: entry
+                        .metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .map(Annotations::modified_at),
+                    ..Default::default()
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Paths under the roots starting with `prefix`, directories ending with a slash. An empty
+/// prefix yields the roots themselves.
+fn complete_path(roots: &[PathBuf], prefix: &str) -> Vec<String> {
+    let dir_path = |path: &Path| {
+        let mut path = path.to_string_lossy().into_owned();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        path
+    };
+    if prefix.is_empty() {
+        return roots
+            .iter()
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .map(|root| dir_path(&root))
+            .collect();
+    }
+    let (dir, name) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+    let Ok(resolved) = roots::resolve(roots, if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&resolved) else {
+        return Vec::new();
+    };
+    let mut values: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let entry_name = entry.file_name().to_string_lossy().into_owned();
+            if !entry_name.starts_with(name) {
+                return None;
+            }
+            let value = format!("{}{}", dir, entry_name);
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => Some(dir_path(Path::new(&value))),
+                _ => Some(value),
+            }
+        })
+        .collect();
+    values.sort();
+    values
+}
+
+/// `file://` URI of a directory, with a trailing slash.
+pub(crate) fn dir_uri(dir: &Path) -> String {
+    let mut uri = roots::path_to_uri(dir);
+    if !uri.ends_with('/') {
+        uri.push('/');
+    }
+    uri
+}
+
+impl ResourceProvider for FileResources {
+    fn list(&self, ctx: &RequestContext) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        for root in ctx
+            .config
+            .roots
+            .iter()
+            .filter_map(|r| fs::canonicalize(r).ok())
+        {
+            resources.push(Resource {
+                uri: dir_uri(&root),
+                name: root.to_string_lossy().into_owned(),
+                description: Some("Directory listing".to_string()),
+                mime_type: Some("application/json".to_string()),
+                ..Default::default()
+            });
+            FileResources::collect(&root, &mut resources);
+        }
+        resources
+    }
+
+    fn templates(&self, ctx: &RequestContext) -> Vec<ResourceTemplate> {
+        if ctx.config.roots.is_empty() {
+            return Vec::new();
+        }
+        vec![ResourceTemplate {
+            uri_template: FILE_TEMPLATE.to_string(),
+            name: "Files under the roots".to_string(),
+            description: Some(
+                "Any file or directory under the roots, by absolute path".to_string(),
+            ),
+            mime_type: None,
+            ..Default::default()
+        }]
+    }
+
+    fn complete(
+        &self,
+        ctx: &RequestContext,
+        uri_template: &str,
+        argument: &str,
+        value: &str,
+    ) -> Option<Vec<String>> {
+        if uri_template != FILE_TEMPLATE {
+            return None;
+        }
+        Some(match argument {
+            "path" => complete_path(&ctx.config.roots, value),
+            _ => Vec::new(),
+        })
+    }
+
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let (path, view) = match csv::split_view(uri) {
+            Ok((base, view)) => (roots::uri_to_path(base)?, view),
+            Err(e) => return roots::uri_to_path(uri).map(|_| Err(e)),
+        };
+        let result = roots::resolve_param(&ctx.config.roots, &path).and_then(|path| {
+            let contents = if path.is_dir() {
+                list_dir(&path).map(|entries| {
+                    ResourceContents::text(uri, entries.to_string())
+                        .with_mime_type("application/json")
+                })
+            } else {
+                self.cache
+                    .read(&path, ctx.config.resource_cache_size)
+                    .map(|file| file_contents(uri, &path, &file))
+                    .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+            };
+            let contents = match view {
+                None => contents,
+                Some(query) => contents.and_then(|contents| {
+                    match contents
+                        .mime_type
+                        .as_deref()
+                        .is_some_and(csv::is_table_mime_type)
+                        && contents.text.is_some()
+                    {
+                        true => Ok(csv::render(contents, query)),
+                        false => Err(format!("{} is not a CSV or TSV file", path.display())),
+                    }
+                }),
+            };
+            contents
+                .map(|contents| ReadResourceResult::new(vec![contents]))
+                .map_err(|e| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e))
+        });
+        Some(result)
+    }
+}
@@ -0,0 +1,313 @@
+//! Read-only git tools for the repositories under the roots, running the `git` executable.
+
+use crate::{
+    context::RequestContext,
+    jsonrpc::JsonRpcError,
+    mcp::CallToolResult,
+    roots,
+    tools::{required_str, Tool},
+};
+use serde_json::{json, Value};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Default number of commits returned by `git_log`.
+const DEFAULT_LOG_COUNT: u64 = 20;
+/// Maximum number of bytes of patch returned by `git_diff`.
+const MAX_DIFF_SIZE: usize = 1024 * 1024;
+
+/// Schema of the `repo` argument shared by the git tools.
+fn repo_property() -> Value {
+    json!({
+        "type": "string",
+        "description": "Path of the repository, defaults to the first root directory"
+    })
+}
+
+/// Builds a git tool whose handler returns a JSON value, turning errors into tool errors.
+fn git_tool(
+    name: &str,
+    description: &str,
+    input_schema: Value,
+    run: fn(&Path, &Value) -> Result<Value, String>,
+) -> Tool {
+    Tool::new(name, description, input_schema, move |ctx, arguments| {
+        let result = repository(ctx, arguments)?.and_then(|repo| run(&repo, arguments));
+        match result {
+            Ok(value) => Ok(CallToolResult::text(value.to_string())),
+            Err(e) => Ok(CallToolResult::error(e)),
+        }
+    })
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// `git_status` tool returning the current branch and the changed files.
+pub fn status_tool() -> Tool {
+    git_tool(
+        "git_status",
+        "Show the working tree status of a git repository: the current branch, its upstream \
+         and the changed files with their index and worktree status codes.",
+        json!({
+            "type": "object",
+            "properties": { "repo": repo_property() }
+        }),
+        status,
+    )
+}
+
+/// `git_log` tool returning the latest commits.
+pub fn log_tool() -> Tool {
+    git_tool(
+        "git_log",
+        "List the latest commits of a git repository, optionally only those touching a path.",
+        json!({
+            "type": "object",
+            "properties": {
+                "repo": repo_property(),
+                "max_count": { "type": "integer", "minimum": 1, "description": "Number of commits, 20 by default" },
+                "rev": { "type": "string", "description": "Revision to start from, HEAD by default" },
+                "path": { "type": "string", "description": "Only list commits touching this path" }
+            }
+        }),
+        log,
+    )
+}
+
+/// `git_diff` tool returning the changed files and the patch.
+pub fn diff_tool() -> Tool {
+    git_tool(
+        "git_diff",
+        "Show changes of a git repository: unstaged changes by default, staged changes with \
+         `staged`, or the changes since `rev`. Returns per-file line counts and the patch.",
+        json!({
+            "type": "object",
+            "properties": {
+                "repo": repo_property(),
+                "staged": { "type": "boolean", "description": "Show the staged changes" },
+                "rev": { "type": "string", "description": "Compare against this revision" },
+                "path": { "type": "string", "description": "Only show changes to this path" }
+            }
+        }),
+        diff,
+    )
+}
+
+/// `git_blame` tool returning the commit that last changed each line of a file.
+pub fn blame_tool() -> Tool {
+    git_tool(
+        "git_blame",
+        "Show the commit, author and date that last modified each line of a file.",
+        json!({
+            "type": "object",
+            "properties": {
+                "repo": repo_property(),
+                "path": { "type": "string", "description": "File to blame, relative to the repository" },
+                "start_line": { "type": "integer", "minimum": 1, "description": "First line" },
+                "end_line": { "type": "integer", "minimum": 1, "description": "Last line" }
+            },
+            "required": ["path"]
+        }),
+        blame,
+    )
+}
+
+/// Resolves the `repo` argument against the roots. The outer error is for invalid arguments.
+fn repository(
+    ctx: &RequestContext,
+    arguments: &Value,
+) -> Result<Result<PathBuf, String>, JsonRpcError> {
+    let repo = match arguments.get("repo") {
+        Some(_) => required_str(arguments, "repo")?,
+        None => ".",
+    };
+    Ok(roots::resolve(&ctx.config.roots, repo))
+}
+
+/// Runs git in `repo` and returns its standard output.
+fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    tracing::info!("Running git {:?} in {}", args, repo.display());
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("cannot run git: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Staged changes of `repo` as a patch, used by the `commit_message` prompt.
+pub(crate) fn staged_patch(repo: &Path) -> Result<String, String> {
+    git(repo, &["diff", "--cached"])
+}
+
+/// Returns the string argument `name`, refusing values that git would take for options.
+fn optional_arg<'a>(arguments: &'a Value, name: &str) -> Result<Option<&'a str>, String> {
+    match arguments.get(name).and_then(Value::as_str) {
+        Some(value) if value.starts_with('-') => Err(format!(
+            "invalid {} '{}': must not start with '-'",
+            name, value
+        )),
+        value => Ok(value),
+    }
+}
+
+fn status(repo: &Path, _arguments: &Value) -> Result<Value, String> {
+    let output = git(repo, &["status", "--porcelain=v1", "--branch", "-z"])?;
+    let mut records = output.split('\0').filter(|r| !r.is_empty());
+    let mut branch = Value::Null;
+    let mut upstream = Value::Null;
+    let mut files = Vec::new();
+    while let Some(record) = records.next() {
+        if let Some(header) = record.strip_prefix("## ") {
+            // "main...origin/main [ahead 1]"
+            let head = header.split(' ').next().unwrap_or_default();
+            match head.split_once("...") {
+                Some((local, remote)) => {
+                    branch = json!(local);
+                    upstream = json!(remote);
+                }
+                None => branch = json!(head),
+            }
+            continue;
+        }
+        let (Some(code), Some(path)) = (record.get(..2), record.get(3..)) else {
+            continue;
+        };
+        let mut file = json!({
+            "path": path,
+            "index": &code[..1],
+            "worktree": &code[1..],
+        });
+        // Renames and copies are followed by the original path
+        if code.contains(['R', 'C']) {
+            file["orig_path"] = json!(records.next());
+        }
+        files.push(file);
+    }
+    Ok(json!({ "branch": branch, "upstream": upstream, "files": files }))
+}
+
+fn log(repo: &Path, arguments: &Value) -> Result<Value, String> {
+    let count = arguments
+        .get("max_count")
+        .and_then(Value::as_u64)
+        .unwrap_or(DEFAULT_LOG_COUNT)
+        .to_string();
+    let mut args = vec![
+        "log",
+        "-n",
+        &count,
+        "--format=%H%x1f%an%x1f%ae%x1f%aI%x1f%s%x1e",
+    ];
+    if let Some(rev) = optional_arg(arguments, "rev")? {
+        args.push(rev);
+    }
+    args.push("--");
+    if let Some(path) = optional_arg(arguments, "path")? {
+        args.push(path);
+    }
+    let output = git(repo, &args)?;
+    let commits: Vec<Value> = output
+        .split('\x1e')
+        .map(str::trim)
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+            let fields: Vec<&str> = record.split('\x1f').collect();
+            json!({
+                "commit": fields.first(),
+                "author": fields.get(1),
+                "email": fields.get(2),
+                "date": fields.get(3),
+                "subject": fields.get(4),
+            })
+        })
+        .collect();
+    Ok(Value::Array(commits))
+}
+
+fn diff(repo: &Path, arguments: &Value) -> Result<Value, String> {
+    let mut args = vec!["diff"];
+    if arguments.get("staged").and_then(Value::as_bool) == Some(true) {
+        args.push("--cached");
+    }
+    if let Some(rev) = optional_arg(arguments, "rev")? {
+        args.push(rev);
+    }
+    args.push("--");
+    if let Some(path) = optional_arg(arguments, "path")? {
+        args.push(path);
+    }
+
+    let mut numstat_args = args.clone();
+    numstat_args.insert(1, "--numstat");
+    let files: Vec<Value> = git(repo, &numstat_args)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let (added, removed, path) = (fields.next()?, fields.next()?, fields.next()?);
+            // Binary files have "-" counts
+            Some(json!({
+                "path": path,
+                "added": added.parse::<u64>().ok(),
+                "removed": removed.parse::<u64>().ok(),
+            }))
+        })
+        .collect();
+
+    let mut patch = git(repo, &args)?;
+    let truncated = patch.len() > MAX_DIFF_SIZE;
+    if truncated {
+        let mut end = MAX_DIFF_SIZE;
+        while !patch.is_char_boundary(end) {
+            end -= 1;
+        }
+        patch.truncate(end);
+    }
+    Ok(json!({ "files": files, "patch": patch, "truncated": truncated }))
+}
+
+fn blame(repo: &Path, arguments: &Value) -> Result<Value, String> {
+    let path = optional_arg(arguments, "path")?.ok_or("missing string argument 'path'")?;
+    let line = |name| arguments.get(name).and_then(Value::as_u64);
+    let range = match (line("start_line"), line("end_line")) {
+        (Some(start), Some(end)) => Some(format!("{},{}", start, end)),
+        (Some(start), None) => Some(format!("{},", start)),
+        (None, Some(end)) => Some(format!("1,{}", end)),
+        (None, None) => None,
+    };
+    let mut args = vec!["blame", "--line-porcelain"];
+    if let Some(range) = &range {
+        args.extend(["-L", range]);
+    }
+    args.extend(["--", path]);
+    let output = git(repo, &args)?;
+
+    let mut lines = Vec::new();
+    let mut current = json!({});
+    for line in output.lines() {
+        if let Some(text) = line.strip_prefix('\t') {
+            current["text"] = json!(text);
+            lines.push(std::mem::replace(&mut current, json!({})));
+        } else if current.get("commit").is_none() {
+            // "<commit> <original line> <final line> [<group size>]"
+            let mut fields = line.split(' ');
+            current["commit"] = json!(fields.next());
+            current["line"] = json!(fields.nth(1).and_then(|l| l.parse::<u64>().ok()));
+        } else if let Some((key, value)) = line.split_once(' ') {
+            match key {
+                "author" => current["author"] = json!(value),
+                "author-mail" => current["email"] = json!(value.trim_matches(['<', '>'])),
+                "author-time" => current["time"] = json!(value.parse::<u64>().ok()),
+                "summary" => current["summary"] = json!(value),
+                _ => {}
+            }
+        }
+    }
+    Ok(Value::Array(lines))
+}
@@ -0,0 +1,244 @@
+//! HTTP requests to web APIs, restricted by the `fetch_*` settings.
+
+use crate::{
+    config::Config,
+    context::{CancellationToken, RequestContext},
+    jsonrpc::{JsonRpcError, ERROR_CODE_INTERNAL_ERROR},
+    mcp::{CallToolResult, ReadResourceResult, ResourceContents},
+    resources::SchemeHandler,
+    tools::{required_str, Tool},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Map, Value};
+use std::{
+    io::Read,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+use ureq::http::{Request, Uri};
+
+/// Interval at which a fetch is checked for completion and cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// `http_fetch` tool sending an HTTP request and returning the status, headers and body
+/// of the response.
+pub fn fetch_tool() -> Tool {
+    Tool::new(
+        "http_fetch",
+        "Send an HTTP request. Returns a JSON object with the status, headers and body of the \
+         response; bodies that are not UTF-8 are returned base64-encoded in `body_base64`.",
+        json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string", "description": "http:// or https:// URL" },
+                "method": { "type": "string", "description": "HTTP method, GET by default" },
+                "headers": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Request headers"
+                },
+                "body": { "type": "string", "description": "Request body" }
+            },
+            "required": ["url"]
+        }),
+        |ctx, arguments| {
+            let url = required_str(arguments, "url")?;
+            let method = arguments
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or("GET");
+            let headers = arguments.get("headers").and_then(Value::as_object);
+            let body = arguments.get("body").and_then(Value::as_str).unwrap_or("");
+            match fetch(ctx, method, url, headers, body) {
+                Ok(response) => Ok(CallToolResult::text(response.to_string())),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "openWorldHint": true }))
+}
+
+/// Reads `http://` and `https://` URIs with a GET request, as `http_fetch` would, returning the
+/// body as text or, when it isn't UTF-8, as a blob, with the `Content-Type` of the response.
+/// Disabled unless `resource_schemes` enables it, as the client could then make the server
+/// contact any host `fetch_allowed_domains` allows.
+pub struct HttpScheme;
+
+impl SchemeHandler for HttpScheme {
+    fn read(&self, ctx: &RequestContext, uri: &str) -> Result<ReadResourceResult, JsonRpcError> {
+        let response = fetch(ctx, "GET", uri, None, "")
+            .map_err(|e| JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, e))?;
+        let status = response["status"].as_u64().unwrap_or_default();
+        if !(200..300).contains(&status) {
+            return Err(JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                format!("fetching {} returned status {}", uri, status),
+            ));
+        }
+        let mime_type = response["headers"]["content-type"].as_str();
+        let contents = match response["body_base64"].as_str() {
+            Some(blob) => {
+                let bytes = BASE64.decode(blob).unwrap_or_default();
+                ResourceContents::blob(uri, &bytes, mime_type.unwrap_or("application/octet-stream"))
+            }
+            None => {
+                let contents = ResourceContents::text(uri, response["body"].as_str().unwrap_or(""));
+                match mime_type {
+                    Some(mime_type) => contents.with_mime_type(mime_type),
+                    None => contents,
+                }
+            }
+        };
+        Ok(ReadResourceResult::new(vec![contents]))
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+}
+
+/// Checks that `uri` is an http(s) URL whose host is allowed by `fetch_allowed_domains`.
+/// A domain also allows its subdomains.
+fn check_allowed(config: &Config, uri: &Uri) -> Result<(), String> {
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(format!("unsupported URL {}, expected http or https", uri));
+    }
+    let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+    match &config.fetch_allowed_domains {
+        Some(domains)
+            if !domains.iter().any(|domain| {
+                let domain = domain.to_ascii_lowercase();
+                host == domain || host.ends_with(&format!(".{}", domain))
+            }) =>
+        {
+            Err(format!("host '{}' is not in the allowed domains", host))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Sends a request and returns the status, headers and body of the response as JSON. If the
+/// request is cancelled meanwhile, the call returns right away and the connection is dropped.
+pub(crate) fn fetch(
+    ctx: &RequestContext,
+    method: &str,
+    url: &str,
+    headers: Option<&Map<String, Value>>,
+    body: &str,
+) -> Result<Value, String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| format!("invalid URL {}: {}", url, e))?;
+    let config = &ctx.config;
+    check_allowed(config, &uri)?;
+
+    let timeout = ctx.limit_timeout(Duration::from_secs(config.fetch_timeout_secs));
+    let agent_config = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global((timeout > Duration::ZERO).then_some(timeout));
+    // Redirects could leave the allowed domains, so return them to the caller instead
+    let agent_config = if config.fetch_allowed_domains.is_some() {
+        agent_config.max_redirects(0)
+    } else {
+        agent_config
+    };
+    let agent: ureq::Agent = agent_config.build().into();
+
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers.into_iter().flatten() {
+        if let Some(value) = value.as_str() {
+            request = request.header(name, value);
+        }
+    }
+    let request = request
+        .body(body.as_bytes().to_vec())
+        .map_err(|e| format!("invalid request: {}", e))?;
+
+    tracing::info!("Fetching {} {}", method, url);
+    // The exchange runs on a thread of its own, which drops the connection at its next read
+    // once the request is cancelled, while the call returns without waiting for it
+    let (done, result) = mpsc::channel();
+    let cancellation = ctx.cancellation.clone();
+    let max_size = config.fetch_max_size;
+    let target = url.to_string();
+    thread::spawn(move || {
+        let _ = done.send(exchange(agent, request, &target, max_size, &cancellation));
+    });
+    loop {
+        match result.recv_timeout(POLL_INTERVAL) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Timeout) if ctx.cancellation.is_cancelled() => {
+                tracing::info!("Request cancelled, aborting the fetch of {}", url);
+                return Err(format!("the fetch of {} was cancelled", url));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(format!("the fetch of {} failed", url));
+            }
+        }
+    }
+}
+
+/// Runs `request` and reads the response, at most `max_size` bytes of its body, giving up
+/// as soon as `cancellation` is cancelled.
+fn exchange(
+    agent: ureq::Agent,
+    request: Request<Vec<u8>>,
+    url: &str,
+    max_size: u64,
+    cancellation: &CancellationToken,
+) -> Result<Value, String> {
+    let cancelled = || format!("the fetch of {} was cancelled", url);
+    let response = agent
+        .run(request)
+        .map_err(|e| format!("cannot fetch {}: {}", url, e))?;
+    if cancellation.is_cancelled() {
+        return Err(cancelled());
+    }
+    let status = response.status().as_u16();
+    let response_headers: Map<String, Value> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), Value::String(value))
+        })
+        .collect();
+
+    let mut bytes = Vec::new();
+    let mut reader = response.into_body().into_reader().take(max_size + 1);
+    let mut buf = [0; 8192];
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| format!("cannot read the response of {}: {}", url, e))?;
+        if n == 0 {
+            break;
+        }
+        if cancellation.is_cancelled() {
+            return Err(cancelled());
+        }
+        bytes.extend_from_slice(&buf[..n]);
+    }
+    let truncated = bytes.len() as u64 > max_size;
+    bytes.truncate(max_size as usize);
+
+    let mut result = json!({
+        "status": status,
+        "headers": response_headers,
+        "truncated": truncated,
+    });
+    match String::from_utf8(bytes) {
+        Ok(text) => result["body"] = Value::String(text),
+        // Truncation may have cut a character in half
+        Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid);
+            result["body"] = Value::String(String::from_utf8(bytes).unwrap_or_default());
+        }
+        Err(e) => result["body_base64"] = Value::String(BASE64.encode(e.into_bytes())),
+    }
+    Ok(result)
+}
@@ -0,0 +1,150 @@
+//! jq filters (run by jaq) applied to JSON given by the client or read from a resource, so that
+//! models can pick what they need out of large documents without reading them whole.
+
+use crate::{
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use jaq_core::{
+    data::JustLut,
+    load::{self, Arena, File, Loader},
+    Compiler, Ctx, Vars,
+};
+use jaq_json::Val;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+/// Outputs of a filter returned, the others being dropped.
+const MAX_OUTPUTS: usize = 1000;
+
+/// Filters of the standard library that are not offered: `env` would disclose the environment
+/// of the server, and there is no input besides the document for `input` and `inputs`.
+const EXCLUDED_FILTERS: &[&str] = &["env", "input", "inputs"];
+
+/// `json_query` tool applying a jq filter to JSON.
+pub fn query_tool() -> Tool {
+    Tool::new(
+        "json_query",
+        "Apply a jq filter (e.g. .items[] | select(.size > 10) | .name) to a JSON document, \
+         given inline as input or read from the resource at uri, and return its outputs. \
+         Useful to extract what is needed from large JSON without reading it whole.",
+        json!({
+            "type": "object",
+            "properties": {
+                "filter": { "type": "string", "description": "jq filter" },
+                "input": { "description": "JSON document to filter" },
+                "uri": { "type": "string", "description": "URI of a JSON resource to filter instead" }
+            },
+            "required": ["filter"]
+        }),
+        |ctx, arguments| {
+            let filter = required_str(arguments, "filter")?;
+            let input = match (arguments.get("input"), arguments.get("uri")) {
+                (Some(input), None) => input.clone(),
+                (None, Some(Value::String(uri))) => match read_json(ctx, uri)? {
+                    Ok(input) => input,
+                    Err(e) => return Ok(CallToolResult::error(e)),
+                },
+                _ => {
+                    return Err(JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        "exactly one of input and uri is required",
+                    ))
+                }
+            };
+            match query(filter, input, ctx.deadline) {
+                Ok(result) => Ok(CallToolResult::structured(result)),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// The JSON text of the resource at `uri`. Unknown resources are an invalid parameter, as for
+/// `resources/read`.
+fn read_json(ctx: &RequestContext, uri: &str) -> Result<Result<Value, String>, JsonRpcError> {
+    let Some(result) = ctx.read_resource(uri) else {
+        return Err(JsonRpcError::new(
+            ERROR_CODE_INVALID_PARAMS,
+            format!("Resource not found: {}", uri),
+        ));
+    };
+    let result = result?;
+    let Some(text) = result.contents.iter().find_map(|c| c.text.as_deref()) else {
+        return Ok(Err(format!("resource {} has no text contents", uri)));
+    };
+    Ok(serde_json::from_str(text).map_err(|e| format!("resource {} is not JSON: {}", uri, e)))
+}
+
+/// Runs `filter` on `input`, returning its outputs and whether some were dropped.
+pub fn query(filter: &str, input: Value, deadline: Option<Instant>) -> Result<Value, String> {
+    let defs = jaq_core::defs()
+        .chain(jaq_std::defs())
+        .chain(jaq_json::defs());
+    let funs = jaq_core::funs()
+        .chain(jaq_std::funs())
+        .chain(jaq_json::funs())
+        .filter(|(name, _, _)| !EXCLUDED_FILTERS.contains(name));
+    let arena = Arena::default();
+    let program = File {
+        code: filter,
+        path: (),
+    };
+    let modules = Loader::new(defs)
+        .load(&arena, program)
+        .map_err(|errors| load_errors(&errors))?;
+    let filter = Compiler::default()
+        .with_funs(funs)
+        .compile(modules)
+        .map_err(|errors| {
+            let undefined: Vec<String> = errors
+                .iter()
+                .flat_map(|(_, errors)| errors)
+                .map(|(name, kind)| format!("undefined {} {}", kind.as_str(), name))
+                .collect();
+            undefined.join(", ")
+        })?;
+    let input = Val::deserialize(input).map_err(|e| e.to_string())?;
+    let ctx = Ctx::<JustLut<Val>>::new(&filter.lut, Vars::new([]));
+    let mut outputs = Vec::new();
+    let mut truncated = false;
+    for output in filter.id.run((ctx, input)) {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err("the filter timed out".to_string());
+        }
+        if outputs.len() == MAX_OUTPUTS {
+            truncated = true;
+            break;
+        }
+        // Rather than `unwrap_valr`, which exits the process on `halt`
+        let output = output.map_err(|e| match e.get_err() {
+            Ok(e) => e.to_string(),
+            Err(_) => "the filter halted".to_string(),
+        })?;
+        // Values JSON cannot carry, such as NaN, come out as null
+        outputs.push(serde_json::from_str(&output.to_string()).unwrap_or(Value::Null));
+    }
+    Ok(json!({ "results": outputs, "truncated": truncated }))
+}
+
+fn load_errors(errors: &load::Errors<&str, ()>) -> String {
+    let messages: Vec<String> = errors
+        .iter()
+        .flat_map(|(_, error)| match error {
+            load::Error::Io(errors) => errors.iter().map(|(_, e)| e.clone()).collect(),
+            load::Error::Lex(errors) => errors
+                .iter()
+                .map(|(expected, at)| format!("expected {} at '{}'", expected.as_str(), at))
+                .collect(),
+            load::Error::Parse(errors) => errors
+                .iter()
+                .map(|(expected, at)| format!("expected {} at '{}'", expected.as_str(), at))
+                .collect::<Vec<_>>(),
+        })
+        .collect();
+    format!("invalid filter: {}", messages.join(", "))
+}
@@ -0,0 +1,245 @@
+//! Key-value memory kept in the JSON file `memory_file`, so that agents can leave notes for
+//! their later sessions: `memory_set` stores a value under a key, for a time or for good,
+//! `memory_get` reads it back and `memory_list` lists the keys.
+
+use crate::{
+    config::Config,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, fs, io, path::Path, sync::Mutex};
+
+/// Longest key accepted, in bytes.
+const MAX_KEY_LENGTH: usize = 256;
+
+/// Keys listed by one `memory_list` call.
+const MAX_LISTED: usize = 1000;
+
+/// Serializes the reads and writes of the memory file by the calls of this process.
+static FILE: Mutex<()> = Mutex::new(());
+
+/// A value of the memory, as stored.
+#[derive(Serialize, Deserialize, Clone)]
+struct Entry {
+    value: Value,
+    /// Last time the value was set, in RFC 3339
+    updated: String,
+    /// Time after which the entry is gone, in RFC 3339; never when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<String>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires
+            .as_deref()
+            .and_then(|expires| DateTime::parse_from_rfc3339(expires).ok())
+            .is_some_and(|expires| expires <= now)
+    }
+}
+
+/// `memory_set` tool storing a JSON value under a key, replacing the one there was. Only
+/// offered when `memory_file` is set.
+pub fn set_tool() -> Tool {
+    Tool::new(
+        "memory_set",
+        "Store a value under a key, in a memory kept across sessions, replacing the value the \
+         key had. With `ttl_secs`, the entry is forgotten after that many seconds.",
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "description": "Key of the entry" },
+                "value": { "description": "Value to store: a note, or any JSON value" },
+                "ttl_secs": { "type": "integer", "minimum": 1, "description": "Seconds the entry is kept, for good when absent" }
+            },
+            "required": ["key", "value"]
+        }),
+        |ctx, arguments| {
+            let (key, value, ttl) = set_arguments(arguments)?;
+            match set(&ctx.config, key, value.clone(), ttl) {
+                Ok(entry) => Ok(CallToolResult::structured(
+                    json!({ "key": key, "expires": entry.expires }),
+                )),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_dry_run(|ctx, arguments| {
+        set_arguments(arguments)?;
+        let path = memory_file(&ctx.config).map_err(invalid)?;
+        let change = match path.exists() {
+            true => "edit",
+            false => "create",
+        };
+        Ok(json!({ "filesTouched": [{ "path": path, "change": change }] }))
+    })
+    .available_when(|config| config.memory_file.is_some())
+}
+
+/// `memory_get` tool returning the value stored under a key.
+pub fn get_tool() -> Tool {
+    Tool::new(
+        "memory_get",
+        "Return the value stored under a key of the memory kept across sessions, with when it \
+         was set and when it expires.",
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "description": "Key of the entry" }
+            },
+            "required": ["key"]
+        }),
+        |ctx, arguments| {
+            let key = required_str(arguments, "key")?;
+            let result = entries(&ctx.config).and_then(|mut entries| {
+                entries
+                    .remove(key)
+                    .ok_or_else(|| format!("nothing is stored under '{}'", key))
+            });
+            match result {
+                Ok(entry) => Ok(CallToolResult::structured(json!({
+                    "key": key,
+                    "value": entry.value,
+                    "updated": entry.updated,
+                    "expires": entry.expires,
+                }))),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.memory_file.is_some())
+}
+
+/// `memory_list` tool listing the keys of the memory, with when they were set and expire.
+pub fn list_tool() -> Tool {
+    Tool::new(
+        "memory_list",
+        "List the keys of the memory kept across sessions, in order, with when each was set \
+         and when it expires. With `prefix`, only the keys starting with it.",
+        json!({
+            "type": "object",
+            "properties": {
+                "prefix": { "type": "string", "description": "Start of the keys to list" }
+            }
+        }),
+        |ctx, arguments| {
+            let prefix = arguments
+                .get("prefix")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match entries(&ctx.config) {
+                Ok(entries) => {
+                    let matching: Vec<_> = entries
+                        .iter()
+                        .filter(|(key, _)| key.starts_with(prefix))
+                        .collect();
+                    let listed: Vec<Value> = matching
+                        .iter()
+                        .take(MAX_LISTED)
+                        .map(|(key, entry)| {
+                            json!({ "key": key, "updated": entry.updated, "expires": entry.expires })
+                        })
+                        .collect();
+                    Ok(CallToolResult::structured(json!({
+                        "entries": listed,
+                        "total": matching.len(),
+                    })))
+                }
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.memory_file.is_some())
+}
+
+/// The key, value and time to live of a `memory_set` call.
+fn set_arguments(arguments: &Value) -> Result<(&str, &Value, Option<u64>), JsonRpcError> {
+    let key = required_str(arguments, "key")?;
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(invalid(format!(
+            "the key must have 1 to {} bytes",
+            MAX_KEY_LENGTH
+        )));
+    }
+    let value = arguments
+        .get("value")
+        .ok_or_else(|| invalid("Missing argument 'value'".to_string()))?;
+    let ttl = match arguments.get("ttl_secs") {
+        None | Some(Value::Null) => None,
+        Some(ttl) => Some(
+            ttl.as_u64()
+                .filter(|ttl| *ttl > 0)
+                .ok_or_else(|| invalid("ttl_secs must be a positive integer".to_string()))?,
+        ),
+    };
+    Ok((key, value, ttl))
+}
+
+fn invalid(message: String) -> JsonRpcError {
+    JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, message)
+}
+
+fn memory_file(config: &Config) -> Result<&Path, String> {
+    config
+        .memory_file
+        .as_deref()
+        .ok_or_else(|| "no memory_file is set".to_string())
+}
+
+/// Stores `value` under `key`, dropping the expired entries while the file is rewritten.
+fn set(config: &Config, key: &str, value: Value, ttl: Option<u64>) -> Result<Entry, String> {
+    let path = memory_file(config)?;
+    let _lock = FILE.lock().unwrap();
+    let now = Utc::now();
+    let mut entries = load(path)?;
+    entries.retain(|_, entry| !entry.is_expired(now));
+    let entry = Entry {
+        value,
+        updated: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        expires: ttl.map(|ttl| {
+            let expires = now + chrono::Duration::seconds(ttl.min(i64::MAX as u64) as i64);
+            expires.to_rfc3339_opts(SecondsFormat::Secs, true)
+        }),
+    };
+    entries.insert(key.to_string(), entry.clone());
+    save(path, &entries)?;
+    Ok(entry)
+}
+
+/// The entries that haven't expired.
+fn entries(config: &Config) -> Result<BTreeMap<String, Entry>, String> {
+    let path = memory_file(config)?;
+    let _lock = FILE.lock().unwrap();
+    let now = Utc::now();
+    let mut entries = load(path)?;
+    entries.retain(|_, entry| !entry.is_expired(now));
+    Ok(entries)
+}
+
+fn load(path: &Path) -> Result<BTreeMap<String, Entry>, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map_err(|e| format!("invalid memory file {}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(format!("cannot read {}: {}", path.display(), e)),
+    }
+}
+
+/// Replaces the file at once, so that a failure leaves it as it was.
+fn save(path: &Path, entries: &BTreeMap<String, Entry>) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    let text = serde_json::to_string_pretty(entries).unwrap_or_default() + "\n";
+    fs::write(&temporary, text)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
@@ -0,0 +1,80 @@
+//! MIME types of the files read as resources, and whether they are returned as text or blobs.
+
+use crate::{builtin::cache::CachedFile, mcp::ResourceContents};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Number of leading bytes inspected to tell text from binary files.
+const SNIFF_SIZE: usize = 8192;
+
+/// MIME type of `path` from its extension, falling back to sniffing `contents` (or the start
+/// of the file when not given): `text/plain` for UTF-8 text, `application/octet-stream` else.
+pub(crate) fn guess_mime_type(path: &Path, contents: Option<&[u8]>) -> String {
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return mime.essence_str().to_string();
+    }
+    let sniffed;
+    let contents = match contents {
+        Some(contents) => contents,
+        None => {
+            sniffed = read_prefix(path).unwrap_or_default();
+            &sniffed
+        }
+    };
+    if looks_like_text(contents) {
+        "text/plain".to_string()
+    } else {
+        "application/octet-stream".to_string()
+    }
+}
+
+fn read_prefix(path: &Path) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(SNIFF_SIZE);
+    fs::File::open(path)?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// True if `contents` has no NUL bytes and is valid UTF-8, allowing a character cut at the end.
+fn looks_like_text(contents: &[u8]) -> bool {
+    let prefix = &contents[..contents.len().min(SNIFF_SIZE)];
+    if prefix.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(prefix) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// True if contents of this MIME type should be returned as text.
+fn is_text_mime_type(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || mime_type.ends_with("+json")
+        || mime_type.ends_with("+xml")
+        || matches!(
+            mime_type,
+            "application/json" | "application/xml" | "application/javascript" | "application/toml"
+        )
+}
+
+/// Contents of the file at `path` read as the resource `uri`: text for textual MIME types,
+/// a base64-encoded blob otherwise (images, archives...), with its modification time.
+pub(crate) fn file_contents(uri: &str, path: &Path, file: &CachedFile) -> ResourceContents {
+    let bytes = file.contents.as_slice();
+    let mime_type = guess_mime_type(path, Some(bytes));
+    let contents = match std::str::from_utf8(bytes) {
+        Ok(text) if is_text_mime_type(&mime_type) => {
+            ResourceContents::text(uri, text).with_mime_type(mime_type)
+        }
+        _ => ResourceContents::blob(uri, bytes, mime_type),
+    };
+    match file.modified {
+        Some(modified) => contents.with_last_modified(modified),
+        None => contents,
+    }
+}
@@ -0,0 +1,50 @@
+//! Tools and resources shipped with the server. Those with dependencies of their own need the
+//! `builtin` feature, or a feature of their own for the database, Redis and Docker clients, the
+//! jq interpreter, the Readability extraction, the clipboard and the PDF and DOCX readers.
+
+#[cfg(feature = "builtin")]
+pub mod assets;
+pub(crate) mod cache;
+pub mod calc;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod command;
+pub mod csv;
+pub mod data;
+#[cfg(feature = "db")]
+pub mod db;
+#[cfg(feature = "docker")]
+pub mod docker;
+#[cfg(feature = "documents")]
+pub mod documents;
+#[cfg(feature = "builtin")]
+pub mod encoding;
+pub mod env;
+pub mod fs;
+pub mod git;
+pub mod http;
+#[cfg(feature = "jq")]
+pub mod jq;
+pub mod memory;
+pub(crate) mod mime;
+#[cfg(feature = "readability")]
+pub mod page;
+pub mod prompts;
+#[cfg(unix)]
+pub mod pty;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(any(feature = "db", feature = "docker"))]
+pub(crate) mod runtime;
+#[cfg(feature = "builtin")]
+pub mod search;
+#[cfg(feature = "builtin")]
+pub mod semantic;
+#[cfg(feature = "builtin")]
+pub mod sqlite;
+#[cfg(feature = "builtin")]
+pub mod system;
+#[cfg(feature = "builtin")]
+pub mod time;
+#[cfg(feature = "builtin")]
+pub mod watch;
@@ -0,0 +1,114 @@
+//! Web pages reduced to their main text by a port of Mozilla's Readability, fetched like
+//! `http_fetch` does and under the same `fetch_*` settings.
+
+use crate::{
+    builtin::http::fetch,
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use dom_smoothie::{Readability, TextMode};
+use serde_json::{json, Value};
+
+/// Characters of text returned by default.
+const DEFAULT_MAX_LENGTH: usize = 20_000;
+
+/// `fetch_page` tool downloading a web page and returning its title, byline and main text as
+/// markdown, or its raw HTML.
+pub fn fetch_page_tool() -> Tool {
+    Tool::new(
+        "fetch_page",
+        "Download a web page and return its main text as markdown, with its title and byline, \
+         leaving out the navigation, ads and other clutter. Set raw to get the HTML instead.",
+        json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string", "description": "http:// or https:// URL of the page" },
+                "raw": { "type": "boolean", "description": "Return the HTML of the page as is" },
+                "max_length": { "type": "integer", "minimum": 1, "description": "Characters of text returned, 20000 by default" }
+            },
+            "required": ["url"]
+        }),
+        |ctx, arguments| {
+            let url = required_str(arguments, "url")?;
+            let raw = arguments
+                .get("raw")
+                .and_then(Value::as_bool)
+                .unwrap_or_default();
+            let max_length = arguments
+                .get("max_length")
+                .and_then(Value::as_u64)
+                .map_or(DEFAULT_MAX_LENGTH, |length| length.max(1) as usize);
+            let response = match fetch(ctx, "GET", url, None, "") {
+                Ok(response) => response,
+                Err(e) => return Ok(CallToolResult::error(e)),
+            };
+            let status = response["status"].as_u64().unwrap_or_default();
+            if !(200..300).contains(&status) {
+                return Ok(CallToolResult::error(format!(
+                    "cannot fetch {}: status {}",
+                    url, status
+                )));
+            }
+            let Some(html) = response["body"].as_str() else {
+                return Ok(CallToolResult::error(format!("{} is not a text page", url)));
+            };
+            let fetch_truncated = response["truncated"].as_bool().unwrap_or_default();
+            if raw {
+                let (html, truncated) = cut(html, max_length);
+                return Ok(CallToolResult::structured(json!({
+                    "url": url,
+                    "html": html,
+                    "truncated": truncated || fetch_truncated,
+                })));
+            }
+            match extract(html, url) {
+                Ok(page) => {
+                    let (markdown, truncated) = cut(&page.markdown, max_length);
+                    Ok(CallToolResult::structured(json!({
+                        "url": url,
+                        "title": page.title,
+                        "byline": page.byline,
+                        "site_name": page.site_name,
+                        "markdown": markdown,
+                        "truncated": truncated || fetch_truncated,
+                    })))
+                }
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true, "openWorldHint": true }))
+}
+
+/// Main content of a page.
+pub struct Page {
+    pub title: String,
+    pub byline: Option<String>,
+    pub site_name: Option<String>,
+    pub markdown: String,
+}
+
+/// Extracts the main content of `html`, the page at `url`, whose links are resolved against it.
+pub fn extract(html: &str, url: &str) -> Result<Page, String> {
+    let config = dom_smoothie::Config {
+        text_mode: TextMode::Markdown,
+        ..Default::default()
+    };
+    let article = Readability::new(html, Some(url), Some(config))
+        .and_then(|mut readability| readability.parse())
+        .map_err(|e| format!("cannot extract the text of {}: {}", url, e))?;
+    Ok(Page {
+        title: article.title,
+        byline: article.byline,
+        site_name: article.site_name,
+        markdown: article.text_content.to_string(),
+    })
+}
+
+/// The first `max_length` characters of `text`, and whether some were left out.
+fn cut(text: &str, max_length: usize) -> (&str, bool) {
+    match text.char_indices().nth(max_length) {
+        Some((end, _)) => (&text[..end], true),
+        None => (text, false),
+    }
+}
@@ -0,0 +1,110 @@
+//! Example prompts built on the filesystem and git built-ins.
+
+use crate::{
+    builtin::git,
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{GetPromptResult, PromptMessage, ResourceContents},
+    policy,
+    prompts::Prompt,
+    roots,
+    tools::required_str,
+};
+use serde_json::Value;
+use std::fs;
+
+/// Reads a file under the roots as an embedded resource.
+fn file_resource(ctx: &RequestContext, path: &str) -> Result<ResourceContents, JsonRpcError> {
+    let invalid = |e: String| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e);
+    let path = roots::resolve_param(&ctx.config.roots, path)?;
+    policy::check_file_in_scopes(&ctx.config, ctx.session.scopes.as_deref(), &path)
+        .map_err(invalid)?;
+    let text = fs::read_to_string(&path)
+        .map_err(|e| invalid(format!("cannot read {}: {}", path.display(), e)))?;
+    Ok(ResourceContents::text(roots::path_to_uri(&path), text))
+}
+
+/// `code_review` prompt asking for a review of a file, embedded in the prompt.
+pub fn code_review_prompt() -> Prompt {
+    Prompt::new(
+        "code_review",
+        "Review a source file for bugs, readability and style.",
+        |ctx, arguments| {
+            let path = required_str(arguments, "path")?;
+            let mut request = format!(
+                "Please review the following file ({}). Point out bugs, unclear code and \
+                 deviations from the conventions of the language, with concrete suggestions.",
+                path
+            );
+            if let Some(focus) = arguments.get("focus").and_then(Value::as_str) {
+                request.push_str(&format!(" Focus in particular on: {}.", focus));
+            }
+            Ok(GetPromptResult::new(vec![
+                PromptMessage::user(request),
+                PromptMessage::user_resource(file_resource(ctx, path)?),
+            ])
+            .with_description(format!("Code review of {}", path)))
+        },
+    )
+    .with_argument("path", "Path of the file to review", true)
+    .with_argument("focus", "Aspect to focus on, e.g. error handling", false)
+}
+
+/// `summarize_file` prompt asking for a summary of a file, embedded in the prompt.
+pub fn summarize_file_prompt() -> Prompt {
+    Prompt::new(
+        "summarize_file",
+        "Summarize the contents of a file.",
+        |ctx, arguments| {
+            let path = required_str(arguments, "path")?;
+            let length = match arguments.get("length").and_then(Value::as_str) {
+                Some("detailed") => "a detailed summary, section by section",
+                _ => "a short summary of a few sentences",
+            };
+            Ok(GetPromptResult::new(vec![
+                PromptMessage::user(format!(
+                    "Write {} of the following file ({}).",
+                    length, path
+                )),
+                PromptMessage::user_resource(file_resource(ctx, path)?),
+            ])
+            .with_description(format!("Summary of {}", path)))
+        },
+    )
+    .with_argument("path", "Path of the file to summarize", true)
+    .with_argument("length", "\"short\" (default) or \"detailed\"", false)
+}
+
+/// `commit_message` prompt asking for a commit message for the staged changes of a
+/// repository, embedded in the prompt.
+pub fn commit_message_prompt() -> Prompt {
+    Prompt::new(
+        "commit_message",
+        "Write a commit message for the staged changes of a git repository.",
+        |ctx, arguments| {
+            let repo = arguments.get("repo").and_then(Value::as_str).unwrap_or(".");
+            let invalid = |e: String| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e);
+            let repo = roots::resolve(&ctx.config.roots, repo).map_err(invalid)?;
+            let patch = git::staged_patch(&repo).map_err(invalid)?;
+            if patch.is_empty() {
+                return Err(invalid(format!("nothing is staged in {}", repo.display())));
+            }
+            let diff = ResourceContents::text(roots::path_to_uri(&repo), patch)
+                .with_mime_type("text/x-diff");
+            Ok(GetPromptResult::new(vec![
+                PromptMessage::user(
+                    "Write a commit message for the following staged changes: a summary line \
+                     of at most 72 characters in the imperative mood, a blank line, then a \
+                     short explanation of what changed and why.",
+                ),
+                PromptMessage::user_resource(diff),
+            ])
+            .with_description("Commit message for the staged changes"))
+        },
+    )
+    .with_argument(
+        "repo",
+        "Path of the repository, defaults to the first root directory",
+        false,
+    )
+}
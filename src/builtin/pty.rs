@@ -0,0 +1,431 @@
+//! Interactive commands run in a pseudo-terminal, for the REPLs and CLIs that behave differently
+//! without one: `shell_session_start` starts one, `shell_session_send` types into it,
+//! `shell_session_read` returns what it printed since the last read and `shell_session_stop`
+//! ends it. The terminals belong to the client session that started them, and are killed when
+//! it ends. Only offered when `allow_commands` is set, under the same `command_*` settings as
+//! `run_command`. Unix only.
+
+use crate::{
+    builtin::command::{prepare, sandbox},
+    context::{random_id, RequestContext},
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{FromRawFd, OwnedFd},
+    os::unix::process::CommandExt,
+    process::{Child, Command, Stdio},
+    ptr,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Terminals a client session may have running at once.
+const MAX_TERMINALS: usize = 8;
+/// Time `shell_session_read` waits for output by default.
+const DEFAULT_WAIT: Duration = Duration::from_millis(500);
+/// Upper bound of `wait_ms`.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+/// Time given to a program to finish printing once it started, so that a read doesn't return
+/// the first bytes of an output alone.
+const SETTLE: Duration = Duration::from_millis(50);
+/// Size of the terminals by default.
+const DEFAULT_SIZE: (u16, u16) = (24, 80);
+
+/// `shell_session_start` tool starting a command in a new terminal.
+pub fn start_tool() -> Tool {
+    Tool::new(
+        "shell_session_start",
+        "Start an interactive command (a shell, a REPL, a CLI asking questions...) in a \
+         pseudo-terminal and return the id of the session along with what it printed first. \
+         Use shell_session_send to type into it, shell_session_read to get its output and \
+         shell_session_stop to end it.",
+        json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "Executable to run, e.g. bash or python3" },
+                "args": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Arguments passed to the executable"
+                },
+                "cwd": {
+                    "type": "string",
+                    "description": "Working directory, defaults to the first root directory"
+                },
+                "rows": { "type": "integer", "minimum": 1, "maximum": 500, "description": "Height of the terminal, 24 by default" },
+                "cols": { "type": "integer", "minimum": 1, "maximum": 500, "description": "Width of the terminal, 80 by default" }
+            },
+            "required": ["command"]
+        }),
+        |ctx, arguments| {
+            let (command, args, cwd) = prepare(ctx, arguments)?;
+            let size = |name, default| {
+                arguments
+                    .get(name)
+                    .and_then(Value::as_u64)
+                    .map_or(default, |n| n.clamp(1, 500) as u16)
+            };
+            let size = (size("rows", DEFAULT_SIZE.0), size("cols", DEFAULT_SIZE.1));
+            let terminals = ctx.session_state_bag().get_or_insert_with(Terminals::default);
+            if terminals.count() >= MAX_TERMINALS {
+                return Ok(CallToolResult::error(format!(
+                    "at most {} shell sessions can run at once, stop one first",
+                    MAX_TERMINALS
+                )));
+            }
+            let mut process = Command::new(command);
+            process.args(&args);
+            if let Some(cwd) = &cwd {
+                process.current_dir(cwd);
+            }
+            if let Err(e) = sandbox(&ctx.config, &mut process) {
+                return Ok(CallToolResult::error(e));
+            }
+            if !ctx.config.command_env.contains_key("TERM") {
+                // Few escape sequences, which the model would have to read through
+                process.env("TERM", "dumb");
+            }
+            tracing::info!("Starting {} {:?} in a terminal in {:?}", command, args, cwd);
+            let terminal = match Terminal::spawn(&mut process, size, ctx.config.command_max_output)
+            {
+                Ok(terminal) => Arc::new(terminal),
+                Err(e) => {
+                    return Ok(CallToolResult::error(format!(
+                        "cannot run {}: {}",
+                        command, e
+                    )))
+                }
+            };
+            let id = random_id();
+            terminals.insert(id.clone(), terminal.clone());
+            let mut result = terminal.read(ctx, DEFAULT_WAIT);
+            result["id"] = json!(id);
+            Ok(CallToolResult::structured(result))
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "openWorldHint": true }))
+    .available_when(|config| config.allow_commands)
+}
+
+/// `shell_session_send` tool writing input to the terminal of a session.
+pub fn send_tool() -> Tool {
+    Tool::new(
+        "shell_session_send",
+        "Type text into a shell session, as if on its keyboard. End it with \\n to press Enter; \
+         control characters such as \\u0003 (Ctrl-C) or \\u0004 (Ctrl-D) are passed as is. \
+         Returns what the session printed in response.",
+        json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Id of the session" },
+                "input": { "type": "string", "description": "Text to type" },
+                "wait_ms": { "type": "integer", "minimum": 0, "maximum": 30000, "description": "Time to wait for output, 500 by default" }
+            },
+            "required": ["id", "input"]
+        }),
+        |ctx, arguments| {
+            let terminal = find(ctx, arguments)?;
+            let input = required_str(arguments, "input")?;
+            if let Err(e) = terminal.write(input.as_bytes()) {
+                return Ok(CallToolResult::error(format!(
+                    "cannot write to the session: {}",
+                    e
+                )));
+            }
+            let result = terminal.read(ctx, wait(arguments));
+            Ok(CallToolResult::structured(result))
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "openWorldHint": true }))
+    .available_when(|config| config.allow_commands)
+}
+
+/// `shell_session_read` tool returning the output of a session not read yet.
+pub fn read_tool() -> Tool {
+    Tool::new(
+        "shell_session_read",
+        "Return what a shell session printed since the last read, waiting up to wait_ms for \
+         it to print something, and whether its command exited.",
+        json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Id of the session" },
+                "wait_ms": { "type": "integer", "minimum": 0, "maximum": 30000, "description": "Time to wait for output, 500 by default" }
+            },
+            "required": ["id"]
+        }),
+        |ctx, arguments| {
+            let terminal = find(ctx, arguments)?;
+            Ok(CallToolResult::structured(
+                terminal.read(ctx, wait(arguments)),
+            ))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_commands)
+}
+
+/// `shell_session_stop` tool killing the command of a session.
+pub fn stop_tool() -> Tool {
+    Tool::new(
+        "shell_session_stop",
+        "End a shell session, killing its command and what it started, and return the output \
+         not read yet.",
+        json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Id of the session" }
+            },
+            "required": ["id"]
+        }),
+        |ctx, arguments| {
+            let terminal = find(ctx, arguments)?;
+            let id = required_str(arguments, "id")?;
+            if let Some(terminals) = ctx.session_state::<Terminals>() {
+                terminals.remove(id);
+            }
+            terminal.kill();
+            Ok(CallToolResult::structured(
+                terminal.read(ctx, Duration::ZERO),
+            ))
+        },
+    )
+    .with_annotations(json!({ "destructiveHint": true, "idempotentHint": true }))
+    .available_when(|config| config.allow_commands)
+}
+
+/// The terminal of the session `id` of the arguments.
+fn find(ctx: &RequestContext, arguments: &Value) -> Result<Arc<Terminal>, JsonRpcError> {
+    let id = required_str(arguments, "id")?;
+    ctx.session_state::<Terminals>()
+        .and_then(|terminals| terminals.get(id))
+        .ok_or_else(|| {
+            JsonRpcError::new(
+                ERROR_CODE_INVALID_PARAMS,
+                format!("Unknown shell session: '{}'", id),
+            )
+        })
+}
+
+fn wait(arguments: &Value) -> Duration {
+    arguments
+        .get("wait_ms")
+        .and_then(Value::as_u64)
+        .map_or(DEFAULT_WAIT, |ms| Duration::from_millis(ms).min(MAX_WAIT))
+}
+
+/// Terminals of a client session, kept in its state and killed with it.
+#[derive(Default)]
+struct Terminals {
+    terminals: Mutex<HashMap<String, Arc<Terminal>>>,
+}
+
+impl Terminals {
+    fn count(&self) -> usize {
+        self.terminals.lock().unwrap().len()
+    }
+
+    fn insert(&self, id: String, terminal: Arc<Terminal>) {
+        self.terminals.lock().unwrap().insert(id, terminal);
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<Terminal>> {
+        self.terminals.lock().unwrap().get(id).cloned()
+    }
+
+    fn remove(&self, id: &str) {
+        self.terminals.lock().unwrap().remove(id);
+    }
+}
+
+/// A command running in a pseudo-terminal.
+struct Terminal {
+    process: Mutex<Child>,
+    /// Master side of the terminal, which the input is written to
+    input: Mutex<File>,
+    /// What the command printed, filled by the reader thread
+    output: Arc<(Mutex<Output>, Condvar)>,
+}
+
+/// Output of a terminal not read yet.
+#[derive(Default)]
+struct Output {
+    bytes: Vec<u8>,
+    /// Bytes dropped since the last read, the output not being read fast enough
+    dropped: usize,
+    /// True once the terminal was closed, every process using it having exited
+    closed: bool,
+}
+
+impl Terminal {
+    /// Starts `process` in a new terminal of `(rows, cols)`, keeping up to `max_output` bytes
+    /// of output between two reads.
+    fn spawn(
+        process: &mut Command,
+        (rows, cols): (u16, u16),
+        max_output: usize,
+    ) -> io::Result<Terminal> {
+        let (mut master, mut slave) = (0, 0);
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: the out-pointers are valid, and the name and termios are optional
+        let opened =
+            unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &size) };
+        if opened != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: both descriptors were just opened and are owned here
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        process
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: setsid and ioctl are async-signal-safe and touch no memory of the parent
+        unsafe {
+            process.pre_exec(|| {
+                // A session of its own, whose controlling terminal is the new one
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = process.spawn()?;
+        // Drops the descriptors of the slave side held by `process`, so that reads of the
+        // master side end once the command and its children exit
+        process
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        let input = File::from(master);
+        let reader = input.try_clone()?;
+        let output = Arc::new((Mutex::new(Output::default()), Condvar::new()));
+        let filled = output.clone();
+        thread::spawn(move || fill(reader, &filled, max_output));
+        Ok(Terminal {
+            process: Mutex::new(child),
+            input: Mutex::new(input),
+            output,
+        })
+    }
+
+    fn write(&self, input: &[u8]) -> io::Result<()> {
+        let mut terminal = self.input.lock().unwrap();
+        terminal.write_all(input)?;
+        terminal.flush()
+    }
+
+    /// `{output, exited, exit_code, dropped}`: the output printed since the last read once
+    /// there is some (or the command exited), waiting up to `wait` and until the call is
+    /// cancelled.
+    fn read(&self, ctx: &RequestContext, wait: Duration) -> Value {
+        let wait = ctx.limit_timeout(wait).min(wait);
+        let start = Instant::now();
+        let (lock, changed) = &*self.output;
+        let mut output = lock.lock().unwrap();
+        while output.bytes.is_empty() && !output.closed && !ctx.cancellation.is_cancelled() {
+            let left = wait.saturating_sub(start.elapsed());
+            if left.is_zero() {
+                break;
+            }
+            output = changed.wait_timeout(output, left.min(SETTLE)).unwrap().0;
+        }
+        if !output.bytes.is_empty() && !output.closed {
+            drop(output);
+            thread::sleep(SETTLE.min(wait.saturating_sub(start.elapsed())));
+            output = lock.lock().unwrap();
+        }
+        // Bytes of a character cut at the end of the output are kept for the next read
+        let complete = match std::str::from_utf8(&output.bytes) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => output.bytes.len(),
+        };
+        let bytes: Vec<u8> = output.bytes.drain(..complete).collect();
+        let dropped = std::mem::take(&mut output.dropped);
+        drop(output);
+        let status = self.process.lock().unwrap().try_wait().ok().flatten();
+        json!({
+            "output": String::from_utf8_lossy(&bytes),
+            "exited": status.is_some(),
+            "exit_code": status.and_then(|status| status.code()),
+            "dropped_bytes": dropped,
+        })
+    }
+
+    /// Kills the command and the processes of its session, then reaps the command.
+    fn kill(&self) {
+        let mut process = self.process.lock().unwrap();
+        if let Ok(Some(_)) = process.try_wait() {
+            return;
+        }
+        if let Ok(leader) = libc::pid_t::try_from(process.id()) {
+            // SAFETY: kill has no memory safety requirements; the group and the session are the
+            // ones the command leads, whose ids can't be reused while the command isn't reaped
+            unsafe {
+                libc::kill(-leader, libc::SIGKILL);
+                for pid in session_members(leader) {
+                    libc::kill(pid, libc::SIGKILL);
+                }
+            }
+        }
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Processes of the session `leader` leads outside of its process group, such as the jobs a
+/// shell runs in the background. Only found on systems with a `/proc`.
+fn session_members(leader: libc::pid_t) -> Vec<libc::pid_t> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        // SAFETY: getsid has no memory safety requirements
+        .filter(|&pid| pid != leader && unsafe { libc::getsid(pid) } == leader)
+        .collect()
+}
+
+/// Reads the master side of a terminal into `output` until it is closed, dropping the oldest
+/// bytes beyond `max_output`.
+fn fill(mut terminal: File, output: &(Mutex<Output>, Condvar), max_output: usize) {
+    let (lock, changed) = output;
+    let mut buf = [0; 8192];
+    loop {
+        // Fails with EIO on Linux once no process has the slave side open
+        let n = terminal.read(&mut buf).unwrap_or(0);
+        let mut output = lock.lock().unwrap();
+        if n == 0 {
+            output.closed = true;
+            changed.notify_all();
+            return;
+        }
+        output.bytes.extend_from_slice(&buf[..n]);
+        let excess = output.bytes.len().saturating_sub(max_output);
+        if excess > 0 {
+            output.bytes.drain(..excess);
+            output.dropped += excess;
+        }
+        changed.notify_all();
+    }
+}
@@ -0,0 +1,197 @@
+//! Commands against the Redis instances listed in `redis_instances`, restricted to the keys
+//! matching `redis_read_keys` and `redis_write_keys`.
+
+use crate::{
+    builtin::env::matches_case_sensitive,
+    config::Config,
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::CallToolResult,
+    tools::{required_str, Tool},
+};
+use ::redis::{Connection, RedisResult};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, time::Duration};
+
+/// Time a command may take, including the connection.
+const REDIS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keys returned by `KEYS`.
+const MAX_KEYS: usize = 1000;
+
+/// Keys asked of each `SCAN` of `KEYS`.
+const SCAN_COUNT: usize = 500;
+
+/// `redis` tool running `GET`, `SET`, `KEYS` or `DEL` against a configured instance. Only
+/// offered when `allow_redis` is set and instances are configured.
+pub fn command_tool() -> Tool {
+    Tool::new(
+        "redis",
+        "Run a command against a configured Redis instance: GET the value of a key, SET it \
+         (for ttl_secs seconds if given), DEL a key, or list the KEYS matching a pattern. Only \
+         the keys the server allows can be read or written.",
+        json!({
+            "type": "object",
+            "properties": {
+                "instance": { "type": "string", "description": "Name of the instance" },
+                "command": { "type": "string", "enum": ["GET", "SET", "KEYS", "DEL"] },
+                "key": { "type": "string", "description": "Key of GET, SET and DEL" },
+                "value": { "type": "string", "description": "Value of SET" },
+                "ttl_secs": { "type": "integer", "minimum": 1, "description": "Seconds the value of SET is kept, for good when absent" },
+                "pattern": { "type": "string", "description": "Pattern of KEYS, * by default" }
+            },
+            "required": ["instance", "command"]
+        }),
+        |ctx, arguments| {
+            let instance = required_str(arguments, "instance")?;
+            let result = match required_str(arguments, "command")? {
+                "GET" => {
+                    let key = required_str(arguments, "key")?;
+                    allowed(&ctx.config.redis_read_keys, key)
+                        .and_then(|_| run(ctx, instance, |connection| get(connection, key)))
+                }
+                "SET" => {
+                    let key = required_str(arguments, "key")?;
+                    let value = required_str(arguments, "value")?;
+                    let ttl = match arguments.get("ttl_secs") {
+                        None | Some(Value::Null) => None,
+                        Some(ttl) => Some(ttl.as_u64().filter(|ttl| *ttl > 0).ok_or_else(|| {
+                            JsonRpcError::new(
+                                ERROR_CODE_INVALID_PARAMS,
+                                "ttl_secs must be a positive integer",
+                            )
+                        })?),
+                    };
+                    allowed(&ctx.config.redis_write_keys, key).and_then(|_| {
+                        run(ctx, instance, |connection| set(connection, key, value, ttl))
+                    })
+                }
+                "DEL" => {
+                    let key = required_str(arguments, "key")?;
+                    allowed(&ctx.config.redis_write_keys, key).and_then(|_| {
+                        run(ctx, instance, |connection| {
+                            let deleted: u64 = ::redis::cmd("DEL").arg(key).query(connection)?;
+                            Ok(json!({ "key": key, "deleted": deleted }))
+                        })
+                    })
+                }
+                "KEYS" => {
+                    let pattern = arguments
+                        .get("pattern")
+                        .and_then(Value::as_str)
+                        .unwrap_or("*");
+                    run(ctx, instance, |connection| {
+                        keys(connection, pattern, &ctx.config.redis_read_keys)
+                    })
+                }
+                other => {
+                    return Err(JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("unsupported command '{}'", other),
+                    ))
+                }
+            };
+            match result {
+                Ok(result) => Ok(CallToolResult::structured(result)),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .available_when(|config| config.allow_redis && !config.redis_instances.is_empty())
+}
+
+/// Refuses the keys matching none of `patterns`.
+fn allowed(patterns: &[String], key: &str) -> Result<(), String> {
+    match patterns
+        .iter()
+        .any(|pattern| matches_case_sensitive(pattern, key))
+    {
+        true => Ok(()),
+        false => Err(format!("access to the key '{}' is not allowed", key)),
+    }
+}
+
+/// Connects to a configured instance and runs `command` on it.
+fn run(
+    ctx: &RequestContext,
+    instance: &str,
+    command: impl FnOnce(&mut Connection) -> RedisResult<Value>,
+) -> Result<Value, String> {
+    let mut connection = connect(&ctx.config, instance, ctx.limit_timeout(REDIS_TIMEOUT))?;
+    tracing::info!("Running a Redis command on {}", instance);
+    command(&mut connection).map_err(|e| e.to_string())
+}
+
+fn connect(config: &Config, instance: &str, timeout: Duration) -> Result<Connection, String> {
+    let Some(url) = config.redis_instances.get(instance) else {
+        return Err(format!("unknown instance '{}'", instance));
+    };
+    let connect = || -> RedisResult<Connection> {
+        let connection =
+            ::redis::Client::open(url.as_str())?.get_connection_with_timeout(timeout)?;
+        connection.set_read_timeout(Some(timeout))?;
+        connection.set_write_timeout(Some(timeout))?;
+        Ok(connection)
+    };
+    connect().map_err(|e| format!("cannot connect to instance '{}': {}", instance, e))
+}
+
+/// The value of `key`, base64-encoded if it isn't UTF-8.
+fn get(connection: &mut Connection, key: &str) -> RedisResult<Value> {
+    let value: Option<Vec<u8>> = ::redis::cmd("GET").arg(key).query(connection)?;
+    Ok(match value {
+        None => json!({ "key": key, "value": null }),
+        Some(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => json!({ "key": key, "value": text }),
+            Err(e) => json!({ "key": key, "value": BASE64.encode(e.as_bytes()), "base64": true }),
+        },
+    })
+}
+
+fn set(
+    connection: &mut Connection,
+    key: &str,
+    value: &str,
+    ttl: Option<u64>,
+) -> RedisResult<Value> {
+    let mut command = ::redis::cmd("SET");
+    command.arg(key).arg(value);
+    if let Some(ttl) = ttl {
+        command.arg("EX").arg(ttl);
+    }
+    command.query::<()>(connection)?;
+    Ok(json!({ "key": key, "ttl_secs": ttl }))
+}
+
+/// The keys matching `pattern` that can be read, in order, scanned rather than listed with
+/// `KEYS`, which would block the instance. `SCAN` may return a key more than once.
+fn keys(connection: &mut Connection, pattern: &str, readable: &[String]) -> RedisResult<Value> {
+    let mut keys = BTreeSet::new();
+    let mut cursor = 0u64;
+    let mut truncated = false;
+    loop {
+        let (next, batch): (u64, Vec<String>) = ::redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .query(connection)?;
+        keys.extend(
+            batch
+                .into_iter()
+                .filter(|key| allowed(readable, key).is_ok()),
+        );
+        if keys.len() > MAX_KEYS {
+            truncated = true;
+            break;
+        }
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    let keys: Vec<String> = keys.into_iter().take(MAX_KEYS).collect();
+    Ok(json!({ "keys": keys, "truncated": truncated }))
+}
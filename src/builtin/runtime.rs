@@ -0,0 +1,37 @@
+//! The tokio runtime driving the asynchronous clients of the built-in tools (sqlx, bollard),
+//! started on first use and shared by them.
+
+use std::{fmt::Display, future::Future, sync::OnceLock, time::Duration};
+use tokio::runtime::Runtime;
+
+static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+
+pub(crate) fn runtime() -> Result<&'static Runtime, String> {
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name("builtin-runtime")
+                .enable_all()
+                .build()
+                .map_err(|e| format!("cannot start the runtime of the tools: {}", e))
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Runs `future` on the runtime, failing after `timeout` unless it's zero.
+pub(crate) fn block_on<T, E: Display>(
+    timeout: Duration,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, String> {
+    runtime()?.block_on(async {
+        if timeout.is_zero() {
+            return future.await.map_err(|e| e.to_string());
+        }
+        match tokio::time::timeout(timeout, future).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("timed out after {:?}", timeout)),
+        }
+    })
+}
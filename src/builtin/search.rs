@@ -0,0 +1,178 @@
+//! Text search across the files under the roots.
+
+use crate::{
+    context::RequestContext,
+    mcp::CallToolResult,
+    roots,
+    tools::{required_str, Tool},
+};
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Default number of matches per page.
+const DEFAULT_LIMIT: u64 = 100;
+/// Files larger than this are not searched.
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// Matched lines are cut to this many characters.
+const MAX_LINE_LENGTH: usize = 500;
+
+/// `search_text` tool returning the lines matching a pattern in the files under the roots.
+pub fn search_tool() -> Tool {
+    Tool::new(
+        "search_text",
+        "Search the files under the root directories for a regular expression (or a literal \
+         string with `literal`), skipping files ignored by .gitignore/.ignore and binary files. \
+         Returns the matches (file, line number, line) one page at a time; pass the returned \
+         `next_offset` as `offset` to get the next page.",
+        json!({
+            "type": "object",
+            "properties": {
+                "pattern": { "type": "string", "description": "Regular expression, or text with `literal`" },
+                "literal": { "type": "boolean", "description": "Search for the pattern as plain text" },
+                "case_insensitive": { "type": "boolean", "description": "Ignore case" },
+                "path": { "type": "string", "description": "Directory or file to search, all the roots by default" },
+                "offset": { "type": "integer", "minimum": 0, "description": "Number of matches to skip" },
+                "limit": { "type": "integer", "minimum": 1, "description": "Matches per page, 100 by default" }
+            },
+            "required": ["pattern"]
+        }),
+        |ctx, arguments| {
+            let pattern = required_str(arguments, "pattern")?;
+            let flag = |name| arguments.get(name).and_then(Value::as_bool) == Some(true);
+            let number = |name| arguments.get(name).and_then(Value::as_u64);
+            let pattern = if flag("literal") {
+                regex::escape(pattern)
+            } else {
+                pattern.to_string()
+            };
+            let regex = match RegexBuilder::new(&pattern)
+                .case_insensitive(flag("case_insensitive"))
+                .build()
+            {
+                Ok(regex) => regex,
+                Err(e) => return Ok(CallToolResult::error(format!("invalid pattern: {}", e))),
+            };
+            let paths = match arguments.get("path").and_then(Value::as_str) {
+                Some(path) => match roots::resolve(&ctx.config.roots, path) {
+                    Ok(path) => vec![path],
+                    Err(e) => return Ok(CallToolResult::error(e)),
+                },
+                None if ctx.config.roots.is_empty() => {
+                    return Ok(CallToolResult::error("no root directories are configured"))
+                }
+                None => ctx.config.roots.clone(),
+            };
+            let offset = number("offset").unwrap_or(0) as usize;
+            let limit = number("limit").unwrap_or(DEFAULT_LIMIT) as usize;
+            Ok(CallToolResult::text(
+                search(ctx, &regex, &paths, offset, limit).to_string(),
+            ))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+/// Collects the matches `offset..offset + limit`, in a stable order (sorted file names). When
+/// the client asked for progress notifications, the matches of each file are streamed as
+/// their messages as the search goes.
+fn search(
+    ctx: &RequestContext,
+    regex: &Regex,
+    paths: &[PathBuf],
+    offset: usize,
+    limit: usize,
+) -> Value {
+    let mut matches = Vec::new();
+    let mut seen = 0;
+    let mut more = false;
+    'walk: for path in paths {
+        let walker = WalkBuilder::new(path)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walker.flatten() {
+            if ctx.cancellation.is_cancelled() {
+                break 'walk;
+            }
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let too_big = entry
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() > MAX_FILE_SIZE);
+            if too_big {
+                continue;
+            }
+            let found = matches.len();
+            for (line_number, line) in search_file(entry.path(), regex) {
+                if seen >= offset + limit {
+                    more = true;
+                    break;
+                }
+                if seen >= offset {
+                    matches.push(json!({
+                        "file": entry.path(),
+                        "line": line_number,
+                        "text": line,
+                    }));
+                }
+                seen += 1;
+            }
+            if matches.len() > found && ctx.wants_progress_messages() {
+                let lines: Vec<String> = matches[found..]
+                    .iter()
+                    .map(|m| {
+                        format!(
+                            "{}:{}: {}",
+                            entry.path().display(),
+                            m["line"],
+                            m["text"].as_str().unwrap_or_default()
+                        )
+                    })
+                    .collect();
+                ctx.send_progress(matches.len(), Some(limit), Some(&lines.join("\n")));
+            }
+            if more {
+                break 'walk;
+            }
+        }
+    }
+    json!({
+        "matches": matches,
+        "next_offset": more.then_some(offset + limit),
+    })
+}
+
+/// Returns the matching lines of a file, or nothing for binary files.
+fn search_file(path: &Path, regex: &Regex) -> Vec<(usize, String)> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    let mut reader = BufReader::new(file);
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if buf.contains(&0) {
+            // Binary file
+            return Vec::new();
+        }
+        line_number += 1;
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if regex.is_match(line) {
+            matches.push((line_number, line.chars().take(MAX_LINE_LENGTH).collect()));
+        }
+    }
+    matches
+}
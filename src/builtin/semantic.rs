@@ -0,0 +1,422 @@
+//! Semantic search across the text files under the roots. `semantic_search` cuts the files into
+//! chunks of lines, embeds them with the OpenAI-compatible endpoint `embedding_url` (a local
+//! Ollama or llama.cpp server, or a hosted API) and returns the chunks closest to the query.
+//!
+//! The embeddings are kept in a flat index, compared to the query by cosine similarity, in
+//! memory and in the `semantic_index` file if set. Each search embeds again the files that
+//! changed since the last one, and only those.
+
+use crate::{
+    config::Config,
+    context::RequestContext,
+    mcp::CallToolResult,
+    roots,
+    tools::{required_str, Tool},
+};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Default number of chunks returned.
+const DEFAULT_LIMIT: usize = 5;
+/// Files larger than this are not indexed.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Lines of a chunk.
+const CHUNK_LINES: usize = 40;
+/// Characters of a chunk, the longer ones being cut at the line before.
+const MAX_CHUNK_LENGTH: usize = 4000;
+/// Chunks of the index, the files past them being left out.
+const MAX_CHUNKS: usize = 50_000;
+/// Chunks embedded by one request to `embedding_url`.
+const BATCH_SIZE: usize = 32;
+/// Time a request to `embedding_url` may take, within the deadline of the call.
+const EMBEDDING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The index of the process, loaded from `semantic_index` on the first search.
+static INDEX: Mutex<Option<Index>> = Mutex::new(None);
+
+/// Embeddings of the chunks of the files, for one model.
+#[derive(Serialize, Deserialize, Default)]
+struct Index {
+    model: String,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    /// Modification time when the file was embedded, in nanoseconds since the Unix epoch
+    modified: u64,
+    size: u64,
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Chunk {
+    /// First line of the chunk, counting from 1
+    start_line: usize,
+    /// Last line of the chunk
+    end_line: usize,
+    /// Embedding, of length 1
+    vector: Vec<f32>,
+}
+
+/// A file waiting to be embedded, with the lines and text of its chunks.
+struct Pending {
+    path: PathBuf,
+    modified: u64,
+    size: u64,
+    chunks: Vec<(usize, usize, String)>,
+}
+
+/// `semantic_search` tool returning the chunks of the files under the roots closest in meaning
+/// to a query. Only offered when `embedding_url` is set.
+pub fn search_tool() -> Tool {
+    Tool::new(
+        "semantic_search",
+        "Search the text files under the root directories by meaning rather than by exact \
+         words, skipping files ignored by .gitignore/.ignore. Returns the passages (file, \
+         lines, text) closest to the query, best first, with their similarity score.",
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "What to look for, in plain words" },
+                "path": { "type": "string", "description": "Directory or file to search, all the roots by default" },
+                "limit": { "type": "integer", "minimum": 1, "description": "Passages returned, 5 by default" }
+            },
+            "required": ["query"]
+        }),
+        |ctx, arguments| {
+            let query = required_str(arguments, "query")?;
+            let paths = match arguments.get("path").and_then(Value::as_str) {
+                Some(path) => match roots::resolve(&ctx.config.roots, path) {
+                    Ok(path) => vec![path],
+                    Err(e) => return Ok(CallToolResult::error(e)),
+                },
+                None if ctx.config.roots.is_empty() => {
+                    return Ok(CallToolResult::error("no root directories are configured"))
+                }
+                None => ctx.config.roots.clone(),
+            };
+            let limit = arguments
+                .get("limit")
+                .and_then(Value::as_u64)
+                .map_or(DEFAULT_LIMIT, |limit| limit.max(1) as usize);
+            match search(ctx, query, &paths, limit) {
+                Ok(result) => Ok(CallToolResult::structured(result)),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.embedding_url.is_some())
+}
+
+fn search(
+    ctx: &RequestContext,
+    query: &str,
+    paths: &[PathBuf],
+    limit: usize,
+) -> Result<Value, String> {
+    let mut index = INDEX.lock().unwrap();
+    let index = index.get_or_insert_with(|| load(&ctx.config));
+    if index.model != ctx.config.embedding_model {
+        *index = Index {
+            model: ctx.config.embedding_model.clone(),
+            ..Index::default()
+        };
+    }
+    let (changed, complete) = refresh(ctx, index, paths)?;
+    if changed {
+        if let Some(path) = &ctx.config.semantic_index {
+            if let Err(e) = save(path, index) {
+                tracing::warn!("Cannot save the semantic index: {}", e);
+            }
+        }
+    }
+
+    let query = embed(ctx, &[query.to_string()])?
+        .pop()
+        .ok_or("the embedding endpoint returned no embedding")?;
+    let mut scored: Vec<(f32, &Path, &Chunk)> = index
+        .files
+        .iter()
+        .filter(|(path, _)| paths.iter().any(|root| path.starts_with(root)))
+        .flat_map(|(path, file)| {
+            file.chunks
+                .iter()
+                .map(|chunk| (dot(&query, &chunk.vector), path.as_path(), chunk))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let results: Vec<Value> = scored
+        .iter()
+        .take(limit)
+        .map(|(score, path, chunk)| {
+            json!({
+                "file": path,
+                "start_line": chunk.start_line,
+                "end_line": chunk.end_line,
+                "score": (f64::from(*score) * 1000.0).round() / 1000.0,
+                "text": lines(path, chunk.start_line, chunk.end_line),
+            })
+        })
+        .collect();
+    Ok(json!({ "results": results, "complete": complete }))
+}
+
+/// Embeds the files under `paths` that changed since they were indexed and forgets those that
+/// are gone. Tells whether the index changed, and whether it holds every file (false once it
+/// reached [`MAX_CHUNKS`]).
+fn refresh(
+    ctx: &RequestContext,
+    index: &mut Index,
+    paths: &[PathBuf],
+) -> Result<(bool, bool), String> {
+    let mut seen = HashSet::new();
+    let mut pending = Vec::new();
+    for root in paths {
+        for entry in WalkBuilder::new(root).build().flatten() {
+            if ctx.cancellation.is_cancelled() {
+                return Err("cancelled".to_string());
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || metadata.len() > MAX_FILE_SIZE {
+                continue;
+            }
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |time| time.as_nanos() as u64);
+            let path = entry.into_path();
+            seen.insert(path.clone());
+            let unchanged = index
+                .files
+                .get(&path)
+                .is_some_and(|file| file.modified == modified && file.size == metadata.len());
+            if !unchanged {
+                pending.push(Pending {
+                    chunks: chunks(&path),
+                    path,
+                    modified,
+                    size: metadata.len(),
+                });
+            }
+        }
+    }
+    let before = index.files.len();
+    index
+        .files
+        .retain(|path, _| seen.contains(path) || !paths.iter().any(|root| path.starts_with(root)));
+    let mut changed = index.files.len() != before;
+
+    let mut room = MAX_CHUNKS.saturating_sub(index.files.values().map(|f| f.chunks.len()).sum());
+    let mut complete = true;
+    pending.retain(|file| match file.chunks.len() <= room {
+        true => {
+            room -= file.chunks.len();
+            true
+        }
+        false => {
+            complete = false;
+            false
+        }
+    });
+    let texts: Vec<&(usize, usize, String)> =
+        pending.iter().flat_map(|file| &file.chunks).collect();
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(BATCH_SIZE) {
+        if ctx.cancellation.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+        let batch: Vec<String> = batch.iter().map(|(_, _, text)| text.clone()).collect();
+        vectors.extend(embed(ctx, &batch)?);
+        ctx.send_progress(
+            vectors.len(),
+            Some(texts.len()),
+            Some("Embedding the changed files"),
+        );
+    }
+    let mut vectors = vectors.into_iter();
+    for file in pending {
+        let chunks = file
+            .chunks
+            .into_iter()
+            .zip(vectors.by_ref())
+            .map(|((start_line, end_line, _), vector)| Chunk {
+                start_line,
+                end_line,
+                vector,
+            })
+            .collect();
+        let indexed = IndexedFile {
+            modified: file.modified,
+            size: file.size,
+            chunks,
+        };
+        index.files.insert(file.path, indexed);
+        changed = true;
+    }
+    Ok((changed, complete))
+}
+
+/// The chunks of a text file, with their first and last lines; none for binary files.
+fn chunks(path: &Path) -> Vec<(usize, usize, String)> {
+    let Ok(bytes) = fs::read(path) else {
+        return Vec::new();
+    };
+    if bytes.contains(&0) {
+        return Vec::new();
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut start = 1;
+    let mut count = 0;
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        if count == CHUNK_LINES || (count > 0 && chunk.len() + line.len() > MAX_CHUNK_LENGTH) {
+            chunks.push((start, number - 1, std::mem::take(&mut chunk)));
+            start = number;
+            count = 0;
+        }
+        let line: String = line.chars().take(MAX_CHUNK_LENGTH).collect();
+        chunk.push_str(&line);
+        chunk.push('\n');
+        count += 1;
+    }
+    if !chunk.trim().is_empty() {
+        chunks.push((start, start + count - 1, chunk));
+    }
+    chunks.retain(|(_, _, text)| !text.trim().is_empty());
+    chunks
+}
+
+/// Lines `start..=end` of a file, as returned to the client.
+fn lines(path: &Path, start: usize, end: usize) -> String {
+    let text = fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = text.lines().skip(start - 1).take(end + 1 - start).collect();
+    lines.join("\n")
+}
+
+/// Embeds `texts` with `embedding_url`, returning vectors of length 1 in the same order.
+fn embed(ctx: &RequestContext, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    let Some(url) = &ctx.config.embedding_url else {
+        return Err("no embedding_url is set".to_string());
+    };
+    let timeout = ctx.limit_timeout(EMBEDDING_TIMEOUT);
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global(Some(timeout))
+        .build()
+        .into();
+    let mut request = agent.post(url).header("Content-Type", "application/json");
+    for (name, value) in &ctx.config.embedding_headers {
+        request = request.header(name, value);
+    }
+    let body = json!({ "model": ctx.config.embedding_model, "input": texts });
+    let mut response = request
+        .send(body.to_string().as_bytes())
+        .map_err(|e| format!("cannot reach the embedding endpoint: {}", e))?;
+    let status = response.status();
+    let text = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| format!("cannot read the answer of the embedding endpoint: {}", e))?;
+    if !status.is_success() {
+        return Err(format!(
+            "the embedding endpoint answered with status {}: {}",
+            status,
+            text.chars().take(500).collect::<String>()
+        ));
+    }
+    #[derive(Deserialize)]
+    struct Embeddings {
+        data: Vec<Embedding>,
+    }
+    #[derive(Deserialize)]
+    struct Embedding {
+        #[serde(default)]
+        index: usize,
+        embedding: Vec<f32>,
+    }
+    let mut embeddings: Embeddings = serde_json::from_str(&text)
+        .map_err(|e| format!("invalid answer from the embedding endpoint: {}", e))?;
+    if embeddings.data.len() != texts.len() {
+        return Err(format!(
+            "the embedding endpoint returned {} embeddings for {} texts",
+            embeddings.data.len(),
+            texts.len()
+        ));
+    }
+    embeddings.data.sort_by_key(|embedding| embedding.index);
+    Ok(embeddings
+        .data
+        .into_iter()
+        .map(|embedding| normalize(embedding.embedding))
+        .collect())
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+/// Cosine similarity of two vectors of length 1, 0 when their dimensions differ.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// The index saved in `semantic_index`, or an empty one.
+fn load(config: &Config) -> Index {
+    let empty = || Index {
+        model: config.embedding_model.clone(),
+        ..Index::default()
+    };
+    let Some(path) = &config.semantic_index else {
+        return empty();
+    };
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            tracing::warn!(
+                "Ignoring the invalid semantic index {}: {}",
+                path.display(),
+                e
+            );
+            empty()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => empty(),
+        Err(e) => {
+            tracing::warn!("Cannot read the semantic index {}: {}", path.display(), e);
+            empty()
+        }
+    }
+}
+
+/// Replaces the file at once, so that a failure leaves it as it was.
+fn save(path: &Path, index: &Index) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    let text = serde_json::to_vec(index).unwrap_or_default();
+    fs::write(&temporary, text)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
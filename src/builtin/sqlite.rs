@@ -0,0 +1,230 @@
+//! Queries against the SQLite databases listed in `sqlite_databases`.
+
+use crate::{
+    config::Config,
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{CallToolResult, ReadResourceResult, ResourceContents},
+    resources::{Resource, ResourceProvider},
+    tools::{required_str, Tool},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rusqlite::{limits::Limit, params_from_iter, types, types::ValueRef, Connection, OpenFlags};
+use serde_json::{json, Map, Value};
+
+/// Maximum number of rows returned by `sqlite_query`.
+const MAX_ROWS: usize = 1000;
+
+/// `sqlite_query` tool running one SQL statement with positional parameters (`?1`, `?2`, ...).
+/// Only offered when databases are configured.
+pub fn query_tool() -> Tool {
+    Tool::new(
+        "sqlite_query",
+        "Run one SQL statement against a configured SQLite database. Queries return a JSON \
+         object with the column names and the rows; other statements return the number of \
+         changed rows. Modifying statements are refused while the server is in read-only mode.",
+        json!({
+            "type": "object",
+            "properties": {
+                "database": { "type": "string", "description": "Name of the database" },
+                "sql": { "type": "string", "description": "SQL statement, with ?1, ?2... placeholders" },
+                "params": { "type": "array", "description": "Values bound to the placeholders" }
+            },
+            "required": ["database", "sql"]
+        }),
+        |ctx, arguments| {
+            let database = required_str(arguments, "database")?;
+            let sql = required_str(arguments, "sql")?;
+            let params = arguments
+                .get("params")
+                .and_then(Value::as_array)
+                .map(|params| params.iter().map(to_sql).collect())
+                .unwrap_or_default();
+            match query(&ctx.config, database, sql, params) {
+                Ok(result) => Ok(CallToolResult::text(result.to_string())),
+                Err(e) => Ok(CallToolResult::error(e)),
+            }
+        },
+    )
+    .available_when(|config| !config.sqlite_databases.is_empty())
+    .guards_read_only()
+}
+
+/// True when statements may not modify the databases.
+fn read_only(config: &Config) -> bool {
+    config.sqlite_read_only || config.read_only
+}
+
+/// Opens a configured database, read-only when `sqlite_read_only` or `read_only` is set.
+fn open(config: &Config, database: &str) -> Result<Connection, String> {
+    let Some(path) = config.sqlite_databases.get(database) else {
+        return Err(format!("unknown database '{}'", database));
+    };
+    let flags = if read_only(config) {
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX
+    } else {
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX
+    };
+    let connection = Connection::open_with_flags(path, flags)
+        .map_err(|e| format!("cannot open database '{}': {}", database, e))?;
+    // ATTACH would reach any other database file, which only the configured ones may be
+    connection
+        .set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0)
+        .map_err(|e| format!("cannot open database '{}': {}", database, e))?;
+    Ok(connection)
+}
+
+fn query(
+    config: &Config,
+    database: &str,
+    sql: &str,
+    params: Vec<types::Value>,
+) -> Result<Value, String> {
+    let connection = open(config, database)?;
+    let mut statement = connection.prepare(sql).map_err(|e| e.to_string())?;
+    if read_only(config) && !statement.readonly() {
+        return Err("the server is in read-only mode".to_string());
+    }
+    tracing::info!("Running SQL on {}: {}", database, sql);
+
+    if statement.column_count() == 0 {
+        let changes = statement
+            .execute(params_from_iter(params))
+            .map_err(|e| e.to_string())?;
+        return Ok(json!({ "changes": changes }));
+    }
+
+    let columns: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let mut rows = statement
+        .query(params_from_iter(params))
+        .map_err(|e| e.to_string())?;
+    let mut result = Vec::new();
+    let mut truncated = false;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        if result.len() == MAX_ROWS {
+            truncated = true;
+            break;
+        }
+        let values = (0..columns.len())
+            .map(|i| row.get_ref(i).map(from_sql).unwrap_or(Value::Null))
+            .collect();
+        result.push(Value::Array(values));
+    }
+    Ok(json!({ "columns": columns, "rows": result, "truncated": truncated }))
+}
+
+fn to_sql(value: &Value) -> types::Value {
+    match value {
+        Value::Null => types::Value::Null,
+        Value::Bool(b) => types::Value::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => types::Value::Integer(i),
+            None => types::Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => types::Value::Text(s.clone()),
+        other => types::Value::Text(other.to_string()),
+    }
+}
+
+/// Converts a column value to JSON, base64-encoding blobs.
+fn from_sql(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => json!(i),
+        ValueRef::Real(f) => json!(f),
+        ValueRef::Text(t) => Value::String(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => Value::String(BASE64.encode(b)),
+    }
+}
+
+/// Exposes the schema of each table of the configured databases as a
+/// `sqlite://<database>/<table>` resource.
+pub struct SchemaResources;
+
+impl SchemaResources {
+    fn tables(connection: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+        let mut statement = connection.prepare(
+            "SELECT name, sql FROM sqlite_schema \
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let tables = statement.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            ))
+        })?;
+        tables.collect()
+    }
+
+    fn schema(connection: &Connection, table: &str) -> Result<Value, String> {
+        let sql = SchemaResources::tables(connection)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|(name, _)| name == table)
+            .map(|(_, sql)| sql)
+            .ok_or_else(|| format!("unknown table '{}'", table))?;
+        let mut statement = connection
+            .prepare("SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1)")
+            .map_err(|e| e.to_string())?;
+        let columns = statement
+            .query_map([table], |row| {
+                let mut column = Map::new();
+                column.insert("name".to_string(), from_sql(row.get_ref(0)?));
+                column.insert("type".to_string(), from_sql(row.get_ref(1)?));
+                column.insert("not_null".to_string(), json!(row.get::<_, i64>(2)? != 0));
+                column.insert("default".to_string(), from_sql(row.get_ref(3)?));
+                column.insert("primary_key".to_string(), json!(row.get::<_, i64>(4)? != 0));
+                Ok(Value::Object(column))
+            })
+            .and_then(|columns| columns.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| e.to_string())?;
+        Ok(json!({ "table": table, "sql": sql, "columns": columns }))
+    }
+}
+
+impl ResourceProvider for SchemaResources {
+    fn list(&self, ctx: &RequestContext) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        for database in ctx.config.sqlite_databases.keys() {
+            let tables = open(&ctx.config, database)
+                .and_then(|connection| {
+                    SchemaResources::tables(&connection).map_err(|e| e.to_string())
+                })
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Cannot list the tables of {}: {}", database, e);
+                    Vec::new()
+                });
+            for (table, _) in tables {
+                resources.push(Resource {
+                    uri: format!("sqlite://{}/{}", database, table),
+                    name: format!("{}.{}", database, table),
+                    description: Some(format!("Schema of table {}", table)),
+                    mime_type: Some("application/json".to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+        resources
+    }
+
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let (database, table) = uri.strip_prefix("sqlite://")?.split_once('/')?;
+        let result = open(&ctx.config, database)
+            .and_then(|connection| SchemaResources::schema(&connection, table))
+            .map(|schema| {
+                let contents = ResourceContents::text(uri, schema.to_string())
+                    .with_mime_type("application/json");
+                ReadResourceResult::new(vec![contents])
+            })
+            .map_err(|e| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e));
+        Some(result)
+    }
+}
@@ -0,0 +1,188 @@
+//! Information about the machine the server runs on.
+
+use crate::{
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::CallToolResult,
+    tools::Tool,
+};
+use serde_json::{json, Value};
+use std::{cmp::Ordering, env, thread};
+use sysinfo::{
+    Disks, Pid, Process, ProcessRefreshKind, ProcessesToUpdate, System, MINIMUM_CPU_UPDATE_INTERVAL,
+};
+
+/// Default number of processes returned by `list_processes`.
+const DEFAULT_PROCESS_LIMIT: u64 = 50;
+
+/// `system_info` tool returning the OS, architecture, hostname, CPU count, memory and disk
+/// usage of the machine.
+pub fn info_tool() -> Tool {
+    Tool::new(
+        "system_info",
+        "Return information about the machine running the server as JSON: OS, architecture, \
+         hostname, CPU count, memory and disk usage (sizes in bytes).",
+        json!({ "type": "object", "properties": {} }),
+        |_ctx, _arguments| Ok(CallToolResult::text(system_info().to_string())),
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+fn system_info() -> Value {
+    let mut system = System::new();
+    system.refresh_memory();
+    let disks: Vec<Value> = Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|disk| {
+            json!({
+                "name": disk.name().to_string_lossy(),
+                "mount_point": disk.mount_point(),
+                "file_system": disk.file_system().to_string_lossy(),
+                "total": disk.total_space(),
+                "available": disk.available_space(),
+            })
+        })
+        .collect();
+    json!({
+        "os": {
+            "family": env::consts::OS,
+            "name": System::name(),
+            "version": System::long_os_version(),
+            "kernel": System::kernel_version(),
+        },
+        "arch": env::consts::ARCH,
+        "hostname": System::host_name(),
+        "uptime_secs": System::uptime(),
+        "cpus": {
+            "logical": thread::available_parallelism().map(|n| n.get()).ok(),
+            "physical": System::physical_core_count(),
+        },
+        "memory": {
+            "total": system.total_memory(),
+            "available": system.available_memory(),
+            "used": system.used_memory(),
+            "swap_total": system.total_swap(),
+            "swap_used": system.used_swap(),
+        },
+        "disks": disks,
+    })
+}
+
+/// `list_processes` tool returning the running processes with their CPU and memory usage.
+/// Only offered when `allow_process_info` is set.
+pub fn list_processes_tool() -> Tool {
+    Tool::new(
+        "list_processes",
+        "List the running processes with their pid, name, CPU usage (percent of one core) and \
+         memory (bytes), sorted by CPU usage by default.",
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "Only list processes whose name contains this text" },
+                "sort_by": { "type": "string", "enum": ["cpu", "memory", "pid", "name"], "description": "Sort order, cpu by default" },
+                "limit": { "type": "integer", "minimum": 1, "description": "Maximum number of processes, 50 by default" }
+            }
+        }),
+        |_ctx, arguments| {
+            let filter = arguments.get("name").and_then(Value::as_str);
+            let sort_by = arguments.get("sort_by").and_then(Value::as_str).unwrap_or("cpu");
+            let limit = arguments
+                .get("limit")
+                .and_then(Value::as_u64)
+                .unwrap_or(DEFAULT_PROCESS_LIMIT) as usize;
+
+            let refresh = ProcessRefreshKind::nothing().with_cpu().with_memory();
+            let system = processes(ProcessesToUpdate::All, refresh);
+            let mut list: Vec<&Process> = system
+                .processes()
+                .values()
+                // Threads are listed as tasks on Linux
+                .filter(|p| p.thread_kind().is_none())
+                .filter(|p| filter.is_none_or(|f| p.name().to_string_lossy().contains(f)))
+                .collect();
+            list.sort_by(|a, b| match sort_by {
+                "memory" => b.memory().cmp(&a.memory()),
+                "pid" => a.pid().cmp(&b.pid()),
+                "name" => a.name().cmp(b.name()),
+                _ => b.cpu_usage().partial_cmp(&a.cpu_usage()).unwrap_or(Ordering::Equal),
+            });
+            let list: Vec<Value> = list
+                .into_iter()
+                .take(limit)
+                .map(|p| {
+                    json!({
+                        "pid": p.pid().as_u32(),
+                        "name": p.name().to_string_lossy(),
+                        "cpu": p.cpu_usage(),
+                        "memory": p.memory(),
+                    })
+                })
+                .collect();
+            Ok(CallToolResult::text(Value::Array(list).to_string()))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_process_info)
+}
+
+/// `process_info` tool returning the details of one process.
+/// Only offered when `allow_process_info` is set.
+pub fn process_info_tool() -> Tool {
+    Tool::new(
+        "process_info",
+        "Return the details of a process: name, command line, executable, working directory, \
+         parent, status, start time, CPU and memory usage.",
+        json!({
+            "type": "object",
+            "properties": {
+                "pid": { "type": "integer", "minimum": 0, "description": "Process id" }
+            },
+            "required": ["pid"]
+        }),
+        |_ctx, arguments| {
+            let Some(pid) = arguments.get("pid").and_then(Value::as_u64) else {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    "Missing integer argument 'pid'",
+                ));
+            };
+            let pid = Pid::from_u32(pid as u32);
+            let system = processes(
+                ProcessesToUpdate::Some(&[pid]),
+                ProcessRefreshKind::everything(),
+            );
+            let Some(p) = system.process(pid) else {
+                return Ok(CallToolResult::error(format!(
+                    "no process with pid {}",
+                    pid
+                )));
+            };
+            let info = json!({
+                "pid": p.pid().as_u32(),
+                "name": p.name().to_string_lossy(),
+                "cmd": p.cmd().iter().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>(),
+                "exe": p.exe(),
+                "cwd": p.cwd(),
+                "parent": p.parent().map(Pid::as_u32),
+                "status": p.status().to_string(),
+                "start_time": p.start_time(),
+                "run_time_secs": p.run_time(),
+                "cpu": p.cpu_usage(),
+                "memory": p.memory(),
+                "virtual_memory": p.virtual_memory(),
+            });
+            Ok(CallToolResult::text(info.to_string()))
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+    .available_when(|config| config.allow_process_info)
+}
+
+/// Refreshes the processes twice, since CPU usage is measured between two refreshes.
+fn processes(which: ProcessesToUpdate, refresh: ProcessRefreshKind) -> System {
+    let mut system = System::new();
+    system.refresh_processes_specifics(which, true, refresh);
+    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_processes_specifics(which, true, refresh);
+    system
+}
@@ -0,0 +1,75 @@
+//! Current time and conversions between time zones.
+
+use crate::{mcp::CallToolResult, tools::Tool};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::{json, Value};
+
+/// Formats accepted for a `time` without an offset.
+const LOCAL_FORMATS: [&str; 3] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"];
+
+/// `get_time` tool returning the current time, or a given time, in an IANA time zone.
+pub fn time_tool() -> Tool {
+    Tool::new(
+        "get_time",
+        "Return the current time in an IANA time zone (UTC by default). With `time`, convert \
+         that time instead; a time without an offset is read in `from_timezone`.",
+        json!({
+            "type": "object",
+            "properties": {
+                "timezone": { "type": "string", "description": "Target IANA time zone, e.g. Europe/Paris" },
+                "time": { "type": "string", "description": "RFC 3339 time, or YYYY-MM-DD HH:MM[:SS] to convert" },
+                "from_timezone": { "type": "string", "description": "IANA time zone of `time` when it has no offset, UTC by default" }
+            }
+        }),
+        |_ctx, arguments| match get_time(arguments) {
+            Ok(time) => Ok(CallToolResult::text(time.to_string())),
+            Err(e) => Ok(CallToolResult::error(e)),
+        },
+    )
+    .with_annotations(json!({ "readOnlyHint": true }))
+}
+
+fn timezone(arguments: &Value, name: &str) -> Result<Tz, String> {
+    match arguments.get(name).and_then(Value::as_str) {
+        Some(tz) => tz
+            .parse()
+            .map_err(|_| format!("unknown time zone '{}'", tz)),
+        None => Ok(Tz::UTC),
+    }
+}
+
+/// Parses `time` as RFC 3339, or as a local time in `from`.
+fn parse_time(time: &str, from: Tz) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let local = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(time, format).ok())
+        .ok_or_else(|| format!("invalid time '{}'", time))?;
+    from.from_local_datetime(&local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} does not exist in {}", time, from))
+}
+
+fn get_time(arguments: &Value) -> Result<Value, String> {
+    let target = timezone(arguments, "timezone")?;
+    let from = timezone(arguments, "from_timezone")?;
+    let utc = match arguments.get("time").and_then(Value::as_str) {
+        Some(time) => parse_time(time, from)?,
+        None => Utc::now(),
+    };
+    let time = utc.with_timezone(&target);
+    Ok(json!({
+        "timezone": target.name(),
+        "datetime": time.to_rfc3339_opts(SecondsFormat::Secs, false),
+        "date": time.format("%Y-%m-%d").to_string(),
+        "time": time.format("%H:%M:%S").to_string(),
+        "weekday": time.format("%A").to_string(),
+        "utc_offset": time.format("%:z").to_string(),
+        "abbreviation": time.format("%Z").to_string(),
+        "unix": utc.timestamp(),
+    }))
+}
@@ -0,0 +1,190 @@
+//! Watches the files backing the `file://` and `static://` resources and notifies the
+//! subscribed clients when they change, and every client when files are added or removed.
+//! Saving a file fires several events, so the notifications are held for
+//! `resource_debounce_ms` after the first event, the next ones joining them.
+
+use crate::{
+    builtin::{assets, fs::dir_uri},
+    config::Config,
+    roots,
+    server::Server,
+};
+use notify::{event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Watches the roots and `resource_dir` recursively, sending `notifications/resources/updated`
+/// for the changed files and the directories containing them, and
+/// `notifications/resources/list_changed` when files are created, removed or renamed.
+pub struct ResourceWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    /// Directories currently watched, canonicalized
+    watched: Mutex<BTreeSet<PathBuf>>,
+    /// Canonical `resource_dir`, used to map paths to `static://` URIs
+    resource_dir: Arc<RwLock<Option<PathBuf>>>,
+}
+
+impl ResourceWatcher {
+    /// Starts watching the directories of the server's configuration.
+    pub fn new(server: Arc<Server>) -> notify::Result<ResourceWatcher> {
+        let resource_dir: Arc<RwLock<Option<PathBuf>>> = Arc::default();
+        let pending: Arc<Pending> = Arc::default();
+        {
+            let pending = pending.clone();
+            let server = server.clone();
+            thread::spawn(move || pending.run(&server));
+        }
+        let watcher = {
+            let resource_dir = resource_dir.clone();
+            let server = server.clone();
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) => {
+                    if matches!(event.kind, EventKind::Access(_)) {
+                        return;
+                    }
+                    let window = Duration::from_millis(server.config().resource_debounce_ms);
+                    // Files appearing or going away change what resources/list returns
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_)
+                            | EventKind::Remove(_)
+                            | EventKind::Modify(ModifyKind::Name(_))
+                    ) {
+                        pending.add(&server, Change::List, window);
+                    }
+                    let resource_dir = resource_dir.read().unwrap();
+                    for path in &event.paths {
+                        for uri in resource_uris(path, resource_dir.as_deref()) {
+                            pending.add(&server, Change::Resource(uri), window);
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("Error watching resources: {}", e),
+            })?
+        };
+        let watcher = ResourceWatcher {
+            watcher: Mutex::new(watcher),
+            watched: Mutex::default(),
+            resource_dir,
+        };
+        watcher.update(&server.config());
+        Ok(watcher)
+    }
+
+    /// Watches the directories of a new configuration, after it was reloaded.
+    pub fn update(&self, config: &Config) {
+        let resource_dir = config
+            .resource_dir
+            .as_ref()
+            .and_then(|dir| fs::canonicalize(dir).ok());
+        let wanted: BTreeSet<PathBuf> = config
+            .roots
+            .iter()
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .chain(resource_dir.clone())
+            .collect();
+        *self.resource_dir.write().unwrap() = resource_dir;
+
+        let mut watcher = self.watcher.lock().unwrap();
+        let mut watched = self.watched.lock().unwrap();
+        for dir in watched.difference(&wanted) {
+            if let Err(e) = watcher.unwatch(dir) {
+                tracing::warn!("Cannot stop watching {}: {}", dir.display(), e);
+            }
+        }
+        for dir in wanted.difference(&watched) {
+            match watcher.watch(dir, RecursiveMode::Recursive) {
+                Ok(()) => tracing::info!("Watching {} for resource changes", dir.display()),
+                Err(e) => tracing::warn!("Cannot watch {}: {}", dir.display(), e),
+            }
+        }
+        *watched = wanted;
+    }
+}
+
+/// What a notification is about.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Change {
+    /// The list of resources, `notifications/resources/list_changed`
+    List,
+    /// A resource, `notifications/resources/updated`
+    Resource(String),
+}
+
+impl Change {
+    fn notify(&self, server: &Server) {
+        match self {
+            Change::List => server.notify_all("notifications/resources/list_changed", None),
+            Change::Resource(uri) => server.notify_resource_updated(uri),
+        }
+    }
+}
+
+/// Notifications held until the end of their window.
+#[derive(Default)]
+struct Pending {
+    /// When each change is to be notified
+    due: Mutex<BTreeMap<Change, Instant>>,
+    added: Condvar,
+}
+
+impl Pending {
+    /// Notifies `change` once `window` elapsed, along with the same changes meanwhile, or
+    /// right away for a zero window.
+    fn add(&self, server: &Server, change: Change, window: Duration) {
+        if window.is_zero() {
+            change.notify(server);
+            return;
+        }
+        let mut due = self.due.lock().unwrap();
+        due.entry(change).or_insert_with(|| Instant::now() + window);
+        self.added.notify_one();
+    }
+
+    /// Sends the notifications as they become due, forever.
+    fn run(&self, server: &Server) {
+        let mut due = self.due.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let ready: Vec<Change> = due
+                .iter()
+                .filter(|(_, at)| **at <= now)
+                .map(|(change, _)| change.clone())
+                .collect();
+            if !ready.is_empty() {
+                for change in &ready {
+                    due.remove(change);
+                }
+                drop(due);
+                for change in ready {
+                    change.notify(server);
+                }
+                due = self.due.lock().unwrap();
+                continue;
+            }
+            due = match due.values().min().copied() {
+                Some(next) => self.added.wait_timeout(due, next - now).unwrap().0,
+                None => self.added.wait(due).unwrap(),
+            };
+        }
+    }
+}
+
+/// URIs of the resources affected by a change of `path`: the file itself, its directory
+/// listing and, under `resource_dir`, its `static://` resource.
+fn resource_uris(path: &Path, resource_dir: Option<&Path>) -> Vec<String> {
+    let mut uris = vec![roots::path_to_uri(path)];
+    if let Some(parent) = path.parent() {
+        uris.push(dir_uri(parent));
+    }
+    if let Some(relative) = resource_dir.and_then(|dir| path.strip_prefix(dir).ok()) {
+        uris.push(assets::static_uri(relative));
+    }
+    uris
+}
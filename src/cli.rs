@@ -0,0 +1,379 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use mcp_rs_test::config::{Config, LogTarget, OpenApiSpec, Transport};
+use std::{collections::BTreeMap, path::PathBuf};
+use tracing_subscriber::filter::LevelFilter;
+
+/// MCP server implementation in Rust.
+///
+/// Every option can also be set through the environment variable shown next to it,
+/// which is how MCP hosts usually pass settings to stdio servers.
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Cli {
+    /// Transport to serve on: stdio, http or ws
+    #[arg(long, env = "MCP_TRANSPORT")]
+    pub transport: Option<Transport>,
+    /// Address the http and ws transports listen on
+    #[arg(long, env = "MCP_LISTEN")]
+    pub listen: Option<String>,
+    /// Where the log is written: stderr, file or both [default: file]
+    #[arg(long, env = "MCP_LOG_TARGET")]
+    pub log_target: Option<LogTarget>,
+    /// File the log is written to [default: mcp_rs_test.log in the platform cache directory]
+    #[arg(long, env = "MCP_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+    /// Minimum level of the logged messages (off, error, warn, info, debug, trace)
+    #[arg(long, env = "MCP_LOG_LEVEL")]
+    pub log_level: Option<LevelFilter>,
+    /// Size in bytes after which the log file is rotated, 0 to never rotate [default: 10 MiB]
+    #[arg(long, env = "MCP_LOG_MAX_SIZE")]
+    pub log_max_size: Option<u64>,
+    /// Number of rotated log files to keep [default: 5]
+    #[arg(long, env = "MCP_LOG_MAX_FILES")]
+    pub log_max_files: Option<usize>,
+    /// Directory the filesystem tools and resources may access (repeatable)
+    #[arg(long = "root", env = "MCP_ROOTS", value_delimiter = ',')]
+    pub roots: Vec<PathBuf>,
+    /// Offer the tools that modify files (fs_write, fs_edit)
+    #[arg(long, env = "MCP_ALLOW_WRITES")]
+    pub allow_writes: bool,
+    /// Offer the run_command tool
+    #[arg(long, env = "MCP_ALLOW_COMMANDS")]
+    pub allow_commands: bool,
+    /// Have the tools that modify files or run commands describe what they would do instead
+    #[arg(long, env = "MCP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Only offer the tools that don't modify anything, and run database statements read-only
+    #[arg(long, env = "MCP_READ_ONLY")]
+    pub read_only: bool,
+    /// OpenAPI 3 document (JSON or YAML) whose operations are exposed as tools (repeatable)
+    #[arg(long, env = "MCP_OPENAPI", value_delimiter = ',')]
+    pub openapi: Vec<PathBuf>,
+    /// JSON configuration file
+    #[arg(long, env = "MCP_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// File every message received and sent is recorded to, for the replay subcommand
+    #[arg(long, env = "MCP_RECORD")]
+    pub record: Option<PathBuf>,
+    /// Check every result and notification sent against the MCP schema, logging the violations
+    #[arg(long, env = "MCP_VALIDATE_OUTGOING")]
+    pub validate_outgoing: bool,
+    /// Answer `metrics/dump` and serve Prometheus metrics on `/metrics` with the HTTP transport
+    #[arg(long, env = "MCP_METRICS")]
+    pub metrics: bool,
+    /// Serve Prometheus metrics on `/metrics` at this address, whatever the transport
+    #[arg(long, env = "MCP_METRICS_LISTEN")]
+    pub metrics_listen: Option<String>,
+    /// Export request spans and metrics to this OTLP/HTTP collector endpoint, when built with
+    /// `otel`
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otel_endpoint: Option<String>,
+    /// Directory the panics of the handlers are written to as crash reports
+    #[arg(long, env = "MCP_CRASH_REPORT_DIR")]
+    pub crash_report_dir: Option<PathBuf>,
+    /// DSN of the Sentry project the panics of the handlers are reported to
+    #[arg(long, env = "SENTRY_DSN")]
+    pub crash_report_dsn: Option<String>,
+    /// Largest message accepted from a client, in bytes [default: 16 MiB]
+    #[arg(long, env = "MCP_MAX_MESSAGE_SIZE")]
+    pub max_message_size: Option<usize>,
+    /// Log every message received and sent (target `wire`), with secrets and long strings redacted
+    #[arg(long, env = "MCP_WIRE_LOG")]
+    pub wire_log: bool,
+    /// Check that the configured server answers initialize and ping, then exit with 0 or 1
+    #[arg(long)]
+    pub health_check: bool,
+    /// Run as a Windows service, started and stopped by the service control manager
+    #[arg(long)]
+    pub service: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Tools run instead of the server.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Send one request to another MCP server and print its result
+    Client(ClientArgs),
+    /// Print the capabilities, tools, resources and prompts of another MCP server
+    Inspect(InspectArgs),
+    /// Feed the client messages of a transcript recorded with --record to this server
+    Replay(ReplayArgs),
+    /// Check a transcript recorded with --record against the rules of JSON-RPC and MCP
+    ValidateTranscript(ValidateTranscriptArgs),
+    /// Type shorthand commands (list tools, call <tool> {...}, ...) against this server
+    Repl(ReplArgs),
+    /// Connect to this server in-process and report what a client would see of it
+    Doctor(DoctorArgs),
+    /// Send many requests to this server or another one and report throughput and latencies
+    Bench(BenchArgs),
+    /// Check a configuration file or print its JSON schema
+    Config(ConfigArgs),
+    /// Export the tools, prompts and resource templates this server registers
+    Schema(SchemaArgs),
+    /// Add this server to the Claude Desktop configuration, with the usual flags
+    Install(InstallArgs),
+    /// Print the configuration of an MCP client reaching this server with the usual flags
+    ExportConfig(ExportConfigArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ClientArgs {
+    /// Method of the request, e.g. tools/list or tools/call
+    pub method: String,
+    /// Params of the request, as JSON
+    #[arg(long)]
+    pub params: Option<String>,
+    #[command(flatten)]
+    pub server: ServerArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InspectArgs {
+    /// Print the summary as JSON
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub server: ServerArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReplayArgs {
+    /// Transcript written by --record
+    pub file: PathBuf,
+    /// Only replay this session of the transcript
+    #[arg(long)]
+    pub session: Option<u64>,
+    /// Compare the responses of the server with the recorded ones instead of printing them
+    #[arg(long)]
+    pub diff: bool,
+    /// JSON pointer of a part of the responses left out of the comparison, e.g.
+    /// /result/serverInfo/version
+    #[arg(long, value_name = "POINTER")]
+    pub ignore: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ValidateTranscriptArgs {
+    /// Transcript written by --record
+    pub file: PathBuf,
+    /// Only check this session of the transcript
+    #[arg(long)]
+    pub session: Option<u64>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReplArgs {
+    /// Seconds after which a request is given up
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DoctorArgs {
+    /// Also call the read-only tools with the sample arguments
+    #[arg(long)]
+    pub call: bool,
+    /// Protocol version to ask for, the latest supported by default
+    #[arg(long)]
+    pub protocol_version: Option<String>,
+    /// Seconds after which a request is given up
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// Number of requests sent
+    #[arg(long, default_value_t = 1000)]
+    pub requests: usize,
+    /// Requests waiting for their response at the same time
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+    /// Request sent, as `[WEIGHT*]METHOD [PARAMS]`, e.g. `3*tools/list` (repeatable); ping by
+    /// default
+    #[arg(long = "mix", value_name = "SPEC")]
+    pub mix: Vec<String>,
+    /// Header sent to an HTTP server, as `Name: value` (repeatable)
+    #[arg(long = "header")]
+    pub headers: Vec<String>,
+    /// Seconds after which a request fails
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+    /// URL of a Streamable HTTP server, or command line of a stdio server; this server
+    /// in-process by default
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub server: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Check a configuration file against the schema, reporting each problem with its line
+    Validate {
+        /// File to check, the one of --config by default
+        file: Option<PathBuf>,
+    },
+    /// Print the JSON schema of the configuration file
+    Schema,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SchemaArgs {
+    #[command(subcommand)]
+    pub command: SchemaCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SchemaCommand {
+    /// Write the tools, prompts and resource templates, with their schemas, as a JSON manifest
+    Export {
+        /// File to write, standard output by default
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InstallArgs {
+    /// Name of the server in the Claude Desktop configuration
+    #[arg(long, default_value = "mcp_rs_test")]
+    pub name: String,
+    /// Environment variable set for the server, as `NAME=value` (repeatable)
+    #[arg(long = "env")]
+    pub env: Vec<String>,
+    /// File to patch instead of the Claude Desktop configuration of the current user
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+    /// Print the entry instead of writing it
+    #[arg(long)]
+    pub print: bool,
+    /// Other arguments the server is started with, after `--`
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportConfigArgs {
+    /// Client the configuration is for
+    #[arg(long, value_enum, default_value_t = ClientFormat::Json)]
+    pub format: ClientFormat,
+    /// Name of the server in the client configuration
+    #[arg(long, default_value = "mcp_rs_test")]
+    pub name: String,
+    /// Environment variable set for a stdio server, as `NAME=value` (repeatable)
+    #[arg(long = "env")]
+    pub env: Vec<String>,
+    /// Other arguments a stdio server is started with, after `--`
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+/// Layout of an MCP client configuration.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientFormat {
+    /// `.vscode/mcp.json`, under `servers`
+    Vscode,
+    /// `~/.cursor/mcp.json` or `.cursor/mcp.json`, under `mcpServers`
+    Cursor,
+    /// The `mcpServers` object most clients read
+    Json,
+}
+
+/// How to reach another MCP server.
+#[derive(Args, Debug, Clone)]
+pub struct ServerArgs {
+    /// Header sent to an HTTP server, as `Name: value` (repeatable)
+    #[arg(long = "header")]
+    pub headers: Vec<String>,
+    /// Seconds after which a request fails
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+    /// URL of a Streamable HTTP server, or command line of a stdio server
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub server: Vec<String>,
+}
+
+impl Cli {
+    /// Builds the effective configuration: defaults, then the config file, then the environment
+    /// variables and flags.
+    pub fn config(&self) -> Result<Config, String> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        if let Some(transport) = self.transport {
+            config.transport = transport;
+        }
+        if let Some(listen) = &self.listen {
+            config.listen = listen.clone();
+        }
+        if !self.roots.is_empty() {
+            config.roots = self.roots.clone();
+        }
+        if self.allow_writes {
+            config.allow_writes = true;
+        }
+        if self.allow_commands {
+            config.allow_commands = true;
+        }
+        if self.dry_run {
+            config.dry_run = true;
+        }
+        if self.read_only {
+            config.read_only = true;
+        }
+        if self.validate_outgoing {
+            config.validate_outgoing = true;
+        }
+        if self.metrics {
+            config.metrics = true;
+        }
+        if self.wire_log {
+            config.wire_log = true;
+        }
+        if let Some(max_message_size) = self.max_message_size {
+            config.max_message_size = max_message_size;
+        }
+        if let Some(metrics_listen) = &self.metrics_listen {
+            config.metrics_listen = Some(metrics_listen.clone());
+        }
+        if let Some(otel_endpoint) = &self.otel_endpoint {
+            config.otel_endpoint = Some(otel_endpoint.clone());
+        }
+        if let Some(crash_report_dir) = &self.crash_report_dir {
+            config.crash_report_dir = Some(crash_report_dir.clone());
+        }
+        if let Some(crash_report_dsn) = &self.crash_report_dsn {
+            config.crash_report_dsn = Some(crash_report_dsn.clone());
+        }
+        config
+            .openapi
+            .extend(self.openapi.iter().map(|path| OpenApiSpec {
+                path: path.clone(),
+                base_url: None,
+                headers: BTreeMap::new(),
+            }));
+        if let Some(log_target) = self.log_target {
+            config.log_target = log_target;
+        }
+        if let Some(log_file) = &self.log_file {
+            config.log_file = log_file.clone();
+        }
+        if let Some(log_level) = self.log_level {
+            config.log_level = log_level.to_string();
+        }
+        if let Some(log_max_size) = self.log_max_size {
+            config.log_max_size = log_max_size;
+        }
+        if let Some(log_max_files) = self.log_max_files {
+            config.log_max_files = log_max_files;
+        }
+        Ok(config)
+    }
+}
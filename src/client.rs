@@ -0,0 +1,440 @@
+//! Client side of the protocol, used to talk to downstream MCP servers over stdio or
+//! Streamable HTTP.
+
+use crate::{
+    codec::{self, BoundedLines, Limits},
+    config::DownstreamServer,
+    context::CancellationToken,
+    errors::TIMEOUT,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INTERNAL_ERROR, ERROR_CODE_METHOD_NOT_FOUND},
+    mcp::Implementation,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufReader, Read, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Protocol version requested during the handshake.
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+/// Interval at which a pending request is checked for timeout and cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Capacity the buffer messages are serialized into keeps after a larger message.
+const RETAINED_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Function called with the method and params of the notifications sent by the server.
+pub type NotificationHandler = Box<dyn Fn(&str, Option<&Value>) + Send + Sync>;
+
+/// What the reader hands to a pending request.
+enum Reply {
+    /// `notifications/progress` sent for the request
+    Progress(Value),
+    Done(Result<Value, JsonRpcError>),
+}
+
+/// A way to send messages to the server. Its replies are handed to [`Shared::receive`].
+trait Transport: Send + Sync {
+    fn send(&self, message: &Value) -> Result<(), String>;
+}
+
+/// State shared between the client and the threads reading the server's messages.
+#[derive(Default)]
+struct Shared {
+    /// Requests waiting for their response, by id
+    pending: Mutex<HashMap<u64, Sender<Reply>>>,
+    on_notification: RwLock<Option<NotificationHandler>>,
+}
+
+impl Shared {
+    /// Routes a message from the server: responses to their pending request, notifications
+    /// to the handler. Requests from the server are answered through `transport`.
+    fn receive(&self, message: Value, transport: &dyn Transport) {
+        let id = message.get("id").cloned();
+        match (message.get("method").and_then(Value::as_str), id) {
+            (Some("notifications/progress"), None) => {
+                let params = message.get("params").cloned().unwrap_or_default();
+                let token = params["progressToken"].as_u64();
+                if let Some(sender) =
+                    token.and_then(|t| self.pending.lock().unwrap().get(&t).cloned())
+                {
+                    let _ = sender.send(Reply::Progress(params));
+                }
+            }
+            (Some(method), None) => {
+                if let Some(handler) = &*self.on_notification.read().unwrap() {
+                    handler(method, message.get("params"));
+                }
+            }
+            (Some(method), Some(id)) => {
+                let response = match method {
+                    "ping" => json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
+                    _ => {
+                        let error = JsonRpcError::new(
+                            ERROR_CODE_METHOD_NOT_FOUND,
+                            format!("Method not found: '{}'", method),
+                        );
+                        json!({ "jsonrpc": "2.0", "id": id, "error": error })
+                    }
+                };
+                if let Err(e) = transport.send(&response) {
+                    tracing::warn!("Cannot answer {} request: {}", method, e);
+                }
+            }
+            (None, Some(id)) => {
+                let Some(sender) = id
+                    .as_u64()
+                    .and_then(|id| self.pending.lock().unwrap().remove(&id))
+                else {
+                    return;
+                };
+                let result = match message.get("error") {
+                    Some(error) => {
+                        Err(serde_json::from_value(error.clone()).unwrap_or_else(|_| {
+                            JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, error.to_string())
+                        }))
+                    }
+                    None => Ok(message.get("result").cloned().unwrap_or_default()),
+                };
+                let _ = sender.send(Reply::Done(result));
+            }
+            (None, None) => tracing::warn!("Ignoring invalid message: {}", message),
+        }
+    }
+
+    /// Fails every pending request, once the connection is gone.
+    fn close(&self, reason: &str) {
+        for (_, sender) in self.pending.lock().unwrap().drain() {
+            let _ = sender.send(Reply::Done(Err(JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                reason,
+            ))));
+        }
+    }
+}
+
+/// Connection to an MCP server.
+pub struct Client {
+    transport: Arc<dyn Transport>,
+    shared: Arc<Shared>,
+    next_id: AtomicU64,
+    /// Time after which a request fails
+    timeout: Duration,
+    /// `capabilities` returned by the server during `initialize`
+    capabilities: Value,
+    /// `serverInfo` returned by the server during `initialize`
+    server_info: Value,
+}
+
+impl Client {
+    /// Connects to a server and performs the `initialize` handshake.
+    pub fn connect(server: &DownstreamServer, timeout: Duration) -> Result<Client, String> {
+        let shared = Arc::new(Shared::default());
+        let transport: Arc<dyn Transport> = match (&server.command, &server.url) {
+            (Some(command), None) => StdioTransport::spawn(command, server, shared.clone())?,
+            (None, Some(url)) => Arc::new(HttpTransport {
+                inner: Arc::new(HttpInner {
+                    agent: ureq::Agent::config_builder()
+                        .http_status_as_error(false)
+                        .build()
+                        .into(),
+                    url: url.clone(),
+                    headers: server.headers.clone(),
+                    session_id: Mutex::new(None),
+                    shared: shared.clone(),
+                }),
+            }),
+            _ => return Err("expected either `command` or `url`".to_string()),
+        };
+        let mut client = Client {
+            transport,
+            shared,
+            next_id: AtomicU64::new(1),
+            timeout,
+            capabilities: Value::Null,
+            server_info: Value::Null,
+        };
+        let result = client
+            .request(
+                "initialize",
+                Some(json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": Implementation::default(),
+                })),
+                None,
+                |_| {},
+            )
+            .map_err(|e| format!("initialize failed: {}", e.message))?;
+        client.capabilities = result.get("capabilities").cloned().unwrap_or_default();
+        client.server_info = result.get("serverInfo").cloned().unwrap_or_default();
+        client.notify("notifications/initialized", None)?;
+        Ok(client)
+    }
+
+    /// `capabilities` the server returned during `initialize`.
+    pub fn capabilities(&self) -> &Value {
+        &self.capabilities
+    }
+
+    /// `serverInfo` (name and version) the server returned during `initialize`.
+    pub fn server_info(&self) -> &Value {
+        &self.server_info
+    }
+
+    /// Sets the function called with the notifications sent by the server.
+    pub fn on_notification(&self, handler: impl Fn(&str, Option<&Value>) + Send + Sync + 'static) {
+        *self.shared.on_notification.write().unwrap() = Some(Box::new(handler));
+    }
+
+    /// Sends a request and waits for its result. `on_progress` receives the params of the
+    /// progress notifications sent for it; the request is cancelled on the server when
+    /// `cancellation` is flipped.
+    pub fn request(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        cancellation: Option<&CancellationToken>,
+        on_progress: impl Fn(Value),
+    ) -> Result<Value, JsonRpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut params = params.unwrap_or_else(|| json!({}));
+        // The request id doubles as progress token
+        params["_meta"]["progressToken"] = json!(id);
+        let (sender, replies) = mpsc::channel();
+        self.shared.pending.lock().unwrap().insert(id, sender);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = self.transport.send(&message) {
+            self.shared.pending.lock().unwrap().remove(&id);
+            return Err(JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, e));
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let failure = loop {
+            match replies.recv_timeout(POLL_INTERVAL) {
+                Ok(Reply::Done(result)) => return result,
+                Ok(Reply::Progress(params)) => on_progress(params),
+                Err(RecvTimeoutError::Disconnected) => {
+                    break JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, "connection closed")
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if cancellation.is_some_and(CancellationToken::is_cancelled) {
+                        break JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, "request cancelled");
+                    }
+                    if Instant::now() >= deadline {
+                        break TIMEOUT.error(format!("request timed out after {:?}", self.timeout));
+                    }
+                }
+            }
+        };
+        self.shared.pending.lock().unwrap().remove(&id);
+        let _ = self.notify(
+            "notifications/cancelled",
+            Some(json!({ "requestId": id, "reason": failure.message })),
+        );
+        Err(failure)
+    }
+
+    /// Items of a paginated list request (`tools/list`, ...), following `nextCursor`.
+    pub fn list(&self, method: &str, field: &str) -> Result<Vec<Value>, JsonRpcError> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.as_ref().map(|cursor| json!({ "cursor": cursor }));
+            let page = self.request(method, params, None, |_| {})?;
+            if let Some(page_items) = page[field].as_array() {
+                items.extend(page_items.iter().cloned());
+            }
+            match page["nextCursor"].as_str() {
+                Some(next) if cursor.as_deref() != Some(next) => cursor = Some(next.to_string()),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    pub fn notify(&self, method: &str, params: Option<Value>) -> Result<(), String> {
+        let mut message = json!({ "jsonrpc": "2.0", "method": method });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        self.transport.send(&message)
+    }
+}
+
+/// Server running as a child process, exchanging newline-delimited JSON over its stdio.
+struct StdioTransport {
+    /// The server's stdin, with the buffer messages are serialized into, reused between
+    /// messages so that each one is written at once without a new allocation
+    stdin: Mutex<(ChildStdin, Vec<u8>)>,
+    child: Mutex<Child>,
+}
+
+impl StdioTransport {
+    fn spawn(
+        command: &str,
+        server: &DownstreamServer,
+        shared: Arc<Shared>,
+    ) -> Result<Arc<dyn Transport>, String> {
+        let mut child = Command::new(command)
+            .args(&server.args)
+            .envs(&server.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", command, e))?;
+        let stdin = child.stdin.take().ok_or("no stdin")?;
+        let stdout = child.stdout.take().ok_or("no stdout")?;
+        let transport = Arc::new(StdioTransport {
+            stdin: Mutex::new((stdin, Vec::new())),
+            child: Mutex::new(child),
+        });
+        // Weak so that dropping the client kills the server, which ends this thread
+        let reader = Arc::downgrade(&transport);
+        thread::spawn(move || {
+            let lines = BoundedLines::new(BufReader::new(stdout), Limits::default().max_size);
+            for line in lines {
+                let Some(transport) = reader.upgrade() else {
+                    break;
+                };
+                match codec::parse(&line) {
+                    Ok(message) => shared.receive(message, &*transport),
+                    Err(e) => tracing::warn!("Ignoring invalid message {}: {}", line, e),
+                }
+            }
+            shared.close("the server exited");
+        });
+        Ok(transport)
+    }
+}
+
+impl Transport for StdioTransport {
+    fn send(&self, message: &Value) -> Result<(), String> {
+        let mut guard = self.stdin.lock().unwrap();
+        let (stdin, buffer) = &mut *guard;
+        buffer.clear();
+        serde_json::to_writer(&mut *buffer, message).map_err(|e| e.to_string())?;
+        buffer.push(b'\n');
+        let result = stdin
+            .write_all(buffer)
+            .and_then(|()| stdin.flush())
+            .map_err(|e| format!("cannot write to the server: {}", e));
+        buffer.shrink_to(RETAINED_BUFFER_SIZE);
+        result
+    }
+}
+
+impl Drop for StdioTransport {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Server reached over Streamable HTTP: every message is POSTed, and the messages of the
+/// response (a JSON body or an event stream) are read on a separate thread.
+struct HttpTransport {
+    inner: Arc<HttpInner>,
+}
+
+struct HttpInner {
+    agent: ureq::Agent,
+    url: String,
+    headers: BTreeMap<String, String>,
+    /// `Mcp-Session-Id` assigned by the server during `initialize`
+    session_id: Mutex<Option<String>>,
+    shared: Arc<Shared>,
+}
+
+impl HttpInner {
+    fn post(self: &Arc<HttpInner>, message: &Value) -> Result<(), String> {
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json, text/event-stream");
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some(session_id) = &*self.session_id.lock().unwrap() {
+            request = request.header("Mcp-Session-Id", session_id);
+        }
+        let response = request
+            .send(message.to_string().as_bytes())
+            .map_err(|e| format!("cannot reach {}: {}", self.url, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("{} answered with status {}", self.url, status));
+        }
+        if let Some(session_id) = response
+            .headers()
+            .get("mcp-session-id")
+            .and_then(|value| value.to_str().ok())
+        {
+            *self.session_id.lock().unwrap() = Some(session_id.to_string());
+        }
+        let event_stream = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        let body = response.into_body().into_reader();
+        let inner = self.clone();
+        thread::spawn(move || inner.read_body(body, event_stream));
+        Ok(())
+    }
+
+    fn read_body(self: Arc<HttpInner>, body: impl Read, event_stream: bool) {
+        let transport = HttpTransport {
+            inner: self.clone(),
+        };
+        let receive = |data: &str| match codec::parse(data) {
+            Ok(Value::Array(messages)) => {
+                for message in messages {
+                    self.shared.receive(message, &transport);
+                }
+            }
+            Ok(message) => self.shared.receive(message, &transport),
+            Err(e) => tracing::warn!("Ignoring invalid message {}: {}", data, e),
+        };
+        let mut reader = BufReader::new(body);
+        if !event_stream {
+            let mut data = String::new();
+            if reader.read_to_string(&mut data).is_ok() && !data.trim().is_empty() {
+                receive(&data);
+            }
+            return;
+        }
+        // Server-sent events: `data:` lines up to a blank line make one message
+        let mut data = String::new();
+        let lines = BoundedLines::new(reader, Limits::default().max_size).keep_blank_lines();
+        for line in lines {
+            if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            } else if line.is_empty() && !data.is_empty() {
+                receive(&data);
+                data.clear();
+            }
+        }
+        if !data.is_empty() {
+            receive(&data);
+        }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn send(&self, message: &Value) -> Result<(), String> {
+        self.inner.post(message)
+    }
+}
@@ -0,0 +1,101 @@
+//! Forwarding of the server's own log events (and those of the `log` crate, which
+//! `tracing-subscriber` bridges) to the clients as `notifications/message`, for the sessions
+//! that asked for them with `logging/setLevel` or through `client_log_level`.
+//!
+//! Events are queued by the logging layer and sent by a thread of their own, so that logging
+//! never waits on the sessions. Events about sending messages to the clients (and the wire log)
+//! aren't forwarded, since each forwarded event would cause another.
+
+use mcp_rs_test::{mcp::LoggingLevel, server::Server};
+use serde_json::json;
+use std::{
+    cell::Cell,
+    fmt::Write as _,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Weak,
+    },
+    thread,
+};
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::{field::Visit, layer::Context, Layer};
+
+/// Events waiting to be forwarded, the others being dropped.
+const QUEUE_SIZE: usize = 256;
+/// Targets whose events aren't forwarded.
+const IGNORED_TARGETS: &[&str] = &["wire", "mcp_rs_test::output"];
+
+thread_local! {
+    /// True on the thread forwarding the events, whose own events aren't forwarded.
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A log event waiting to be forwarded.
+struct LogEvent {
+    level: LoggingLevel,
+    target: String,
+    message: String,
+}
+
+/// Layer queueing the log events for [`forward`].
+pub struct ClientLogLayer {
+    events: SyncSender<LogEvent>,
+}
+
+/// The events queued by a [`ClientLogLayer`].
+pub struct ClientLogQueue {
+    events: Receiver<LogEvent>,
+}
+
+pub fn layer() -> (ClientLogLayer, ClientLogQueue) {
+    let (events, queue) = mpsc::sync_channel(QUEUE_SIZE);
+    (ClientLogLayer { events }, ClientLogQueue { events: queue })
+}
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if FORWARDING.get() || IGNORED_TARGETS.contains(&metadata.target()) {
+            return;
+        }
+        let level = match *metadata.level() {
+            Level::ERROR => LoggingLevel::Error,
+            Level::WARN => LoggingLevel::Warning,
+            Level::INFO => LoggingLevel::Info,
+            _ => LoggingLevel::Debug,
+        };
+        let mut message = Message::default();
+        event.record(&mut message);
+        let _ = self.events.try_send(LogEvent {
+            level,
+            target: metadata.target().to_string(),
+            message: message.0,
+        });
+    }
+}
+
+/// Sends the queued events to the clients of `server` until it is dropped.
+pub fn forward(queue: ClientLogQueue, server: Weak<Server>) {
+    thread::spawn(move || {
+        FORWARDING.set(true);
+        for event in queue.events {
+            let Some(server) = server.upgrade() else {
+                return;
+            };
+            server.log_to_clients(event.level, &event.target, json!(event.message));
+        }
+    });
+}
+
+/// Fields of an event, the message (always recorded first) followed by the others.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let _ = match field.name() {
+            "message" => write!(self.0, "{:?}", value),
+            name => write!(self.0, " {}={:?}", name, value),
+        };
+    }
+}
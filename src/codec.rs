@@ -0,0 +1,405 @@
+//! Decoding of incoming messages, hardened against malformed or hostile input: the size,
+//! nesting depth and string lengths of a message are checked in a single pass before it is
+//! parsed, and decoding never panics.
+
+use crate::jsonrpc::{
+    IdNumber, JsonRpcError, JsonRpcId, JsonRpcNotification, JsonRpcRequest,
+    ERROR_CODE_INVALID_REQUEST, ERROR_CODE_PARSE_ERROR,
+};
+use serde::{de::IgnoredAny, Deserialize, Deserializer};
+use serde_json::{error::Category, json, value::RawValue, Value};
+use std::{
+    borrow::Cow,
+    fmt::{self, Display},
+    io::{BufRead, Read},
+};
+
+/// Limits applied to every incoming message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of a message, in bytes
+    pub max_size: usize,
+    /// Maximum nesting of arrays and objects
+    pub max_depth: usize,
+    /// Maximum length of a string or object key, in bytes as written (escapes included)
+    pub max_string_length: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_size: 16 * 1024 * 1024,
+            max_depth: 64,
+            max_string_length: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// A decoded client message.
+#[derive(Debug)]
+pub enum Message {
+    Request(RawRequest),
+    Notification(JsonRpcNotification),
+    /// Response to a request of the server, see [`crate::requests`]
+    Response(JsonRpcId, Result<Value, JsonRpcError>),
+}
+
+/// A request whose envelope is decoded but whose `params` are still the JSON text they were
+/// sent as, so that large arguments, such as base64 blobs, are made into a [`Value`] once, by
+/// [`RawRequest::parse`] when the request is handled, rather than as soon as it is read.
+#[derive(Debug)]
+pub struct RawRequest {
+    pub id: JsonRpcId,
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Option<Box<RawValue>>,
+}
+
+/// The `_meta` member of the params of a request, the other members being skipped unparsed.
+#[derive(Deserialize)]
+struct Meta {
+    #[serde(rename = "_meta")]
+    meta: Option<Value>,
+}
+
+impl RawRequest {
+    /// `params._meta`, which steers the request before it is handled, read without parsing the
+    /// rest of `params`.
+    pub fn meta(&self) -> Option<Value> {
+        let params = self.params.as_ref()?.get();
+        // Serde would also read the struct from an array, taking its first item for `_meta`
+        if !params.starts_with('{') {
+            return None;
+        }
+        serde_json::from_str::<Meta>(params).ok()?.meta
+    }
+
+    /// The request with its `params` parsed, or the id to answer with the error.
+    pub fn parse(self) -> Result<JsonRpcRequest, (JsonRpcId, DecodeError)> {
+        let params = match &self.params {
+            Some(params) => match parse(params.get()) {
+                Ok(params) => Some(params),
+                Err(e) => return Err((self.id, DecodeError::Invalid(e))),
+            },
+            None => None,
+        };
+        Ok(JsonRpcRequest {
+            id: self.id,
+            jsonrpc: self.jsonrpc,
+            method: self.method,
+            params,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    TooLarge {
+        max: usize,
+    },
+    TooDeep {
+        max: usize,
+    },
+    StringTooLong {
+        max: usize,
+    },
+    /// Not JSON
+    Invalid(String),
+    /// JSON, but not a JSON-RPC request or notification
+    InvalidRequest(String),
+}
+
+/// Fields shared by requests and notifications, which are told apart by the presence of `id`.
+/// Strings are borrowed from the input line where possible, and `id` and `params` are kept raw
+/// until the envelope is known to be valid.
+#[derive(Deserialize)]
+struct Envelope<'a> {
+    /// `Some` with a raw `null` for `"id": null`, which is a request with a null id rather than
+    /// a notification
+    #[serde(default, borrow, deserialize_with = "present")]
+    id: Option<&'a RawValue>,
+    #[serde(borrow)]
+    jsonrpc: Cow<'a, str>,
+    #[serde(borrow)]
+    method: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    params: Option<&'a RawValue>,
+    /// Set, instead of `method`, by the responses to the requests of the server
+    #[serde(borrow)]
+    result: Option<&'a RawValue>,
+    #[serde(borrow)]
+    error: Option<&'a RawValue>,
+}
+
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<&'de RawValue>, D::Error> {
+    <&RawValue>::deserialize(deserializer).map(Some)
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooLarge { max } => {
+                write!(f, "message exceeds the limit of {} bytes", max)
+            }
+            DecodeError::TooDeep { max } => write!(f, "message nesting exceeds {} levels", max),
+            DecodeError::StringTooLong { max } => {
+                write!(f, "message contains a string longer than {} bytes", max)
+            }
+            DecodeError::Invalid(e) => write!(f, "{}", e),
+            DecodeError::InvalidRequest(e) => write!(f, "invalid request: {}", e),
+        }
+    }
+}
+
+impl DecodeError {
+    /// The error sent back to the client: a parse error, or an invalid request error for
+    /// JSON that isn't a request. Both say what is wrong in `data.reason`, except for invalid
+    /// JSON.
+    pub fn to_error(&self) -> JsonRpcError {
+        let mut error = match self {
+            DecodeError::InvalidRequest(_) => {
+                JsonRpcError::new(ERROR_CODE_INVALID_REQUEST, "Invalid Request")
+            }
+            _ => JsonRpcError::new(ERROR_CODE_PARSE_ERROR, "Parse error"),
+        };
+        if !matches!(self, DecodeError::Invalid(_)) {
+            error.data = Some(json!({ "reason": self.to_string() }));
+        }
+        error
+    }
+
+    /// Id of the response carrying the error about `input`: that of the message if it can be
+    /// read, null otherwise, as for invalid JSON. `None` for a malformed notification (a
+    /// `method` without an `id`), which JSON-RPC never answers.
+    pub fn response_id(&self, input: &str) -> Option<JsonRpcId> {
+        let DecodeError::InvalidRequest(_) = self else {
+            return Some(JsonRpcId::Null);
+        };
+        // Parsed again only on this error path, and known to be JSON
+        let Ok(Value::Object(message)) = parse(input) else {
+            return Some(JsonRpcId::Null);
+        };
+        match message.get("id") {
+            Some(id) => Some(serde_json::from_value(id.clone()).unwrap_or(JsonRpcId::Null)),
+            None if message.contains_key("method") => None,
+            None => Some(JsonRpcId::Null),
+        }
+    }
+}
+
+/// Parses a JSON message, with simd-json when the `simd-json` feature is enabled.
+#[cfg(feature = "simd-json")]
+pub(crate) fn parse(input: &str) -> Result<Value, String> {
+    // simd-json parses in place
+    let mut bytes = input.as_bytes().to_vec();
+    simd_json::serde::from_slice(&mut bytes).map_err(|e| e.to_string())
+}
+
+/// Parses a JSON message, with simd-json when the `simd-json` feature is enabled.
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn parse(input: &str) -> Result<Value, String> {
+    serde_json::from_str(input).map_err(|e| e.to_string())
+}
+
+/// Decodes one message, checking `limits` first. A message with an `id` is a request, one
+/// without is a notification, and one with a `result` or an `error` instead of a `method` is a
+/// response.
+pub fn decode(input: &str, limits: &Limits) -> Result<Message, DecodeError> {
+    check_limits(input.as_bytes(), limits)?;
+    // Serde would also read an envelope from an array
+    if !input.trim_start().starts_with('{') {
+        return Err(
+            match serde_json::from_str::<IgnoredAny>(input).map_err(|e| e.to_string()) {
+                Ok(_) => DecodeError::InvalidRequest("a message must be a JSON object".to_string()),
+                Err(e) => DecodeError::Invalid(e),
+            },
+        );
+    }
+    let envelope: Envelope = serde_json::from_str(input).map_err(|e| match e.classify() {
+        Category::Data => DecodeError::InvalidRequest(e.to_string()),
+        _ => DecodeError::Invalid(e.to_string()),
+    })?;
+    let Some(method) = envelope.method else {
+        return decode_response(&envelope);
+    };
+    let (jsonrpc, method) = (envelope.jsonrpc.into_owned(), method.into_owned());
+    match envelope.id {
+        // Parsed when handled
+        Some(id) => Ok(Message::Request(RawRequest {
+            id: decode_id(id)?,
+            jsonrpc,
+            method,
+            params: envelope.params.map(ToOwned::to_owned),
+        })),
+        None => Ok(Message::Notification(JsonRpcNotification {
+            jsonrpc,
+            method,
+            params: match envelope.params {
+                Some(params) => Some(parse(params.get()).map_err(DecodeError::Invalid)?),
+                None => None,
+            },
+        })),
+    }
+}
+
+fn decode_response(envelope: &Envelope) -> Result<Message, DecodeError> {
+    let invalid = |message: &str| DecodeError::InvalidRequest(message.to_string());
+    let id = envelope
+        .id
+        .ok_or_else(|| invalid("missing field `method`"))?;
+    let id = decode_id(id)?;
+    let response = match (envelope.result, envelope.error) {
+        (Some(result), None) => Ok(parse(result.get()).map_err(DecodeError::Invalid)?),
+        (None, Some(error)) => Err(serde_json::from_str::<JsonRpcError>(error.get())
+            .map_err(|e| DecodeError::InvalidRequest(format!("invalid error: {}", e)))?),
+        _ => return Err(invalid("a response needs either a result or an error")),
+    };
+    Ok(Message::Response(id, response))
+}
+
+fn decode_id(id: &RawValue) -> Result<JsonRpcId, DecodeError> {
+    // Numbers keep their text, to be sent back as they were received
+    if let Some(number) = IdNumber::parse(id.get()) {
+        return Ok(JsonRpcId::Number(number));
+    }
+    serde_json::from_str::<JsonRpcId>(id.get()).map_err(|_| {
+        DecodeError::InvalidRequest(format!("id must be a number, a string or null, not {}", id))
+    })
+}
+
+/// Scans the raw message for the limits, without parsing it.
+pub fn check_limits(input: &[u8], limits: &Limits) -> Result<(), DecodeError> {
+    if input.len() > limits.max_size {
+        return Err(DecodeError::TooLarge {
+            max: limits.max_size,
+        });
+    }
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut string_length = 0usize;
+    for byte in input {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if *byte == b'\\' {
+                escaped = true;
+            } else if *byte == b'"' {
+                in_string = false;
+                continue;
+            }
+            string_length += 1;
+            if string_length > limits.max_string_length {
+                return Err(DecodeError::StringTooLong {
+                    max: limits.max_string_length,
+                });
+            }
+            continue;
+        }
+        match byte {
+            b'"' => {
+                in_string = true;
+                string_length = 0;
+            }
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > limits.max_depth {
+                    return Err(DecodeError::TooDeep {
+                        max: limits.max_depth,
+                    });
+                }
+            }
+            // Unbalanced brackets are left for the parser to report
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Lines of a stream, each holding at most `max_size` bytes so that a client can't make the
+/// server buffer a huge line: the rest of a longer line is read and discarded as it arrives,
+/// and the line is cut to `max_size + 1` bytes, which [`decode`] then rejects as too large.
+///
+/// Lines are read incrementally into one buffer each, which grows with the chunks the reader
+/// hands out, and become the `String` yielded without another copy. Lines may end with `\r\n`,
+/// the stream may start with a UTF-8 byte order mark, and blank lines are skipped, as some
+/// Windows hosts send them, unless [`BoundedLines::keep_blank_lines`] is set. Invalid UTF-8 is
+/// replaced rather than ending the stream.
+pub struct BoundedLines<R> {
+    reader: R,
+    max_size: usize,
+    /// True until the first line has been read
+    at_start: bool,
+    keep_blank_lines: bool,
+}
+
+impl<R: BufRead> BoundedLines<R> {
+    pub fn new(reader: R, max_size: usize) -> BoundedLines<R> {
+        BoundedLines {
+            reader,
+            max_size,
+            at_start: true,
+            keep_blank_lines: false,
+        }
+    }
+
+    /// Yields the blank lines too, for formats where they mean something, such as server-sent
+    /// events.
+    pub fn keep_blank_lines(mut self) -> BoundedLines<R> {
+        self.keep_blank_lines = true;
+        self
+    }
+
+    /// Next line as read, or None at the end of the input.
+    fn read_line(&mut self) -> Option<Vec<u8>> {
+        let mut line = Vec::new();
+        // Room for `\r\n` after a line of `max_size` bytes
+        let limit = self.max_size.saturating_add(2) as u64;
+        match (&mut self.reader).take(limit).read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Error reading input: {}", e);
+                return None;
+            }
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        } else if line.len() > self.max_size {
+            // Kept cut, so that it is rejected rather than taken for a shorter message
+            line.truncate(self.max_size.saturating_add(1));
+            let _ = self.reader.skip_until(b'\n');
+            tracing::warn!(
+                "Discarded a message over the limit of {} bytes",
+                self.max_size
+            );
+        }
+        Some(line)
+    }
+}
+
+/// UTF-8 byte order mark.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+impl<R: BufRead> Iterator for BoundedLines<R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let mut line = self.read_line()?;
+            if std::mem::take(&mut self.at_start) && line.starts_with(BOM) {
+                line.drain(..BOM.len());
+            }
+            if self.keep_blank_lines || !line.iter().all(u8::is_ascii_whitespace) {
+                return Some(match String::from_utf8(line) {
+                    Ok(line) => line,
+                    Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! `bench`: sends many requests to this server in-process, or to another one over stdio or
+//! Streamable HTTP, from several threads at once, and reports the throughput and the latency
+//! percentiles of each method, so that regressions of the dispatcher and the writer show up as
+//! numbers.
+//!
+//! In-process and over HTTP, each thread has a session of its own; the threads share the one
+//! connection of a stdio server.
+
+use crate::cli::{BenchArgs, Cli, ServerArgs};
+use mcp_rs_test::{client::Client, plugins, proxy::Proxy, testing::MockClient};
+use serde_json::Value;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A request of the mix.
+struct Request {
+    method: String,
+    params: Option<Value>,
+    weight: usize,
+}
+
+/// Sends a request and waits for its response, failing when there's none or it's an error.
+type Caller = Box<dyn FnMut(&str, Option<Value>) -> Result<(), String> + Send>;
+
+/// Latency of a request, with the index of its request in the mix and whether it failed.
+struct Sample {
+    request: usize,
+    latency: Duration,
+    failed: bool,
+}
+
+pub fn run(cli: &Cli, args: &BenchArgs) -> Result<(), String> {
+    let mix = match args.mix.is_empty() {
+        true => vec![parse_request("ping")?],
+        false => args
+            .mix
+            .iter()
+            .map(|spec| parse_request(spec))
+            .collect::<Result<_, _>>()?,
+    };
+    // Each request of the mix repeated by its weight, request i of the run being order[i % len]
+    let order: Vec<usize> = mix
+        .iter()
+        .enumerate()
+        .flat_map(|(index, request)| std::iter::repeat_n(index, request.weight))
+        .collect();
+    if order.is_empty() {
+        return Err("every request of the mix has a weight of 0".to_string());
+    }
+    let callers = connect(cli, args)?;
+
+    eprintln!(
+        "Sending {} requests from {} threads",
+        args.requests,
+        callers.len()
+    );
+    let next = AtomicUsize::new(0);
+    let start = Instant::now();
+    let samples: Vec<Sample> = thread::scope(|scope| {
+        let workers: Vec<_> = callers
+            .into_iter()
+            .map(|mut call| {
+                let (next, mix, order) = (&next, &mix, &order);
+                scope.spawn(move || {
+                    let mut samples = Vec::new();
+                    loop {
+                        let number = next.fetch_add(1, Ordering::Relaxed);
+                        if number >= args.requests {
+                            return samples;
+                        }
+                        let request = order[number % order.len()];
+                        let sent = Instant::now();
+                        let result = call(&mix[request].method, mix[request].params.clone());
+                        samples.push(Sample {
+                            request,
+                            latency: sent.elapsed(),
+                            failed: result.is_err(),
+                        });
+                        if let Err(e) = result {
+                            tracing::debug!("{} failed: {}", mix[request].method, e);
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+    let elapsed = start.elapsed();
+
+    let failed = samples.iter().filter(|sample| sample.failed).count();
+    println!(
+        "{} requests in {:.2} s: {:.1} requests/s, {} failed",
+        samples.len(),
+        elapsed.as_secs_f64(),
+        samples.len() as f64 / elapsed.as_secs_f64(),
+        failed
+    );
+    println!(
+        "{:<24} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "method", "count", "failed", "p50", "p90", "p99", "max"
+    );
+    for (index, request) in mix.iter().enumerate() {
+        let latencies = samples.iter().filter(|sample| sample.request == index);
+        print_row(&request.method, latencies);
+    }
+    if mix.len() > 1 {
+        print_row("all", samples.iter());
+    }
+    Ok(())
+}
+
+/// A request of `--mix`: `[WEIGHT*]METHOD [PARAMS]`.
+fn parse_request(spec: &str) -> Result<Request, String> {
+    let (weight, rest) = match spec.split_once('*') {
+        Some((weight, rest)) if weight.trim().chars().all(|c| c.is_ascii_digit()) => (
+            weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight in '{}'", spec))?,
+            rest,
+        ),
+        _ => (1, spec),
+    };
+    let rest = rest.trim();
+    let (method, params) = match rest.split_once(char::is_whitespace) {
+        Some((method, params)) => {
+            let params = serde_json::from_str(params.trim())
+                .map_err(|e| format!("invalid params in '{}': {}", spec, e))?;
+            (method, Some(params))
+        }
+        None => (rest, None),
+    };
+    if method.is_empty() {
+        return Err(format!("no method in '{}'", spec));
+    }
+    Ok(Request {
+        method: method.to_string(),
+        params,
+        weight,
+    })
+}
+
+/// A caller for each thread, connected and past the handshake.
+fn connect(cli: &Cli, args: &BenchArgs) -> Result<Vec<Caller>, String> {
+    let threads = args.concurrency.max(1);
+    let timeout = Duration::from_secs(args.timeout);
+    let Some(first) = args.server.first() else {
+        let config = cli.config()?;
+        let proxy = Proxy::connect(&config);
+        let server = Arc::new(crate::build_server(
+            &config,
+            &proxy,
+            plugins::load_tools(&config),
+        ));
+        proxy.attach(&server);
+        return (0..threads)
+            .map(|_| {
+                let mut client = MockClient::connect(server.clone()).with_timeout(timeout);
+                client.initialize()?;
+                let caller: Caller = Box::new(move |method, params| {
+                    let response = client.request(method, params)?;
+                    match response.get("error") {
+                        Some(error) => Err(error.to_string()),
+                        None => Ok(()),
+                    }
+                });
+                Ok(caller)
+            })
+            .collect();
+    };
+    let server = ServerArgs {
+        headers: args.headers.clone(),
+        timeout: args.timeout,
+        server: args.server.clone(),
+    };
+    let remote = |client: Arc<Client>| -> Caller {
+        Box::new(move |method, params| {
+            client
+                .request(method, params, None, |_| {})
+                .map(|_| ())
+                .map_err(|e| e.message)
+        })
+    };
+    if first.starts_with("http://") || first.starts_with("https://") {
+        (0..threads)
+            .map(|_| Ok(remote(Arc::new(server.connect()?))))
+            .collect()
+    } else {
+        let client = Arc::new(server.connect()?);
+        Ok((0..threads).map(|_| remote(client.clone())).collect())
+    }
+}
+
+/// Prints the count, failures and latency percentiles of `samples`.
+fn print_row<'a>(name: &str, samples: impl Iterator<Item = &'a Sample>) {
+    let mut count = 0;
+    let mut failed = 0;
+    let mut latencies = Vec::new();
+    for sample in samples {
+        count += 1;
+        failed += usize::from(sample.failed);
+        latencies.push(sample.latency);
+    }
+    latencies.sort();
+    let percentile = |p: usize| match latencies.len() {
+        0 => "-".to_string(),
+        len => milliseconds(latencies[(len * p).div_ceil(100).clamp(1, len) - 1]),
+    };
+    println!(
+        "{:<24} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        name,
+        count,
+        failed,
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        percentile(100)
+    );
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,32 @@
+//! `client`: sends one request to another MCP server and prints its result.
+
+use crate::cli::ClientArgs;
+use serde_json::Value;
+use std::io::{self, Write};
+
+pub fn run(args: &ClientArgs) -> Result<(), String> {
+    let params: Option<Value> = match &args.params {
+        Some(params) => {
+            Some(serde_json::from_str(params).map_err(|e| format!("invalid --params: {}", e))?)
+        }
+        None => None,
+    };
+    let client = args.server.connect()?;
+    // Notifications and progress are informational, so they go to stderr
+    client.on_notification(|method, params| match params {
+        Some(params) => eprintln!("{} {}", method, params),
+        None => eprintln!("{}", method),
+    });
+    let result = client
+        .request(&args.method, params, None, |progress| {
+            eprintln!("notifications/progress {}", progress)
+        })
+        .map_err(|e| format!("{} (code {})", e.message, e.code))?;
+    // Ignoring write errors, when the output is piped to a command that exits early
+    let _ = writeln!(
+        io::stdout(),
+        "{}",
+        serde_json::to_string_pretty(&result).unwrap()
+    );
+    Ok(())
+}
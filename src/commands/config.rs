@@ -0,0 +1,253 @@
+//! `config validate` and `config schema`: checks a configuration file against the JSON schema of
+//! [`Config`], which unlike the server reading the file rejects unknown keys, and reports each
+//! problem with the line it is on.
+
+use crate::cli::{Cli, ConfigArgs, ConfigCommand};
+use jsonschema::error::ValidationErrorKind;
+use mcp_rs_test::config::Config;
+use serde_json::Value;
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+pub fn run(cli: &Cli, args: &ConfigArgs) -> Result<(), String> {
+    match &args.command {
+        ConfigCommand::Schema => {
+            let schema = serde_json::to_string_pretty(&Config::schema()).unwrap_or_default();
+            println!("{}", schema);
+            Ok(())
+        }
+        ConfigCommand::Validate { file } => {
+            let Some(path) = file.as_ref().or(cli.config.as_ref()) else {
+                return Err("no configuration file given, nor --config".to_string());
+            };
+            let problems = validate(path)?;
+            for problem in &problems {
+                println!("{}:{}", path.display(), problem);
+            }
+            match problems.len() {
+                0 => {
+                    eprintln!("{} is valid", path.display());
+                    Ok(())
+                }
+                1 => Err(format!("1 problem in {}", path.display())),
+                count => Err(format!("{} problems in {}", count, path.display())),
+            }
+        }
+    }
+}
+
+/// A problem of a configuration file.
+pub struct Problem {
+    /// Line the problem is on, counting from 1
+    pub line: usize,
+    /// JSON pointer of the value at fault, empty for the whole file
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pointer.as_str() {
+            "" => write!(f, "{}: {}", self.line, self.message),
+            pointer => write!(f, "{}: {} (at {})", self.line, self.message, pointer),
+        }
+    }
+}
+
+/// The problems of the configuration file at `path`, in the order of their lines.
+pub fn validate(path: &Path) -> Result<Vec<Problem>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read config file {}: {}", path.display(), e))?;
+    check(&text)
+}
+
+fn check(text: &str) -> Result<Vec<Problem>, String> {
+    let value: Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(e) => return Ok(vec![serde_problem(&e)]),
+    };
+    let validator = jsonschema::validator_for(&Config::schema())
+        .map_err(|e| format!("invalid configuration schema: {}", e))?;
+    let lines = Lines::of(text);
+    let mut problems = Vec::new();
+    for error in validator.iter_errors(&value) {
+        let pointer = error.instance_path().as_str().to_string();
+        match error.kind() {
+            ValidationErrorKind::AdditionalProperties { unexpected } => {
+                for key in unexpected {
+                    let pointer = format!("{}/{}", pointer, escape(key));
+                    problems.push(Problem {
+                        line: lines.line(&pointer),
+                        pointer,
+                        message: format!("unknown key '{}'", key),
+                    });
+                }
+            }
+            _ => problems.push(Problem {
+                line: lines.line(&pointer),
+                pointer,
+                message: error.to_string(),
+            }),
+        }
+    }
+    // What the schema can't tell, such as the format of the rate limits
+    if problems.is_empty() {
+        if let Err(e) = serde_json::from_str::<Config>(text) {
+            problems.push(serde_problem(&e));
+        }
+    }
+    problems.sort_by_key(|problem| problem.line);
+    Ok(problems)
+}
+
+/// A problem serde found, whose message has its line.
+fn serde_problem(error: &serde_json::Error) -> Problem {
+    let message = error.to_string();
+    let message = match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    };
+    Problem {
+        line: error.line(),
+        pointer: String::new(),
+        message,
+    }
+}
+
+/// `key` as a JSON pointer token.
+pub(super) fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Line of each value of a JSON text by JSON pointer, that of its key for object members.
+struct Lines(HashMap<String, usize>);
+
+impl Lines {
+    /// Lines of the values of `text`, which must be valid JSON.
+    fn of(text: &str) -> Lines {
+        let mut scanner = Scanner {
+            text: text.as_bytes(),
+            position: 0,
+            line: 1,
+            lines: HashMap::new(),
+        };
+        scanner.value(String::new());
+        Lines(scanner.lines)
+    }
+
+    /// Line of the value at `pointer`, or of its closest parent when it doesn't exist, e.g. for
+    /// a missing key.
+    fn line(&self, pointer: &str) -> usize {
+        let mut pointer = pointer;
+        loop {
+            if let Some(line) = self.0.get(pointer) {
+                return *line;
+            }
+            match pointer.rsplit_once('/') {
+                Some((parent, _)) => pointer = parent,
+                None => return 1,
+            }
+        }
+    }
+}
+
+struct Scanner<'a> {
+    text: &'a [u8],
+    position: usize,
+    line: usize,
+    lines: HashMap<String, usize>,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek().filter(u8::is_ascii_whitespace) {
+            if byte == b'\n' {
+                self.line += 1;
+            }
+            self.position += 1;
+        }
+    }
+
+    /// Scans the value at `pointer`, recording its line unless that of its key was.
+    fn value(&mut self, pointer: String) {
+        self.skip_whitespace();
+        self.lines.entry(pointer.clone()).or_insert(self.line);
+        match self.peek() {
+            Some(b'{') => {
+                self.position += 1;
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b'"') => {
+                            let line = self.line;
+                            let member = format!("{}/{}", pointer, escape(&self.string()));
+                            self.lines.insert(member.clone(), line);
+                            self.skip_whitespace();
+                            if self.peek() == Some(b':') {
+                                self.position += 1;
+                            }
+                            self.value(member);
+                        }
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return;
+                        }
+                        _ => return,
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return;
+                        }
+                        Some(_) => {
+                            self.value(format!("{}/{}", pointer, index));
+                            index += 1;
+                        }
+                        None => return,
+                    }
+                }
+            }
+            Some(b'"') => {
+                self.string();
+            }
+            Some(_) => {
+                self.position += 1;
+                while self
+                    .peek()
+                    .is_some_and(|byte| !b",:]} \t\r\n".contains(&byte))
+                {
+                    self.position += 1;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Scans a string, returning what it contains.
+    fn string(&mut self) -> String {
+        let start = self.position;
+        self.position += 1;
+        while let Some(byte) = self.peek() {
+            self.position += 1;
+            match byte {
+                b'\\' => self.position += 1,
+                b'"' => break,
+                _ => {}
+            }
+        }
+        let end = self.position.min(self.text.len());
+        serde_json::from_slice(&self.text[start..end]).unwrap_or_default()
+    }
+}
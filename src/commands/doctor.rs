@@ -0,0 +1,279 @@
+//! `doctor`: builds the server from the configuration, connects to it in-process the way a
+//! client would and reports what the client gets: the handshake, the lists, and each tool
+//! checked with sample arguments generated from its schema. Meant for "the server doesn't show
+//! up in my client" reports.
+
+use crate::cli::{Cli, DoctorArgs};
+use mcp_rs_test::{plugins, protocol::ProtocolVersion, proxy::Proxy, testing::MockClient};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Pages after which a paginated list is considered endless.
+const MAX_PAGES: usize = 100;
+/// Longest tool name clients accept.
+const MAX_TOOL_NAME: usize = 64;
+
+/// Findings of the checks, printed as they come.
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: impl AsRef<str>) {
+        let _ = writeln!(io::stdout(), "ok    {}", message.as_ref());
+    }
+
+    fn warn(&mut self, message: impl AsRef<str>) {
+        self.warnings += 1;
+        let _ = writeln!(io::stdout(), "warn  {}", message.as_ref());
+    }
+
+    fn fail(&mut self, message: impl AsRef<str>) {
+        self.failures += 1;
+        let _ = writeln!(io::stdout(), "FAIL  {}", message.as_ref());
+    }
+}
+
+pub fn run(cli: &Cli, args: &DoctorArgs) -> Result<(), String> {
+    let config = cli.config()?;
+    let proxy = Proxy::connect(&config);
+    let server = Arc::new(crate::build_server(
+        &config,
+        &proxy,
+        plugins::load_tools(&config),
+    ));
+    proxy.attach(&server);
+    let mut client = MockClient::connect(server).with_timeout(Duration::from_secs(args.timeout));
+    let mut report = Report::default();
+
+    let requested = match &args.protocol_version {
+        Some(version) => version.clone(),
+        None => ProtocolVersion::LATEST.to_string(),
+    };
+    let response = client.request(
+        "initialize",
+        Some(json!({
+            "protocolVersion": requested,
+            "capabilities": {},
+            "clientInfo": { "name": "mcp_rs_test-doctor", "version": env!("CARGO_PKG_VERSION") },
+        })),
+    )?;
+    let Some(initialize) = response.get("result").cloned() else {
+        report.fail(format!("initialize failed: {}", response["error"]));
+        return Err("the handshake failed".to_string());
+    };
+    client.notify("notifications/initialized", None)?;
+    report.ok(format!(
+        "initialize: {} {}, protocol {}",
+        initialize["serverInfo"]["name"]
+            .as_str()
+            .unwrap_or("(no name)"),
+        initialize["serverInfo"]["version"]
+            .as_str()
+            .unwrap_or("(no version)"),
+        initialize["protocolVersion"]
+            .as_str()
+            .unwrap_or("(no version)")
+    ));
+    if initialize["protocolVersion"] != requested.as_str() {
+        report.warn(format!(
+            "protocol {} was asked for, clients that only speak it will disconnect",
+            requested
+        ));
+    }
+    let capabilities = &initialize["capabilities"];
+    for (capability, method, field) in [
+        ("tools", "tools/list", "tools"),
+        ("resources", "resources/list", "resources"),
+        ("prompts", "prompts/list", "prompts"),
+    ] {
+        let items = match list(&mut client, method, field) {
+            Ok(items) => items,
+            Err(e) => {
+                report.fail(e);
+                continue;
+            }
+        };
+        match (capabilities.get(capability).is_some(), items.is_empty()) {
+            (true, _) => report.ok(format!("{}: {}", method, items.len())),
+            (false, true) => report.ok(format!("{}: none, and no {} capability", method, field)),
+            (false, false) => report.warn(format!(
+                "{} returns {} {} but the {} capability isn't announced, clients won't ask",
+                method,
+                items.len(),
+                field,
+                capability
+            )),
+        }
+        if field == "tools" {
+            check_tools(&mut client, &mut report, &items, args.call);
+        }
+    }
+    client.close()?;
+
+    let _ = writeln!(
+        io::stdout(),
+        "{} warnings, {} failures",
+        report.warnings,
+        report.failures
+    );
+    match report.failures {
+        0 => Ok(()),
+        failures => Err(format!("{} checks failed", failures)),
+    }
+}
+
+/// Items of a paginated list.
+pub(super) fn list(
+    client: &mut MockClient,
+    method: &str,
+    field: &str,
+) -> Result<Vec<Value>, String> {
+    let mut items = Vec::new();
+    let mut params = None;
+    for _ in 0..MAX_PAGES {
+        let response = client.request(method, params)?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error["message"]));
+        }
+        let page = &response["result"];
+        let Some(page_items) = page[field].as_array() else {
+            return Err(format!("{} result has no `{}` array", method, field));
+        };
+        items.extend(page_items.iter().cloned());
+        match page["nextCursor"].as_str() {
+            Some(cursor) => params = Some(json!({ "cursor": cursor })),
+            None => return Ok(items),
+        }
+    }
+    Err(format!("{} returned more than {} pages", method, MAX_PAGES))
+}
+
+/// Checks the definition of every tool and its schema against a sample of arguments, calling
+/// the read-only ones with it when `call` is set.
+fn check_tools(client: &mut MockClient, report: &mut Report, tools: &[Value], call: bool) {
+    let mut names = HashSet::new();
+    for tool in tools {
+        let Some(name) = tool["name"].as_str() else {
+            report.fail(format!("a tool has no name: {}", tool));
+            continue;
+        };
+        if !names.insert(name) {
+            report.fail(format!("tool {}: listed twice", name));
+        }
+        let valid_name = (1..=MAX_TOOL_NAME).contains(&name.len())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            report.warn(format!(
+                "tool {}: some clients only accept names of 1 to {} letters, digits, '_' and '-'",
+                name, MAX_TOOL_NAME
+            ));
+        }
+        if tool["description"].as_str().is_none_or(str::is_empty) {
+            report.warn(format!(
+                "tool {}: no description, the model won't know when to use it",
+                name
+            ));
+        }
+        let schema = &tool["inputSchema"];
+        if schema["type"] != "object" {
+            report.fail(format!("tool {}: inputSchema isn't of type object", name));
+            continue;
+        }
+        let validator = match jsonschema::validator_for(schema) {
+            Ok(validator) => validator,
+            Err(e) => {
+                report.fail(format!("tool {}: invalid inputSchema: {}", name, e));
+                continue;
+            }
+        };
+        let arguments = sample(schema);
+        if let Some(error) = validator.iter_errors(&arguments).next() {
+            report.warn(format!(
+                "tool {}: sample arguments {} don't match the schema: {}",
+                name, arguments, error
+            ));
+            continue;
+        }
+        let read_only = tool["annotations"]["readOnlyHint"] == true;
+        if !call || !read_only {
+            report.ok(format!("tool {}: schema accepts {}", name, arguments));
+            continue;
+        }
+        let response = client.request(
+            "tools/call",
+            Some(json!({ "name": name, "arguments": arguments })),
+        );
+        match response {
+            Err(e) => report.fail(format!("tool {}: {}", name, e)),
+            Ok(response) => match (&response["error"], &response["result"]) {
+                (Value::Null, result) if result["content"].is_array() => {
+                    let outcome = match result["isError"] == true {
+                        true => "an error result",
+                        false => "a result",
+                    };
+                    report.ok(format!("tool {}: {} gets {}", name, arguments, outcome));
+                }
+                (Value::Null, result) => {
+                    report.fail(format!("tool {}: result without content: {}", name, result))
+                }
+                (error, _) => report.warn(format!(
+                    "tool {}: {} is answered with error {}",
+                    name, arguments, error["message"]
+                )),
+            },
+        }
+    }
+}
+
+/// A value following `schema`: its default or first example if any, otherwise the simplest
+/// value of its type, with the required properties of objects.
+fn sample(schema: &Value) -> Value {
+    if let Some(value) = schema
+        .get("default")
+        .or_else(|| schema.get("const"))
+        .or_else(|| schema["examples"].get(0))
+        .or_else(|| schema["enum"].get(0))
+    {
+        return value.clone();
+    }
+    if let Some(first) = schema["anyOf"].get(0).or_else(|| schema["oneOf"].get(0)) {
+        return sample(first);
+    }
+    let kind = match &schema["type"] {
+        Value::Array(kinds) => kinds.first().and_then(Value::as_str),
+        kind => kind.as_str(),
+    };
+    match kind {
+        Some("object") => {
+            let mut object = Map::new();
+            for name in schema["required"].as_array().into_iter().flatten() {
+                if let Some(name) = name.as_str() {
+                    object.insert(name.to_string(), sample(&schema["properties"][name]));
+                }
+            }
+            Value::Object(object)
+        }
+        Some("array") => {
+            let count = schema["minItems"].as_u64().unwrap_or(0) as usize;
+            Value::Array(vec![sample(&schema["items"]); count])
+        }
+        Some("string") => {
+            let length = schema["minLength"].as_u64().unwrap_or(1) as usize;
+            Value::String("x".repeat(length.max(1)))
+        }
+        Some("integer") => json!(schema["minimum"].as_i64().unwrap_or(0)),
+        Some("number") => json!(schema["minimum"].as_f64().unwrap_or(0.0)),
+        Some("boolean") => json!(false),
+        _ => Value::Null,
+    }
+}
@@ -0,0 +1,59 @@
+//! `export-config`: prints the configuration an MCP client needs to reach this server as the
+//! flags and config file set it up: the command starting it for stdio, or the URL of the HTTP
+//! or WebSocket endpoint, in the layout of VS Code, Cursor or the usual `mcpServers` object.
+
+use super::install::{environment, stdio_entry};
+use crate::cli::{Cli, ClientFormat, ExportConfigArgs};
+use mcp_rs_test::config::Transport;
+use serde_json::{json, Value};
+
+pub fn run(cli: &Cli, args: &ExportConfigArgs) -> Result<(), String> {
+    let config = cli.config()?;
+    let entry = match config.transport {
+        Transport::Stdio => {
+            let mut entry = stdio_entry(cli, &args.args)?;
+            let env = environment(&args.env)?;
+            if !env.is_empty() {
+                entry["env"] = Value::Object(env);
+            }
+            if args.format == ClientFormat::Vscode {
+                entry["type"] = json!("stdio");
+            }
+            entry
+        }
+        Transport::Http => {
+            let url = format!("http://{}/mcp", client_address(&config.listen));
+            match args.format {
+                ClientFormat::Vscode => json!({ "type": "http", "url": url }),
+                ClientFormat::Cursor | ClientFormat::Json => json!({ "url": url }),
+            }
+        }
+        Transport::Ws => match args.format {
+            ClientFormat::Json => {
+                json!({ "url": format!("ws://{}", client_address(&config.listen)) })
+            }
+            ClientFormat::Vscode | ClientFormat::Cursor => {
+                return Err(
+                    "the client doesn't support WebSocket servers, use stdio or http".to_string(),
+                )
+            }
+        },
+    };
+    let client_config = match args.format {
+        ClientFormat::Vscode => json!({ "servers": { &args.name: entry } }),
+        ClientFormat::Cursor | ClientFormat::Json => json!({ "mcpServers": { &args.name: entry } }),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&client_config).unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// `listen` as clients reach it: `localhost` when listening on every interface.
+fn client_address(listen: &str) -> String {
+    match listen.rsplit_once(':') {
+        Some(("" | "0.0.0.0" | "[::]", port)) => format!("localhost:{}", port),
+        _ => listen.to_string(),
+    }
+}
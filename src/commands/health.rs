@@ -0,0 +1,120 @@
+//! `--health-check`: checks that the configured server answers `initialize` and `ping`, for
+//! Docker's HEALTHCHECK, systemd watchdogs and other supervisors.
+//!
+//! The http and ws transports are checked by connecting to the address the server listens on;
+//! a stdio server has nothing to connect to, so a new one is spawned with the same options.
+
+use crate::cli::Cli;
+use mcp_rs_test::{
+    client::{Client, PROTOCOL_VERSION},
+    config::{DownstreamServer, Transport},
+    mcp::Implementation,
+};
+use serde_json::{json, Value};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+use tungstenite::{stream::MaybeTlsStream, Message};
+
+/// Time the server has to answer each request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn run(cli: &Cli) -> Result<(), String> {
+    let config = cli.config()?;
+    let start = Instant::now();
+    let server_info = match config.transport {
+        Transport::Stdio => check_client(&spawned_server()?)?,
+        Transport::Http => check_client(&DownstreamServer {
+            url: Some(format!("http://{}/mcp", local_address(&config.listen))),
+            ..Default::default()
+        })?,
+        Transport::Ws => check_ws(&format!("ws://{}", local_address(&config.listen)))?,
+    };
+    println!(
+        "ok: {} {} answered in {} ms",
+        server_info["name"].as_str().unwrap_or_default(),
+        server_info["version"].as_str().unwrap_or_default(),
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+/// This executable with the same arguments, minus `--health-check`.
+fn spawned_server() -> Result<DownstreamServer, String> {
+    let command = env::current_exe().map_err(|e| format!("cannot find the executable: {}", e))?;
+    Ok(DownstreamServer {
+        command: Some(command.to_string_lossy().into_owned()),
+        args: env::args()
+            .skip(1)
+            .filter(|arg| arg != "--health-check")
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// Connects (which performs `initialize`) and pings, returning the `serverInfo`.
+fn check_client(server: &DownstreamServer) -> Result<Value, String> {
+    let client = Client::connect(server, TIMEOUT)?;
+    client
+        .request("ping", None, None, |_| {})
+        .map_err(|e| format!("ping failed: {}", e.message))?;
+    Ok(client.server_info().clone())
+}
+
+/// Same as [`check_client`] over WebSocket, which the client doesn't speak.
+fn check_ws(url: &str) -> Result<Value, String> {
+    let (mut socket, _) =
+        tungstenite::connect(url).map_err(|e| format!("cannot connect to {}: {}", url, e))?;
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+    }
+    let mut request = |id: u64, method: &str, params: Value| -> Result<Value, String> {
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        socket
+            .send(Message::text(message.to_string()))
+            .map_err(|e| e.to_string())?;
+        loop {
+            let text = match socket
+                .read()
+                .map_err(|e| format!("{} failed: {}", method, e))?
+            {
+                Message::Text(text) => text,
+                Message::Close(_) => return Err(format!("{} failed: connection closed", method)),
+                _ => continue,
+            };
+            let response: Value = serde_json::from_str(&text).unwrap_or_default();
+            if response["id"] != id {
+                continue;
+            }
+            return match response.get("result") {
+                Some(result) => Ok(result.clone()),
+                None => Err(format!(
+                    "{} failed: {}",
+                    method, response["error"]["message"]
+                )),
+            };
+        }
+    };
+    let result = request(
+        1,
+        "initialize",
+        json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": Implementation::default(),
+        }),
+    )?;
+    request(2, "ping", json!({}))?;
+    let _ = socket.close(None);
+    Ok(result["serverInfo"].clone())
+}
+
+/// Address to connect to for a server listening on `listen`, which may be a wildcard.
+fn local_address(listen: &str) -> String {
+    match listen.rsplit_once(':') {
+        Some(("0.0.0.0", port)) => format!("127.0.0.1:{}", port),
+        Some(("[::]", port)) => format!("[::1]:{}", port),
+        _ => listen.to_string(),
+    }
+}
@@ -0,0 +1,152 @@
+//! `inspect`: prints what another MCP server offers.
+
+use crate::cli::InspectArgs;
+use mcp_rs_test::client::Client;
+use serde_json::{json, Map, Value};
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+/// The lists fetched from the server, with the capability announcing them.
+const LISTS: [(&str, &str, &str); 3] = [
+    ("tools", "tools/list", "tools"),
+    ("resources", "resources/list", "resources"),
+    ("prompts", "prompts/list", "prompts"),
+];
+
+pub fn run(args: &InspectArgs) -> Result<(), String> {
+    let client = args.server.connect()?;
+    let mut summary = json!({
+        "serverInfo": client.server_info(),
+        "capabilities": client.capabilities(),
+    });
+    for (capability, method, field) in LISTS {
+        summary[field] = Value::Array(list(&client, capability, method, field)?);
+    }
+    let output = if args.json {
+        serde_json::to_string_pretty(&summary).unwrap()
+    } else {
+        format_summary(&summary)
+    };
+    // Ignoring write errors, when the output is piped to a command that exits early
+    let _ = writeln!(io::stdout(), "{}", output.trim_end());
+    Ok(())
+}
+
+/// Items of a list, empty when the server lacks the capability.
+fn list(
+    client: &Client,
+    capability: &str,
+    method: &str,
+    field: &str,
+) -> Result<Vec<Value>, String> {
+    if client.capabilities().get(capability).is_none() {
+        return Ok(Vec::new());
+    }
+    client
+        .list(method, field)
+        .map_err(|e| format!("{} failed: {}", method, e.message))
+}
+
+fn format_summary(summary: &Value) -> String {
+    let mut out = String::new();
+    let info = &summary["serverInfo"];
+    let _ = writeln!(
+        out,
+        "{} {}",
+        info["name"].as_str().unwrap_or("(unnamed server)"),
+        info["version"].as_str().unwrap_or_default()
+    );
+    let capabilities = summary["capabilities"]
+        .as_object()
+        .map(|capabilities| {
+            capabilities
+                .iter()
+                .map(|(name, options)| match flags(options) {
+                    flags if flags.is_empty() => name.clone(),
+                    flags => format!("{} ({})", name, flags.join(", ")),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    let _ = writeln!(out, "Capabilities: {}", capabilities);
+
+    section(&mut out, "Tools", &summary["tools"], |tool| {
+        let hints: Vec<String> = flags(&tool["annotations"])
+            .into_iter()
+            .filter_map(|hint| hint.strip_suffix("Hint").map(str::to_string))
+            .collect();
+        let hints = match hints.is_empty() {
+            true => String::new(),
+            false => format!(" [{}]", hints.join(", ")),
+        };
+        (
+            format!("{}{}", text(&tool["name"]), hints),
+            text(&tool["description"]),
+        )
+    });
+    section(&mut out, "Resources", &summary["resources"], |resource| {
+        let mut title = text(&resource["uri"]);
+        if let Some(mime_type) = resource["mimeType"].as_str() {
+            let _ = write!(title, " ({})", mime_type);
+        }
+        let description = [&resource["name"], &resource["description"]]
+            .iter()
+            .filter_map(|text| text.as_str())
+            .collect::<Vec<_>>()
+            .join(": ");
+        (title, description)
+    });
+    section(&mut out, "Prompts", &summary["prompts"], |prompt| {
+        let arguments: Vec<String> = prompt["arguments"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|argument| match argument["required"] == json!(true) {
+                true => text(&argument["name"]),
+                false => format!("{}?", text(&argument["name"])),
+            })
+            .collect();
+        (
+            format!("{}({})", text(&prompt["name"]), arguments.join(", ")),
+            text(&prompt["description"]),
+        )
+    });
+    out
+}
+
+/// Writes a titled list, each item on a line followed by the first line of its description.
+fn section(
+    out: &mut String,
+    title: &str,
+    items: &Value,
+    item: impl Fn(&Value) -> (String, String),
+) {
+    let items = items.as_array().map(Vec::as_slice).unwrap_or_default();
+    let _ = writeln!(out, "\n{} ({}):", title, items.len());
+    for value in items {
+        let (heading, description) = item(value);
+        let _ = writeln!(out, "  {}", heading);
+        if let Some(line) = description.lines().next().filter(|line| !line.is_empty()) {
+            let _ = writeln!(out, "      {}", line);
+        }
+    }
+}
+
+/// Names of the options set to `true`, e.g. `listChanged`.
+fn flags(options: &Value) -> Vec<String> {
+    options
+        .as_object()
+        .map(Map::iter)
+        .into_iter()
+        .flatten()
+        .filter(|(_, value)| **value == json!(true))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
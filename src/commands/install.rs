@@ -0,0 +1,145 @@
+//! `install`: adds this server to the `mcpServers` of `claude_desktop_config.json`, started with
+//! the absolute path of the binary and the flags given before the subcommand, so that setting up
+//! a new machine doesn't mean editing the file by hand.
+
+use crate::cli::{Cli, InstallArgs};
+use directories::BaseDirs;
+use mcp_rs_test::config::Transport;
+use serde_json::{json, Map, Value};
+use std::{
+    env, fs,
+    path::{self, Path, PathBuf},
+};
+
+pub fn run(cli: &Cli, args: &InstallArgs) -> Result<(), String> {
+    let transport = cli.config()?.transport;
+    if transport != Transport::Stdio {
+        return Err(format!(
+            "Claude Desktop starts its servers over stdio, not {}",
+            transport
+        ));
+    }
+    let mut entry = stdio_entry(cli, &args.args)?;
+    let env = environment(&args.env)?;
+    if !env.is_empty() {
+        entry["env"] = Value::Object(env);
+    }
+    if args.print {
+        let config = json!({ "mcpServers": { &args.name: entry } });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&config).unwrap_or_default()
+        );
+        return Ok(());
+    }
+    let path = match &args.file {
+        Some(path) => path.clone(),
+        None => claude_desktop_config()?,
+    };
+    let replaced = patch(&path, &args.name, entry)?;
+    let action = if replaced { "Updated" } else { "Added" };
+    eprintln!("{} {} in {}", action, args.name, path.display());
+    eprintln!("Restart Claude Desktop for it to start the server");
+    Ok(())
+}
+
+/// `command` and `args` starting this server over stdio with the flags of `cli` and `extra`
+/// arguments, paths made absolute since the client doesn't start it from the current directory.
+pub(super) fn stdio_entry(cli: &Cli, extra: &[String]) -> Result<Value, String> {
+    let binary = env::current_exe()
+        .map_err(|e| format!("cannot find the path of the server binary: {}", e))?;
+    let mut args = Vec::new();
+    if let Some(config) = &cli.config {
+        args.extend(["--config".to_string(), absolute(config)?]);
+    }
+    for root in &cli.roots {
+        args.extend(["--root".to_string(), absolute(root)?]);
+    }
+    for openapi in &cli.openapi {
+        args.extend(["--openapi".to_string(), absolute(openapi)?]);
+    }
+    if let Some(level) = cli.log_level {
+        args.extend(["--log-level".to_string(), level.to_string().to_lowercase()]);
+    }
+    if let Some(file) = &cli.log_file {
+        args.extend(["--log-file".to_string(), absolute(file)?]);
+    }
+    let switches = [
+        (cli.allow_writes, "--allow-writes"),
+        (cli.allow_commands, "--allow-commands"),
+        (cli.dry_run, "--dry-run"),
+        (cli.read_only, "--read-only"),
+        (cli.validate_outgoing, "--validate-outgoing"),
+        (cli.wire_log, "--wire-log"),
+    ];
+    args.extend(
+        switches
+            .into_iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, flag)| flag.to_string()),
+    );
+    args.extend(extra.iter().cloned());
+    Ok(json!({ "command": binary.to_string_lossy(), "args": args }))
+}
+
+fn absolute(path: &Path) -> Result<String, String> {
+    path::absolute(path)
+        .map(|path| path.to_string_lossy().into_owned())
+        .map_err(|e| format!("cannot make {} absolute: {}", path.display(), e))
+}
+
+/// The `NAME=value` pairs given with `--env`.
+pub(super) fn environment(pairs: &[String]) -> Result<Map<String, Value>, String> {
+    pairs
+        .iter()
+        .map(|pair| {
+            let (name, value) = pair
+                .split_once('=')
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| format!("invalid variable '{}', expected `NAME=value`", pair))?;
+            Ok((name.to_string(), json!(value)))
+        })
+        .collect()
+}
+
+/// `claude_desktop_config.json` of the current user: in `~/Library/Application Support/Claude`
+/// on macOS, `%APPDATA%\Claude` on Windows and `~/.config/Claude` elsewhere.
+fn claude_desktop_config() -> Result<PathBuf, String> {
+    let dirs = BaseDirs::new().ok_or("cannot find the home directory")?;
+    Ok(dirs
+        .config_dir()
+        .join("Claude")
+        .join("claude_desktop_config.json"))
+}
+
+/// Sets `mcpServers.<name>` of the JSON file at `path`, keeping the rest of it, and creates the
+/// file if there's none. True if an entry of that name was replaced. The file is replaced at
+/// once, so that a failure leaves it as it was.
+fn patch(path: &Path, name: &str, entry: Value) -> Result<bool, String> {
+    let mut config = match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map_err(|e| format!("invalid JSON in {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => json!({}),
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e)),
+    };
+    let Some(config_object) = config.as_object_mut() else {
+        return Err(format!("{} isn't a JSON object", path.display()));
+    };
+    let servers = config_object
+        .entry("mcpServers")
+        .or_insert_with(|| json!({}));
+    let Some(servers) = servers.as_object_mut() else {
+        return Err(format!("mcpServers of {} isn't an object", path.display()));
+    };
+    let replaced = servers.insert(name.to_string(), entry).is_some();
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    let text = serde_json::to_string_pretty(&config).unwrap_or_default() + "\n";
+    fs::write(&temporary, text)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    Ok(replaced)
+}
@@ -0,0 +1,419 @@
+//! `validate-transcript`: checks a transcript recorded with `--record` against the rules of
+//! JSON-RPC and MCP: the envelope of every message, request ids used once and answered once, the
+//! order of the handshake, the negotiated protocol version and the MCP schema of what the server
+//! sent. A transcript attached to a bug report then says what went wrong before it is replayed.
+
+use crate::cli::ValidateTranscriptArgs;
+use mcp_rs_test::{
+    protocol::ProtocolVersion,
+    schema,
+    transcript::{self, Direction, Entry},
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+pub fn run(args: &ValidateTranscriptArgs) -> Result<(), String> {
+    let entries = transcript::read_numbered(&args.file)
+        .map_err(|e| format!("cannot read {}: {}", args.file.display(), e))?;
+    let entries: Vec<(usize, Entry)> = entries
+        .into_iter()
+        .filter(|(_, entry)| args.session.is_none_or(|s| s == entry.session))
+        .collect();
+    let violations = check(&entries);
+    for violation in &violations {
+        println!("{}:{}", args.file.display(), violation);
+    }
+    match violations.len() {
+        0 => {
+            eprintln!(
+                "{} messages of {} follow the protocol",
+                entries.len(),
+                args.file.display()
+            );
+            Ok(())
+        }
+        1 => Err(format!("1 violation in {}", args.file.display())),
+        count => Err(format!("{} violations in {}", count, args.file.display())),
+    }
+}
+
+/// A message of a transcript breaking a rule of the protocol.
+pub struct Violation {
+    /// Line of the transcript, counting from 1
+    pub line: usize,
+    pub session: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: session {}: {}",
+            self.line, self.session, self.message
+        )
+    }
+}
+
+/// The violations of the numbered `entries` of a transcript, in the order of their lines.
+pub fn check(entries: &[(usize, Entry)]) -> Vec<Violation> {
+    let mut sessions: BTreeMap<u64, Session> = BTreeMap::new();
+    let mut violations = Vec::new();
+    for (line, entry) in entries {
+        let session = sessions.entry(entry.session).or_default();
+        let mut report = |message: String| {
+            violations.push(Violation {
+                line: *line,
+                session: entry.session,
+                message,
+            })
+        };
+        session.entry(*line, entry.direction, &entry.message, &mut report);
+    }
+    for (number, session) in sessions {
+        for (side, requests) in [("client", session.client), ("server", session.server)] {
+            for (id, (line, method)) in requests.pending {
+                violations.push(Violation {
+                    line,
+                    session: number,
+                    message: format!("{} request {} ({}) is never answered", side, id, method),
+                });
+            }
+        }
+    }
+    violations.sort_by_key(|violation| violation.line);
+    violations
+}
+
+/// Requests sent by one side of a session.
+#[derive(Default)]
+struct Requests {
+    /// Line and method of the requests waiting for their response, by id as JSON
+    pending: HashMap<String, (usize, String)>,
+    /// Every id used, which MCP forbids using again in the session
+    used: HashSet<String>,
+    /// Ids whose response was seen
+    answered: HashSet<String>,
+    /// Ids of the requests cancelled before their response was seen
+    cancelled: HashSet<String>,
+}
+
+/// What the messages of a session so far tell of it.
+#[derive(Default)]
+struct Session {
+    client: Requests,
+    server: Requests,
+    /// Protocol version the client's initialize asked for, once it was sent
+    requested: Option<Option<String>>,
+    /// Whether the server answered the initialize request
+    initialized: bool,
+    /// Whether the client sent `notifications/initialized`
+    ready: bool,
+    /// Version the handshake settled on
+    version: Option<ProtocolVersion>,
+}
+
+const REQUEST_FIELDS: &[&str] = &["jsonrpc", "id", "method", "params"];
+const NOTIFICATION_FIELDS: &[&str] = &["jsonrpc", "method", "params"];
+const RESPONSE_FIELDS: &[&str] = &["jsonrpc", "id", "result", "error"];
+const ERROR_FIELDS: &[&str] = &["code", "message", "data"];
+
+impl Session {
+    fn entry(
+        &mut self,
+        line: usize,
+        direction: Direction,
+        message: &Value,
+        report: &mut impl FnMut(String),
+    ) {
+        match message {
+            Value::String(_) => report("the line isn't a JSON object".to_string()),
+            Value::Array(batch) => {
+                if batch.is_empty() {
+                    report("empty batch".to_string());
+                }
+                if self
+                    .version
+                    .is_some_and(|version| version >= ProtocolVersion::V2025_06_18)
+                {
+                    report("batches were removed in protocol version 2025-06-18".to_string());
+                }
+                for message in batch {
+                    self.message(line, direction, message, report);
+                }
+            }
+            message => self.message(line, direction, message, report),
+        }
+    }
+
+    fn message(
+        &mut self,
+        line: usize,
+        direction: Direction,
+        message: &Value,
+        report: &mut impl FnMut(String),
+    ) {
+        let Some(object) = message.as_object() else {
+            report(format!("{} isn't a JSON-RPC message", message));
+            return;
+        };
+        if object.get("jsonrpc") != Some(&Value::from("2.0")) {
+            report("`jsonrpc` isn't \"2.0\"".to_string());
+        }
+        if let Some(method) = object.get("method") {
+            let Some(method) = method.as_str() else {
+                report(format!("method {} isn't a string", method));
+                return;
+            };
+            if let Some(params) = object.get("params").filter(|params| !params.is_object()) {
+                report(format!("params of {} aren't an object: {}", method, params));
+            }
+            match object.get("id") {
+                Some(id) => {
+                    unknown_fields(object, REQUEST_FIELDS, "", report);
+                    self.request(line, direction, id, method, object, report);
+                }
+                None => {
+                    unknown_fields(object, NOTIFICATION_FIELDS, "", report);
+                    self.notification(direction, method, message, report);
+                }
+            }
+        } else if ["id", "result", "error"]
+            .iter()
+            .any(|field| object.contains_key(*field))
+        {
+            unknown_fields(object, RESPONSE_FIELDS, "", report);
+            self.response(direction, object, report);
+        } else {
+            report("neither a request, a notification nor a response".to_string());
+        }
+    }
+
+    fn request(
+        &mut self,
+        line: usize,
+        direction: Direction,
+        id: &Value,
+        method: &str,
+        request: &Map<String, Value>,
+        report: &mut impl FnMut(String),
+    ) {
+        if !valid_id(id) {
+            report(format!("invalid id {} of {}", id, method));
+        }
+        let requests = match direction {
+            Direction::In => &mut self.client,
+            Direction::Out => &mut self.server,
+        };
+        let key = id.to_string();
+        if !requests.used.insert(key.clone()) {
+            report(format!("id {} of {} was already used", id, method));
+        }
+        requests.pending.insert(key, (line, method.to_string()));
+
+        match (direction, method) {
+            (Direction::In, "initialize") => {
+                if self.requested.is_some() {
+                    report("initialize sent again".to_string());
+                }
+                let version = request["params"]["protocolVersion"].as_str();
+                if version.is_none() {
+                    report("initialize without a protocolVersion".to_string());
+                }
+                self.requested = Some(version.map(str::to_string));
+            }
+            (_, "ping") => {}
+            (Direction::In, method) if !self.initialized => {
+                report(format!("{} sent before the initialize response", method));
+            }
+            (Direction::In, method) if !self.ready => {
+                report(format!("{} sent before notifications/initialized", method));
+            }
+            (Direction::Out, method) if !self.ready => {
+                report(format!(
+                    "server sent {} before notifications/initialized",
+                    method
+                ));
+            }
+            (Direction::Out, "elicitation/create")
+                if self
+                    .version
+                    .is_some_and(|version| !version.supports_elicitation()) =>
+            {
+                report(format!(
+                    "elicitation/create sent in protocol version {}",
+                    self.version.unwrap_or(ProtocolVersion::LATEST)
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    fn notification(
+        &mut self,
+        direction: Direction,
+        method: &str,
+        notification: &Value,
+        report: &mut impl FnMut(String),
+    ) {
+        let sender = match direction {
+            Direction::In => &mut self.client,
+            Direction::Out => &mut self.server,
+        };
+        match method {
+            "notifications/cancelled" => {
+                let request_id = &notification["params"]["requestId"];
+                let key = request_id.to_string();
+                match sender.pending.remove(&key) {
+                    Some((_, method)) if method == "initialize" => {
+                        report("initialize cannot be cancelled".to_string());
+                    }
+                    Some(_) => {
+                        sender.cancelled.insert(key);
+                    }
+                    // Cancelling a request that was just answered is a race the receiver ignores
+                    None if sender.answered.contains(&key) => {}
+                    None => report(format!("cancels unknown request {}", request_id)),
+                }
+            }
+            "notifications/initialized" if direction == Direction::In => {
+                if !self.initialized {
+                    report("notifications/initialized sent before the initialize response".into());
+                } else if self.ready {
+                    report("notifications/initialized sent again".to_string());
+                }
+                self.ready = true;
+            }
+            _ => {}
+        }
+        if direction == Direction::Out {
+            for (pointer, error) in schema::notification_violations(notification) {
+                report(schema_violation(method, &pointer, &error));
+            }
+        }
+    }
+
+    fn response(
+        &mut self,
+        direction: Direction,
+        response: &Map<String, Value>,
+        report: &mut impl FnMut(String),
+    ) {
+        let id = response.get("id").unwrap_or(&Value::Null);
+        let (result, error) = (response.get("result"), response.get("error"));
+        if result.is_some() == error.is_some() {
+            report(format!(
+                "response {} has not exactly one of result and error",
+                id
+            ));
+        }
+        if let Some(error) = error {
+            match error.as_object() {
+                Some(object) => {
+                    if !object.get("code").is_some_and(Value::is_i64) {
+                        report(format!("error of response {} has no integer code", id));
+                    }
+                    if !object.get("message").is_some_and(Value::is_string) {
+                        report(format!("error of response {} has no message", id));
+                    }
+                    unknown_fields(object, ERROR_FIELDS, " of the error", report);
+                }
+                None => report(format!("error of response {} isn't an object", id)),
+            }
+        }
+        // Errors about messages whose id couldn't be read, such as parse errors, have none
+        if id.is_null() {
+            if error.is_none() {
+                report("response without an id".to_string());
+            }
+            return;
+        }
+        let requests = match direction {
+            Direction::In => &mut self.server,
+            Direction::Out => &mut self.client,
+        };
+        let key = id.to_string();
+        let Some((_, method)) = requests.pending.remove(&key) else {
+            if requests.answered.contains(&key) {
+                report(format!("request {} answered again", id));
+            } else if !requests.cancelled.contains(&key) {
+                report(format!("response to unknown request {}", id));
+            }
+            return;
+        };
+        requests.answered.insert(key);
+        let (Direction::Out, Some(result)) = (direction, result) else {
+            return;
+        };
+        if method == "initialize" {
+            self.initialize_result(result, report);
+        }
+        for (pointer, error) in schema::result_violations(&method, result) {
+            report(schema_violation(
+                &format!("{} result", method),
+                &pointer,
+                &error,
+            ));
+        }
+        if method == "tools/call"
+            && result.get("structuredContent").is_some()
+            && self
+                .version
+                .is_some_and(|version| !version.supports_structured_output())
+        {
+            report(format!(
+                "tools/call result has structuredContent in protocol version {}",
+                self.version.unwrap_or(ProtocolVersion::LATEST)
+            ));
+        }
+    }
+
+    /// Checks the version the server answered to the initialize request.
+    fn initialize_result(&mut self, result: &Value, report: &mut impl FnMut(String)) {
+        self.initialized = true;
+        let Some(answered) = result["protocolVersion"].as_str() else {
+            report("initialize result without a protocolVersion".to_string());
+            return;
+        };
+        self.version = ProtocolVersion::parse(answered);
+        if self.version.is_none() {
+            report(format!("unknown protocol version {} negotiated", answered));
+        }
+        if let Some(Some(requested)) = &self.requested {
+            // A server speaking the version the client asks for must answer with it
+            if requested != answered && ProtocolVersion::parse(requested).is_some() {
+                report(format!(
+                    "server answered protocol version {} to a client asking for {}, \
+                     which it supports",
+                    answered, requested
+                ));
+            }
+        }
+    }
+}
+
+/// Whether `id` is a string or an integer.
+fn valid_id(id: &Value) -> bool {
+    id.is_string() || id.is_i64() || id.is_u64()
+}
+
+fn unknown_fields(
+    object: &Map<String, Value>,
+    known: &[&str],
+    of: &str,
+    report: &mut impl FnMut(String),
+) {
+    for key in object.keys().filter(|key| !known.contains(&key.as_str())) {
+        report(format!("unknown field `{}`{}", key, of));
+    }
+}
+
+fn schema_violation(what: &str, pointer: &str, error: &str) -> String {
+    match pointer {
+        "" => format!("{} violates the MCP schema: {}", what, error),
+        pointer => format!("{} violates the MCP schema at {}: {}", what, pointer, error),
+    }
+}
@@ -0,0 +1,77 @@
+//! Subcommands run instead of the server.
+
+mod bench;
+mod client;
+pub mod config;
+mod doctor;
+mod export;
+mod health;
+mod inspect;
+mod install;
+mod lint;
+mod repl;
+mod replay;
+mod schema;
+
+use crate::cli::{Cli, Command, ServerArgs};
+use mcp_rs_test::{client::Client, config::DownstreamServer};
+use std::time::Duration;
+
+/// Runs a subcommand, returning the exit code of the process.
+pub fn run(cli: &Cli, command: &Command) -> i32 {
+    let result = match command {
+        Command::Client(args) => client::run(args),
+        Command::Inspect(args) => inspect::run(args),
+        Command::Replay(args) => replay::run(cli, args),
+        Command::ValidateTranscript(args) => lint::run(args),
+        Command::Repl(args) => repl::run(cli, args),
+        Command::Doctor(args) => doctor::run(cli, args),
+        Command::Bench(args) => bench::run(cli, args),
+        Command::Config(args) => config::run(cli, args),
+        Command::Schema(args) => schema::run(cli, args),
+        Command::Install(args) => install::run(cli, args),
+        Command::ExportConfig(args) => export::run(cli, args),
+    };
+    exit_code(result)
+}
+
+/// Runs `--health-check`, returning the exit code of the process.
+pub fn health_check(cli: &Cli) -> i32 {
+    exit_code(health::run(cli))
+}
+
+fn exit_code(result: Result<(), String>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+impl ServerArgs {
+    /// Connects to the server, as a URL or a command line.
+    pub fn connect(&self) -> Result<Client, String> {
+        let (first, rest) = self.server.split_first().ok_or("no server given")?;
+        let mut server = DownstreamServer::default();
+        if first.starts_with("http://") || first.starts_with("https://") {
+            if !rest.is_empty() {
+                return Err("unexpected arguments after the server URL".to_string());
+            }
+            server.url = Some(first.clone());
+            for header in &self.headers {
+                let (name, value) = header.split_once(':').ok_or_else(|| {
+                    format!("invalid header '{}', expected `Name: value`", header)
+                })?;
+                server
+                    .headers
+                    .insert(name.trim().to_string(), value.trim().to_string());
+            }
+        } else {
+            server.command = Some(first.clone());
+            server.args = rest.to_vec();
+        }
+        Client::connect(&server, Duration::from_secs(self.timeout))
+    }
+}
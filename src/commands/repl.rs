@@ -0,0 +1,156 @@
+//! `repl`: reads shorthand commands, sends the matching requests to a server built from the
+//! configuration and prints the responses.
+
+use crate::cli::{Cli, ReplArgs};
+use mcp_rs_test::{plugins, proxy::Proxy, testing::MockClient};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+/// How long the answer to a `raw` message is waited for.
+const RAW_ANSWER_DELAY: Duration = Duration::from_millis(200);
+
+const HELP: &str = "\
+list tools|resources|prompts   list what the server offers
+call <tool> [arguments]        call a tool with a JSON object of arguments
+read <uri>                     read a resource
+prompt <name> [arguments]      get a prompt with a JSON object of arguments
+subscribe <uri>                subscribe to the updates of a resource
+unsubscribe <uri>              stop receiving them
+ping                           ping the server
+<method> [params]              send any request, e.g. resources/list {\"cursor\": \"...\"}
+raw <message>                  send a line as is
+help                           show this help
+quit                           leave (or end the input)";
+
+pub fn run(cli: &Cli, args: &ReplArgs) -> Result<(), String> {
+    let config = cli.config()?;
+    let proxy = Proxy::connect(&config);
+    let server = Arc::new(crate::build_server(
+        &config,
+        &proxy,
+        plugins::load_tools(&config),
+    ));
+    proxy.attach(&server);
+    let mut client = MockClient::connect(server).with_timeout(Duration::from_secs(args.timeout));
+    let result = client.initialize()?;
+    println!(
+        "Connected to {} {}, type `help` for the commands",
+        result["serverInfo"]["name"].as_str().unwrap_or_default(),
+        result["serverInfo"]["version"].as_str().unwrap_or_default()
+    );
+
+    let interactive = io::stdin().is_terminal();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("mcp> ");
+            let _ = io::stdout().flush();
+        }
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line {
+            "quit" | "exit" => break,
+            "help" => println!("{}", HELP),
+            _ => match execute(&mut client, line) {
+                Ok(Some(output)) => println!("{}", pretty(&output)),
+                Ok(None) => {}
+                Err(e) => println!("error: {}", e),
+            },
+        }
+        for message in client.drain() {
+            print_message(&message);
+        }
+    }
+    Ok(())
+}
+
+/// Runs one command, returning what to print.
+fn execute(client: &mut MockClient, line: &str) -> Result<Option<Value>, String> {
+    let (command, rest) = split_word(line);
+    let (method, params) = match command {
+        "raw" => {
+            client.send_raw(rest)?;
+            // Gives the server a moment to answer, the answer being printed with the other
+            // received messages
+            let _ = client.expect_silence(RAW_ANSWER_DELAY);
+            return Ok(None);
+        }
+        "list" => match rest {
+            "tools" | "resources" | "prompts" => (format!("{}/list", rest), None),
+            _ => return Err("usage: list tools|resources|prompts".to_string()),
+        },
+        "call" | "prompt" => {
+            let (name, arguments) = split_word(rest);
+            if name.is_empty() {
+                return Err(format!("usage: {} <name> [arguments]", command));
+            }
+            let arguments = parse_json(arguments)?.unwrap_or_else(|| json!({}));
+            let method = match command {
+                "call" => "tools/call",
+                _ => "prompts/get",
+            };
+            (
+                method.to_string(),
+                Some(json!({ "name": name, "arguments": arguments })),
+            )
+        }
+        "read" | "subscribe" | "unsubscribe" => {
+            if rest.is_empty() {
+                return Err(format!("usage: {} <uri>", command));
+            }
+            let method = match command {
+                "read" => "resources/read".to_string(),
+                _ => format!("resources/{}", command),
+            };
+            (method, Some(json!({ "uri": rest })))
+        }
+        "ping" => ("ping".to_string(), None),
+        method if method.contains('/') => (method.to_string(), parse_json(rest)?),
+        _ => return Err(format!("unknown command '{}', type `help`", command)),
+    };
+    let response = client.request(&method, params)?;
+    Ok(Some(match response.get("result") {
+        Some(result) => result.clone(),
+        None => json!({ "error": response["error"] }),
+    }))
+}
+
+fn print_message(message: &Value) {
+    match message["method"].as_str() {
+        Some(method) => match message.get("params") {
+            Some(params) => println!("<- {} {}", method, params),
+            None => println!("<- {}", method),
+        },
+        None => println!("<- {}", pretty(message)),
+    }
+}
+
+/// First word of `text` and the rest, trimmed.
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+fn parse_json(text: &str) -> Result<Option<Value>, String> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(text)
+        .map(Some)
+        .map_err(|e| format!("invalid JSON: {}", e))
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
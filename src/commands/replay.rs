@@ -0,0 +1,209 @@
+//! `replay`: serves the client messages of a recorded transcript again, printing what the
+//! server sends back, or with `--diff` comparing its responses with the recorded ones, so that
+//! behavior changing between two versions of the server shows.
+
+use super::config::escape;
+use crate::cli::{Cli, ReplayArgs};
+use mcp_rs_test::{
+    plugins,
+    proxy::Proxy,
+    transcript::{self, Direction},
+    transport::ChannelWriter,
+};
+use serde_json::Value;
+use std::{collections::BTreeMap, io, sync::mpsc};
+
+/// Messages of a recorded session.
+#[derive(Default)]
+struct Session {
+    /// Lines the client sent
+    lines: Vec<String>,
+    /// Method of each request of the client, by id as JSON
+    methods: BTreeMap<String, String>,
+    /// Responses the server sent, as recorded, by id as JSON in the order they were sent
+    responses: Vec<(String, Value)>,
+}
+
+pub fn run(cli: &Cli, args: &ReplayArgs) -> Result<(), String> {
+    let entries = transcript::read(&args.file)
+        .map_err(|e| format!("cannot read {}: {}", args.file.display(), e))?;
+    let mut sessions: BTreeMap<u64, Session> = BTreeMap::new();
+    for entry in entries {
+        if args.session.is_some_and(|s| s != entry.session) {
+            continue;
+        }
+        match entry.direction {
+            Direction::In => {
+                let session = sessions.entry(entry.session).or_default();
+                for message in messages(&entry.message) {
+                    if let (Some(id), Some(method)) =
+                        (message.get("id"), message["method"].as_str())
+                    {
+                        session.methods.insert(id.to_string(), method.to_string());
+                    }
+                }
+                session.lines.push(entry.line());
+            }
+            Direction::Out => {
+                if let Some(session) = sessions.get_mut(&entry.session) {
+                    session.responses.extend(responses(&entry.message));
+                }
+            }
+        }
+    }
+    if sessions.is_empty() {
+        return Err("no client messages to replay".to_string());
+    }
+
+    let config = cli.config()?;
+    let proxy = Proxy::connect(&config);
+    let server = crate::build_server(&config, &proxy, plugins::load_tools(&config));
+    let (mut compared, mut changed) = (0, 0);
+    // Sessions are replayed one after the other, each message once the previous one is handled
+    for (number, session) in sessions {
+        eprintln!(
+            "Replaying session {} ({} messages)",
+            number,
+            session.lines.len()
+        );
+        if !args.diff {
+            server
+                .serve(session.lines, io::stdout())
+                .map_err(|e| format!("cannot write to stdout: {}", e))?;
+            continue;
+        }
+        let (output_tx, output) = mpsc::channel();
+        server
+            .serve(session.lines, ChannelWriter::new(output_tx))
+            .map_err(|e| format!("cannot replay session {}: {}", number, e))?;
+        let mut replayed: BTreeMap<String, Value> = output
+            .iter()
+            .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+            .flat_map(|message| responses(&message))
+            .collect();
+        for (id, recorded) in &session.responses {
+            let method = session.methods.get(id).map_or("?", String::as_str);
+            compared += 1;
+            let Some(response) = replayed.remove(id) else {
+                println!(
+                    "session {}: response {} ({}) is missing",
+                    number, id, method
+                );
+                changed += 1;
+                continue;
+            };
+            let mut found = Vec::new();
+            differences(String::new(), recorded, &response, &mut found);
+            found.retain(|(pointer, _)| !ignored(pointer, &args.ignore));
+            if !found.is_empty() {
+                println!("session {}: response {} ({}) differs", number, id, method);
+                for (pointer, difference) in found {
+                    println!("  {}: {}", pointer, difference);
+                }
+                changed += 1;
+            }
+        }
+        for id in replayed.keys() {
+            let method = session.methods.get(id).map_or("?", String::as_str);
+            println!(
+                "session {}: response {} ({}) wasn't recorded",
+                number, id, method
+            );
+            changed += 1;
+        }
+    }
+    match (args.diff, changed) {
+        (false, _) => Ok(()),
+        (true, 0) => {
+            eprintln!("The {} recorded responses are unchanged", compared);
+            Ok(())
+        }
+        (true, changed) => Err(format!("{} responses changed", changed)),
+    }
+}
+
+/// The messages of a line: those of a batch, or the message itself.
+fn messages(message: &Value) -> Vec<&Value> {
+    match message {
+        Value::Array(batch) => batch.iter().collect(),
+        message => vec![message],
+    }
+}
+
+/// The responses of a line, by id as JSON.
+fn responses(message: &Value) -> Vec<(String, Value)> {
+    messages(message)
+        .into_iter()
+        .filter(|message| message.get("method").is_none())
+        .filter_map(|message| Some((message.get("id")?.to_string(), message.clone())))
+        .collect()
+}
+
+/// Adds the JSON pointers under `pointer` where `replayed` isn't as `recorded`, with what each
+/// was.
+fn differences(
+    pointer: String,
+    recorded: &Value,
+    replayed: &Value,
+    found: &mut Vec<(String, String)>,
+) {
+    match (recorded, replayed) {
+        (Value::Object(recorded), Value::Object(replayed)) => {
+            for (key, value) in recorded {
+                let member = format!("{}/{}", pointer, escape(key));
+                match replayed.get(key) {
+                    Some(other) => differences(member, value, other, found),
+                    None => found.push((member, format!("{} is no longer there", brief(value)))),
+                }
+            }
+            for (key, value) in replayed {
+                if !recorded.contains_key(key) {
+                    let member = format!("{}/{}", pointer, escape(key));
+                    found.push((member, format!("{} is new", brief(value))));
+                }
+            }
+        }
+        (Value::Array(recorded), Value::Array(replayed)) => {
+            for index in 0..recorded.len().max(replayed.len()) {
+                let item = format!("{}/{}", pointer, index);
+                match (recorded.get(index), replayed.get(index)) {
+                    (Some(value), Some(other)) => differences(item, value, other, found),
+                    (Some(value), None) => {
+                        found.push((item, format!("{} is no longer there", brief(value))))
+                    }
+                    (None, Some(other)) => found.push((item, format!("{} is new", brief(other)))),
+                    (None, None) => {}
+                }
+            }
+        }
+        (recorded, replayed) if recorded == replayed => {}
+        (recorded, replayed) => {
+            let pointer = if pointer.is_empty() {
+                "/".to_string()
+            } else {
+                pointer
+            };
+            let change = format!("{} became {}", brief(recorded), brief(replayed));
+            found.push((pointer, change));
+        }
+    }
+}
+
+/// `value` as JSON, cut short past a few dozen characters.
+fn brief(value: &Value) -> String {
+    const MAX_CHARS: usize = 60;
+    let text = value.to_string();
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// Whether `pointer` is one of the `ignored` ones or under one of them.
+fn ignored(pointer: &str, ignored: &[String]) -> bool {
+    ignored.iter().any(|ignored| {
+        pointer
+            .strip_prefix(ignored.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
@@ -0,0 +1,68 @@
+//! `schema export`: writes what the server registers, as a client lists it, to a JSON manifest:
+//! the tools with their input schemas and annotations, the prompts with their arguments and the
+//! resource templates, for generating documentation or caching in clients.
+
+use super::doctor::list;
+use crate::cli::{Cli, SchemaArgs, SchemaCommand};
+use mcp_rs_test::{plugins, protocol::ProtocolVersion, proxy::Proxy, testing::MockClient};
+use serde_json::{json, Value};
+use std::{fs, sync::Arc};
+
+pub fn run(cli: &Cli, args: &SchemaArgs) -> Result<(), String> {
+    let SchemaCommand::Export { output } = &args.command;
+    let config = cli.config()?;
+    let proxy = Proxy::connect(&config);
+    let server = Arc::new(crate::build_server(
+        &config,
+        &proxy,
+        plugins::load_tools(&config),
+    ));
+    proxy.attach(&server);
+    let mut client = MockClient::connect(server);
+    // The latest version, whose definitions have every field
+    let response = client.request(
+        "initialize",
+        Some(json!({
+            "protocolVersion": ProtocolVersion::LATEST.as_str(),
+            "capabilities": {},
+            "clientInfo": { "name": "mcp_rs_test-schema", "version": env!("CARGO_PKG_VERSION") },
+        })),
+    )?;
+    let Some(initialize) = response.get("result") else {
+        return Err(format!("initialize failed: {}", response["error"]));
+    };
+    client.notify("notifications/initialized", None)?;
+
+    let mut manifest = json!({
+        "protocolVersion": initialize["protocolVersion"],
+        "serverInfo": initialize["serverInfo"],
+    });
+    for (capability, method, field) in [
+        ("tools", "tools/list", "tools"),
+        ("prompts", "prompts/list", "prompts"),
+        ("resources", "resources/templates/list", "resourceTemplates"),
+    ] {
+        let items = match initialize["capabilities"].get(capability) {
+            Some(_) => list(&mut client, method, field)?,
+            None => Vec::new(),
+        };
+        manifest[field] = Value::Array(items);
+    }
+    let _ = client.close();
+
+    let text = serde_json::to_string_pretty(&manifest).unwrap_or_default() + "\n";
+    match output {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+            eprintln!(
+                "Wrote {} tools, {} prompts and {} resource templates to {}",
+                manifest["tools"].as_array().map_or(0, Vec::len),
+                manifest["prompts"].as_array().map_or(0, Vec::len),
+                manifest["resourceTemplates"].as_array().map_or(0, Vec::len),
+                path.display()
+            );
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
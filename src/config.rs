@@ -0,0 +1,650 @@
+use crate::{
+    codec::Limits,
+    mcp::{LoggingLevel, Role},
+    policy::{ApprovalMethod, Permission, SamplingApproval, SchemeSettings, ScopeGrant},
+    prompts::PromptArgument,
+    ratelimit::RateLimit,
+};
+use directories::ProjectDirs;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Transport the server listens on.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Newline-delimited JSON over stdin/stdout
+    #[default]
+    Stdio,
+    /// Streamable HTTP on `listen`
+    Http,
+    /// WebSocket on `listen`
+    Ws,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Transport, String> {
+        match s {
+            "stdio" => Ok(Transport::Stdio),
+            "http" => Ok(Transport::Http),
+            "ws" => Ok(Transport::Ws),
+            _ => Err(format!(
+                "unknown transport '{}' (expected stdio, http or ws)",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Stdio => write!(f, "stdio"),
+            Transport::Http => write!(f, "http"),
+            Transport::Ws => write!(f, "ws"),
+        }
+    }
+}
+
+/// Where the log is written.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// The log file only
+    #[default]
+    File,
+    /// Standard error only, which stdio hosts usually show in their log panel
+    Stderr,
+    /// Both the log file and standard error
+    Both,
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogTarget, String> {
+        match s {
+            "file" => Ok(LogTarget::File),
+            "stderr" => Ok(LogTarget::Stderr),
+            "both" => Ok(LogTarget::Both),
+            _ => Err(format!(
+                "unknown log target '{}' (expected stderr, file or both)",
+                s
+            )),
+        }
+    }
+}
+
+/// Server settings, read from the JSON file given with `--config`.
+/// Every field is optional in the file; command-line flags take precedence over it.
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+// Unknown keys are ignored when reading the file, but reported by `config validate`
+#[cfg_attr(feature = "config-schema", schemars(deny_unknown_fields))]
+pub struct Config {
+    /// Transport to serve on
+    pub transport: Transport,
+    /// Address the network transports listen on
+    pub listen: String,
+    /// Where the log is written
+    pub log_target: LogTarget,
+    /// File the log is written to, defaults to [`default_log_file`]
+    pub log_file: PathBuf,
+    /// Minimum level of the logged messages (off, error, warn, info, debug, trace)
+    pub log_level: String,
+    /// Size in bytes after which the log file is rotated, 0 to never rotate
+    pub log_max_size: u64,
+    /// Number of rotated log files kept next to the current one
+    pub log_max_files: usize,
+    /// Directories the filesystem tools and `file://` resources are restricted to
+    pub roots: Vec<PathBuf>,
+    /// Directory whose files are exposed as `static://` resources
+    pub resource_dir: Option<PathBuf>,
+    /// Bytes of file contents kept in memory for the `file://` and `static://` resources read
+    /// again; 0 disables the cache
+    pub resource_cache_size: usize,
+    /// Milliseconds during which the changes of a watched file are gathered into one
+    /// notification, 0 to notify every change
+    pub resource_debounce_ms: u64,
+    /// Items per page of the list methods, all of them in one page when 0
+    pub page_size: usize,
+    /// Bytes of contents per page of `resources/read`, larger resources being read in pages;
+    /// all of them at once when 0
+    pub resource_page_size: usize,
+    /// Seconds after which a pagination cursor is refused, 0 to keep cursors valid
+    pub cursor_ttl_secs: u64,
+    /// Key signing the pagination cursors, so that they stay valid across restarts and servers;
+    /// drawn at random when the server starts when absent
+    pub cursor_secret: Option<String>,
+    /// Offers the tools that modify files (`fs_write`, `fs_edit`)
+    pub allow_writes: bool,
+    /// Offers the `run_command` tool
+    pub allow_commands: bool,
+    /// Tools that modify files or run commands describe what they would do instead of doing
+    /// it, like with their `dryRun` argument
+    pub dry_run: bool,
+    /// Only offers the read-only tools, whatever `allow_writes` and `allow_commands` say, and
+    /// runs database statements read-only
+    pub read_only: bool,
+    /// Executables `run_command` may start (names found on the `PATH`, or paths); any when absent
+    pub command_allowlist: Option<Vec<String>>,
+    /// Executables `run_command` refuses to start, even if allowlisted
+    pub command_denylist: Vec<String>,
+    /// Seconds after which a command is killed, 0 for no limit
+    pub command_timeout_secs: u64,
+    /// Maximum number of bytes kept from each of stdout and stderr of a command
+    pub command_max_output: usize,
+    /// Environment variables of the server passed to `run_command`, by name or pattern (`*`
+    /// matching anything); all of them when absent, none when empty
+    pub command_env_allowlist: Option<Vec<String>>,
+    /// Environment variables set for `run_command`, over those passed
+    pub command_env: BTreeMap<String, String>,
+    /// Working directory of `run_command`, under the roots; commands may choose their own
+    /// under the roots when absent
+    pub command_cwd: Option<PathBuf>,
+    /// Seconds of CPU time a `run_command` process may use before being killed, 0 for no
+    /// limit (Unix only)
+    pub command_cpu_secs: u64,
+    /// User `run_command` runs its commands as, which needs the server to run as root (Unix
+    /// only)
+    pub command_user: Option<String>,
+    /// Hosts `http_fetch` may contact, including their subdomains; any when absent
+    pub fetch_allowed_domains: Option<Vec<String>>,
+    /// Seconds after which an `http_fetch` request fails, 0 for no limit
+    pub fetch_timeout_secs: u64,
+    /// Maximum number of bytes of a response body returned by `http_fetch`
+    pub fetch_max_size: u64,
+    /// SQLite database files available to `sqlite_query`, by name
+    pub sqlite_databases: BTreeMap<String, PathBuf>,
+    /// Opens the SQLite databases read-only and refuses modifying statements
+    pub sqlite_read_only: bool,
+    /// Postgres and MySQL databases available to `db_query`, by name, as connection strings
+    /// (`postgres://...`, `mysql://...`)
+    pub databases: BTreeMap<String, String>,
+    /// Runs the statements of `db_query` in read-only transactions, always rolled back
+    pub database_read_only: bool,
+    /// Maximum number of connections opened to each database
+    pub database_pool_size: u32,
+    /// Seconds after which a `db_query` statement fails, 0 for no limit
+    pub database_timeout_secs: u64,
+    /// Offers the `redis` tool
+    pub allow_redis: bool,
+    /// Redis instances available to the `redis` tool, by name, as `redis://` URLs
+    pub redis_instances: BTreeMap<String, String>,
+    /// Patterns (`*` matching anything) of the keys the `redis` tool may get and list
+    pub redis_read_keys: Vec<String>,
+    /// Patterns of the keys the `redis` tool may set and delete; none by default
+    pub redis_write_keys: Vec<String>,
+    /// Offers the Docker tools (`docker_list_containers`, `docker_stop`, ...)
+    pub allow_docker: bool,
+    /// Docker engine the Docker tools operate, e.g. `unix:///var/run/docker.sock` or
+    /// `tcp://host:2375`; `DOCKER_HOST`, else the local engine, when absent
+    pub docker_host: Option<String>,
+    /// Offers the tools reading and writing the clipboard of the desktop (`clipboard_get`,
+    /// `clipboard_set`)
+    pub allow_clipboard: bool,
+    /// OpenAI-compatible embeddings endpoint (`.../v1/embeddings`) `semantic_search` embeds the
+    /// files and queries with; the tool is only offered when it is set
+    pub embedding_url: Option<String>,
+    /// Model asked of `embedding_url`
+    pub embedding_model: String,
+    /// Headers sent to `embedding_url`, e.g. `Authorization`
+    pub embedding_headers: BTreeMap<String, String>,
+    /// File the embeddings of the files are kept in between runs, so that only the files that
+    /// changed are embedded again; they are kept in memory when absent
+    pub semantic_index: Option<PathBuf>,
+    /// JSON file the entries of the memory tools (`memory_set`, ...) are kept in; the tools are
+    /// only offered when it is set
+    pub memory_file: Option<PathBuf>,
+    /// Offers the tools listing and inspecting processes (`list_processes`, `process_info`)
+    pub allow_process_info: bool,
+    /// Patterns (`*` matching anything, case-insensitive) of the environment variables whose
+    /// value is hidden from the `env://` resource
+    pub env_redact: Vec<String>,
+    /// Directory of the WebAssembly plugins (`*.wasm`) loaded as tools at startup
+    pub plugin_dir: Option<PathBuf>,
+    /// JSON or YAML files defining tools as command templates
+    pub tool_manifests: Vec<PathBuf>,
+    /// OpenAPI 3 documents whose operations are exposed as tools
+    pub openapi: Vec<OpenApiSpec>,
+    /// Directory of the Rhai scripts (`*.rhai`) loaded as tools at startup
+    pub script_dir: Option<PathBuf>,
+    /// Prompts whose messages are Handlebars templates
+    pub prompts: Vec<PromptTemplate>,
+    /// JSON or YAML files defining prompts as Handlebars templates
+    pub prompt_templates: Vec<PathBuf>,
+    /// Time after which a plugin or script call is aborted
+    pub plugin_timeout_secs: u64,
+    /// Maximum memory a plugin instance can use, in bytes
+    pub plugin_max_memory: usize,
+    /// Reloads the plugins, scripts and tool manifests when their files change, while the
+    /// server is running
+    pub plugin_reload: bool,
+    /// Downstream MCP servers whose tools, resources and prompts are re-exposed, by name
+    pub downstream_servers: BTreeMap<String, DownstreamServer>,
+    /// Time after which a request forwarded to a downstream server fails
+    pub downstream_timeout_secs: u64,
+    /// Separator between the namespace of a downstream server and the names of its tools and
+    /// prompts, e.g. `.` for `github.search_issues`
+    pub downstream_separator: String,
+    /// Names of the tools offered to clients; all registered tools when absent
+    pub enabled_tools: Option<Vec<String>>,
+    /// Permissions of the tools (`allow`, `deny` or `ask`), by name or pattern (`*` matching
+    /// anything), see [`crate::policy`]
+    pub tool_permissions: BTreeMap<String, Permission>,
+    /// Scopes of the HTTP clients, by the API key they send as `Authorization: Bearer <key>` or
+    /// `X-API-Key`; when set, requests without a known key are refused
+    pub api_keys: BTreeMap<String, Vec<String>>,
+    /// Tools, resources and prompts each scope gives access to; sessions with scopes only see
+    /// those granted by one of them, see [`crate::policy::ScopeGrant`]
+    pub scopes: BTreeMap<String, ScopeGrant>,
+    /// Which URIs `resources/read` reads, by lowercase scheme (`file`, `http`, `data`, ...),
+    /// see [`crate::policy::SchemeSettings`]; the schemes not listed keep their default
+    pub resource_schemes: BTreeMap<String, SchemeSettings>,
+    /// How the calls of the tools marked `ask` are approved (`none`, `console`, `elicitation` or
+    /// `webhook`)
+    pub approval: ApprovalMethod,
+    /// URL the calls to approve are posted to with `approval = "webhook"`
+    pub approval_webhook: Option<String>,
+    /// Time after which the approval webhook is given up on, refusing the call; 0 for no limit
+    pub approval_timeout_secs: u64,
+    /// How the sampling requests of the handlers are approved (`auto`, `console` or `deny`)
+    pub sampling_approval: SamplingApproval,
+    /// Sampling requests sent to the client of a session at most; 0 for no limit
+    pub sampling_max_requests: u64,
+    /// Tokens the sampling requests sent to the client of a session may ask for in total, by
+    /// their `maxTokens`; 0 for no limit
+    pub sampling_max_tokens: u64,
+    /// File every tool call is recorded in, see [`crate::audit`]
+    pub audit_log: Option<PathBuf>,
+    /// Also record the arguments of the calls, redacted like the wire log, besides their hash
+    pub audit_arguments: bool,
+    /// Answer `audit/query` with the records of the audit log
+    pub audit_query: bool,
+    /// `serverInfo.name` sent to clients, defaults to the crate name
+    pub server_name: Option<String>,
+    /// `serverInfo.version` sent to clients, defaults to the crate version
+    pub server_version: Option<String>,
+    /// Check every result and notification sent against the MCP schema and log the violations
+    pub validate_outgoing: bool,
+    /// Answer `metrics/dump` and serve `/metrics` on the HTTP transport
+    pub metrics: bool,
+    /// Answer `server/methods` with the methods the server serves and the schema of their params
+    pub introspection: bool,
+    /// Request methods served, by pattern (`*` matching anything, e.g. `tools/*`); all of them
+    /// when absent. `initialize` is always served
+    pub allowed_methods: Option<Vec<String>>,
+    /// Request methods answered with METHOD_NOT_FOUND, by pattern, even if allowed
+    pub denied_methods: Vec<String>,
+    /// Address of a separate HTTP listener serving `/metrics`, whatever the transport
+    pub metrics_listen: Option<String>,
+    /// OTLP/HTTP endpoint of the OpenTelemetry collector the request spans and metrics are
+    /// exported to, such as `http://localhost:4318`, when built with `otel`
+    pub otel_endpoint: Option<String>,
+    /// Seconds between two exports to `otel_endpoint`
+    pub otel_interval_secs: u64,
+    /// Serve the HTTP transport over HTTP/2 as well as HTTP/1.1, when built with `http2`
+    pub http2: bool,
+    /// Advertise the HTTP and WebSocket transports on the local network over mDNS, as
+    /// `_mcp._tcp` services, when built with `mdns`
+    pub mdns: bool,
+    /// Compress the HTTP responses of the clients accepting gzip or deflate
+    pub http_compression: bool,
+    /// Log every message received and sent under the `wire` target, redacted
+    pub wire_log: bool,
+    /// Patterns (`*` matching anything, case-insensitive) of the keys whose value is hidden
+    /// from the wire log
+    pub wire_log_redact: Vec<String>,
+    /// Length in bytes above which strings are replaced by their length in the wire log
+    pub wire_log_max_string: usize,
+    /// Time after which a request still being served is logged as slow, and again every as
+    /// long; 0 disables the warning
+    pub slow_request_secs: u64,
+    /// Also warn the client about slow requests with `notifications/message`
+    pub slow_request_notify: bool,
+    /// Largest message accepted from a client, in bytes; larger ones are discarded unread
+    pub max_message_size: usize,
+    /// Notifications written before flushing the output when several are waiting; responses
+    /// and the last waiting message are always flushed right away
+    pub notification_batch: usize,
+    /// Tool calls running at once across sessions, further calls waiting for their turn;
+    /// 0 for no limit
+    pub max_concurrent_tool_calls: usize,
+    /// Calls of a tool running at once, by tool name, on top of `max_concurrent_tool_calls`
+    pub tool_concurrency: BTreeMap<String, usize>,
+    /// Seconds a tool call may take, unless the tool sets its own timeout; 0 for no limit
+    pub tool_timeout_secs: u64,
+    /// Seconds the calls of a tool may take, by tool name, overriding the tool's own timeout and
+    /// `tool_timeout_secs` (0 for no limit)
+    pub tool_timeouts: BTreeMap<String, u64>,
+    /// Bytes of text a tool result may hold, the rest being cut; 0 for no limit
+    pub tool_max_output: usize,
+    /// Keeps the full text of the cut results as `output://` resources the results link to
+    pub tool_output_spill: bool,
+    /// Requests allowed to each session, by method pattern (`*` matching anything), like
+    /// `{"tools/call": "10/min"}`; requests over a limit get an error telling when to retry
+    pub rate_limits: BTreeMap<String, RateLimit>,
+    /// Level of the log events sent to the clients as `notifications/message` until they call
+    /// `logging/setLevel`; none are sent by default
+    pub client_log_level: Option<LoggingLevel>,
+    /// Log events sent to each client, the others being dropped
+    pub client_log_rate: RateLimit,
+    /// File where HTTP sessions are saved, so that clients can go on with them after a restart
+    pub state_file: Option<PathBuf>,
+    /// Directory the panics of the handlers are written to as crash reports, one JSON file each
+    pub crash_report_dir: Option<PathBuf>,
+    /// DSN of the Sentry project the panics of the handlers are reported to
+    pub crash_report_dsn: Option<String>,
+    /// SQLite database keeping the events of the HTTP SSE streams so that they can be resumed
+    /// after a restart; they are kept in memory when absent
+    pub event_store: Option<PathBuf>,
+    /// Time the events of the HTTP SSE streams are kept, in seconds
+    pub event_retention_secs: u64,
+    /// Events of the HTTP SSE streams kept by session, the older ones being dropped
+    pub event_retention_max: usize,
+    /// Seconds a response of a resumable (HTTP) session is kept to be replayed to a retried
+    /// request, with the same id, method and params, instead of serving it again; 0 to serve
+    /// every request
+    pub idempotency_ttl_secs: u64,
+    /// Responses kept for replay by session, the older ones being dropped
+    pub idempotency_max_responses: usize,
+    /// Seconds the requests in flight get to finish when the server is stopped, before they
+    /// are cancelled
+    pub shutdown_grace_secs: u64,
+    /// Seconds the server waits for the client to answer one of its requests (sampling, roots,
+    /// ...); 0 for no limit
+    pub client_request_timeout_secs: u64,
+    /// Times a request to the client is sent again when it isn't answered in time
+    pub client_request_retries: u32,
+    /// Milliseconds before the first retry of a request to the client, doubled for each retry
+    /// after it
+    pub client_request_backoff_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            transport: Transport::Stdio,
+            listen: "127.0.0.1:8080".to_string(),
+            log_target: LogTarget::File,
+            log_file: default_log_file(),
+            log_level: "info".to_string(),
+            log_max_size: 10 * 1024 * 1024,
+            log_max_files: 5,
+            roots: Vec::new(),
+            resource_dir: None,
+            resource_cache_size: 64 * 1024 * 1024,
+            resource_debounce_ms: 100,
+            page_size: 0,
+            resource_page_size: 0,
+            cursor_ttl_secs: 3600,
+            cursor_secret: None,
+            allow_writes: false,
+            allow_commands: false,
+            dry_run: false,
+            read_only: false,
+            command_allowlist: None,
+            command_denylist: Vec::new(),
+            command_timeout_secs: 30,
+            command_max_output: 1024 * 1024,
+            command_env_allowlist: None,
+            command_env: BTreeMap::new(),
+            command_cwd: None,
+            command_cpu_secs: 0,
+            command_user: None,
+            fetch_allowed_domains: None,
+            fetch_timeout_secs: 30,
+            fetch_max_size: 1024 * 1024,
+            sqlite_databases: BTreeMap::new(),
+            embedding_url: None,
+            embedding_model: "nomic-embed-text".to_string(),
+            embedding_headers: BTreeMap::new(),
+            semantic_index: None,
+            memory_file: None,
+            sqlite_read_only: true,
+            databases: BTreeMap::new(),
+            database_read_only: true,
+            database_pool_size: 5,
+            database_timeout_secs: 30,
+            allow_redis: false,
+            redis_instances: BTreeMap::new(),
+            redis_read_keys: vec!["*".to_string()],
+            redis_write_keys: Vec::new(),
+            allow_docker: false,
+            docker_host: None,
+            allow_clipboard: false,
+            allow_process_info: false,
+            env_redact: [
+                "*_TOKEN",
+                "*_KEY",
+                "*_SECRET",
+                "*PASSWORD*",
+                "*CREDENTIALS*",
+            ]
+            .map(String::from)
+            .to_vec(),
+            plugin_dir: None,
+            tool_manifests: Vec::new(),
+            openapi: Vec::new(),
+            script_dir: None,
+            prompts: Vec::new(),
+            prompt_templates: Vec::new(),
+            plugin_timeout_secs: 30,
+            plugin_max_memory: 64 * 1024 * 1024,
+            plugin_reload: false,
+            downstream_servers: BTreeMap::new(),
+            downstream_timeout_secs: 60,
+            downstream_separator: "__".to_string(),
+            enabled_tools: None,
+            tool_permissions: BTreeMap::new(),
+            api_keys: BTreeMap::new(),
+            scopes: BTreeMap::new(),
+            resource_schemes: BTreeMap::new(),
+            approval: ApprovalMethod::None,
+            approval_webhook: None,
+            approval_timeout_secs: 60,
+            sampling_approval: SamplingApproval::Auto,
+            sampling_max_requests: 0,
+            sampling_max_tokens: 0,
+            audit_log: None,
+            audit_arguments: false,
+            audit_query: false,
+            server_name: None,
+            server_version: None,
+            validate_outgoing: false,
+            metrics: false,
+            introspection: false,
+            allowed_methods: None,
+            denied_methods: Vec::new(),
+            metrics_listen: None,
+            otel_endpoint: None,
+            otel_interval_secs: 10,
+            http2: false,
+            mdns: false,
+            http_compression: true,
+            wire_log: false,
+            wire_log_redact: [
+                "token",
+                "*_token",
+                "*accesstoken",
+                "*refreshtoken",
+                "*authtoken",
+                "*password*",
+                "*secret*",
+                "*api_key*",
+                "*apikey*",
+                "authorization",
+                "cookie",
+            ]
+            .map(String::from)
+            .to_vec(),
+            wire_log_max_string: 1024,
+            slow_request_secs: 30,
+            slow_request_notify: false,
+            max_message_size: Limits::default().max_size,
+            notification_batch: 1,
+            max_concurrent_tool_calls: 0,
+            tool_concurrency: BTreeMap::new(),
+            tool_timeout_secs: 0,
+            tool_timeouts: BTreeMap::new(),
+            tool_max_output: 256 * 1024,
+            tool_output_spill: false,
+            rate_limits: BTreeMap::new(),
+            client_log_level: None,
+            client_log_rate: RateLimit {
+                count: 20,
+                period: Duration::from_secs(1),
+            },
+            state_file: None,
+            crash_report_dir: None,
+            crash_report_dsn: None,
+            event_store: None,
+            event_retention_secs: 60 * 60,
+            event_retention_max: 1000,
+            idempotency_ttl_secs: 5 * 60,
+            idempotency_max_responses: 100,
+            shutdown_grace_secs: 5,
+            client_request_timeout_secs: 60,
+            client_request_retries: 0,
+            client_request_backoff_ms: 500,
+        }
+    }
+}
+
+/// An OpenAPI 3 document whose operations are exposed as tools.
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "config-schema", schemars(deny_unknown_fields))]
+pub struct OpenApiSpec {
+    /// JSON or YAML file of the document
+    pub path: PathBuf,
+    /// URL the operation paths are relative to, the first of the document's `servers` by default
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Headers sent with every request, e.g. `Authorization`
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// A prompt whose messages are Handlebars templates, rendered with the arguments of `prompts/get`.
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct PromptTemplate {
+    pub name: String,
+    /// Name to display, if different from `name`
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    pub messages: Vec<MessageTemplate>,
+}
+
+/// A message of a [`PromptTemplate`]: a text, a file embedded as a resource or an image, exactly
+/// one of `text`, `resource` and `image` being set.
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct MessageTemplate {
+    #[serde(default = "user_role")]
+    pub role: Role,
+    /// Template of the text of the message
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Template of the path (or `file://` URI) of a file under the roots, embedded in the message
+    #[serde(default)]
+    pub resource: Option<String>,
+    /// Template of the path (or `file://` URI) of an image file under the roots
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+fn user_role() -> Role {
+    Role::User
+}
+
+/// A downstream MCP server, reached over stdio (`command`) or Streamable HTTP (`url`).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct DownstreamServer {
+    /// Executable of a stdio server
+    pub command: Option<String>,
+    /// Arguments of `command`
+    pub args: Vec<String>,
+    /// Environment variables set for `command`
+    pub env: BTreeMap<String, String>,
+    /// Endpoint of a Streamable HTTP server
+    pub url: Option<String>,
+    /// Headers sent with every HTTP request, e.g. `Authorization`
+    pub headers: BTreeMap<String, String>,
+    /// Prefix of the names of its tools and prompts; the name of the server when absent
+    pub namespace: Option<String>,
+    /// Prefixes the URIs of its resources with `<namespace>+` as well
+    pub namespace_resources: bool,
+}
+
+impl Config {
+    /// Watches a configuration file, calling `on_change` from a background thread whenever its
+    /// modification time changes. The file is polled every `interval`.
+    pub fn watch<F>(path: &Path, interval: Duration, on_change: F) -> JoinHandle<()>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let path = path.to_path_buf();
+        let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+        thread::spawn(move || {
+            let mut last = modified(&path);
+            loop {
+                thread::sleep(interval);
+                let current = modified(&path);
+                if current != last {
+                    last = current;
+                    tracing::info!("Config file {} changed", path.display());
+                    on_change();
+                }
+            }
+        })
+    }
+
+    /// Reads a configuration file.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("invalid config file {}: {}", path.display(), e))
+    }
+
+    /// JSON schema of the configuration file, which unlike reading it rejects unknown keys.
+    #[cfg(feature = "config-schema")]
+    pub fn schema() -> serde_json::Value {
+        schemars::schema_for!(Config).to_value()
+    }
+}
+
+/// Default location of the log file: `mcp_rs_test.log` in the platform cache directory
+/// (e.g. `~/.cache/mcp_rs_test` on Linux, `%LOCALAPPDATA%\mcp_rs_test\cache` on Windows),
+/// or in the temporary directory when no home directory can be found.
+pub fn default_log_file() -> PathBuf {
+    let dir = match ProjectDirs::from("", "", "mcp_rs_test") {
+        Some(dirs) => dirs.cache_dir().to_path_buf(),
+        None => env::temp_dir(),
+    };
+    dir.join("mcp_rs_test.log")
+}
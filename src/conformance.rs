@@ -0,0 +1,325 @@
+//! Protocol conformance checks: scripted exchanges driving a [`Server`] through an in-process
+//! connection and asserting on its responses, covering the handshake, list pagination,
+//! cancellation and error reporting.
+//!
+//! Embedders can run them against their own server, typically from a test:
+//!
+//! ```no_run
+//! # use mcp_rs_test::{conformance, server::Server};
+//! conformance::run(Server::new).unwrap();
+//! ```
+
+use crate::{
+    jsonrpc::{
+        ERROR_CODE_INVALID_PARAMS, ERROR_CODE_INVALID_REQUEST, ERROR_CODE_METHOD_NOT_FOUND,
+        ERROR_CODE_PARSE_ERROR,
+    },
+    server::Server,
+    testing::{check_envelope, MockClient},
+};
+use serde_json::{json, Value};
+use std::{collections::HashSet, sync::Arc};
+
+/// Pages after which a paginated list is considered endless.
+const MAX_PAGES: usize = 100;
+
+/// Items of a paginated list, following `nextCursor`.
+fn list(client: &mut MockClient, method: &str, field: &str) -> Result<Vec<Value>, String> {
+    let mut items = Vec::new();
+    let mut params = None;
+    for _ in 0..MAX_PAGES {
+        let page = result(&client.request(method, params)?)?;
+        let page_items = page[field]
+            .as_array()
+            .ok_or_else(|| format!("{} result has no `{}` array", method, field))?;
+        items.extend(page_items.iter().cloned());
+        match &page["nextCursor"] {
+            Value::Null => return Ok(items),
+            Value::String(cursor) => params = Some(json!({ "cursor": cursor })),
+            cursor => {
+                return Err(format!(
+                    "{} returned a non-string cursor {}",
+                    method, cursor
+                ))
+            }
+        }
+    }
+    Err(format!("{} returned more than {} pages", method, MAX_PAGES))
+}
+
+/// A scripted exchange with a freshly started session.
+pub struct Check {
+    pub name: &'static str,
+    pub run: fn(&mut MockClient) -> Result<(), String>,
+}
+
+/// The checks of the library.
+pub fn checks() -> Vec<Check> {
+    vec![
+        Check {
+            name: "initialize result",
+            run: initialize_result,
+        },
+        Check {
+            name: "initialized notification is not answered",
+            run: initialized_not_answered,
+        },
+        Check {
+            name: "requests before initialize are rejected",
+            run: request_before_initialize,
+        },
+        Check {
+            name: "a second initialize is rejected",
+            run: second_initialize,
+        },
+        Check {
+            name: "a malformed initialize is rejected",
+            run: malformed_initialize,
+        },
+        Check {
+            name: "an unsupported protocol version is answered with another one",
+            run: unsupported_version,
+        },
+        Check {
+            name: "ping",
+            run: ping,
+        },
+        Check {
+            name: "request ids are echoed",
+            run: ids_echoed,
+        },
+        Check {
+            name: "lists follow pagination",
+            run: pagination,
+        },
+        Check {
+            name: "cancelling an unknown request is ignored",
+            run: cancel_unknown,
+        },
+        Check {
+            name: "cancelling a completed request is ignored",
+            run: cancel_completed,
+        },
+        Check {
+            name: "unknown method",
+            run: unknown_method,
+        },
+        Check {
+            name: "unknown tool",
+            run: unknown_tool,
+        },
+        Check {
+            name: "parse error",
+            run: parse_error,
+        },
+    ]
+}
+
+/// Runs every check on its own session with a server built by `make_server`. Returns the
+/// failures, one per line, as the error.
+pub fn run(make_server: impl Fn() -> Server) -> Result<(), String> {
+    let failures: Vec<String> = checks()
+        .into_iter()
+        .filter_map(|check| {
+            let mut client = MockClient::connect(Arc::new(make_server()));
+            let outcome = (check.run)(&mut client).and_then(|()| client.close());
+            outcome.err().map(|e| format!("{}: {}", check.name, e))
+        })
+        .collect();
+    match failures.is_empty() {
+        true => Ok(()),
+        false => Err(failures.join("\n")),
+    }
+}
+
+fn result(response: &Value) -> Result<Value, String> {
+    match response.get("result") {
+        Some(result) => Ok(result.clone()),
+        None => Err(format!("expected a result, got {}", response["error"])),
+    }
+}
+
+/// Checks that `response` is an error with `code`.
+fn expect_error(response: &Value, code: i32) -> Result<(), String> {
+    match response["error"]["code"].as_i64() {
+        Some(actual) if actual == code as i64 => match response["error"]["message"].is_string() {
+            true => Ok(()),
+            false => Err(format!("error without a message: {}", response)),
+        },
+        _ => Err(format!("expected error code {}, got {}", code, response)),
+    }
+}
+
+/// Checks that the server is still answering, and that nothing was sent before the answer.
+fn expect_responsive(client: &mut MockClient) -> Result<(), String> {
+    let id = json!("responsive");
+    client.send(&json!({ "jsonrpc": "2.0", "id": id, "method": "ping" }))?;
+    let message = client.receive()?;
+    match message["id"] == id {
+        true => Ok(()),
+        false => Err(format!("expected the ping response, got {}", message)),
+    }
+}
+
+fn initialize_result(client: &mut MockClient) -> Result<(), String> {
+    let result = client.initialize()?;
+    if !result["protocolVersion"].is_string() {
+        return Err("no protocolVersion".to_string());
+    }
+    if !result["capabilities"].is_object() {
+        return Err("no capabilities object".to_string());
+    }
+    if !result["serverInfo"]["name"].is_string() || !result["serverInfo"]["version"].is_string() {
+        return Err("serverInfo must have a name and a version".to_string());
+    }
+    Ok(())
+}
+
+fn initialized_not_answered(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    expect_responsive(client)
+}
+
+fn request_before_initialize(client: &mut MockClient) -> Result<(), String> {
+    let response = client.request("tools/list", None)?;
+    expect_error(&response, ERROR_CODE_INVALID_REQUEST)?;
+    // ping is allowed at any time
+    result(&client.request("ping", None)?)?;
+    client.initialize()?;
+    Ok(())
+}
+
+fn second_initialize(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    let response = client.request(
+        "initialize",
+        Some(json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": {},
+            "clientInfo": { "name": "mock-client", "version": "0" },
+        })),
+    )?;
+    expect_error(&response, ERROR_CODE_INVALID_REQUEST)?;
+    expect_responsive(client)
+}
+
+fn malformed_initialize(client: &mut MockClient) -> Result<(), String> {
+    let response = client.request(
+        "initialize",
+        Some(
+            json!({ "capabilities": {}, "clientInfo": { "name": "mock-client", "version": "0" } }),
+        ),
+    )?;
+    expect_error(&response, ERROR_CODE_INVALID_PARAMS)?;
+    if response["error"]["data"]["pointer"] != "/protocolVersion" {
+        return Err(format!("error doesn't name protocolVersion: {}", response));
+    }
+    client.initialize()?;
+    Ok(())
+}
+
+fn unsupported_version(client: &mut MockClient) -> Result<(), String> {
+    let response = client.request(
+        "initialize",
+        Some(json!({
+            "protocolVersion": "1999-01-01",
+            "capabilities": {},
+            "clientInfo": { "name": "mock-client", "version": "0" },
+        })),
+    )?;
+    match &result(&response)?["protocolVersion"] {
+        Value::String(version) if version != "1999-01-01" => Ok(()),
+        version => Err(format!("expected a supported version, got {}", version)),
+    }
+}
+
+fn ping(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    let response = client.request("ping", None)?;
+    match result(&response)? == json!({}) {
+        true => Ok(()),
+        false => Err(format!("expected an empty result, got {}", response)),
+    }
+}
+
+fn ids_echoed(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    for id in [json!("a string id"), json!(0), json!(9007199254740991u64)] {
+        client.request_with_id(id, "ping", None)?;
+    }
+    Ok(())
+}
+
+fn pagination(client: &mut MockClient) -> Result<(), String> {
+    let capabilities = client.initialize()?["capabilities"].clone();
+    let lists = [
+        ("tools", "tools/list", "name"),
+        ("resources", "resources/list", "uri"),
+        ("prompts", "prompts/list", "name"),
+    ];
+    for (field, method, key) in lists {
+        if capabilities.get(field).is_none() {
+            continue;
+        }
+        let items = list(client, method, field)?;
+        let mut seen = HashSet::new();
+        for item in &items {
+            let key = item[key]
+                .as_str()
+                .ok_or_else(|| format!("{} item without a `{}`: {}", method, key, item))?;
+            if !seen.insert(key.to_string()) {
+                return Err(format!("{} lists {} twice", method, key));
+            }
+            if field == "tools" && item["inputSchema"]["type"] != "object" {
+                return Err(format!(
+                    "the inputSchema of {} is not an object schema",
+                    key
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cancel_unknown(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    client.notify(
+        "notifications/cancelled",
+        Some(json!({ "requestId": "never sent", "reason": "test" })),
+    )?;
+    expect_responsive(client)
+}
+
+fn cancel_completed(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    let response = client.request("ping", None)?;
+    client.notify(
+        "notifications/cancelled",
+        Some(json!({ "requestId": response["id"], "reason": "test" })),
+    )?;
+    expect_responsive(client)
+}
+
+fn unknown_method(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    let response = client.request("conformance/unknown", None)?;
+    expect_error(&response, ERROR_CODE_METHOD_NOT_FOUND)
+}
+
+fn unknown_tool(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    let response = client.request(
+        "tools/call",
+        Some(json!({ "name": "conformance unknown tool", "arguments": {} })),
+    )?;
+    expect_error(&response, ERROR_CODE_INVALID_PARAMS)
+}
+
+fn parse_error(client: &mut MockClient) -> Result<(), String> {
+    client.initialize()?;
+    client.send_raw("{\"jsonrpc\": \"2.0\", \"id\": ")?;
+    let response = client.receive()?;
+    check_envelope(&response)?;
+    expect_error(&response, ERROR_CODE_PARSE_ERROR)?;
+    expect_responsive(client)
+}
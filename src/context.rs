@@ -0,0 +1,325 @@
+use crate::config::Config;
+use crate::jsonrpc::{JsonRpcError, JsonRpcId, JsonRpcNotification, ERROR_CODE_INTERNAL_ERROR};
+use crate::mcp::{CreateMessageRequest, CreateMessageResult, ReadResourceResult};
+use crate::policy::{self, SamplingHook};
+use crate::protocol::ProtocolVersion;
+use crate::requests::{OutgoingRequests, RetryPolicy};
+use crate::state_bag::StateBag;
+use serde_json::{json, Value};
+use std::{
+    collections::hash_map::{DefaultHasher, RandomState},
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Key of the trace id in `_meta`, accepted from clients and sent with the notifications and
+/// downstream requests of the operation.
+pub const TRACE_ID_META: &str = "traceId";
+/// Longest trace id accepted from a client.
+const MAX_TRACE_ID_LENGTH: usize = 128;
+
+/// Flag shared between the dispatcher and a handler so a request can be cancelled
+/// by the client with `notifications/cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Marks the request as cancelled.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true once the client has cancelled the request.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Information negotiated with the client during `initialize`.
+#[derive(Clone, Debug, Default)]
+pub struct SessionInfo {
+    /// Protocol version requested by the client
+    pub protocol_version: Option<String>,
+    /// Protocol version agreed on, which decides the features the session gets
+    pub version: ProtocolVersion,
+    /// `clientInfo` sent by the client (name and version)
+    pub client_info: Option<Value>,
+    /// `capabilities` sent by the client
+    pub client_capabilities: Option<Value>,
+    /// True once `notifications/initialized` has been received
+    pub initialized: bool,
+    /// Scopes the client authenticated with, which limit the tools and resources it gets (see
+    /// [`crate::policy`]); None when it didn't authenticate
+    pub scopes: Option<Vec<String>>,
+}
+
+/// Handle used by handlers to push notifications to the client.
+pub type NotificationSender = Arc<dyn Fn(JsonRpcNotification) + Send + Sync>;
+
+/// Handle used by handlers to read the resources of the server, see
+/// [`RequestContext::read_resource`].
+pub type ResourceReader = Arc<
+    dyn Fn(&RequestContext, &str) -> Option<Result<ReadResourceResult, JsonRpcError>> + Send + Sync,
+>;
+
+/// Everything a handler may need to know about the request it is serving.
+pub struct RequestContext {
+    /// Identifier of the request being served
+    pub id: JsonRpcId,
+    /// Snapshot of the session state at the time the request was received
+    pub session: SessionInfo,
+    /// Progress token sent by the client in `params._meta.progressToken`, if any
+    pub progress_token: Option<Value>,
+    /// Trace id of the operation, see [`trace_id`]
+    pub trace_id: String,
+    /// Token flipped when the client cancels this request
+    pub cancellation: CancellationToken,
+    /// Server settings at the time the request was received
+    pub config: Arc<Config>,
+    /// When a tool call times out, see [`crate::tools::Tool::timeout`]
+    pub deadline: Option<Instant>,
+    notifier: NotificationSender,
+    /// Requests to the session's client, see [`RequestContext::send_request`]
+    requests: Option<Arc<OutgoingRequests>>,
+    /// Resources of the server, see [`RequestContext::read_resource`]
+    resources: Option<ResourceReader>,
+    /// State of the session, see [`RequestContext::session_state`]
+    session_state: Arc<StateBag>,
+    /// State shared by all the sessions, see [`RequestContext::shared_state`]
+    shared_state: Arc<StateBag>,
+    /// Approves the sampling requests, see [`RequestContext::create_message`]
+    sampling_hook: Option<SamplingHook>,
+}
+
+impl RequestContext {
+    pub fn new(
+        id: JsonRpcId,
+        session: SessionInfo,
+        progress_token: Option<Value>,
+        trace_id: String,
+        cancellation: CancellationToken,
+        config: Arc<Config>,
+        notifier: NotificationSender,
+    ) -> RequestContext {
+        RequestContext {
+            id,
+            session,
+            progress_token,
+            trace_id,
+            cancellation,
+            config,
+            deadline: None,
+            notifier,
+            requests: None,
+            resources: None,
+            session_state: Arc::default(),
+            shared_state: Arc::default(),
+            sampling_hook: None,
+        }
+    }
+
+    pub fn with_requests(mut self, requests: Arc<OutgoingRequests>) -> RequestContext {
+        self.requests = Some(requests);
+        self
+    }
+
+    pub fn with_resource_reader(mut self, resources: ResourceReader) -> RequestContext {
+        self.resources = Some(resources);
+        self
+    }
+
+    pub fn with_session_state(mut self, state: Arc<StateBag>) -> RequestContext {
+        self.session_state = state;
+        self
+    }
+
+    pub fn with_shared_state(mut self, state: Arc<StateBag>) -> RequestContext {
+        self.shared_state = state;
+        self
+    }
+
+    pub fn with_sampling_hook(mut self, hook: Option<SamplingHook>) -> RequestContext {
+        self.sampling_hook = hook;
+        self
+    }
+
+    /// The value of type `T` of the session serving the request, see
+    /// [`crate::server::Server::with_session_state`].
+    pub fn session_state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.session_state.get()
+    }
+
+    /// All the state of the session, for handlers that store values of their own in it.
+    pub fn session_state_bag(&self) -> &StateBag {
+        &self.session_state
+    }
+
+    /// The value of type `T` shared by all the sessions, see
+    /// [`crate::server::Server::with_shared_state`].
+    pub fn shared_state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.shared_state.get()
+    }
+
+    /// Reads `uri` as `resources/read` would, or returns None if no resource of the server has
+    /// that URI.
+    pub fn read_resource(&self, uri: &str) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        self.resources.as_ref().and_then(|read| read(self, uri))
+    }
+
+    /// `timeout` (none when zero) shortened to the time left before the deadline, for the
+    /// handlers that stop their work after a timeout of their own.
+    pub fn limit_timeout(&self, timeout: Duration) -> Duration {
+        limit_timeout(timeout, self.deadline)
+    }
+
+    /// Capabilities the client declared during `initialize`, if any.
+    pub fn client_capabilities(&self) -> Option<&Value> {
+        self.session.client_capabilities.as_ref()
+    }
+
+    /// Sends a notification to the client, tagged with the trace id in `params._meta`.
+    pub fn send_notification(&self, method: &str, params: Option<Value>) {
+        let mut params = params.unwrap_or_else(|| json!({}));
+        if params.is_object() {
+            params["_meta"][TRACE_ID_META] = json!(self.trace_id);
+        }
+        let params = Some(params);
+        (self.notifier)(JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+        });
+    }
+
+    /// Sends `notifications/progress` for the request if the client asked for them with a
+    /// progress token. `message` is left out for the sessions that don't know it, see
+    /// [`RequestContext::wants_progress_messages`].
+    pub fn send_progress(&self, progress: usize, total: Option<usize>, message: Option<&str>) {
+        let Some(token) = &self.progress_token else {
+            return;
+        };
+        let mut params = json!({ "progressToken": token, "progress": progress });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        if let Some(message) = message.filter(|_| self.session.version.supports_progress_message())
+        {
+            params["message"] = json!(message);
+        }
+        self.send_notification("notifications/progress", Some(params));
+    }
+
+    /// True if the client asked for progress notifications and they can carry a message, for
+    /// handlers streaming partial output through them.
+    pub fn wants_progress_messages(&self) -> bool {
+        self.progress_token.is_some() && self.session.version.supports_progress_message()
+    }
+
+    /// Sends a request to the client and waits for its result, up to
+    /// `client_request_timeout_secs` (and the deadline of the request being served) and until
+    /// the request being served is cancelled. Unanswered requests are sent again up to
+    /// `client_request_retries` times.
+    pub fn send_request(&self, method: &str, params: Option<Value>) -> Result<Value, JsonRpcError> {
+        let Some(requests) = &self.requests else {
+            return Err(JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                format!("Cannot send {} to the client", method),
+            ));
+        };
+        let policy = RetryPolicy::new(Duration::from_secs(self.config.client_request_timeout_secs))
+            .with_retries(
+                self.config.client_request_retries,
+                Duration::from_millis(self.config.client_request_backoff_ms),
+            );
+        requests.request(
+            method,
+            params,
+            &policy,
+            self.deadline,
+            Some(&self.cancellation),
+        )
+    }
+
+    /// Asks the client to sample a message with `sampling/createMessage`, once the request is
+    /// approved and fits in the session's budget (see [`crate::policy::check_sampling`]).
+    /// Refusals are INTERNAL_ERROR, with their reason.
+    pub fn create_message(
+        &self,
+        request: &CreateMessageRequest,
+    ) -> Result<CreateMessageResult, JsonRpcError> {
+        let supported = self
+            .session
+            .client_capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.get("sampling").is_some());
+        if !supported {
+            return Err(JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                "The client doesn't support sampling",
+            ));
+        }
+        policy::check_sampling(self.sampling_hook.as_ref(), self, request).map_err(|reason| {
+            tracing::info!("Sampling request refused: {}", reason);
+            JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                format!("Sampling refused: {}", reason),
+            )
+        })?;
+        let result = self.send_request("sampling/createMessage", Some(request.to_value()))?;
+        serde_json::from_value(result).map_err(|e| {
+            JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                format!("Invalid sampling/createMessage result: {}", e),
+            )
+        })
+    }
+}
+
+/// `timeout` (none when zero) shortened to the time left before `deadline`, if any.
+pub fn limit_timeout(timeout: Duration, deadline: Option<Instant>) -> Duration {
+    let Some(deadline) = deadline else {
+        return timeout;
+    };
+    // Not zero once the deadline passed, which would mean no limit
+    let left = deadline
+        .saturating_duration_since(Instant::now())
+        .max(Duration::from_millis(1));
+    match timeout {
+        Duration::ZERO => left,
+        timeout => timeout.min(left),
+    }
+}
+
+/// Trace id of a request: the one the client sent in `params._meta.traceId`, or a new one.
+pub fn trace_id(params: Option<&Value>) -> String {
+    trace_id_of_meta(params.and_then(|p| p.get("_meta")))
+}
+
+/// Trace id of a request whose `params._meta` is `meta`, see [`trace_id`].
+pub(crate) fn trace_id_of_meta(meta: Option<&Value>) -> String {
+    meta.and_then(|meta| meta[TRACE_ID_META].as_str())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_TRACE_ID_LENGTH)
+        .map(str::to_string)
+        .unwrap_or_else(random_id)
+}
+
+/// Random 128-bit identifier, as 32 hexadecimal digits.
+pub(crate) fn random_id() -> String {
+    let mut id = String::new();
+    for _ in 0..2 {
+        let mut hasher: DefaultHasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        id.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    id
+}
@@ -0,0 +1,248 @@
+//! Crash reports: the panics of the request handlers, with their backtrace, the request being
+//! served and the last messages of its session, redacted like the wire log. They are handed to
+//! the [`CrashReporter`]s of the server, which write them to a directory as bundles users can
+//! attach to bug reports ([`CrashDirectory`]), or send them to Sentry ([`SentryReporter`]).
+
+use crate::{context::random_id, mcp::Implementation, transcript::Direction, wirelog::WireLog};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    collections::VecDeque,
+    fs, panic,
+    path::PathBuf,
+    sync::{Mutex, Once},
+    thread,
+    time::Duration,
+};
+
+/// Messages of a session kept for its crash reports.
+const RECENT_MESSAGES: usize = 20;
+
+/// Size in bytes above which a message is kept as its length only.
+const MAX_RECENT_MESSAGE: usize = 64 * 1024;
+
+/// Time a crash report may take to reach Sentry.
+const SENTRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What went wrong while serving a request, and what led to it.
+#[derive(Serialize, Debug, Clone)]
+pub struct Crash {
+    /// When the panic was caught, in RFC 3339
+    pub time: String,
+    /// Panic message
+    pub message: String,
+    /// File, line and column of the panic
+    pub location: Option<String>,
+    /// Name of the thread that panicked
+    pub thread: Option<String>,
+    pub backtrace: String,
+    /// `serverInfo` of the server
+    pub server: Implementation,
+    pub session: Option<String>,
+    /// Method of the request being served
+    pub method: Option<String>,
+    pub request_id: Option<String>,
+    pub trace_id: Option<String>,
+    /// Last messages of the session, oldest first
+    pub recent_messages: Vec<RecentMessage>,
+}
+
+/// A message received or sent shortly before a crash.
+#[derive(Serialize, Debug, Clone)]
+pub struct RecentMessage {
+    pub direction: Direction,
+    /// The message, redacted, or a string telling its length if it wasn't JSON or was too large
+    pub message: Value,
+}
+
+/// Where the crashes go.
+pub trait CrashReporter: Send + Sync {
+    /// Reports `crash`, returning where it went (a path, an event id) for the log.
+    fn report(&self, crash: &Crash) -> Result<String, String>;
+}
+
+/// What the panic hook saw of the last panic of a thread.
+struct Panic {
+    location: Option<String>,
+    backtrace: String,
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<Panic>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook, once for the process, recording the location and backtrace of each
+/// panic for [`Crash::capture`] before running the hook that was there.
+pub fn capture_panics() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let panic = Panic {
+                location: info.location().map(ToString::to_string),
+                backtrace: Backtrace::force_capture().to_string(),
+            };
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some(panic));
+            previous(info);
+        }));
+    });
+}
+
+impl Crash {
+    /// The crash of the panic with `message` that the current thread just caught, with its
+    /// location and backtrace if [`capture_panics`] was called. The request and messages are
+    /// left for the caller to fill in.
+    pub fn capture(message: &str, server: Implementation) -> Crash {
+        let panic = LAST_PANIC.with(|last| last.borrow_mut().take());
+        let (location, backtrace) = match panic {
+            Some(panic) => (panic.location, panic.backtrace),
+            None => (None, String::new()),
+        };
+        Crash {
+            time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            message: message.to_string(),
+            location,
+            thread: thread::current().name().map(str::to_string),
+            backtrace,
+            server,
+            session: None,
+            method: None,
+            request_id: None,
+            trace_id: None,
+            recent_messages: Vec::new(),
+        }
+    }
+}
+
+/// The last messages of a session, as received and sent.
+#[derive(Default)]
+pub struct RecentMessages(Mutex<VecDeque<(Direction, String)>>);
+
+impl RecentMessages {
+    pub fn push(&self, direction: Direction, line: &str) {
+        let line = match line.len() > MAX_RECENT_MESSAGE {
+            true => format!("\"[{} bytes]\"", line.len()),
+            false => line.to_string(),
+        };
+        let mut messages = self.0.lock().unwrap();
+        if messages.len() == RECENT_MESSAGES {
+            messages.pop_front();
+        }
+        messages.push_back((direction, line));
+    }
+
+    /// The messages, oldest first, redacted by `redaction`.
+    pub fn redacted(&self, redaction: &WireLog) -> Vec<RecentMessage> {
+        let messages = self.0.lock().unwrap();
+        messages
+            .iter()
+            .map(|(direction, line)| RecentMessage {
+                direction: *direction,
+                message: match serde_json::from_str(line) {
+                    Ok(message) => redaction.redact(message),
+                    Err(_) => json!(format!("[{} bytes of invalid JSON]", line.len())),
+                },
+            })
+            .collect()
+    }
+}
+
+/// Writes each crash to a JSON file of its own in a directory, created if needed.
+pub struct CrashDirectory {
+    dir: PathBuf,
+}
+
+impl CrashDirectory {
+    pub fn new(dir: impl Into<PathBuf>) -> CrashDirectory {
+        CrashDirectory { dir: dir.into() }
+    }
+}
+
+impl CrashReporter for CrashDirectory {
+    fn report(&self, crash: &Crash) -> Result<String, String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("cannot create {}: {}", self.dir.display(), e))?;
+        let time = chrono::Utc::now().format("%Y%m%dT%H%M%S");
+        let path = self
+            .dir
+            .join(format!("crash-{}-{}.json", time, &random_id()[..8]));
+        let text = serde_json::to_string_pretty(crash).unwrap_or_default() + "\n";
+        fs::write(&path, text).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        Ok(path.display().to_string())
+    }
+}
+
+/// Sends each crash as an event to Sentry, or to a server speaking its protocol.
+pub struct SentryReporter {
+    /// Store endpoint of the project
+    url: String,
+    /// `X-Sentry-Auth` header
+    auth: String,
+}
+
+impl SentryReporter {
+    /// The reporter of a project with its DSN, `https://<public key>@<host>/<project id>`.
+    pub fn from_dsn(dsn: &str) -> Result<SentryReporter, String> {
+        let invalid = || format!("invalid Sentry DSN '{}'", dsn);
+        let (scheme, rest) = dsn.split_once("://").ok_or_else(invalid)?;
+        let (key, rest) = rest.split_once('@').ok_or_else(invalid)?;
+        let (host, project) = rest.rsplit_once('/').ok_or_else(invalid)?;
+        let key = key.split(':').next().unwrap_or_default();
+        if key.is_empty() || host.is_empty() || project.is_empty() {
+            return Err(invalid());
+        }
+        Ok(SentryReporter {
+            url: format!("{}://{}/api/{}/store/", scheme, host, project),
+            auth: format!(
+                "Sentry sentry_version=7, sentry_key={}, sentry_client={}/{}",
+                key,
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ),
+        })
+    }
+}
+
+impl CrashReporter for SentryReporter {
+    fn report(&self, crash: &Crash) -> Result<String, String> {
+        let event_id = random_id();
+        let event = json!({
+            "event_id": event_id,
+            "timestamp": crash.time,
+            "platform": "native",
+            "level": "fatal",
+            "logger": env!("CARGO_PKG_NAME"),
+            "release": format!("{}@{}", crash.server.name, crash.server.version),
+            "message": { "formatted": crash.message },
+            "exception": {
+                "values": [{
+                    "type": "panic",
+                    "value": crash.message,
+                    "mechanism": { "type": "panic", "handled": true },
+                }],
+            },
+            "tags": { "method": crash.method, "session": crash.session },
+            "extra": {
+                "location": crash.location,
+                "thread": crash.thread,
+                "backtrace": crash.backtrace,
+                "request_id": crash.request_id,
+                "trace_id": crash.trace_id,
+                "recent_messages": crash.recent_messages,
+            },
+        });
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(SENTRY_TIMEOUT))
+            .build()
+            .into();
+        agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header("X-Sentry-Auth", &self.auth)
+            .send(event.to_string().as_bytes())
+            .map_err(|e| format!("cannot send the crash to Sentry: {}", e))?;
+        Ok(format!("Sentry event {}", event_id))
+    }
+}
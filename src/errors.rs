@@ -0,0 +1,143 @@
+//! Server-specific error codes, in the -32000..-32099 range that JSON-RPC leaves to servers, so
+//! that clients can tell apart failures the generic codes lump together. Each code has a name,
+//! sent as `data.error`, and a message; embedders add their own with
+//! [`Server::with_error_code`](crate::server::Server::with_error_code).
+
+use crate::jsonrpc::{JsonRpcError, ERROR_CODE_RATE_LIMITED};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// Codes JSON-RPC reserves for implementation-defined server errors.
+const SERVER_ERROR_CODES: RangeInclusive<i32> = -32099..=-32000;
+
+/// The tool exists but the configuration doesn't allow calling it
+pub const TOOL_NOT_ALLOWED: ErrorCode =
+    ErrorCode::new(-32001, "ToolNotAllowed", "Tool not allowed");
+/// The path of the request is outside the configured roots
+pub const ROOT_VIOLATION: ErrorCode =
+    ErrorCode::new(-32002, "RootViolation", "Path outside the allowed roots");
+/// The request (or the one it was forwarded as) took too long
+pub const TIMEOUT: ErrorCode = ErrorCode::new(-32003, "Timeout", "Request timed out").retryable();
+/// The request was refused by a `rate_limits` entry, `data.retryAfterMs` telling when to retry
+pub const RATE_LIMITED: ErrorCode = ErrorCode::new(
+    ERROR_CODE_RATE_LIMITED,
+    "RateLimited",
+    "Rate limit exceeded",
+)
+.retryable();
+
+/// An application error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode {
+    pub code: i32,
+    /// Sent as `data.error`, e.g. `ToolNotAllowed`
+    pub name: &'static str,
+    /// What the code means, the message of the errors that don't say more
+    pub message: &'static str,
+    /// True if a request failing with the code may succeed when sent again later
+    pub retryable: bool,
+}
+
+impl ErrorCode {
+    pub const fn new(code: i32, name: &'static str, message: &'static str) -> ErrorCode {
+        ErrorCode {
+            code,
+            name,
+            message,
+            retryable: false,
+        }
+    }
+
+    pub const fn retryable(mut self) -> ErrorCode {
+        self.retryable = true;
+        self
+    }
+
+    /// An error with this code and `message`, naming the code in `data.error`.
+    pub fn error(&self, message: impl Into<String>) -> JsonRpcError {
+        JsonRpcError::new(self.code, message).with_data("error", self.name)
+    }
+}
+
+impl From<ErrorCode> for JsonRpcError {
+    fn from(code: ErrorCode) -> JsonRpcError {
+        code.error(code.message)
+    }
+}
+
+/// The application error codes of a server by code, the built-in ones to begin with.
+pub struct ErrorRegistry {
+    codes: BTreeMap<i32, ErrorCode>,
+}
+
+impl Default for ErrorRegistry {
+    fn default() -> ErrorRegistry {
+        let codes = [TOOL_NOT_ALLOWED, ROOT_VIOLATION, TIMEOUT, RATE_LIMITED];
+        ErrorRegistry {
+            codes: codes.into_iter().map(|code| (code.code, code)).collect(),
+        }
+    }
+}
+
+impl ErrorRegistry {
+    /// Adds a code, which must be in the -32000..-32099 range and not already used by another
+    /// name.
+    pub fn register(&mut self, code: ErrorCode) -> Result<(), String> {
+        if !SERVER_ERROR_CODES.contains(&code.code) {
+            return Err(format!("{} isn't in the -32000..-32099 range", code.code));
+        }
+        match self.codes.get(&code.code) {
+            Some(known) if known.name != code.name => {
+                Err(format!("{} is already used by {}", code.code, known.name))
+            }
+            _ => {
+                self.codes.insert(code.code, code);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get(&self, code: i32) -> Option<&ErrorCode> {
+        self.codes.get(&code)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ErrorCode> {
+        self.codes.values()
+    }
+
+    /// Fills the fields of `error.data` every error of the server has, unless the error set
+    /// them: the `method` of the request, when known, the `error` name of its code and whether
+    /// sending the request again later may succeed (`retryable`). Errors about a param name it
+    /// with `pointer`, a JSON pointer into the params, and may say what was `expected` and
+    /// `received`.
+    pub(crate) fn diagnose(&self, mut error: JsonRpcError, method: Option<&str>) -> JsonRpcError {
+        let known = self.get(error.code);
+        if let Value::Object(data) = error.data.get_or_insert_with(|| json!({})) {
+            if let Some(method) = method {
+                data.entry("method").or_insert_with(|| json!(method));
+            }
+            if let Some(known) = known {
+                data.entry("error").or_insert_with(|| json!(known.name));
+            }
+            let retryable = known.is_some_and(|known| known.retryable);
+            data.entry("retryable").or_insert(json!(retryable));
+        }
+        error
+    }
+
+    /// The codes as listed by `server/methods`.
+    pub(crate) fn to_value(&self) -> Value {
+        let codes: Vec<Value> = self
+            .iter()
+            .map(|code| {
+                json!({
+                    "code": code.code,
+                    "name": code.name,
+                    "message": code.message,
+                    "retryable": code.retryable,
+                })
+            })
+            .collect();
+        Value::Array(codes)
+    }
+}
@@ -0,0 +1,123 @@
+//! Vendor-specific methods, named `<prefix>/<name>` after the prefix of their extension (e.g.
+//! `x-myco`), so that private extensions don't collide with the methods of the spec. The server
+//! announces each extension under `capabilities.experimental.<prefix>` in its `initialize`
+//! result.
+
+use crate::{context::RequestContext, jsonrpc::JsonRpcError};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Function called with the params of a request, as the client sent them.
+pub type MethodHandler =
+    Box<dyn Fn(&RequestContext, Option<&Value>) -> Result<Value, JsonRpcError> + Send + Sync>;
+
+/// First segments of the methods of the spec, which extensions can't use as prefix.
+const RESERVED_PREFIXES: &[&str] = &[
+    "completion",
+    "elicitation",
+    "initialize",
+    "logging",
+    "notifications",
+    "ping",
+    "prompts",
+    "resources",
+    "roots",
+    "sampling",
+    "tools",
+];
+
+/// Methods sharing a vendor prefix.
+pub struct Extension {
+    /// Prefix of the methods, without the `/`
+    pub prefix: String,
+    /// Announced under `capabilities.experimental.<prefix>`, an empty object by default
+    pub capability: Value,
+    /// Handlers by method name, without the prefix
+    methods: BTreeMap<String, MethodHandler>,
+}
+
+impl Extension {
+    pub fn new(prefix: impl Into<String>) -> Extension {
+        Extension {
+            prefix: prefix.into().trim_end_matches('/').to_string(),
+            capability: json!({}),
+            methods: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_capability(mut self, capability: Value) -> Extension {
+        self.capability = capability;
+        self
+    }
+
+    /// Adds the method `<prefix>/<name>`.
+    pub fn with_method<F>(mut self, name: impl Into<String>, handler: F) -> Extension
+    where
+        F: Fn(&RequestContext, Option<&Value>) -> Result<Value, JsonRpcError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.methods.insert(name.into(), Box::new(handler));
+        self
+    }
+
+    /// Full names of the methods, sorted.
+    pub fn methods(&self) -> impl Iterator<Item = String> + '_ {
+        self.methods
+            .keys()
+            .map(|name| format!("{}/{}", self.prefix, name))
+    }
+}
+
+/// Extensions registered on the server, by prefix.
+#[derive(Default)]
+pub struct ExtensionRegistry {
+    extensions: BTreeMap<String, Extension>,
+}
+
+impl ExtensionRegistry {
+    /// Adds an extension, replacing any registered under the same prefix. Prefixes that are
+    /// empty, contain a `/` or are used by the spec are refused.
+    pub fn register(&mut self, extension: Extension) -> Result<(), String> {
+        let prefix = &extension.prefix;
+        if prefix.is_empty() || prefix.contains('/') {
+            return Err(format!("invalid extension prefix '{}'", prefix));
+        }
+        if RESERVED_PREFIXES.contains(&prefix.as_str()) {
+            return Err(format!("the prefix '{}' is used by the spec", prefix));
+        }
+        self.extensions.insert(prefix.clone(), extension);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Extension> {
+        self.extensions.values()
+    }
+
+    /// Serves a request of `method`, or returns None if no extension has it.
+    pub fn call(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        params: Option<&Value>,
+    ) -> Option<Result<Value, JsonRpcError>> {
+        let (prefix, name) = method.split_once('/')?;
+        let handler = self.extensions.get(prefix)?.methods.get(name)?;
+        Some(handler(ctx, params))
+    }
+
+    /// The `experimental` capabilities announcing the extensions.
+    pub fn capabilities(&self) -> Value {
+        let capabilities: Map<String, Value> = self
+            .extensions
+            .values()
+            .map(|extension| (extension.prefix.clone(), extension.capability.clone()))
+            .collect();
+        Value::Object(capabilities)
+    }
+}
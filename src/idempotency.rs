@@ -0,0 +1,101 @@
+//! Responses kept to be replayed to retried requests. A client whose response got lost, e.g.
+//! with a dropped HTTP connection, sends the request again with the same id; the session then
+//! replays the response it sent instead of running the handler, and its side effects, twice.
+//! Only resumable sessions (those of the HTTP transport) keep their responses, for
+//! `idempotency_ttl_secs`, at most `idempotency_max_responses` by session. A request reusing the
+//! id of another one (a different method or params) is served anew.
+
+use crate::{
+    config::Config,
+    jsonrpc::{JsonRpcId, JsonRpcResponse},
+};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Identifies the contents of a request, whatever its id: the SHA-256 of its method and params.
+pub fn fingerprint(method: &str, params: Option<&RawValue>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update([0]);
+    if let Some(params) = params {
+        hasher.update(params.get().as_bytes());
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Responses a session sent, oldest first.
+#[derive(Default)]
+pub struct ResponseCache {
+    responses: Mutex<VecDeque<Sent>>,
+}
+
+struct Sent {
+    fingerprint: String,
+    at: Instant,
+    response: JsonRpcResponse,
+}
+
+impl ResponseCache {
+    /// The response to replay to the request with `id` and `fingerprint`, if the same request
+    /// was answered recently enough.
+    pub fn replay(
+        &self,
+        config: &Config,
+        id: &JsonRpcId,
+        fingerprint: &str,
+    ) -> Option<JsonRpcResponse> {
+        let mut responses = self.responses.lock().unwrap();
+        expire(&mut responses, config);
+        responses
+            .iter()
+            .rev()
+            .find(|sent| sent.response.id() == id)
+            .filter(|sent| sent.fingerprint == fingerprint)
+            .map(|sent| sent.response.clone())
+    }
+
+    /// Keeps `response` to the request with `fingerprint` for replay, unless it is an error
+    /// that the same request may not get again (`data.retryable`).
+    pub fn store(&self, config: &Config, fingerprint: String, response: &JsonRpcResponse) {
+        if config.idempotency_ttl_secs == 0 || config.idempotency_max_responses == 0 {
+            return;
+        }
+        if let JsonRpcResponse::Error { error, .. } = response {
+            let retryable = error.data.as_ref().and_then(|data| data.get("retryable"));
+            if retryable.and_then(|retryable| retryable.as_bool()) == Some(true) {
+                return;
+            }
+        }
+        let mut responses = self.responses.lock().unwrap();
+        expire(&mut responses, config);
+        responses.retain(|sent| sent.response.id() != response.id());
+        while responses.len() >= config.idempotency_max_responses {
+            responses.pop_front();
+        }
+        responses.push_back(Sent {
+            fingerprint,
+            at: Instant::now(),
+            response: response.clone(),
+        });
+    }
+}
+
+/// Drops the responses older than `idempotency_ttl_secs`.
+fn expire(responses: &mut VecDeque<Sent>, config: &Config) {
+    let ttl = Duration::from_secs(config.idempotency_ttl_secs);
+    while responses
+        .front()
+        .is_some_and(|sent| sent.at.elapsed() >= ttl)
+    {
+        responses.pop_front();
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, value::RawValue, Value};
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
+pub const ERROR_CODE_PARSE_ERROR: i32 = -32700;
+pub const ERROR_CODE_INVALID_REQUEST: i32 = -32600;
+pub const ERROR_CODE_METHOD_NOT_FOUND: i32 = -32601;
+pub const ERROR_CODE_INVALID_PARAMS: i32 = -32602;
+pub const ERROR_CODE_INTERNAL_ERROR: i32 = -32603;
+/// Server error: the request was refused by a `rate_limits` entry, `data.retryAfterMs` telling
+/// when to retry
+pub const ERROR_CODE_RATE_LIMITED: i32 = -32029;
+
+/// Represents a JSON-RPC ID that can be a number, a string or null according to the JSON-RPC 2.0
+/// specification. Numbers keep the text they were received as: `1.10` or `1e2` are sent back
+/// unchanged, not as `1.1` or `100.0`.
+/// See https://www.jsonrpc.org/specification#id1
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum JsonRpcId {
+    Number(IdNumber),
+    String(String),
+    /// Discouraged by JSON-RPC, and what the responses to messages whose id couldn't be read have
+    Null,
+}
+
+impl Display for JsonRpcId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonRpcId::Number(n) => write!(f, "{}", n),
+            JsonRpcId::String(s) => write!(f, "{}", s),
+            JsonRpcId::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// Number of a [`JsonRpcId`]: ids are told apart by its value, and written with its text.
+#[derive(Debug, Clone)]
+pub struct IdNumber {
+    value: serde_json::Number,
+    text: String,
+}
+
+impl IdNumber {
+    /// The number written as `text`, `None` if `text` isn't a JSON number.
+    pub fn parse(text: &str) -> Option<IdNumber> {
+        let value = serde_json::from_str(text).ok()?;
+        Some(IdNumber {
+            value,
+            text: text.trim().to_string(),
+        })
+    }
+}
+
+impl From<serde_json::Number> for IdNumber {
+    fn from(value: serde_json::Number) -> IdNumber {
+        IdNumber {
+            text: value.to_string(),
+            value,
+        }
+    }
+}
+
+impl From<u64> for IdNumber {
+    fn from(value: u64) -> IdNumber {
+        serde_json::Number::from(value).into()
+    }
+}
+
+impl PartialEq for IdNumber {
+    fn eq(&self, other: &IdNumber) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for IdNumber {}
+
+impl Hash for IdNumber {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl Display for IdNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Serialize for IdNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawValue::from_string(self.text.clone())
+            .map_err(ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+/// Read back without the text of the number, see [`IdNumber::parse`] to keep it.
+impl<'de> Deserialize<'de> for IdNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<IdNumber, D::Error> {
+        serde_json::Number::deserialize(deserializer).map(IdNumber::from)
+    }
+}
+
+/// Represents a JSON-RPC request object according to the JSON-RPC 2.0 specification.
+/// See https://www.jsonrpc.org/specification
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JsonRpcRequest {
+    /// Identifier established by the client that should be sent back in the response
+    pub id: JsonRpcId,
+    /// JSON-RPC protocol version, must be "2.0"
+    pub jsonrpc: String,
+    /// Name of the method to be invoked
+    pub method: String,
+    /// Parameters to pass to the method, if any
+    pub params: Option<Value>,
+}
+
+/// Represents a JSON-RPC error object according to the JSON-RPC 2.0 specification.
+/// See https://www.jsonrpc.org/specification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonRpcError {
+    /// A number indicating the error type that occurred
+    pub code: i32,
+    /// A short description of the error
+    pub message: String,
+    /// Additional information about the error, if available
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> JsonRpcError {
+        JsonRpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Sets `data.<key>`, keeping the other fields of `data`.
+    pub fn with_data(mut self, key: &str, value: impl Into<Value>) -> JsonRpcError {
+        if let Value::Object(data) = self.data.get_or_insert_with(|| json!({})) {
+            data.insert(key.to_string(), value.into());
+        }
+        self
+    }
+}
+
+/// Message that can be written to the client.
+pub trait JsonRpcMessage: Serialize {
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+/// Represents a JSON-RPC response object according to the JSON-RPC 2.0 specification: either a
+/// result or an error, never both nor neither.
+/// See https://www.jsonrpc.org/specification#response_object
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged, try_from = "ResponseFields")]
+pub enum JsonRpcResponse {
+    Success {
+        /// Identifier matching the id that was sent in the request
+        id: JsonRpcId,
+        /// JSON-RPC protocol version, must be "2.0"
+        jsonrpc: String,
+        /// Result of the RPC call
+        result: Value,
+    },
+    Error {
+        /// Identifier matching the id that was sent in the request, null when it couldn't be
+        /// read
+        id: JsonRpcId,
+        /// JSON-RPC protocol version, must be "2.0"
+        jsonrpc: String,
+        /// Why the RPC call failed
+        error: JsonRpcError,
+    },
+}
+
+impl JsonRpcResponse {
+    /// The response with the `result` of the request with `id`.
+    pub fn success(id: JsonRpcId, result: Value) -> JsonRpcResponse {
+        JsonRpcResponse::Success {
+            id,
+            jsonrpc: "2.0".to_string(),
+            result,
+        }
+    }
+
+    /// The response failing the request with `id` with `error`.
+    pub fn error(id: JsonRpcId, error: JsonRpcError) -> JsonRpcResponse {
+        JsonRpcResponse::Error {
+            id,
+            jsonrpc: "2.0".to_string(),
+            error,
+        }
+    }
+
+    pub fn id(&self) -> &JsonRpcId {
+        match self {
+            JsonRpcResponse::Success { id, .. } | JsonRpcResponse::Error { id, .. } => id,
+        }
+    }
+}
+
+impl JsonRpcMessage for JsonRpcResponse {}
+
+/// Fields of a response as read, before checking it has exactly one of `result` and `error`.
+#[derive(Deserialize)]
+struct ResponseFields {
+    id: JsonRpcId,
+    jsonrpc: String,
+    /// `Some(Value::Null)` for `"result": null`, which is a result
+    #[serde(default, deserialize_with = "present")]
+    result: Option<Value>,
+    error: Option<JsonRpcError>,
+}
+
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+impl TryFrom<ResponseFields> for JsonRpcResponse {
+    type Error = &'static str;
+
+    fn try_from(fields: ResponseFields) -> Result<JsonRpcResponse, &'static str> {
+        let ResponseFields {
+            id,
+            jsonrpc,
+            result,
+            error,
+        } = fields;
+        match (result, error) {
+            (Some(result), None) => Ok(JsonRpcResponse::Success {
+                id,
+                jsonrpc,
+                result,
+            }),
+            (None, Some(error)) => Ok(JsonRpcResponse::Error { id, jsonrpc, error }),
+            (Some(_), Some(_)) => Err("a response cannot have both a result and an error"),
+            (None, None) => Err("a response needs either a result or an error"),
+        }
+    }
+}
+
+/// Represents a JSON-RPC notification object according to the JSON-RPC 2.0 specification.
+/// Notifications are similar to requests but do not require a response from the server.
+/// See https://www.jsonrpc.org/specification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonRpcNotification {
+    /// JSON-RPC protocol version, must be "2.0"
+    pub jsonrpc: String,
+    /// Name of the method to be invoked
+    pub method: String,
+    /// Parameters to pass to the method, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+impl JsonRpcMessage for JsonRpcNotification {}
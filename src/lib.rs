@@ -0,0 +1,46 @@
+//! MCP server implementation in Rust.
+//!
+//! The [`server::Server`] type drives the JSON-RPC loop over stdin/stdout (or one of the
+//! network transports in [`transport`]) and exposes hook points so embedders can plug their
+//! own behavior in.
+
+pub mod audit;
+pub mod builtin;
+pub mod client;
+pub mod codec;
+pub mod config;
+pub mod conformance;
+pub mod context;
+pub mod crash;
+pub mod errors;
+pub mod extensions;
+pub mod idempotency;
+pub mod jsonrpc;
+pub mod logging;
+pub mod mcp;
+pub mod methods;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod output;
+pub mod pagination;
+pub mod plugins;
+pub mod policy;
+pub mod prompts;
+pub mod protocol;
+pub mod proxy;
+pub mod ratelimit;
+pub mod requests;
+pub mod resources;
+pub mod roots;
+pub mod schema;
+pub mod server;
+pub mod spill;
+pub mod state;
+pub mod state_bag;
+pub mod testing;
+pub mod tools;
+pub mod transcript;
+pub mod transport;
+pub mod wirelog;
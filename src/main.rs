@@ -1,237 +1,397 @@
-use log::LevelFilter;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use simplelog::{Config, WriteLogger};
+mod cli;
+mod client_log;
+mod commands;
+#[cfg(windows)]
+mod service;
+mod signals;
+
+use clap::Parser;
+use cli::Cli;
+use client_log::ClientLogQueue;
+use mcp_rs_test::{
+    builtin::{self, watch::ResourceWatcher},
+    codec::Limits,
+    config::{Config, LogTarget, Transport},
+    crash::{CrashDirectory, SentryReporter},
+    logging::RotatingFile,
+    mcp::Implementation,
+    plugins::{self, watch::ToolWatcher},
+    proxy::Proxy,
+    server::{panic_message, Server},
+    state::StateStore,
+    tools::Tool,
+    transcript::Recorder,
+    transport,
+};
 use std::{
-    fmt::Display,
-    fs::File,
-    io::{self, BufRead, Write},
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    process,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::{self, format::FmtSpan},
+    prelude::*,
+    reload, Registry,
 };
 
-#[allow(dead_code)]
-const ERROR_CODE_PARSE_ERROR: i32 = -32700;
-#[allow(dead_code)]
-const ERROR_CODE_INVALID_REQUEST: i32 = -32600;
-#[allow(dead_code)]
-const ERROR_CODE_METHOD_NOT_FOUND: i32 = -32601;
-#[allow(dead_code)]
-const ERROR_CODE_INVALID_PARAMS: i32 = -32602;
-#[allow(dead_code)]
-const ERROR_CODE_INTERNAL_ERROR: i32 = -32603;
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Exit code once stdin was closed, or once the server or the service was stopped.
+const EXIT_OK: i32 = 0;
+/// Exit code when the transport failed, e.g. when the address to listen on is in use.
+const EXIT_TRANSPORT_ERROR: i32 = 1;
+/// Exit code for an invalid command line or configuration.
+const EXIT_CONFIG_ERROR: i32 = 2;
+/// Exit code when stdout was closed by the client before stdin.
+const EXIT_OUTPUT_CLOSED: i32 = 3;
+/// Exit code when the server panicked.
+const EXIT_PANIC: i32 = 4;
 
-/// Represents a JSON-RPC ID that can be either a number or string according to the JSON-RPC 2.0 specification
-/// See https://www.jsonrpc.org/specification#id1
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-enum JsonRpcId {
-    Number(u64),
-    String(String),
-}
+fn main() {
+    let cli = Cli::parse();
+    if let Some(command) = &cli.command {
+        process::exit(commands::run(&cli, command));
+    }
+    if cli.health_check {
+        process::exit(commands::health_check(&cli));
+    }
+    let config = cli.config().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(EXIT_CONFIG_ERROR);
+    });
+    let log_level: LevelFilter = config.log_level.parse().unwrap_or_else(|_| {
+        eprintln!("error: invalid log level '{}'", config.log_level);
+        process::exit(EXIT_CONFIG_ERROR);
+    });
 
-impl JsonRpcId {
-    fn clone(&self) -> JsonRpcId {
-        match self {
-            JsonRpcId::Number(n) => JsonRpcId::Number(*n),
-            JsonRpcId::String(s) => JsonRpcId::String(s.clone()),
+    let run_as_service = cli.service;
+    if run_as_service && config.transport == Transport::Stdio {
+        eprintln!("error: --service needs --transport http or ws");
+        process::exit(EXIT_CONFIG_ERROR);
+    }
+    let (log_level_handle, client_log) = init_logger(&config, log_level, run_as_service);
+    // The keys that aren't settings are ignored, which is most likely a typo
+    if let Some(path) = &cli.config {
+        for problem in commands::config::validate(path).unwrap_or_default() {
+            tracing::warn!("{}:{}", path.display(), problem);
         }
     }
-}
 
-impl Display for JsonRpcId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            JsonRpcId::Number(n) => write!(f, "{}", n),
-            JsonRpcId::String(s) => write!(f, "{}", s),
+    let proxy = Proxy::connect(&config);
+    let plugin_tools = plugins::load_tools(&config);
+    let plugin_tool_names = plugin_tools.iter().map(|tool| tool.name.clone()).collect();
+    let mut server = build_server(&config, &proxy, plugin_tools);
+    if let Some(path) = &cli.record {
+        match Recorder::create(path) {
+            Ok(recorder) => server = server.with_recorder(recorder),
+            Err(e) => {
+                eprintln!("error: cannot create {}: {}", path.display(), e);
+                process::exit(EXIT_CONFIG_ERROR);
+            }
         }
     }
-}
-
-/// Represents a JSON-RPC request object according to the JSON-RPC 2.0 specification.
-/// See https://www.jsonrpc.org/specification
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonRpcRequest {
-    /// Identifier established by the client that should be sent back in the response
-    id: JsonRpcId,
-    /// JSON-RPC protocol version, must be "2.0"
-    jsonrpc: String,
-    /// Name of the method to be invoked
-    method: String,
-    /// Parameters to pass to the method, if any
-    params: Option<Value>,
-}
-
-/// Represents a JSON-RPC error object according to the JSON-RPC 2.0 specification.
-/// See https://www.jsonrpc.org/specification
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonRpcError {
-    /// A number indicating the error type that occurred
-    code: i32,
-    /// A short description of the error
-    message: String,
-    /// Additional information about the error, if available
-    data: Option<Value>,
-}
-
-trait JsonRpcResponse {
-    fn to_json(&self) -> Result<String, serde_json::Error>;
-}
-
-/// Represents a JSON-RPC response object according to the JSON-RPC 2.0 specification.
-/// See https://www.jsonrpc.org/specification
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonRpcResponseSuccess {
-    /// Identifier matching the id that was sent in the request
-    id: JsonRpcId,
-    /// JSON-RPC protocol version, must be "2.0"
-    jsonrpc: String,
-    /// Result of the RPC call if successful
-    result: Option<Value>,
-}
-
-impl JsonRpcResponse for JsonRpcResponseSuccess {
-    fn to_json(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
+    let server = Arc::new(server);
+    client_log::forward(client_log, Arc::downgrade(&server));
+    proxy.attach(&server);
+    let watcher = ResourceWatcher::new(server.clone())
+        .map_err(|e| tracing::error!("Cannot watch resource files: {}", e))
+        .ok();
+    let tool_watcher = ToolWatcher::new(server.clone(), plugin_tool_names)
+        .map_err(|e| tracing::error!("Cannot watch plugin files: {}", e))
+        .ok();
+    if let Some(path) = cli.config.clone() {
+        let server = server.clone();
+        Config::watch(&path, CONFIG_POLL_INTERVAL, move || {
+            reload_config(&cli, &server, &log_level_handle);
+            if let Some(watcher) = &watcher {
+                watcher.update(&server.config());
+            }
+            if let Some(tool_watcher) = &tool_watcher {
+                tool_watcher.update(&server.config());
+            }
+        });
     }
+    if let Some(listen) = &config.metrics_listen {
+        if let Err(e) = transport::http::serve_metrics(server.clone(), listen) {
+            tracing::error!("Cannot serve metrics on {}: {}", listen, e);
+        }
+    }
+    #[cfg(feature = "otel")]
+    mcp_rs_test::otel::spawn_exporter(&server);
+    // The service control manager stops the service itself
+    if !run_as_service {
+        let stopping = server.clone();
+        let installed = signals::on_stop(move || {
+            tracing::info!("Asked to stop, shutting down");
+            stopping.shutdown();
+            exit(&stopping, EXIT_OK);
+        });
+        if let Err(e) = installed {
+            tracing::error!("Cannot handle stop signals: {}", e);
+        }
+    }
+    let code = panic::catch_unwind(AssertUnwindSafe(|| run(&server, &config, run_as_service)))
+        .unwrap_or_else(|payload| {
+            tracing::error!("Server panicked: {}", panic_message(&*payload));
+            EXIT_PANIC
+        });
+    exit(&server, code);
 }
 
-/// Represents a JSON-RPC response object according to the JSON-RPC 2.0 specification.
-/// See https://www.jsonrpc.org/specification
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonRpcResponseError {
-    /// Identifier matching the id that was sent in the request
-    id: JsonRpcId,
-    /// JSON-RPC protocol version, must be "2.0"
-    jsonrpc: String,
-    /// Error information if the RPC call failed
-    error: Option<JsonRpcError>,
-}
-
-impl JsonRpcResponse for JsonRpcResponseError {
-    fn to_json(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
+/// Logs the summary of what the server did and exits with `code`.
+fn exit(server: &Server, code: i32) -> ! {
+    tracing::info!("Exiting with status {}: {}", code, server.summary());
+    // What was served since the last export
+    #[cfg(feature = "otel")]
+    if let Err(e) = mcp_rs_test::otel::export(server) {
+        tracing::warn!("Cannot export to the OpenTelemetry collector: {}", e);
     }
+    process::exit(code);
 }
 
-/// Represents a JSON-RPC notification object according to the JSON-RPC 2.0 specification.
-/// Notifications are similar to requests but do not require a response from the server.
-/// See https://www.jsonrpc.org/specification
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonRpcNotification {
-    /// JSON-RPC protocol version, must be "2.0"
-    jsonrpc: String,
-    /// Name of the method to be invoked
-    method: String,
-    /// Parameters to pass to the method, if any
-    params: Option<Value>,
+/// Serves the configured transport, as a Windows service with `as_service`, returning the exit
+/// code of the process.
+fn run(server: &Arc<Server>, config: &Config, as_service: bool) -> i32 {
+    if as_service {
+        #[cfg(windows)]
+        let result = {
+            let (server, config) = (server.clone(), config.clone());
+            service::run(move || serve(server, &config))
+        };
+        #[cfg(not(windows))]
+        let result: io::Result<()> = Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--service is only available on Windows",
+        ));
+        return match result {
+            Ok(()) => EXIT_OK,
+            Err(e) => {
+                tracing::error!("Cannot run as a service: {}", e);
+                eprintln!("error: {}", e);
+                EXIT_TRANSPORT_ERROR
+            }
+        };
+    }
+    match serve(server.clone(), config) {
+        Ok(()) => EXIT_OK,
+        Err(e) if config.transport == Transport::Stdio => {
+            tracing::error!("Cannot write to stdout, exiting: {}", e);
+            EXIT_OUTPUT_CLOSED
+        }
+        Err(e) => {
+            tracing::error!("Error serving {} transport: {}", config.transport, e);
+            eprintln!("error: {}", e);
+            EXIT_TRANSPORT_ERROR
+        }
+    }
 }
 
-fn send_response<T: JsonRpcResponse>(response: T) {
-    let response_str = response.to_json();
-    match response_str {
-        Ok(s) => {
-            log::info!("Sending response: {}", s);
-            let mut stdout = io::stdout();
-            stdout.write_all(s.as_bytes()).unwrap();
-            stdout.write_all(b"\n").unwrap();
-            stdout.flush().unwrap();
-        }
-        Err(e) => log::error!("Error serializing response: {}", e),
+/// Serves the configured transport until it fails or, for stdio, the client goes away. Serving
+/// stdio only fails once stdout can't be written to. With `mdns`, the network transports are
+/// advertised on the local network while they are served.
+fn serve(server: Arc<Server>, config: &Config) -> io::Result<()> {
+    let _advertisement = match config.mdns && config.transport != Transport::Stdio {
+        true => transport::mdns::advertise(&server, config)
+            .map_err(|e| tracing::error!("Cannot advertise the server over mDNS: {}", e))
+            .ok(),
+        false => None,
+    };
+    match config.transport {
+        Transport::Stdio => server.run(),
+        Transport::Http => transport::http::serve(server, &config.listen),
+        Transport::Ws => transport::ws::serve(server, &config.listen),
     }
 }
 
-fn handle_request(request: &JsonRpcRequest) {
-    log::info!("handle_request: {:?}", request);
-    match request.method.as_str() {
-        "initialize" => {
-            log::info!("Initializing server...");
-            let mut result = Value::Object(Default::default());
-            result["protocolVersion"] = Value::String("2024-11-05".to_string());
-            result["capabilities"] = Value::Object(Default::default());
-            // result["capabilities"]["prompts"] = Value::Object(Default::default());
-            // result["capabilities"]["prompts"]["listChanged"] = Value::Bool(true);
-            result["serverInfo"] = Value::Object(Default::default());
-            result["serverInfo"]["name"] = Value::String("MCP Rust test server".to_string());
-            result["serverInfo"]["version"] = Value::String("0.1.0".to_string());
-            let response = JsonRpcResponseSuccess {
-                id: request.id.clone(),
-                jsonrpc: "2.0".to_string(),
-                result: Some(result),
-            };
-            send_response(response);
-        }
-        "ping" => {
-            log::info!("Client ping server...");
-            let response = JsonRpcResponseSuccess {
-                id: request.id.clone(),
-                jsonrpc: "2.0".to_string(),
-                result: Some(Value::Object(Default::default())),
-            };
-            send_response(response);
+/// Builds the server described by the configuration with the tools loaded from the plugin
+/// files, re-exposing what the downstream servers connected by `proxy` offer.
+fn build_server(config: &Config, proxy: &Arc<Proxy>, plugin_tools: Vec<Tool>) -> Server {
+    let defaults = Implementation::default();
+    let server = Server::new()
+        .with_server_info(
+            config.server_name.clone().unwrap_or(defaults.name),
+            config.server_version.clone().unwrap_or(defaults.version),
+        )
+        .with_config(config.clone())
+        .with_limits(Limits {
+            max_size: config.max_message_size,
+            ..Limits::default()
+        });
+    let server = match &config.state_file {
+        Some(path) => server.with_state_store(StateStore::open(path)),
+        None => server,
+    };
+    let server = match &config.crash_report_dir {
+        Some(dir) => server.with_crash_reporter(CrashDirectory::new(dir)),
+        None => server,
+    };
+    let server = match config
+        .crash_report_dsn
+        .as_deref()
+        .map(SentryReporter::from_dsn)
+    {
+        Some(Ok(reporter)) => server.with_crash_reporter(reporter),
+        Some(Err(e)) => {
+            tracing::error!("Cannot report crashes to Sentry: {}", e);
+            server
         }
-        _ => {
-            log::error!("Unknown request method: {}", request.method);
-            let err = JsonRpcError {
-                code: ERROR_CODE_INVALID_REQUEST,
-                message: format!("Invalid request: '{}'", request.method),
-                data: None
-            };
-            let response = JsonRpcResponseError {
-                id: request.id.clone(),
-                jsonrpc: "2.0".to_string(),
-                error: Some(err)
-            };
-            send_response(response);
-        }
-    }
+        None => server,
+    };
+    // Before the file resources, which would claim the downstream file:// URIs
+    let server = if proxy.is_empty() {
+        server
+    } else {
+        let forwarder = proxy.clone();
+        server
+            .with_resources(proxy.resources())
+            .with_fallback(move |ctx, method, params| forwarder.forward(ctx, method, params))
+    };
+    let databases = builtin::db::Databases::new();
+    let server = server
+        .with_tool(builtin::fs::read_tool())
+        .with_tool(builtin::fs::list_tool())
+        .with_tool(builtin::fs::write_tool())
+        .with_tool(builtin::fs::edit_tool())
+        .with_tool(builtin::csv::preview_tool())
+        .with_tool(builtin::documents::extract_text_tool())
+        .with_tool(builtin::search::search_tool())
+        .with_tool(builtin::semantic::search_tool())
+        .with_tool(builtin::command::run_tool())
+        .with_tool(builtin::http::fetch_tool())
+        .with_tool(builtin::page::fetch_page_tool())
+        .with_tool(builtin::sqlite::query_tool())
+        .with_tool(builtin::db::query_tool(&databases))
+        .with_tool(builtin::redis::command_tool())
+        .with_tool(builtin::docker::list_containers_tool())
+        .with_tool(builtin::docker::inspect_tool())
+        .with_tool(builtin::docker::logs_tool())
+        .with_tool(builtin::docker::start_tool())
+        .with_tool(builtin::docker::stop_tool())
+        .with_tool(builtin::jq::query_tool())
+        .with_tool(builtin::clipboard::get_tool())
+        .with_tool(builtin::clipboard::set_tool())
+        .with_tool(builtin::memory::set_tool())
+        .with_tool(builtin::memory::get_tool())
+        .with_tool(builtin::memory::list_tool())
+        .with_tool(builtin::system::info_tool())
+        .with_tool(builtin::system::list_processes_tool())
+        .with_tool(builtin::system::process_info_tool())
+        .with_tool(builtin::time::time_tool())
+        .with_tool(builtin::calc::evaluate_tool())
+        .with_tool(builtin::encoding::uuid_tool())
+        .with_tool(builtin::encoding::base64_encode_tool())
+        .with_tool(builtin::encoding::base64_decode_tool())
+        .with_tool(builtin::encoding::url_encode_tool())
+        .with_tool(builtin::encoding::url_decode_tool())
+        .with_tool(builtin::encoding::hash_tool())
+        .with_tool(builtin::git::status_tool())
+        .with_tool(builtin::git::log_tool())
+        .with_tool(builtin::git::diff_tool())
+        .with_tool(builtin::git::blame_tool())
+        .with_prompt(builtin::prompts::code_review_prompt())
+        .with_prompt(builtin::prompts::summarize_file_prompt())
+        .with_prompt(builtin::prompts::commit_message_prompt())
+        .with_resources(builtin::fs::FileResources::default())
+        .with_resources(builtin::assets::StaticResources::default())
+        .with_resources(builtin::sqlite::SchemaResources)
+        .with_resources(builtin::db::SchemaResources::new(&databases))
+        .with_resources(builtin::env::EnvResources)
+        .with_scheme("data", builtin::data::DataScheme)
+        .with_scheme("http", builtin::http::HttpScheme)
+        .with_scheme("https", builtin::http::HttpScheme);
+    #[cfg(unix)]
+    let server = server
+        .with_tool(builtin::pty::start_tool())
+        .with_tool(builtin::pty::send_tool())
+        .with_tool(builtin::pty::read_tool())
+        .with_tool(builtin::pty::stop_tool());
+    let server = plugin_tools
+        .into_iter()
+        .chain(plugins::openapi::load_tools(config))
+        .chain(proxy.tools())
+        .fold(server, Server::with_tool);
+    plugins::templates::load_prompts(config)
+        .into_iter()
+        .chain(proxy.prompts())
+        .fold(server, Server::with_prompt)
 }
 
-fn handle_notification(notification: &JsonRpcNotification) {
-    log::info!("handle_notification: {:?}", notification);
-    match notification.method.as_str() {
-        "notifications/initialized" => {
-            log::info!("Server initialized.");
+/// Re-reads the configuration after the config file changed and applies what can change at
+/// runtime: the log level and the server's runtime settings.
+fn reload_config(cli: &Cli, server: &Server, log_level_handle: &LogLevelHandle) {
+    let config = match cli.config() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Error reloading config, keeping the current one: {}", e);
+            return;
         }
-        _ => {
-            log::error!("Unknown notification method: {}", notification.method);
+    };
+    match config.log_level.parse::<LevelFilter>() {
+        Ok(level) => {
+            let _ = log_level_handle.modify(|current| *current = level);
         }
+        Err(_) => tracing::error!("Invalid log level '{}'", config.log_level),
     }
+    server.reload_config(config);
 }
 
-fn main() {
-    // Initialize the logger to write to a file
-    let _ = WriteLogger::init(
-        LevelFilter::Info,
-        Config::default(),
-        File::create("C:\\tmp\\my_rust_bin.log").unwrap(),
-    );
+/// Handle used to change the log level at runtime.
+type LogLevelHandle = reload::Handle<LevelFilter, Registry>;
 
-    let stdin = io::stdin();
-
-    for line in stdin.lock().lines() {
-        if let Ok(input) = line {
-            log::info!("Received line: {}", input);
-            let request = serde_json::from_str::<JsonRpcRequest>(&input);
-            if let Ok(req) = request {
-                handle_request(&req);
-            } else {
-                let notification = serde_json::from_str::<JsonRpcNotification>(&input);
-                if let Ok(notif) = notification {
-                    handle_notification(&notif);
-                } else {
-                    log::error!("Error parsing request: {:?}", request);
-                    let err = JsonRpcError {
-                        code: ERROR_CODE_PARSE_ERROR,
-                        message: "Parse error".to_string(),
-                        data: None
-                    };
-                    let response = JsonRpcResponseError {
-                        id: JsonRpcId::Number(0),
-                        jsonrpc: "2.0".to_string(),
-                        error: Some(err)
-                    };
-                    send_response(response);
-                }
-            }
+/// Initializes the logger to write to the log file, stderr or both, as configured.
+/// Each request runs in its own span, whose close event records how long it took.
+/// If the log file can't be opened, logs go to stderr instead. A Windows service also writes
+/// its warnings and errors to the event log. Events are also queued for the clients, see
+/// [`client_log`].
+fn init_logger(
+    config: &Config,
+    log_level: LevelFilter,
+    service: bool,
+) -> (LogLevelHandle, ClientLogQueue) {
+    let (log_level, log_level_handle) = reload::Layer::new(log_level);
+    let file = (config.log_target != LogTarget::Stderr).then(|| {
+        if let Some(dir) = config.log_file.parent() {
+            let _ = fs::create_dir_all(dir);
         }
+        RotatingFile::open(&config.log_file, config.log_max_size, config.log_max_files)
+    });
+    let (file, file_error) = match file {
+        Some(Ok(file)) => (Some(file), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let file_layer = file.map(|file| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(Mutex::new(file))
+    });
+    let stderr_layer = (config.log_target != LogTarget::File || file_error.is_some()).then(|| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(io::stderr)
+    });
+    let registry = tracing_subscriber::registry()
+        .with(log_level)
+        .with(file_layer)
+        .with(stderr_layer);
+    let (client_layer, client_log) = client_log::layer();
+    let registry = registry.with(client_layer);
+    #[cfg(windows)]
+    let registry = registry.with(service.then(service::EventLog::register).flatten());
+    #[cfg(not(windows))]
+    let _ = service;
+    let _ = registry.try_init();
+    if let Some(e) = file_error {
+        tracing::warn!(
+            "Cannot open log file {}, logging to stderr instead: {}",
+            config.log_file.display(),
+            e
+        );
     }
+    (log_level_handle, client_log)
 }
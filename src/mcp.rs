@@ -0,0 +1,680 @@
+use crate::protocol::ProtocolVersion;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::SystemTime;
+
+/// Name and version of an MCP implementation, as sent in `serverInfo` / `clientInfo`.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/basic/lifecycle/#initialization
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Implementation {
+    pub name: String,
+    pub version: String,
+}
+
+impl Default for Implementation {
+    /// Name and version of this crate.
+    fn default() -> Implementation {
+        Implementation {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// An image a client can show next to a tool, resource or prompt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Icon {
+    /// URL of the image, possibly a `data:` URL
+    pub src: String,
+    /// MIME type of the image, if the URL doesn't tell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Sizes the image fits, such as `48x48` or `any`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sizes: Vec<String>,
+}
+
+impl Icon {
+    pub fn new(src: impl Into<String>) -> Icon {
+        Icon {
+            src: src.into(),
+            mime_type: None,
+            sizes: Vec::new(),
+        }
+    }
+
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Icon {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    pub fn with_size(mut self, size: impl Into<String>) -> Icon {
+        self.sizes.push(size.into());
+        self
+    }
+}
+
+/// Hints on who a resource is meant for and how much it matters.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/resources/#listing-resources
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Annotations {
+    /// Intended readers: the user, the model (`assistant`) or both
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audience: Vec<Role>,
+    /// Importance, from 0 (entirely optional) to 1 (required)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<f64>,
+    /// Time of the last modification (RFC 3339), since 2025-06-18
+    #[serde(rename = "lastModified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Annotations {
+    /// Annotations meant for `audience`.
+    pub fn for_audience(audience: impl IntoIterator<Item = Role>) -> Annotations {
+        Annotations {
+            audience: audience.into_iter().collect(),
+            priority: None,
+            last_modified: None,
+        }
+    }
+
+    /// Annotations telling when a resource was last modified.
+    pub fn modified_at(modified: SystemTime) -> Annotations {
+        Annotations {
+            last_modified: Some(rfc3339(modified)),
+            ..Annotations::default()
+        }
+    }
+
+    /// Sets the priority, clamped to [0, 1].
+    pub fn with_priority(mut self, priority: f64) -> Annotations {
+        self.priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+}
+
+/// A piece of content returned by a tool or embedded in a prompt message.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/tools/#tool-result
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Content {
+    /// Plain text content
+    Text { text: String },
+    /// Base64-encoded image data
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// Base64-encoded audio data, since 2025-03-26
+    Audio {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// A resource embedded in the result
+    Resource { resource: ResourceContents },
+    /// A resource the client can read with `resources/read`, since 2025-06-18
+    #[serde(rename = "resource_link")]
+    ResourceLink {
+        uri: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        /// Size in bytes
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+    },
+}
+
+impl Content {
+    pub fn text(text: impl Into<String>) -> Content {
+        Content::Text { text: text.into() }
+    }
+
+    /// Builds image content, base64-encoding the raw bytes.
+    pub fn image(bytes: &[u8], mime_type: impl Into<String>) -> Content {
+        Content::Image {
+            data: BASE64.encode(bytes),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// Builds audio content, base64-encoding the raw bytes.
+    pub fn audio(bytes: &[u8], mime_type: impl Into<String>) -> Content {
+        Content::Audio {
+            data: BASE64.encode(bytes),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    pub fn resource(resource: ResourceContents) -> Content {
+        Content::Resource { resource }
+    }
+
+    /// The content as `version` can carry it: audio becomes a text placeholder before
+    /// 2025-03-26, and resource links before 2025-06-18, when embedded resources lose `_meta`.
+    pub fn for_version(self, version: ProtocolVersion) -> Content {
+        match self {
+            Content::Audio { data, mime_type } if !version.supports_audio() => Content::text(
+                format!("[{} audio, {} bytes of base64]", mime_type, data.len()),
+            ),
+            Content::ResourceLink { uri, name, .. } if !version.supports_resource_links() => {
+                Content::text(format!("[{}: read the resource {}]", name, uri))
+            }
+            Content::Resource { mut resource } if !version.supports_last_modified() => {
+                resource.meta = None;
+                Content::Resource { resource }
+            }
+            content => content,
+        }
+    }
+}
+
+/// Contents of a resource, either as text or as a base64-encoded blob.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/resources/#resource-contents
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    /// URI identifying the resource
+    pub uri: String,
+    /// MIME type of the contents, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Text of the resource, for textual contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Base64-encoded data, for binary contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+    /// Metadata, such as `lastModified`
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+impl ResourceContents {
+    pub fn text(uri: impl Into<String>, text: impl Into<String>) -> ResourceContents {
+        ResourceContents {
+            uri: uri.into(),
+            mime_type: None,
+            text: Some(text.into()),
+            blob: None,
+            meta: None,
+        }
+    }
+
+    /// Builds binary contents, base64-encoding the raw bytes.
+    pub fn blob(
+        uri: impl Into<String>,
+        bytes: &[u8],
+        mime_type: impl Into<String>,
+    ) -> ResourceContents {
+        ResourceContents {
+            uri: uri.into(),
+            mime_type: Some(mime_type.into()),
+            text: None,
+            blob: Some(BASE64.encode(bytes)),
+            meta: None,
+        }
+    }
+
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> ResourceContents {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Adds the time of the last modification as `_meta.lastModified`.
+    pub fn with_last_modified(mut self, modified: SystemTime) -> ResourceContents {
+        let meta = self.meta.get_or_insert_with(|| json!({}));
+        meta["lastModified"] = json!(rfc3339(modified));
+        self
+    }
+
+    /// The `length` bytes (all those left when None) from `offset`, counted from the end when
+    /// negative, of the text or the decoded blob, described in `_meta.range` by the offset and
+    /// length returned and the size of the whole. A range cutting a character of the text is
+    /// moved back to the start of the character.
+    pub fn range(mut self, offset: i64, length: Option<u64>) -> ResourceContents {
+        let bounds = |size: usize| {
+            let start = match usize::try_from(offset) {
+                Ok(offset) => offset.min(size),
+                Err(_) => size.saturating_sub(offset.unsigned_abs() as usize),
+            };
+            let end = length.map_or(size, |length| {
+                start.saturating_add(length as usize).min(size)
+            });
+            (start, end)
+        };
+        let (start, end, size) = if let Some(text) = &mut self.text {
+            let (mut start, mut end) = bounds(text.len());
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut forward = end;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end == start {
+                // A range shorter than the character, which is returned whole to make progress
+                while !text.is_char_boundary(forward) {
+                    forward += 1;
+                }
+                end = forward;
+            }
+            let size = text.len();
+            *text = text[start..end].to_string();
+            (start, end, size)
+        } else if let Some(blob) = &mut self.blob {
+            let bytes = BASE64.decode(&*blob).unwrap_or_default();
+            let (start, end) = bounds(bytes.len());
+            *blob = BASE64.encode(&bytes[start..end]);
+            (start, end, bytes.len())
+        } else {
+            (0, 0, 0)
+        };
+        let meta = self.meta.get_or_insert_with(|| json!({}));
+        meta["range"] = json!({ "offset": start, "length": end - start, "size": size });
+        self
+    }
+
+    /// Size in bytes of the text or of the decoded blob.
+    pub fn size(&self) -> usize {
+        match (&self.text, &self.blob) {
+            (Some(text), _) => text.len(),
+            (None, Some(blob)) => {
+                let padding = blob.bytes().rev().take_while(|&b| b == b'=').count();
+                (blob.len() / 4 * 3).saturating_sub(padding)
+            }
+            (None, None) => 0,
+        }
+    }
+}
+
+/// `time` in RFC 3339 format, in UTC.
+fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Result of a `tools/call` request.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/tools/#calling-tools
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    /// Content produced by the tool
+    pub content: Vec<Content>,
+    /// True if the tool failed; the content then describes the failure
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+    /// Result as a JSON value following the tool's `outputSchema`, since 2025-06-18
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+}
+
+impl CallToolResult {
+    pub fn new(content: Vec<Content>) -> CallToolResult {
+        CallToolResult {
+            content,
+            is_error: false,
+            structured_content: None,
+        }
+    }
+
+    /// Successful result with `value` as structured content, and as JSON text for the
+    /// clients that don't read it.
+    pub fn structured(value: Value) -> CallToolResult {
+        CallToolResult {
+            structured_content: Some(value.clone()),
+            ..CallToolResult::text(value.to_string())
+        }
+    }
+
+    /// Successful result with a single text content.
+    pub fn text(text: impl Into<String>) -> CallToolResult {
+        CallToolResult::new(vec![Content::text(text)])
+    }
+
+    /// Failed result with the error message as text content.
+    pub fn error(message: impl Into<String>) -> CallToolResult {
+        CallToolResult {
+            is_error: true,
+            ..CallToolResult::text(message)
+        }
+    }
+
+    pub fn with_content(mut self, content: Content) -> CallToolResult {
+        self.content.push(content);
+        self
+    }
+
+    /// The result as `version` can carry it: structured content is dropped before 2025-06-18,
+    /// after being added as JSON text if there's no other content.
+    pub fn for_version(mut self, version: ProtocolVersion) -> CallToolResult {
+        if !version.supports_structured_output() {
+            if let Some(value) = self.structured_content.take() {
+                if self.content.is_empty() {
+                    self.content.push(Content::text(value.to_string()));
+                }
+            }
+        }
+        self.content = self
+            .content
+            .into_iter()
+            .map(|content| content.for_version(version))
+            .collect();
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Result of a `resources/read` request.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/resources/#reading-resources
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadResourceResult {
+    pub contents: Vec<ResourceContents>,
+    /// Cursor of the next page of a resource read in pages (an extension of the spec)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl ReadResourceResult {
+    pub fn new(contents: Vec<ResourceContents>) -> ReadResourceResult {
+        ReadResourceResult {
+            contents,
+            next_cursor: None,
+        }
+    }
+
+    /// The result with every contents cut to a range, see [`ResourceContents::range`].
+    pub fn range(mut self, offset: i64, length: Option<u64>) -> ReadResourceResult {
+        self.contents = self
+            .contents
+            .into_iter()
+            .map(|contents| contents.range(offset, length))
+            .collect();
+        self
+    }
+
+    /// Offset of the first byte left out by [`ReadResourceResult::range`], the smallest one of
+    /// the contents not returned up to their end, or None when they all were.
+    pub fn next_offset(&self) -> Option<usize> {
+        self.contents
+            .iter()
+            .filter_map(|contents| {
+                let range = contents.meta.as_ref()?.get("range")?;
+                let end = range["offset"].as_u64()? + range["length"].as_u64()?;
+                (end < range["size"].as_u64()?).then_some(end as usize)
+            })
+            .min()
+    }
+
+    /// The result as `version` can carry it: contents have no `_meta` before 2025-06-18.
+    pub fn for_version(mut self, version: ProtocolVersion) -> ReadResourceResult {
+        if !version.supports_last_modified() {
+            for contents in &mut self.contents {
+                contents.meta = None;
+            }
+        }
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Severity of a `notifications/message`, from the least to the most severe, as set by the
+/// client with `logging/setLevel`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+/// Sender of a prompt or sampling message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// One message of a prompt.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/prompts/#promptmessage
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromptMessage {
+    pub role: Role,
+    pub content: Content,
+}
+
+impl PromptMessage {
+    pub fn new(role: Role, content: Content) -> PromptMessage {
+        PromptMessage { role, content }
+    }
+
+    /// User message with text content.
+    pub fn user(text: impl Into<String>) -> PromptMessage {
+        PromptMessage {
+            role: Role::User,
+            content: Content::text(text),
+        }
+    }
+
+    /// User message embedding a resource.
+    pub fn user_resource(resource: ResourceContents) -> PromptMessage {
+        PromptMessage {
+            role: Role::User,
+            content: Content::resource(resource),
+        }
+    }
+
+    /// Assistant message with text content, e.g. the answer of a few-shot example.
+    pub fn assistant(text: impl Into<String>) -> PromptMessage {
+        PromptMessage {
+            role: Role::Assistant,
+            content: Content::text(text),
+        }
+    }
+}
+
+/// Result of a `prompts/get` request.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/prompts/#getting-a-prompt
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GetPromptResult {
+    /// Description of the prompt, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+impl GetPromptResult {
+    pub fn new(messages: Vec<PromptMessage>) -> GetPromptResult {
+        GetPromptResult {
+            description: None,
+            messages,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> GetPromptResult {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Appends a message, after those already in the result.
+    pub fn with_message(mut self, message: PromptMessage) -> GetPromptResult {
+        self.messages.push(message);
+        self
+    }
+
+    /// The result with each message's content as `version` can carry it.
+    pub fn for_version(mut self, version: ProtocolVersion) -> GetPromptResult {
+        self.messages = self
+            .messages
+            .into_iter()
+            .map(|message| PromptMessage {
+                content: message.content.for_version(version),
+                ..message
+            })
+            .collect();
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// One message of a `sampling/createMessage` request or its result.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/client/sampling/#messages
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SamplingMessage {
+    pub role: Role,
+    pub content: Content,
+}
+
+impl SamplingMessage {
+    /// User message with text content.
+    pub fn user(text: impl Into<String>) -> SamplingMessage {
+        SamplingMessage {
+            role: Role::User,
+            content: Content::text(text),
+        }
+    }
+
+    /// Assistant message with text content, for the earlier turns of a conversation.
+    pub fn assistant(text: impl Into<String>) -> SamplingMessage {
+        SamplingMessage {
+            role: Role::Assistant,
+            content: Content::text(text),
+        }
+    }
+}
+
+/// Model the server would like the client to sample with, matched by the client against the
+/// models it has (a substring of the name, such as `claude-3-5-sonnet` or `sonnet`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelHint {
+    pub name: String,
+}
+
+/// What matters to the server when the client chooses a model: hints in order of preference,
+/// and priorities from 0 (not important) to 1 (most important).
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/client/sampling/#model-preferences
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPreferences {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<ModelHint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_priority: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_priority: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intelligence_priority: Option<f64>,
+}
+
+impl ModelPreferences {
+    pub fn with_hint(mut self, name: impl Into<String>) -> ModelPreferences {
+        self.hints.push(ModelHint { name: name.into() });
+        self
+    }
+
+    /// Sets the priorities, each clamped between 0 and 1.
+    pub fn with_priorities(mut self, cost: f64, speed: f64, intelligence: f64) -> ModelPreferences {
+        self.cost_priority = Some(cost.clamp(0.0, 1.0));
+        self.speed_priority = Some(speed.clamp(0.0, 1.0));
+        self.intelligence_priority = Some(intelligence.clamp(0.0, 1.0));
+        self
+    }
+}
+
+/// Params of a `sampling/createMessage` request, asking the client for a completion of
+/// `messages`.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/client/sampling/#creating-messages
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageRequest {
+    pub messages: Vec<SamplingMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<ModelPreferences>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Maximum number of tokens to sample, which the client may lower
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+}
+
+impl CreateMessageRequest {
+    pub fn new(messages: Vec<SamplingMessage>, max_tokens: u32) -> CreateMessageRequest {
+        CreateMessageRequest {
+            messages,
+            model_preferences: None,
+            system_prompt: None,
+            max_tokens,
+            temperature: None,
+            stop_sequences: Vec::new(),
+        }
+    }
+
+    pub fn with_message(mut self, message: SamplingMessage) -> CreateMessageRequest {
+        self.messages.push(message);
+        self
+    }
+
+    pub fn with_model_preferences(mut self, preferences: ModelPreferences) -> CreateMessageRequest {
+        self.model_preferences = Some(preferences);
+        self
+    }
+
+    pub fn with_system_prompt(mut self, system_prompt: impl Into<String>) -> CreateMessageRequest {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f64) -> CreateMessageRequest {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_stop_sequence(mut self, stop: impl Into<String>) -> CreateMessageRequest {
+        self.stop_sequences.push(stop.into());
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Result of a `sampling/createMessage` request: the sampled message and the model that
+/// produced it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageResult {
+    pub role: Role,
+    pub content: Content,
+    pub model: String,
+    /// `endTurn`, `stopSequence`, `maxTokens` or another reason given by the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
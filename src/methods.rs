@@ -0,0 +1,244 @@
+//! The methods the server serves, with the JSON schema of their params. With `introspection`,
+//! `server/methods` lists them for client authors testing against the server, along with the
+//! methods of the registered extensions and the application error codes.
+//!
+//! `allowed_methods` and `denied_methods` turn requests off by method pattern, whoever serves
+//! them, see [`is_allowed`].
+
+use crate::{
+    builtin::env::matches_case_sensitive, config::Config, errors::ErrorRegistry,
+    extensions::ExtensionRegistry,
+};
+use serde_json::{json, Value};
+
+/// A method of the server.
+struct Method {
+    name: &'static str,
+    /// True for notifications, which get no response
+    notification: bool,
+    /// False when the settings disable the method
+    enabled: fn(&Config) -> bool,
+    /// JSON schema of the params
+    params: fn() -> Value,
+}
+
+/// Requests and notifications served by the server.
+const METHODS: &[Method] = &[
+    request("initialize", always, || {
+        json!({
+            "type": "object",
+            "properties": {
+                "protocolVersion": { "type": "string" },
+                "capabilities": { "type": "object" },
+                "clientInfo": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "version": { "type": "string" },
+                    },
+                    "required": ["name", "version"],
+                },
+            },
+            "required": ["protocolVersion", "capabilities", "clientInfo"],
+        })
+    }),
+    request("ping", always, no_params),
+    request("resources/list", always, list_params),
+    request("resources/templates/list", always, list_params),
+    request("resources/read", always, || {
+        json!({
+            "type": "object",
+            "properties": {
+                "uri": { "type": "string" },
+                "offset": { "type": "integer" },
+                "length": { "type": "integer", "minimum": 0 },
+                "cursor": { "type": "string" },
+            },
+            "required": ["uri"],
+        })
+    }),
+    request("resources/subscribe", always, uri_params),
+    request("resources/unsubscribe", always, uri_params),
+    request("prompts/list", always, list_params),
+    request("prompts/get", always, || {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "arguments": { "type": "object", "additionalProperties": { "type": "string" } },
+            },
+            "required": ["name"],
+        })
+    }),
+    request("tools/list", always, list_params),
+    request("tools/call", always, || {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "arguments": { "type": "object" },
+            },
+            "required": ["name"],
+        })
+    }),
+    request("logging/setLevel", always, || {
+        json!({
+            "type": "object",
+            "properties": {
+                "level": {
+                    "enum": [
+                        "debug", "info", "notice", "warning", "error", "critical", "alert",
+                        "emergency",
+                    ],
+                },
+            },
+            "required": ["level"],
+        })
+    }),
+    request("completion/complete", always, || {
+        json!({
+            "type": "object",
+            "properties": {
+                "ref": {
+                    "type": "object",
+                    "properties": { "type": { "enum": ["ref/prompt", "ref/resource"] } },
+                    "required": ["type"],
+                },
+                "argument": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "value": { "type": "string" },
+                    },
+                    "required": ["name", "value"],
+                },
+            },
+            "required": ["ref", "argument"],
+        })
+    }),
+    request(
+        "audit/query",
+        |config| config.audit_query,
+        || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "tool": { "type": "string" },
+                    "session": { "type": "string" },
+                    "outcome": { "enum": ["ok", "error", "failed", "denied"] },
+                    "since": { "type": "string", "format": "date-time" },
+                    "limit": { "type": "integer", "minimum": 0 },
+                },
+            })
+        },
+    ),
+    request("metrics/dump", metrics_enabled, no_params),
+    request("server/methods", |config| config.introspection, no_params),
+    Method {
+        name: "notifications/initialized",
+        notification: true,
+        enabled: always,
+        params: no_params,
+    },
+    Method {
+        name: "notifications/cancelled",
+        notification: true,
+        enabled: always,
+        params: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "requestId": { "type": ["string", "integer"] },
+                    "reason": { "type": "string" },
+                },
+                "required": ["requestId"],
+            })
+        },
+    },
+];
+
+const fn request(
+    name: &'static str,
+    enabled: fn(&Config) -> bool,
+    params: fn() -> Value,
+) -> Method {
+    Method {
+        name,
+        notification: false,
+        enabled,
+        params,
+    }
+}
+
+fn always(_config: &Config) -> bool {
+    true
+}
+
+fn metrics_enabled(config: &Config) -> bool {
+    cfg!(feature = "metrics") && config.metrics
+}
+
+fn no_params() -> Value {
+    json!({ "type": "object" })
+}
+
+fn list_params() -> Value {
+    json!({ "type": "object", "properties": { "cursor": { "type": "string" } } })
+}
+
+fn uri_params() -> Value {
+    json!({
+        "type": "object",
+        "properties": { "uri": { "type": "string" } },
+        "required": ["uri"],
+    })
+}
+
+/// True if `method` is one of the requests of [`METHODS`], enabled or not.
+pub(crate) fn is_request(method: &str) -> bool {
+    METHODS
+        .iter()
+        .any(|known| known.name == method && !known.notification)
+}
+
+/// True if the settings let the server answer requests of `method`: `initialize`, or a method
+/// matching `allowed_methods` (when set) and none of `denied_methods`.
+pub fn is_allowed(config: &Config, method: &str) -> bool {
+    let matching = |patterns: &[String]| {
+        patterns
+            .iter()
+            .any(|pattern| matches_case_sensitive(pattern, method))
+    };
+    method == "initialize"
+        || (config.allowed_methods.as_deref().is_none_or(&matching)
+            && !matching(&config.denied_methods))
+}
+
+/// Result of `server/methods`: the enabled methods, then those of the extensions, whose params
+/// aren't known, and the application error codes.
+pub(crate) fn list(
+    config: &Config,
+    extensions: &ExtensionRegistry,
+    errors: &ErrorRegistry,
+) -> Value {
+    let mut methods: Vec<Value> = METHODS
+        .iter()
+        .filter(|method| (method.enabled)(config))
+        .filter(|method| method.notification || is_allowed(config, method.name))
+        .map(|method| {
+            json!({
+                "name": method.name,
+                "kind": if method.notification { "notification" } else { "request" },
+                "paramsSchema": (method.params)(),
+            })
+        })
+        .collect();
+    methods.extend(
+        extensions
+            .iter()
+            .flat_map(|extension| extension.methods())
+            .filter(|name| is_allowed(config, name))
+            .map(|name| json!({ "name": name, "kind": "request" })),
+    );
+    json!({ "methods": methods, "errors": errors.to_value() })
+}
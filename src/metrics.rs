@@ -0,0 +1,218 @@
+//! Request metrics: counts, errors and latency histograms by method, dumped as JSON by the
+//! `metrics/dump` method or in the Prometheus text format on `/metrics`, and exported over OTLP
+//! with the `otel` feature.
+
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+/// Upper bounds of the latency histogram buckets, in seconds (Prometheus' defaults).
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Label the requests for unknown methods are counted under, so that clients can't grow the
+/// metrics without bound.
+pub const OTHER_METHOD: &str = "other";
+
+#[derive(Default, Clone)]
+struct MethodMetrics {
+    requests: u64,
+    /// Requests answered with a JSON-RPC error (not tool results flagged `isError`)
+    errors: u64,
+    /// Number of requests in each bucket of [`BUCKETS`], the last one counting the slower ones
+    buckets: [u64; BUCKETS.len() + 1],
+    /// Total time spent, in seconds
+    seconds: f64,
+}
+
+impl MethodMetrics {
+    /// Cumulative counts of requests at most as long as each bound, ending with `+Inf`.
+    fn cumulative_buckets(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        let bounds = BUCKETS
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(["+Inf".to_string()]);
+        bounds.zip(self.buckets.iter().scan(0, |total, count| {
+            *total += count;
+            Some(*total)
+        }))
+    }
+}
+
+/// Metrics of the requests served by a [`Server`](crate::server::Server), kept since it
+/// started.
+#[derive(Default)]
+pub struct Metrics {
+    methods: Mutex<BTreeMap<String, MethodMetrics>>,
+}
+
+impl Metrics {
+    /// Records a request for `method` that took `duration`.
+    pub fn record(&self, method: &str, duration: Duration, is_error: bool) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(method.to_string()).or_default();
+        let seconds = duration.as_secs_f64();
+        metrics.requests += 1;
+        if is_error {
+            metrics.errors += 1;
+        }
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        metrics.buckets[bucket] += 1;
+        metrics.seconds += seconds;
+    }
+
+    /// Metrics by method, with the cumulative latency buckets and their upper bound.
+    pub fn to_json(&self) -> Value {
+        let methods = self.methods.lock().unwrap();
+        let methods: Map<String, Value> = methods
+            .iter()
+            .map(|(method, metrics)| {
+                let buckets: Vec<Value> = metrics
+                    .cumulative_buckets()
+                    .map(|(bound, count)| json!({ "le": bound, "count": count }))
+                    .collect();
+                let value = json!({
+                    "requests": metrics.requests,
+                    "errors": metrics.errors,
+                    "latency_seconds": { "sum": metrics.seconds, "buckets": buckets },
+                });
+                (method.clone(), value)
+            })
+            .collect();
+        json!({ "methods": methods })
+    }
+
+    /// Metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let methods = self.methods.lock().unwrap().clone();
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "# HELP mcp_requests_total Requests served, by method.\n\
+             # TYPE mcp_requests_total counter"
+        );
+        for (method, metrics) in &methods {
+            let _ = writeln!(
+                text,
+                "mcp_requests_total{{method=\"{}\"}} {}",
+                escape(method),
+                metrics.requests
+            );
+        }
+        let _ = writeln!(
+            text,
+            "# HELP mcp_request_errors_total Requests answered with an error, by method.\n\
+             # TYPE mcp_request_errors_total counter"
+        );
+        for (method, metrics) in &methods {
+            let _ = writeln!(
+                text,
+                "mcp_request_errors_total{{method=\"{}\"}} {}",
+                escape(method),
+                metrics.errors
+            );
+        }
+        let _ = writeln!(
+            text,
+            "# HELP mcp_request_duration_seconds Time spent serving requests, by method.\n\
+             # TYPE mcp_request_duration_seconds histogram"
+        );
+        for (method, metrics) in &methods {
+            let method = escape(method);
+            for (bound, count) in metrics.cumulative_buckets() {
+                let _ = writeln!(
+                    text,
+                    "mcp_request_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    method, bound, count
+                );
+            }
+            let _ = writeln!(
+                text,
+                "mcp_request_duration_seconds_sum{{method=\"{}\"}} {}\n\
+                 mcp_request_duration_seconds_count{{method=\"{}\"}} {}",
+                method, metrics.seconds, method, metrics.requests
+            );
+        }
+        text
+    }
+}
+
+#[cfg(feature = "otel")]
+impl Metrics {
+    /// Metrics as the `metrics` of an OTLP export, cumulative since `start`, both times in
+    /// nanoseconds since the Unix epoch. Empty until a request was served.
+    pub fn to_otlp(&self, start: u128, now: u128) -> Vec<Value> {
+        use crate::otel::attribute;
+
+        let methods = self.methods.lock().unwrap().clone();
+        if methods.is_empty() {
+            return Vec::new();
+        }
+        let point = |method: &str| {
+            json!({
+                "attributes": [attribute("rpc.method", json!({ "stringValue": method }))],
+                "startTimeUnixNano": start.to_string(),
+                "timeUnixNano": now.to_string(),
+            })
+        };
+        let counter = |name: &str, description: &str, count: fn(&MethodMetrics) -> u64| {
+            let points: Vec<Value> = methods
+                .iter()
+                .map(|(method, metrics)| {
+                    let mut point = point(method);
+                    point["asInt"] = json!(count(metrics).to_string());
+                    point
+                })
+                .collect();
+            json!({
+                "name": name,
+                "description": description,
+                "unit": "{request}",
+                // Cumulative
+                "sum": { "dataPoints": points, "aggregationTemporality": 2, "isMonotonic": true },
+            })
+        };
+        let histogram: Vec<Value> = methods
+            .iter()
+            .map(|(method, metrics)| {
+                let mut point = point(method);
+                point["count"] = json!(metrics.requests.to_string());
+                point["sum"] = json!(metrics.seconds);
+                point["bucketCounts"] = metrics
+                    .buckets
+                    .iter()
+                    .map(|count| json!(count.to_string()))
+                    .collect();
+                point["explicitBounds"] = json!(BUCKETS);
+                point
+            })
+            .collect();
+        vec![
+            counter("mcp.requests", "Requests served, by method.", |m| {
+                m.requests
+            }),
+            counter(
+                "mcp.request.errors",
+                "Requests answered with an error, by method.",
+                |m| m.errors,
+            ),
+            json!({
+                "name": "mcp.request.duration",
+                "description": "Time spent serving requests, by method.",
+                "unit": "s",
+                "histogram": { "dataPoints": histogram, "aggregationTemporality": 2 },
+            }),
+        ]
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,207 @@
+//! OpenTelemetry export: the spans of the requests served and the metrics of
+//! [`crate::metrics`], posted every `otel_interval_secs` to the OTLP/HTTP endpoint of a
+//! collector (`otel_endpoint`) in the JSON encoding of OTLP.
+//!
+//! Both are sent under a resource naming the server (`service.name` and `service.version`), the
+//! spans grouped by session, whose resource also has the `session.id`.
+
+use crate::{mcp::Implementation, server::Server};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Spans kept while waiting for an export, the oldest being dropped past it, so that an
+/// unreachable collector doesn't grow the memory of the server.
+const MAX_QUEUED_SPANS: usize = 4096;
+
+/// Time an export may take.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start of the cumulative metrics, when the exports started.
+static START: OnceLock<SystemTime> = OnceLock::new();
+
+/// A request served, as exported.
+pub struct RequestSpan {
+    /// Trace id of the request, see [`crate::context::trace_id`]
+    pub trace_id: String,
+    /// Session the request was read from
+    pub session: String,
+    /// Method of the request, [`crate::metrics::OTHER_METHOD`] for unknown methods
+    pub method: String,
+    /// JSON-RPC id of the request
+    pub id: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// Code and message of the error the request was answered with
+    pub error: Option<(i32, String)>,
+}
+
+/// Spans waiting for the next export.
+#[derive(Default)]
+pub struct Spans(Mutex<VecDeque<RequestSpan>>);
+
+impl Spans {
+    pub fn push(&self, span: RequestSpan) {
+        let mut spans = self.0.lock().unwrap();
+        if spans.len() == MAX_QUEUED_SPANS {
+            spans.pop_front();
+        }
+        spans.push_back(span);
+    }
+
+    fn take(&self) -> Vec<RequestSpan> {
+        self.0.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// Exports the spans and metrics of `server` every `otel_interval_secs` from a thread of its
+/// own, for as long as the server lives. Nothing is exported while `otel_endpoint` isn't set.
+pub fn spawn_exporter(server: &Arc<Server>) {
+    let server = Arc::downgrade(server);
+    START.get_or_init(SystemTime::now);
+    thread::spawn(move || loop {
+        let interval = match server.upgrade() {
+            Some(server) => Duration::from_secs(server.config().otel_interval_secs.max(1)),
+            None => return,
+        };
+        thread::sleep(interval);
+        let Some(server) = server.upgrade() else {
+            return;
+        };
+        if let Err(e) = export(&server) {
+            tracing::warn!("Cannot export to the OpenTelemetry collector: {}", e);
+        }
+    });
+}
+
+/// Posts the spans waiting and the metrics of `server` to `otel_endpoint`, if set. The spans
+/// are dropped even if the collector can't be reached.
+pub fn export(server: &Server) -> Result<(), String> {
+    let Some(endpoint) = server.config().otel_endpoint.clone() else {
+        return Ok(());
+    };
+    let endpoint = endpoint.trim_end_matches('/');
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(EXPORT_TIMEOUT))
+        .build()
+        .into();
+    let post = |path: &str, body: Value| {
+        let url = format!("{}{}", endpoint, path);
+        agent
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .send(body.to_string().as_bytes())
+            .map(|_| ())
+            .map_err(|e| format!("{}: {}", url, e))
+    };
+    let spans = server.otel_spans().take();
+    if !spans.is_empty() {
+        tracing::debug!("Exporting {} spans to {}", spans.len(), endpoint);
+        post("/v1/traces", traces(server.info(), spans))?;
+    }
+    let metrics = server.metrics().to_otlp(
+        nanos(*START.get_or_init(SystemTime::now)),
+        nanos(SystemTime::now()),
+    );
+    if !metrics.is_empty() {
+        let body = json!({
+            "resourceMetrics": [{
+                "resource": resource(server.info(), None),
+                "scopeMetrics": [{ "scope": scope(), "metrics": metrics }],
+            }],
+        });
+        post("/v1/metrics", body)?;
+    }
+    Ok(())
+}
+
+/// Body of an export of `spans`, grouped by session.
+fn traces(info: &Implementation, spans: Vec<RequestSpan>) -> Value {
+    let mut sessions: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for span in spans {
+        let mut attributes = vec![
+            attribute("rpc.system", json!({ "stringValue": "jsonrpc" })),
+            attribute("rpc.method", json!({ "stringValue": span.method })),
+            attribute("rpc.jsonrpc.request_id", json!({ "stringValue": span.id })),
+        ];
+        // Unset, or an error with the message of the JSON-RPC error
+        let status = match &span.error {
+            Some((code, message)) => {
+                attributes.push(attribute(
+                    "rpc.jsonrpc.error_code",
+                    json!({ "intValue": code.to_string() }),
+                ));
+                json!({ "code": 2, "message": message })
+            }
+            None => json!({}),
+        };
+        let value = json!({
+            "traceId": otlp_trace_id(&span.trace_id),
+            "spanId": crate::context::random_id()[..16],
+            "name": span.method,
+            // Server
+            "kind": 2,
+            "startTimeUnixNano": nanos(span.start).to_string(),
+            "endTimeUnixNano": nanos(span.end).to_string(),
+            "attributes": attributes,
+            "status": status,
+        });
+        sessions.entry(span.session).or_default().push(value);
+    }
+    let resource_spans: Vec<Value> = sessions
+        .into_iter()
+        .map(|(session, spans)| {
+            json!({
+                "resource": resource(info, Some(&session)),
+                "scopeSpans": [{ "scope": scope(), "spans": spans }],
+            })
+        })
+        .collect();
+    json!({ "resourceSpans": resource_spans })
+}
+
+/// The resource of the server, and of one of its sessions with `session`.
+fn resource(info: &Implementation, session: Option<&str>) -> Value {
+    let mut attributes = vec![
+        attribute("service.name", json!({ "stringValue": info.name })),
+        attribute("service.version", json!({ "stringValue": info.version })),
+    ];
+    if let Some(session) = session {
+        attributes.push(attribute("session.id", json!({ "stringValue": session })));
+    }
+    json!({ "attributes": attributes })
+}
+
+/// The instrumentation scope: this crate.
+fn scope() -> Value {
+    json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") })
+}
+
+pub(crate) fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+/// `trace_id` as the 32 hexadecimal digits OTLP expects: as it is if it already has that form,
+/// else a hash of it, so that the spans of a trace id a client chose still share a trace.
+fn otlp_trace_id(trace_id: &str) -> String {
+    let is_hex = trace_id.len() == 32 && trace_id.bytes().all(|byte| byte.is_ascii_hexdigit());
+    if is_hex && trace_id.bytes().any(|byte| byte != b'0') {
+        return trace_id.to_ascii_lowercase();
+    }
+    Sha256::digest(trace_id.as_bytes())[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Nanoseconds since the Unix epoch.
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0)
+}
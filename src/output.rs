@@ -0,0 +1,346 @@
+use crate::jsonrpc::{
+    JsonRpcError, JsonRpcMessage, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse,
+    ERROR_CODE_INTERNAL_ERROR,
+};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tracing::Span;
+
+/// Size of the buffer messages are written to the client through, and of the largest message
+/// serialized in memory before it is written.
+const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+/// Default number of messages that can wait for the writer before producers are held back.
+pub const DEFAULT_OUTGOING_CAPACITY: usize = 1024;
+
+/// Writer passing everything through to `inner` and handing each complete line it writes to
+/// `tap` (to record or log it).
+pub(crate) struct TapWriter<W, F> {
+    inner: W,
+    tap: F,
+    buffer: Vec<u8>,
+}
+
+impl<W, F: FnMut(&str)> TapWriter<W, F> {
+    pub(crate) fn new(inner: W, tap: F) -> TapWriter<W, F> {
+        TapWriter {
+            inner,
+            tap,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write, F: FnMut(&str)> Write for TapWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for chunk in buf[..written].split_inclusive(|b| *b == b'\n') {
+            match chunk.strip_suffix(b"\n") {
+                Some(end) => {
+                    self.buffer.extend_from_slice(end);
+                    (self.tap)(&String::from_utf8_lossy(&self.buffer));
+                    self.buffer.clear();
+                }
+                None => self.buffer.extend_from_slice(chunk),
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Any message the server writes to the client.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum OutgoingMessage {
+    Response(JsonRpcResponse),
+    Notification(JsonRpcNotification),
+    /// Request of the server, see [`crate::requests`]
+    Request(JsonRpcRequest),
+}
+
+impl JsonRpcMessage for OutgoingMessage {}
+
+impl OutgoingMessage {
+    /// What the message is, for the log: its method or id, never its payload.
+    fn describe(&self) -> String {
+        match self {
+            OutgoingMessage::Response(response) => format!("response to {}", response.id()),
+            OutgoingMessage::Notification(notification) => notification.method.clone(),
+            OutgoingMessage::Request(request) => {
+                format!("{} request {}", request.method, request.id)
+            }
+        }
+    }
+}
+
+impl From<JsonRpcResponse> for OutgoingMessage {
+    fn from(response: JsonRpcResponse) -> OutgoingMessage {
+        OutgoingMessage::Response(response)
+    }
+}
+
+impl From<JsonRpcNotification> for OutgoingMessage {
+    fn from(notification: JsonRpcNotification) -> OutgoingMessage {
+        OutgoingMessage::Notification(notification)
+    }
+}
+
+impl From<JsonRpcRequest> for OutgoingMessage {
+    fn from(request: JsonRpcRequest) -> OutgoingMessage {
+        OutgoingMessage::Request(request)
+    }
+}
+
+/// Where messages are serialized before they are written: in memory up to `WRITE_CHUNK_SIZE`
+/// bytes, and in a temporary file past that, so that a large message never takes its whole size
+/// in memory.
+#[derive(Default)]
+struct SpillBuffer {
+    memory: Vec<u8>,
+    file: Option<BufWriter<File>>,
+    len: usize,
+}
+
+impl SpillBuffer {
+    /// Empties the buffer for the next message, keeping its memory.
+    fn clear(&mut self) {
+        self.memory.clear();
+        self.file = None;
+        self.len = 0;
+    }
+
+    /// Writes what the buffer holds to `out`.
+    fn copy_to(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let Some(file) = self.file.take() else {
+            return out.write_all(&self.memory);
+        };
+        let mut file = file.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut file, out).map(|_| ())
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() && self.memory.len() + buf.len() > WRITE_CHUNK_SIZE {
+            let mut file = BufWriter::with_capacity(WRITE_CHUNK_SIZE, tempfile::tempfile()?);
+            file.write_all(&self.memory)?;
+            self.memory.clear();
+            self.file = Some(file);
+        }
+        match &mut self.file {
+            Some(file) => file.write_all(buf)?,
+            None => self.memory.extend_from_slice(buf),
+        }
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes `message` to `out` as one JSON line, without flushing. The message is serialized to
+/// `buffer` before anything is written, so that one failing to serialize never leaves part of a
+/// line behind: a response is then replaced with an INTERNAL_ERROR response to its request, and
+/// other messages are logged and skipped. Fails if `out` can't be written to.
+fn send_response(
+    out: &mut dyn Write,
+    buffer: &mut SpillBuffer,
+    message: OutgoingMessage,
+) -> io::Result<()> {
+    buffer.clear();
+    if let Err(e) = serde_json::to_writer(&mut *buffer, &message) {
+        tracing::error!("Error serializing {}: {}", message.describe(), e);
+        let OutgoingMessage::Response(response) = message else {
+            return Ok(());
+        };
+        let error = JsonRpcError::new(
+            ERROR_CODE_INTERNAL_ERROR,
+            format!("The response couldn't be serialized: {}", e),
+        );
+        buffer.clear();
+        serde_json::to_writer(
+            &mut *buffer,
+            &JsonRpcResponse::error(response.id().clone(), error),
+        )?;
+    }
+    tracing::info!("Sending message ({} bytes)", buffer.len);
+    buffer.copy_to(out)?;
+    out.write_all(b"\n")
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Messages with the span they were produced in, so writing them is attributed to it
+    messages: VecDeque<(OutgoingMessage, Span)>,
+    closed: bool,
+    /// True once the writer thread returned
+    finished: bool,
+}
+
+/// Bounded queue of outgoing messages drained by a dedicated writer thread.
+///
+/// The writer thread is the only place that writes to the client, so concurrent producers can never
+/// interleave their output: every message goes out as one complete JSON line. Handlers only pay
+/// for pushing a message, not for writing it. When the client drains messages slower than they
+/// are produced, a notification identical to one still waiting in the queue is coalesced into
+/// it, and once the queue is full producers block until the writer catches up.
+pub struct OutgoingQueue {
+    capacity: usize,
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    finished: Condvar,
+}
+
+impl OutgoingQueue {
+    pub fn new(capacity: usize) -> OutgoingQueue {
+        OutgoingQueue {
+            capacity: capacity.max(1),
+            state: Mutex::new(QueueState::default()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Queues a message, waiting for room if the queue is full.
+    pub fn push(&self, message: impl Into<OutgoingMessage>) {
+        let message = message.into();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                tracing::warn!("Outgoing queue closed, dropping {}", message.describe());
+                return;
+            }
+            if let OutgoingMessage::Notification(notification) = &message {
+                let duplicate = state.messages.iter().any(|(m, _)| match m {
+                    OutgoingMessage::Notification(n) => {
+                        n.method == notification.method && n.params == notification.params
+                    }
+                    _ => false,
+                });
+                if duplicate {
+                    tracing::info!("Coalescing queued notification {}", notification.method);
+                    return;
+                }
+            }
+            if state.messages.len() < self.capacity {
+                state.messages.push_back((message, Span::current()));
+                self.not_empty.notify_one();
+                return;
+            }
+            state = self.not_full.wait(state).unwrap();
+        }
+    }
+
+    /// Waits for the next message; returns None once the queue is closed and drained.
+    fn pop(&self) -> Option<(OutgoingMessage, Span)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(message) = state.messages.pop_front() {
+                self.not_full.notify_one();
+                return Some(message);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// True if no message is waiting.
+    fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().messages.is_empty()
+    }
+
+    /// True once [`close`](OutgoingQueue::close) was called.
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// Stops accepting messages; the writer exits once the remaining ones are written.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// Waits up to `timeout` for the writer to write the remaining messages once the queue is
+    /// closed. Returns false if it didn't finish in time.
+    pub fn wait_written(&self, timeout: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .finished
+            .wait_timeout_while(state, timeout, |state| !state.finished)
+            .unwrap();
+        state.finished
+    }
+
+    /// Called by the writer thread when it returns.
+    fn finish(&self) {
+        self.state.lock().unwrap().finished = true;
+        self.finished.notify_all();
+    }
+}
+
+/// Spawns the thread writing queued messages to `out` until the queue is closed. If writing
+/// fails (the client closed the pipe), the queue is closed so that the session stops, and the
+/// thread returns the error.
+///
+/// `out` is buffered for the whole session and flushed after every message, except that up to
+/// `notification_batch` notifications are flushed together when more messages are waiting, so
+/// that bursts (file watching, progress) take fewer writes.
+pub fn spawn_writer<W: Write + Send + 'static>(
+    queue: Arc<OutgoingQueue>,
+    out: W,
+    notification_batch: usize,
+) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let result = write_messages(&queue, out, notification_batch);
+        queue.finish();
+        result
+    })
+}
+
+fn write_messages<W: Write>(
+    queue: &OutgoingQueue,
+    out: W,
+    notification_batch: usize,
+) -> io::Result<()> {
+    let mut out = BufWriter::with_capacity(WRITE_CHUNK_SIZE, out);
+    let mut buffer = SpillBuffer::default();
+    let mut unflushed = 0;
+    while let Some((message, span)) = queue.pop() {
+        let _enter = span.enter();
+        let is_notification = matches!(message, OutgoingMessage::Notification(_));
+        unflushed += 1;
+        let result = send_response(&mut out, &mut buffer, message).and_then(|()| {
+            match is_notification && unflushed < notification_batch && !queue.is_empty() {
+                true => Ok(()),
+                false => {
+                    unflushed = 0;
+                    out.flush()
+                }
+            }
+        });
+        if let Err(e) = result {
+            tracing::error!("Cannot write to the client, ending the session: {}", e);
+            queue.close();
+            return Err(e);
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,142 @@
+//! Pagination of `tools/list`, `resources/list`, `resources/templates/list` and `prompts/list`:
+//! with `page_size` set, each result holds at most that many items and a `nextCursor` for the
+//! rest. The large resources are read in pages of `resource_page_size` bytes the same way.
+//! Cursors are opaque to the clients: the base64 of the method, the position of the page and
+//! when the cursor was issued, followed by their HMAC-SHA256, so that forged, altered or expired
+//! (after `cursor_ttl_secs`) cursors are refused with INVALID_PARAMS.
+
+use crate::{
+    config::Config,
+    context::random_id,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Length of the HMAC-SHA256 at the end of a cursor.
+const TAG_SIZE: usize = 32;
+
+/// One page of a list.
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, if there is one
+    pub next_cursor: Option<String>,
+}
+
+/// Issues and checks the cursors, signed with `cursor_secret` or else with a key drawn when the
+/// server starts, in which case the cursors don't survive a restart.
+pub(crate) struct Paginator {
+    key: String,
+}
+
+impl Default for Paginator {
+    fn default() -> Paginator {
+        Paginator {
+            key: format!("{}{}", random_id(), random_id()),
+        }
+    }
+}
+
+impl Paginator {
+    /// The page of `items` starting at `cursor`, or the first page without one.
+    pub(crate) fn page<T>(
+        &self,
+        config: &Config,
+        method: &str,
+        cursor: Option<&str>,
+        mut items: Vec<T>,
+    ) -> Result<Page<T>, JsonRpcError> {
+        let start = match cursor {
+            Some(cursor) => self.position(config, method, cursor)?,
+            None => 0,
+        };
+        if start > items.len() {
+            return Err(invalid_cursor("it is past the end of the list"));
+        }
+        items.drain(..start);
+        if config.page_size == 0 || items.len() <= config.page_size {
+            return Ok(Page {
+                items,
+                next_cursor: None,
+            });
+        }
+        items.truncate(config.page_size);
+        let next = start + config.page_size;
+        Ok(Page {
+            items,
+            next_cursor: Some(self.cursor(config, method, next)),
+        })
+    }
+
+    fn mac(&self, config: &Config) -> Hmac<Sha256> {
+        let key = config.cursor_secret.as_deref().unwrap_or(&self.key);
+        Hmac::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any size")
+    }
+
+    /// Cursor of the page at `position` in the list of `method`.
+    pub(crate) fn cursor(&self, config: &Config, method: &str, position: usize) -> String {
+        let mut token = format!("{}\n{}\n{}", method, position, now()).into_bytes();
+        let mut mac = self.mac(config);
+        mac.update(&token);
+        token.extend_from_slice(&mac.finalize().into_bytes());
+        BASE64.encode(token)
+    }
+
+    /// Position in the list of `method` of the page `cursor` points to.
+    pub(crate) fn position(
+        &self,
+        config: &Config,
+        method: &str,
+        cursor: &str,
+    ) -> Result<usize, JsonRpcError> {
+        let token = BASE64
+            .decode(cursor)
+            .map_err(|_| invalid_cursor("it isn't one of this server's cursors"))?;
+        if token.len() <= TAG_SIZE {
+            return Err(invalid_cursor("it isn't one of this server's cursors"));
+        }
+        let (payload, tag) = token.split_at(token.len() - TAG_SIZE);
+        let mut mac = self.mac(config);
+        mac.update(payload);
+        mac.verify_slice(tag)
+            .map_err(|_| invalid_cursor("its signature doesn't match"))?;
+        let payload = String::from_utf8_lossy(payload);
+        let mut fields = payload.split('\n');
+        let (Some(cursor_method), Some(position), Some(issued), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid_cursor("it isn't one of this server's cursors"));
+        };
+        if cursor_method != method {
+            return Err(invalid_cursor(&format!(
+                "it was issued by {}",
+                cursor_method
+            )));
+        }
+        let issued: u64 = issued
+            .parse()
+            .map_err(|_| invalid_cursor("it isn't one of this server's cursors"))?;
+        if config.cursor_ttl_secs > 0 && now().saturating_sub(issued) > config.cursor_ttl_secs {
+            return Err(invalid_cursor("it expired, list again from the start"));
+        }
+        position
+            .parse()
+            .map_err(|_| invalid_cursor("it isn't one of this server's cursors"))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn invalid_cursor(reason: &str) -> JsonRpcError {
+    JsonRpcError::new(
+        ERROR_CODE_INVALID_PARAMS,
+        format!("Invalid cursor: {}", reason),
+    )
+}
@@ -0,0 +1,232 @@
+//! Tool manifests: JSON or YAML files mapping tools to command templates.
+//!
+//! ```yaml
+//! tools:
+//!   - name: disk_usage
+//!     description: Size of a directory
+//!     inputSchema:
+//!       type: object
+//!       properties:
+//!         path: { type: string }
+//!       required: [path]
+//!     command: du
+//!     args: ["-sh", "{path}"]
+//! ```
+//!
+//! `{name}` in `args` and `cwd` is replaced by the argument `name` (`{{` and `}}` stand for
+//! literal braces). An element of `args` that is only a placeholder is removed when the
+//! argument is absent, and expanded to one element per item when it is an array. The command
+//! runs without a shell, so arguments can't inject other commands. With `json_input`, the
+//! arguments are also passed as a JSON object on stdin. See [`subprocess`] for how the output
+//! is turned into the result.
+
+use crate::{
+    config::Config,
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INTERNAL_ERROR, ERROR_CODE_INVALID_PARAMS},
+    plugins::subprocess,
+    roots,
+    tools::Tool,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+#[derive(Deserialize)]
+struct Manifest {
+    tools: Vec<ToolTemplate>,
+}
+
+/// A tool of a manifest.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolTemplate {
+    name: String,
+    /// Name to display, if different from `name`
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(rename = "inputSchema", default = "empty_schema")]
+    input_schema: Value,
+    #[serde(default)]
+    annotations: Option<Value>,
+    /// Executable to run
+    command: String,
+    /// Arguments passed to the executable, with placeholders
+    #[serde(default)]
+    args: Vec<String>,
+    /// Working directory, with placeholders; defaults to the first root directory
+    #[serde(default)]
+    cwd: Option<String>,
+    /// Passes the arguments as a JSON object on stdin
+    #[serde(default)]
+    json_input: bool,
+    /// Time after which the command is killed, `command_timeout_secs` by default
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+fn empty_schema() -> Value {
+    json!({ "type": "object" })
+}
+
+/// Loads the tools of the `tool_manifests` files. Files that can't be loaded are logged and
+/// skipped.
+pub fn load_tools(config: &Config) -> Vec<Tool> {
+    let mut tools = Vec::new();
+    for path in &config.tool_manifests {
+        match load(path) {
+            Ok(manifest) => {
+                for template in manifest.tools {
+                    tracing::info!("Loaded tool {} from {}", template.name, path.display());
+                    tools.push(template.into_tool());
+                }
+            }
+            Err(e) => tracing::error!("Cannot load tool manifest {}: {}", path.display(), e),
+        }
+    }
+    tools
+}
+
+/// Reads a manifest, as YAML for `.yaml`/`.yml` files and JSON otherwise.
+fn load(path: &Path) -> Result<Manifest, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    if yaml {
+        serde_yaml::from_str(&text).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+impl ToolTemplate {
+    fn into_tool(self) -> Tool {
+        let name = self.name.clone();
+        let description = self.description.clone();
+        let input_schema = self.input_schema.clone();
+        let title = self.title.clone();
+        let annotations = self.annotations.clone();
+        let template = Arc::new(self);
+        let planned = template.clone();
+        let tool = Tool::new(name, description, input_schema, move |ctx, arguments| {
+            let args = template.expand_args(arguments)?;
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let cwd = template.cwd(ctx, arguments)?;
+            let stdin = template
+                .json_input
+                .then(|| arguments.to_string().into_bytes());
+            let timeout = template.timeout_secs.map(Duration::from_secs);
+            Ok(subprocess::run(
+                ctx,
+                &template.command,
+                &args,
+                cwd,
+                stdin,
+                timeout,
+            ))
+        })
+        .with_dry_run(move |ctx, arguments| {
+            let args = planned.expand_args(arguments)?;
+            let cwd = planned.cwd(ctx, arguments)?;
+            Ok(json!({ "commandsRun": [{ "command": planned.command, "args": args, "cwd": cwd }] }))
+        });
+        let tool = match title {
+            Some(title) => tool.with_title(title),
+            None => tool,
+        };
+        match annotations {
+            Some(annotations) => tool.with_annotations(annotations),
+            None => tool,
+        }
+    }
+
+    /// `args` with the placeholders replaced by the arguments of the call.
+    /// Working directory of the command, which must lie inside the roots (INTERNAL_ERROR,
+    /// which the model sees, otherwise).
+    fn cwd(
+        &self,
+        ctx: &RequestContext,
+        arguments: &Value,
+    ) -> Result<Option<PathBuf>, JsonRpcError> {
+        match &self.cwd {
+            Some(cwd) => roots::resolve(&ctx.config.roots, &substitute(cwd, arguments)?)
+                .map(Some)
+                .map_err(|e| JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, e)),
+            None => Ok(ctx.config.roots.first().cloned()),
+        }
+    }
+
+    fn expand_args(&self, arguments: &Value) -> Result<Vec<String>, JsonRpcError> {
+        let mut args = Vec::new();
+        for arg in &self.args {
+            let whole = arg
+                .strip_prefix('{')
+                .and_then(|arg| arg.strip_suffix('}'))
+                .filter(|name| !name.contains(['{', '}']));
+            match whole.map(|name| arguments.get(name)) {
+                Some(None | Some(Value::Null)) => {}
+                Some(Some(Value::Array(items))) => {
+                    args.extend(items.iter().map(to_arg));
+                }
+                Some(Some(value)) => args.push(to_arg(value)),
+                None => args.push(substitute(arg, arguments)?),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Replaces the `{name}` placeholders of `template` by the arguments.
+fn substitute(template: &str, arguments: &Value) -> Result<String, JsonRpcError> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        result.push_str(&rest[..start]);
+        let brace = rest.as_bytes()[start];
+        rest = &rest[start + 1..];
+        // Doubled braces are literal ones
+        if rest.as_bytes().first() == Some(&brace) {
+            result.push(brace as char);
+            rest = &rest[1..];
+            continue;
+        }
+        let end = match (brace, rest.find('}')) {
+            (b'{', Some(end)) => end,
+            _ => {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    format!("Invalid placeholder in '{}'", template),
+                ))
+            }
+        };
+        let name = &rest[..end];
+        match arguments.get(name) {
+            Some(value) if !value.is_null() => result.push_str(&to_arg(value)),
+            _ => {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    format!("Missing argument '{}'", name),
+                ))
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Command-line form of an argument value: strings as is, anything else as JSON.
+fn to_arg(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
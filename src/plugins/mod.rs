@@ -0,0 +1,27 @@
+//! Tools and prompts loaded at startup from files outside the server binary.
+
+pub mod manifest;
+pub mod openapi;
+pub mod script;
+pub mod subprocess;
+pub mod templates;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "builtin")]
+pub mod watch;
+
+use crate::{config::Config, tools::Tool};
+
+/// Loads the tools defined by local files: the WebAssembly plugins, the scripts and the tool
+/// manifests, which `watch::ToolWatcher` reloads when they change.
+pub fn load_tools(config: &Config) -> Vec<Tool> {
+    #[cfg(feature = "wasm")]
+    let plugins = wasm::load_tools(config);
+    #[cfg(not(feature = "wasm"))]
+    let plugins = Vec::new();
+    plugins
+        .into_iter()
+        .chain(script::load_tools(config))
+        .chain(manifest::load_tools(config))
+        .collect()
+}
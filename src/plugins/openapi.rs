@@ -0,0 +1,289 @@
+//! OpenAPI 3 documents: each operation becomes a tool performing the HTTP request.
+//!
+//! The tool is named after the `operationId` (or the method and path when absent). Its
+//! arguments are the operation's path, query and header parameters, plus `body` for a JSON
+//! request body. Local `$ref`s are inlined, since clients only see the tool's schema. Calls go
+//! through the same code as `http_fetch`, so the `fetch_*` settings apply.
+
+use crate::{
+    builtin::http,
+    config::{Config, OpenApiSpec},
+    context::RequestContext,
+    mcp::CallToolResult,
+    tools::Tool,
+};
+use serde_json::{json, Map, Value};
+use std::fs;
+
+const METHODS: [&str; 7] = ["get", "put", "post", "delete", "patch", "head", "options"];
+/// Depth after which nested `$ref`s are no longer inlined, which also stops recursive schemas.
+const MAX_REF_DEPTH: usize = 8;
+/// Maximum length of tool names.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Where a parameter goes in the request.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Location {
+    Path,
+    Query,
+    Header,
+}
+
+/// An operation of the document, turned into a tool.
+struct Operation {
+    method: String,
+    /// Full URL template, with `{name}` placeholders for path parameters
+    url: String,
+    parameters: Vec<(String, Location)>,
+    /// Headers sent with every request
+    headers: Map<String, Value>,
+}
+
+/// Loads the tools of the `openapi` documents. Documents that can't be loaded are logged and
+/// skipped.
+pub fn load_tools(config: &Config) -> Vec<Tool> {
+    let mut tools = Vec::new();
+    for spec in &config.openapi {
+        match load(spec) {
+            Ok(loaded) => {
+                tracing::info!(
+                    "Loaded {} tools from OpenAPI document {}",
+                    loaded.len(),
+                    spec.path.display()
+                );
+                tools.extend(loaded);
+            }
+            Err(e) => tracing::error!(
+                "Cannot load OpenAPI document {}: {}",
+                spec.path.display(),
+                e
+            ),
+        }
+    }
+    tools
+}
+
+fn load(spec: &OpenApiSpec) -> Result<Vec<Tool>, String> {
+    let text = fs::read_to_string(&spec.path).map_err(|e| e.to_string())?;
+    // YAML is a superset of JSON
+    let document: Value = serde_yaml::from_str(&text).map_err(|e| e.to_string())?;
+    let base_url = match &spec.base_url {
+        Some(url) => url.clone(),
+        None => document["servers"][0]["url"]
+            .as_str()
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .ok_or("the document has no absolute server URL, set `base_url`")?
+            .to_string(),
+    };
+    let headers: Map<String, Value> = spec
+        .headers
+        .iter()
+        .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+        .collect();
+
+    let mut tools = Vec::new();
+    let paths = document["paths"]
+        .as_object()
+        .ok_or("the document has no paths")?;
+    for (path, item) in paths {
+        let item = resolve(&document, item, 0);
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+            // Parameters of the path item apply to all its operations
+            let parameters = item["parameters"]
+                .as_array()
+                .into_iter()
+                .chain(operation["parameters"].as_array())
+                .flatten()
+                .map(|parameter| resolve(&document, parameter, 0));
+            let mut properties = Map::new();
+            let mut required = Vec::new();
+            let mut locations = Vec::new();
+            for parameter in parameters {
+                let Some(name) = parameter["name"].as_str() else {
+                    continue;
+                };
+                let location = match parameter["in"].as_str() {
+                    Some("path") => Location::Path,
+                    Some("query") => Location::Query,
+                    Some("header") => Location::Header,
+                    _ => continue,
+                };
+                let mut schema = match parameter.get("schema") {
+                    Some(schema) => resolve(&document, schema, 0),
+                    None => json!({ "type": "string" }),
+                };
+                if let (Some(description), Some(schema)) =
+                    (parameter.get("description"), schema.as_object_mut())
+                {
+                    schema.insert("description".to_string(), description.clone());
+                }
+                properties.insert(name.to_string(), schema);
+                if location == Location::Path || parameter["required"] == json!(true) {
+                    required.push(name.to_string());
+                }
+                locations.push((name.to_string(), location));
+            }
+            if let Some(body) = operation.get("requestBody") {
+                let body = resolve(&document, body, 0);
+                if let Some(schema) = body["content"]["application/json"].get("schema") {
+                    properties.insert("body".to_string(), resolve(&document, schema, 0));
+                    if body["required"] == json!(true) {
+                        required.push("body".to_string());
+                    }
+                }
+            }
+
+            let description = [&operation["summary"], &operation["description"]]
+                .iter()
+                .filter_map(|text| text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            let name = match operation["operationId"].as_str() {
+                Some(id) => tool_name(id),
+                None => tool_name(&format!("{}_{}", method, path)),
+            };
+            let operation = Operation {
+                method: method.to_ascii_uppercase(),
+                url: format!("{}{}", base_url.trim_end_matches('/'), path),
+                parameters: locations,
+                headers: headers.clone(),
+            };
+            let read_only = matches!(method, "get" | "head" | "options");
+            tools.push(
+                Tool::new(
+                    name,
+                    description,
+                    json!({
+                        "type": "object",
+                        "properties": properties,
+                        "required": required,
+                    }),
+                    move |ctx, arguments| Ok(operation.call(ctx, arguments)),
+                )
+                .with_annotations(json!({ "readOnlyHint": read_only, "openWorldHint": true })),
+            );
+        }
+    }
+    Ok(tools)
+}
+
+impl Operation {
+    fn call(&self, ctx: &RequestContext, arguments: &Value) -> CallToolResult {
+        let mut url = self.url.clone();
+        let mut query = Vec::new();
+        let mut headers = self.headers.clone();
+        for (name, location) in &self.parameters {
+            let Some(value) = arguments.get(name).filter(|value| !value.is_null()) else {
+                if *location == Location::Path {
+                    return CallToolResult::error(format!("missing path parameter '{}'", name));
+                }
+                continue;
+            };
+            match location {
+                Location::Path => {
+                    url = url.replace(&format!("{{{}}}", name), &encode(&to_text(value)));
+                }
+                Location::Query => {
+                    let values = match value {
+                        Value::Array(values) => values.iter().collect(),
+                        value => vec![value],
+                    };
+                    for value in values {
+                        query.push(format!("{}={}", encode(name), encode(&to_text(value))));
+                    }
+                }
+                Location::Header => {
+                    headers.insert(name.clone(), Value::String(to_text(value)));
+                }
+            }
+        }
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query.join("&"));
+        }
+        let body = match arguments.get("body") {
+            Some(body) => {
+                headers.insert("Content-Type".to_string(), json!("application/json"));
+                body.to_string()
+            }
+            None => String::new(),
+        };
+        match http::fetch(ctx, &self.method, &url, Some(&headers), &body) {
+            Ok(response) => CallToolResult {
+                is_error: response["status"]
+                    .as_u64()
+                    .is_some_and(|status| status >= 400),
+                ..CallToolResult::text(response.to_string())
+            },
+            Err(e) => CallToolResult::error(e),
+        }
+    }
+}
+
+/// Inlines the local `$ref`s (`#/components/...`) of `value`.
+fn resolve(document: &Value, value: &Value, depth: usize) -> Value {
+    match value {
+        Value::Object(object) => {
+            if let Some(reference) = object.get("$ref").and_then(Value::as_str) {
+                let target = reference
+                    .strip_prefix('#')
+                    .and_then(|pointer| document.pointer(pointer));
+                return match target {
+                    Some(target) if depth < MAX_REF_DEPTH => resolve(document, target, depth + 1),
+                    _ => json!({}),
+                };
+            }
+            let object = object
+                .iter()
+                .map(|(key, value)| (key.clone(), resolve(document, value, depth)))
+                .collect();
+            Value::Object(object)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| resolve(document, item, depth))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// Tool name made of the letters, digits, `_` and `-` of `text`.
+fn tool_name(text: &str) -> String {
+    let mut name = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_end_matches('_')
+        .chars()
+        .take(MAX_NAME_LENGTH)
+        .collect()
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986.
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
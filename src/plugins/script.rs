@@ -0,0 +1,129 @@
+//! Rhai scripts: each `*.rhai` file of `script_dir` defines one tool.
+//!
+//! A script sets the constants `NAME` (the file name without extension by default),
+//! `DESCRIPTION` and `SCHEMA` (an object map holding the JSON schema of the arguments) and
+//! defines `fn run(args)`, which receives the arguments as an object map. A string returned by
+//! `run` is the tool result as is, anything else is returned as JSON; `throw "message"` makes
+//! the call fail with that message. See https://rhai.rs/book/ for the language.
+
+use crate::{config::Config, context::CancellationToken, mcp::CallToolResult, tools::Tool};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Loads the tools of the scripts in `script_dir`, in file name order. Scripts that can't be
+/// loaded are logged and skipped.
+pub fn load_tools(config: &Config) -> Vec<Tool> {
+    let Some(dir) = &config.script_dir else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect(),
+        Err(e) => {
+            tracing::error!("Cannot read script directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match load_tool(path, config) {
+            Ok(tool) => {
+                tracing::info!("Loaded script {} as tool {}", path.display(), tool.name);
+                Some(tool)
+            }
+            Err(e) => {
+                tracing::error!("Cannot load script {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn load_tool(path: &Path, config: &Config) -> Result<Tool, String> {
+    let timeout = Duration::from_secs(config.plugin_timeout_secs);
+    let engine = new_engine(timeout, None);
+    let ast = engine
+        .compile_file(path.to_path_buf())
+        .map_err(|e| e.to_string())?;
+    if !ast
+        .iter_functions()
+        .any(|f| f.name == "run" && f.params.len() == 1)
+    {
+        return Err("the script doesn't define `fn run(args)`".to_string());
+    }
+
+    // Run the top level of the script to get its constants
+    let mut scope = Scope::new();
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|e| e.to_string())?;
+    let name = match scope.get_value::<String>("NAME") {
+        Some(name) => name,
+        None => path
+            .file_stem()
+            .ok_or("invalid file name")?
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let description = scope.get_value::<String>("DESCRIPTION").unwrap_or_default();
+    let schema = match scope.get_value::<Dynamic>("SCHEMA") {
+        Some(schema) => {
+            rhai::serde::from_dynamic(&schema).map_err(|e| format!("invalid SCHEMA: {}", e))?
+        }
+        None => json!({ "type": "object" }),
+    };
+
+    let tool = Tool::new(name, description, schema, move |ctx, arguments| {
+        let timeout = ctx.limit_timeout(Duration::from_secs(ctx.config.plugin_timeout_secs));
+        let engine = new_engine(timeout, Some(ctx.cancellation.clone()));
+        let result = rhai::serde::to_dynamic(arguments).and_then(|args| {
+            engine.call_fn_with_options::<Dynamic>(
+                CallFnOptions::new().eval_ast(false),
+                &mut Scope::new(),
+                &ast,
+                "run",
+                (args,),
+            )
+        });
+        Ok(match result {
+            Ok(value) if value.is_string() => CallToolResult::text(value.cast::<String>()),
+            Ok(value) => match rhai::serde::from_dynamic::<Value>(&value) {
+                Ok(value) => CallToolResult::text(value.to_string()),
+                Err(e) => CallToolResult::error(format!("invalid script result: {}", e)),
+            },
+            Err(e) => CallToolResult::error(describe_error(*e)),
+        })
+    });
+    Ok(tool)
+}
+
+/// Engine aborting scripts after `timeout` or once `cancellation` is flipped. `print` and
+/// `debug` go to the log since stdout is the protocol stream.
+fn new_engine(timeout: Duration, cancellation: Option<CancellationToken>) -> Engine {
+    let mut engine = Engine::new();
+    let deadline = Instant::now() + timeout;
+    engine.on_progress(move |_| {
+        let cancelled = cancellation.as_ref().is_some_and(|c| c.is_cancelled());
+        (cancelled || Instant::now() >= deadline).then_some(Dynamic::UNIT)
+    });
+    engine.on_print(|text| tracing::info!("Script: {}", text));
+    engine.on_debug(|text, _, position| tracing::debug!("Script {}: {}", position, text));
+    engine
+}
+
+fn describe_error(error: EvalAltResult) -> String {
+    match error {
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        EvalAltResult::ErrorTerminated(..) => "interrupted (timed out or cancelled)".to_string(),
+        e => e.to_string(),
+    }
+}
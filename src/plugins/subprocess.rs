@@ -0,0 +1,121 @@
+//! Tools implemented by an external executable: each call spawns it with the arguments as JSON
+//! on stdin, and its stdout is the result.
+
+use crate::{builtin::command, context::RequestContext, mcp::CallToolResult, tools::Tool};
+use serde_json::{json, Value};
+use std::{path::PathBuf, time::Duration};
+
+/// Builder of a tool backed by an executable.
+///
+/// The executable receives the call arguments as a JSON object on stdin. Its stdout is the
+/// result: either a `tools/call` result object (`{"content": [...], "isError": ...}`) or any
+/// other text, returned as is. A non-zero exit code makes the call fail with stderr, and the
+/// process is killed after the timeout (`command_timeout_secs` unless set on the tool).
+pub struct SubprocessTool {
+    name: String,
+    description: String,
+    input_schema: Value,
+    command: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    timeout: Option<Duration>,
+}
+
+impl SubprocessTool {
+    pub fn new(name: impl Into<String>, command: impl Into<String>) -> SubprocessTool {
+        SubprocessTool {
+            name: name.into(),
+            description: String::new(),
+            input_schema: json!({ "type": "object" }),
+            command: command.into(),
+            args: Vec::new(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> SubprocessTool {
+        self.description = description.into();
+        self
+    }
+
+    pub fn with_input_schema(mut self, input_schema: Value) -> SubprocessTool {
+        self.input_schema = input_schema;
+        self
+    }
+
+    /// Fixed arguments passed to the executable.
+    pub fn with_args(mut self, args: Vec<String>) -> SubprocessTool {
+        self.args = args;
+        self
+    }
+
+    /// Working directory of the executable; the first root directory by default.
+    pub fn with_cwd(mut self, cwd: impl Into<PathBuf>) -> SubprocessTool {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> SubprocessTool {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn into_tool(self) -> Tool {
+        let name = self.name.clone();
+        let description = self.description.clone();
+        let input_schema = self.input_schema.clone();
+        Tool::new(name, description, input_schema, move |ctx, arguments| {
+            let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+            let cwd = self
+                .cwd
+                .clone()
+                .or_else(|| ctx.config.roots.first().cloned());
+            let stdin = arguments.to_string().into_bytes();
+            Ok(run(
+                ctx,
+                &self.command,
+                &args,
+                cwd,
+                Some(stdin),
+                self.timeout,
+            ))
+        })
+    }
+}
+
+/// Runs `command` and maps its outcome to a tool result. A `tools/call` result printed on
+/// stdout is returned as is, and output that isn't text as a base64-encoded blob.
+pub(crate) fn run(
+    ctx: &RequestContext,
+    command: &str,
+    args: &[&str],
+    cwd: Option<PathBuf>,
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+) -> CallToolResult {
+    let timeout = timeout.unwrap_or(Duration::from_secs(ctx.config.command_timeout_secs));
+    let output = match command::run(ctx, command, args, cwd.as_deref(), stdin, timeout, false) {
+        Ok(output) => output,
+        Err(e) => return CallToolResult::error(e),
+    };
+    let summary = &output.summary;
+    let text = |field: &str| summary[field].as_str().unwrap_or_default().to_string();
+    if summary["timed_out"] == json!(true) {
+        CallToolResult::error(format!("{} timed out", command))
+    } else if summary["exit_code"].is_null() {
+        CallToolResult::error(format!("{} was killed", command))
+    } else if summary["exit_code"] != json!(0) {
+        let stderr = text("stderr");
+        CallToolResult::error(if stderr.is_empty() {
+            format!("{} exited with {}", command, summary["exit_code"])
+        } else {
+            stderr
+        })
+    } else if summary["stdout_encoding"] == json!("unknown") {
+        CallToolResult::new(vec![output.blob("stdout")])
+    } else {
+        let stdout = text("stdout");
+        serde_json::from_str(&stdout).unwrap_or_else(|_| CallToolResult::text(stdout))
+    }
+}
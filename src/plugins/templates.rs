@@ -0,0 +1,261 @@
+//! Prompts defined as Handlebars templates, in the `prompts` setting or in the JSON or YAML
+//! files listed in `prompt_templates`.
+//!
+//! ```yaml
+//! prompts:
+//!   - name: explain
+//!     description: Explain a file
+//!     arguments:
+//!       - { name: path, description: File to explain, required: true }
+//!       - { name: audience, description: Who the explanation is for }
+//!     messages:
+//!       - role: user
+//!         text: "Explain {{path}}{{#if audience}} to {{audience}}{{/if}}."
+//!       - role: user
+//!         resource: "{{path}}"
+//! ```
+//!
+//! The templates are rendered with the arguments of `prompts/get`, without any HTML escaping.
+//! `{{file "path"}}` inserts the text of a file under the roots. A message with `resource`
+//! instead of `text` embeds the file it names (a path or a `file://` URI) as a resource, and one
+//! with `image` the image file it names. Few-shot prompts alternate `user` and `assistant`
+//! messages.
+
+use crate::{
+    builtin::{cache::CachedFile, mime},
+    config::{Config, PromptTemplate},
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{Content, GetPromptResult, PromptMessage, ResourceContents, Role},
+    policy,
+    prompts::Prompt,
+    roots,
+};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(Deserialize)]
+struct TemplateFile {
+    prompts: Vec<PromptTemplate>,
+}
+
+/// Loads the prompts of the `prompts` setting and of the `prompt_templates` files. Files and
+/// templates that can't be loaded are logged and skipped.
+pub fn load_prompts(config: &Config) -> Vec<Prompt> {
+    let mut templates = config.prompts.clone();
+    for path in &config.prompt_templates {
+        match load(path) {
+            Ok(file) => templates.extend(file.prompts),
+            Err(e) => tracing::error!("Cannot load prompt templates {}: {}", path.display(), e),
+        }
+    }
+    templates
+        .into_iter()
+        .filter_map(|template| {
+            let name = template.name.clone();
+            match compile(template) {
+                Ok(prompt) => {
+                    tracing::info!("Loaded prompt template {}", name);
+                    Some(prompt)
+                }
+                Err(e) => {
+                    tracing::error!("Invalid prompt template {}: {}", name, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Reads a template file, as YAML for `.yaml`/`.yml` files and JSON otherwise.
+fn load(path: &Path) -> Result<TemplateFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    if yaml {
+        serde_yaml::from_str(&text).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+/// A compiled message, whose template is registered under [`template_name`].
+struct Message {
+    role: Role,
+    kind: MessageKind,
+}
+
+/// What the rendered template of a message is.
+#[derive(Clone, Copy)]
+enum MessageKind {
+    Text,
+    /// The path of a file to embed
+    Resource,
+    /// The path of an image
+    Image,
+}
+
+/// Compiles the templates of the messages, so that syntax errors are reported at startup.
+fn compile(template: PromptTemplate) -> Result<Prompt, String> {
+    let mut registry = Handlebars::new();
+    registry.register_escape_fn(handlebars::no_escape);
+    let mut messages = Vec::new();
+    for (i, message) in template.messages.iter().enumerate() {
+        let (source, kind) = match (&message.text, &message.resource, &message.image) {
+            (Some(text), None, None) => (text, MessageKind::Text),
+            (None, Some(resource), None) => (resource, MessageKind::Resource),
+            (None, None, Some(image)) => (image, MessageKind::Image),
+            _ => {
+                return Err(format!(
+                    "message {} needs one of text, resource and image",
+                    i + 1
+                ))
+            }
+        };
+        registry
+            .register_template_string(&template_name(i), source)
+            .map_err(|e| e.to_string())?;
+        messages.push(Message {
+            role: message.role,
+            kind,
+        });
+    }
+    let registry = Arc::new(registry);
+    let name = template.name.clone();
+    let description = (!template.description.is_empty()).then(|| template.description.clone());
+    let mut prompt = Prompt::new(
+        template.name,
+        template.description,
+        move |ctx, arguments| {
+            render(ctx, &registry, &messages, arguments)
+                .map(|result| match &description {
+                    Some(description) => result.with_description(description.clone()),
+                    None => result,
+                })
+                .map_err(|e| {
+                    JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("Cannot render the prompt {}: {}", name, e),
+                    )
+                })
+        },
+    );
+    prompt.arguments = template.arguments;
+    Ok(match template.title {
+        Some(title) => prompt.with_title(title),
+        None => prompt,
+    })
+}
+
+fn template_name(index: usize) -> String {
+    format!("message {}", index + 1)
+}
+
+fn render(
+    ctx: &RequestContext,
+    registry: &Handlebars<'static>,
+    messages: &[Message],
+    arguments: &Value,
+) -> Result<GetPromptResult, String> {
+    // The helper reads from the roots of the request's configuration, what its scopes grant
+    let mut registry = registry.clone();
+    registry.register_helper(
+        "file",
+        Box::new(FileHelper {
+            config: ctx.config.clone(),
+            scopes: ctx.session.scopes.clone(),
+        }),
+    );
+    let mut rendered = Vec::new();
+    for (i, message) in messages.iter().enumerate() {
+        let output = registry
+            .render(&template_name(i), arguments)
+            .map_err(|e| e.to_string())?;
+        let content = match message.kind {
+            MessageKind::Text => Content::text(output),
+            MessageKind::Resource => Content::resource(embedded_file(ctx, output.trim())?),
+            MessageKind::Image => image(ctx, output.trim())?,
+        };
+        rendered.push(PromptMessage::new(message.role, content));
+    }
+    Ok(GetPromptResult::new(rendered))
+}
+
+/// The file at `path`, a path relative to the first root or a `file://` URI, which must lie
+/// under the roots and be granted by `scopes`.
+fn resolve(config: &Config, scopes: Option<&[String]>, path: &str) -> Result<PathBuf, String> {
+    let path = match roots::uri_to_path(path) {
+        Some(path) => roots::resolve(&config.roots, &path),
+        None => roots::resolve(&config.roots, path),
+    }?;
+    policy::check_file_in_scopes(config, scopes, &path)?;
+    Ok(path)
+}
+
+/// The file at `path` as an embedded resource: text, or a blob for binary files.
+fn embedded_file(ctx: &RequestContext, path: &str) -> Result<ResourceContents, String> {
+    let path = resolve(&ctx.config, ctx.session.scopes.as_deref(), path)?;
+    let file = fs::read(&path)
+        .map(|contents| CachedFile {
+            contents: Arc::new(contents),
+            modified: fs::metadata(&path).and_then(|m| m.modified()).ok(),
+        })
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    Ok(mime::file_contents(
+        &roots::path_to_uri(&path),
+        &path,
+        &file,
+    ))
+}
+
+/// The image file at `path`, whose MIME type must be an image type.
+fn image(ctx: &RequestContext, path: &str) -> Result<Content, String> {
+    let path = resolve(&ctx.config, ctx.session.scopes.as_deref(), path)?;
+    let bytes = fs::read(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let mime_type = mime::guess_mime_type(&path, Some(&bytes));
+    if !mime_type.starts_with("image/") {
+        return Err(format!(
+            "{} isn't an image but {}",
+            path.display(),
+            mime_type
+        ));
+    }
+    Ok(Content::image(&bytes, mime_type))
+}
+
+/// `{{file "path"}}`: the text of a file under the roots.
+struct FileHelper {
+    config: Arc<Config>,
+    scopes: Option<Vec<String>>,
+}
+
+impl HelperDef for FileHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let path = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("file", 0))?;
+        let path = resolve(&self.config, self.scopes.as_deref(), path)
+            .map_err(RenderErrorReason::Other)?;
+        let text = fs::read_to_string(&path).map_err(|e| {
+            RenderErrorReason::Other(format!("cannot read {}: {}", path.display(), e))
+        })?;
+        Ok(ScopedJson::Derived(Value::String(text)))
+    }
+}
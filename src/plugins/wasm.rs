@@ -0,0 +1,228 @@
+//! WebAssembly plugins: each `*.wasm` module of `plugin_dir` implements one tool.
+//!
+//! Plugins run sandboxed: no imports are provided (no WASI, no host functions), each call runs
+//! in a fresh instance whose memory is capped by `plugin_max_memory`, and calls are aborted after
+//! `plugin_timeout_secs` or when the client cancels the request.
+//!
+//! A plugin module exports:
+//! - `memory`: its linear memory;
+//! - `alloc(len: i32) -> i32`: allocates `len` bytes and returns their address, used by the
+//!   server to pass input;
+//! - `describe() -> i64`: the tool definition, a JSON object with `name`, `description` and
+//!   `inputSchema`;
+//! - `call(ptr: i32, len: i32) -> i64`: runs the tool with the JSON `arguments` found at
+//!   `ptr..ptr + len` and returns a JSON object with `text` and optionally `is_error`.
+//!
+//! Strings are UTF-8 and returned as `(ptr << 32) | len`.
+
+use crate::{config::Config, context::CancellationToken, mcp::CallToolResult, tools::Tool};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fs,
+    path::Path,
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+use wasmtime::{
+    Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+    UpdateDeadline,
+};
+
+/// How often the engine's epoch is incremented, i.e. how often running plugins check whether
+/// they should be interrupted.
+const EPOCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Tool definition returned by a plugin's `describe` export.
+#[derive(Deserialize)]
+struct Definition {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(rename = "inputSchema")]
+    input_schema: Value,
+}
+
+/// Result returned by a plugin's `call` export.
+#[derive(Deserialize)]
+struct PluginResult {
+    text: String,
+    #[serde(default)]
+    is_error: bool,
+}
+
+/// Engine shared by all plugins, with a background thread ticking its epoch.
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = wasmtime::Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).expect("invalid wasmtime configuration");
+        let ticker = engine.clone();
+        thread::spawn(move || loop {
+            thread::sleep(EPOCH_INTERVAL);
+            ticker.increment_epoch();
+        });
+        engine
+    })
+}
+
+/// Loads the tools of the plugins in `plugin_dir`, in file name order. Modules that can't be
+/// loaded are logged and skipped.
+pub fn load_tools(config: &Config) -> Vec<Tool> {
+    let Some(dir) = &config.plugin_dir else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+            .collect(),
+        Err(e) => {
+            tracing::error!("Cannot read plugin directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match load_tool(path, config) {
+            Ok(tool) => {
+                tracing::info!("Loaded plugin {} as tool {}", path.display(), tool.name);
+                Some(tool)
+            }
+            Err(e) => {
+                tracing::error!("Cannot load plugin {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn load_tool(path: &Path, config: &Config) -> Result<Tool, String> {
+    let module = Module::from_file(engine(), path).map_err(|e| e.to_string())?;
+    let timeout = Duration::from_secs(config.plugin_timeout_secs);
+    let mut plugin = Plugin::instantiate(&module, config.plugin_max_memory, timeout, None)?;
+    let output = plugin.invoke("describe", None)?;
+    let definition: Definition =
+        serde_json::from_slice(&output).map_err(|e| format!("invalid tool definition: {}", e))?;
+    let tool = Tool::new(
+        definition.name,
+        definition.description,
+        definition.input_schema,
+        move |ctx, arguments| {
+            let timeout = ctx.limit_timeout(Duration::from_secs(ctx.config.plugin_timeout_secs));
+            let input = arguments.to_string();
+            let output = Plugin::instantiate(
+                &module,
+                ctx.config.plugin_max_memory,
+                timeout,
+                Some(ctx.cancellation.clone()),
+            )
+            .and_then(|mut plugin| plugin.invoke("call", Some(input.as_bytes())))
+            .and_then(|output| {
+                serde_json::from_slice::<PluginResult>(&output)
+                    .map_err(|e| format!("invalid plugin result: {}", e))
+            });
+            Ok(match output {
+                Ok(result) if result.is_error => CallToolResult::error(result.text),
+                Ok(result) => CallToolResult::text(result.text),
+                Err(e) => CallToolResult::error(format!("plugin failed: {}", e)),
+            })
+        },
+    );
+    Ok(tool)
+}
+
+/// A fresh instance of a plugin module.
+struct Plugin {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Plugin {
+    fn instantiate(
+        module: &Module,
+        max_memory: usize,
+        timeout: Duration,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<Plugin, String> {
+        let limits = StoreLimitsBuilder::new().memory_size(max_memory).build();
+        let mut store = Store::new(module.engine(), limits);
+        store.limiter(|limits| limits);
+        let deadline = Instant::now() + timeout;
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            let cancelled = cancellation.as_ref().is_some_and(|c| c.is_cancelled());
+            if cancelled || Instant::now() >= deadline {
+                Ok(UpdateDeadline::Interrupt)
+            } else {
+                Ok(UpdateDeadline::Continue(1))
+            }
+        });
+        // No imports: plugins can only compute on what they are given
+        let instance = Linker::new(module.engine())
+            .instantiate(&mut store, module)
+            .map_err(|e| e.to_string())?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("the module doesn't export its memory")?;
+        Ok(Plugin {
+            store,
+            instance,
+            memory,
+        })
+    }
+
+    /// Calls `export`, passing `input` as `(ptr, len)` when given, and returns the output.
+    fn invoke(&mut self, export: &str, input: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let packed = match input {
+            Some(input) => {
+                let len = i32::try_from(input.len()).map_err(|_| "input is too large")?;
+                let alloc = self
+                    .instance
+                    .get_typed_func::<i32, i32>(&mut self.store, "alloc")
+                    .map_err(|e| e.to_string())?;
+                let ptr = alloc.call(&mut self.store, len).map_err(describe_trap)?;
+                self.memory
+                    .write(&mut self.store, ptr as u32 as usize, input)
+                    .map_err(|e| e.to_string())?;
+                self.instance
+                    .get_typed_func::<(i32, i32), i64>(&mut self.store, export)
+                    .map_err(|e| e.to_string())?
+                    .call(&mut self.store, (ptr, len))
+                    .map_err(describe_trap)?
+            }
+            None => self
+                .instance
+                .get_typed_func::<(), i64>(&mut self.store, export)
+                .map_err(|e| e.to_string())?
+                .call(&mut self.store, ())
+                .map_err(describe_trap)?,
+        };
+        let ptr = (packed as u64 >> 32) as usize;
+        let len = (packed as u64 & 0xffff_ffff) as usize;
+        // The module says where its output is: only copied once known to lie in its memory,
+        // which `plugin_max_memory` caps
+        let output = ptr
+            .checked_add(len)
+            .and_then(|end| self.memory.data(&self.store).get(ptr..end))
+            .ok_or_else(|| {
+                format!(
+                    "invalid output: {} bytes at {} are out of the module's memory",
+                    len, ptr
+                )
+            })?;
+        Ok(output.to_vec())
+    }
+}
+
+fn describe_trap(error: wasmtime::Error) -> String {
+    match error.downcast_ref::<Trap>() {
+        Some(Trap::Interrupt) => "interrupted (timed out or cancelled)".to_string(),
+        _ => error.to_string(),
+    }
+}
@@ -0,0 +1,166 @@
+//! Reloads the tools of the plugins, scripts and tool manifests when their files change, with
+//! `plugin_reload`. Writing a file fires several events, so the tools are reloaded once no
+//! event came for [`DEBOUNCE`].
+
+use crate::{config::Config, plugins, server::Server};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Time without events after which the tools are reloaded.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches `plugin_dir`, `script_dir` and the directories of the `tool_manifests`, replacing
+/// the tools loaded from them and sending one `notifications/tools/list_changed` per reload.
+pub struct ToolWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    targets: Arc<Mutex<Targets>>,
+    reloads: Sender<()>,
+}
+
+/// Files whose changes reload the tools, canonicalized.
+#[derive(Default, Clone, PartialEq, Eq)]
+struct Targets {
+    plugin_dir: Option<PathBuf>,
+    script_dir: Option<PathBuf>,
+    manifests: BTreeSet<PathBuf>,
+}
+
+impl Targets {
+    /// The files of the configuration, none without `plugin_reload`.
+    fn of(config: &Config) -> Targets {
+        if !config.plugin_reload {
+            return Targets::default();
+        }
+        Targets {
+            plugin_dir: config
+                .plugin_dir
+                .as_ref()
+                .and_then(|dir| fs::canonicalize(dir).ok()),
+            script_dir: config
+                .script_dir
+                .as_ref()
+                .and_then(|dir| fs::canonicalize(dir).ok()),
+            // The file itself may be replaced, or not exist yet
+            manifests: config
+                .tool_manifests
+                .iter()
+                .filter_map(|path| {
+                    let parent = match path.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                    Some(fs::canonicalize(parent).ok()?.join(path.file_name()?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Directories to watch, non-recursively.
+    fn dirs(&self) -> BTreeSet<PathBuf> {
+        self.plugin_dir
+            .iter()
+            .chain(&self.script_dir)
+            .cloned()
+            .chain(
+                self.manifests
+                    .iter()
+                    .filter_map(|path| Some(path.parent()?.to_path_buf())),
+            )
+            .collect()
+    }
+
+    /// Whether a change of `path` changes the tools.
+    fn concern(&self, path: &Path) -> bool {
+        let in_dir = |dir: &Option<PathBuf>, extension: &str| {
+            dir.as_deref() == path.parent() && path.extension().is_some_and(|ext| ext == extension)
+        };
+        in_dir(&self.plugin_dir, "wasm")
+            || in_dir(&self.script_dir, "rhai")
+            || self.manifests.contains(path)
+    }
+}
+
+impl ToolWatcher {
+    /// Starts watching the files of the server's configuration. `loaded` are the names of the
+    /// tools loaded from them at startup, which the first reload replaces.
+    pub fn new(server: Arc<Server>, loaded: Vec<String>) -> notify::Result<ToolWatcher> {
+        let (reloads, requested) = mpsc::channel::<()>();
+        let config = server.config();
+        thread::spawn(move || {
+            let mut names = loaded;
+            while requested.recv().is_ok() {
+                while requested.recv_timeout(DEBOUNCE).is_ok() {}
+                tracing::info!("Plugin files changed, reloading their tools");
+                names = server.replace_tools(&names, plugins::load_tools(&server.config()));
+            }
+        });
+        let targets: Arc<Mutex<Targets>> = Arc::default();
+        let watcher = {
+            let targets = targets.clone();
+            let reloads = reloads.clone();
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) => {
+                    if matches!(event.kind, EventKind::Access(_)) {
+                        return;
+                    }
+                    let targets = targets.lock().unwrap();
+                    if event.paths.iter().any(|path| targets.concern(path)) {
+                        let _ = reloads.send(());
+                    }
+                }
+                Err(e) => tracing::error!("Error watching plugins: {}", e),
+            })?
+        };
+        let watcher = ToolWatcher {
+            watcher: Mutex::new(watcher),
+            targets,
+            reloads,
+        };
+        watcher.watch(Targets::of(&config));
+        Ok(watcher)
+    }
+
+    /// Watches the files of a new configuration, after it was reloaded, reloading the tools
+    /// when they are different files.
+    pub fn update(&self, config: &Config) {
+        if self.watch(Targets::of(config)) {
+            let _ = self.reloads.send(());
+        }
+    }
+
+    /// Watches the directories of `wanted`, returning whether the targets changed.
+    fn watch(&self, wanted: Targets) -> bool {
+        let watched = {
+            let mut targets = self.targets.lock().unwrap();
+            if *targets == wanted {
+                return false;
+            }
+            // Released before watching, as the watcher waits for its event handler
+            std::mem::replace(&mut *targets, wanted.clone()).dirs()
+        };
+        let dirs = wanted.dirs();
+        let mut watcher = self.watcher.lock().unwrap();
+        for dir in watched.difference(&dirs) {
+            if let Err(e) = watcher.unwatch(dir) {
+                tracing::warn!("Cannot stop watching {}: {}", dir.display(), e);
+            }
+        }
+        for dir in dirs.difference(&watched) {
+            match watcher.watch(dir, RecursiveMode::NonRecursive) {
+                Ok(()) => tracing::info!("Watching {} for plugin changes", dir.display()),
+                Err(e) => tracing::warn!("Cannot watch {}: {}", dir.display(), e),
+            }
+        }
+        true
+    }
+}
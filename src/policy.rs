@@ -0,0 +1,425 @@
+//! Permissions of the tools: `tool_permissions` marks tools, by name pattern, `allow`, `deny` or
+//! `ask`. Denied tools aren't offered to the clients, and the calls of the tools marked `ask`
+//! only run once approved, by the hook registered with
+//! [`Server::with_approval_hook`](crate::server::Server::with_approval_hook) or else as the
+//! `approval` setting says: on the server's terminal, by the user of the client through an
+//! elicitation, or by a webhook.
+//!
+//! The `sampling/createMessage` requests handlers send to the client, which spend the user's
+//! tokens, go through [`check_sampling`] first: the hook registered with
+//! [`Server::with_sampling_hook`](crate::server::Server::with_sampling_hook), or else the
+//! `sampling_approval` setting, approves them, within the per-session budget of
+//! `sampling_max_requests` and `sampling_max_tokens`.
+//!
+//! `resources/read` only reads the URIs of the schemes `resource_schemes` enables, when they
+//! match its allowlist, see [`check_scheme`].
+//!
+//! Sessions authenticated with scopes (an entry of `api_keys`, or
+//! [`Server::serve_authenticated`](crate::server::Server::serve_authenticated)) are further
+//! limited to the tools, resources and prompts their scopes grant in `scopes`, the files that
+//! prompts embed included.
+
+use crate::{
+    builtin::env::{matches, matches_case_sensitive},
+    config::Config,
+    context::RequestContext,
+    mcp::{Content, CreateMessageRequest},
+    roots,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Hook deciding whether a call of a tool marked `ask` may run, with the name of the tool and
+/// the arguments of the call; the error is the reason of a refusal, shown to the model.
+pub type ApprovalHook =
+    Box<dyn Fn(&RequestContext, &str, &Value) -> Result<(), String> + Send + Sync>;
+
+/// Hook deciding whether a `sampling/createMessage` may be sent to the client on behalf of the
+/// request being served; the error is the reason of a refusal.
+pub type SamplingHook =
+    Arc<dyn Fn(&RequestContext, &CreateMessageRequest) -> Result<(), String> + Send + Sync>;
+
+/// What a tool is allowed to do.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Calls run right away
+    #[default]
+    Allow,
+    /// The tool isn't offered
+    Deny,
+    /// Calls wait for an approval
+    Ask,
+}
+
+/// How the calls of the tools marked `ask` are approved when no hook is registered.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalMethod {
+    /// They are refused
+    #[default]
+    None,
+    /// A question on the terminal the server was started from
+    Console,
+    /// An `elicitation/create` request asking the user of the client
+    Elicitation,
+    /// A POST to `approval_webhook`
+    Webhook,
+}
+
+/// How the sampling requests of the handlers are approved when no hook is registered.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingApproval {
+    /// They are sent right away
+    #[default]
+    Auto,
+    /// A question on the terminal the server was started from
+    Console,
+    /// They are refused
+    Deny,
+}
+
+/// What a scope gives access to, by name or URI pattern (`*` matching anything).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct ScopeGrant {
+    /// Tools that can be listed and called, ignoring case
+    pub tools: Vec<String>,
+    /// Resources that can be listed, read, subscribed to and completed, by URI, and files that
+    /// can be embedded in prompts, by `file://` URI
+    pub resources: Vec<String>,
+    /// Prompts that can be listed, got and completed, ignoring case
+    pub prompts: Vec<String>,
+}
+
+/// Whether the URIs of a scheme can be read, an entry of `resource_schemes`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemeSettings {
+    /// Whether the scheme can be read at all; the default of its handler when absent, enabled
+    /// for all but `http` and `https`
+    pub enabled: Option<bool>,
+    /// URIs that can be read (`*` matching anything); any when absent
+    pub allow: Option<Vec<String>>,
+}
+
+/// True if `resource_schemes` enables `scheme`, or doesn't say and `default` does.
+pub fn scheme_enabled(config: &Config, scheme: &str, default: bool) -> bool {
+    config
+        .resource_schemes
+        .get(scheme)
+        .and_then(|settings| settings.enabled)
+        .unwrap_or(default)
+}
+
+/// Checks that `uri`, of `scheme`, can be read by `resources/read`: the scheme is enabled (see
+/// [`scheme_enabled`]) and its allowlist has a pattern matching the URI.
+pub fn check_scheme(config: &Config, scheme: &str, default: bool, uri: &str) -> Result<(), String> {
+    if !scheme_enabled(config, scheme, default) {
+        return Err(format!("reading {}: URIs is disabled", scheme));
+    }
+    let allowed = config
+        .resource_schemes
+        .get(scheme)
+        .and_then(|settings| settings.allow.as_ref())
+        .is_none_or(|allow| {
+            allow
+                .iter()
+                .any(|pattern| matches_case_sensitive(pattern, uri))
+        });
+    match allowed {
+        true => Ok(()),
+        false => Err(format!("{} is not in the allowed {}: URIs", uri, scheme)),
+    }
+}
+
+/// True if `scopes` grant the tool `name`. Sessions without scopes get every tool.
+pub fn tool_in_scopes(config: &Config, scopes: Option<&[String]>, name: &str) -> bool {
+    granted(config, scopes, |grant| {
+        grant.tools.iter().any(|pattern| matches(pattern, name))
+    })
+}
+
+/// True if `scopes` grant the resource at `uri`. Sessions without scopes get every resource.
+pub fn resource_in_scopes(config: &Config, scopes: Option<&[String]>, uri: &str) -> bool {
+    granted(config, scopes, |grant| {
+        grant
+            .resources
+            .iter()
+            .any(|pattern| matches_case_sensitive(pattern, uri))
+    })
+}
+
+/// True if `scopes` grant the prompt `name`. Sessions without scopes get every prompt.
+pub fn prompt_in_scopes(config: &Config, scopes: Option<&[String]>, name: &str) -> bool {
+    granted(config, scopes, |grant| {
+        grant.prompts.iter().any(|pattern| matches(pattern, name))
+    })
+}
+
+/// Checks that `scopes` grant the file at `path` as the resource of its `file://` URI, before a
+/// prompt embeds it.
+pub fn check_file_in_scopes(
+    config: &Config,
+    scopes: Option<&[String]>,
+    path: &Path,
+) -> Result<(), String> {
+    let uri = roots::path_to_uri(path);
+    match resource_in_scopes(config, scopes, &uri) {
+        true => Ok(()),
+        false => Err(format!("{} isn't granted to the session", uri)),
+    }
+}
+
+fn granted(
+    config: &Config,
+    scopes: Option<&[String]>,
+    grants: impl Fn(&ScopeGrant) -> bool,
+) -> bool {
+    let Some(scopes) = scopes else {
+        return true;
+    };
+    scopes
+        .iter()
+        .filter_map(|scope| config.scopes.get(scope))
+        .any(grants)
+}
+
+/// Permission of the tool `name`: that of its entry in `tool_permissions`, or else of the
+/// longest pattern matching it (`*` matching anything, ignoring case), `allow` when none does.
+pub fn permission(config: &Config, name: &str) -> Permission {
+    if let Some(permission) = config.tool_permissions.get(name) {
+        return *permission;
+    }
+    config
+        .tool_permissions
+        .iter()
+        .filter(|(pattern, _)| pattern.contains('*') && matches(pattern, name))
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, permission)| *permission)
+        .unwrap_or_default()
+}
+
+/// Checks that the call of `tool` with `arguments` may run, asking for an approval if the tool
+/// is marked `ask`.
+pub(crate) fn check(
+    hook: Option<&ApprovalHook>,
+    ctx: &RequestContext,
+    tool: &str,
+    arguments: &Value,
+) -> Result<(), String> {
+    if permission(&ctx.config, tool) != Permission::Ask {
+        return Ok(());
+    }
+    tracing::info!("Asking for the approval of a call of {}", tool);
+    if let Some(hook) = hook {
+        return hook(ctx, tool, arguments);
+    }
+    match ctx.config.approval {
+        ApprovalMethod::None => {
+            Err("the tool needs an approval and no approver is set".to_string())
+        }
+        ApprovalMethod::Console => {
+            ask_console(&format!("Allow the call of {} with {}?", tool, arguments))
+        }
+        ApprovalMethod::Elicitation => ask_client(ctx, tool, arguments),
+        ApprovalMethod::Webhook => ask_webhook(ctx, tool, arguments),
+    }
+}
+
+/// Checks that `request` may be sent to the client of the session serving `ctx`: that it fits
+/// in what is left of the session's budget, and that it is approved.
+pub(crate) fn check_sampling(
+    hook: Option<&SamplingHook>,
+    ctx: &RequestContext,
+    request: &CreateMessageRequest,
+) -> Result<(), String> {
+    let budget = ctx
+        .session_state_bag()
+        .get_or_insert_with(SamplingBudget::default);
+    let tokens = u64::from(request.max_tokens);
+    budget.reserve(&ctx.config, tokens)?;
+    let approved = match (hook, ctx.config.sampling_approval) {
+        (Some(hook), _) => hook(ctx, request),
+        (None, SamplingApproval::Auto) => Ok(()),
+        (None, SamplingApproval::Deny) => Err("sampling is disabled on this server".to_string()),
+        (None, SamplingApproval::Console) => {
+            tracing::info!("Asking for the approval of a sampling request");
+            let prompt = match request.messages.last().map(|message| &message.content) {
+                Some(Content::Text { text }) => {
+                    format!("{:?}", text.chars().take(200).collect::<String>())
+                }
+                Some(_) => "a message that isn't text".to_string(),
+                None => "no message".to_string(),
+            };
+            ask_console(&format!(
+                "Allow sampling up to {} tokens from the client's model for {}?",
+                tokens, prompt
+            ))
+        }
+    };
+    if approved.is_err() {
+        budget.release(tokens);
+    }
+    approved
+}
+
+/// Sampling requests sent to the client of a session and the tokens they asked for at most,
+/// kept in its state.
+#[derive(Default)]
+struct SamplingBudget {
+    spent: Mutex<(u64, u64)>,
+}
+
+impl SamplingBudget {
+    /// Counts a request of up to `tokens`, unless it would go over `sampling_max_requests` or
+    /// `sampling_max_tokens`.
+    fn reserve(&self, config: &Config, tokens: u64) -> Result<(), String> {
+        let mut spent = self.spent.lock().unwrap();
+        let (requests, spent_tokens) = *spent;
+        if config.sampling_max_requests > 0 && requests >= config.sampling_max_requests {
+            return Err(format!(
+                "the session used its {} sampling requests",
+                config.sampling_max_requests
+            ));
+        }
+        if config.sampling_max_tokens > 0 && spent_tokens + tokens > config.sampling_max_tokens {
+            return Err(format!(
+                "the session has {} of its {} sampling tokens left, {} were asked",
+                config.sampling_max_tokens.saturating_sub(spent_tokens),
+                config.sampling_max_tokens,
+                tokens
+            ));
+        }
+        *spent = (requests + 1, spent_tokens + tokens);
+        Ok(())
+    }
+
+    /// Gives back a request that wasn't sent.
+    fn release(&self, tokens: u64) {
+        let mut spent = self.spent.lock().unwrap();
+        *spent = (spent.0.saturating_sub(1), spent.1.saturating_sub(tokens));
+    }
+}
+
+/// Asks `question` on the terminal, one question at a time.
+fn ask_console(question: &str) -> Result<(), String> {
+    static PROMPT: Mutex<()> = Mutex::new(());
+    let _prompt = PROMPT.lock().unwrap();
+    let (input, mut output) =
+        open_terminal().map_err(|e| format!("cannot open the terminal to ask: {}", e))?;
+    let mut answer = String::new();
+    write!(output, "{} [y/N] ", question)
+        .and_then(|_| output.flush())
+        .and_then(|_| BufReader::new(input).read_line(&mut answer))
+        .map_err(|e| format!("cannot ask on the terminal: {}", e))?;
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err("refused on the server's terminal".to_string()),
+    }
+}
+
+/// The terminal the server was started from, to read from and write to, whatever its stdin
+/// and stdout are connected to.
+#[cfg(unix)]
+fn open_terminal() -> io::Result<(File, File)> {
+    let terminal = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    Ok((terminal.try_clone()?, terminal))
+}
+
+#[cfg(windows)]
+fn open_terminal() -> io::Result<(File, File)> {
+    let input = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+    let output = OpenOptions::new().read(true).write(true).open("CONOUT$")?;
+    Ok((input, output))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn open_terminal() -> io::Result<(File, File)> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Asks the user of the client with an `elicitation/create` request, which they accept to
+/// approve the call.
+fn ask_client(ctx: &RequestContext, tool: &str, arguments: &Value) -> Result<(), String> {
+    let supported = ctx.session.version.supports_elicitation()
+        && ctx
+            .session
+            .client_capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.get("elicitation").is_some());
+    if !supported {
+        return Err("the client can't be asked for an approval".to_string());
+    }
+    let params = json!({
+        "message": format!("Allow the call of {} with {}?", tool, arguments),
+        "requestedSchema": { "type": "object", "properties": {} },
+    });
+    let answer = ctx
+        .send_request("elicitation/create", Some(params))
+        .map_err(|e| format!("cannot ask the client: {}", e.message))?;
+    match answer["action"].as_str() {
+        Some("accept") => Ok(()),
+        Some("decline") => Err("declined by the user".to_string()),
+        Some("cancel") => Err("cancelled by the user".to_string()),
+        _ => Err(format!("invalid answer from the client: {}", answer)),
+    }
+}
+
+/// Posts the call to `approval_webhook`, which answers `{"approved": true}`, or
+/// `{"approved": false, "reason": "..."}`.
+fn ask_webhook(ctx: &RequestContext, tool: &str, arguments: &Value) -> Result<(), String> {
+    let Some(url) = &ctx.config.approval_webhook else {
+        return Err("no approval_webhook is set".to_string());
+    };
+    let timeout = Duration::from_secs(ctx.config.approval_timeout_secs);
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global((timeout > Duration::ZERO).then_some(timeout))
+        .build()
+        .into();
+    let body = json!({
+        "tool": tool,
+        "arguments": arguments,
+        "clientInfo": ctx.session.client_info,
+        "traceId": ctx.trace_id,
+    });
+    let mut response = agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body.to_string().as_bytes())
+        .map_err(|e| format!("cannot reach the approval webhook: {}", e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!(
+            "the approval webhook answered with status {}",
+            status
+        ));
+    }
+    let answer: Value = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| e.to_string())
+        .and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string()))
+        .map_err(|e| format!("invalid answer from the approval webhook: {}", e))?;
+    if answer["approved"].as_bool() == Some(true) {
+        return Ok(());
+    }
+    match answer["reason"].as_str() {
+        Some(reason) => Err(reason.to_string()),
+        None => Err("refused by the approval webhook".to_string()),
+    }
+}
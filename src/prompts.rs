@@ -0,0 +1,182 @@
+use crate::{
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{GetPromptResult, Icon},
+    protocol::ProtocolVersion,
+    schema,
+};
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// Function called with the `arguments` of a `prompts/get` request.
+pub type PromptHandler =
+    Box<dyn Fn(&RequestContext, &Value) -> Result<GetPromptResult, JsonRpcError> + Send + Sync>;
+
+/// An argument accepted by a prompt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+pub struct PromptArgument {
+    pub name: String,
+    /// Human-readable description of the argument
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// True if the prompt can't be rendered without it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+}
+
+/// A prompt template the client can fetch with `prompts/get`.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/prompts/
+pub struct Prompt {
+    /// Unique name of the prompt
+    pub name: String,
+    /// Name to display, if different from `name`
+    pub title: Option<String>,
+    /// Human-readable description of what the prompt is for
+    pub description: String,
+    /// Arguments used to render the prompt
+    pub arguments: Vec<PromptArgument>,
+    /// Icons to display
+    pub icons: Vec<Icon>,
+    handler: PromptHandler,
+}
+
+impl Prompt {
+    pub fn new<F>(name: impl Into<String>, description: impl Into<String>, handler: F) -> Prompt
+    where
+        F: Fn(&RequestContext, &Value) -> Result<GetPromptResult, JsonRpcError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Prompt {
+            name: name.into(),
+            title: None,
+            description: description.into(),
+            arguments: Vec::new(),
+            icons: Vec::new(),
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Prompt {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: Icon) -> Prompt {
+        self.icons.push(icon);
+        self
+    }
+
+    /// Declares an argument of the prompt.
+    pub fn with_argument(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        required: bool,
+    ) -> Prompt {
+        self.arguments.push(PromptArgument {
+            name: name.into(),
+            description: Some(description.into()),
+            required,
+        });
+        self
+    }
+
+    /// Renders the prompt with the given arguments, once checked to be strings as the protocol
+    /// requires and to include every required argument.
+    pub fn get(
+        &self,
+        ctx: &RequestContext,
+        arguments: &Value,
+    ) -> Result<GetPromptResult, JsonRpcError> {
+        static VALIDATOR: OnceLock<Option<Validator>> = OnceLock::new();
+        let validator = VALIDATOR.get_or_init(|| {
+            schema::compile(
+                "prompts",
+                &json!({ "type": "object", "additionalProperties": { "type": "string" } }),
+            )
+        });
+        if let Some(validator) = validator {
+            schema::check(validator, arguments)?;
+        }
+        let missing: Vec<&str> = self
+            .arguments
+            .iter()
+            .filter(|argument| argument.required && arguments.get(&argument.name).is_none())
+            .map(|argument| argument.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            let mut error = JsonRpcError::new(
+                ERROR_CODE_INVALID_PARAMS,
+                format!(
+                    "Missing required arguments of prompt {}: {}",
+                    self.name,
+                    missing.join(", ")
+                ),
+            );
+            error.data = Some(json!({
+                "pointer": format!("/{}", missing[0]),
+                "errors": missing
+                    .iter()
+                    .map(|name| {
+                        json!({ "pointer": format!("/{}", name), "message": "is required" })
+                    })
+                    .collect::<Vec<_>>(),
+            }));
+            return Err(error);
+        }
+        (self.handler)(ctx, arguments)
+    }
+
+    /// Definition of the prompt as listed by `prompts/list` to a client speaking `version`.
+    pub fn definition(&self, version: ProtocolVersion) -> Value {
+        let mut definition = json!({
+            "name": self.name,
+            "description": self.description,
+            "arguments": self.arguments,
+        });
+        if version.supports_titles() {
+            if let Some(title) = &self.title {
+                definition["title"] = json!(title);
+            }
+            if !self.icons.is_empty() {
+                definition["icons"] = json!(self.icons);
+            }
+        }
+        definition
+    }
+}
+
+/// Prompts registered on the server, sorted by name.
+#[derive(Default)]
+pub struct PromptRegistry {
+    prompts: BTreeMap<String, Prompt>,
+}
+
+impl PromptRegistry {
+    /// Adds a prompt, replacing any prompt registered under the same name.
+    pub fn register(&mut self, prompt: Prompt) {
+        self.prompts.insert(prompt.name.clone(), prompt);
+    }
+
+    /// Removes a prompt, returning it if it was registered.
+    pub fn unregister(&mut self, name: &str) -> Option<Prompt> {
+        self.prompts.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prompt> {
+        self.prompts.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prompts.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Prompt> {
+        self.prompts.values()
+    }
+}
@@ -0,0 +1,89 @@
+//! MCP protocol versions the server speaks, and the features each of them has, so that a
+//! session only gets what the version negotiated in `initialize` knows about.
+
+use std::fmt::{self, Display};
+
+/// A revision of the MCP specification.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    /// The first revision, also assumed for sessions that didn't negotiate one
+    #[default]
+    V2024_11_05,
+    /// Adds audio content, tool annotations and the streamable HTTP transport
+    V2025_03_26,
+    /// Adds structured tool output, elicitation and display titles
+    V2025_06_18,
+}
+
+impl ProtocolVersion {
+    /// Versions the server supports, the latest first.
+    pub const SUPPORTED: [ProtocolVersion; 3] = [
+        ProtocolVersion::V2025_06_18,
+        ProtocolVersion::V2025_03_26,
+        ProtocolVersion::V2024_11_05,
+    ];
+
+    /// The latest supported version.
+    pub const LATEST: ProtocolVersion = ProtocolVersion::SUPPORTED[0];
+
+    /// The version named `version` (e.g. `2025-03-26`), if supported.
+    pub fn parse(version: &str) -> Option<ProtocolVersion> {
+        ProtocolVersion::SUPPORTED
+            .into_iter()
+            .find(|supported| supported.as_str() == version)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProtocolVersion::V2024_11_05 => "2024-11-05",
+            ProtocolVersion::V2025_03_26 => "2025-03-26",
+            ProtocolVersion::V2025_06_18 => "2025-06-18",
+        }
+    }
+
+    /// True if content can be audio.
+    pub fn supports_audio(self) -> bool {
+        self >= ProtocolVersion::V2025_03_26
+    }
+
+    /// True if tool definitions can carry `annotations`.
+    pub fn supports_tool_annotations(self) -> bool {
+        self >= ProtocolVersion::V2025_03_26
+    }
+
+    /// True if tools, resources and prompts can have a `title` and `icons` for display.
+    pub fn supports_titles(self) -> bool {
+        self >= ProtocolVersion::V2025_06_18
+    }
+
+    /// True if `notifications/progress` can carry a `message`.
+    pub fn supports_progress_message(self) -> bool {
+        self >= ProtocolVersion::V2025_03_26
+    }
+
+    /// True if tools can declare an `outputSchema` and return `structuredContent`.
+    pub fn supports_structured_output(self) -> bool {
+        self >= ProtocolVersion::V2025_06_18
+    }
+
+    /// True if content can be a `resource_link`.
+    pub fn supports_resource_links(self) -> bool {
+        self >= ProtocolVersion::V2025_06_18
+    }
+
+    /// True if annotations can carry `lastModified`.
+    pub fn supports_last_modified(self) -> bool {
+        self >= ProtocolVersion::V2025_06_18
+    }
+
+    /// True if the server can ask the client for input with `elicitation/create`.
+    pub fn supports_elicitation(self) -> bool {
+        self >= ProtocolVersion::V2025_06_18
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
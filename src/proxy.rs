@@ -0,0 +1,520 @@
+//! Aggregation of downstream MCP servers: their tools, resources and prompts are re-exposed
+//! by this server, and calls are forwarded to them.
+//!
+//! Tools and prompts are renamed `<namespace>__<name>`, the namespace being the name of the
+//! downstream server in `downstream_servers` unless it sets one, and the separator
+//! `downstream_separator`; resources keep their URIs unless the server sets
+//! `namespace_resources`, which makes them `<namespace>+<uri>`. Names and URIs claimed by two
+//! servers are kept by the first one. Requests of methods this server doesn't know are forwarded
+//! to the downstream servers, the first that knows the method answering.
+
+use crate::{
+    client::Client,
+    config::Config,
+    context::{RequestContext, TRACE_ID_META},
+    jsonrpc::{
+        JsonRpcError, ERROR_CODE_INTERNAL_ERROR, ERROR_CODE_INVALID_PARAMS,
+        ERROR_CODE_METHOD_NOT_FOUND,
+    },
+    mcp::{CallToolResult, GetPromptResult, ReadResourceResult},
+    prompts::{Prompt, PromptArgument},
+    resources::{Resource, ResourceProvider},
+    server::Server,
+    tools::Tool,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// A connected downstream server.
+struct Downstream {
+    name: String,
+    client: Client,
+    /// Prefix of the names of its tools and prompts
+    prefix: String,
+    /// Prefix of the URIs of its resources, if they are namespaced
+    uri_prefix: Option<String>,
+    /// Names and URIs of all the downstream servers
+    claims: Arc<Claims>,
+    /// Names under which its tools are registered
+    tools: Mutex<Vec<String>>,
+    /// Names under which its prompts are registered
+    prompts: Mutex<Vec<String>>,
+    /// URIs of its resources, as last listed
+    resource_uris: Mutex<HashSet<String>>,
+}
+
+/// Downstream server owning each tool, prompt and resource, to detect the collisions between
+/// servers.
+#[derive(Default)]
+struct Claims {
+    tools: Mutex<HashMap<String, String>>,
+    prompts: Mutex<HashMap<String, String>>,
+    resources: Mutex<HashMap<String, String>>,
+}
+
+impl Claims {
+    /// Takes `names` of the given kind for `owner`, releasing those it claimed before, and
+    /// returns the ones it got. The names other servers already have are logged and left out.
+    fn claim(
+        claims: &Mutex<HashMap<String, String>>,
+        kind: &str,
+        owner: &str,
+        names: impl IntoIterator<Item = String>,
+    ) -> HashSet<String> {
+        let mut claims = claims.lock().unwrap();
+        claims.retain(|_, claimant| claimant != owner);
+        let mut claimed = HashSet::new();
+        for name in names {
+            match claims.get(&name) {
+                Some(other) => tracing::warn!(
+                    "The {} {} of downstream server {} collides with that of {}, skipping it",
+                    kind,
+                    name,
+                    owner,
+                    other
+                ),
+                None => {
+                    claims.insert(name.clone(), owner.to_string());
+                    claimed.insert(name);
+                }
+            }
+        }
+        claimed
+    }
+}
+
+/// Connections to the `downstream_servers`.
+pub struct Proxy {
+    downstreams: Vec<Arc<Downstream>>,
+}
+
+impl Proxy {
+    /// Connects to the downstream servers. Servers that can't be reached are logged and
+    /// skipped.
+    pub fn connect(config: &Config) -> Arc<Proxy> {
+        let timeout = Duration::from_secs(config.downstream_timeout_secs);
+        let claims = Arc::new(Claims::default());
+        let downstreams = config
+            .downstream_servers
+            .iter()
+            .filter_map(|(name, server)| match Client::connect(server, timeout) {
+                Ok(client) => {
+                    tracing::info!("Connected to downstream server {}", name);
+                    let namespace = server.namespace.as_ref().unwrap_or(name);
+                    Some(Arc::new(Downstream {
+                        name: name.clone(),
+                        client,
+                        prefix: format!("{}{}", namespace, config.downstream_separator),
+                        uri_prefix: server
+                            .namespace_resources
+                            .then(|| format!("{}+", namespace)),
+                        claims: claims.clone(),
+                        tools: Mutex::default(),
+                        prompts: Mutex::default(),
+                        resource_uris: Mutex::default(),
+                    }))
+                }
+                Err(e) => {
+                    tracing::error!("Cannot connect to downstream server {}: {}", name, e);
+                    None
+                }
+            })
+            .collect();
+        Arc::new(Proxy { downstreams })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.downstreams.is_empty()
+    }
+
+    /// Tools of all the downstream servers.
+    pub fn tools(&self) -> Vec<Tool> {
+        self.downstreams
+            .iter()
+            .flat_map(Downstream::tools)
+            .collect()
+    }
+
+    /// Prompts of all the downstream servers.
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.downstreams
+            .iter()
+            .flat_map(Downstream::prompts)
+            .collect()
+    }
+
+    /// Provider of the resources of all the downstream servers.
+    pub fn resources(self: &Arc<Proxy>) -> ProxyResources {
+        ProxyResources {
+            proxy: self.clone(),
+        }
+    }
+
+    /// Forwards a request of a method unknown to this server to the downstream servers in turn,
+    /// until one doesn't answer METHOD_NOT_FOUND. Meant for [`Server::with_fallback`].
+    pub fn forward(
+        &self,
+        ctx: &RequestContext,
+        method: &str,
+        params: Option<&Value>,
+    ) -> Result<Value, JsonRpcError> {
+        let params = match params {
+            None => json!({}),
+            Some(params @ Value::Object(_)) => params.clone(),
+            Some(_) => {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    format!(
+                        "Invalid params: {} is forwarded with named params only",
+                        method
+                    ),
+                ))
+            }
+        };
+        for downstream in &self.downstreams {
+            match downstream.request(method, params.clone(), Some(ctx)) {
+                Err(e) if e.code == ERROR_CODE_METHOD_NOT_FOUND => {}
+                result => {
+                    tracing::info!("Forwarded {} to {}", method, downstream.name);
+                    return result;
+                }
+            }
+        }
+        Err(JsonRpcError::new(
+            ERROR_CODE_METHOD_NOT_FOUND,
+            format!("Method not found: '{}'", method),
+        ))
+    }
+
+    /// Forwards the notifications of the downstream servers to the clients of `server`, and
+    /// keeps its tools and prompts in sync with theirs.
+    pub fn attach(&self, server: &Arc<Server>) {
+        for downstream in &self.downstreams {
+            let server = Arc::downgrade(server);
+            let weak = Arc::downgrade(downstream);
+            downstream.client.on_notification(move |method, params| {
+                let (server, weak) = (server.clone(), weak.clone());
+                let (method, params) = (method.to_string(), params.cloned());
+                // Handled on another thread since it may send requests, whose responses are
+                // read by the thread calling us
+                thread::spawn(move || {
+                    if let (Some(server), Some(downstream)) = (server.upgrade(), weak.upgrade()) {
+                        downstream.forward_notification(&server, &method, params.as_ref());
+                    }
+                });
+            });
+        }
+    }
+}
+
+impl Downstream {
+    fn has_capability(&self, name: &str) -> bool {
+        self.client.capabilities().get(name).is_some()
+    }
+
+    fn prefixed(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    /// URI under which this server's resource `uri` is exposed.
+    fn local_uri(&self, uri: &str) -> String {
+        match &self.uri_prefix {
+            Some(prefix) => format!("{}{}", prefix, uri),
+            None => uri.to_string(),
+        }
+    }
+
+    /// URI of the resource exposed as `uri`, as this server knows it.
+    fn remote_uri<'a>(&self, uri: &'a str) -> &'a str {
+        match &self.uri_prefix {
+            Some(prefix) => uri.strip_prefix(prefix.as_str()).unwrap_or(uri),
+            None => uri,
+        }
+    }
+
+    fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        mut params: Value,
+        ctx: Option<&RequestContext>,
+    ) -> Result<T, JsonRpcError> {
+        // The downstream server can log the operation under the same trace id
+        if let Some(ctx) = ctx {
+            params["_meta"][TRACE_ID_META] = json!(ctx.trace_id);
+        }
+        let result = self.client.request(
+            method,
+            Some(params),
+            ctx.map(|ctx| &ctx.cancellation),
+            |mut progress| {
+                // Progress of a forwarded request goes to our client under its own token
+                let Some(ctx) = ctx else {
+                    return;
+                };
+                if let Some(token) = &ctx.progress_token {
+                    progress["progressToken"] = token.clone();
+                    ctx.send_notification("notifications/progress", Some(progress));
+                }
+            },
+        )?;
+        serde_json::from_value(result).map_err(|e| {
+            JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                format!("invalid {} result from {}: {}", method, self.name, e),
+            )
+        })
+    }
+
+    /// Items of a paginated list request, logging failures.
+    fn list(&self, method: &str, field: &str) -> Vec<Value> {
+        self.client.list(method, field).unwrap_or_else(|e| {
+            tracing::error!("{} failed on {}: {}", method, self.name, e.message);
+            Vec::new()
+        })
+    }
+
+    fn tools(self: &Arc<Downstream>) -> Vec<Tool> {
+        if !self.has_capability("tools") {
+            return Vec::new();
+        }
+        let definitions = self.list("tools/list", "tools");
+        let claimed = Claims::claim(
+            &self.claims.tools,
+            "tool",
+            &self.name,
+            definitions
+                .iter()
+                .filter_map(|definition| Some(self.prefixed(definition["name"].as_str()?))),
+        );
+        let mut names = Vec::new();
+        let tools: Vec<Tool> = definitions
+            .into_iter()
+            .filter(|definition| {
+                let name = definition["name"].as_str().unwrap_or_default();
+                claimed.contains(&self.prefixed(name))
+            })
+            .filter_map(|definition| {
+                let name = definition["name"].as_str()?.to_string();
+                let downstream = self.clone();
+                let remote = name.clone();
+                let tool = Tool::new(
+                    self.prefixed(&name),
+                    definition["description"].as_str().unwrap_or_default(),
+                    definition
+                        .get("inputSchema")
+                        .cloned()
+                        .unwrap_or_else(|| json!({ "type": "object" })),
+                    move |ctx, arguments| {
+                        downstream.request::<CallToolResult>(
+                            "tools/call",
+                            json!({ "name": remote, "arguments": arguments }),
+                            Some(ctx),
+                        )
+                    },
+                );
+                names.push(tool.name.clone());
+                Some(match definition.get("annotations") {
+                    Some(annotations) => tool.with_annotations(annotations.clone()),
+                    None => tool,
+                })
+            })
+            .collect();
+        *self.tools.lock().unwrap() = names;
+        tools
+    }
+
+    fn prompts(self: &Arc<Downstream>) -> Vec<Prompt> {
+        if !self.has_capability("prompts") {
+            return Vec::new();
+        }
+        let definitions = self.list("prompts/list", "prompts");
+        let claimed = Claims::claim(
+            &self.claims.prompts,
+            "prompt",
+            &self.name,
+            definitions
+                .iter()
+                .filter_map(|definition| Some(self.prefixed(definition["name"].as_str()?))),
+        );
+        let mut names = Vec::new();
+        let prompts: Vec<Prompt> = definitions
+            .into_iter()
+            .filter(|definition| {
+                let name = definition["name"].as_str().unwrap_or_default();
+                claimed.contains(&self.prefixed(name))
+            })
+            .filter_map(|definition| {
+                let name = definition["name"].as_str()?.to_string();
+                let downstream = self.clone();
+                let remote = name.clone();
+                let mut prompt = Prompt::new(
+                    self.prefixed(&name),
+                    definition["description"].as_str().unwrap_or_default(),
+                    move |ctx, arguments| {
+                        downstream.request::<GetPromptResult>(
+                            "prompts/get",
+                            json!({ "name": remote, "arguments": arguments }),
+                            Some(ctx),
+                        )
+                    },
+                );
+                prompt.arguments =
+                    serde_json::from_value::<Vec<PromptArgument>>(definition["arguments"].clone())
+                        .unwrap_or_default();
+                names.push(prompt.name.clone());
+                Some(prompt)
+            })
+            .collect();
+        *self.prompts.lock().unwrap() = names;
+        prompts
+    }
+
+    fn forward_notification(
+        self: &Arc<Downstream>,
+        server: &Server,
+        method: &str,
+        params: Option<&Value>,
+    ) {
+        match method {
+            "notifications/tools/list_changed" => {
+                let old = self.tools.lock().unwrap().clone();
+                let tools = self.tools();
+                for name in old {
+                    if !tools.iter().any(|tool| tool.name == name) {
+                        server.unregister_tool(&name);
+                    }
+                }
+                for tool in tools {
+                    server.register_tool(tool);
+                }
+            }
+            "notifications/prompts/list_changed" => {
+                let old = self.prompts.lock().unwrap().clone();
+                let prompts = self.prompts();
+                for name in old {
+                    if !prompts.iter().any(|prompt| prompt.name == name) {
+                        server.unregister_prompt(&name);
+                    }
+                }
+                for prompt in prompts {
+                    server.register_prompt(prompt);
+                }
+            }
+            "notifications/resources/list_changed" => {
+                server.notify_all(method, None);
+            }
+            "notifications/resources/updated" => {
+                if let Some(uri) = params.and_then(|p| p["uri"].as_str()) {
+                    server.notify_resource_updated(&self.local_uri(uri));
+                }
+            }
+            "notifications/message" => {
+                tracing::info!("Downstream server {}: {}", self.name, json!(params));
+            }
+            _ => tracing::debug!("Ignoring {} from {}", method, self.name),
+        }
+    }
+
+    /// True if `uri` is one of the resources of this server, listing them if not known yet.
+    fn owns(&self, uri: &str) -> bool {
+        if self.resource_uris.lock().unwrap().is_empty() {
+            self.list_resources();
+        }
+        self.resource_uris.lock().unwrap().contains(uri)
+    }
+
+    fn list_resources(&self) -> Vec<Resource> {
+        if !self.has_capability("resources") {
+            return Vec::new();
+        }
+        let resources: Vec<Resource> = self
+            .list("resources/list", "resources")
+            .into_iter()
+            .filter_map(|resource| serde_json::from_value::<Resource>(resource).ok())
+            .map(|resource| Resource {
+                uri: self.local_uri(&resource.uri),
+                ..resource
+            })
+            .collect();
+        let claimed = Claims::claim(
+            &self.claims.resources,
+            "resource",
+            &self.name,
+            resources.iter().map(|r| r.uri.clone()),
+        );
+        let resources: Vec<Resource> = resources
+            .into_iter()
+            .filter(|r| claimed.contains(&r.uri))
+            .collect();
+        *self.resource_uris.lock().unwrap() = claimed;
+        resources
+    }
+}
+
+/// Resources of the downstream servers, read from the server that lists them.
+pub struct ProxyResources {
+    proxy: Arc<Proxy>,
+}
+
+impl ResourceProvider for ProxyResources {
+    fn list(&self, _ctx: &RequestContext) -> Vec<Resource> {
+        self.proxy
+            .downstreams
+            .iter()
+            .flat_map(|downstream| downstream.list_resources())
+            .collect()
+    }
+
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let downstream = self.owner(uri)?;
+        let result = downstream.request::<ReadResourceResult>(
+            "resources/read",
+            json!({ "uri": downstream.remote_uri(uri) }),
+            Some(ctx),
+        );
+        Some(result.map(|mut result| {
+            for contents in &mut result.contents {
+                contents.uri = downstream.local_uri(&contents.uri);
+            }
+            result
+        }))
+    }
+
+    fn subscribe(&self, uri: &str) {
+        self.set_subscribed(uri, "resources/subscribe");
+    }
+
+    fn unsubscribe(&self, uri: &str) {
+        self.set_subscribed(uri, "resources/unsubscribe");
+    }
+}
+
+impl ProxyResources {
+    fn owner(&self, uri: &str) -> Option<&Arc<Downstream>> {
+        self.proxy.downstreams.iter().find(|d| d.owns(uri))
+    }
+
+    fn set_subscribed(&self, uri: &str, method: &str) {
+        let Some(downstream) = self.owner(uri) else {
+            return;
+        };
+        let subscribable =
+            downstream.client.capabilities()["resources"]["subscribe"] == json!(true);
+        if subscribable {
+            let params = json!({ "uri": downstream.remote_uri(uri) });
+            if let Err(e) = downstream.request::<Value>(method, params, None) {
+                tracing::warn!("{} failed on {}: {}", method, downstream.name, e.message);
+            }
+        }
+    }
+}
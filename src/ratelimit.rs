@@ -0,0 +1,194 @@
+//! Rate limits on the requests of a session: `rate_limits` maps method patterns (`*` matching
+//! anything) to a number of requests per period, enforced with a token bucket per session and
+//! pattern so that short bursts up to the limit go through.
+
+use crate::{builtin::env::matches, errors::RATE_LIMITED, jsonrpc::JsonRpcError};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A number of requests per period, written `10/min`, `5/s`, `100/hour` or `20/10s`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-schema", derive(schemars::JsonSchema))]
+#[serde(try_from = "String")]
+pub struct RateLimit {
+    pub count: u32,
+    pub period: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RateLimit, String> {
+        let invalid = || {
+            format!(
+                "invalid rate limit '{}' (expected <count>/<period>, like 10/min)",
+                s
+            )
+        };
+        let (count, period) = s.split_once('/').ok_or_else(invalid)?;
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+        let period = period.trim();
+        let digits = period
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (multiple, unit) = period.split_at(digits);
+        let multiple: u32 = match multiple {
+            "" => 1,
+            multiple => multiple.parse().map_err(|_| invalid())?,
+        };
+        let unit = match unit {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 3600,
+            "d" | "day" => 86400,
+            _ => return Err(invalid()),
+        };
+        if count == 0 || multiple == 0 {
+            return Err(invalid());
+        }
+        Ok(RateLimit {
+            count,
+            period: Duration::from_secs(u64::from(multiple) * unit),
+        })
+    }
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<RateLimit, String> {
+        s.parse()
+    }
+}
+
+impl Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.period.as_secs() {
+            1 => write!(f, "{}/s", self.count),
+            60 => write!(f, "{}/min", self.count),
+            3600 => write!(f, "{}/hour", self.count),
+            86400 => write!(f, "{}/day", self.count),
+            secs => write!(f, "{}/{}s", self.count, secs),
+        }
+    }
+}
+
+/// Tokens left for a pattern, refilled continuously up to the limit's count.
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Bucket {
+        Bucket {
+            limit,
+            tokens: f64::from(limit.count),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let rate = f64::from(self.limit.count) / self.limit.period.as_secs_f64();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.limit.count));
+        self.updated = now;
+    }
+
+    /// Time until a token is available.
+    fn wait(&self) -> Duration {
+        let rate = f64::from(self.limit.count) / self.limit.period.as_secs_f64();
+        Duration::from_secs_f64(((1.0 - self.tokens) / rate).max(0.0))
+    }
+}
+
+/// The buckets of a session, by pattern.
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Takes a token for a request of `method` from the bucket of each pattern it matches, or
+    /// none if one of them is empty, in which case the error tells when to retry.
+    pub(crate) fn check(
+        &self,
+        limits: &BTreeMap<String, RateLimit>,
+        method: &str,
+    ) -> Result<(), JsonRpcError> {
+        if limits.is_empty() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut matching = Vec::new();
+        for (pattern, limit) in limits {
+            if !matches(pattern, method) {
+                continue;
+            }
+            let bucket = buckets
+                .entry(pattern.clone())
+                .or_insert_with(|| Bucket::new(*limit, now));
+            // The configuration was reloaded with another limit
+            if bucket.limit != *limit {
+                *bucket = Bucket::new(*limit, now);
+            }
+            bucket.refill(now);
+            matching.push(pattern);
+        }
+        let exceeded = matching
+            .iter()
+            .map(|pattern| (*pattern, &buckets[pattern.as_str()]))
+            .filter(|(_, bucket)| bucket.tokens < 1.0)
+            .max_by_key(|(_, bucket)| bucket.wait());
+        if let Some((pattern, bucket)) = exceeded {
+            let retry_after = bucket.wait();
+            tracing::warn!(
+                "Rate limit {} of '{}' exceeded by {}, retry after {:?}",
+                bucket.limit,
+                pattern,
+                method,
+                retry_after
+            );
+            let err = RATE_LIMITED
+                .error(format!("Rate limit exceeded for {}", method))
+                .with_data("method", method)
+                .with_data("pattern", pattern.as_str())
+                .with_data("limit", bucket.limit.to_string())
+                .with_data("retryAfterMs", retry_after.as_millis().max(1) as u64);
+            return Err(err);
+        }
+        for pattern in matching {
+            if let Some(bucket) = buckets.get_mut(pattern.as_str()) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes a token from the bucket of `key` for `limit`, returning false without logging if
+    /// it is empty.
+    pub(crate) fn try_take(&self, key: &str, limit: RateLimit) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(key.to_string())
+            .or_insert_with(|| Bucket::new(limit, now));
+        if bucket.limit != limit {
+            *bucket = Bucket::new(limit, now);
+        }
+        bucket.refill(now);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
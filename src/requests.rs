@@ -0,0 +1,244 @@
+//! Requests the server sends to a client (`ping`, `sampling/createMessage`, `roots/list`,
+//! `elicitation/create`, ...). Each session numbers its requests and keeps those waiting for a
+//! response, which the session hands over to the waiting caller when the client answers.
+//! Requests the client doesn't answer in time may be sent again, as a [`RetryPolicy`] says.
+
+use crate::{
+    context::{limit_timeout, CancellationToken},
+    errors::TIMEOUT,
+    jsonrpc::{
+        JsonRpcError, JsonRpcId, JsonRpcNotification, JsonRpcRequest, ERROR_CODE_INTERNAL_ERROR,
+    },
+    output::OutgoingQueue,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a waiting request checks whether it was cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type Response = Result<Value, JsonRpcError>;
+
+/// How long a request to the client waits for its response, and how often it is sent again when
+/// it isn't answered in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Time each attempt waits for the response; zero for no limit
+    pub timeout: Duration,
+    /// Times the request is sent again after timing out
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each retry after it
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// A single attempt waiting up to `timeout`.
+    pub fn new(timeout: Duration) -> RetryPolicy {
+        RetryPolicy {
+            timeout,
+            retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> RetryPolicy {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Wait before retry number `retry`, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
+
+/// The requests a session sent to its client, by id, until they are answered.
+pub struct OutgoingRequests {
+    /// Where the requests are written
+    outgoing: Arc<OutgoingQueue>,
+    next_id: AtomicU64,
+    /// Where the response of each request waiting is delivered, keyed by id
+    pending: Mutex<HashMap<JsonRpcId, Sender<Response>>>,
+}
+
+impl OutgoingRequests {
+    pub fn new(outgoing: Arc<OutgoingQueue>) -> OutgoingRequests {
+        OutgoingRequests {
+            outgoing,
+            next_id: AtomicU64::new(1),
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Sends a request for `method` to the client, returning the handle its response arrives
+    /// on.
+    pub fn send(self: &Arc<Self>, method: &str, params: Option<Value>) -> PendingRequest {
+        let id = JsonRpcId::Number(self.next_id.fetch_add(1, Ordering::SeqCst).into());
+        let (sender, response) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+        tracing::info!("Sending request {} ({}) to the client", id, method);
+        self.outgoing.push(JsonRpcRequest {
+            id: id.clone(),
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+        });
+        PendingRequest {
+            id,
+            method: method.to_string(),
+            response,
+            requests: self.clone(),
+        }
+    }
+
+    /// Sends a request for `method` and waits for its response as `policy` says, each retry
+    /// being a new request with an id of its own. Attempts also stop waiting at `deadline`, after
+    /// which the request isn't sent again, and when `cancellation` is flipped. A request that is
+    /// never answered fails with a `Timeout` error telling the `attempts` made.
+    pub fn request(
+        self: &Arc<Self>,
+        method: &str,
+        params: Option<Value>,
+        policy: &RetryPolicy,
+        deadline: Option<Instant>,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<Value, JsonRpcError> {
+        let mut retry = 0;
+        loop {
+            let timeout = limit_timeout(policy.timeout, deadline);
+            let error = match self
+                .send(method, params.clone())
+                .wait(timeout, cancellation)
+            {
+                Err(error) if error.code == TIMEOUT.code => error,
+                result => return result,
+            };
+            let delay = policy.delay(retry + 1);
+            let past_deadline = deadline.is_some_and(|deadline| Instant::now() + delay >= deadline);
+            if retry == policy.retries || past_deadline {
+                return Err(error.with_data("attempts", retry + 1));
+            }
+            retry += 1;
+            tracing::info!(
+                "Sending {} again in {:?} ({} of {})",
+                method,
+                delay,
+                retry,
+                policy.retries
+            );
+            let waited = Instant::now() + delay;
+            while Instant::now() < waited {
+                if cancellation.is_some_and(CancellationToken::is_cancelled) {
+                    return Err(JsonRpcError::new(
+                        ERROR_CODE_INTERNAL_ERROR,
+                        format!("{} was cancelled", method),
+                    ));
+                }
+                thread::sleep(POLL_INTERVAL.min(waited.saturating_duration_since(Instant::now())));
+            }
+        }
+    }
+
+    /// Hands the client's response to the request with `id`. False if no request waits for it,
+    /// e.g. because it timed out.
+    pub fn complete(&self, id: &JsonRpcId, response: Response) -> bool {
+        match self.pending.lock().unwrap().remove(id) {
+            Some(sender) => {
+                let _ = sender.send(response);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fails the requests still waiting, once the session ends.
+    pub fn close(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Number of requests waiting for their response.
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A request sent to the client, waiting for its response.
+pub struct PendingRequest {
+    id: JsonRpcId,
+    method: String,
+    response: Receiver<Response>,
+    requests: Arc<OutgoingRequests>,
+}
+
+impl PendingRequest {
+    pub fn id(&self) -> &JsonRpcId {
+        &self.id
+    }
+
+    /// Waits for the response, up to `timeout` (none when zero) and until `cancellation` is
+    /// flipped. The client is told with `notifications/cancelled` when the request is given
+    /// up on, and a response arriving later is dropped.
+    pub fn wait(
+        self,
+        timeout: Duration,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<Value, JsonRpcError> {
+        let deadline = (!timeout.is_zero()).then(|| Instant::now() + timeout);
+        let failure = loop {
+            match self.response.recv_timeout(POLL_INTERVAL) {
+                Ok(response) => return response,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(JsonRpcError::new(
+                        ERROR_CODE_INTERNAL_ERROR,
+                        format!(
+                            "The session ended before the client answered {}",
+                            self.method
+                        ),
+                    ))
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if cancellation.is_some_and(CancellationToken::is_cancelled) {
+                        break JsonRpcError::new(
+                            ERROR_CODE_INTERNAL_ERROR,
+                            format!("{} was cancelled", self.method),
+                        );
+                    }
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        break TIMEOUT.error(format!(
+                            "The client didn't answer {} within {:?}",
+                            self.method, timeout
+                        ));
+                    }
+                }
+            }
+        };
+        tracing::warn!("Giving up on request {}: {}", self.id, failure.message);
+        self.requests.outgoing.push(JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": self.id, "reason": failure.message })),
+        });
+        Err(failure)
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        self.requests.pending.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{
+    context::RequestContext,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{Annotations, Icon, ReadResourceResult},
+    policy,
+    protocol::ProtocolVersion,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A resource as listed by `resources/list`.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/resources/#listing-resources
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    /// URI identifying the resource
+    pub uri: String,
+    /// Name of the resource, such as its file name
+    pub name: String,
+    /// Name to display, if different from `name`, since 2025-06-18
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Description of the resource, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIME type of the resource, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Icons to display, since 2025-06-18
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub icons: Vec<Icon>,
+    /// Audience and priority of the resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Annotations>,
+}
+
+impl Resource {
+    pub fn with_title(mut self, title: impl Into<String>) -> Resource {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: Icon) -> Resource {
+        self.icons.push(icon);
+        self
+    }
+
+    pub fn with_annotations(mut self, annotations: Annotations) -> Resource {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    /// The resource without the display fields `version` doesn't know about.
+    pub fn for_version(mut self, version: ProtocolVersion) -> Resource {
+        if !version.supports_titles() {
+            self.title = None;
+            self.icons.clear();
+        }
+        if !version.supports_last_modified() {
+            if let Some(annotations) = &mut self.annotations {
+                annotations.last_modified = None;
+            }
+            if self.annotations == Some(Annotations::default()) {
+                self.annotations = None;
+            }
+        }
+        self
+    }
+}
+
+/// A family of resources whose URIs follow an RFC 6570 template, as listed by
+/// `resources/templates/list`.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/resources/#resource-templates
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplate {
+    /// Template of the URIs, such as `file://{path}`
+    pub uri_template: String,
+    /// Human-readable name of the resources
+    pub name: String,
+    /// Name to display, if different from `name`, since 2025-06-18
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Description of the resources, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIME type of the resources, if they all have the same
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Audience and priority of the resources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Annotations>,
+}
+
+impl ResourceTemplate {
+    /// The template without the display fields `version` doesn't know about.
+    pub fn for_version(mut self, version: ProtocolVersion) -> ResourceTemplate {
+        if !version.supports_titles() {
+            self.title = None;
+        }
+        self
+    }
+}
+
+/// Source of resources. The server asks every registered provider for its resources when
+/// listing, and hands `resources/read` to the first provider that claims the URI.
+pub trait ResourceProvider: Send + Sync {
+    /// Resources currently offered by this provider.
+    fn list(&self, ctx: &RequestContext) -> Vec<Resource>;
+
+    /// Reads `uri`, or returns None if the URI doesn't belong to this provider.
+    fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>>;
+
+    /// Templates of the resources this provider can read besides the listed ones.
+    fn templates(&self, _ctx: &RequestContext) -> Vec<ResourceTemplate> {
+        Vec::new()
+    }
+
+    /// Values completing `value` for the variable `argument` of `uri_template`, for
+    /// `completion/complete`, or None if the template doesn't belong to this provider.
+    fn complete(
+        &self,
+        _ctx: &RequestContext,
+        _uri_template: &str,
+        _argument: &str,
+        _value: &str,
+    ) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Called when a client subscribes to `uri`, for providers that must ask for updates.
+    fn subscribe(&self, _uri: &str) {}
+
+    /// Called when a client unsubscribes from `uri`.
+    fn unsubscribe(&self, _uri: &str) {}
+}
+
+/// Backend reading the URIs of a scheme, such as `data:` or `git://`, whose resources can't be
+/// listed. The handler of a URI's scheme is asked before the providers.
+pub trait SchemeHandler: Send + Sync {
+    /// Reads `uri`, whose scheme is the handler's.
+    fn read(&self, ctx: &RequestContext, uri: &str) -> Result<ReadResourceResult, JsonRpcError>;
+
+    /// Whether the scheme can be read when `resource_schemes` doesn't say.
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+}
+
+/// Resource providers and scheme handlers registered on the server.
+#[derive(Default)]
+pub struct ResourceRegistry {
+    providers: Vec<Box<dyn ResourceProvider>>,
+    /// Handlers by lowercase scheme
+    schemes: BTreeMap<String, Box<dyn SchemeHandler>>,
+}
+
+impl ResourceRegistry {
+    pub fn register(&mut self, provider: Box<dyn ResourceProvider>) {
+        self.providers.push(provider);
+    }
+
+    /// Whether `scheme` can be read when `resource_schemes` doesn't say: as its handler says,
+    /// or else enabled.
+    fn enabled_by_default(&self, scheme: &str) -> bool {
+        self.schemes
+            .get(scheme)
+            .is_none_or(|handler| handler.enabled_by_default())
+    }
+
+    /// Hands the URIs of `scheme` (without `:`) to `handler`, replacing its previous handler.
+    pub fn register_scheme(&mut self, scheme: &str, handler: Box<dyn SchemeHandler>) {
+        self.schemes.insert(scheme.to_ascii_lowercase(), handler);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// Resources of every provider the session's scopes grant and `resource_schemes` allows,
+    /// as the session's protocol version can carry them.
+    pub fn list(&self, ctx: &RequestContext) -> Vec<Resource> {
+        self.providers
+            .iter()
+            .flat_map(|p| p.list(ctx))
+            .filter(|resource| in_scopes(ctx, &resource.uri))
+            .filter(|resource| match scheme(&resource.uri) {
+                Some(scheme) => {
+                    let default = self.enabled_by_default(&scheme);
+                    policy::check_scheme(&ctx.config, &scheme, default, &resource.uri).is_ok()
+                }
+                None => true,
+            })
+            .map(|resource| resource.for_version(ctx.session.version))
+            .collect()
+    }
+
+    /// Reads `uri` with the handler of its scheme, or else from the first provider claiming it.
+    /// Resources the session's scopes don't grant are unknown, and those of the schemes
+    /// `resource_schemes` disables or whose allowlist doesn't match the URI can't be read.
+    pub fn read(
+        &self,
+        ctx: &RequestContext,
+        uri: &str,
+    ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        if !in_scopes(ctx, uri) {
+            return None;
+        }
+        let scheme = scheme(uri);
+        let handler = scheme
+            .as_deref()
+            .and_then(|scheme| self.schemes.get(scheme));
+        if let Some(scheme) = &scheme {
+            let enabled = handler.is_none_or(|handler| handler.enabled_by_default());
+            if let Err(e) = policy::check_scheme(&ctx.config, scheme, enabled, uri) {
+                return Some(Err(
+                    JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e).with_data("pointer", "/uri")
+                ));
+            }
+        }
+        match handler {
+            Some(handler) => Some(handler.read(ctx, uri)),
+            None => self.providers.iter().find_map(|p| p.read(ctx, uri)),
+        }
+    }
+
+    /// Resource templates of every provider the session's scopes grant, matching their
+    /// patterns against the templates themselves, and of the enabled schemes, as the session's
+    /// protocol version can carry them.
+    pub fn templates(&self, ctx: &RequestContext) -> Vec<ResourceTemplate> {
+        self.providers
+            .iter()
+            .flat_map(|p| p.templates(ctx))
+            .filter(|template| in_scopes(ctx, &template.uri_template))
+            .filter(|template| {
+                scheme(&template.uri_template).is_none_or(|scheme| {
+                    let default = self.enabled_by_default(&scheme);
+                    policy::scheme_enabled(&ctx.config, &scheme, default)
+                })
+            })
+            .map(|template| template.for_version(ctx.session.version))
+            .collect()
+    }
+
+    /// Completes a template variable with the first provider claiming the template, keeping the
+    /// values making URIs the session's scopes grant.
+    pub fn complete(
+        &self,
+        ctx: &RequestContext,
+        uri_template: &str,
+        argument: &str,
+        value: &str,
+    ) -> Option<Vec<String>> {
+        let values = self
+            .providers
+            .iter()
+            .find_map(|p| p.complete(ctx, uri_template, argument, value))?;
+        Some(
+            values
+                .into_iter()
+                .filter(|value| in_scopes(ctx, &expand(uri_template, argument, value)))
+                .collect(),
+        )
+    }
+
+    /// Tells every provider that a client subscribed to `uri`, or unsubscribed from it.
+    pub fn set_subscribed(&self, uri: &str, subscribed: bool) {
+        for provider in &self.providers {
+            if subscribed {
+                provider.subscribe(uri);
+            } else {
+                provider.unsubscribe(uri);
+            }
+        }
+    }
+}
+
+/// Lowercase scheme of `uri`, the part before the first `:` when it is a valid RFC 3986
+/// scheme.
+pub fn scheme(uri: &str) -> Option<String> {
+    let (scheme, _) = uri.split_once(':')?;
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then(|| scheme.to_ascii_lowercase())
+}
+
+/// URI of `uri_template` with `value` in place of the variable `argument`, the others left as
+/// they are.
+fn expand(uri_template: &str, argument: &str, value: &str) -> String {
+    ["", "+", "#"]
+        .iter()
+        .fold(uri_template.to_string(), |uri, operator| {
+            uri.replace(&format!("{{{}{}}}", operator, argument), value)
+        })
+}
+
+/// True if the scopes of the session serving `ctx` grant the resource at `uri`.
+fn in_scopes(ctx: &RequestContext, uri: &str) -> bool {
+    policy::resource_in_scopes(&ctx.config, ctx.session.scopes.as_deref(), uri)
+}
@@ -0,0 +1,258 @@
+//! Confinement of filesystem access to the configured root directories.
+
+use crate::{
+    errors::ROOT_VIOLATION,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Symbolic links followed at most when resolving a path that doesn't exist, as by the OS.
+const MAX_LINKS: usize = 40;
+
+/// Why a path can't be resolved.
+enum Unresolved {
+    Outside(String),
+    Invalid(String),
+}
+
+/// Resolves `path` against the allowed `roots`, returning its canonical form.
+///
+/// Relative paths are taken relative to the first root. The path is canonicalized (resolving
+/// `..` components and symbolic links) before being checked, so it cannot escape the roots.
+/// Paths that don't exist yet are accepted when their parent directory resolves inside a root.
+pub fn resolve(roots: &[PathBuf], path: &str) -> Result<PathBuf, String> {
+    locate(roots, path).map_err(|(Unresolved::Outside(e) | Unresolved::Invalid(e))| e)
+}
+
+/// [`resolve`] for the path of a request: a path outside the roots is a [`ROOT_VIOLATION`]
+/// error, and the other failures invalid params.
+pub fn resolve_param(roots: &[PathBuf], path: &str) -> Result<PathBuf, JsonRpcError> {
+    locate(roots, path).map_err(|unresolved| match unresolved {
+        Unresolved::Outside(e) => ROOT_VIOLATION.error(e),
+        Unresolved::Invalid(e) => JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e),
+    })
+}
+
+fn locate(roots: &[PathBuf], path: &str) -> Result<PathBuf, Unresolved> {
+    let invalid = Unresolved::Invalid;
+    let Some(first) = roots.first() else {
+        return Err(invalid("no root directories are configured".to_string()));
+    };
+    let path = Path::new(path);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        first.join(path)
+    };
+
+    let canonical = match fs::canonicalize(&path) {
+        Ok(canonical) => canonical,
+        Err(_) => resolve_missing(&path).map_err(invalid)?,
+    };
+
+    let allowed = roots
+        .iter()
+        .filter_map(|root| fs::canonicalize(root).ok())
+        .any(|root| canonical.starts_with(root));
+    if allowed {
+        Ok(canonical)
+    } else {
+        Err(Unresolved::Outside(format!(
+            "{} is outside the allowed roots",
+            path.display()
+        )))
+    }
+}
+
+/// Canonical form of `path`, which doesn't exist: its canonical parent joined with its name.
+/// A name that is a symbolic link to a missing target (a dangling link) is followed, since
+/// creating the file would create its target, which may lie out of the roots.
+fn resolve_missing(path: &Path) -> Result<PathBuf, String> {
+    let mut missing = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        let (Some(parent), Some(name)) = (missing.parent(), missing.file_name()) else {
+            return Err(format!("invalid path: {}", path.display()));
+        };
+        let parent = fs::canonicalize(parent)
+            .map_err(|e| format!("cannot resolve {}: {}", path.display(), e))?;
+        let joined = parent.join(name);
+        match fs::read_link(&joined) {
+            // Relative targets are relative to the directory of the link
+            Ok(target) => missing = parent.join(target),
+            Err(_) => return Ok(joined),
+        }
+    }
+    Err(format!(
+        "cannot resolve {}: too many symbolic links",
+        path.display()
+    ))
+}
+
+/// Converts an absolute path to a `file://` URI.
+pub fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Converts a `file://` URI back to a path, or returns None if `uri` is not a file URI.
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    let path = uri.strip_prefix("file://")?;
+    let path = String::from_utf8(percent_decode(path)).ok()?;
+    // file:///C:/dir on Windows
+    match path.as_bytes() {
+        [b'/', _, b':', ..] => Some(path[1..].to_string()),
+        _ => Some(path),
+    }
+}
+
+/// Bytes of `text` with its `%XX` escapes decoded; other `%` are kept as is.
+pub(crate) fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A temporary directory with two roots, `a` (holding `sub/inside.txt`) and `b`, and an
+    /// `outside` directory next to them holding `secret.txt`.
+    fn dirs() -> (TempDir, Vec<PathBuf>, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let base = fs::canonicalize(dir.path()).unwrap();
+        let roots = vec![base.join("a"), base.join("b")];
+        let outside = base.join("outside");
+        for dir in roots.iter().chain([&outside, &roots[0].join("sub")]) {
+            fs::create_dir(dir).unwrap();
+        }
+        fs::write(roots[0].join("sub/inside.txt"), "").unwrap();
+        fs::write(outside.join("secret.txt"), "").unwrap();
+        (dir, roots, outside)
+    }
+
+    fn is_outside(result: Result<PathBuf, Unresolved>) -> bool {
+        matches!(result, Err(Unresolved::Outside(_)))
+    }
+
+    #[test]
+    fn relative_paths_are_taken_in_the_first_root() {
+        let (_dir, roots, _) = dirs();
+        let inside = roots[0].join("sub/inside.txt");
+        assert_eq!(locate(&roots, "sub/inside.txt").ok(), Some(inside.clone()));
+        assert_eq!(locate(&roots, "./sub/../sub/inside.txt").ok(), Some(inside));
+        assert!(matches!(locate(&[], "x"), Err(Unresolved::Invalid(_))));
+    }
+
+    #[test]
+    fn parent_components_cannot_leave_the_roots() {
+        let (_dir, roots, outside) = dirs();
+        assert!(is_outside(locate(&roots, "../outside/secret.txt")));
+        assert!(is_outside(locate(&roots, "sub/../../outside/secret.txt")));
+        // Not existing yet, checked by its parent
+        assert!(is_outside(locate(&roots, "../outside/new.txt")));
+        let through_root = outside.join("../a/sub/inside.txt");
+        assert_eq!(
+            locate(&roots, &through_root.to_string_lossy()).ok(),
+            Some(roots[0].join("sub/inside.txt"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links_out_of_the_roots_are_refused() {
+        let (_dir, roots, outside) = dirs();
+        std::os::unix::fs::symlink(&outside, roots[0].join("dir_link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), roots[0].join("file_link")).unwrap();
+        assert!(is_outside(locate(&roots, "dir_link/secret.txt")));
+        assert!(is_outside(locate(&roots, "dir_link/new.txt")));
+        assert!(is_outside(locate(&roots, "file_link")));
+        // Dangling links are followed to where the file would be created
+        std::os::unix::fs::symlink(outside.join("new.txt"), roots[0].join("dangling")).unwrap();
+        std::os::unix::fs::symlink("dangling", roots[0].join("to_dangling")).unwrap();
+        assert!(is_outside(locate(&roots, "dangling")));
+        assert!(is_outside(locate(&roots, "to_dangling")));
+        std::os::unix::fs::symlink("sub/new.txt", roots[0].join("dangling_inside")).unwrap();
+        assert_eq!(
+            locate(&roots, "dangling_inside").ok(),
+            Some(roots[0].join("sub/new.txt"))
+        );
+        std::os::unix::fs::symlink("loop", roots[0].join("loop")).unwrap();
+        assert!(matches!(
+            locate(&roots, "loop"),
+            Err(Unresolved::Invalid(_))
+        ));
+        // Links between roots are followed
+        std::os::unix::fs::symlink(&roots[1], roots[0].join("b_link")).unwrap();
+        assert_eq!(
+            locate(&roots, "b_link/new.txt").ok(),
+            Some(roots[1].join("new.txt"))
+        );
+    }
+
+    #[test]
+    fn paths_whose_parent_does_not_exist_are_invalid() {
+        let (_dir, roots, _) = dirs();
+        assert_eq!(
+            locate(&roots, "sub/new.txt").ok(),
+            Some(roots[0].join("sub/new.txt"))
+        );
+        assert!(matches!(
+            locate(&roots, "missing/new.txt"),
+            Err(Unresolved::Invalid(_))
+        ));
+        assert!(matches!(
+            resolve_param(&roots, "missing/new.txt"),
+            Err(e) if e.code == ERROR_CODE_INVALID_PARAMS
+        ));
+    }
+
+    #[test]
+    fn absolute_paths_may_be_in_any_root() {
+        let (_dir, roots, outside) = dirs();
+        let in_second = roots[1].join("new.txt");
+        assert_eq!(
+            locate(&roots, &in_second.to_string_lossy()).ok(),
+            Some(in_second)
+        );
+        let secret = outside.join("secret.txt");
+        assert!(is_outside(locate(&roots, &secret.to_string_lossy())));
+        assert!(matches!(
+            resolve_param(&roots, &secret.to_string_lossy()),
+            Err(e) if e.code == ROOT_VIOLATION.code
+        ));
+        // A root doesn't extend to the directories its name is a prefix of
+        fs::create_dir(roots[0].with_file_name("ab")).unwrap();
+        let sibling = roots[0].with_file_name("ab").join("new.txt");
+        assert!(is_outside(locate(&roots, &sibling.to_string_lossy())));
+    }
+}
@@ -0,0 +1,191 @@
+//! JSON schema validation of the arguments sent to tools and prompts, and of the messages the
+//! server sends against the MCP schema.
+
+use crate::jsonrpc::{JsonRpcError, JsonRpcNotification, ERROR_CODE_INVALID_PARAMS};
+use jsonschema::Validator;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::OnceLock};
+
+/// Definitions of the MCP 2024-11-05 schema for the messages a server sends.
+const MCP_SCHEMA: &str = include_str!("../schema/mcp-2024-11-05.json");
+
+/// Compiles `schema`. Invalid schemas are logged and yield no validator, so that the
+/// arguments aren't checked rather than always rejected.
+pub(crate) fn compile(owner: &str, schema: &Value) -> Option<Validator> {
+    match jsonschema::validator_for(schema) {
+        Ok(validator) => Some(validator),
+        Err(e) => {
+            tracing::error!(
+                "Invalid schema for {}, arguments won't be checked: {}",
+                owner,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Checks `arguments`, failing with INVALID_PARAMS. The error says where the first problem is,
+/// and `data.errors` lists all of them with their JSON pointer in the arguments.
+pub(crate) fn check(validator: &Validator, arguments: &Value) -> Result<(), JsonRpcError> {
+    let errors: Vec<(String, String)> = validator
+        .iter_errors(arguments)
+        .map(|error| {
+            (
+                error.instance_path().as_str().to_string(),
+                error.to_string(),
+            )
+        })
+        .collect();
+    let Some((pointer, message)) = errors.first() else {
+        return Ok(());
+    };
+    let message = match pointer.as_str() {
+        "" => format!("Invalid arguments: {}", message),
+        pointer => format!("Invalid argument at '{}': {}", pointer, message),
+    };
+    let mut error = JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, message);
+    error.data = Some(json!({
+        "pointer": pointer,
+        "errors": errors
+            .iter()
+            .map(|(pointer, message)| json!({ "pointer": pointer, "message": message }))
+            .collect::<Vec<_>>(),
+    }));
+    Err(error)
+}
+
+/// Definitions messages are checked against, those of [`result_definition`] and
+/// [`notification_definition`].
+const DEFINITIONS: &[&str] = &[
+    "EmptyResult",
+    "InitializeResult",
+    "ListResourcesResult",
+    "ListResourceTemplatesResult",
+    "ReadResourceResult",
+    "ListPromptsResult",
+    "GetPromptResult",
+    "ListToolsResult",
+    "CallToolResult",
+    "CompleteResult",
+    "CancelledNotification",
+    "ProgressNotification",
+    "LoggingMessageNotification",
+    "ResourceUpdatedNotification",
+    "ResourceListChangedNotification",
+    "ToolListChangedNotification",
+    "PromptListChangedNotification",
+];
+
+/// Definition the result of a `method` request must follow.
+fn result_definition(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "initialize" => "InitializeResult",
+        "ping" | "resources/subscribe" | "resources/unsubscribe" | "logging/setLevel" => {
+            "EmptyResult"
+        }
+        "resources/list" => "ListResourcesResult",
+        "resources/templates/list" => "ListResourceTemplatesResult",
+        "resources/read" => "ReadResourceResult",
+        "prompts/list" => "ListPromptsResult",
+        "prompts/get" => "GetPromptResult",
+        "tools/list" => "ListToolsResult",
+        "tools/call" => "CallToolResult",
+        "completion/complete" => "CompleteResult",
+        _ => return None,
+    })
+}
+
+/// Definition a `method` notification must follow.
+fn notification_definition(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "notifications/cancelled" => "CancelledNotification",
+        "notifications/progress" => "ProgressNotification",
+        "notifications/message" => "LoggingMessageNotification",
+        "notifications/resources/updated" => "ResourceUpdatedNotification",
+        "notifications/resources/list_changed" => "ResourceListChangedNotification",
+        "notifications/tools/list_changed" => "ToolListChangedNotification",
+        "notifications/prompts/list_changed" => "PromptListChangedNotification",
+        _ => return None,
+    })
+}
+
+/// Validators of the MCP schema definitions, compiled once.
+fn mcp_validators() -> &'static HashMap<&'static str, Validator> {
+    static VALIDATORS: OnceLock<HashMap<&'static str, Validator>> = OnceLock::new();
+    VALIDATORS.get_or_init(|| {
+        let document: Value = serde_json::from_str(MCP_SCHEMA).unwrap_or_default();
+        DEFINITIONS
+            .iter()
+            .filter_map(|&name| {
+                let schema = json!({
+                    "$schema": document["$schema"],
+                    "definitions": document["definitions"],
+                    "$ref": format!("#/definitions/{}", name),
+                });
+                compile(name, &schema).map(|validator| (name, validator))
+            })
+            .collect()
+    })
+}
+
+/// Checks the result of a `method` request against the MCP schema, logging the violations.
+/// Methods the schema doesn't define aren't checked.
+pub(crate) fn check_result(method: &str, result: &Value) {
+    if let Some(definition) = result_definition(method) {
+        log_violations(&format!("{} result", method), definition, result);
+    }
+}
+
+/// Checks a notification against the MCP schema, logging the violations.
+pub(crate) fn check_notification(notification: &JsonRpcNotification) {
+    if let Some(definition) = notification_definition(&notification.method) {
+        let message = serde_json::to_value(notification).unwrap_or_default();
+        log_violations(&notification.method, definition, &message);
+    }
+}
+
+fn log_violations(what: &str, definition: &str, message: &Value) {
+    for (pointer, error) in violations(definition, message) {
+        tracing::warn!(
+            "{} violates the MCP schema ({}) at '{}': {}",
+            what,
+            definition,
+            pointer,
+            error
+        );
+    }
+}
+
+/// Where the result of a `method` request violates the MCP schema, as JSON pointers in the
+/// result and what is wrong there. Methods the schema doesn't define have no violations.
+pub fn result_violations(method: &str, result: &Value) -> Vec<(String, String)> {
+    result_definition(method)
+        .map(|definition| violations(definition, result))
+        .unwrap_or_default()
+}
+
+/// Where a notification the server sent violates the MCP schema, as for
+/// [`result_violations`].
+pub fn notification_violations(notification: &Value) -> Vec<(String, String)> {
+    notification["method"]
+        .as_str()
+        .and_then(notification_definition)
+        .map(|definition| violations(definition, notification))
+        .unwrap_or_default()
+}
+
+fn violations(definition: &str, message: &Value) -> Vec<(String, String)> {
+    let Some(validator) = mcp_validators().get(definition) else {
+        return Vec::new();
+    };
+    validator
+        .iter_errors(message)
+        .map(|error| {
+            (
+                error.instance_path().as_str().to_string(),
+                error.to_string(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,2099 @@
+use crate::audit::{self, AuditLog, AuditQuery, AuditRecord, Outcome};
+use crate::codec::{self, BoundedLines, Limits, Message, RawRequest};
+use crate::config::Config;
+use crate::context::{
+    self, CancellationToken, NotificationSender, RequestContext, ResourceReader, SessionInfo,
+    TRACE_ID_META,
+};
+use crate::crash::{self, Crash, CrashReporter, RecentMessages};
+use crate::errors::{self, ErrorCode, ErrorRegistry};
+use crate::extensions::{Extension, ExtensionRegistry};
+use crate::idempotency::{self, ResponseCache};
+use crate::jsonrpc::*;
+use crate::mcp::{CallToolResult, CreateMessageRequest, Implementation, LoggingLevel};
+use crate::methods;
+#[cfg(feature = "metrics")]
+use crate::metrics::{Metrics, OTHER_METHOD};
+#[cfg(feature = "otel")]
+use crate::otel::{RequestSpan, Spans};
+use crate::output::{spawn_writer, OutgoingQueue, TapWriter, DEFAULT_OUTGOING_CAPACITY};
+use crate::pagination::Paginator;
+use crate::policy::{self, ApprovalHook, SamplingHook};
+use crate::prompts::{Prompt, PromptRegistry};
+use crate::protocol::ProtocolVersion;
+use crate::ratelimit::RateLimiter;
+use crate::requests::OutgoingRequests;
+use crate::resources::{ResourceProvider, ResourceRegistry, SchemeHandler};
+use crate::schema;
+use crate::spill::{self, SpilledOutputs};
+use crate::state::{SavedSession, StateStore};
+use crate::state_bag::StateBag;
+use crate::tools::{CallLimiter, Tool, ToolRegistry};
+use crate::transcript::{Direction, Recorder};
+use crate::wirelog::WireLog;
+use serde::Serialize;
+use serde_json::{json, Value};
+#[cfg(feature = "otel")]
+use std::time::SystemTime;
+use std::{
+    any::Any,
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex, RwLock, Weak,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::Span;
+
+/// Hook invoked when a client sends the `initialize` request, with the request params.
+pub type InitializeHook = Box<dyn Fn(Option<&Value>) + Send + Sync>;
+/// Hook invoked on a lifecycle transition that carries no data.
+pub type LifecycleHook = Box<dyn Fn() + Send + Sync>;
+/// Handler of the requests whose method the server doesn't know, with the method and the params
+/// as the client sent them.
+pub type FallbackHandler =
+    Box<dyn Fn(&RequestContext, &str, Option<&Value>) -> Result<Value, JsonRpcError> + Send + Sync>;
+/// Stores the state of a new session in its bag, see [`Server::with_session_state`].
+pub type SessionStateFactory = Box<dyn Fn(&StateBag) + Send + Sync>;
+
+/// The message of a panic, when it is a string.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// How often [`Server::shutdown`] checks whether the requests in flight are done.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Time [`Server::shutdown`] gives the sessions to write their remaining messages.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lifecycle hook points that embedders can use to allocate and tear down per-session resources.
+#[derive(Default)]
+struct Hooks {
+    /// Fired when a client connects and sends `initialize`
+    on_initialize: Option<InitializeHook>,
+    /// Fired when the client completes the handshake with `notifications/initialized`
+    on_initialized: Option<LifecycleHook>,
+    /// Fired when the client disconnects (end of input)
+    on_shutdown: Option<LifecycleHook>,
+}
+
+/// Messages handled and errors answered by a session or by the whole server.
+struct Stats {
+    started: Instant,
+    messages: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+impl Stats {
+    fn new() -> Stats {
+        Stats {
+            started: Instant::now(),
+            messages: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+        }
+    }
+
+    fn summary(&self) -> Summary {
+        Summary {
+            messages: self.messages.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            uptime: self.started.elapsed(),
+        }
+    }
+}
+
+/// What a server or a session did, logged when it ends.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    /// Incoming messages, valid or not
+    pub messages: usize,
+    /// Error responses sent
+    pub errors: usize,
+    pub uptime: Duration,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} messages handled, {} errors, up {:.1?}",
+            self.messages, self.errors, self.uptime
+        )
+    }
+}
+
+/// State of one client connection.
+struct Session {
+    /// Identifier of the session in the audit log
+    id: String,
+    /// Key the session is saved under in the state file, for sessions that can be resumed
+    key: Option<String>,
+    info: Mutex<SessionInfo>,
+    /// Cancellation tokens of the requests currently being served, keyed by request id
+    in_flight: Mutex<HashMap<JsonRpcId, CancellationToken>>,
+    /// Responses replayed to the requests the client sends again, see [`crate::idempotency`]
+    responses: ResponseCache,
+    /// Messages waiting to be written by the writer thread
+    outgoing: Arc<OutgoingQueue>,
+    /// Requests sent to the client, waiting for its responses
+    requests: Arc<OutgoingRequests>,
+    /// URIs of the resources the client subscribed to with `resources/subscribe`
+    subscriptions: Mutex<HashSet<String>>,
+    /// True once `initialize` has been received
+    initialize_received: AtomicBool,
+    /// True once the shutdown hook ran for the session, which it does only once
+    shut_down: AtomicBool,
+    /// Token buckets of the `rate_limits`
+    rate_limiter: RateLimiter,
+    /// Level set with `logging/setLevel`, `client_log_level` until then
+    log_level: Mutex<Option<LoggingLevel>>,
+    /// Token bucket of the `client_log_rate`
+    log_limiter: RateLimiter,
+    /// Log events dropped by the `client_log_rate` since the last one sent
+    dropped_logs: AtomicUsize,
+    /// Last messages received and sent, kept for the crash reports if there are reporters
+    recent: Option<Arc<RecentMessages>>,
+    /// Values made by the session state factories, dropped with the session
+    state: Arc<StateBag>,
+    stats: Stats,
+}
+
+/// MCP server speaking JSON-RPC over newline-delimited streams (stdio by default).
+pub struct Server {
+    hooks: Hooks,
+    /// Name and version sent in the `initialize` result
+    info: Implementation,
+    outgoing_capacity: usize,
+    /// Limits checked on every incoming message
+    limits: Limits,
+    /// Settings that can change while sessions are running, see [`Server::reload_config`]
+    config: RwLock<Arc<Config>>,
+    tools: RwLock<ToolRegistry>,
+    resources: Arc<RwLock<ResourceRegistry>>,
+    prompts: RwLock<PromptRegistry>,
+    /// Connected sessions, used to broadcast notifications
+    sessions: Mutex<Vec<Weak<Session>>>,
+    /// Where the messages of every session are recorded, see [`Server::with_recorder`]
+    recorder: Option<Arc<Recorder>>,
+    /// Where the panics of the handlers are reported, see [`Server::with_crash_reporter`]
+    crash_reporters: Vec<Box<dyn CrashReporter>>,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    /// Spans of the requests served, waiting for [`crate::otel`] to export them
+    #[cfg(feature = "otel")]
+    otel_spans: Spans,
+    tool_calls: CallLimiter,
+    /// Approves the calls of the tools marked `ask`, see [`Server::with_approval_hook`]
+    approval: Option<ApprovalHook>,
+    /// Approves the sampling requests of the handlers, see [`Server::with_sampling_hook`]
+    sampling_hook: Option<SamplingHook>,
+    /// Vendor-specific methods, see [`Server::with_extension`]
+    extensions: ExtensionRegistry,
+    /// Serves the requests of unknown methods, see [`Server::with_fallback`]
+    fallback: Option<FallbackHandler>,
+    /// Application error codes, see [`Server::with_error_code`]
+    errors: ErrorRegistry,
+    audit: AuditLog,
+    pagination: Paginator,
+    /// Full outputs of the tool results that were cut, see [`crate::spill`]
+    spilled: Arc<SpilledOutputs>,
+    /// Number of sessions subscribed to each resource URI
+    subscribers: Mutex<HashMap<String, usize>>,
+    /// Where resumable sessions are saved, see [`Server::with_state_store`]
+    state: Option<StateStore>,
+    /// State shared by all the sessions, see [`Server::with_shared_state`]
+    shared_state: Arc<StateBag>,
+    /// Make the state of each session, see [`Server::with_session_state`]
+    session_state: Vec<SessionStateFactory>,
+    /// Totals of all the sessions, see [`Server::summary`]
+    stats: Stats,
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server {
+            hooks: Hooks::default(),
+            info: Implementation::default(),
+            outgoing_capacity: DEFAULT_OUTGOING_CAPACITY,
+            limits: Limits::default(),
+            config: RwLock::default(),
+            tools: RwLock::default(),
+            resources: Arc::default(),
+            prompts: RwLock::default(),
+            sessions: Mutex::default(),
+            recorder: None,
+            crash_reporters: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::default(),
+            #[cfg(feature = "otel")]
+            otel_spans: Spans::default(),
+            tool_calls: CallLimiter::default(),
+            approval: None,
+            sampling_hook: None,
+            extensions: ExtensionRegistry::default(),
+            fallback: None,
+            errors: ErrorRegistry::default(),
+            audit: AuditLog::default(),
+            pagination: Paginator::default(),
+            spilled: Arc::default(),
+            subscribers: Mutex::default(),
+            state: None,
+            shared_state: Arc::default(),
+            session_state: Vec::new(),
+            stats: Stats::new(),
+        }
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Overrides the `serverInfo` name and version, which default to the crate's.
+    pub fn with_server_info(
+        mut self,
+        name: impl Into<String>,
+        version: impl Into<String>,
+    ) -> Server {
+        self.info = Implementation {
+            name: name.into(),
+            version: version.into(),
+        };
+        self
+    }
+
+    /// Sets the initial runtime settings (enabled tools, ...).
+    pub fn with_config(self, config: Config) -> Server {
+        *self.config.write().unwrap() = Arc::new(config);
+        self
+    }
+
+    /// Registers a tool at build time.
+    pub fn with_tool(self, tool: Tool) -> Server {
+        self.tools.write().unwrap().register(tool);
+        self
+    }
+
+    /// Registers a resource provider at build time.
+    pub fn with_resources(self, provider: impl ResourceProvider + 'static) -> Server {
+        self.resources.write().unwrap().register(Box::new(provider));
+        self
+    }
+
+    /// Hands the `resources/read` of the URIs of `scheme` (without `:`) to `handler` at build
+    /// time, before the resource providers.
+    pub fn with_scheme(self, scheme: &str, handler: impl SchemeHandler + 'static) -> Server {
+        self.resources
+            .write()
+            .unwrap()
+            .register_scheme(scheme, Box::new(handler));
+        self
+    }
+
+    /// Registers a prompt at build time.
+    pub fn with_prompt(self, prompt: Prompt) -> Server {
+        self.prompts.write().unwrap().register(prompt);
+        self
+    }
+
+    /// Sets how many messages may wait to be written before producers are held back.
+    pub fn with_outgoing_capacity(mut self, capacity: usize) -> Server {
+        self.outgoing_capacity = capacity;
+        self
+    }
+
+    /// Limits checked on every incoming message.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Sets the size, depth and string length limits of incoming messages.
+    pub fn with_limits(mut self, limits: Limits) -> Server {
+        self.limits = limits;
+        self
+    }
+
+    /// Records every message received and sent by the sessions to a transcript.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Server {
+        self.recorder = Some(Arc::new(recorder));
+        self
+    }
+
+    /// Reports the panics of the handlers to `reporter`, with their backtrace, the request being
+    /// served and the last messages of its session, see [`crate::crash`]. Installs a panic hook
+    /// to capture the backtraces.
+    pub fn with_crash_reporter(mut self, reporter: impl CrashReporter + 'static) -> Server {
+        crash::capture_panics();
+        self.crash_reporters.push(Box::new(reporter));
+        self
+    }
+
+    /// Makes `value` available to the handlers of every session with
+    /// [`RequestContext::shared_state`], replacing the shared value of the same type.
+    pub fn with_shared_state<T: Send + Sync + 'static>(self, value: T) -> Server {
+        self.shared_state.insert(value);
+        self
+    }
+
+    /// Calls `factory` when a session connects, making its value available to the handlers of
+    /// that session alone with [`RequestContext::session_state`]. The value is dropped when the
+    /// session disconnects.
+    pub fn with_session_state<T, F>(mut self, factory: F) -> Server
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        self.session_state
+            .push(Box::new(move |state: &StateBag| state.insert(factory())));
+        self
+    }
+
+    /// Saves the sessions served with [`Server::serve_resumable`] to `store`, and resumes
+    /// those saved before the server started.
+    pub fn with_state_store(mut self, store: StateStore) -> Server {
+        self.state = Some(store);
+        self
+    }
+
+    /// True if a session saved under `key` before the server started can be resumed.
+    pub fn has_saved_session(&self, key: &str) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.has_session(key))
+    }
+
+    /// Registers a hook fired when a client sends `initialize`.
+    pub fn on_initialize<F>(mut self, hook: F) -> Server
+    where
+        F: Fn(Option<&Value>) + Send + Sync + 'static,
+    {
+        self.hooks.on_initialize = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers a hook fired when the client sends `notifications/initialized`.
+    pub fn on_initialized<F>(mut self, hook: F) -> Server
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.hooks.on_initialized = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers a hook fired once for every session, when the client disconnects or the
+    /// server is shut down with [`Server::shutdown`].
+    pub fn on_shutdown<F>(mut self, hook: F) -> Server
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.hooks.on_shutdown = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers the hook approving the calls of the tools whose permission is `ask`, in place
+    /// of the `approval` setting.
+    pub fn with_approval_hook<F>(mut self, hook: F) -> Server
+    where
+        F: Fn(&RequestContext, &str, &Value) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.approval = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers the hook approving the `sampling/createMessage` requests the handlers send
+    /// with [`RequestContext::create_message`], in place of the `sampling_approval` setting.
+    /// The `sampling_max_*` budgets still apply.
+    pub fn with_sampling_hook<F>(mut self, hook: F) -> Server
+    where
+        F: Fn(&RequestContext, &CreateMessageRequest) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.sampling_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Registers the vendor-specific methods of `extension`, announced under
+    /// `capabilities.experimental`. Extensions whose prefix is used by the spec are logged and
+    /// skipped.
+    pub fn with_extension(mut self, extension: Extension) -> Server {
+        let prefix = extension.prefix.clone();
+        if let Err(e) = self.extensions.register(extension) {
+            tracing::error!("Cannot register the extension {}: {}", prefix, e);
+        }
+        self
+    }
+
+    /// Registers an application error code, in the -32000..-32099 range, whose name is then
+    /// sent in the `data.error` of the errors with the code. Codes out of the range or already
+    /// used by another name are logged and skipped.
+    pub fn with_error_code(mut self, code: ErrorCode) -> Server {
+        if let Err(e) = self.errors.register(code) {
+            tracing::error!("Cannot register the error code {}: {}", code.name, e);
+        }
+        self
+    }
+
+    /// Registers the handler of the requests whose method no one else claims, which are
+    /// otherwise answered with METHOD_NOT_FOUND. The handler can answer METHOD_NOT_FOUND too.
+    pub fn with_fallback<F>(mut self, handler: F) -> Server
+    where
+        F: Fn(&RequestContext, &str, Option<&Value>) -> Result<Value, JsonRpcError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Current runtime settings.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// `serverInfo` sent to the clients.
+    pub fn info(&self) -> &Implementation {
+        &self.info
+    }
+
+    /// Capabilities announced in the `initialize` result, which depend on what is registered.
+    pub fn capabilities(&self) -> Value {
+        let config = self.config();
+        // Areas whose methods are all denied by the settings aren't announced
+        let allowed = |methods: &[&str]| {
+            methods
+                .iter()
+                .any(|method| methods::is_allowed(&config, method))
+        };
+        let mut capabilities = json!({});
+        if !self.tools.read().unwrap().is_empty() && allowed(&["tools/list", "tools/call"]) {
+            capabilities["tools"] = json!({ "listChanged": true });
+        }
+        // Spilled outputs are read as resources
+        if (!self.resources.read().unwrap().is_empty() || config.tool_output_spill)
+            && allowed(&["resources/list", "resources/read"])
+        {
+            capabilities["resources"] = json!({ "subscribe": true, "listChanged": true });
+        }
+        if !self.prompts.read().unwrap().is_empty() && allowed(&["prompts/list", "prompts/get"]) {
+            capabilities["prompts"] = json!({ "listChanged": true });
+        }
+        if allowed(&["logging/setLevel"]) {
+            capabilities["logging"] = json!({});
+        }
+        if !self.extensions.is_empty() {
+            capabilities["experimental"] = self.extensions.capabilities();
+        }
+        capabilities
+    }
+
+    /// Messages handled and errors answered by all the sessions since the server started.
+    pub fn summary(&self) -> Summary {
+        self.stats.summary()
+    }
+
+    /// Metrics of the requests served since the server started.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    #[cfg(feature = "otel")]
+    pub(crate) fn otel_spans(&self) -> &Spans {
+        &self.otel_spans
+    }
+
+    /// Applies new settings to the running server, notifying connected clients of what changed.
+    pub fn reload_config(&self, config: Config) {
+        let tools_before = self.tools.read().unwrap().available_names(&self.config());
+        let previous = std::mem::replace(&mut *self.config.write().unwrap(), Arc::new(config));
+        let tools_after = self.tools.read().unwrap().available_names(&self.config());
+        if tools_before != tools_after {
+            tracing::info!("Available tools changed");
+            self.notify_all("notifications/tools/list_changed", None);
+            self.save_state();
+        }
+        let config = self.config();
+        if previous.roots != config.roots
+            || previous.resource_dir != config.resource_dir
+            || previous.sqlite_databases != config.sqlite_databases
+        {
+            tracing::info!("Resource sources changed");
+            self.notify_all("notifications/resources/list_changed", None);
+        }
+    }
+
+    /// Registers a tool while the server is running and tells clients the tool list changed.
+    pub fn register_tool(&self, tool: Tool) {
+        self.tools.write().unwrap().register(tool);
+        self.notify_all("notifications/tools/list_changed", None);
+        self.save_state();
+    }
+
+    /// Removes a tool while the server is running and tells clients the tool list changed.
+    pub fn unregister_tool(&self, name: &str) {
+        if self.tools.write().unwrap().unregister(name).is_some() {
+            self.notify_all("notifications/tools/list_changed", None);
+            self.save_state();
+        }
+    }
+
+    /// Replaces the tools named `previous` by `tools` while the server is running, telling
+    /// clients the tool list changed once. Returns the names of the new tools.
+    pub fn replace_tools(&self, previous: &[String], tools: Vec<Tool>) -> Vec<String> {
+        let names: Vec<String> = tools.iter().map(|tool| tool.name.clone()).collect();
+        {
+            let mut registry = self.tools.write().unwrap();
+            for name in previous {
+                registry.unregister(name);
+            }
+            for tool in tools {
+                registry.register(tool);
+            }
+        }
+        if !previous.is_empty() || !names.is_empty() {
+            self.notify_all("notifications/tools/list_changed", None);
+            self.save_state();
+        }
+        names
+    }
+
+    /// Adds a resource provider while the server is running and tells clients the resource
+    /// list changed.
+    pub fn register_resources(&self, provider: impl ResourceProvider + 'static) {
+        self.resources.write().unwrap().register(Box::new(provider));
+        self.notify_all("notifications/resources/list_changed", None);
+    }
+
+    /// Adds a prompt while the server is running and tells clients the prompt list changed.
+    pub fn register_prompt(&self, prompt: Prompt) {
+        self.prompts.write().unwrap().register(prompt);
+        self.notify_all("notifications/prompts/list_changed", None);
+        self.save_state();
+    }
+
+    /// Removes a prompt while the server is running and tells clients the prompt list changed.
+    pub fn unregister_prompt(&self, name: &str) {
+        if self.prompts.write().unwrap().unregister(name).is_some() {
+            self.notify_all("notifications/prompts/list_changed", None);
+            self.save_state();
+        }
+    }
+
+    /// Sends a notification to every connected session.
+    pub fn notify_all(&self, method: &str, params: Option<Value>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|session| session.strong_count() > 0);
+        let notification = JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+        };
+        if self.config().validate_outgoing {
+            schema::check_notification(&notification);
+        }
+        for session in sessions.iter().filter_map(Weak::upgrade) {
+            session.outgoing.push(notification.clone());
+        }
+    }
+
+    /// Sends a `notifications/message` to the initialized sessions whose log level is at most
+    /// `level`, as long as they are within the `client_log_rate`. A session that missed events
+    /// because of the rate is told how many with the next event it gets.
+    pub fn log_to_clients(&self, level: LoggingLevel, logger: &str, data: Value) {
+        let config = self.config();
+        let message = |level: LoggingLevel, logger: &str, data: Value| {
+            let notification = JsonRpcNotification {
+                jsonrpc: "2.0".to_string(),
+                method: "notifications/message".to_string(),
+                params: Some(json!({ "level": level, "logger": logger, "data": data })),
+            };
+            if config.validate_outgoing {
+                schema::check_notification(&notification);
+            }
+            notification
+        };
+        let sessions = self.sessions.lock().unwrap();
+        for session in sessions.iter().filter_map(Weak::upgrade) {
+            let wanted = *session.log_level.lock().unwrap();
+            if wanted.is_none_or(|wanted| level < wanted)
+                || !session.info.lock().unwrap().initialized
+            {
+                continue;
+            }
+            let key = "notifications/message";
+            if !session.log_limiter.try_take(key, config.client_log_rate) {
+                session.dropped_logs.fetch_add(1, Ordering::SeqCst);
+                continue;
+            }
+            let dropped = session.dropped_logs.swap(0, Ordering::SeqCst);
+            if dropped > 0 {
+                let notice = format!("{} log messages dropped over the rate limit", dropped);
+                session
+                    .outgoing
+                    .push(message(LoggingLevel::Warning, "client_log", json!(notice)));
+            }
+            session.outgoing.push(message(level, logger, data.clone()));
+        }
+    }
+
+    /// Sends `notifications/resources/updated` to the sessions subscribed to `uri`.
+    pub fn notify_resource_updated(&self, uri: &str) {
+        let sessions = self.sessions.lock().unwrap();
+        for session in sessions.iter().filter_map(Weak::upgrade) {
+            if session.subscriptions.lock().unwrap().contains(uri) {
+                tracing::info!("Resource {} updated", uri);
+                let notification = JsonRpcNotification {
+                    jsonrpc: "2.0".to_string(),
+                    method: "notifications/resources/updated".to_string(),
+                    params: Some(json!({ "uri": uri })),
+                };
+                if self.config().validate_outgoing {
+                    schema::check_notification(&notification);
+                }
+                session.outgoing.push(notification);
+            }
+        }
+    }
+
+    /// Stops serving before the process exits, e.g. on SIGTERM: the requests in flight get
+    /// `shutdown_grace_secs` to finish before they are cancelled, then the sessions are closed
+    /// once their remaining messages are written and their shutdown hooks run. Resumable
+    /// sessions stay in the state file.
+    pub fn shutdown(&self) {
+        let sessions: Vec<Arc<Session>> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        let in_flight = || -> usize {
+            sessions
+                .iter()
+                .map(|session| session.in_flight.lock().unwrap().len())
+                .sum()
+        };
+        let grace = Duration::from_secs(self.config().shutdown_grace_secs);
+        let deadline = Instant::now() + grace;
+        let pending = in_flight();
+        if pending > 0 {
+            tracing::info!(
+                "Shutting down, waiting up to {:?} for {} requests in flight",
+                grace,
+                pending
+            );
+        }
+        while in_flight() > 0 && Instant::now() < deadline {
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+        for session in &sessions {
+            for (id, cancellation) in session.in_flight.lock().unwrap().iter() {
+                tracing::warn!("Cancelling request {} of session {}", id, session.id);
+                cancellation.cancel();
+            }
+            session.outgoing.close();
+        }
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        for session in &sessions {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if !session.outgoing.wait_written(left) {
+                tracing::warn!("Messages to session {} were left unwritten", session.id);
+            }
+            self.run_shutdown_hook(session);
+        }
+    }
+
+    /// Fires the shutdown hook for `session`, unless it already was.
+    fn run_shutdown_hook(&self, session: &Session) {
+        if session.shut_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(hook) = &self.hooks.on_shutdown {
+            hook();
+        }
+    }
+
+    /// Runs the server over stdin/stdout until stdin is closed, or fails once stdout can't be
+    /// written to.
+    pub fn run(&self) -> io::Result<()> {
+        let lines = BoundedLines::new(io::stdin().lock(), self.limits.max_size);
+        self.serve(lines, io::stdout())
+    }
+
+    /// Serves one client session: every item of `lines` is an incoming JSON-RPC message and
+    /// every outgoing message is written to `out` as one line. Returns once `lines` is exhausted,
+    /// or with the error once writing to `out` failed, at the next incoming message.
+    pub fn serve<I, W>(&self, lines: I, out: W) -> io::Result<()>
+    where
+        I: IntoIterator<Item = String>,
+        W: Write + Send + 'static,
+    {
+        self.serve_session(None, None, lines, out)
+    }
+
+    /// Same as [`Server::serve`] for a session saved under `key` while it runs, if the server
+    /// has a state store. A session saved under `key` before the server started is resumed.
+    pub fn serve_resumable<I, W>(&self, key: &str, lines: I, out: W) -> io::Result<()>
+    where
+        I: IntoIterator<Item = String>,
+        W: Write + Send + 'static,
+    {
+        self.serve_session(Some(key.to_string()), None, lines, out)
+    }
+
+    /// Same as [`Server::serve`] for a client authenticated with `scopes`, which only gets the
+    /// tools and resources they grant in the `scopes` setting. The session is resumable when
+    /// it has a `key`, as with [`Server::serve_resumable`].
+    pub fn serve_authenticated<I, W>(
+        &self,
+        key: Option<&str>,
+        scopes: Vec<String>,
+        lines: I,
+        out: W,
+    ) -> io::Result<()>
+    where
+        I: IntoIterator<Item = String>,
+        W: Write + Send + 'static,
+    {
+        self.serve_session(key.map(str::to_string), Some(scopes), lines, out)
+    }
+
+    fn serve_session<I, W>(
+        &self,
+        key: Option<String>,
+        scopes: Option<Vec<String>>,
+        lines: I,
+        out: W,
+    ) -> io::Result<()>
+    where
+        I: IntoIterator<Item = String>,
+        W: Write + Send + 'static,
+    {
+        let outgoing = Arc::new(OutgoingQueue::new(self.outgoing_capacity));
+        let session = Arc::new(Session {
+            id: key.clone().unwrap_or_else(context::random_id),
+            key,
+            info: Mutex::new(SessionInfo {
+                scopes,
+                ..SessionInfo::default()
+            }),
+            in_flight: Mutex::default(),
+            responses: ResponseCache::default(),
+            requests: Arc::new(OutgoingRequests::new(outgoing.clone())),
+            outgoing,
+            subscriptions: Mutex::default(),
+            initialize_received: AtomicBool::new(false),
+            shut_down: AtomicBool::new(false),
+            rate_limiter: RateLimiter::default(),
+            log_level: Mutex::new(self.config().client_log_level),
+            log_limiter: RateLimiter::default(),
+            dropped_logs: AtomicUsize::new(0),
+            recent: (!self.crash_reporters.is_empty()).then(Arc::default),
+            state: Arc::new(self.new_session_state()),
+            stats: Stats::new(),
+        });
+        let recording = self
+            .recorder
+            .clone()
+            .map(|recorder| (recorder.start_session(), recorder));
+        let config = self.config();
+        let wire_log = WireLog::from_config(&config).map(Arc::new);
+        let batch = config.notification_batch;
+        let writer = match (recording.clone(), wire_log.clone(), session.recent.clone()) {
+            (None, None, None) => spawn_writer(session.outgoing.clone(), out, batch),
+            (recording, wire_log, recent) => {
+                let tap = move |line: &str| {
+                    if let Some((id, recorder)) = &recording {
+                        recorder.record(*id, Direction::Out, line);
+                    }
+                    if let Some(wire_log) = &wire_log {
+                        wire_log.log(Direction::Out, line);
+                    }
+                    if let Some(recent) = &recent {
+                        recent.push(Direction::Out, line);
+                    }
+                };
+                spawn_writer(session.outgoing.clone(), TapWriter::new(out, tap), batch)
+            }
+        };
+        self.sessions.lock().unwrap().push(Arc::downgrade(&session));
+        let saved = session
+            .key
+            .as_deref()
+            .zip(self.state.as_ref())
+            .and_then(|(key, state)| state.take_session(key));
+        if let Some(saved) = saved {
+            self.resume(&session, saved);
+        }
+
+        // Two lanes: the control messages are handled as soon as they are read, while the
+        // other messages are queued for a worker that handles them in order
+        thread::scope(|scope| {
+            let (queue, queued) = mpsc::channel::<Work>();
+            let worker = scope.spawn(|| {
+                for work in queued {
+                    if session.outgoing.is_closed() {
+                        break;
+                    }
+                    match work {
+                        Work::Request(request, cancellation, trace_id) => {
+                            self.handle_request(&session, request, cancellation, trace_id)
+                        }
+                        Work::Notification(notification) => {
+                            self.handle_notification(&session, &notification)
+                        }
+                    }
+                }
+            });
+            for input in lines {
+                if session.outgoing.is_closed() {
+                    break;
+                }
+                if let Some((id, recorder)) = &recording {
+                    recorder.record(*id, Direction::In, &input);
+                }
+                if let Some(wire_log) = &wire_log {
+                    wire_log.log(Direction::In, &input);
+                }
+                if let Some(recent) = &session.recent {
+                    recent.push(Direction::In, &input);
+                }
+                tracing::info!("Received message ({} bytes)", input.len());
+                self.count_message(&session);
+                let work = match codec::decode(&input, &self.limits) {
+                    Ok(Message::Request(request)) => match self.admit(&session, &request) {
+                        Some((cancellation, trace_id)) if is_control(&request.method) => {
+                            self.handle_request(&session, request, cancellation, trace_id);
+                            continue;
+                        }
+                        Some((cancellation, trace_id)) => {
+                            Work::Request(request, cancellation, trace_id)
+                        }
+                        None => continue,
+                    },
+                    Ok(Message::Notification(notification)) if is_control(&notification.method) => {
+                        self.handle_notification(&session, &notification);
+                        continue;
+                    }
+                    Ok(Message::Notification(notification)) => Work::Notification(notification),
+                    Ok(Message::Response(id, response)) => {
+                        if !session.requests.complete(&id, response) {
+                            tracing::warn!("Ignoring response to unknown request {}", id);
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::error!("Error parsing request: {}", e);
+                        self.count_error(&session);
+                        let Some(id) = e.response_id(&input) else {
+                            tracing::debug!("Not answering a malformed notification");
+                            continue;
+                        };
+                        let error = self.errors.diagnose(e.to_error(), None);
+                        session.outgoing.push(JsonRpcResponse::error(id, error));
+                        continue;
+                    }
+                };
+                if queue.send(work).is_err() {
+                    break;
+                }
+            }
+            // Handlers waiting on the client fail instead of waiting for their timeout
+            session.requests.close();
+            drop(queue);
+            let _ = worker.join();
+        });
+
+        tracing::info!("Client disconnected: {}", session.stats.summary());
+        self.end_session(&session);
+        self.run_shutdown_hook(&session);
+        session.outgoing.close();
+        writer.join().unwrap_or(Ok(()))
+    }
+
+    /// Subscribes `session` to `uri`, or unsubscribes it. The resource providers are told
+    /// when the first session subscribes and when the last one unsubscribes.
+    fn set_subscribed(&self, session: &Session, uri: &str, subscribed: bool) {
+        // Held while the providers are told, so that they see the changes in order
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let changed = match subscribed {
+            true => session
+                .subscriptions
+                .lock()
+                .unwrap()
+                .insert(uri.to_string()),
+            false => session.subscriptions.lock().unwrap().remove(uri),
+        };
+        if !changed {
+            return;
+        }
+        let count = subscribers.entry(uri.to_string()).or_default();
+        let first_or_last = match subscribed {
+            true => {
+                *count += 1;
+                *count == 1
+            }
+            false => {
+                *count -= 1;
+                *count == 0
+            }
+        };
+        if *count == 0 {
+            subscribers.remove(uri);
+        }
+        if first_or_last {
+            self.resources
+                .read()
+                .unwrap()
+                .set_subscribed(uri, subscribed);
+        }
+    }
+
+    /// Drops the subscriptions of a disconnected session and forgets it.
+    fn end_session(&self, session: &Session) {
+        let uris: Vec<String> = session
+            .subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        for uri in uris {
+            tracing::info!("Dropping subscription to {}", uri);
+            self.set_subscribed(session, &uri, false);
+        }
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|other| other.strong_count() > 0 && !std::ptr::eq(other.as_ptr(), session));
+        self.save_state();
+    }
+
+    /// Restores a session saved before the server started, and tells its client about what
+    /// may have changed meanwhile: the lists that differ from those it was offered, and the
+    /// resources it subscribed to.
+    fn resume(&self, session: &Session, saved: SavedSession) {
+        tracing::info!("Resuming saved session");
+        {
+            let mut info = session.info.lock().unwrap();
+            info.version = negotiate(saved.protocol_version.as_deref());
+            info.protocol_version = saved.protocol_version;
+            info.client_info = saved.client_info;
+            info.client_capabilities = saved.client_capabilities;
+            info.initialized = saved.initialized;
+        }
+        session.initialize_received.store(true, Ordering::SeqCst);
+        let mut notifications = vec![("notifications/resources/list_changed", None)];
+        if saved.tools != self.tool_names() {
+            notifications.push(("notifications/tools/list_changed", None));
+        }
+        if saved.prompts != self.prompt_names() {
+            notifications.push(("notifications/prompts/list_changed", None));
+        }
+        for uri in &saved.subscriptions {
+            self.set_subscribed(session, uri, true);
+            notifications.push((
+                "notifications/resources/updated",
+                Some(json!({ "uri": uri })),
+            ));
+        }
+        let validate = self.config().validate_outgoing;
+        for (method, params) in notifications {
+            let notification = JsonRpcNotification {
+                jsonrpc: "2.0".to_string(),
+                method: method.to_string(),
+                params,
+            };
+            if validate {
+                schema::check_notification(&notification);
+            }
+            session.outgoing.push(notification);
+        }
+    }
+
+    /// Saves the resumable sessions, if the server has a state store.
+    fn save_state(&self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let tools = self.tool_names();
+        let prompts = self.prompt_names();
+        let sessions = self.sessions.lock().unwrap();
+        let saved = sessions
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter_map(|session| {
+                let key = session.key.clone()?;
+                let info = session.info.lock().unwrap().clone();
+                let saved = SavedSession {
+                    protocol_version: info.protocol_version,
+                    client_info: info.client_info,
+                    client_capabilities: info.client_capabilities,
+                    initialized: info.initialized,
+                    subscriptions: session
+                        .subscriptions
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    tools: tools.clone(),
+                    prompts: prompts.clone(),
+                    saved_at: 0,
+                };
+                // Sessions that haven't sent initialize have nothing to resume
+                session
+                    .initialize_received
+                    .load(Ordering::SeqCst)
+                    .then_some((key, saved))
+            })
+            .collect();
+        drop(sessions);
+        state.save(saved);
+    }
+
+    /// Names of the tools offered under the current settings.
+    fn tool_names(&self) -> BTreeSet<String> {
+        let config = self.config();
+        self.tools
+            .read()
+            .unwrap()
+            .available_names(&config)
+            .into_iter()
+            .collect()
+    }
+
+    fn prompt_names(&self) -> BTreeSet<String> {
+        let prompts = self.prompts.read().unwrap();
+        prompts.iter().map(|prompt| prompt.name.clone()).collect()
+    }
+
+    /// Builds the context handed to the handler of `request`.
+    fn new_session_state(&self) -> StateBag {
+        let state = StateBag::new();
+        for factory in &self.session_state {
+            factory(&state);
+        }
+        state
+    }
+
+    fn request_context(
+        &self,
+        session: &Session,
+        request: &JsonRpcRequest,
+        trace_id: String,
+        cancellation: CancellationToken,
+    ) -> RequestContext {
+        let progress_token = request
+            .params
+            .as_ref()
+            .and_then(|p| p.get("_meta"))
+            .and_then(|m| m.get("progressToken"))
+            .cloned();
+        let outgoing = session.outgoing.clone();
+        let validate = self.config().validate_outgoing;
+        let notifier: NotificationSender = Arc::new(move |n: JsonRpcNotification| {
+            if validate {
+                schema::check_notification(&n);
+            }
+            outgoing.push(n)
+        });
+        let mut ctx = RequestContext::new(
+            request.id.clone(),
+            session.info.lock().unwrap().clone(),
+            progress_token,
+            trace_id,
+            cancellation,
+            self.config(),
+            notifier,
+        )
+        .with_requests(session.requests.clone())
+        .with_resource_reader(self.resource_reader())
+        .with_session_state(session.state.clone())
+        .with_shared_state(self.shared_state.clone())
+        .with_sampling_hook(self.sampling_hook.clone());
+        if request.method == "tools/call" {
+            let name = request.params.as_ref().and_then(|p| p["name"].as_str());
+            let timeout = name.and_then(|name| {
+                let tools = self.tools.read().unwrap();
+                tools.get(name).and_then(|tool| tool.timeout(&ctx.config))
+            });
+            ctx.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        }
+        ctx
+    }
+
+    /// Reads the spilled tool outputs, then the resources of the providers.
+    fn resource_reader(&self) -> ResourceReader {
+        let (spilled, resources) = (self.spilled.clone(), self.resources.clone());
+        Arc::new(move |ctx, uri| {
+            spilled
+                .read(uri)
+                .or_else(|| resources.read().unwrap().read(ctx, uri))
+        })
+    }
+
+    /// Span of the log messages about `request`.
+    fn request_span(method: &str, id: &JsonRpcId, trace_id: &str) -> Span {
+        tracing::info_span!("request", method = %method, id = %id, trace_id = %trace_id)
+    }
+
+    /// Checks the rate limits of a request as soon as it is read and registers it as in flight,
+    /// so that it can be cancelled while queued, along with its trace id. None if it was
+    /// answered with an error.
+    fn admit(
+        &self,
+        session: &Session,
+        request: &RawRequest,
+    ) -> Option<(CancellationToken, String)> {
+        let trace_id = context::trace_id_of_meta(request.meta().as_ref());
+        let _enter = Server::request_span(&request.method, &request.id, &trace_id).entered();
+        let config = self.config();
+        // Only the clients of resumable sessions (HTTP) retry requests whose response got lost
+        if session.key.is_some() && config.idempotency_ttl_secs > 0 {
+            let fingerprint = idempotency::fingerprint(&request.method, request.params.as_deref());
+            if let Some(response) = session.responses.replay(&config, &request.id, &fingerprint) {
+                tracing::info!("Request sent again, replaying its response");
+                session.outgoing.push(response);
+                return None;
+            }
+        }
+        let admitted = session
+            .rate_limiter
+            .check(&config.rate_limits, &request.method)
+            .and_then(|_| track_in_flight(session, &request.id));
+        match admitted {
+            Ok(cancellation) => Some((cancellation, trace_id)),
+            Err(err) => {
+                session.outgoing.push(JsonRpcResponse::error(
+                    request.id.clone(),
+                    self.errors.diagnose(err, Some(&request.method)),
+                ));
+                self.count_error(session);
+                None
+            }
+        }
+    }
+
+    fn handle_request(
+        &self,
+        session: &Session,
+        request: RawRequest,
+        cancellation: CancellationToken,
+        trace_id: String,
+    ) {
+        let span = Server::request_span(&request.method, &request.id, &trace_id);
+        let _enter = span.enter();
+        if cancellation.is_cancelled() {
+            tracing::info!("Request was cancelled while queued, dropping it");
+            session.in_flight.lock().unwrap().remove(&request.id);
+            return;
+        }
+        let fingerprint = idempotency::fingerprint(&request.method, request.params.as_deref());
+        let request = match request.parse() {
+            Ok(request) => request,
+            Err((id, e)) => {
+                session.in_flight.lock().unwrap().remove(&id);
+                session
+                    .outgoing
+                    .push(JsonRpcResponse::error(id, e.to_error()));
+                self.count_error(session);
+                return;
+            }
+        };
+        let request = &request;
+        tracing::info!("Handling request");
+        let ctx = self.request_context(session, request, trace_id, cancellation);
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        #[cfg(feature = "otel")]
+        let started = SystemTime::now();
+        let watch = self.watch_slow_request(session, request, &ctx, &span);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.dispatch_request(session, request, &ctx)
+        }))
+        .unwrap_or_else(|payload| {
+            tracing::error!("Handler panicked: {}", panic_message(&*payload));
+            self.report_crash(
+                session,
+                &request.method,
+                Some(&ctx),
+                panic_message(&*payload),
+            );
+            Err(JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                format!("Internal error while serving {}", request.method),
+            ))
+        });
+        drop(watch);
+        #[cfg(feature = "metrics")]
+        {
+            let method = match &result {
+                Err(e) if e.code == ERROR_CODE_METHOD_NOT_FOUND => OTHER_METHOD,
+                _ => &request.method,
+            };
+            self.metrics
+                .record(method, start.elapsed(), result.is_err());
+            #[cfg(feature = "otel")]
+            if ctx.config.otel_endpoint.is_some() {
+                self.otel_spans.push(RequestSpan {
+                    trace_id: ctx.trace_id.clone(),
+                    session: session.id.clone(),
+                    method: method.to_string(),
+                    id: ctx.id.to_string(),
+                    start: started,
+                    end: SystemTime::now(),
+                    error: result.as_ref().err().map(|e| (e.code, e.message.clone())),
+                });
+            }
+        }
+        if ctx.cancellation.is_cancelled() {
+            session.in_flight.lock().unwrap().remove(&ctx.id);
+            tracing::info!("Request {} was cancelled, dropping response", ctx.id);
+            return;
+        }
+        // The schema is that of 2024-11-05, which later results may legitimately go beyond
+        let checked = ctx.session.version == ProtocolVersion::V2024_11_05;
+        if let (Ok(result), true) = (&result, ctx.config.validate_outgoing && checked) {
+            schema::check_result(&request.method, result);
+        }
+        let response = match result {
+            Ok(result) => JsonRpcResponse::success(ctx.id.clone(), result),
+            Err(err) => {
+                self.count_error(session);
+                JsonRpcResponse::error(
+                    ctx.id.clone(),
+                    self.errors.diagnose(err, Some(&request.method)),
+                )
+            }
+        };
+        // Kept before the request leaves the requests in flight, so that the same request sent
+        // again meanwhile is refused rather than served a second time
+        if session.key.is_some() {
+            session.responses.store(&ctx.config, fingerprint, &response);
+        }
+        session.in_flight.lock().unwrap().remove(&ctx.id);
+        session.outgoing.push(response);
+    }
+
+    /// Counts an incoming message in the stats of `session` and of the server.
+    fn count_message(&self, session: &Session) {
+        for stats in [&session.stats, &self.stats] {
+            stats.messages.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts an error response in the stats of `session` and of the server.
+    fn count_error(&self, session: &Session) {
+        for stats in [&session.stats, &self.stats] {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Warns (in the log and, if configured, to the client) every `slow_request_secs` while
+    /// `request` is being served, until the returned sender is dropped.
+    fn watch_slow_request(
+        &self,
+        session: &Session,
+        request: &JsonRpcRequest,
+        ctx: &RequestContext,
+        span: &Span,
+    ) -> Option<Sender<()>> {
+        if ctx.config.slow_request_secs == 0 {
+            return None;
+        }
+        let threshold = Duration::from_secs(ctx.config.slow_request_secs);
+        let notify = ctx.config.slow_request_notify;
+        let validate = ctx.config.validate_outgoing;
+        let (done, finished) = mpsc::channel::<()>();
+        let outgoing = session.outgoing.clone();
+        let (method, id, trace_id) = (request.method.clone(), ctx.id.clone(), ctx.trace_id.clone());
+        let span = span.clone();
+        let start = Instant::now();
+        thread::spawn(move || {
+            let _enter = span.enter();
+            while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(threshold) {
+                let elapsed = start.elapsed().as_secs();
+                tracing::warn!(
+                    "Request {} ({}) still running after {} s",
+                    id,
+                    method,
+                    elapsed
+                );
+                if notify {
+                    let notification = JsonRpcNotification {
+                        jsonrpc: "2.0".to_string(),
+                        method: "notifications/message".to_string(),
+                        params: Some(json!({
+                            "level": "warning",
+                            "logger": "slow_request",
+                            "data": {
+                                "message": format!("{} still running after {} s", method, elapsed),
+                                "method": method,
+                                "requestId": id,
+                                "elapsedSecs": elapsed,
+                            },
+                            "_meta": { TRACE_ID_META: trace_id },
+                        })),
+                    };
+                    if validate {
+                        schema::check_notification(&notification);
+                    }
+                    outgoing.push(notification);
+                }
+            }
+        });
+        Some(done)
+    }
+
+    fn dispatch_request(
+        &self,
+        session: &Session,
+        request: &JsonRpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<Value, JsonRpcError> {
+        if !methods::is_allowed(&ctx.config, &request.method) {
+            tracing::error!("Request method {} is not allowed", request.method);
+            return Err(method_not_found(&request.method));
+        }
+        let params = match named_params(&request.method, request.params.as_ref()) {
+            // Extensions and the fallback handler get the params as they were sent
+            Err(_) if !methods::is_request(&request.method) => None,
+            params => params?,
+        };
+        let params = params.as_deref();
+        if request.method == "initialize" {
+            // Before the lifecycle check, so that a malformed initialize can be sent again
+            for (path, expected) in INITIALIZE_PARAMS {
+                required_param(params, path, expected)?;
+            }
+        }
+        check_lifecycle(session, &request.method)?;
+        match request.method.as_str() {
+            "initialize" => {
+                tracing::info!("Initializing server...");
+                if let Some(params) = params {
+                    let mut info = session.info.lock().unwrap();
+                    info.protocol_version = params
+                        .get("protocolVersion")
+                        .and_then(Value::as_str)
+                        .map(str::to_string);
+                    info.version = negotiate(info.protocol_version.as_deref());
+                    info.client_info = params.get("clientInfo").cloned();
+                    info.client_capabilities = params.get("capabilities").cloned();
+                }
+                if let Some(hook) = &self.hooks.on_initialize {
+                    hook(params);
+                }
+                let version = session.info.lock().unwrap().version;
+                let mut result = Value::Object(Default::default());
+                result["protocolVersion"] = Value::String(version.to_string());
+                result["capabilities"] = self.capabilities();
+                result["serverInfo"] = serde_json::to_value(&self.info).unwrap_or_default();
+                self.save_state();
+                Ok(result)
+            }
+            "ping" => {
+                tracing::info!("Client ping server...");
+                Ok(Value::Object(Default::default()))
+            }
+            "resources/list" => {
+                let resources = self.resources.read().unwrap().list(ctx);
+                self.list_page(&request.method, params, ctx, "resources", resources)
+            }
+            "resources/templates/list" => {
+                let templates = self.resources.read().unwrap().templates(ctx);
+                self.list_page(&request.method, params, ctx, "resourceTemplates", templates)
+            }
+            "completion/complete" => self.complete(params, ctx),
+            "resources/read" => self.read_resource(params, ctx),
+            "prompts/list" => {
+                let prompts = self.prompts.read().unwrap();
+                let definitions: Vec<Value> = prompts
+                    .iter()
+                    .filter(|prompt| {
+                        policy::prompt_in_scopes(
+                            &ctx.config,
+                            ctx.session.scopes.as_deref(),
+                            &prompt.name,
+                        )
+                    })
+                    .map(|prompt| prompt.definition(ctx.session.version))
+                    .collect();
+                self.list_page(&request.method, params, ctx, "prompts", definitions)
+            }
+            "prompts/get" => {
+                let name = required_str(params, "/name")?;
+                let no_arguments = json!({});
+                let arguments = optional_param(params, "/arguments", ParamType::Object)?
+                    .unwrap_or(&no_arguments);
+                let prompts = self.prompts.read().unwrap();
+                let granted =
+                    policy::prompt_in_scopes(&ctx.config, ctx.session.scopes.as_deref(), name);
+                match prompts.get(name).filter(|_| granted) {
+                    Some(prompt) => {
+                        tracing::info!("Getting prompt {}", name);
+                        prompt
+                            .get(ctx, arguments)
+                            .map(|result| result.for_version(ctx.session.version).to_value())
+                    }
+                    None => {
+                        tracing::error!("Unknown prompt: {}", name);
+                        Err(JsonRpcError::new(
+                            ERROR_CODE_INVALID_PARAMS,
+                            format!("Unknown prompt: '{}'", name),
+                        )
+                        .with_data("pointer", "/name"))
+                    }
+                }
+            }
+            "resources/subscribe" | "resources/unsubscribe" => {
+                let uri = required_str(params, "/uri")?;
+                if !policy::resource_in_scopes(&ctx.config, ctx.session.scopes.as_deref(), uri) {
+                    tracing::error!("Unknown resource: {}", uri);
+                    return Err(JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("Unknown resource: '{}'", uri),
+                    )
+                    .with_data("pointer", "/uri"));
+                }
+                self.set_subscribed(session, uri, request.method == "resources/subscribe");
+                self.save_state();
+                Ok(json!({}))
+            }
+            "tools/list" => {
+                let tools = self.tools.read().unwrap();
+                let definitions: Vec<Value> = tools
+                    .iter()
+                    .filter(|tool| tool.is_available(&ctx.config))
+                    .filter(|tool| {
+                        policy::tool_in_scopes(
+                            &ctx.config,
+                            ctx.session.scopes.as_deref(),
+                            &tool.name,
+                        )
+                    })
+                    .map(|tool| tool.definition(ctx.session.version))
+                    .collect();
+                self.list_page(&request.method, params, ctx, "tools", definitions)
+            }
+            "tools/call" => self.call_tool(session, params, ctx),
+            "logging/setLevel" => {
+                let level = required_str(params, "/level")?;
+                let level: LoggingLevel = serde_json::from_value(json!(level)).map_err(|_| {
+                    JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("Invalid params: unknown log level '{}'", level),
+                    )
+                })?;
+                tracing::info!("Client set its log level to {:?}", level);
+                *session.log_level.lock().unwrap() = Some(level);
+                Ok(json!({}))
+            }
+            "audit/query" if ctx.config.audit_query => self.query_audit(params, ctx),
+            #[cfg(feature = "metrics")]
+            "metrics/dump" if ctx.config.metrics => Ok(self.metrics.to_json()),
+            "server/methods" if ctx.config.introspection => {
+                Ok(methods::list(&ctx.config, &self.extensions, &self.errors))
+            }
+            method => {
+                let params = request.params.as_ref();
+                if let Some(result) = self.extensions.call(ctx, method, params) {
+                    return result;
+                }
+                if let Some(fallback) = &self.fallback {
+                    tracing::info!("Handing {} to the fallback handler", method);
+                    return fallback(ctx, method, params);
+                }
+                tracing::error!("Unknown request method: {}", method);
+                Err(method_not_found(method))
+            }
+        }
+    }
+
+    /// Serves `tools/call`, recording the call in the audit log.
+    fn call_tool(
+        &self,
+        session: &Session,
+        params: Option<&Value>,
+        ctx: &RequestContext,
+    ) -> Result<Value, JsonRpcError> {
+        let name = required_str(params, "/name")?;
+        // Borrowed, the arguments of a call being as large as its params
+        let no_arguments = json!({});
+        let arguments =
+            optional_param(params, "/arguments", ParamType::Object)?.unwrap_or(&no_arguments);
+        let time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let start = Instant::now();
+        let (result, outcome) = self.run_tool(name, arguments, ctx);
+        let Some(store) = self.audit.store(&ctx.config) else {
+            return result;
+        };
+        let error = match &result {
+            Err(e) => Some(e.message.clone()),
+            Ok(result) if outcome != Outcome::Ok => {
+                result["content"][0]["text"].as_str().map(str::to_string)
+            }
+            Ok(_) => None,
+        };
+        let record = AuditRecord {
+            time,
+            session: session.id.clone(),
+            client: ctx
+                .session
+                .client_info
+                .as_ref()
+                .and_then(|info| info.get("name").and_then(Value::as_str).map(str::to_string)),
+            tool: name.to_string(),
+            arguments_sha256: audit::hash_arguments(arguments),
+            arguments: ctx
+                .config
+                .audit_arguments
+                .then(|| WireLog::redacting(&ctx.config).redact(arguments.clone())),
+            duration_ms: start.elapsed().as_millis() as u64,
+            outcome,
+            error,
+        };
+        if let Err(e) = store.record(&record) {
+            tracing::error!("Cannot record the call of {} in the audit log: {}", name, e);
+        }
+        result
+    }
+
+    /// Runs the tool `name` once the permissions allow it, telling how the call ended.
+    fn run_tool(
+        &self,
+        name: &str,
+        arguments: &Value,
+        ctx: &RequestContext,
+    ) -> (Result<Value, JsonRpcError>, Outcome) {
+        let tools = self.tools.read().unwrap();
+        if let Some(tool) = tools.get(name) {
+            if !tool.is_available(&ctx.config) {
+                tracing::error!("Tool {} isn't allowed", name);
+                let err = errors::TOOL_NOT_ALLOWED
+                    .error(format!("The configuration doesn't allow calling {}", name))
+                    .with_data("pointer", "/name");
+                return (Err(err), Outcome::Failed);
+            }
+            if !policy::tool_in_scopes(&ctx.config, ctx.session.scopes.as_deref(), name) {
+                tracing::error!("Tool {} isn't granted to the session's scopes", name);
+                let err = errors::TOOL_NOT_ALLOWED
+                    .error(format!("The session's scopes don't allow calling {}", name))
+                    .with_data("pointer", "/name");
+                return (Err(err), Outcome::Failed);
+            }
+        }
+        let Some(tool) = tools.get(name) else {
+            tracing::error!("Unknown tool: {}", name);
+            let err = JsonRpcError::new(
+                ERROR_CODE_INVALID_PARAMS,
+                format!("Unknown tool: '{}'", name),
+            )
+            .with_data("pointer", "/name");
+            return (Err(err), Outcome::Failed);
+        };
+        if let Err(reason) = policy::check(self.approval.as_ref(), ctx, name, arguments) {
+            tracing::warn!("Call of {} not approved: {}", name, reason);
+            let result =
+                CallToolResult::error(format!("The call of {} was not approved: {}", name, reason));
+            let result = result.for_version(ctx.session.version).to_value();
+            return (Ok(result), Outcome::Denied);
+        }
+        tracing::info!("Calling tool {}", name);
+        let result = self.tool_calls.run(name, ctx, || tool.call(ctx, arguments));
+        if ctx
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            let timeout = tool.timeout(&ctx.config).unwrap_or_default();
+            tracing::warn!("Call of {} timed out after {:?}", name, timeout);
+            let result = CallToolResult::error(format!(
+                "The call of {} timed out after {:?}",
+                name, timeout
+            ));
+            return (
+                Ok(result.for_version(ctx.session.version).to_value()),
+                Outcome::Error,
+            );
+        }
+        match result {
+            Ok(result) => {
+                let spilled = ctx.config.tool_output_spill.then_some(&*self.spilled);
+                let result = spill::limit_output(result, name, ctx.config.tool_max_output, spilled);
+                let outcome = match result.is_error {
+                    true => Outcome::Error,
+                    false => Outcome::Ok,
+                };
+                (
+                    Ok(result.for_version(ctx.session.version).to_value()),
+                    outcome,
+                )
+            }
+            Err(e) => (Err(e), Outcome::Failed),
+        }
+    }
+
+    /// The page of the `items` listed by `method` the `cursor` param points to, as the `field`
+    /// of the result, with the `nextCursor` of the rest, see [`crate::pagination`].
+    fn list_page<T: Serialize>(
+        &self,
+        method: &str,
+        params: Option<&Value>,
+        ctx: &RequestContext,
+        field: &str,
+        items: Vec<T>,
+    ) -> Result<Value, JsonRpcError> {
+        let cursor = optional_param(params, "/cursor", ParamType::String)?.and_then(Value::as_str);
+        let page = self.pagination.page(&ctx.config, method, cursor, items)?;
+        let mut result = json!({ field: page.items });
+        if let Some(next_cursor) = page.next_cursor {
+            result["nextCursor"] = Value::String(next_cursor);
+        }
+        Ok(result)
+    }
+
+    /// Serves `resources/read`: the whole resource, the range of the `offset` and `length`
+    /// params, or with `resource_page_size` set, the page `cursor` points to of a resource larger
+    /// than that, with the `nextCursor` of the rest.
+    fn read_resource(
+        &self,
+        params: Option<&Value>,
+        ctx: &RequestContext,
+    ) -> Result<Value, JsonRpcError> {
+        let uri = required_str(params, "/uri")?;
+        let offset = optional_param(params, "/offset", ParamType::Integer)?.and_then(Value::as_i64);
+        let length = optional_param(params, "/length", ParamType::Integer)?
+            .map(|length| {
+                length.as_u64().ok_or_else(|| {
+                    JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        "Invalid params: 'length' must not be negative",
+                    )
+                    .with_data("pointer", "/length")
+                })
+            })
+            .transpose()?;
+        let cursor = optional_param(params, "/cursor", ParamType::String)?.and_then(Value::as_str);
+        if cursor.is_some() && (offset.is_some() || length.is_some()) {
+            return Err(JsonRpcError::new(
+                ERROR_CODE_INVALID_PARAMS,
+                "Invalid params: 'cursor' can't be given with 'offset' or 'length'",
+            )
+            .with_data("pointer", "/cursor"));
+        }
+        // Cursors of a resource aren't accepted for another one
+        let method = format!("resources/read {}", uri);
+        let position = cursor
+            .map(|cursor| self.pagination.position(&ctx.config, &method, cursor))
+            .transpose()?;
+        let Some(result) = ctx.read_resource(uri) else {
+            tracing::error!("Unknown resource: {}", uri);
+            return Err(JsonRpcError::new(
+                ERROR_CODE_INVALID_PARAMS,
+                format!("Unknown resource: '{}'", uri),
+            )
+            .with_data("pointer", "/uri"));
+        };
+        let result = result?;
+        let page_size = ctx.config.resource_page_size;
+        let result = if offset.is_some() || length.is_some() {
+            result.range(offset.unwrap_or(0), length)
+        } else if position.is_some()
+            || (page_size > 0 && result.contents.iter().any(|c| c.size() > page_size))
+        {
+            let page_size = (page_size > 0).then_some(page_size as u64);
+            let mut page = result.range(position.unwrap_or(0) as i64, page_size);
+            page.next_cursor = page
+                .next_offset()
+                .map(|next| self.pagination.cursor(&ctx.config, &method, next));
+            page
+        } else {
+            result
+        };
+        Ok(result.for_version(ctx.session.version).to_value())
+    }
+
+    /// Serves `audit/query`: the records of the audit log matching the params.
+    fn query_audit(
+        &self,
+        params: Option<&Value>,
+        ctx: &RequestContext,
+    ) -> Result<Value, JsonRpcError> {
+        let Some(store) = self.audit.store(&ctx.config) else {
+            return Err(JsonRpcError::new(
+                ERROR_CODE_INVALID_REQUEST,
+                "No audit log is kept",
+            ));
+        };
+        let query: AuditQuery = match params {
+            Some(params) => serde_json::from_value(params.clone()).map_err(|e| {
+                JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, format!("Invalid query: {}", e))
+            })?,
+            None => AuditQuery::default(),
+        };
+        let records = store
+            .query(&query)
+            .map_err(|e| JsonRpcError::new(ERROR_CODE_INTERNAL_ERROR, e))?;
+        Ok(json!({ "records": records }))
+    }
+
+    /// Serves `completion/complete`: template variables are completed by the resource
+    /// providers, while prompt arguments have no completions.
+    fn complete(
+        &self,
+        params: Option<&Value>,
+        ctx: &RequestContext,
+    ) -> Result<Value, JsonRpcError> {
+        let argument = required_str(params, "/argument/name")?;
+        let value = required_str(params, "/argument/value")?;
+        let mut values = match required_str(params, "/ref/type")? {
+            "ref/resource" => {
+                let uri = required_str(params, "/ref/uri")?;
+                let values = self
+                    .resources
+                    .read()
+                    .unwrap()
+                    .complete(ctx, uri, argument, value);
+                values.ok_or_else(|| {
+                    JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("Unknown resource template: '{}'", uri),
+                    )
+                })?
+            }
+            "ref/prompt" => {
+                let name = required_str(params, "/ref/name")?;
+                let granted =
+                    policy::prompt_in_scopes(&ctx.config, ctx.session.scopes.as_deref(), name);
+                if !granted || self.prompts.read().unwrap().get(name).is_none() {
+                    return Err(JsonRpcError::new(
+                        ERROR_CODE_INVALID_PARAMS,
+                        format!("Unknown prompt: '{}'", name),
+                    ));
+                }
+                Vec::new()
+            }
+            other => {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    format!("Unknown reference type: '{}'", other),
+                ))
+            }
+        };
+        let total = values.len();
+        values.truncate(MAX_COMPLETION_VALUES);
+        Ok(json!({
+            "completion": { "values": values, "total": total, "hasMore": total > values.len() },
+        }))
+    }
+
+    fn handle_notification(&self, session: &Session, notification: &JsonRpcNotification) {
+        let span = tracing::info_span!("notification", method = %notification.method);
+        let _enter = span.enter();
+        tracing::info!("Handling notification");
+        let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.dispatch_notification(session, notification)
+        }));
+        if let Err(payload) = handled {
+            tracing::error!("Handler panicked: {}", panic_message(&*payload));
+            self.report_crash(
+                session,
+                &notification.method,
+                None,
+                panic_message(&*payload),
+            );
+        }
+    }
+
+    /// Hands the panic with `message` that a handler of `method` just had to the crash
+    /// reporters, with the request of `ctx` and the last messages of `session`.
+    fn report_crash(
+        &self,
+        session: &Session,
+        method: &str,
+        ctx: Option<&RequestContext>,
+        message: &str,
+    ) {
+        if self.crash_reporters.is_empty() {
+            return;
+        }
+        let mut crash = Crash::capture(message, self.info.clone());
+        crash.session = Some(session.id.clone());
+        crash.method = Some(method.to_string());
+        crash.request_id = ctx.map(|ctx| ctx.id.to_string());
+        crash.trace_id = ctx.map(|ctx| ctx.trace_id.clone());
+        if let Some(recent) = &session.recent {
+            crash.recent_messages = recent.redacted(&WireLog::redacting(&self.config()));
+        }
+        for reporter in &self.crash_reporters {
+            match reporter.report(&crash) {
+                Ok(place) => tracing::error!("Crash reported to {}", place),
+                Err(e) => tracing::error!("Cannot report the crash: {}", e),
+            }
+        }
+    }
+
+    fn dispatch_notification(&self, session: &Session, notification: &JsonRpcNotification) {
+        match notification.method.as_str() {
+            "notifications/initialized" => {
+                if !session.initialize_received.load(Ordering::SeqCst) {
+                    tracing::warn!("Ignoring notifications/initialized sent before initialize");
+                    return;
+                }
+                tracing::info!("Server initialized.");
+                session.info.lock().unwrap().initialized = true;
+                self.save_state();
+                if let Some(hook) = &self.hooks.on_initialized {
+                    hook();
+                }
+            }
+            "notifications/cancelled" => {
+                let request_id = notification
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("requestId"))
+                    .and_then(|id| serde_json::from_value::<JsonRpcId>(id.clone()).ok());
+                if let Some(id) = request_id {
+                    tracing::info!("Client cancelled request {}", id);
+                    if let Some(token) = session.in_flight.lock().unwrap().get(&id) {
+                        token.cancel();
+                    }
+                }
+            }
+            _ => {
+                tracing::error!("Unknown notification method: {}", notification.method);
+            }
+        }
+    }
+}
+
+/// Message read from a session and queued for its worker.
+enum Work {
+    /// A request with its cancellation and trace id, its params parsed once handled
+    Request(RawRequest, CancellationToken, String),
+    Notification(JsonRpcNotification),
+}
+
+/// True for the messages handled as soon as they are read, ahead of the requests queued before
+/// them, so that a session busy with long tool calls still answers pings and sees
+/// cancellations.
+fn is_control(method: &str) -> bool {
+    matches!(method, "ping" | "notifications/cancelled")
+}
+
+/// Most values a completion returns, as the spec allows.
+const MAX_COMPLETION_VALUES: usize = 100;
+
+/// Version to answer `initialize` with: the one the client asked for if supported, otherwise
+/// the latest one, which the client may then accept or disconnect.
+fn negotiate(requested: Option<&str>) -> ProtocolVersion {
+    let requested = requested.unwrap_or_default();
+    ProtocolVersion::parse(requested).unwrap_or_else(|| {
+        tracing::warn!(
+            "Client asked for protocol version '{}', which isn't supported, offering {}",
+            requested,
+            ProtocolVersion::LATEST
+        );
+        ProtocolVersion::LATEST
+    })
+}
+
+/// Registers a request as in flight, returning its cancellation token. Fails with
+/// INVALID_REQUEST if a request in flight already has this id, since their responses couldn't
+/// be told apart.
+fn track_in_flight(session: &Session, id: &JsonRpcId) -> Result<CancellationToken, JsonRpcError> {
+    match session.in_flight.lock().unwrap().entry(id.clone()) {
+        Entry::Occupied(_) => {
+            tracing::error!("Request id {} is already in use", id);
+            Err(JsonRpcError::new(
+                ERROR_CODE_INVALID_REQUEST,
+                format!("Request id {} is already used by a request in flight", id),
+            )
+            .with_data("retryable", true))
+        }
+        Entry::Vacant(entry) => Ok(entry.insert(CancellationToken::new()).clone()),
+    }
+}
+
+/// Names of the parameters of `method`, in the order they are given in positional (array)
+/// params.
+fn positional_params(method: &str) -> &'static [&'static str] {
+    match method {
+        "initialize" => &["protocolVersion", "capabilities", "clientInfo"],
+        "resources/read" => &["uri", "offset", "length"],
+        "resources/subscribe" | "resources/unsubscribe" => &["uri"],
+        "prompts/get" | "tools/call" => &["name", "arguments"],
+        "completion/complete" => &["ref", "argument"],
+        "logging/setLevel" => &["level"],
+        _ => &[],
+    }
+}
+
+fn method_not_found(method: &str) -> JsonRpcError {
+    JsonRpcError {
+        code: ERROR_CODE_METHOD_NOT_FOUND,
+        message: format!("Method not found: '{}'", method),
+        data: None,
+    }
+}
+
+/// `params` as an object: positional params are named after [`positional_params`], failing
+/// with INVALID_PARAMS if there are more than `method` takes.
+fn named_params<'a>(
+    method: &str,
+    params: Option<&'a Value>,
+) -> Result<Option<Cow<'a, Value>>, JsonRpcError> {
+    let Some(Value::Array(values)) = params else {
+        return Ok(params.map(Cow::Borrowed));
+    };
+    let names = positional_params(method);
+    if values.len() > names.len() {
+        let message = format!(
+            "Invalid params: {} takes at most {} positional params, got {}",
+            method,
+            names.len(),
+            values.len()
+        );
+        tracing::error!("{}", message);
+        let mut error = JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, message);
+        error.data = Some(json!({ "expected": names, "received": values.len() }));
+        return Err(error);
+    }
+    let named = names
+        .iter()
+        .zip(values)
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+    Ok(Some(Cow::Owned(Value::Object(named))))
+}
+
+/// Parameters `initialize` requires, with their type.
+const INITIALIZE_PARAMS: [(&str, ParamType); 5] = [
+    ("/protocolVersion", ParamType::String),
+    ("/capabilities", ParamType::Object),
+    ("/clientInfo", ParamType::Object),
+    ("/clientInfo/name", ParamType::String),
+    ("/clientInfo/version", ParamType::String),
+];
+
+/// JSON type a request parameter must have.
+#[derive(Clone, Copy)]
+enum ParamType {
+    String,
+    Integer,
+    Object,
+}
+
+impl ParamType {
+    fn name(self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Object => "object",
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            ParamType::String => value.is_string(),
+            ParamType::Integer => value.is_i64() || value.is_u64(),
+            ParamType::Object => value.is_object(),
+        }
+    }
+}
+
+/// Name of the JSON type of `value`.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// The parameter at `path` (a JSON pointer) of a request, if present and not null. Fails with
+/// INVALID_PARAMS if it's of another type than `expected`; `data` then holds the path of the
+/// parameter, the expected type and the received one.
+fn optional_param<'a>(
+    params: Option<&'a Value>,
+    path: &str,
+    expected: ParamType,
+) -> Result<Option<&'a Value>, JsonRpcError> {
+    match params.and_then(|params| params.pointer(path)) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) if expected.matches(value) => Ok(Some(value)),
+        Some(value) => Err(invalid_param(path, expected, type_name(value))),
+    }
+}
+
+/// Same as [`optional_param`] for a parameter that must be present, `received` being `missing`
+/// in the error if it isn't.
+fn required_param<'a>(
+    params: Option<&'a Value>,
+    path: &str,
+    expected: ParamType,
+) -> Result<&'a Value, JsonRpcError> {
+    optional_param(params, path, expected)?.ok_or_else(|| invalid_param(path, expected, "missing"))
+}
+
+fn required_str<'a>(params: Option<&'a Value>, path: &str) -> Result<&'a str, JsonRpcError> {
+    required_param(params, path, ParamType::String).map(|value| value.as_str().unwrap_or_default())
+}
+
+fn invalid_param(path: &str, expected: ParamType, received: &str) -> JsonRpcError {
+    let field = path.trim_start_matches('/').replace('/', ".");
+    let message = match received {
+        "missing" => format!("Invalid params: missing '{}' ({})", field, expected.name()),
+        _ => format!(
+            "Invalid params: '{}' must be of type {} (got {})",
+            field,
+            expected.name(),
+            received
+        ),
+    };
+    tracing::error!("{}", message);
+    JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, message)
+        .with_data("pointer", path)
+        .with_data("expected", expected.name())
+        .with_data("received", received)
+}
+
+/// Serving order of the session lifecycle: `initialize` comes first and only once, and the
+/// other requests, besides `ping`, wait for `notifications/initialized`.
+fn check_lifecycle(session: &Session, method: &str) -> Result<(), JsonRpcError> {
+    let message = match method {
+        "initialize" => match session.initialize_received.swap(true, Ordering::SeqCst) {
+            true => "The session is already initialized",
+            false => return Ok(()),
+        },
+        "ping" => return Ok(()),
+        _ if session.info.lock().unwrap().initialized => return Ok(()),
+        _ if session.initialize_received.load(Ordering::SeqCst) => {
+            "The session is not initialized yet: send notifications/initialized first"
+        }
+        _ => "The session is not initialized: send initialize first",
+    };
+    tracing::error!("{} ({} rejected)", message, method);
+    let error = JsonRpcError::new(ERROR_CODE_INVALID_REQUEST, message);
+    // Other requests succeed once the session is initialized
+    Err(match method {
+        "initialize" => error,
+        _ => error.with_data("retryable", true),
+    })
+}
@@ -0,0 +1,185 @@
+//! `--service`: runs the server under the Windows service control manager, which starts and
+//! stops it, with its warnings and errors written to the Application event log.
+//!
+//! Install it with
+//! `sc create mcp_rs_test binPath= "C:\...\mcp_rs_test.exe --service --transport http"`.
+
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    io, iter,
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::{field::Visit, layer::Context, Layer};
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+};
+use windows_sys::Win32::{
+    Foundation::HANDLE,
+    System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+        EVENTLOG_WARNING_TYPE,
+    },
+};
+
+/// Name of the service, and source of its events in the event log.
+const SERVICE_NAME: &str = "mcp_rs_test";
+/// Exit code reported to the service control manager when the transport failed.
+const EXIT_TRANSPORT_FAILED: u32 = 1;
+
+/// The transport the service runs, taken by [`service_main`].
+type Serve = Box<dyn FnOnce() -> io::Result<()> + Send>;
+
+static SERVE: Mutex<Option<Serve>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Hands the process over to the service control manager, which runs `serve` until the
+/// service is stopped. Fails when the process wasn't started as a service.
+pub fn run(serve: impl FnOnce() -> io::Result<()> + Send + 'static) -> io::Result<()> {
+    *SERVE.lock().unwrap() = Some(Box::new(serve));
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(io::Error::other)
+}
+
+/// What ends the service.
+enum Stop {
+    Requested,
+    Failed(io::Error),
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        tracing::error!("Service failed: {}", e);
+    }
+}
+
+fn run_service() -> windows_service::Result<()> {
+    let (stop, stopped) = mpsc::channel();
+    let requested = stop.clone();
+    let status = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            let _ = requested.send(Stop::Requested);
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let report = |state, exit_code| {
+        status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: match state {
+                ServiceState::Running => {
+                    ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+                }
+                _ => ServiceControlAccept::empty(),
+            },
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+    if let Some(serve) = SERVE.lock().unwrap().take() {
+        thread::spawn(move || {
+            let result = serve();
+            let _ = stop.send(match result {
+                Ok(()) => Stop::Requested,
+                Err(e) => Stop::Failed(e),
+            });
+        });
+    }
+    report(ServiceState::Running, 0)?;
+    tracing::info!("Service started");
+    let exit_code = match stopped.recv() {
+        Ok(Stop::Failed(e)) => {
+            tracing::error!("Error serving the transport, stopping the service: {}", e);
+            EXIT_TRANSPORT_FAILED
+        }
+        Ok(Stop::Requested) | Err(_) => 0,
+    };
+    tracing::info!("Service stopped");
+    // The transport threads end with the process, once the dispatcher returns
+    report(ServiceState::Stopped, exit_code)
+}
+
+/// Layer writing warnings and errors to the Application event log.
+pub struct EventLog {
+    source: HANDLE,
+}
+
+// SAFETY: event log handles can be used from any thread
+unsafe impl Send for EventLog {}
+unsafe impl Sync for EventLog {}
+
+impl EventLog {
+    /// Registers the event source, or None if that fails.
+    pub fn register() -> Option<EventLog> {
+        let name = wide(SERVICE_NAME);
+        // SAFETY: `name` is a null-terminated UTF-16 string that outlives the call
+        let source = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        (!source.is_null()).then_some(EventLog { source })
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        // SAFETY: `source` was returned by RegisterEventSourceW and isn't used afterwards
+        unsafe { DeregisterEventSource(self.source) };
+    }
+}
+
+impl<S: Subscriber> Layer<S> for EventLog {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let kind = match *event.metadata().level() {
+            Level::ERROR => EVENTLOG_ERROR_TYPE,
+            Level::WARN => EVENTLOG_WARNING_TYPE,
+            _ => return,
+        };
+        let mut message = Message::default();
+        event.record(&mut message);
+        let text = wide(&message.0);
+        let strings = [text.as_ptr()];
+        // SAFETY: `strings` holds one null-terminated UTF-16 string, both outliving the call
+        unsafe {
+            ReportEventW(
+                self.source,
+                kind,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            )
+        };
+    }
+}
+
+/// Fields of an event, the message (always recorded first) followed by the others.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let _ = match field.name() {
+            "message" => write!(self.0, "{:?}", value),
+            name => write!(self.0, " {}={:?}", name, value),
+        };
+    }
+}
+
+/// `text` as a null-terminated UTF-16 string.
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(iter::once(0)).collect()
+}
@@ -0,0 +1,117 @@
+//! Graceful stop on SIGINT and SIGTERM, or on Ctrl-C, Ctrl-Break and closing the console on
+//! Windows, instead of the process dying in the middle of a write. Asking a second time ends the
+//! process right away.
+
+use std::{io, thread};
+
+/// Waits for the first stop request.
+type Wait = Box<dyn FnOnce() + Send>;
+
+/// Calls `stop` on a thread of its own when the process is asked to stop.
+pub fn on_stop(stop: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    let wait = imp::install()?;
+    thread::spawn(move || {
+        wait();
+        stop();
+    });
+    Ok(())
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::Wait;
+    use std::{
+        fs::File,
+        io::{self, Read},
+        mem,
+        os::fd::FromRawFd,
+        ptr,
+        sync::atomic::{AtomicI32, Ordering},
+    };
+
+    /// Write end of the pipe the signal handler wakes the waiting thread with.
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle(_signal: libc::c_int) {
+        let byte = 0u8;
+        // SAFETY: write is async-signal-safe and the pipe stays open for the life of the process
+        unsafe {
+            libc::write(PIPE.load(Ordering::Relaxed), ptr::addr_of!(byte).cast(), 1);
+        }
+    }
+
+    pub(super) fn install() -> io::Result<Wait> {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for both ends of the pipe
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        PIPE.store(fds[1], Ordering::Relaxed);
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: the handler only calls async-signal-safe functions. It is reset to the
+            // default action once called, so that a second signal ends the process.
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART | libc::SA_RESETHAND;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        // SAFETY: the read end of the pipe is owned by the file from now on
+        let mut pipe = unsafe { File::from_raw_fd(fds[0]) };
+        Ok(Box::new(move || {
+            let _ = pipe.read(&mut [0]);
+        }))
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::Wait;
+    use std::{
+        io,
+        sync::{
+            mpsc::{self, Sender},
+            Mutex,
+        },
+        thread,
+    };
+    use windows_sys::{
+        core::BOOL,
+        Win32::System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT},
+    };
+
+    /// Wakes the waiting thread, taken by the first stop request.
+    static STOP: Mutex<Option<Sender<()>>> = Mutex::new(None);
+
+    unsafe extern "system" fn handle(ctrl_type: u32) -> BOOL {
+        let Some(stop) = STOP.lock().unwrap().take() else {
+            // Not handled, so that the default handler ends the process
+            return 0;
+        };
+        let _ = stop.send(());
+        // The process is ended as soon as the handler of a close, logoff or shutdown event
+        // returns, so it waits for the stop to exit the process
+        if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+            loop {
+                thread::park();
+            }
+        }
+        1
+    }
+
+    pub(super) fn install() -> io::Result<Wait> {
+        let (stop, stopped) = mpsc::channel();
+        *STOP.lock().unwrap() = Some(stop);
+        // SAFETY: the handler is a function with the expected signature
+        if unsafe { SetConsoleCtrlHandler(Some(handle), 1) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Box::new(move || {
+            let _ = stopped.recv();
+        }))
+    }
+}
@@ -0,0 +1,144 @@
+//! Size limit of the tool results: text content beyond `tool_max_output` bytes is cut with a
+//! marker saying how much was left out. With `tool_output_spill`, the full text is kept in a
+//! temporary file served as an `output://` resource, which the result links to so that the
+//! client can still read it.
+
+use crate::{
+    context::random_id,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INVALID_PARAMS},
+    mcp::{CallToolResult, Content, ReadResourceResult, ResourceContents},
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::Mutex,
+};
+
+const OUTPUT_SCHEME: &str = "output://";
+/// Spilled outputs kept, the oldest being removed first.
+const MAX_SPILLED_OUTPUTS: usize = 32;
+
+/// The full outputs of the truncated results, in anonymous temporary files, which the system
+/// removes once they are closed.
+#[derive(Default)]
+pub(crate) struct SpilledOutputs {
+    /// Ids and files of the outputs kept, oldest first
+    files: Mutex<VecDeque<(String, File)>>,
+}
+
+impl SpilledOutputs {
+    /// Saves `text`, returning the URI it can be read at.
+    fn save(&self, text: &str) -> io::Result<String> {
+        let mut file = tempfile::tempfile()?;
+        file.write_all(text.as_bytes())?;
+        let id = random_id();
+        let mut files = self.files.lock().unwrap();
+        files.push_back((id.clone(), file));
+        while files.len() > MAX_SPILLED_OUTPUTS {
+            files.pop_front();
+        }
+        Ok(format!("{}{}", OUTPUT_SCHEME, id))
+    }
+
+    /// Reads a spilled output, or None if `uri` isn't an `output://` URI.
+    pub(crate) fn read(&self, uri: &str) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+        let id = uri.strip_prefix(OUTPUT_SCHEME)?;
+        let mut files = self.files.lock().unwrap();
+        let result = match files.iter_mut().find(|(kept, _)| kept == id) {
+            Some((_, file)) => {
+                let mut text = String::new();
+                file.seek(SeekFrom::Start(0))
+                    .and_then(|_| file.read_to_string(&mut text))
+                    .map(|_| {
+                        let contents =
+                            ResourceContents::text(uri, text).with_mime_type("text/plain");
+                        ReadResourceResult::new(vec![contents])
+                    })
+                    .map_err(|e| format!("cannot read {}: {}", uri, e))
+            }
+            None => Err(format!("{} is no longer available", uri)),
+        };
+        Some(result.map_err(|e| JsonRpcError::new(ERROR_CODE_INVALID_PARAMS, e)))
+    }
+}
+
+/// Cuts the text contents of `result` once they total more than `max_size` bytes (0 for no
+/// limit), spilling the full text to `spilled` if given. Structured content that is larger
+/// than the limit is dropped too.
+pub(crate) fn limit_output(
+    mut result: CallToolResult,
+    tool: &str,
+    max_size: usize,
+    spilled: Option<&SpilledOutputs>,
+) -> CallToolResult {
+    if max_size == 0 {
+        return result;
+    }
+    let total: usize = result
+        .content
+        .iter()
+        .map(|content| match content {
+            Content::Text { text } => text.len(),
+            _ => 0,
+        })
+        .sum();
+    if total <= max_size {
+        return result;
+    }
+    tracing::warn!(
+        "Output of {} truncated from {} to {} bytes",
+        tool,
+        total,
+        max_size
+    );
+    let full = spilled.map(|_| {
+        let texts: Vec<&str> = result
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                Content::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        texts.join("\n")
+    });
+    let mut left = max_size;
+    for content in &mut result.content {
+        let Content::Text { text } = content else {
+            continue;
+        };
+        if text.len() <= left {
+            left -= text.len();
+            continue;
+        }
+        let mut end = left;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let omitted = text.len() - end;
+        text.truncate(end);
+        text.push_str(&format!("\n[... {} more bytes truncated]", omitted));
+        left = 0;
+    }
+    let structured_size = result
+        .structured_content
+        .as_ref()
+        .map_or(0, |value| value.to_string().len());
+    if structured_size > max_size {
+        result.structured_content = None;
+    }
+    if let (Some(spilled), Some(full)) = (spilled, full) {
+        match spilled.save(&full) {
+            Ok(uri) => result.content.push(Content::ResourceLink {
+                uri,
+                name: format!("Full output of {}", tool),
+                description: None,
+                mime_type: Some("text/plain".to_string()),
+                size: Some(full.len() as u64),
+            }),
+            Err(e) => tracing::error!("Cannot save the full output of {}: {}", tool, e),
+        }
+    }
+    result
+}
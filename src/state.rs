@@ -0,0 +1,131 @@
+//! Session state saved to a file, so that HTTP sessions outlive a restart of the server: a
+//! client still using its `Mcp-Session-Id` is served as if nothing happened, with its
+//! subscriptions in place and notifications about what may have changed meanwhile.
+//!
+//! Tools and prompts are closures and can't be saved; they come back with whatever registers
+//! them at startup. Their names are saved with each session, to tell its client whether the
+//! lists changed.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Time after which a saved session that wasn't resumed is forgotten.
+const SESSION_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// State of one session, as saved.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SavedSession {
+    /// Protocol version requested by the client
+    pub protocol_version: Option<String>,
+    pub client_info: Option<Value>,
+    pub client_capabilities: Option<Value>,
+    /// True once `notifications/initialized` had been received
+    pub initialized: bool,
+    /// URIs of the resources the client subscribed to
+    pub subscriptions: BTreeSet<String>,
+    /// Names of the tools offered to the client
+    pub tools: BTreeSet<String>,
+    /// Names of the prompts offered to the client
+    pub prompts: BTreeSet<String>,
+    /// When the session was saved, in seconds since the Unix epoch
+    pub saved_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SavedState {
+    sessions: BTreeMap<String, SavedSession>,
+}
+
+/// The state file of a server.
+pub struct StateStore {
+    path: PathBuf,
+    /// Sessions saved before the server started that haven't been resumed yet
+    pending: Mutex<BTreeMap<String, SavedSession>>,
+}
+
+impl StateStore {
+    /// Opens the state file, which doesn't need to exist. An unreadable file is logged and
+    /// ignored, and sessions saved too long ago are dropped.
+    pub fn open(path: &Path) -> StateStore {
+        let state = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                tracing::error!("Ignoring invalid state file {}: {}", path.display(), e);
+                SavedState::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => SavedState::default(),
+            Err(e) => {
+                tracing::error!("Cannot read state file {}: {}", path.display(), e);
+                SavedState::default()
+            }
+        };
+        let oldest = now().saturating_sub(SESSION_RETENTION.as_secs());
+        let pending: BTreeMap<String, SavedSession> = state
+            .sessions
+            .into_iter()
+            .filter(|(_, session)| session.saved_at >= oldest)
+            .collect();
+        if !pending.is_empty() {
+            tracing::info!(
+                "Sessions that can be resumed from {}: {}",
+                path.display(),
+                pending.len()
+            );
+        }
+        StateStore {
+            path: path.to_path_buf(),
+            pending: Mutex::new(pending),
+        }
+    }
+
+    /// True if a session saved under `key` can be resumed.
+    pub fn has_session(&self, key: &str) -> bool {
+        self.pending.lock().unwrap().contains_key(key)
+    }
+
+    /// Takes the session saved under `key`, to resume it.
+    pub fn take_session(&self, key: &str) -> Option<SavedSession> {
+        self.pending.lock().unwrap().remove(key)
+    }
+
+    /// Writes the running `sessions` along with those not resumed yet. The file is replaced
+    /// at once, so that a crash while saving leaves the previous state.
+    pub fn save(&self, mut sessions: BTreeMap<String, SavedSession>) {
+        let saved_at = now();
+        for session in sessions.values_mut() {
+            session.saved_at = saved_at;
+        }
+        // Held while writing, so that concurrent saves don't mix their files
+        let pending = self.pending.lock().unwrap();
+        for (key, session) in pending.iter() {
+            sessions
+                .entry(key.clone())
+                .or_insert_with(|| session.clone());
+        }
+        if let Err(e) = self.write(&SavedState { sessions }) {
+            tracing::error!("Cannot save the state to {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn write(&self, state: &SavedState) -> io::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(state)?)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+/// Current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,72 @@
+//! Typed state for handlers: one value per type, shared by every session
+//! ([`crate::server::Server::with_shared_state`]) or created for each session when it connects
+//! and dropped when it disconnects ([`crate::server::Server::with_session_state`]).
+//!
+//! Handlers reach them with [`crate::context::RequestContext::shared_state`] and
+//! [`crate::context::RequestContext::session_state`]. Values are shared between the requests
+//! being served, so those that change hold their own locks or atomics.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Map from a type to the one value of that type.
+#[derive(Default)]
+pub struct StateBag {
+    values: RwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl StateBag {
+    pub fn new() -> StateBag {
+        StateBag::default()
+    }
+
+    /// Stores `value`, replacing the value of the same type if there was one.
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) {
+        self.values
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// The value of type `T`, if one was stored.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let value = self.values.read().unwrap().get(&TypeId::of::<T>())?.clone();
+        value.downcast().ok()
+    }
+
+    /// The value of type `T`, storing the one made by `make` first if there was none.
+    pub fn get_or_insert_with<T: Send + Sync + 'static>(&self, make: impl FnOnce() -> T) -> Arc<T> {
+        let mut values = self.values.write().unwrap();
+        let value = values
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(make()))
+            .clone();
+        // Entries are only ever stored under the id of their own type
+        value.downcast().ok().unwrap()
+    }
+
+    /// Removes the value of type `T`, returning it.
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let value = self.values.write().unwrap().remove(&TypeId::of::<T>())?;
+        value.downcast().ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl std::fmt::Debug for StateBag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateBag")
+            .field("values", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,341 @@
+//! Utilities for black-box tests of a [`Server`]: a [`MockClient`] runs a session on another
+//! thread and lets tests send messages and wait for what the server sends back.
+//!
+//! ```no_run
+//! # use mcp_rs_test::{server::Server, testing::MockClient};
+//! # use std::sync::Arc;
+//! # fn main() -> Result<(), String> {
+//! let mut client = MockClient::connect(Arc::new(Server::new()));
+//! client.initialize()?;
+//! let response = client.request("ping", None)?;
+//! assert_eq!(response["result"], serde_json::json!({}));
+//! client.expect_silence(std::time::Duration::from_millis(100))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{server::Server, transport::ChannelWriter};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    io,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Time an expected message is waited for, unless set with [`MockClient::with_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A message a test waits for or checks the order of.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    /// The response (result or error) to the request with this id
+    Response(Value),
+    /// A notification with this method
+    Notification(String),
+    /// A request of the server with this method
+    Request(String),
+}
+
+impl Expected {
+    pub fn matches(&self, message: &Value) -> bool {
+        let (has_method, has_id) = (message.get("method").is_some(), message.get("id").is_some());
+        match self {
+            Expected::Response(id) => !has_method && message.get("id") == Some(id),
+            Expected::Notification(method) => !has_id && message["method"] == **method,
+            Expected::Request(method) => has_id && message["method"] == **method,
+        }
+    }
+}
+
+impl Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Response(id) => write!(f, "the response to request {}", id),
+            Expected::Notification(method) => write!(f, "a {} notification", method),
+            Expected::Request(method) => write!(f, "a {} request", method),
+        }
+    }
+}
+
+/// A client session with a server running on another thread.
+///
+/// Messages the server sends are kept until a test consumes them, so waiting for one message
+/// doesn't lose the others, and every message received is kept in [`MockClient::history`].
+pub struct MockClient {
+    input: Option<Sender<String>>,
+    output: Receiver<String>,
+    session: Option<JoinHandle<io::Result<()>>>,
+    next_id: u64,
+    timeout: Duration,
+    /// Received messages not consumed yet, in arrival order
+    pending: VecDeque<Value>,
+    history: Vec<Value>,
+}
+
+impl MockClient {
+    /// Starts a session with `server`.
+    pub fn connect(server: Arc<Server>) -> MockClient {
+        MockClient::start(move |lines, out| server.serve(lines, out))
+    }
+
+    /// Starts a session with `server` authenticated by `key`, limited to what `scopes` grant.
+    pub fn connect_authenticated(
+        server: Arc<Server>,
+        key: &str,
+        scopes: Vec<String>,
+    ) -> MockClient {
+        let key = key.to_string();
+        MockClient::start(move |lines, out| {
+            server.serve_authenticated(Some(&key), scopes, lines, out)
+        })
+    }
+
+    /// Starts a session with `server` that can be resumed under `key`, as the HTTP transport
+    /// serves them.
+    pub fn connect_resumable(server: Arc<Server>, key: &str) -> MockClient {
+        let key = key.to_string();
+        MockClient::start(move |lines, out| server.serve_resumable(&key, lines, out))
+    }
+
+    fn start(
+        serve: impl FnOnce(Receiver<String>, ChannelWriter) -> io::Result<()> + Send + 'static,
+    ) -> MockClient {
+        let (input, lines) = mpsc::channel::<String>();
+        let (output_tx, output) = mpsc::channel();
+        let session = thread::spawn(move || serve(lines, ChannelWriter::new(output_tx)));
+        MockClient {
+            input: Some(input),
+            output,
+            session: Some(session),
+            next_id: 1,
+            timeout: DEFAULT_TIMEOUT,
+            pending: VecDeque::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Sets how long expected messages are waited for.
+    pub fn with_timeout(mut self, timeout: Duration) -> MockClient {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends a raw line, which doesn't have to be valid JSON.
+    pub fn send_raw(&self, line: impl Into<String>) -> Result<(), String> {
+        self.input
+            .as_ref()
+            .and_then(|input| input.send(line.into()).ok())
+            .ok_or_else(|| "the session has ended".to_string())
+    }
+
+    /// Sends a message, either a JSON value or one of the [`jsonrpc`](crate::jsonrpc) types.
+    pub fn send(&self, message: &impl Serialize) -> Result<(), String> {
+        let line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        self.send_raw(line)
+    }
+
+    /// Sends a request without waiting for its response, returning its id.
+    pub fn send_request(&mut self, method: &str, params: Option<Value>) -> Result<Value, String> {
+        let id = json!(self.next_id);
+        self.next_id += 1;
+        self.send(&request(&id, method, params))?;
+        Ok(id)
+    }
+
+    /// Sends a request and waits for its response.
+    pub fn request(&mut self, method: &str, params: Option<Value>) -> Result<Value, String> {
+        let id = self.send_request(method, params)?;
+        self.expect_response(&id)
+    }
+
+    /// Sends a request with a chosen id and waits for its response.
+    pub fn request_with_id(
+        &mut self,
+        id: Value,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<Value, String> {
+        self.send(&request(&id, method, params))?;
+        self.expect_response(&id)
+    }
+
+    pub fn notify(&self, method: &str, params: Option<Value>) -> Result<(), String> {
+        let mut message = json!({ "jsonrpc": "2.0", "method": method });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        self.send(&message)
+    }
+
+    /// Performs the `initialize` handshake, returning the `initialize` result.
+    pub fn initialize(&mut self) -> Result<Value, String> {
+        let response = self.request(
+            "initialize",
+            Some(json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "mock-client", "version": "0" },
+            })),
+        )?;
+        let result = response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| format!("initialize failed: {}", response["error"]))?;
+        self.notify("notifications/initialized", None)?;
+        Ok(result)
+    }
+
+    /// Next message not consumed yet, whatever it is.
+    pub fn receive(&mut self) -> Result<Value, String> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(message);
+        }
+        let deadline = Instant::now() + self.timeout;
+        self.fetch(deadline)?;
+        Ok(self.pending.pop_front().unwrap_or_default())
+    }
+
+    /// Waits for the first message matching `expected`, leaving the others for later.
+    pub fn expect(&mut self, expected: &Expected) -> Result<Value, String> {
+        let deadline = Instant::now() + self.timeout;
+        let mut checked = 0;
+        loop {
+            if let Some(index) = self
+                .pending
+                .iter()
+                .skip(checked)
+                .position(|message| expected.matches(message))
+            {
+                return Ok(self.pending.remove(checked + index).unwrap_or_default());
+            }
+            checked = self.pending.len();
+            self.fetch(deadline)
+                .map_err(|e| format!("waiting for {}: {}", expected, e))?;
+        }
+    }
+
+    /// Waits for the response to request `id`, checking its JSON-RPC envelope.
+    pub fn expect_response(&mut self, id: &Value) -> Result<Value, String> {
+        let response = self.expect(&Expected::Response(id.clone()))?;
+        check_envelope(&response)?;
+        Ok(response)
+    }
+
+    /// Waits for a notification with `method`, returning its params.
+    pub fn expect_notification(&mut self, method: &str) -> Result<Value, String> {
+        let notification = self.expect(&Expected::Notification(method.to_string()))?;
+        Ok(notification.get("params").cloned().unwrap_or_default())
+    }
+
+    /// Waits for a request of the server with `method`, returning it.
+    pub fn expect_request(&mut self, method: &str) -> Result<Value, String> {
+        self.expect(&Expected::Request(method.to_string()))
+    }
+
+    /// Answers the request of the server with `id`.
+    pub fn respond(&self, id: &Value, result: Value) -> Result<(), String> {
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    /// Checks that the server sends nothing for `duration`, besides messages already received.
+    pub fn expect_silence(&mut self, duration: Duration) -> Result<(), String> {
+        match self.fetch(Instant::now() + duration) {
+            Ok(()) => Err(format!(
+                "unexpected message: {}",
+                json!(self.pending.back())
+            )),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Messages not consumed yet, including those already sent by the server but not
+    /// received, without waiting.
+    pub fn drain(&mut self) -> Vec<Value> {
+        while self.fetch(Instant::now()).is_ok() {}
+        self.pending.drain(..).collect()
+    }
+
+    /// Every message received so far, consumed or not, in arrival order.
+    pub fn history(&self) -> &[Value] {
+        &self.history
+    }
+
+    /// Checks that a message matching `first` was received before one matching `second`.
+    pub fn assert_order(&self, first: &Expected, second: &Expected) -> Result<(), String> {
+        let position = |expected: &Expected| {
+            self.history
+                .iter()
+                .position(|message| expected.matches(message))
+                .ok_or_else(|| format!("{} was not received", expected))
+        };
+        match position(first)? < position(second)? {
+            true => Ok(()),
+            false => Err(format!("{} was received after {}", first, second)),
+        }
+    }
+
+    /// Ends the session and waits for the server to finish it.
+    pub fn close(mut self) -> Result<(), String> {
+        self.input.take();
+        match self.session.take().map(JoinHandle::join) {
+            Some(Err(_)) => Err("the server panicked".to_string()),
+            Some(Ok(Err(e))) => Err(format!("the server couldn't write: {}", e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits for the next message from the server and queues it.
+    fn fetch(&mut self, deadline: Instant) -> Result<(), String> {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        let line = self.output.recv_timeout(wait).map_err(|e| match e {
+            RecvTimeoutError::Timeout => "no message received in time".to_string(),
+            RecvTimeoutError::Disconnected => "the session has ended".to_string(),
+        })?;
+        let message: Value = serde_json::from_str(&line)
+            .map_err(|e| format!("invalid JSON from the server: {}: {}", e, line))?;
+        self.history.push(message.clone());
+        self.pending.push_back(message);
+        Ok(())
+    }
+}
+
+impl Drop for MockClient {
+    fn drop(&mut self) {
+        self.input.take();
+        if let Some(session) = self.session.take() {
+            let _ = session.join();
+        }
+    }
+}
+
+fn request(id: &Value, method: &str, params: Option<Value>) -> Value {
+    let mut message = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+    if let Some(params) = params {
+        message["params"] = params;
+    }
+    message
+}
+
+/// Checks the JSON-RPC envelope of a response.
+pub fn check_envelope(response: &Value) -> Result<(), String> {
+    if response["jsonrpc"] != "2.0" {
+        return Err(format!(
+            "response without `\"jsonrpc\": \"2.0\"`: {}",
+            response
+        ));
+    }
+    match (response.get("result"), response.get("error")) {
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => Err(format!(
+            "response must have exactly one of `result` and `error`: {}",
+            response
+        )),
+    }
+}
@@ -0,0 +1,415 @@
+use crate::{
+    config::Config,
+    context::{CancellationToken, RequestContext},
+    errors::TIMEOUT,
+    jsonrpc::{JsonRpcError, ERROR_CODE_INTERNAL_ERROR, ERROR_CODE_INVALID_PARAMS},
+    mcp::{CallToolResult, Icon},
+    policy::{self, Permission},
+    protocol::ProtocolVersion,
+    schema,
+};
+use jsonschema::Validator;
+use serde_json::{json, Value};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    time::Duration,
+};
+
+/// Interval at which a call waiting for a slot checks whether it was cancelled.
+const SLOT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Function called with the `arguments` of a `tools/call` request.
+pub type ToolHandler =
+    Box<dyn Fn(&RequestContext, &Value) -> Result<CallToolResult, JsonRpcError> + Send + Sync>;
+/// Function describing what a call would do, without doing it, see [`Tool::with_dry_run`].
+pub type DryRunHandler =
+    Box<dyn Fn(&RequestContext, &Value) -> Result<Value, JsonRpcError> + Send + Sync>;
+
+/// Argument asking a tool to describe what it would do instead of doing it.
+pub const DRY_RUN_ARGUMENT: &str = "dryRun";
+
+/// A tool the client can invoke with `tools/call`.
+/// See https://spec.modelcontextprotocol.io/specification/2024-11-05/server/tools/
+pub struct Tool {
+    /// Unique name of the tool
+    pub name: String,
+    /// Name to display, if different from `name`
+    pub title: Option<String>,
+    /// Human-readable description of what the tool does
+    pub description: String,
+    /// JSON schema of the expected arguments
+    pub input_schema: Value,
+    /// Behavior hints for the client (`readOnlyHint`, `destructiveHint`, ...)
+    /// See https://spec.modelcontextprotocol.io/specification/2025-03-26/server/tools/#tool-annotations
+    pub annotations: Option<Value>,
+    /// JSON schema of the `structuredContent` of the results, if the tool returns any
+    pub output_schema: Option<Value>,
+    /// Icons to display
+    pub icons: Vec<Icon>,
+    /// Settings under which the tool is offered; always offered when absent
+    available: Option<fn(&Config) -> bool>,
+    /// Offered in `read_only` mode, see [`Tool::guards_read_only`]
+    guards_read_only: bool,
+    /// `input_schema`, compiled on the first call
+    validator: OnceLock<Option<Validator>>,
+    handler: ToolHandler,
+    dry_run: Option<DryRunHandler>,
+    /// Time a call may take, see [`Tool::timeout`]
+    timeout: Option<Duration>,
+}
+
+impl Tool {
+    pub fn new<F>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        input_schema: Value,
+        handler: F,
+    ) -> Tool
+    where
+        F: Fn(&RequestContext, &Value) -> Result<CallToolResult, JsonRpcError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Tool {
+            name: name.into(),
+            title: None,
+            description: description.into(),
+            input_schema,
+            annotations: None,
+            output_schema: None,
+            icons: Vec::new(),
+            available: None,
+            guards_read_only: false,
+            validator: OnceLock::new(),
+            handler: Box::new(handler),
+            dry_run: None,
+            timeout: None,
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Tool {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: Icon) -> Tool {
+        self.icons.push(icon);
+        self
+    }
+
+    pub fn with_annotations(mut self, annotations: Value) -> Tool {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    pub fn with_output_schema(mut self, output_schema: Value) -> Tool {
+        self.output_schema = Some(output_schema);
+        self
+    }
+
+    /// Lets the calls take up to `timeout` instead of `tool_timeout_secs`; the zero duration
+    /// lifts the limit.
+    pub fn with_timeout(mut self, timeout: Duration) -> Tool {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Time a call may take under `config`: that of the tool's entry in `tool_timeouts`, or else
+    /// its own, or else `tool_timeout_secs`. None when there is no limit.
+    pub fn timeout(&self, config: &Config) -> Option<Duration> {
+        let timeout = match config.tool_timeouts.get(&self.name) {
+            Some(secs) => Duration::from_secs(*secs),
+            None => self
+                .timeout
+                .unwrap_or(Duration::from_secs(config.tool_timeout_secs)),
+        };
+        (timeout > Duration::ZERO).then_some(timeout)
+    }
+
+    /// Lets the tool be called with `dryRun`, or under the `dry_run` setting, in which case
+    /// `plan` describes what the call would do (files touched, commands run...) instead of the
+    /// handler doing it. The `dryRun` argument is added to the input schema.
+    pub fn with_dry_run<F>(mut self, plan: F) -> Tool
+    where
+        F: Fn(&RequestContext, &Value) -> Result<Value, JsonRpcError> + Send + Sync + 'static,
+    {
+        if let Some(properties) = self.input_schema["properties"].as_object_mut() {
+            properties.insert(
+                DRY_RUN_ARGUMENT.to_string(),
+                json!({
+                    "type": "boolean",
+                    "description": "Describe what the call would do without doing it"
+                }),
+            );
+        }
+        self.dry_run = Some(Box::new(plan));
+        self
+    }
+
+    /// Only offers the tool while `available` returns true for the current settings,
+    /// e.g. to make it opt-in through a config flag.
+    pub fn available_when(mut self, available: fn(&Config) -> bool) -> Tool {
+        self.available = Some(available);
+        self
+    }
+
+    /// Keeps offering the tool in `read_only` mode, in which it refuses to modify anything
+    /// itself, as the database tools do. The other tools that aren't read-only are withdrawn.
+    pub fn guards_read_only(mut self) -> Tool {
+        self.guards_read_only = true;
+        self
+    }
+
+    /// True if the tool is offered under `config`.
+    pub fn is_available(&self, config: &Config) -> bool {
+        let enabled = match &config.enabled_tools {
+            Some(enabled) => enabled.contains(&self.name),
+            None => true,
+        };
+        enabled
+            && policy::permission(config, &self.name) != Permission::Deny
+            && self.available.is_none_or(|available| available(config))
+            && (!config.read_only || self.is_read_only() || self.guards_read_only)
+    }
+
+    /// Runs the tool with the given arguments, once they are checked against `input_schema`.
+    /// A handler failing with INTERNAL_ERROR (a command that can't run, a downstream server that
+    /// went away, ...) or [`TIMEOUT`] yields an `isError` result, so that the model sees the
+    /// failure.
+    ///
+    /// Calls with `dryRun` set, and the calls of the tools that aren't read-only under the
+    /// `dry_run` setting, are described by the plan of [`Tool::with_dry_run`] instead. Read-only
+    /// tools without a plan run as usual, and the others are refused.
+    pub fn call(
+        &self,
+        ctx: &RequestContext,
+        arguments: &Value,
+    ) -> Result<CallToolResult, JsonRpcError> {
+        let mut arguments = Cow::Borrowed(arguments);
+        let mut dry_run = ctx.config.dry_run && !self.is_read_only();
+        if let Some(requested) = arguments.get(DRY_RUN_ARGUMENT) {
+            let Some(requested) = requested.as_bool() else {
+                return Err(JsonRpcError::new(
+                    ERROR_CODE_INVALID_PARAMS,
+                    format!("'{}' must be a boolean", DRY_RUN_ARGUMENT),
+                ));
+            };
+            dry_run |= requested;
+            if let Some(arguments) = arguments.to_mut().as_object_mut() {
+                arguments.remove(DRY_RUN_ARGUMENT);
+            }
+        }
+        let validator = self
+            .validator
+            .get_or_init(|| schema::compile(&format!("tool {}", self.name), &self.input_schema));
+        if let Some(validator) = validator {
+            schema::check(validator, &arguments)?;
+        }
+        let result = match (&self.dry_run, dry_run) {
+            (Some(plan), true) => {
+                tracing::info!("Dry run of {}", self.name);
+                plan(ctx, &arguments)
+                    .map(|plan| CallToolResult::structured(json!({ "dryRun": true, "plan": plan })))
+            }
+            (None, true) if !self.is_read_only() => Ok(CallToolResult::error(format!(
+                "{} can't describe what it would do, so it doesn't run in dry-run mode",
+                self.name
+            ))),
+            _ => (self.handler)(ctx, &arguments),
+        };
+        match result {
+            Err(e) if e.code == ERROR_CODE_INTERNAL_ERROR || e.code == TIMEOUT.code => {
+                Ok(CallToolResult::error(e.message))
+            }
+            result => result,
+        }
+    }
+
+    /// True if the tool says it doesn't modify its environment (`readOnlyHint`).
+    fn is_read_only(&self) -> bool {
+        self.annotations
+            .as_ref()
+            .is_some_and(|annotations| annotations["readOnlyHint"] == json!(true))
+    }
+
+    /// Definition of the tool as listed by `tools/list` to a client speaking `version`, without
+    /// the fields it doesn't know about.
+    pub fn definition(&self, version: ProtocolVersion) -> Value {
+        let mut definition = json!({
+            "name": self.name,
+            "description": self.description,
+            "inputSchema": self.input_schema,
+        });
+        if version.supports_titles() {
+            if let Some(title) = &self.title {
+                definition["title"] = json!(title);
+            }
+            if !self.icons.is_empty() {
+                definition["icons"] = json!(self.icons);
+            }
+        }
+        if let (Some(annotations), true) = (&self.annotations, version.supports_tool_annotations())
+        {
+            definition["annotations"] = annotations.clone();
+        }
+        if let (Some(output_schema), true) =
+            (&self.output_schema, version.supports_structured_output())
+        {
+            definition["outputSchema"] = output_schema.clone();
+        }
+        definition
+    }
+}
+
+/// Tools registered on the server, sorted by name.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Tool>,
+}
+
+impl ToolRegistry {
+    /// Adds a tool, replacing any tool registered under the same name.
+    pub fn register(&mut self, tool: Tool) {
+        self.tools.insert(tool.name.clone(), tool);
+    }
+
+    /// Removes a tool, returning it if it was registered.
+    pub fn unregister(&mut self, name: &str) -> Option<Tool> {
+        self.tools.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.tools.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tool> {
+        self.tools.values()
+    }
+
+    /// Names of the tools offered under `config`.
+    pub fn available_names(&self, config: &Config) -> Vec<String> {
+        self.iter()
+            .filter(|tool| tool.is_available(config))
+            .map(|tool| tool.name.clone())
+            .collect()
+    }
+}
+
+/// Counting semaphore handing out slots in arrival order. The limit is given at each
+/// acquisition, so that it follows configuration reloads.
+#[derive(Default)]
+struct Slots {
+    state: Mutex<SlotsState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct SlotsState {
+    taken: usize,
+    /// Tickets of the callers waiting for a slot, in arrival order
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+}
+
+/// A taken slot, given back when dropped.
+struct Slot<'a>(&'a Slots);
+
+impl Slots {
+    /// Takes a slot once fewer than `limit` are taken (right away if `limit` is 0) and the
+    /// callers that arrived earlier got theirs. Gives up if `cancellation` is cancelled.
+    fn acquire(&self, limit: usize, cancellation: &CancellationToken) -> Option<Slot<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push_back(ticket);
+        loop {
+            if state.waiting.front() == Some(&ticket) && (limit == 0 || state.taken < limit) {
+                state.waiting.pop_front();
+                state.taken += 1;
+                self.changed.notify_all();
+                return Some(Slot(self));
+            }
+            if cancellation.is_cancelled() {
+                state.waiting.retain(|waiting| *waiting != ticket);
+                self.changed.notify_all();
+                return None;
+            }
+            state = self
+                .changed
+                .wait_timeout(state, SLOT_POLL_INTERVAL)
+                .unwrap()
+                .0;
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().taken -= 1;
+        self.0.changed.notify_all();
+    }
+}
+
+/// Limits on the tool calls running at once, across sessions: `max_concurrent_tool_calls` in
+/// total and `tool_concurrency` by tool. Calls over a limit wait for their turn.
+#[derive(Default)]
+pub(crate) struct CallLimiter {
+    all: Slots,
+    by_tool: Mutex<HashMap<String, Arc<Slots>>>,
+}
+
+impl CallLimiter {
+    /// Runs `call`, a call of the tool `name`, once the limits allow it.
+    pub(crate) fn run<T>(
+        &self,
+        name: &str,
+        ctx: &RequestContext,
+        call: impl FnOnce() -> Result<T, JsonRpcError>,
+    ) -> Result<T, JsonRpcError> {
+        let cancelled = || {
+            JsonRpcError::new(
+                ERROR_CODE_INTERNAL_ERROR,
+                "Cancelled while waiting for a slot",
+            )
+        };
+        let tool_limit = ctx.config.tool_concurrency.get(name).copied().unwrap_or(0);
+        let tool_slots = (tool_limit > 0).then(|| {
+            self.by_tool
+                .lock()
+                .unwrap()
+                .entry(name.to_string())
+                .or_default()
+                .clone()
+        });
+        let _tool_slot = match &tool_slots {
+            Some(slots) => Some(
+                slots
+                    .acquire(tool_limit, &ctx.cancellation)
+                    .ok_or_else(cancelled)?,
+            ),
+            None => None,
+        };
+        let _slot = self
+            .all
+            .acquire(ctx.config.max_concurrent_tool_calls, &ctx.cancellation)
+            .ok_or_else(cancelled)?;
+        call()
+    }
+}
+
+/// Returns the string argument `name`, or an INVALID_PARAMS error if it is missing.
+pub fn required_str<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, JsonRpcError> {
+    arguments.get(name).and_then(Value::as_str).ok_or_else(|| {
+        JsonRpcError::new(
+            ERROR_CODE_INVALID_PARAMS,
+            format!("Missing string argument '{}'", name),
+        )
+    })
+}
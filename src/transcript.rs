@@ -0,0 +1,108 @@
+//! Session transcripts: every message a server receives and sends, with timestamps, written as
+//! one JSON object per line so bug reports can be replayed.
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Sent by the client
+    In,
+    /// Sent by the server
+    Out,
+}
+
+/// One line of a transcript.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    /// RFC 3339 timestamp, in UTC
+    pub time: String,
+    /// Number of the session, in the order they started
+    pub session: u64,
+    pub direction: Direction,
+    /// The message, or the raw line as a string when it wasn't a JSON object or array
+    pub message: Value,
+}
+
+impl Entry {
+    /// The line the message was received or sent as.
+    pub fn line(&self) -> String {
+        match &self.message {
+            Value::String(raw) => raw.clone(),
+            message => message.to_string(),
+        }
+    }
+}
+
+/// Writes the transcript of every session of a server to a file.
+pub struct Recorder {
+    file: Mutex<File>,
+    next_session: AtomicU64,
+}
+
+impl Recorder {
+    /// Creates (or truncates) the transcript file.
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: Mutex::new(File::create(path)?),
+            next_session: AtomicU64::new(1),
+        })
+    }
+
+    /// Number of a new session.
+    pub(crate) fn start_session(&self) -> u64 {
+        self.next_session.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub(crate) fn record(&self, session: u64, direction: Direction, line: &str) {
+        let entry = Entry {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            session,
+            direction,
+            message: serde_json::from_str(line)
+                .ok()
+                .filter(|message: &Value| message.is_object() || message.is_array())
+                .unwrap_or_else(|| Value::String(line.to_string())),
+        };
+        let mut text = serde_json::to_string(&entry).unwrap();
+        text.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(text.as_bytes()) {
+            tracing::error!("Cannot write to the transcript: {}", e);
+        }
+    }
+}
+
+/// Reads a transcript. Lines that aren't entries fail with their line number.
+pub fn read(path: &Path) -> Result<Vec<Entry>, String> {
+    Ok(read_numbered(path)?
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .collect())
+}
+
+/// Reads a transcript, with the line number of each entry, counting from 1.
+pub fn read_numbered(path: &Path) -> Result<Vec<(usize, Entry)>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry =
+            serde_json::from_str(&line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        entries.push((number + 1, entry));
+    }
+    Ok(entries)
+}
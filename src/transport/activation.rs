@@ -0,0 +1,111 @@
+//! systemd socket activation: when systemd starts the server for a `.socket` unit, it passes
+//! the socket it already bound, and the network transports accept connections on it instead of
+//! binding `listen` themselves. The server then only runs once a client connects.
+//! See https://www.freedesktop.org/software/systemd/man/latest/sd_listen_fds.html
+
+use std::{fmt, io, net::TcpListener};
+
+#[cfg(unix)]
+use std::os::{
+    fd::{FromRawFd, OwnedFd, RawFd},
+    unix::net::UnixListener,
+};
+
+/// First file descriptor of the sockets passed by systemd.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed by systemd.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(address) => write!(f, "{}", address),
+                Err(_) => f.write_str("TCP socket"),
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let address = listener.local_addr().ok();
+                match address.as_ref().and_then(|address| address.as_pathname()) {
+                    Some(path) => write!(f, "{}", path.display()),
+                    None => f.write_str("Unix socket"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<Listener> for tiny_http::Listener {
+    fn from(listener: Listener) -> tiny_http::Listener {
+        match listener {
+            Listener::Tcp(listener) => tiny_http::Listener::Tcp(listener),
+            #[cfg(unix)]
+            Listener::Unix(listener) => tiny_http::Listener::Unix(listener),
+        }
+    }
+}
+
+/// Takes the socket passed by systemd, if `LISTEN_FDS` and `LISTEN_PID` say there is one for
+/// this process. The variables are removed, so that the processes started by the tools don't
+/// see them, and only the first socket is used.
+#[cfg(unix)]
+pub fn take_listener() -> io::Result<Option<Listener>> {
+    let (Ok(pid), Ok(fds)) = (std::env::var("LISTEN_PID"), std::env::var("LISTEN_FDS")) else {
+        return Ok(None);
+    };
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(None);
+    }
+    let count = fds.parse::<RawFd>().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid LISTEN_FDS: {}", fds),
+        )
+    })?;
+    if count < 1 {
+        return Ok(None);
+    }
+    if count > 1 {
+        tracing::warn!(
+            "systemd passed {} sockets, only the first one is used",
+            count
+        );
+    }
+    // SAFETY: systemd passes the sockets open from LISTEN_FDS_START, for this process only
+    // (LISTEN_PID was checked), and nothing else in the process takes them
+    let fd = unsafe { OwnedFd::from_raw_fd(LISTEN_FDS_START) };
+    // Duplicating it sets close-on-exec, which systemd doesn't, so that children don't keep the
+    // socket open; the original descriptor is closed when `fd` is dropped
+    let fd = fd.try_clone()?;
+    let tcp = TcpListener::from(fd);
+    if tcp.local_addr().is_ok() {
+        return Ok(Some(Listener::Tcp(tcp)));
+    }
+    let unix = UnixListener::from(OwnedFd::from(tcp));
+    match unix.local_addr() {
+        Ok(_) => Ok(Some(Listener::Unix(unix))),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!(
+                "the socket passed by systemd isn't a TCP or Unix stream socket: {}",
+                e
+            ),
+        )),
+    }
+}
+
+/// Socket activation only exists on Unix.
+#[cfg(not(unix))]
+pub fn take_listener() -> io::Result<Option<Listener>> {
+    Ok(None)
+}
@@ -0,0 +1,112 @@
+//! gzip and deflate compression of the HTTP bodies: responses are compressed with the encoding
+//! the client prefers among those its `Accept-Encoding` lists, and request bodies sent with a
+//! `Content-Encoding` are decompressed before being parsed.
+
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use std::io::{Read, Write};
+
+/// Bodies smaller than this are sent as they are, compressing them saving next to nothing.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// A content coding the transport supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Gzip,
+    /// zlib-wrapped deflate, as HTTP's `deflate` is
+    Deflate,
+}
+
+impl Encoding {
+    fn parse(name: &str) -> Option<Encoding> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// The encoding to compress a response with, given the client's `Accept-Encoding`: the one
+/// with the highest `q`, gzip when they are equal, and none if neither is accepted.
+pub(crate) fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let encoding = match name {
+            "*" => Encoding::Gzip,
+            name => match Encoding::parse(name) {
+                Some(encoding) => encoding,
+                None => continue,
+            },
+        };
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// `body` compressed with `encoding`, or none if it is too small to be worth it.
+pub(crate) fn compress(body: &[u8], encoding: Encoding) -> Option<Vec<u8>> {
+    if body.len() < MIN_COMPRESSED_SIZE {
+        return None;
+    }
+    let compressed = match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).and_then(|_| encoder.finish())
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).and_then(|_| encoder.finish())
+        }
+    };
+    match compressed {
+        Ok(compressed) => Some(compressed),
+        Err(e) => {
+            tracing::error!(
+                "Error compressing HTTP response with {}: {}",
+                encoding.name(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Reader of `body` decompressing the codings of its `Content-Encoding`, applied in the order
+/// they are listed. Fails with the name of a coding that isn't supported.
+pub(crate) fn decoder<'a>(
+    content_encoding: Option<&str>,
+    body: &'a mut dyn Read,
+) -> Result<Box<dyn Read + 'a>, String> {
+    let mut reader: Box<dyn Read + 'a> = Box::new(body);
+    let Some(content_encoding) = content_encoding else {
+        return Ok(reader);
+    };
+    for name in content_encoding.rsplit(',').map(str::trim) {
+        reader = match Encoding::parse(name) {
+            Some(Encoding::Gzip) => Box::new(GzDecoder::new(reader)),
+            Some(Encoding::Deflate) => Box::new(ZlibDecoder::new(reader)),
+            None if name.is_empty() || name.eq_ignore_ascii_case("identity") => reader,
+            None => return Err(name.to_string()),
+        };
+    }
+    Ok(reader)
+}
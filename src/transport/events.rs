@@ -0,0 +1,190 @@
+//! Event stores of the Streamable HTTP transport: every message sent on an SSE stream is kept
+//! under an event id for a while, so that a client whose connection dropped can `GET` the ones
+//! it missed with `Last-Event-ID`.
+//! See https://spec.modelcontextprotocol.io/specification/2025-03-26/basic/transports/#resumability-and-redelivery
+
+use rusqlite::{params, Connection};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long events are kept and how many of them, by session.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    pub max_age: Duration,
+    pub max_events: usize,
+}
+
+/// Where the events of the SSE streams are kept.
+pub trait EventStore: Send + Sync {
+    /// Keeps a message sent to the session `session`, returning its event id, or None if it
+    /// couldn't be kept.
+    fn append(&self, session: &str, message: &str) -> Option<String>;
+
+    /// Events of `session` sent after the event `last_event_id`, oldest first. Unknown ids
+    /// yield no events.
+    fn after(&self, session: &str, last_event_id: &str) -> Vec<(String, String)>;
+
+    /// Forgets the events of a session that ended.
+    fn remove(&self, session: &str);
+}
+
+/// Events kept in memory, lost when the server stops.
+pub struct MemoryEventStore {
+    retention: Retention,
+    state: Mutex<MemoryEvents>,
+}
+
+#[derive(Default)]
+struct MemoryEvents {
+    next_id: u64,
+    /// Id, time and message of the events, by session
+    sessions: HashMap<String, VecDeque<(u64, Instant, String)>>,
+}
+
+impl MemoryEventStore {
+    pub fn new(retention: Retention) -> MemoryEventStore {
+        MemoryEventStore {
+            retention,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl EventStore for MemoryEventStore {
+    fn append(&self, session: &str, message: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        let now = Instant::now();
+        for events in state.sessions.values_mut() {
+            while events
+                .front()
+                .is_some_and(|(_, time, _)| now.duration_since(*time) > self.retention.max_age)
+            {
+                events.pop_front();
+            }
+        }
+        state.sessions.retain(|_, events| !events.is_empty());
+        let events = state.sessions.entry(session.to_string()).or_default();
+        events.push_back((id, now, message.to_string()));
+        while events.len() > self.retention.max_events {
+            events.pop_front();
+        }
+        Some(id.to_string())
+    }
+
+    fn after(&self, session: &str, last_event_id: &str) -> Vec<(String, String)> {
+        let Ok(last) = last_event_id.parse::<u64>() else {
+            return Vec::new();
+        };
+        let state = self.state.lock().unwrap();
+        state
+            .sessions
+            .get(session)
+            .into_iter()
+            .flatten()
+            .filter(|(id, _, _)| *id > last)
+            .map(|(id, _, message)| (id.to_string(), message.clone()))
+            .collect()
+    }
+
+    fn remove(&self, session: &str) {
+        self.state.lock().unwrap().sessions.remove(session);
+    }
+}
+
+/// Events kept in a SQLite database, so that streams can be resumed after a restart of the
+/// server along with their sessions (see [`crate::state`]).
+pub struct SqliteEventStore {
+    retention: Retention,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteEventStore {
+    /// Opens the database, creating it and its table if needed.
+    pub fn open(path: &Path, retention: Retention) -> rusqlite::Result<SqliteEventStore> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS events (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 session TEXT NOT NULL,
+                 time INTEGER NOT NULL,
+                 message TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS events_session ON events (session, id);",
+        )?;
+        Ok(SqliteEventStore {
+            retention,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn try_append(&self, session: &str, message: &str) -> rusqlite::Result<i64> {
+        let connection = self.connection.lock().unwrap();
+        let now = now();
+        connection.execute(
+            "INSERT INTO events (session, time, message) VALUES (?1, ?2, ?3)",
+            params![session, now, message],
+        )?;
+        let id = connection.last_insert_rowid();
+        let oldest = now.saturating_sub(self.retention.max_age.as_secs() as i64);
+        connection.execute("DELETE FROM events WHERE time < ?1", params![oldest])?;
+        connection.execute(
+            "DELETE FROM events WHERE session = ?1 AND id NOT IN
+                 (SELECT id FROM events WHERE session = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![session, self.retention.max_events as i64],
+        )?;
+        Ok(id)
+    }
+
+    fn try_after(&self, session: &str, last: i64) -> rusqlite::Result<Vec<(String, String)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, message FROM events WHERE session = ?1 AND id > ?2 ORDER BY id")?;
+        let events = statement.query_map(params![session, last], |row| {
+            Ok((row.get::<_, i64>(0)?.to_string(), row.get(1)?))
+        })?;
+        events.collect()
+    }
+}
+
+impl EventStore for SqliteEventStore {
+    fn append(&self, session: &str, message: &str) -> Option<String> {
+        match self.try_append(session, message) {
+            Ok(id) => Some(id.to_string()),
+            Err(e) => {
+                tracing::error!("Cannot store event: {}", e);
+                None
+            }
+        }
+    }
+
+    fn after(&self, session: &str, last_event_id: &str) -> Vec<(String, String)> {
+        let Ok(last) = last_event_id.parse::<i64>() else {
+            return Vec::new();
+        };
+        self.try_after(session, last).unwrap_or_else(|e| {
+            tracing::error!("Cannot read events: {}", e);
+            Vec::new()
+        })
+    }
+
+    fn remove(&self, session: &str) {
+        let connection = self.connection.lock().unwrap();
+        if let Err(e) = connection.execute("DELETE FROM events WHERE session = ?1", [session]) {
+            tracing::error!("Cannot remove the events of session {}: {}", session, e);
+        }
+    }
+}
+
+/// Current time in seconds since the Unix epoch.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
@@ -0,0 +1,515 @@
+use crate::{
+    codec,
+    config::Config,
+    context::random_id,
+    protocol::ProtocolVersion,
+    server::Server,
+    transport::{
+        activation,
+        compression::{self, Encoding},
+        events::{EventStore, MemoryEventStore, Retention, SqliteEventStore},
+        ChannelWriter, Client,
+    },
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Read},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Request, Response, StatusCode};
+
+/// Path of the Streamable HTTP endpoint.
+pub(crate) const MCP_PATH: &str = "/mcp";
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+/// Header in which clients on 2025-06-18 and later repeat the negotiated protocol version.
+const VERSION_HEADER: &str = "MCP-Protocol-Version";
+/// Header of a `GET` asking for the SSE events missed after the given one.
+const LAST_EVENT_HEADER: &str = "Last-Event-ID";
+/// Path of the Prometheus metrics, see [`crate::metrics`].
+#[cfg(any(feature = "metrics", feature = "tower"))]
+pub(crate) const METRICS_PATH: &str = "/metrics";
+
+/// One client session, fed by POST requests carrying the same `Mcp-Session-Id`.
+struct HttpSession {
+    /// Incoming messages; dropping it ends the session
+    input: Sender<String>,
+    /// Outgoing messages; locked by the POST waiting for its response
+    output: Mutex<Receiver<String>>,
+    /// API key the session was started with, which its other requests must send too
+    api_key: Option<String>,
+}
+
+/// The Streamable HTTP endpoint, whichever HTTP server receives its requests: the sessions and
+/// the events of their streams. Handling a request blocks until the server answers it.
+pub(crate) struct Endpoint {
+    server: Arc<Server>,
+    sessions: Mutex<HashMap<String, Arc<HttpSession>>>,
+    events: Arc<dyn EventStore>,
+}
+
+/// A request to the endpoint.
+pub(crate) struct EndpointRequest<'a> {
+    /// Method, in capitals
+    pub method: &'a str,
+    /// Path, without the query
+    pub path: &'a str,
+    pub headers: Vec<(String, String)>,
+    /// Length announced by the client, if any
+    pub body_length: Option<usize>,
+    pub body: &'a mut dyn Read,
+}
+
+/// The answer to a request.
+pub(crate) struct EndpointResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl EndpointResponse {
+    pub fn empty(status: u16) -> EndpointResponse {
+        EndpointResponse {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn text(status: u16, content_type: &str, body: String) -> EndpointResponse {
+        EndpointResponse {
+            body: body.into_bytes(),
+            ..EndpointResponse::empty(status).with_header("Content-Type", content_type)
+        }
+    }
+
+    /// The response with its body compressed with `encoding`, unless it is too small to be
+    /// worth it.
+    fn compressed(mut self, encoding: Encoding) -> EndpointResponse {
+        match compression::compress(&self.body, encoding) {
+            Some(body) => {
+                self.body = body;
+                self.with_header("Content-Encoding", encoding.name())
+            }
+            None => self,
+        }
+    }
+
+    fn with_header(mut self, name: &'static str, value: impl Into<String>) -> EndpointResponse {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Answers 413 and closes the connection, so the rest of the body isn't read.
+    pub fn too_large() -> EndpointResponse {
+        EndpointResponse::empty(413).with_header("Connection", "close")
+    }
+}
+
+/// Serves the Streamable HTTP transport on `listen`: each POST to `/mcp` carries one JSON-RPC
+/// message, and responses are returned either as JSON or as an SSE stream that also carries
+/// the notifications produced while serving the request. The events of the SSE streams can be
+/// fetched again with a `GET` carrying `Last-Event-ID`. A socket passed by systemd is used
+/// instead of `listen` (see [`activation`]). With `http2`, it is served over HTTP/2 as well, see
+/// [`http2`](super::http2).
+/// See https://spec.modelcontextprotocol.io/specification/2025-03-26/basic/transports/#streamable-http
+pub fn serve(server: Arc<Server>, listen: &str) -> io::Result<()> {
+    #[cfg(feature = "http2")]
+    if server.config().http2 {
+        return super::http2::serve(server, listen);
+    }
+    let http = match activation::take_listener()? {
+        Some(listener) => {
+            tracing::info!(
+                "Listening for Streamable HTTP on {}{}, passed by systemd",
+                listener,
+                MCP_PATH
+            );
+            tiny_http::Server::from_listener(listener, None)
+        }
+        None => {
+            tracing::info!(
+                "Listening for Streamable HTTP on http://{}{}",
+                listen,
+                MCP_PATH
+            );
+            tiny_http::Server::http(listen)
+        }
+    }
+    .map_err(io::Error::other)?;
+    let endpoint = Arc::new(Endpoint::new(server));
+    for request in http.incoming_requests() {
+        let endpoint = endpoint.clone();
+        thread::spawn(move || handle_request(&endpoint, request));
+    }
+    Ok(())
+}
+
+/// The SQLite database of `event_store`, or memory if there's none or it can't be opened.
+fn event_store(config: &Config) -> Arc<dyn EventStore> {
+    let retention = Retention {
+        max_age: Duration::from_secs(config.event_retention_secs),
+        max_events: config.event_retention_max,
+    };
+    if let Some(path) = &config.event_store {
+        match SqliteEventStore::open(path, retention) {
+            Ok(store) => return Arc::new(store),
+            Err(e) => tracing::error!(
+                "Cannot open event store {}, keeping events in memory: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    Arc::new(MemoryEventStore::new(retention))
+}
+
+/// Serves only `/metrics` on `listen`, for servers using another transport.
+#[cfg(feature = "metrics")]
+pub fn serve_metrics(server: Arc<Server>, listen: &str) -> io::Result<()> {
+    let http = tiny_http::Server::http(listen).map_err(io::Error::other)?;
+    tracing::info!("Serving metrics on http://{}{}", listen, METRICS_PATH);
+    thread::spawn(move || {
+        for request in http.incoming_requests() {
+            let response = match request.url().split('?').next() == Some(METRICS_PATH) {
+                true => metrics(&server, request.method().as_str()),
+                false => EndpointResponse::empty(404),
+            };
+            respond(request, response);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+fn metrics(server: &Server, method: &str) -> EndpointResponse {
+    if method != "GET" {
+        return EndpointResponse::empty(405);
+    }
+    EndpointResponse::text(
+        200,
+        "text/plain; version=0.0.4",
+        server.metrics().to_prometheus(),
+    )
+}
+
+/// Hands a request received by tiny_http to the endpoint.
+fn handle_request(endpoint: &Endpoint, mut request: Request) {
+    let method = request.method().as_str().to_string();
+    let url = request.url().to_string();
+    let headers = request
+        .headers()
+        .iter()
+        .map(|h| (h.field.as_str().to_string(), h.value.to_string()))
+        .collect();
+    let body_length = request.body_length();
+    let response = endpoint.handle(EndpointRequest {
+        method: &method,
+        path: url.split('?').next().unwrap_or_default(),
+        headers,
+        body_length,
+        body: request.as_reader(),
+    });
+    respond(request, response);
+}
+
+impl Endpoint {
+    pub fn new(server: Arc<Server>) -> Endpoint {
+        let events = event_store(&server.config());
+        Endpoint {
+            server,
+            sessions: Mutex::default(),
+            events,
+        }
+    }
+
+    #[cfg(feature = "tower")]
+    pub fn server(&self) -> &Arc<Server> {
+        &self.server
+    }
+
+    /// True if requests to `path` are the endpoint's.
+    #[cfg(feature = "tower")]
+    pub fn handles(&self, path: &str) -> bool {
+        path == MCP_PATH
+            || (cfg!(feature = "metrics") && path == METRICS_PATH && self.server.config().metrics)
+    }
+
+    /// Answers `request`, compressing the response when the client accepts it and
+    /// `http_compression` is on.
+    pub fn handle(&self, request: EndpointRequest) -> EndpointResponse {
+        let encoding = request
+            .header("Accept-Encoding")
+            .filter(|_| self.server.config().http_compression)
+            .and_then(|accepted| compression::negotiate(&accepted));
+        let response = self.route(request);
+        match encoding {
+            Some(encoding) if !response.body.is_empty() => response
+                .compressed(encoding)
+                .with_header("Vary", "Accept-Encoding"),
+            _ => response,
+        }
+    }
+
+    fn route(&self, request: EndpointRequest) -> EndpointResponse {
+        #[cfg(feature = "metrics")]
+        if request.path == METRICS_PATH && self.server.config().metrics {
+            return metrics(&self.server, request.method);
+        }
+        if request.path != MCP_PATH {
+            return EndpointResponse::empty(404);
+        }
+        let client = Client::authenticate(&self.server.config(), |name| request.header(name));
+        if client == Client::Refused {
+            tracing::error!("Refused an HTTP request without a known API key");
+            return EndpointResponse::empty(401).with_header("WWW-Authenticate", "Bearer");
+        }
+        let session_id = request.header(SESSION_HEADER);
+        if let Some(version) = request.header(VERSION_HEADER) {
+            if ProtocolVersion::parse(&version).is_none() {
+                tracing::error!("Unsupported {} header: {}", VERSION_HEADER, version);
+                let message = format!("Unsupported protocol version '{}'", version);
+                return EndpointResponse::text(400, "text/plain; charset=UTF-8", message);
+            }
+        }
+        match request.method {
+            "POST" => self.post(session_id, &client, request),
+            "GET" => self.replay(session_id, &client, &request),
+            "DELETE" => {
+                let removed = session_id.filter(|id| {
+                    let mut sessions = self.sessions.lock().unwrap();
+                    let owned = sessions.get(id).is_some_and(|s| s.belongs_to(&client));
+                    owned && sessions.remove(id).is_some()
+                });
+                if let Some(id) = &removed {
+                    self.events.remove(id);
+                }
+                EndpointResponse::empty(if removed.is_some() { 200 } else { 404 })
+            }
+            _ => EndpointResponse::empty(405),
+        }
+    }
+
+    fn post(
+        &self,
+        session_id: Option<String>,
+        client: &Client,
+        request: EndpointRequest,
+    ) -> EndpointResponse {
+        let max_size = self.server.limits().max_size;
+        if request.body_length.is_some_and(|length| length > max_size) {
+            tracing::error!("Request body over the limit of {} bytes", max_size);
+            return EndpointResponse::too_large();
+        }
+        let content_encoding = request.header("Content-Encoding");
+        let decoder = match compression::decoder(content_encoding.as_deref(), request.body) {
+            Ok(decoder) => decoder,
+            Err(encoding) => {
+                tracing::error!("Unsupported request Content-Encoding: {}", encoding);
+                return EndpointResponse::empty(415)
+                    .with_header("Accept-Encoding", "gzip, deflate");
+            }
+        };
+        // The limit applies to the decompressed body, whatever the size of the compressed one
+        let mut body = String::new();
+        let mut reader = decoder.take(max_size as u64 + 1);
+        if let Err(e) = reader.read_to_string(&mut body) {
+            tracing::error!("Error reading request body: {}", e);
+            return EndpointResponse::empty(400);
+        }
+        drop(reader);
+        if body.len() > max_size {
+            tracing::error!("Request body over the limit of {} bytes", max_size);
+            return EndpointResponse::too_large();
+        }
+        let message = match codec::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::error!("Error parsing request body: {}", e);
+                return EndpointResponse::empty(400);
+            }
+        };
+
+        let is_initialize = message.get("method").and_then(Value::as_str) == Some("initialize");
+        let (session_id, session) = match session_id {
+            Some(id) => match self.find_session(&id, client) {
+                Some(session) => (id, session),
+                None => return EndpointResponse::empty(404),
+            },
+            None if is_initialize => {
+                let id = random_id();
+                let session = start_session(self.server.clone(), id.clone(), client);
+                self.sessions
+                    .lock()
+                    .unwrap()
+                    .insert(id.clone(), session.clone());
+                (id, session)
+            }
+            None => return EndpointResponse::empty(400),
+        };
+
+        let request_id = match (message.get("id"), message.get("method")) {
+            (Some(id), Some(_)) => id.clone(),
+            _ => {
+                // Notifications and responses from the client don't get a reply
+                let _ = session.input.send(body);
+                return EndpointResponse::empty(202);
+            }
+        };
+
+        let output = session.output.lock().unwrap();
+        if session.input.send(body).is_err() {
+            return EndpointResponse::empty(404);
+        }
+        let wants_sse = request
+            .header("Accept")
+            .is_some_and(|a| a.contains("text/event-stream"));
+        let mut stream = String::new();
+        let mut response = None;
+        for line in output.iter() {
+            let is_response = serde_json::from_str::<Value>(&line).is_ok_and(|m| {
+                m.get("id") == Some(&request_id)
+                    && (m.get("result").is_some() || m.get("error").is_some())
+            });
+            if is_response {
+                response = Some(line);
+                break;
+            }
+            if wants_sse {
+                stream.push_str(&sse_event(self.events.append(&session_id, &line), &line));
+            }
+        }
+        drop(output);
+
+        let Some(response) = response else {
+            return EndpointResponse::empty(500);
+        };
+        let response = if wants_sse {
+            stream.push_str(&sse_event(
+                self.events.append(&session_id, &response),
+                &response,
+            ));
+            EndpointResponse::text(200, "text/event-stream", stream)
+        } else {
+            EndpointResponse::text(200, "application/json", response)
+        };
+        response.with_header(SESSION_HEADER, session_id)
+    }
+
+    /// Answers a `GET` with the events of the session sent after `Last-Event-ID`. Without it,
+    /// the client is asking for a stream of its own, which isn't offered.
+    fn replay(
+        &self,
+        session_id: Option<String>,
+        client: &Client,
+        request: &EndpointRequest,
+    ) -> EndpointResponse {
+        let Some(last_event_id) = request.header(LAST_EVENT_HEADER) else {
+            return EndpointResponse::empty(405);
+        };
+        let Some(session_id) = session_id else {
+            return EndpointResponse::empty(400);
+        };
+        if self.find_session(&session_id, client).is_none() {
+            return EndpointResponse::empty(404);
+        }
+        let missed = self.events.after(&session_id, &last_event_id);
+        tracing::info!(
+            "Replaying {} events after {} to HTTP session {}",
+            missed.len(),
+            last_event_id,
+            session_id
+        );
+        let stream: String = missed
+            .into_iter()
+            .map(|(id, message)| sse_event(Some(id), &message))
+            .collect();
+        EndpointResponse::text(200, "text/event-stream", stream)
+            .with_header(SESSION_HEADER, session_id)
+    }
+
+    /// The running session `id`, or the one saved under `id` before a restart, which is
+    /// resumed. Sessions started with another API key than the client's are not found.
+    fn find_session(&self, id: &str, client: &Client) -> Option<Arc<HttpSession>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get(id) {
+            return session.belongs_to(client).then(|| session.clone());
+        }
+        if !self.server.has_saved_session(id) {
+            return None;
+        }
+        let session = start_session(self.server.clone(), id.to_string(), client);
+        sessions.insert(id.to_string(), session.clone());
+        Some(session)
+    }
+}
+
+impl EndpointRequest<'_> {
+    /// Value of the header `name`, whatever its case.
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+}
+
+/// An SSE `message` event, with its id when it was kept.
+fn sse_event(id: Option<String>, data: &str) -> String {
+    match id {
+        Some(id) => format!("id: {}\nevent: message\ndata: {}\n\n", id, data),
+        None => format!("event: message\ndata: {}\n\n", data),
+    }
+}
+
+impl HttpSession {
+    /// True if `client` sent the API key the session was started with.
+    fn belongs_to(&self, client: &Client) -> bool {
+        match client {
+            Client::Authenticated { key, .. } => self.api_key.as_ref() == Some(key),
+            _ => self.api_key.is_none(),
+        }
+    }
+}
+
+/// Starts the dispatcher thread of the session `id`, resuming it if it was saved before the
+/// server started. Authenticated clients only get what their scopes grant.
+fn start_session(server: Arc<Server>, id: String, client: &Client) -> Arc<HttpSession> {
+    let (input, lines) = mpsc::channel::<String>();
+    let (output_tx, output) = mpsc::channel::<String>();
+    tracing::info!("Started HTTP session {}", id);
+    let out = ChannelWriter::new(output_tx);
+    let api_key = match client.clone() {
+        Client::Authenticated { key, scopes } => {
+            thread::spawn(move || server.serve_authenticated(Some(&id), scopes, lines, out));
+            Some(key)
+        }
+        _ => {
+            thread::spawn(move || server.serve_resumable(&id, lines, out));
+            None
+        }
+    };
+    Arc::new(HttpSession {
+        input,
+        output: Mutex::new(output),
+        api_key,
+    })
+}
+
+fn respond(request: Request, response: EndpointResponse) {
+    let status = StatusCode(response.status);
+    let length = response.body.len();
+    let body = Cursor::new(response.body);
+    let mut tiny = Response::new(status, Vec::new(), body, Some(length), None);
+    for (name, value) in response.headers {
+        tiny.add_header(Header::from_bytes(name, value).unwrap());
+    }
+    if let Err(e) = request.respond(tiny) {
+        tracing::error!("Error sending HTTP {} response: {}", status.0, e);
+    }
+}
@@ -0,0 +1,85 @@
+//! HTTP/2 for the Streamable HTTP transport: with `http2`, the endpoint is served by hyper,
+//! through [`McpService`], which speaks HTTP/1.1 and cleartext HTTP/2 on the same socket, telling
+//! them apart by the HTTP/2 preface. A client multiplexes all its POSTs and SSE streams over one
+//! connection instead of opening one connection for each request waiting for its answer.
+//! Enabled by the `http2` feature.
+
+use crate::{
+    server::Server,
+    transport::{
+        activation::{self, Listener},
+        http::MCP_PATH,
+        service::McpService,
+    },
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use std::{io, sync::Arc};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Serves the Streamable HTTP endpoint over HTTP/1.1 and HTTP/2 on `listen`, or on the socket
+/// passed by systemd.
+pub fn serve(server: Arc<Server>, listen: &str) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_io()
+        .build()?;
+    let service = McpService::new(server);
+    let listener = activation::take_listener()?;
+    match &listener {
+        Some(listener) => tracing::info!(
+            "Listening for Streamable HTTP (HTTP/2) on {}{}, passed by systemd",
+            listener,
+            MCP_PATH
+        ),
+        None => tracing::info!(
+            "Listening for Streamable HTTP (HTTP/2) on http://{}{}",
+            listen,
+            MCP_PATH
+        ),
+    }
+    runtime.block_on(async move {
+        match listener {
+            Some(Listener::Tcp(listener)) => {
+                listener.set_nonblocking(true)?;
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    tokio::spawn(serve_connection(stream, service.clone()));
+                }
+            }
+            #[cfg(unix)]
+            Some(Listener::Unix(listener)) => {
+                listener.set_nonblocking(true)?;
+                let listener = tokio::net::UnixListener::from_std(listener)?;
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    tokio::spawn(serve_connection(stream, service.clone()));
+                }
+            }
+            None => {
+                let listener = tokio::net::TcpListener::bind(listen).await?;
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    tokio::spawn(serve_connection(stream, service.clone()));
+                }
+            }
+        }
+    })
+}
+
+/// Serves the requests of one connection, in whichever HTTP version the client speaks.
+async fn serve_connection<S>(stream: S, service: McpService)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = TowerToHyperService::new(service);
+    if let Err(e) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+    {
+        tracing::debug!("HTTP connection closed: {}", e);
+    }
+}
@@ -0,0 +1,120 @@
+//! mDNS (zeroconf) advertisement of the network transports: the server announces itself on the
+//! local network as a `_mcp._tcp` service, so that clients and development tools find it
+//! without being told its address. The TXT record of the service says how to connect and sums
+//! up what the server offers. Enabled by the `mdns` feature.
+
+#[cfg(feature = "http")]
+use crate::transport::http::MCP_PATH;
+use crate::{
+    config::{Config, Transport},
+    protocol::ProtocolVersion,
+    server::Server,
+};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+};
+
+/// Service type of MCP servers.
+const SERVICE_TYPE: &str = "_mcp._tcp.local.";
+
+/// The service announced on the local network, withdrawn when dropped.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+/// Announces the transport `server` listens on, as `config` describes it. The instance is named
+/// after `serverInfo.name`, and the TXT record has the `transport`, the `path` of the HTTP
+/// endpoint, the `version` of the server, the latest `protocol` version it speaks and its
+/// `capabilities`, comma-separated.
+pub fn advertise(server: &Server, config: &Config) -> io::Result<Advertisement> {
+    if config.transport == Transport::Stdio {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the stdio transport cannot be advertised",
+        ));
+    }
+    let address = listen_address(&config.listen)?;
+    if address.ip().is_loopback() {
+        tracing::warn!(
+            "Advertising {} over mDNS, which only local clients can reach",
+            address
+        );
+    }
+    let host = sysinfo::System::host_name().unwrap_or_else(|| "localhost".to_string());
+    let capabilities = server.capabilities();
+    let capabilities = capabilities
+        .as_object()
+        .map(|capabilities| capabilities.keys().cloned().collect::<Vec<_>>().join(","))
+        .unwrap_or_default();
+    let transport = config.transport.to_string();
+    let mut properties = vec![
+        ("transport", transport.as_str()),
+        ("version", server.info().version.as_str()),
+        ("protocol", ProtocolVersion::LATEST.as_str()),
+        ("capabilities", capabilities.as_str()),
+    ];
+    properties.extend(endpoint_path(config.transport).map(|path| ("path", path)));
+    // Without an address of its own, the daemon announces those of every interface
+    let addresses: Vec<IpAddr> = Some(address.ip())
+        .filter(|ip| !ip.is_unspecified())
+        .into_iter()
+        .collect();
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        &server.info().name,
+        &format!("{}.local.", host),
+        &addresses[..],
+        address.port(),
+        &properties[..],
+    )
+    .map_err(io::Error::other)?;
+    let service = match addresses.is_empty() {
+        true => service.enable_addr_auto(),
+        false => service,
+    };
+    let fullname = service.get_fullname().to_string();
+    let daemon = ServiceDaemon::new().map_err(io::Error::other)?;
+    daemon.register(service).map_err(io::Error::other)?;
+    tracing::info!(
+        "Advertising {} over mDNS on port {}",
+        fullname,
+        address.port()
+    );
+    Ok(Advertisement { daemon, fullname })
+}
+
+/// Path of the MCP endpoint of `transport`, for those serving other paths too.
+fn endpoint_path(transport: Transport) -> Option<&'static str> {
+    match transport {
+        #[cfg(feature = "http")]
+        Transport::Http => Some(MCP_PATH),
+        _ => None,
+    }
+}
+
+/// The address `listen` (`host:port`) stands for, the first one if it resolves to several.
+fn listen_address(listen: &str) -> io::Result<SocketAddr> {
+    listen.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} resolves to no address", listen),
+        )
+    })
+}
+
+impl Advertisement {
+    /// Name of the service instance, e.g. `mcp_rs_test._mcp._tcp.local.`.
+    pub fn fullname(&self) -> &str {
+        &self.fullname
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
@@ -0,0 +1,106 @@
+//! Network transports. Each one turns a client connection into the line stream consumed by
+//! [`Server::serve`](crate::server::Server::serve).
+
+#[cfg(any(feature = "http", feature = "ws"))]
+pub mod activation;
+#[cfg(feature = "http")]
+pub mod compression;
+#[cfg(feature = "http")]
+pub mod events;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "http2")]
+pub mod http2;
+#[cfg(all(feature = "mdns", any(feature = "http", feature = "ws")))]
+pub mod mdns;
+#[cfg(feature = "axum")]
+pub mod router;
+#[cfg(feature = "tower")]
+pub mod service;
+#[cfg(feature = "ws")]
+pub mod ws;
+
+#[cfg(any(feature = "http", feature = "ws"))]
+use crate::config::Config;
+use std::{
+    io::{self, Write},
+    sync::mpsc::Sender,
+};
+
+/// Who a client connecting over the network is, going by its API key.
+#[cfg(any(feature = "http", feature = "ws"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Client {
+    /// No `api_keys` are set: anyone gets everything
+    Anonymous,
+    /// The client sent one of the `api_keys`
+    Authenticated { key: String, scopes: Vec<String> },
+    /// The client sent no key, or an unknown one
+    Refused,
+}
+
+#[cfg(any(feature = "http", feature = "ws"))]
+impl Client {
+    /// Authenticates a client by the key it sent as `Authorization: Bearer <key>` or
+    /// `X-API-Key`, `header` returning the value of a header whatever its case.
+    pub(crate) fn authenticate(config: &Config, header: impl Fn(&str) -> Option<String>) -> Client {
+        if config.api_keys.is_empty() {
+            return Client::Anonymous;
+        }
+        let bearer = header("Authorization").and_then(|value| {
+            let (scheme, key) = value.split_once(' ')?;
+            scheme
+                .eq_ignore_ascii_case("Bearer")
+                .then(|| key.trim().to_string())
+        });
+        let Some(key) = bearer.or_else(|| header("X-API-Key")) else {
+            return Client::Refused;
+        };
+        match config.api_keys.get(&key) {
+            Some(scopes) => Client::Authenticated {
+                scopes: scopes.clone(),
+                key,
+            },
+            None => Client::Refused,
+        }
+    }
+}
+
+/// Writer that splits what the session writer produces into lines and forwards each complete
+/// line over a channel, so a transport can frame it however it needs to.
+pub struct ChannelWriter {
+    buffer: Vec<u8>,
+    lines: Sender<String>,
+}
+
+impl ChannelWriter {
+    pub fn new(lines: Sender<String>) -> ChannelWriter {
+        ChannelWriter {
+            buffer: Vec::new(),
+            lines,
+        }
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|b| *b == b'\n') {
+            match chunk.strip_suffix(b"\n") {
+                Some(end) => {
+                    self.buffer.extend_from_slice(end);
+                    let line = String::from_utf8_lossy(&self.buffer).into_owned();
+                    self.buffer.clear();
+                    self.lines
+                        .send(line)
+                        .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+                }
+                None => self.buffer.extend_from_slice(chunk),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
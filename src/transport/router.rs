@@ -0,0 +1,145 @@
+//! An axum [`Router`] serving MCP, for web services adding it next to their own routes:
+//! `/mcp` is the Streamable HTTP endpoint (see [`McpService`]), and `/sse` with `/messages` the
+//! HTTP+SSE transport of the 2024-11-05 revision, for clients that don't speak Streamable HTTP
+//! yet. Enabled by the `axum` feature.
+//! See https://spec.modelcontextprotocol.io/specification/2024-11-05/basic/transports/#http-with-sse
+
+use crate::{
+    context::random_id,
+    server::Server,
+    transport::{
+        http::{MCP_PATH, METRICS_PATH},
+        service::McpService,
+        ChannelWriter,
+    },
+};
+use axum::{
+    extract::{DefaultBodyLimit, OriginalUri, Query, State},
+    http::StatusCode,
+    response::sse::{Event, Sse},
+    routing::{get, post},
+    Router,
+};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Path of the SSE stream of the HTTP+SSE transport.
+const SSE_PATH: &str = "/sse";
+/// Path the clients of the HTTP+SSE transport post their messages to.
+const MESSAGES_PATH: &str = "/messages";
+/// How often an idle stream checks whether its client went away.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Events waiting to be sent on a stream before the session waits for the client.
+const STREAM_BUFFER: usize = 64;
+
+/// Sessions of the HTTP+SSE transport: the input of each of them, by session id.
+struct SseSessions {
+    server: Arc<Server>,
+    inputs: Mutex<HashMap<String, Sender<String>>>,
+}
+
+#[derive(Deserialize)]
+struct MessageQuery {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+}
+
+/// Routes serving `server`: `/mcp` (and `/metrics` when enabled), `/sse` and `/messages`.
+/// Merge them into the application's router, or nest them under a prefix.
+pub fn mcp_router(server: Arc<Server>) -> Router {
+    let max_size = server.limits().max_size;
+    let mcp = McpService::new(server.clone());
+    let sessions = Arc::new(SseSessions {
+        server,
+        inputs: Mutex::default(),
+    });
+    Router::new()
+        .route_service(MCP_PATH, mcp.clone())
+        .route_service(METRICS_PATH, mcp)
+        .route(SSE_PATH, get(open_stream))
+        .route(
+            MESSAGES_PATH,
+            post(post_message).layer(DefaultBodyLimit::max(max_size)),
+        )
+        .with_state(sessions)
+}
+
+/// Starts a session, whose messages are sent on the returned stream after an `endpoint` event
+/// telling the client where to post its own.
+async fn open_stream(
+    State(sessions): State<Arc<SseSessions>>,
+    OriginalUri(uri): OriginalUri,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let id = random_id();
+    let (input, lines) = mpsc::channel::<String>();
+    let (output_tx, output) = mpsc::channel::<String>();
+    sessions.inputs.lock().unwrap().insert(id.clone(), input);
+    let server = sessions.server.clone();
+    thread::spawn(move || server.serve(lines, ChannelWriter::new(output_tx)));
+    tracing::info!("Started HTTP+SSE session {}", id);
+
+    // Relative to where the routes are mounted
+    let prefix = uri.path().strip_suffix(SSE_PATH).unwrap_or_default();
+    let endpoint = format!("{}{}?sessionId={}", prefix, MESSAGES_PATH, id);
+    let (events, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+    let _ = events.try_send(Event::default().event("endpoint").data(endpoint));
+    thread::spawn(move || forward(&sessions, &id, output, events));
+    Sse::new(stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok(event), receiver))
+    }))
+}
+
+/// Sends the messages of the session `id` on its stream until the client goes away, which
+/// ends the session.
+fn forward(
+    sessions: &SseSessions,
+    id: &str,
+    output: Receiver<String>,
+    events: tokio::sync::mpsc::Sender<Event>,
+) {
+    loop {
+        match output.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => {
+                if events
+                    .blocking_send(Event::default().event("message").data(line))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if events.is_closed() => break,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    // Dropping the input ends the session
+    sessions.inputs.lock().unwrap().remove(id);
+    tracing::info!("Ended HTTP+SSE session {}", id);
+}
+
+/// Hands a message to its session, which answers on the stream.
+async fn post_message(
+    State(sessions): State<Arc<SseSessions>>,
+    Query(query): Query<MessageQuery>,
+    body: String,
+) -> StatusCode {
+    let Some(id) = query.session_id else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let input = sessions.inputs.lock().unwrap().get(&id).cloned();
+    match input {
+        Some(input) if input.send(body).is_ok() => StatusCode::ACCEPTED,
+        _ => StatusCode::NOT_FOUND,
+    }
+}
@@ -0,0 +1,177 @@
+//! The Streamable HTTP endpoint as a [`tower::Service`], for applications that already run an
+//! HTTP server (axum, hyper...) and want to serve MCP next to their other routes instead of
+//! leaving the listener to [`http::serve`](super::http::serve). Enabled by the `tower` feature.
+//!
+//! The services must run in a Tokio runtime: each request is handled on a blocking thread,
+//! since the endpoint waits for the server's answer.
+
+use crate::{
+    server::Server,
+    transport::http::{Endpoint, EndpointRequest, EndpointResponse},
+};
+use bytes::{Buf, Bytes};
+use http::{HeaderValue, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::{BodyExt, Either, Full, LengthLimitError, Limited};
+use std::{
+    convert::Infallible,
+    error::Error,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Service answering the requests of the MCP endpoint, `/mcp` (and `/metrics` when enabled),
+/// and 404 to the others.
+#[derive(Clone)]
+pub struct McpService {
+    endpoint: Arc<Endpoint>,
+}
+
+impl McpService {
+    pub fn new(server: Arc<Server>) -> McpService {
+        McpService {
+            endpoint: Arc::new(Endpoint::new(server)),
+        }
+    }
+}
+
+impl<B> Service<Request<B>> for McpService
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = BoxFuture<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let response = handle(self.endpoint.clone(), request);
+        Box::pin(async move { Ok(response.await) })
+    }
+}
+
+/// Layer putting the MCP endpoint in front of a service, which gets the requests to other
+/// paths.
+#[derive(Clone)]
+pub struct McpLayer {
+    service: McpService,
+}
+
+impl McpLayer {
+    pub fn new(server: Arc<Server>) -> McpLayer {
+        McpLayer {
+            service: McpService::new(server),
+        }
+    }
+}
+
+impl<S> Layer<S> for McpLayer {
+    type Service = WithMcp<S>;
+
+    fn layer(&self, inner: S) -> WithMcp<S> {
+        WithMcp {
+            mcp: self.service.clone(),
+            inner,
+        }
+    }
+}
+
+/// Service made by [`McpLayer`]: answers the requests of the MCP endpoint, passing the
+/// others to `inner`.
+#[derive(Clone)]
+pub struct WithMcp<S> {
+    mcp: McpService,
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for WithMcp<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Response = Response<Either<Full<Bytes>, ResBody>>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        if self.mcp.endpoint.handles(request.uri().path()) {
+            let response = handle(self.mcp.endpoint.clone(), request);
+            return Box::pin(async move { Ok(response.await.map(Either::Left)) });
+        }
+        let response = self.inner.call(request);
+        Box::pin(async move { Ok(response.await?.map(Either::Right)) })
+    }
+}
+
+/// Reads the body of `request` and has the endpoint answer it on a blocking thread.
+async fn handle<B>(endpoint: Arc<Endpoint>, request: Request<B>) -> Response<Full<Bytes>>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let (parts, body) = request.into_parts();
+    let max_size = endpoint.server().limits().max_size;
+    // One byte over the limit tells a body at the limit from a larger one
+    let body = match Limited::new(body, max_size + 1).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => {
+            tracing::error!("Request body over the limit of {} bytes", max_size);
+            return into_response(EndpointResponse::too_large());
+        }
+        Err(e) => {
+            tracing::error!("Error reading request body: {}", e);
+            return into_response(EndpointResponse::empty(400));
+        }
+    };
+    let response = tokio::task::spawn_blocking(move || {
+        let headers = parts
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        endpoint.handle(EndpointRequest {
+            method: parts.method.as_str(),
+            path: parts.uri.path(),
+            headers,
+            body_length: Some(body.len()),
+            body: &mut body.reader(),
+        })
+    })
+    .await
+    .unwrap_or_else(|e| {
+        tracing::error!("Error handling HTTP request: {}", e);
+        EndpointResponse::empty(500)
+    });
+    into_response(response)
+}
+
+fn into_response(response: EndpointResponse) -> Response<Full<Bytes>> {
+    let mut http = Response::new(Full::new(Bytes::from(response.body)));
+    *http.status_mut() =
+        StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    for (name, value) in response.headers {
+        if let Ok(value) = HeaderValue::try_from(value) {
+            http.headers_mut().append(name, value);
+        }
+    }
+    http
+}
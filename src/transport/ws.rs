@@ -0,0 +1,141 @@
+use crate::{
+    server::Server,
+    transport::{activation, ChannelWriter, Client},
+};
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    protocol::WebSocketConfig,
+    Error, Message,
+};
+
+/// How long a connection waits for an incoming frame before checking for outgoing messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Accepts WebSocket connections on `listen`, serving one session per connection.
+/// Each text frame carries one JSON-RPC message. With `api_keys`, the handshake must carry one
+/// of the keys, as for the Streamable HTTP transport. A TCP socket passed by systemd is used instead
+/// of `listen` (see [`activation`]).
+pub fn serve(server: Arc<Server>, listen: &str) -> io::Result<()> {
+    let listener = match activation::take_listener()? {
+        Some(activation::Listener::Tcp(listener)) => {
+            tracing::info!(
+                "Listening for WebSocket connections on ws://{}, passed by systemd",
+                listener.local_addr()?
+            );
+            listener
+        }
+        #[cfg(unix)]
+        Some(listener @ activation::Listener::Unix(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "the WebSocket transport needs a TCP socket, systemd passed {}",
+                    listener
+                ),
+            ))
+        }
+        None => {
+            let listener = TcpListener::bind(listen)?;
+            tracing::info!("Listening for WebSocket connections on ws://{}", listen);
+            listener
+        }
+    };
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let server = server.clone();
+                thread::spawn(move || handle_connection(server, stream));
+            }
+            Err(e) => tracing::error!("Error accepting connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(server: Arc<Server>, stream: TcpStream) {
+    let peer = stream
+        .peer_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+    // Larger messages fail the read, which closes the connection, before they are buffered
+    let max_size = server.limits().max_size;
+    let config = WebSocketConfig {
+        max_message_size: Some(max_size),
+        max_frame_size: Some(max_size),
+        ..WebSocketConfig::default()
+    };
+    let settings = server.config();
+    let mut client = Client::Anonymous;
+    // The error type is tungstenite's
+    #[allow(clippy::result_large_err)]
+    let authenticate = |request: &Request, response: Response| {
+        let header = |name: &str| {
+            let value = request.headers().get(name)?;
+            value.to_str().ok().map(str::to_string)
+        };
+        client = Client::authenticate(&settings, header);
+        if client == Client::Refused {
+            let mut refusal = ErrorResponse::new(Some("Unknown API key".to_string()));
+            *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+            return Err(refusal);
+        }
+        Ok(response)
+    };
+    let mut socket = match tungstenite::accept_hdr_with_config(stream, authenticate, Some(config)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::error!("WebSocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    tracing::info!("WebSocket client connected: {}", peer);
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        tracing::error!("Error configuring connection: {}", e);
+        return;
+    }
+
+    let (input, lines) = mpsc::channel::<String>();
+    let (output_tx, output) = mpsc::channel::<String>();
+    let out = ChannelWriter::new(output_tx);
+    let session = thread::spawn(move || match client {
+        Client::Authenticated { scopes, .. } => {
+            server.serve_authenticated(None, scopes, lines, out)
+        }
+        _ => server.serve(lines, out),
+    });
+
+    'connection: loop {
+        while let Ok(line) = output.try_recv() {
+            if let Err(e) = socket.send(Message::text(line)) {
+                tracing::error!("Error writing to {}: {}", peer, e);
+                break 'connection;
+            }
+        }
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let _ = input.send(text);
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            }
+            Err(e) => {
+                tracing::error!("Error reading from {}: {}", peer, e);
+                break;
+            }
+        }
+    }
+
+    drop(input);
+    let _ = session.join();
+    tracing::info!("WebSocket client disconnected: {}", peer);
+}
@@ -0,0 +1,80 @@
+//! Wire log: every message received and sent, logged under the `wire` target with the values
+//! of secret-looking keys, also in the JSON text of strings, and of long strings (file contents,
+//! ...) redacted.
+
+use crate::{builtin::env::matches, config::Config, transcript::Direction};
+use serde_json::Value;
+
+/// Replacement of the redacted values.
+const REDACTED: &str = "[REDACTED]";
+
+/// Redaction settings of a session's wire log, taken from the configuration when it starts.
+pub struct WireLog {
+    /// Patterns (`*` matching anything, case-insensitive) of the keys whose value is hidden
+    redact: Vec<String>,
+    /// Length in bytes above which strings are replaced by their length
+    max_string: usize,
+}
+
+impl WireLog {
+    /// The wire log described by `config`, if enabled.
+    pub fn from_config(config: &Config) -> Option<WireLog> {
+        config.wire_log.then(|| WireLog::redacting(config))
+    }
+
+    /// Redaction with the settings of `config`, whether the wire log is enabled or not.
+    pub fn redacting(config: &Config) -> WireLog {
+        WireLog {
+            redact: config.wire_log_redact.clone(),
+            max_string: config.wire_log_max_string,
+        }
+    }
+
+    /// Logs a line received or sent. Lines that aren't valid JSON are logged as their length,
+    /// since they can't be redacted.
+    pub fn log(&self, direction: Direction, line: &str) {
+        let arrow = match direction {
+            Direction::In => "<-",
+            Direction::Out => "->",
+        };
+        match serde_json::from_str::<Value>(line) {
+            Ok(message) => tracing::info!(target: "wire", "{} {}", arrow, self.redact(message)),
+            Err(_) => {
+                tracing::info!(target: "wire", "{} [{} bytes of invalid JSON]", arrow, line.len())
+            }
+        }
+    }
+
+    /// `value` with the values of the matching keys replaced by `[REDACTED]` and the long
+    /// strings by `[<length> bytes]`, at any depth, strings holding JSON (the text of structured
+    /// tool results) included.
+    pub fn redact(&self, value: Value) -> Value {
+        match value {
+            Value::String(text) if text.len() > self.max_string => {
+                Value::String(format!("[{} bytes]", text.len()))
+            }
+            Value::String(text) if text.starts_with(['{', '[']) => {
+                match serde_json::from_str::<Value>(&text) {
+                    Ok(embedded) => Value::String(self.redact(embedded).to_string()),
+                    Err(_) => Value::String(text),
+                }
+            }
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|item| self.redact(item)).collect())
+            }
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let value = match self.redact.iter().any(|p| matches(p, &key)) {
+                            true => Value::String(REDACTED.to_string()),
+                            false => self.redact(value),
+                        };
+                        (key, value)
+                    })
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use mcp_rs_test::{builtin, conformance, server::Server};
+
+#[test]
+fn empty_server_conforms() {
+    conformance::run(Server::new).unwrap();
+}
+
+#[test]
+fn server_with_builtins_conforms() {
+    conformance::run(|| {
+        Server::new()
+            .with_tool(builtin::calc::evaluate_tool())
+            .with_tool(builtin::time::time_tool())
+            .with_tool(builtin::encoding::uuid_tool())
+            .with_tool(builtin::encoding::hash_tool())
+            .with_prompt(builtin::prompts::code_review_prompt())
+            .with_resources(builtin::env::EnvResources)
+    })
+    .unwrap();
+}
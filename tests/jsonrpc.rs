@@ -0,0 +1,40 @@
+use mcp_rs_test::jsonrpc::{JsonRpcError, JsonRpcId, JsonRpcResponse};
+use serde_json::{json, Value};
+
+#[test]
+fn responses_have_either_a_result_or_an_error() {
+    let success = JsonRpcResponse::success(JsonRpcId::Number(1u64.into()), Value::Null);
+    assert_eq!(
+        serde_json::to_value(&success).unwrap(),
+        json!({ "id": 1, "jsonrpc": "2.0", "result": null })
+    );
+    let error = JsonRpcResponse::error(JsonRpcId::Null, JsonRpcError::new(-32700, "Parse error"));
+    assert_eq!(
+        serde_json::to_value(&error).unwrap(),
+        json!({
+            "id": null,
+            "jsonrpc": "2.0",
+            "error": { "code": -32700, "message": "Parse error", "data": null },
+        })
+    );
+}
+
+#[test]
+fn responses_without_exactly_one_of_result_and_error_are_rejected() {
+    let read = |response: Value| serde_json::from_value::<JsonRpcResponse>(response);
+    let response = read(json!({ "id": "a", "jsonrpc": "2.0", "result": null })).unwrap();
+    assert!(matches!(
+        response,
+        JsonRpcResponse::Success {
+            result: Value::Null,
+            ..
+        }
+    ));
+    assert_eq!(response.id(), &JsonRpcId::String("a".to_string()));
+    let error = json!({ "code": -32601, "message": "Method not found" });
+    let response = read(json!({ "id": 2, "jsonrpc": "2.0", "error": error })).unwrap();
+    assert!(matches!(response, JsonRpcResponse::Error { error, .. } if error.code == -32601));
+
+    assert!(read(json!({ "id": 3, "jsonrpc": "2.0" })).is_err());
+    assert!(read(json!({ "id": 4, "jsonrpc": "2.0", "result": {}, "error": error })).is_err());
+}
@@ -0,0 +1,1575 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, KeyInit, Mac};
+use mcp_rs_test::{
+    builtin,
+    config::{Config, PromptTemplate},
+    context::RequestContext,
+    crash::{Crash, CrashReporter},
+    errors,
+    extensions::Extension,
+    jsonrpc::{JsonRpcError, ERROR_CODE_METHOD_NOT_FOUND, ERROR_CODE_RATE_LIMITED},
+    mcp::{
+        CallToolResult, CreateMessageRequest, ReadResourceResult, ResourceContents, SamplingMessage,
+    },
+    plugins::{self, watch::ToolWatcher},
+    policy::{ApprovalMethod, Permission, ScopeGrant},
+    resources::{Resource, ResourceProvider, SchemeHandler},
+    server::Server,
+    testing::{Expected, MockClient},
+    tools::Tool,
+};
+use serde_json::json;
+use sha2::Sha256;
+use std::{
+    io::{Read, Seek},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+#[test]
+fn list_changed_is_sent_before_the_next_response() {
+    let server = Arc::new(Server::new().with_tool(builtin::calc::evaluate_tool()));
+    let mut client = MockClient::connect(server.clone());
+    client.initialize().unwrap();
+    client.expect_silence(Duration::from_millis(50)).unwrap();
+
+    server.register_tool(builtin::time::time_tool());
+    let id = client.send_request("tools/list", None).unwrap();
+    let response = client.expect_response(&id).unwrap();
+    assert_eq!(response["result"]["tools"].as_array().unwrap().len(), 2);
+    client
+        .expect_notification("notifications/tools/list_changed")
+        .unwrap();
+    client
+        .assert_order(
+            &Expected::Notification("notifications/tools/list_changed".to_string()),
+            &Expected::Response(id),
+        )
+        .unwrap();
+    client.close().unwrap();
+}
+
+#[test]
+fn raw_lines_and_chosen_ids() {
+    let mut client = MockClient::connect(Arc::new(Server::new()));
+    client.send_raw("not json").unwrap();
+    let response = client.receive().unwrap();
+    assert_eq!(response["error"]["code"], json!(-32700));
+    assert_eq!(response["id"], json!(null));
+    // Malformed notifications aren't answered, malformed requests are with their id
+    client.send_raw(r#"{"jsonrpc":"2.0","method":42}"#).unwrap();
+    client.expect_silence(Duration::from_millis(50)).unwrap();
+    client
+        .send_raw(r#"{"jsonrpc":"2.0","id":"bad","method":42}"#)
+        .unwrap();
+    let response = client.receive().unwrap();
+    assert_eq!(response["error"]["code"], json!(-32600));
+    assert_eq!(response["id"], json!("bad"));
+    let response = client
+        .request_with_id(json!("typed"), "ping", None)
+        .unwrap();
+    assert_eq!(response["result"], json!({}));
+}
+
+#[test]
+fn ids_are_sent_back_as_received() {
+    let mut client = MockClient::connect(Arc::new(Server::new()));
+    for id in [json!(-7), json!(2.5), json!(u64::MAX), json!(null)] {
+        let response = client.request_with_id(id.clone(), "ping", None).unwrap();
+        assert_eq!(response["id"], id);
+        assert_eq!(response["result"], json!({}));
+    }
+    // Sent back with the very text they were received with, which the parsed messages of the
+    // mock client don't keep
+    let ids = ["-0.125", "1.10", "1e2", "18446744073709551616"];
+    let lines = ids.map(|id| format!(r#"{{"jsonrpc":"2.0","id":{},"method":"ping"}}"#, id));
+    let mut out = tempfile::tempfile().unwrap();
+    Server::new()
+        .serve(lines, out.try_clone().unwrap())
+        .unwrap();
+    let mut written = String::new();
+    out.rewind().unwrap();
+    out.read_to_string(&mut written).unwrap();
+    assert_eq!(written.lines().count(), ids.len());
+    for (id, response) in ids.iter().zip(written.lines()) {
+        assert!(
+            response.contains(&format!(r#""id":{},"#, id)),
+            "{}",
+            response
+        );
+    }
+}
+
+#[test]
+fn ids_of_different_types_are_different_requests() {
+    let slow = Tool::new(
+        "slow",
+        "Slow",
+        json!({ "type": "object" }),
+        |_ctx, _arguments| {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(CallToolResult::text("done"))
+        },
+    );
+    let mut client = MockClient::connect(Arc::new(Server::new().with_tool(slow)));
+    client.initialize().unwrap();
+    let call = r#""method":"tools/call","params":{"name":"slow","arguments":{}}"#;
+    for id in ["1", r#""1""#] {
+        client
+            .send_raw(format!(r#"{{"jsonrpc":"2.0","id":{},{}}}"#, id, call))
+            .unwrap();
+    }
+    for id in [json!(1), json!("1")] {
+        let response = client.expect_response(&id).unwrap();
+        assert_eq!(response["result"]["content"][0]["text"], json!("done"));
+    }
+}
+
+#[test]
+fn extension_methods_are_announced_and_served() {
+    let extension = Extension::new("x-myco/")
+        .with_capability(json!({ "version": 1 }))
+        .with_method("echo", |_, params| Ok(json!({ "params": params })));
+    let server = Server::new()
+        .with_extension(extension)
+        .with_extension(Extension::new("tools"))
+        .with_fallback(|_, method, _| Ok(json!({ "fallback": method })))
+        .with_config(Config {
+            introspection: true,
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    let result = client.initialize().unwrap();
+    assert_eq!(
+        result["capabilities"]["experimental"],
+        json!({ "x-myco": { "version": 1 } })
+    );
+
+    let response = client.request("x-myco/echo", Some(json!([1, 2]))).unwrap();
+    assert_eq!(response["result"], json!({ "params": [1, 2] }));
+    let response = client.request("x-other/echo", None).unwrap();
+    assert_eq!(response["result"], json!({ "fallback": "x-other/echo" }));
+
+    let response = client.request("server/methods", None).unwrap();
+    let methods = response["result"]["methods"].as_array().unwrap();
+    assert!(methods.contains(&json!({ "name": "x-myco/echo", "kind": "request" })));
+}
+
+#[test]
+fn session_state_is_kept_apart_and_dropped_on_disconnect() {
+    struct Calls(AtomicUsize, Arc<AtomicUsize>);
+    impl Drop for Calls {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let tool = Tool::new("count", "Counts", json!({ "type": "object" }), |ctx, _| {
+        let session = ctx.session_state::<Calls>().unwrap();
+        let shared = ctx.shared_state::<AtomicUsize>().unwrap();
+        Ok(CallToolResult::structured(json!({
+            "session": session.0.fetch_add(1, Ordering::SeqCst) + 1,
+            "shared": shared.fetch_add(1, Ordering::SeqCst) + 1,
+        })))
+    });
+    let factory_dropped = dropped.clone();
+    let server = Arc::new(
+        Server::new()
+            .with_tool(tool)
+            .with_shared_state(AtomicUsize::new(0))
+            .with_session_state(move || Calls(AtomicUsize::new(0), factory_dropped.clone())),
+    );
+    let mut first = MockClient::connect(server.clone());
+    let mut second = MockClient::connect(server);
+    first.initialize().unwrap();
+    second.initialize().unwrap();
+    let call = |client: &mut MockClient| {
+        let response = client
+            .request("tools/call", Some(json!({ "name": "count" })))
+            .unwrap();
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        serde_json::from_str::<serde_json::Value>(text).unwrap()
+    };
+    assert_eq!(call(&mut first), json!({ "session": 1, "shared": 1 }));
+    assert_eq!(call(&mut first), json!({ "session": 2, "shared": 2 }));
+    assert_eq!(call(&mut second), json!({ "session": 1, "shared": 3 }));
+    first.close().unwrap();
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    second.close().unwrap();
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn shutdown_hooks_run_once_per_session_on_shutdown() {
+    let shut_down = Arc::new(AtomicUsize::new(0));
+    let counter = shut_down.clone();
+    let server = Arc::new(Server::new().on_shutdown(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    }));
+    let mut first = MockClient::connect(server.clone());
+    let mut second = MockClient::connect(server.clone());
+    first.initialize().unwrap();
+    second.initialize().unwrap();
+    server.shutdown();
+    assert_eq!(shut_down.load(Ordering::SeqCst), 2);
+    // Not run again once their clients disconnect
+    drop((first, second));
+    assert_eq!(shut_down.load(Ordering::SeqCst), 2);
+    let client = MockClient::connect(server);
+    client.close().unwrap();
+    assert_eq!(shut_down.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn resources_are_read_in_ranges() {
+    struct Log;
+    impl ResourceProvider for Log {
+        fn list(&self, _ctx: &RequestContext) -> Vec<Resource> {
+            Vec::new()
+        }
+
+        fn read(
+            &self,
+            _ctx: &RequestContext,
+            uri: &str,
+        ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+            let contents = match uri {
+                "log://text" => ResourceContents::text(uri, "first\nsecond é\nthird\n"),
+                "log://blob" => ResourceContents::blob(uri, &[0, 1, 2, 3, 4, 5], "image/png"),
+                _ => return None,
+            };
+            Some(Ok(ReadResourceResult::new(vec![contents])))
+        }
+    }
+    let mut client = MockClient::connect(Arc::new(Server::new().with_resources(Log)));
+    // `_meta` of the contents, which holds the range, is only sent since 2025-06-18
+    client
+        .request(
+            "initialize",
+            Some(json!({
+                "protocolVersion": "2025-06-18",
+                "capabilities": {},
+                "clientInfo": { "name": "mock-client", "version": "0" },
+            })),
+        )
+        .unwrap();
+    client.notify("notifications/initialized", None).unwrap();
+    let mut read = |params| {
+        let response = client.request("resources/read", Some(params)).unwrap();
+        response["result"]["contents"][0].clone()
+    };
+    let tail = read(json!({ "uri": "log://text", "offset": -6 }));
+    assert_eq!(tail["text"], json!("third\n"));
+    assert_eq!(
+        tail["_meta"]["range"],
+        json!({ "offset": 16, "length": 6, "size": 22 })
+    );
+    // Moved back to the start of the cut character
+    let middle = read(json!({ "uri": "log://text", "offset": 14, "length": 2 }));
+    assert_eq!(middle["text"], json!("é\n"));
+    let blob = read(json!({ "uri": "log://blob", "offset": 2, "length": 2 }));
+    assert_eq!(blob["blob"], json!("AgM="));
+    assert_eq!(blob["_meta"]["range"]["size"], json!(6));
+    let whole = read(json!({ "uri": "log://blob" }));
+    assert!(whole.get("_meta").is_none());
+    let response = client
+        .request(
+            "resources/read",
+            Some(json!({ "uri": "log://text", "length": -1 })),
+        )
+        .unwrap();
+    assert_eq!(response["error"]["code"], json!(-32602));
+}
+
+#[test]
+fn resources_are_read_by_the_handler_of_their_scheme() {
+    struct Git;
+    impl SchemeHandler for Git {
+        fn read(
+            &self,
+            _ctx: &RequestContext,
+            uri: &str,
+        ) -> Result<ReadResourceResult, JsonRpcError> {
+            let (_, path) = uri.split_once("://").unwrap_or_default();
+            Ok(ReadResourceResult::new(vec![ResourceContents::text(
+                uri,
+                format!("contents of {}", path),
+            )]))
+        }
+    }
+    let config = Config {
+        resource_schemes: [
+            ("data", json!({ "enabled": false })),
+            ("git", json!({ "allow": ["git://HEAD/*"] })),
+        ]
+        .into_iter()
+        .map(|(scheme, settings)| {
+            (
+                scheme.to_string(),
+                serde_json::from_value(settings).unwrap(),
+            )
+        })
+        .collect(),
+        ..Config::default()
+    };
+    let server = Server::new()
+        .with_scheme("data", builtin::data::DataScheme)
+        .with_scheme("git", Git);
+    let server = Arc::new(server);
+    let mut client = MockClient::connect(server.clone());
+    client.initialize().unwrap();
+    let mut read = |uri: &str| {
+        client
+            .request("resources/read", Some(json!({ "uri": uri })))
+            .unwrap()
+    };
+    let response = read("data:,Hello%2C%20World");
+    assert_eq!(
+        response["result"]["contents"][0],
+        json!({ "uri": "data:,Hello%2C%20World", "mimeType": "text/plain;charset=US-ASCII", "text": "Hello, World" })
+    );
+    let response = read("data:image/png;base64,iVBORw==");
+    assert_eq!(response["result"]["contents"][0]["blob"], json!("iVBORw=="));
+    assert_eq!(
+        response["result"]["contents"][0]["mimeType"],
+        json!("image/png")
+    );
+    let response = read("GIT://HEAD/README.md");
+    assert_eq!(
+        response["result"]["contents"][0]["text"],
+        json!("contents of HEAD/README.md")
+    );
+
+    server.reload_config(config);
+    let response = read("data:,Hello");
+    assert_eq!(response["error"]["code"], json!(-32602));
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("disabled"));
+    assert!(read("git://HEAD/README.md")["result"].is_object());
+    let response = read("git://main/README.md");
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("not in the allowed"));
+}
+
+#[test]
+fn large_resources_are_read_in_pages() {
+    struct Large;
+    impl ResourceProvider for Large {
+        fn list(&self, _ctx: &RequestContext) -> Vec<Resource> {
+            Vec::new()
+        }
+
+        fn read(
+            &self,
+            _ctx: &RequestContext,
+            uri: &str,
+        ) -> Option<Result<ReadResourceResult, JsonRpcError>> {
+            let contents = match uri {
+                "large://text" => ResourceContents::text(uri, "abcéfghij"),
+                "small://text" => ResourceContents::text(uri, "abc"),
+                _ => return None,
+            };
+            Some(Ok(ReadResourceResult::new(vec![contents])))
+        }
+    }
+    let server = Server::new().with_resources(Large).with_config(Config {
+        resource_page_size: 4,
+        ..Config::default()
+    });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let mut pages = Vec::new();
+    let mut params = json!({ "uri": "large://text" });
+    loop {
+        let response = client
+            .request("resources/read", Some(params.clone()))
+            .unwrap();
+        let result = &response["result"];
+        pages.push(result["contents"][0]["text"].as_str().unwrap().to_string());
+        match result.get("nextCursor") {
+            Some(cursor) => params["cursor"] = cursor.clone(),
+            None => break,
+        }
+    }
+    // The page cutting "é" stops before it
+    assert_eq!(pages, ["abc", "éfg", "hij"]);
+    let response = client
+        .request("resources/read", Some(json!({ "uri": "small://text" })))
+        .unwrap();
+    assert_eq!(response["result"]["contents"][0]["text"], json!("abc"));
+    assert!(response["result"].get("nextCursor").is_none());
+    // Cursors are only accepted for their resource
+    params["uri"] = json!("small://text");
+    let response = client.request("resources/read", Some(params)).unwrap();
+    assert_eq!(response["error"]["code"], json!(-32602));
+}
+
+#[test]
+fn tampered_foreign_and_expired_cursors_are_refused() {
+    let secret = "cursor secret";
+    let mut server = Server::new().with_config(Config {
+        page_size: 2,
+        cursor_secret: Some(secret.to_string()),
+        ..Config::default()
+    });
+    for name in ["a", "b", "c"] {
+        let tool = Tool::new(name, "Does nothing", json!({ "type": "object" }), |_, _| {
+            Ok(CallToolResult::text(""))
+        });
+        server = server.with_tool(tool);
+    }
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let first = client.request("tools/list", None).unwrap();
+    let cursor = first["result"]["nextCursor"].as_str().unwrap().to_string();
+    let mut list = |method: &str, cursor: &str| {
+        let response = client
+            .request(method, Some(json!({ "cursor": cursor })))
+            .unwrap();
+        match response.get("error") {
+            Some(error) => Err(error["code"].clone()),
+            None => Ok(response["result"].clone()),
+        }
+    };
+    let rest = list("tools/list", &cursor).unwrap();
+    assert_eq!(rest["tools"].as_array().unwrap().len(), 1);
+
+    // The position changed without signing it again
+    let mut token = URL_SAFE_NO_PAD.decode(&cursor).unwrap();
+    let position = "tools/list\n".len();
+    assert_eq!(token[position], b'2');
+    token[position] = b'0';
+    let tampered = URL_SAFE_NO_PAD.encode(&token);
+    assert_eq!(list("tools/list", &tampered), Err(json!(-32602)));
+    assert_eq!(list("tools/list", "not a cursor"), Err(json!(-32602)));
+    // Issued by another listing
+    assert_eq!(list("prompts/list", &cursor), Err(json!(-32602)));
+
+    // Signed as the server does, only the time they were issued at tells them apart
+    let sign = |issued: u64| {
+        let mut token = format!("tools/list\n2\n{}", issued).into_bytes();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(&token);
+        token.extend_from_slice(&mac.finalize().into_bytes());
+        URL_SAFE_NO_PAD.encode(token)
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert_eq!(list("tools/list", &sign(now)), Ok(rest));
+    assert_eq!(
+        list("tools/list", &sign(now - 2 * 3600)),
+        Err(json!(-32602))
+    );
+}
+
+#[test]
+fn writes_never_create_directories_outside_the_roots() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("root");
+    std::fs::create_dir(&root).unwrap();
+    let server = Server::new()
+        .with_tool(builtin::fs::write_tool())
+        .with_config(Config {
+            roots: vec![root.clone()],
+            allow_writes: true,
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let mut write = |path: &str| {
+        let arguments = json!({ "path": path, "content": "x" });
+        let params = json!({ "name": "fs_write", "arguments": arguments });
+        client.request("tools/call", Some(params)).unwrap()["result"].clone()
+    };
+    for path in ["newdir/../../escaped/f.txt", "../escaped/f.txt"] {
+        assert_eq!(write(path)["isError"], json!(true), "{}", path);
+    }
+    assert!(!dir.path().join("escaped").exists());
+    assert!(!root.join("newdir").exists());
+    assert!(write("new/dir/f.txt").get("isError").is_none());
+    assert_eq!(
+        std::fs::read_to_string(root.join("new/dir/f.txt")).unwrap(),
+        "x"
+    );
+}
+
+#[test]
+fn files_are_read_and_written_only_inside_the_roots() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = std::fs::canonicalize(dir.path()).unwrap();
+    let (first, second, outside) = (base.join("a"), base.join("b"), base.join("outside"));
+    for dir in [&first, &second, &outside] {
+        std::fs::create_dir(dir).unwrap();
+    }
+    std::fs::write(first.join("inside.txt"), "inside").unwrap();
+    std::fs::write(second.join("other.txt"), "other").unwrap();
+    std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&outside, first.join("dir_link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), first.join("file_link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("new.txt"), first.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(first.join("inside.txt"), first.join("inside_link")).unwrap();
+    }
+    let server = Server::new()
+        .with_tool(builtin::fs::read_tool())
+        .with_tool(builtin::fs::write_tool())
+        .with_config(Config {
+            roots: vec![first.clone(), second.clone()],
+            allow_writes: true,
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let mut call = |name: &str, arguments: serde_json::Value| {
+        let params = json!({ "name": name, "arguments": arguments });
+        let result = client.request("tools/call", Some(params)).unwrap()["result"].clone();
+        let text = result["content"][0]["text"].as_str().unwrap().to_string();
+        (result["isError"] == json!(true), text)
+    };
+    let mut read = |path: &str| call("fs_read", json!({ "path": path }));
+    assert_eq!(read("inside.txt"), (false, "inside".to_string()));
+    let other = second.join("other.txt");
+    assert_eq!(read(&other.to_string_lossy()), (false, "other".to_string()));
+    let mut refused = vec!["../outside/secret.txt", "missing/inside.txt"];
+    if cfg!(unix) {
+        refused.extend(["dir_link/secret.txt", "file_link"]);
+    }
+    for path in &refused {
+        assert!(read(path).0, "{}", path);
+    }
+
+    let mut write = |path: &str| call("fs_write", json!({ "path": path, "content": "new" }));
+    assert!(!write("missing/deeper/new.txt").0);
+    assert!(first.join("missing/deeper/new.txt").exists());
+    assert!(!write(&second.join("new.txt").to_string_lossy()).0);
+    assert!(second.join("new.txt").exists());
+    let mut refused = vec!["../outside/new.txt", "../outside/missing/new.txt"];
+    if cfg!(unix) {
+        refused.extend([
+            "dir_link/new.txt",
+            "dir_link/missing/new.txt",
+            "file_link",
+            "dangling",
+            // Not even to a file inside the roots
+            "inside_link",
+        ]);
+    }
+    for path in &refused {
+        assert!(write(path).0, "{}", path);
+    }
+    let mut left = std::fs::read_dir(&outside)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    left.sort();
+    assert_eq!(left, ["secret.txt"]);
+    assert_eq!(
+        std::fs::read_to_string(first.join("inside.txt")).unwrap(),
+        "inside"
+    );
+    assert_eq!(
+        std::fs::read_to_string(outside.join("secret.txt")).unwrap(),
+        "secret"
+    );
+}
+
+#[test]
+fn scoped_sessions_only_get_the_prompts_files_and_completions_granted() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = std::fs::canonicalize(dir.path()).unwrap();
+    std::fs::create_dir(base.join("docs")).unwrap();
+    std::fs::write(base.join("docs/guide.md"), "guide").unwrap();
+    std::fs::write(base.join("secret.txt"), "secret").unwrap();
+    let template: PromptTemplate = serde_json::from_value(json!({
+        "name": "quote",
+        "arguments": [{ "name": "path", "required": true }],
+        "messages": [{ "text": "{{file path}}" }],
+    }))
+    .unwrap();
+    let config = Config {
+        roots: vec![base.clone()],
+        prompts: vec![template],
+        scopes: [(
+            "docs".to_string(),
+            ScopeGrant {
+                resources: vec![format!("file://{}/docs/*", base.display())],
+                prompts: vec!["code_review".to_string(), "quote".to_string()],
+                ..ScopeGrant::default()
+            },
+        )]
+        .into(),
+        ..Config::default()
+    };
+    let mut server = Server::new()
+        .with_prompt(builtin::prompts::code_review_prompt())
+        .with_prompt(builtin::prompts::summarize_file_prompt())
+        .with_resources(builtin::fs::FileResources::default());
+    for prompt in plugins::templates::load_prompts(&config) {
+        server = server.with_prompt(prompt);
+    }
+    let server = Arc::new(server.with_config(config));
+
+    let mut unscoped = MockClient::connect(server.clone());
+    unscoped.initialize().unwrap();
+    let prompts = unscoped.request("prompts/list", None).unwrap();
+    assert_eq!(prompts["result"]["prompts"].as_array().unwrap().len(), 3);
+
+    let mut client = MockClient::connect_authenticated(server, "k-docs", vec!["docs".to_string()]);
+    client.initialize().unwrap();
+    let prompts = client.request("prompts/list", None).unwrap();
+    let mut names = prompts["result"]["prompts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|prompt| prompt["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["code_review", "quote"]);
+
+    let mut get = |name: &str, path: &str| {
+        let params = json!({ "name": name, "arguments": { "path": path } });
+        client.request("prompts/get", Some(params)).unwrap()
+    };
+    let review = get("code_review", "docs/guide.md");
+    let embedded = &review["result"]["messages"][1]["content"]["resource"];
+    assert_eq!(embedded["text"], "guide");
+    let quote = get("quote", "docs/guide.md");
+    assert_eq!(quote["result"]["messages"][0]["content"]["text"], "guide");
+    for (name, path) in [
+        ("summarize_file", "docs/guide.md"),
+        ("code_review", "secret.txt"),
+        ("quote", "secret.txt"),
+    ] {
+        let response = get(name, path);
+        assert_eq!(response["error"]["code"], -32602, "{} {}", name, path);
+        assert!(!response.to_string().contains("\"secret\""));
+    }
+
+    let complete = |client: &mut MockClient, reference: serde_json::Value| {
+        let params = json!({ "ref": reference, "argument": { "name": "path", "value": format!("{}/", base.display()) } });
+        client.request("completion/complete", Some(params)).unwrap()
+    };
+    let files = json!({ "type": "ref/resource", "uri": "file://{path}" });
+    let values = complete(&mut client, files.clone());
+    assert_eq!(
+        values["result"]["completion"]["values"],
+        json!([format!("{}/docs/", base.display())])
+    );
+    let prompt = json!({ "type": "ref/prompt", "name": "summarize_file" });
+    assert_eq!(complete(&mut client, prompt)["error"]["code"], -32602);
+}
+
+#[test]
+fn read_only_mode_withdraws_the_tools_that_modify_anything() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut server = Server::new()
+        .with_tool(builtin::fs::read_tool())
+        .with_tool(builtin::fs::write_tool())
+        .with_tool(builtin::command::run_tool())
+        .with_tool(builtin::memory::get_tool())
+        .with_tool(builtin::memory::set_tool());
+    #[cfg(unix)]
+    {
+        server = server.with_tool(builtin::pty::start_tool());
+    }
+    #[cfg(feature = "redis")]
+    {
+        server = server.with_tool(builtin::redis::command_tool());
+    }
+    let server = server.with_config(Config {
+        roots: vec![dir.path().to_path_buf()],
+        allow_writes: true,
+        allow_commands: true,
+        allow_redis: true,
+        redis_instances: [("main".to_string(), "redis://127.0.0.1:1".to_string())].into(),
+        memory_file: Some(dir.path().join("memory.json")),
+        read_only: true,
+        ..Config::default()
+    });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let tools = client.request("tools/list", None).unwrap();
+    let mut names = tools["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["fs_read", "memory_get"]);
+
+    for (name, arguments) in [
+        ("fs_write", json!({ "path": "new.txt", "content": "new" })),
+        (
+            "run_command",
+            json!({ "command": "touch", "args": ["new.txt"] }),
+        ),
+        ("memory_set", json!({ "key": "k", "value": "v" })),
+        ("shell_session_start", json!({ "command": "sh" })),
+        (
+            "redis",
+            json!({ "instance": "main", "command": "DEL", "key": "k" }),
+        ),
+    ] {
+        let params = json!({ "name": name, "arguments": arguments });
+        let response = client.request("tools/call", Some(params)).unwrap();
+        assert!(response.get("result").is_none(), "{}", name);
+    }
+    assert!(!dir.path().join("new.txt").exists());
+    assert!(!dir.path().join("memory.json").exists());
+}
+
+#[test]
+fn sqlite_queries_only_reach_the_configured_databases() {
+    let dir = tempfile::tempdir().unwrap();
+    // SQLite takes an empty file for an empty database
+    std::fs::write(dir.path().join("main.db"), "").unwrap();
+    let server = Server::new()
+        .with_tool(builtin::sqlite::query_tool())
+        .with_config(Config {
+            sqlite_databases: [("main".to_string(), dir.path().join("main.db"))].into(),
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let mut query = |sql: &str| {
+        let arguments = json!({ "database": "main", "sql": sql });
+        let params = json!({ "name": "sqlite_query", "arguments": arguments });
+        let result = client.request("tools/call", Some(params)).unwrap()["result"].clone();
+        let text = result["content"][0]["text"].as_str().unwrap().to_string();
+        (result["isError"] == json!(true), text)
+    };
+    assert!(!query("SELECT 1 AS one").0);
+    let other = dir.path().join("other.db");
+    for sql in [
+        format!("ATTACH DATABASE '{}' AS other", other.display()),
+        format!("ATTACH '{}' AS other", other.display()),
+        "ATTACH ':memory:' AS other".to_string(),
+    ] {
+        let (failed, text) = query(&sql);
+        assert!(failed, "{}: {}", sql, text);
+    }
+    assert!(!other.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn commands_end_with_their_process_even_when_leaving_a_child_behind() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = Server::new()
+        .with_tool(builtin::command::run_tool())
+        .with_config(Config {
+            roots: vec![dir.path().to_path_buf()],
+            allow_commands: true,
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    // The background sleep inherits the output pipes of the shell, and is killed when the
+    // shell exits rather than holding the output open for 30 seconds
+    let arguments = json!({ "command": "sh", "args": ["-c", "sleep 30 & echo started"] });
+    let params = json!({ "name": "run_command", "arguments": arguments });
+    let response = client.request("tools/call", Some(params)).unwrap();
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    let summary: serde_json::Value = serde_json::from_str(text).unwrap();
+    assert_eq!(summary["exit_code"], json!(0));
+    assert_eq!(summary["stdout"], json!("started\n"));
+}
+
+#[cfg(unix)]
+#[cfg(unix)]
+#[test]
+fn commands_get_only_the_allowed_environment_and_run_inside_the_roots() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = std::fs::canonicalize(dir.path()).unwrap();
+    let (root, outside) = (base.join("root"), base.join("outside"));
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::create_dir(&outside).unwrap();
+    // A variable of the server that the allowlist leaves out
+    let (hidden, _) = std::env::vars()
+        .find(|(name, _)| name != "PATH")
+        .expect("the tests run with more variables than PATH");
+    let server = Server::new()
+        .with_tool(builtin::command::run_tool())
+        .with_config(Config {
+            roots: vec![root.clone()],
+            allow_commands: true,
+            command_env_allowlist: Some(vec!["PATH".to_string()]),
+            command_env: [("GREETING".to_string(), "hello".to_string())].into(),
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let mut run = |command: &str, cwd: Option<&str>| {
+        let mut arguments = json!({ "command": command });
+        if let Some(cwd) = cwd {
+            arguments["cwd"] = json!(cwd);
+        }
+        let params = json!({ "name": "run_command", "arguments": arguments });
+        let result = client.request("tools/call", Some(params)).unwrap()["result"].clone();
+        let text = result["content"][0]["text"].as_str().unwrap().to_string();
+        match result["isError"] == json!(true) {
+            true => Err(text),
+            false => Ok(serde_json::from_str::<serde_json::Value>(&text).unwrap()),
+        }
+    };
+    let output = run("env", None).unwrap();
+    let variables = output["stdout"]
+        .as_str()
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.split_once('=').map(|(name, _)| name.to_string()))
+        .collect::<Vec<_>>();
+    assert!(variables.contains(&"PATH".to_string()), "{:?}", variables);
+    assert!(
+        variables.contains(&"GREETING".to_string()),
+        "{:?}",
+        variables
+    );
+    assert!(
+        !variables.contains(&hidden),
+        "{} in {:?}",
+        hidden,
+        variables
+    );
+
+    let output = run("pwd", Some("sub")).unwrap();
+    assert_eq!(
+        output["stdout"].as_str().unwrap().trim(),
+        root.join("sub").to_string_lossy()
+    );
+    for cwd in ["..", "../outside", &outside.to_string_lossy()] {
+        assert!(run("pwd", Some(cwd)).is_err(), "{}", cwd);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn commands_are_allowed_and_denied_by_program_name() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let base = std::fs::canonicalize(dir.path()).unwrap();
+    // Programs of the same names as allowed ones, but elsewhere than on the PATH
+    std::fs::create_dir(base.join("bin")).unwrap();
+    for name in ["git", "echo", "tool"] {
+        let path = base.join("bin").join(name);
+        std::fs::write(&path, "#!/bin/sh\necho fake\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    std::os::unix::fs::symlink(base.join("bin/tool"), base.join("tool_link")).unwrap();
+    let config = |allowlist: Option<&[&str]>, denylist: &[&str]| Config {
+        roots: vec![base.clone()],
+        allow_commands: true,
+        command_allowlist: allowlist.map(|list| list.iter().map(|s| s.to_string()).collect()),
+        command_denylist: denylist.iter().map(|s| s.to_string()).collect(),
+        ..Config::default()
+    };
+    let server = Arc::new(
+        Server::new()
+            .with_tool(builtin::command::run_tool())
+            .with_config(config(None, &["rm"])),
+    );
+    let mut client = MockClient::connect(server.clone());
+    client.initialize().unwrap();
+    let mut run = |command: &str, args: &[&str], cwd: &str| {
+        let arguments = json!({ "command": command, "args": args, "cwd": cwd });
+        let params = json!({ "name": "run_command", "arguments": arguments });
+        let result = client.request("tools/call", Some(params)).unwrap()["result"].clone();
+        let text = result["content"][0]["text"].as_str().unwrap().to_string();
+        match result["isError"] == json!(true) {
+            true => Err(text),
+            false => Ok(()),
+        }
+    };
+    let root = base.to_string_lossy().into_owned();
+    let missing = base.join("missing").to_string_lossy().into_owned();
+    let rm = std::env::split_paths(&std::env::var_os("PATH").unwrap())
+        .map(|dir| dir.join("rm"))
+        .find(|path| path.is_file())
+        .unwrap();
+    let rm = rm.to_string_lossy().into_owned();
+    // A bare name denies the executable found on the PATH, whichever path it is run by
+    for command in ["rm", &rm] {
+        let refusal = run(command, &["-f", &missing], &root).unwrap_err();
+        assert!(refusal.contains("denied"), "{}", refusal);
+    }
+    assert!(run("echo", &["rm"], &root).is_ok());
+    // And a path denies the executable it names, run by its name too
+    server.reload_config(config(None, &[&rm]));
+    let refusal = run("rm", &["-f", &missing], &root).unwrap_err();
+    assert!(refusal.contains("denied"), "{}", refusal);
+
+    // Matched against the program, never against the arguments or a part of its name
+    server.reload_config(config(Some(&["echo", "git"]), &[]));
+    assert!(run("echo", &["hello"], &root).is_ok());
+    for (command, args, cwd) in [
+        ("sh", &["-c", "echo hello"][..], &root[..]),
+        ("printf", &["echo"][..], &root),
+        ("echoes", &[][..], &root),
+        ("/bin/echo/../sh", &["-c", "true"][..], &root),
+        // A bare name only allows the program of that name on the PATH
+        ("/bin/echo", &["hello"][..], &root),
+        ("./git", &["status"][..], &format!("{}/bin", root)),
+        ("bin/git", &["status"][..], &root),
+        (&format!("{}/bin/git", root), &["status"][..], &root),
+        (&format!("{}/bin/echo", root), &[][..], &root),
+    ] {
+        let refusal = run(command, args, cwd).unwrap_err();
+        assert!(refusal.contains("not in the allowlist"), "{}", refusal);
+    }
+
+    // A path allows the executable it names, by whichever path it is run
+    let tool = base.join("bin/tool").to_string_lossy().into_owned();
+    server.reload_config(config(Some(&[&tool]), &[]));
+    assert!(run(&tool, &[], &root).is_ok());
+    assert!(run("bin/tool", &[], &root).is_ok());
+    assert!(run("./tool", &[], &format!("{}/bin", root)).is_ok());
+    assert!(run("tool_link", &[], &root).is_err());
+    assert!(run("./tool_link", &[], &root).is_ok());
+    let refusal = run("./git", &[], &format!("{}/bin", root)).unwrap_err();
+    assert!(refusal.contains("not in the allowlist"), "{}", refusal);
+}
+
+#[test]
+fn fetches_only_reach_the_allowed_hosts() {
+    // Redirects every request to localhost, which isn't allowed, recording the request lines
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            std::io::BufRead::read_line(&mut reader, &mut line).unwrap();
+            received.lock().unwrap().push(line.trim_end().to_string());
+            let response = format!(
+                "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/secret\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n",
+                port
+            );
+            std::io::Write::write_all(&mut stream, response.as_bytes()).unwrap();
+        }
+    });
+    let server = Server::new()
+        .with_tool(builtin::http::fetch_tool())
+        .with_config(Config {
+            fetch_allowed_domains: Some(vec!["127.0.0.1".to_string(), "example.com".to_string()]),
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let mut fetch = |url: &str| {
+        let params = json!({ "name": "http_fetch", "arguments": { "url": url } });
+        let result = client.request("tools/call", Some(params)).unwrap()["result"].clone();
+        let text = result["content"][0]["text"].as_str().unwrap().to_string();
+        match result["isError"] == json!(true) {
+            true => Err(text),
+            false => Ok(serde_json::from_str::<serde_json::Value>(&text).unwrap()),
+        }
+    };
+    for url in [
+        format!("http://localhost:{}/", port),
+        // The host is what follows the userinfo
+        format!("http://127.0.0.1@localhost:{}/", port),
+        "http://example.com.evil.test/".to_string(),
+        "http://notexample.com/".to_string(),
+    ] {
+        let refusal = fetch(&url).unwrap_err();
+        assert!(
+            refusal.contains("not in the allowed domains"),
+            "{}",
+            refusal
+        );
+    }
+    assert!(requests.lock().unwrap().is_empty());
+
+    // The redirect is returned rather than followed out of the allowed hosts
+    let response = fetch(&format!("http://127.0.0.1:{}/start", port)).unwrap();
+    assert_eq!(response["status"], json!(302));
+    assert_eq!(
+        response["headers"]["location"],
+        json!(format!("http://localhost:{}/secret", port))
+    );
+    assert_eq!(*requests.lock().unwrap(), ["GET /start HTTP/1.1"]);
+}
+
+#[test]
+fn progress_messages_are_only_sent_to_sessions_knowing_them() {
+    let tool = Tool::new("work", "Works", json!({ "type": "object" }), |ctx, _| {
+        ctx.send_progress(1, Some(2), Some("half done"));
+        Ok(CallToolResult::text("done"))
+    });
+    let server = Arc::new(Server::new().with_tool(tool));
+    let call = json!({ "name": "work", "_meta": { "progressToken": "work" } });
+    let mut old = MockClient::connect(server.clone());
+    old.initialize().unwrap();
+    old.request("tools/call", Some(call.clone())).unwrap();
+    let progress = old.expect_notification("notifications/progress").unwrap();
+    assert_eq!(progress["progress"], json!(1));
+    assert!(progress.get("message").is_none());
+
+    let mut recent = MockClient::connect(server);
+    recent
+        .request(
+            "initialize",
+            Some(json!({
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "mock-client", "version": "0" },
+            })),
+        )
+        .unwrap();
+    recent.notify("notifications/initialized", None).unwrap();
+    recent.request("tools/call", Some(call)).unwrap();
+    let progress = recent
+        .expect_notification("notifications/progress")
+        .unwrap();
+    assert_eq!(progress["message"], json!("half done"));
+    assert_eq!(progress["total"], json!(2));
+}
+
+#[test]
+fn manifest_tools_are_reloaded_when_the_file_changes() {
+    let dir = std::env::temp_dir().join(format!("mcp_rs_test-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let manifest = dir.join("tools.json");
+    let write = |name: &str| {
+        let tools = json!({ "tools": [{ "name": name, "command": "true" }] });
+        std::fs::write(&manifest, tools.to_string()).unwrap();
+    };
+    write("first");
+    let config = Config {
+        tool_manifests: vec![manifest.clone()],
+        plugin_reload: true,
+        ..Config::default()
+    };
+    let tools = plugins::load_tools(&config);
+    let names = tools.iter().map(|tool| tool.name.clone()).collect();
+    let server = Arc::new(
+        tools.into_iter().fold(
+            Server::new()
+                .with_tool(builtin::calc::evaluate_tool())
+                .with_config(config),
+            Server::with_tool,
+        ),
+    );
+    let _watcher = ToolWatcher::new(server.clone(), names).unwrap();
+    let mut client = MockClient::connect(server);
+    client.initialize().unwrap();
+
+    write("second");
+    client
+        .expect_notification("notifications/tools/list_changed")
+        .unwrap();
+    let response = client.request("tools/list", None).unwrap();
+    let mut listed: Vec<_> = response["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap().to_string())
+        .collect();
+    listed.sort();
+    assert_eq!(listed, ["evaluate", "second"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn requests_sent_again_get_the_same_response_without_running_twice() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let tool = Tool::new(
+        "count",
+        "Counts",
+        json!({ "type": "object" }),
+        move |_, _| {
+            let n = counted.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(CallToolResult::text(n.to_string()))
+        },
+    );
+    let server = Arc::new(Server::new().with_tool(tool));
+    let mut client = MockClient::connect_resumable(server.clone(), "session-1");
+    client.initialize().unwrap();
+    let call = json!({ "name": "count", "arguments": {} });
+    let first = client
+        .request_with_id(json!(7), "tools/call", Some(call.clone()))
+        .unwrap();
+    let again = client
+        .request_with_id(json!(7), "tools/call", Some(call.clone()))
+        .unwrap();
+    assert_eq!(again, first);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    // Another request under the same id is served
+    let other = json!({ "name": "count", "arguments": { "again": true } });
+    let response = client
+        .request_with_id(json!(7), "tools/call", Some(other))
+        .unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], json!("2"));
+    // Not even once the other request replaced it
+    let response = client
+        .request_with_id(json!(7), "tools/call", Some(call.clone()))
+        .unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], json!("3"));
+
+    // Sessions that can't be resumed serve every request
+    let mut stdio = MockClient::connect(server.clone());
+    stdio.initialize().unwrap();
+    for n in ["4", "5"] {
+        let response = stdio
+            .request_with_id(json!(9), "tools/call", Some(call.clone()))
+            .unwrap();
+        assert_eq!(response["result"]["content"][0]["text"], json!(n));
+    }
+
+    server.reload_config(Config {
+        idempotency_ttl_secs: 0,
+        ..Config::default()
+    });
+    client
+        .request_with_id(json!(8), "tools/call", Some(call.clone()))
+        .unwrap();
+    client
+        .request_with_id(json!(8), "tools/call", Some(call))
+        .unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 7);
+}
+
+#[test]
+fn messages_larger_than_the_write_buffer_are_sent_whole() {
+    let text = "0123456789abcdef".repeat(12 * 1024);
+    let large = text.clone();
+    let tool = Tool::new(
+        "large",
+        "Prints",
+        json!({ "type": "object" }),
+        move |_, _| Ok(CallToolResult::text(large.clone())),
+    );
+    let mut client = MockClient::connect(Arc::new(Server::new().with_tool(tool)));
+    client.initialize().unwrap();
+    for _ in 0..2 {
+        let response = client
+            .request("tools/call", Some(json!({ "name": "large" })))
+            .unwrap();
+        assert_eq!(response["result"]["content"][0]["text"], json!(text));
+    }
+    let response = client.request("ping", None).unwrap();
+    assert_eq!(response["result"], json!({}));
+}
+
+#[test]
+fn requests_over_the_rate_limit_are_refused_until_the_bucket_refills() {
+    let server = Server::new().with_config(Config {
+        rate_limits: [("ping".to_string(), "2/s".parse().unwrap())].into(),
+        ..Config::default()
+    });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    for _ in 0..2 {
+        let response = client.request("ping", None).unwrap();
+        assert_eq!(response["result"], json!({}));
+    }
+    let refused = client.request("ping", None).unwrap();
+    assert_eq!(refused["error"]["code"], json!(ERROR_CODE_RATE_LIMITED));
+    assert_eq!(refused["error"]["data"]["limit"], json!("2/s"));
+    // Other methods aren't limited
+    let response = client.request("tools/list", None).unwrap();
+    assert!(response.get("error").is_none());
+
+    let retry_after = refused["error"]["data"]["retryAfterMs"].as_u64().unwrap();
+    assert!(retry_after <= 500, "{}", retry_after);
+    std::thread::sleep(Duration::from_millis(retry_after + 50));
+    let response = client.request("ping", None).unwrap();
+    assert_eq!(response["result"], json!({}));
+    let refused = client.request("ping", None).unwrap();
+    assert_eq!(refused["error"]["code"], json!(ERROR_CODE_RATE_LIMITED));
+}
+
+#[test]
+fn denied_methods_are_not_found_while_the_session_goes_on() {
+    let tool = Tool::new("echo", "Echoes", json!({ "type": "object" }), |_, _| {
+        Ok(CallToolResult::text("echo"))
+    });
+    let server = Arc::new(Server::new().with_tool(tool).with_config(Config {
+        denied_methods: vec!["tools/*".to_string()],
+        ..Config::default()
+    }));
+    let mut client = MockClient::connect(server.clone());
+    let result = client.initialize().unwrap();
+    assert!(result["capabilities"].get("tools").is_none());
+    for (method, params) in [
+        ("tools/list", None),
+        ("tools/call", Some(json!({ "name": "echo" }))),
+    ] {
+        let response = client.request(method, params).unwrap();
+        assert_eq!(
+            response["error"]["code"],
+            json!(ERROR_CODE_METHOD_NOT_FOUND),
+            "{}",
+            method
+        );
+    }
+    let response = client.request("ping", None).unwrap();
+    assert_eq!(response["result"], json!({}));
+
+    // Not even denying everything stops a session from starting
+    server.reload_config(Config {
+        denied_methods: vec!["*".to_string()],
+        ..Config::default()
+    });
+    let mut client = MockClient::connect(server);
+    let result = client.initialize().unwrap();
+    assert!(result["protocolVersion"].is_string());
+    let response = client.request("resources/list", None).unwrap();
+    assert_eq!(
+        response["error"]["code"],
+        json!(ERROR_CODE_METHOD_NOT_FOUND)
+    );
+}
+
+/// Messages logged under the `wire` target, by every test of this file.
+#[derive(Default)]
+struct WireEvents(Mutex<Vec<String>>);
+
+impl WireEvents {
+    /// The events logged since the process started, collected once installed as the global
+    /// subscriber.
+    fn installed() -> Arc<WireEvents> {
+        static EVENTS: std::sync::OnceLock<Arc<WireEvents>> = std::sync::OnceLock::new();
+        EVENTS
+            .get_or_init(|| {
+                let events = Arc::new(WireEvents::default());
+                tracing::subscriber::set_global_default(events.clone()).unwrap();
+                events
+            })
+            .clone()
+    }
+}
+
+impl tracing::Subscriber for WireEvents {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+        metadata.target() == "wire"
+    }
+
+    fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        tracing::span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        struct Message(String);
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{:?}", value);
+                }
+            }
+        }
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        self.0.lock().unwrap().push(message.0);
+    }
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[test]
+fn secrets_are_redacted_from_the_wire_log_only() {
+    let events = WireEvents::installed();
+    let tool = Tool::new("login", "Logs in", json!({ "type": "object" }), |_, _| {
+        Ok(CallToolResult::structured(json!({
+            "user": "ada",
+            "session": { "access_token": "out-4f9c-token" },
+        })))
+    });
+    let mut wire_log_redact = Config::default().wire_log_redact;
+    wire_log_redact.push("passphrase".to_string());
+    let server = Server::new().with_tool(tool).with_config(Config {
+        wire_log: true,
+        wire_log_redact,
+        ..Config::default()
+    });
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let arguments = json!({
+        "headers": { "Authorization": "Bearer in-81ab-bearer" },
+        "passphrase": "in-81ab-passphrase",
+        "user": "ada",
+    });
+    let params = json!({ "name": "login", "arguments": arguments });
+    let response = client.request("tools/call", Some(params)).unwrap();
+    // The client gets the messages as they are
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains("out-4f9c-token"), "{}", text);
+    client.close().unwrap();
+
+    let logged = events
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|event| event.contains("ada"))
+        .cloned()
+        .collect::<Vec<_>>();
+    let (received, sent): (Vec<_>, Vec<_>) =
+        logged.iter().partition(|event| event.starts_with("<-"));
+    assert_eq!((received.len(), sent.len()), (1, 1), "{:?}", logged);
+    for event in &logged {
+        for secret in ["out-4f9c-token", "in-81ab-bearer", "in-81ab-passphrase"] {
+            assert!(!event.contains(secret), "{} in {}", secret, event);
+        }
+        assert!(event.contains("[REDACTED]"), "{}", event);
+        assert!(event.contains("ada"), "{}", event);
+    }
+}
+
+#[test]
+fn a_panicking_tool_fails_only_its_request() {
+    let tool = Tool::new("boom", "Panics", json!({ "type": "object" }), |_, _| {
+        panic!("boom")
+    });
+    let mut client = MockClient::connect(Arc::new(Server::new().with_tool(tool)));
+    client.initialize().unwrap();
+    let response = client
+        .request("tools/call", Some(json!({ "name": "boom" })))
+        .unwrap();
+    assert_eq!(response["error"]["code"], json!(-32603));
+    let response = client.request("ping", None).unwrap();
+    assert_eq!(response["result"], json!({}));
+}
+
+#[test]
+fn panics_are_reported_with_the_request_and_the_redacted_messages() {
+    struct Crashes(Arc<Mutex<Vec<Crash>>>);
+    impl CrashReporter for Crashes {
+        fn report(&self, crash: &Crash) -> Result<String, String> {
+            self.0.lock().unwrap().push(crash.clone());
+            Ok("memory".to_string())
+        }
+    }
+    let crashes = Arc::new(Mutex::new(Vec::new()));
+    let tool = Tool::new("boom", "Panics", json!({ "type": "object" }), |_, _| {
+        panic!("boom")
+    });
+    let server = Server::new()
+        .with_tool(tool)
+        .with_crash_reporter(Crashes(crashes.clone()));
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let arguments = json!({ "token": "secret" });
+    client
+        .request(
+            "tools/call",
+            Some(json!({ "name": "boom", "arguments": arguments })),
+        )
+        .unwrap();
+
+    let crashes = crashes.lock().unwrap();
+    assert_eq!(crashes.len(), 1);
+    let crash = &crashes[0];
+    assert_eq!(crash.message, "boom");
+    assert_eq!(crash.method.as_deref(), Some("tools/call"));
+    assert!(crash
+        .location
+        .as_deref()
+        .unwrap()
+        .contains("mock_client.rs"));
+    assert!(!crash.backtrace.is_empty());
+    let last = &crash.recent_messages.last().unwrap().message;
+    assert_eq!(last["params"]["arguments"]["token"], json!("[REDACTED]"));
+}
+
+#[test]
+fn server_requests_get_the_client_responses() {
+    let extension = Extension::new("x-test")
+        .with_method("roots", |ctx, _| ctx.send_request("roots/list", None));
+    let mut client = MockClient::connect(Arc::new(Server::new().with_extension(extension)));
+    client.initialize().unwrap();
+    let first = client.send_request("x-test/roots", None).unwrap();
+    let request = client.expect_request("roots/list").unwrap();
+    client
+        .respond(&request["id"], json!({ "roots": [] }))
+        .unwrap();
+    let response = client.expect_response(&first).unwrap();
+    assert_eq!(response["result"], json!({ "roots": [] }));
+}
+
+#[test]
+fn denied_tools_never_run_and_asked_ones_wait_for_the_user() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut server = Server::new();
+    for name in ["removed", "guarded"] {
+        let counted = calls.clone();
+        let tool = Tool::new(name, "Counts", json!({ "type": "object" }), move |_, _| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(CallToolResult::text("ran"))
+        });
+        server = server.with_tool(tool);
+    }
+    let server = server.with_config(Config {
+        tool_permissions: [
+            ("removed".to_string(), Permission::Deny),
+            ("guarded".to_string(), Permission::Ask),
+        ]
+        .into(),
+        approval: ApprovalMethod::Elicitation,
+        ..Config::default()
+    });
+    let mut client = MockClient::connect(Arc::new(server));
+    client
+        .request(
+            "initialize",
+            Some(json!({
+                "protocolVersion": "2025-06-18",
+                "capabilities": { "elicitation": {} },
+                "clientInfo": { "name": "mock-client", "version": "0" },
+            })),
+        )
+        .unwrap();
+    client.notify("notifications/initialized", None).unwrap();
+    let tools = client.request("tools/list", None).unwrap();
+    assert_eq!(tools["result"]["tools"].as_array().unwrap().len(), 1);
+    assert_eq!(tools["result"]["tools"][0]["name"], json!("guarded"));
+
+    let params = json!({ "name": "removed", "arguments": {} });
+    let response = client.request("tools/call", Some(params)).unwrap();
+    assert_eq!(
+        response["error"]["code"],
+        json!(errors::TOOL_NOT_ALLOWED.code)
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    let params = json!({ "name": "guarded", "arguments": { "path": "notes.txt" } });
+    for (action, ran) in [("decline", false), ("cancel", false), ("accept", true)] {
+        let id = client
+            .send_request("tools/call", Some(params.clone()))
+            .unwrap();
+        let request = client.expect_request("elicitation/create").unwrap();
+        let message = request["params"]["message"].as_str().unwrap();
+        assert!(message.contains("guarded") && message.contains("notes.txt"));
+        client
+            .respond(&request["id"], json!({ "action": action }))
+            .unwrap();
+        let response = client.expect_response(&id).unwrap();
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        match ran {
+            true => assert_eq!(text, "ran"),
+            false => assert!(text.contains("not approved"), "{}", text),
+        }
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn sampling_requests_are_approved_within_the_session_budget() {
+    let tool = Tool::new(
+        "ask",
+        "Samples",
+        json!({ "type": "object" }),
+        |ctx, arguments| {
+            let max_tokens = arguments["max_tokens"].as_u64().unwrap_or(10) as u32;
+            let request = CreateMessageRequest::new(vec![SamplingMessage::user("Hi")], max_tokens);
+            Ok(match ctx.create_message(&request) {
+                Ok(result) => CallToolResult::text(result.model),
+                Err(e) => CallToolResult::error(e.message),
+            })
+        },
+    );
+    let server = Server::new()
+        .with_tool(tool)
+        .with_sampling_hook(|_, request| match request.max_tokens {
+            0..=100 => Ok(()),
+            _ => Err("too long".to_string()),
+        })
+        .with_config(Config {
+            sampling_max_requests: 1,
+            ..Config::default()
+        });
+    let mut client = MockClient::connect(Arc::new(server));
+    client
+        .request(
+            "initialize",
+            Some(json!({
+                "protocolVersion": "2025-06-18",
+                "capabilities": { "sampling": {} },
+                "clientInfo": { "name": "mock-client", "version": "0" },
+            })),
+        )
+        .unwrap();
+    client.notify("notifications/initialized", None).unwrap();
+    let call = |client: &mut MockClient, max_tokens: u64| {
+        let params = json!({ "name": "ask", "arguments": { "max_tokens": max_tokens } });
+        client.send_request("tools/call", Some(params)).unwrap()
+    };
+    // Refused by the hook, which leaves the budget as it was
+    let refused = call(&mut client, 500);
+    let response = client.expect_response(&refused).unwrap();
+    assert!(response["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("too long"));
+    let id = call(&mut client, 50);
+    let request = client.expect_request("sampling/createMessage").unwrap();
+    assert_eq!(request["params"]["maxTokens"], json!(50));
+    let sampled = json!({
+        "role": "assistant",
+        "content": { "type": "text", "text": "Hello" },
+        "model": "mock-model",
+    });
+    client.respond(&request["id"], sampled).unwrap();
+    let response = client.expect_response(&id).unwrap();
+    assert_eq!(
+        response["result"]["content"][0]["text"],
+        json!("mock-model")
+    );
+    let over = call(&mut client, 50);
+    let response = client.expect_response(&over).unwrap();
+    assert!(response["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("sampling requests"));
+}
+
+#[test]
+fn unanswered_server_requests_are_sent_again() {
+    let config = Config {
+        client_request_timeout_secs: 1,
+        client_request_retries: 1,
+        client_request_backoff_ms: 10,
+        ..Config::default()
+    };
+    let extension = Extension::new("x-test")
+        .with_method("roots", |ctx, _| ctx.send_request("roots/list", None));
+    let server = Server::new().with_config(config).with_extension(extension);
+    let mut client = MockClient::connect(Arc::new(server));
+    client.initialize().unwrap();
+    let first = client.send_request("x-test/roots", None).unwrap();
+    let unanswered = client.expect_request("roots/list").unwrap();
+    let cancelled = client
+        .expect_notification("notifications/cancelled")
+        .unwrap();
+    assert_eq!(cancelled["requestId"], unanswered["id"]);
+    let retried = client.expect_request("roots/list").unwrap();
+    assert_ne!(retried["id"], unanswered["id"]);
+    client
+        .respond(&retried["id"], json!({ "roots": [] }))
+        .unwrap();
+    let response = client.expect_response(&first).unwrap();
+    assert_eq!(response["result"], json!({ "roots": [] }));
+}